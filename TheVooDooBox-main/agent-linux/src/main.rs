@@ -0,0 +1,398 @@
+mod procfs;
+
+use notify::{RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+
+// Same newline-delimited JSON protocol as the Windows agent, so the backend
+// orchestrates Linux sandboxes without knowing which guest OS it talks to.
+
+#[derive(Serialize, Clone)]
+struct AgentEvent {
+    event_type: String,
+    process_id: u32,
+    parent_process_id: u32,
+    process_name: String,
+    details: String,
+    decoded_details: Option<String>,
+    timestamp: i64,
+    hostname: String,
+    digital_signature: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AgentCommand {
+    command: String,
+    pid: Option<u32>,
+    url: Option<String>,
+    filename: Option<String>,
+    args: Option<Vec<String>>,
+    noise: Option<Vec<String>>,
+    allow: Option<Vec<String>>,
+}
+
+/// Backend-managed process filter applied before events leave the guest.
+#[derive(Default)]
+struct NoiseFilter {
+    noise: Vec<String>,
+    allow: Vec<String>,
+}
+
+impl NoiseFilter {
+    fn should_drop(&self, evt: &AgentEvent) -> bool {
+        if evt.event_type == "SESSION_INIT" || evt.event_type.starts_with("AGENT_") {
+            return false;
+        }
+        let name = evt.process_name.to_lowercase();
+        if self.allow.iter().any(|a| name.contains(a.as_str())) {
+            return false;
+        }
+        self.noise.iter().any(|n| name.contains(n.as_str()))
+    }
+}
+
+fn event(event_type: &str, pid: u32, ppid: u32, name: &str, details: String, hostname: &str) -> AgentEvent {
+    AgentEvent {
+        event_type: event_type.to_string(),
+        process_id: pid,
+        parent_process_id: ppid,
+        process_name: name.to_string(),
+        details,
+        decoded_details: None,
+        timestamp: chrono::Utc::now().timestamp_millis(),
+        hostname: hostname.to_string(),
+        digital_signature: None,
+    }
+}
+
+/// Random version-4 UUID for this agent run, from the OS-seeded RandomState keys.
+fn new_session_id() -> String {
+    use std::hash::{BuildHasher, Hasher};
+    let mut bytes = [0u8; 16];
+    for half in bytes.chunks_mut(8) {
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u128(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0));
+        half.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let h = hex::encode(bytes);
+    format!("{}-{}-{}-{}-{}", &h[0..8], &h[8..12], &h[12..16], &h[16..20], &h[20..32])
+}
+
+fn calculate_sha256(path: &Path) -> String {
+    let mut file = match std::fs::File::open(path) {
+        Ok(f) => f,
+        Err(_) => return "N/A".to_string(),
+    };
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 4096];
+    while let Ok(n) = file.read(&mut buffer) {
+        if n == 0 { break; }
+        hasher.update(&buffer[..n]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Tries the usual X11 capture tools in turn; headless guests simply produce nothing.
+async fn take_and_upload_screenshot(backend_url: &str, session_id: &str) {
+    let path = format!("/tmp/voodoobox_screenshot_{}.png", chrono::Utc::now().timestamp());
+    let captured = [
+        ("import", vec!["-window", "root", path.as_str()]),
+        ("scrot", vec!["-o", path.as_str()]),
+        ("gnome-screenshot", vec!["-f", path.as_str()]),
+    ]
+    .iter()
+    .any(|(tool, args)| {
+        std::process::Command::new(tool)
+            .args(args)
+            .env("DISPLAY", std::env::var("DISPLAY").unwrap_or_else(|_| ":0".to_string()))
+            .status()
+            .map(|s| s.success())
+            .unwrap_or(false)
+    });
+
+    if !captured {
+        println!("[AGENT] Screenshot unavailable (no X display or capture tool)");
+        return;
+    }
+
+    if let Ok(bytes) = tokio::fs::read(&path).await {
+        let file_name = Path::new(&path).file_name().unwrap_or_default().to_string_lossy().to_string();
+        if let Ok(part) = reqwest::multipart::Part::bytes(bytes).file_name(file_name).mime_str("image/png") {
+            // The backend reads session_id ahead of the file part to find the task
+            let form = reqwest::multipart::Form::new()
+                .text("session_id", session_id.to_string())
+                .part("file", part);
+            let _ = reqwest::Client::new()
+                .post(format!("{}/vms/telemetry/screenshot", backend_url))
+                .multipart(form)
+                .send()
+                .await;
+        }
+    }
+    let _ = tokio::fs::remove_file(&path).await;
+}
+
+/// Downloads the sample, marks it executable and runs it, reporting each step like the Windows agent.
+async fn download_and_execute(url: String, filename: String, args: Vec<String>, tx: mpsc::UnboundedSender<AgentEvent>, hostname: String) {
+    let dest_path = format!("/tmp/{}", filename);
+
+    let bytes = match reqwest::get(&url).await.and_then(|r| r.error_for_status()) {
+        Ok(resp) => match resp.bytes().await {
+            Ok(b) => b,
+            Err(e) => {
+                let _ = tx.send(event("DOWNLOAD_ERROR", 0, 0, "Agent", format!("Failed to read download body: {}", e), &hostname));
+                return;
+            }
+        },
+        Err(e) => {
+            let _ = tx.send(event("DOWNLOAD_ERROR", 0, 0, "Agent", format!("Download of {} failed: {}", url, e), &hostname));
+            return;
+        }
+    };
+
+    if let Err(e) = tokio::fs::write(&dest_path, &bytes).await {
+        let _ = tx.send(event("DOWNLOAD_ERROR", 0, 0, "Agent", format!("Failed to write file: {}", e), &hostname));
+        return;
+    }
+
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = std::fs::set_permissions(&dest_path, std::fs::Permissions::from_mode(0o755));
+    }
+
+    let _ = tx.send(event("FILE_VERIFIED", 0, 0, &dest_path, format!("INTEGRITY: File verified on disk (SHA256: {}). Starting detonation.", calculate_sha256(Path::new(&dest_path))), &hostname));
+
+    // Scripts without a shebang still need an interpreter; ELF files run directly
+    let is_elf = bytes.starts_with(b"\x7fELF");
+    let mut cmd = if is_elf || bytes.starts_with(b"#!") {
+        tokio::process::Command::new(&dest_path)
+    } else {
+        let mut c = tokio::process::Command::new("/bin/sh");
+        c.arg(&dest_path);
+        c
+    };
+    cmd.args(&args).current_dir("/tmp");
+
+    match cmd.spawn() {
+        Ok(child) => {
+            let _ = tx.send(event("EXEC_SUCCESS", child.id().unwrap_or(0), std::process::id(), &dest_path, format!("Binary executed ({})", if is_elf { "ELF" } else { "script" }), &hostname));
+        }
+        Err(e) => {
+            let _ = tx.send(event("EXEC_ERROR", 0, 0, &dest_path, format!("Failed to execute: {}", e), &hostname));
+        }
+    }
+}
+
+fn describe_fs_kind(kind: &notify::EventKind) -> Option<&'static str> {
+    use notify::EventKind;
+    match kind {
+        EventKind::Create(_) => Some("FILE_CREATE"),
+        EventKind::Modify(notify::event::ModifyKind::Data(_)) => Some("FILE_MODIFY"),
+        EventKind::Modify(notify::event::ModifyKind::Metadata(_)) => Some("FILE_CHMOD"),
+        EventKind::Remove(_) => Some("FILE_DELETE"),
+        _ => None,
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("Mallab Linux Agent - v0.1.0");
+
+    let addr = std::env::var("AGENT_SERVER_ADDR").unwrap_or_else(|_| "192.168.50.11:9001".to_string());
+
+    // Connection Retry Loop
+    let stream = loop {
+        match TcpStream::connect(&addr).await {
+            Ok(s) => {
+                println!("Connected to Hyper-Bridge @ {}", addr);
+                break s;
+            }
+            Err(e) => {
+                println!("[AGENT] Failed to connect to {}: {}. Retrying in 5 seconds...", addr, e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+    };
+    let (read_half, mut write_half) = stream.into_split();
+    let mut lines = BufReader::new(read_half).lines();
+
+    let host_ip = addr.split(':').next().unwrap_or("192.168.50.11");
+    let backend_url = format!("http://{}:8080", host_ip);
+
+    let hostname = std::fs::read_to_string("/etc/hostname")
+        .map(|h| h.trim().to_string())
+        .unwrap_or_else(|_| "unknown-linux-vm".to_string());
+    let session_id = new_session_id();
+    println!("[AGENT] Identity: {} (session {})", hostname, session_id);
+
+    // Handshake first, so the backend keys this connection by our session rather than the socket
+    let handshake = serde_json::json!({
+        "type": "HANDSHAKE",
+        "session_id": session_id,
+        "hostname": hostname,
+        "pid": std::process::id(),
+    });
+    write_half.write_all(format!("{}\n", handshake).as_bytes()).await?;
+
+    let (evt_tx, mut evt_rx) = mpsc::unbounded_channel::<AgentEvent>();
+
+    let _ = evt_tx.send(event("SESSION_INIT", std::process::id(), 0, "mallab-agent", format!("Agent initialized and ready. Computer: {} (Linux)", hostname), &hostname));
+
+    // 1. File System Watcher (inotify via notify)
+    let tx_fs = evt_tx.clone();
+    let hostname_fs = hostname.clone();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if let Ok(fs_event) = res {
+            if let (Some(event_type), Some(path)) = (describe_fs_kind(&fs_event.kind), fs_event.paths.first()) {
+                let hash = if event_type == "FILE_DELETE" || path.is_dir() { "N/A".to_string() } else { calculate_sha256(path) };
+                let _ = tx_fs.send(event(event_type, 0, 0, "Filesystem", format!("File Activity: {} (SHA256: {})", path.display(), hash), &hostname_fs));
+            }
+        }
+    })?;
+
+    let mut watch_paths = vec!["/tmp".to_string(), "/var/tmp".to_string(), "/dev/shm".to_string(), "/etc/cron.d".to_string(), "/etc/systemd/system".to_string()];
+    if let Ok(home) = std::env::var("HOME") {
+        watch_paths.push(format!("{}/.config/autostart", home));
+        watch_paths.push(format!("{}/.ssh", home));
+    }
+    for p in &watch_paths {
+        if Path::new(p).exists() {
+            let _ = watcher.watch(Path::new(p), RecursiveMode::Recursive);
+        }
+    }
+
+    // PID -> process name, so exits can still be attributed after /proc/<pid> is gone
+    let mut known_procs: HashMap<u32, String> = procfs::list_pids().into_iter()
+        .map(|pid| (pid, procfs::read_process(pid).map(|p| p.name).unwrap_or_default()))
+        .collect();
+    let mut known_conns: HashSet<(u64, String)> = procfs::list_connections().into_iter().map(|c| (c.inode, c.remote)).collect();
+    let mut noise_filter = NoiseFilter::default();
+    let mut screenshot_iter = 0;
+
+    loop {
+        tokio::select! {
+            // Commands from Backend
+            line = lines.next_line() => {
+                let line = match line {
+                    Ok(Some(l)) => l,
+                    _ => break,
+                };
+                let cmd = match serde_json::from_str::<AgentCommand>(&line) {
+                    Ok(c) => c,
+                    Err(_) => continue,
+                };
+                match cmd.command.as_str() {
+                    "KILL" => {
+                        if let Some(pid) = cmd.pid {
+                            let _ = std::process::Command::new("kill").args(["-9", &pid.to_string()]).status();
+                        }
+                    },
+                    "SCREENSHOT" => {
+                        let b_url = backend_url.clone();
+                        let sid = session_id.clone();
+                        tokio::spawn(async move { take_and_upload_screenshot(&b_url, &sid).await; });
+                    },
+                    "DOWNLOAD_EXEC" => {
+                        if let Some(url) = cmd.url {
+                            let filename = cmd.filename
+                                .map(|f| f.replace('/', "_"))
+                                .unwrap_or_else(|| format!("sample_{}", chrono::Utc::now().timestamp()));
+                            tokio::spawn(download_and_execute(url, filename, cmd.args.unwrap_or_default(), evt_tx.clone(), hostname.clone()));
+                        }
+                    },
+                    "SET_NOISE_FILTER" => {
+                        noise_filter = NoiseFilter {
+                            noise: cmd.noise.unwrap_or_default(),
+                            allow: cmd.allow.unwrap_or_default(),
+                        };
+                    },
+                    _ => println!("Unknown command: {}", cmd.command),
+                }
+            }
+
+            // Events from watchers / command handlers
+            Some(evt) = evt_rx.recv() => {
+                if noise_filter.should_drop(&evt) {
+                    continue;
+                }
+                let msg = serde_json::to_string(&evt)? + "\n";
+                if write_half.write_all(msg.as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+
+            // Periodic Scans (Process + Network)
+            _ = tokio::time::sleep(Duration::from_secs(5)) => {
+                // 1. Process Lifecycle
+                let current_pids = procfs::list_pids();
+                let new_pids: Vec<u32> = current_pids.iter().filter(|p| !known_procs.contains_key(p)).copied().collect();
+                for pid in new_pids {
+                    let name = match procfs::read_process(pid) {
+                        Some(p) => {
+                            let _ = evt_tx.send(event(
+                                "PROCESS_CREATE",
+                                p.pid,
+                                p.ppid,
+                                &p.name,
+                                format!("New process: {} Cmd: {} (SHA256: {})", p.exe, p.cmdline, calculate_sha256(Path::new(&p.exe))),
+                                &hostname,
+                            ));
+                            p.name
+                        }
+                        None => String::new(),
+                    };
+                    known_procs.insert(pid, name);
+                }
+                known_procs.retain(|pid, name| {
+                    if current_pids.contains(pid) {
+                        return true;
+                    }
+                    let _ = evt_tx.send(event("PROCESS_EXIT", *pid, 0, name, format!("Process {} ({}) exited", pid, name), &hostname));
+                    false
+                });
+
+                // 2. Network Connections
+                let conns = procfs::list_connections();
+                let owners = procfs::socket_owners();
+                let mut names: HashMap<u32, String> = HashMap::new();
+                let mut current_conns = HashSet::new();
+                for c in conns {
+                    if c.state == "LISTEN" || c.remote.ends_with(":0") {
+                        continue;
+                    }
+                    let key = (c.inode, c.remote.clone());
+                    if !known_conns.contains(&key) {
+                        let pid = owners.get(&c.inode).copied().unwrap_or(0);
+                        let name = names.entry(pid)
+                            .or_insert_with(|| procfs::read_process(pid).map(|p| p.name).unwrap_or_else(|| "Unknown".to_string()))
+                            .clone();
+                        let _ = evt_tx.send(event("NETWORK_CONNECT", pid, 0, &name, format!("{} {} -> {} ({})", c.proto, c.local, c.remote, c.state), &hostname));
+                    }
+                    current_conns.insert(key);
+                }
+                known_conns = current_conns;
+
+                // 3. Periodic Screenshot (every 30s approx, assuming 5s loop)
+                screenshot_iter += 1;
+                if screenshot_iter >= 6 {
+                    let b_url = backend_url.clone();
+                    let sid = session_id.clone();
+                    tokio::spawn(async move { take_and_upload_screenshot(&b_url, &sid).await; });
+                    screenshot_iter = 0;
+                }
+            }
+        }
+    }
+
+    println!("[AGENT] Connection to backend lost, exiting.");
+    Ok(())
+}
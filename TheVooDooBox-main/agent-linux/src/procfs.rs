@@ -0,0 +1,135 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+// Thin /proc readers. Polling is coarse compared to eBPF, but it needs no
+// kernel headers or privileges beyond root in the guest.
+
+#[derive(Clone, Debug)]
+pub struct ProcInfo {
+    pub pid: u32,
+    pub ppid: u32,
+    pub name: String,
+    pub exe: String,
+    pub cmdline: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Connection {
+    pub proto: &'static str,
+    pub local: String,
+    pub remote: String,
+    pub state: &'static str,
+    pub inode: u64,
+}
+
+pub fn list_pids() -> HashSet<u32> {
+    std::fs::read_dir("/proc")
+        .map(|entries| {
+            entries.flatten()
+                .filter_map(|e| e.file_name().to_str().and_then(|n| n.parse().ok()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn read_process(pid: u32) -> Option<ProcInfo> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // comm is parenthesised and may itself contain spaces or ')', so split on the last ')'
+    let open = stat.find('(')?;
+    let close = stat.rfind(')')?;
+    let name = stat[open + 1..close].to_string();
+    let ppid = stat[close + 1..].split_whitespace().nth(1)?.parse().ok()?;
+
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid))
+        .map(|raw| {
+            raw.split(|b| *b == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| String::from_utf8_lossy(arg).to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .unwrap_or_default();
+    let exe = std::fs::read_link(format!("/proc/{}/exe", pid))
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    Some(ProcInfo { pid, ppid, name, exe, cmdline })
+}
+
+/// Socket inode -> owning PID, built from /proc/*/fd symlinks ("socket:[inode]").
+pub fn socket_owners() -> HashMap<u64, u32> {
+    let mut owners = HashMap::new();
+    for pid in list_pids() {
+        if let Ok(fds) = std::fs::read_dir(format!("/proc/{}/fd", pid)) {
+            for fd in fds.flatten() {
+                if let Ok(target) = std::fs::read_link(fd.path()) {
+                    let target = target.to_string_lossy();
+                    if let Some(inode) = target.strip_prefix("socket:[").and_then(|s| s.strip_suffix(']')) {
+                        if let Ok(inode) = inode.parse() {
+                            owners.insert(inode, pid);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    owners
+}
+
+pub fn list_connections() -> Vec<Connection> {
+    let mut conns = Vec::new();
+    for (file, proto, v6) in [("/proc/net/tcp", "TCP", false), ("/proc/net/tcp6", "TCP", true), ("/proc/net/udp", "UDP", false), ("/proc/net/udp6", "UDP", true)] {
+        let content = match std::fs::read_to_string(file) {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+        for line in content.lines().skip(1) {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            if cols.len() < 10 {
+                continue;
+            }
+            let (local, remote) = match (parse_addr(cols[1], v6), parse_addr(cols[2], v6)) {
+                (Some(l), Some(r)) => (l, r),
+                _ => continue,
+            };
+            conns.push(Connection {
+                proto,
+                local,
+                remote,
+                state: tcp_state(cols[3]),
+                inode: cols[9].parse().unwrap_or(0),
+            });
+        }
+    }
+    conns
+}
+
+/// /proc/net addresses are hex "ADDR:PORT" with the address in host (little-endian) word order.
+fn parse_addr(field: &str, v6: bool) -> Option<String> {
+    let (addr_hex, port_hex) = field.split_once(':')?;
+    let port = u16::from_str_radix(port_hex, 16).ok()?;
+    if v6 {
+        let mut bytes = [0u8; 16];
+        for (i, chunk) in bytes.chunks_mut(4).enumerate() {
+            let word = u32::from_str_radix(addr_hex.get(i * 8..i * 8 + 8)?, 16).ok()?;
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        Some(format!("[{}]:{}", Ipv6Addr::from(bytes), port))
+    } else {
+        let word = u32::from_str_radix(addr_hex, 16).ok()?;
+        Some(format!("{}:{}", Ipv4Addr::from(word.to_le_bytes()), port))
+    }
+}
+
+fn tcp_state(hex: &str) -> &'static str {
+    match hex {
+        "01" => "ESTABLISHED",
+        "02" => "SYN_SENT",
+        "03" => "SYN_RECV",
+        "06" => "TIME_WAIT",
+        "07" => "CLOSE",
+        "08" => "CLOSE_WAIT",
+        "0A" => "LISTEN",
+        _ => "OTHER",
+    }
+}
@@ -0,0 +1,146 @@
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+// Minimal HTTP/1.1 request reader for the browser extension listener.
+// Only what the extension needs: Content-Length and chunked bodies, keep-alive.
+
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+const MAX_BODY_BYTES: usize = 16 * 1024 * 1024; // Full-page DOM snapshots can be several MB
+
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// HTTP/1.1 defaults to keep-alive unless the client asks to close.
+    pub fn keep_alive(&self) -> bool {
+        !self.header("Connection").map_or(false, |v| v.eq_ignore_ascii_case("close"))
+    }
+}
+
+/// Only the extension (or local tooling) may post; web pages could otherwise forge events.
+pub fn is_allowed_origin(origin: Option<&str>) -> bool {
+    match origin {
+        None => true,
+        Some(o) => {
+            let o = o.to_ascii_lowercase();
+            o.starts_with("chrome-extension://")
+                || o.starts_with("moz-extension://")
+                || o.starts_with("edge-extension://")
+                || o == "http://localhost" || o.starts_with("http://localhost:")
+                || o == "http://127.0.0.1" || o.starts_with("http://127.0.0.1:")
+        }
+    }
+}
+
+/// Reads the next request from the connection. `Ok(None)` means the client closed cleanly.
+pub async fn read_request<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<HttpRequest>, String> {
+    let mut request_line = String::new();
+    // Tolerate stray CRLFs between pipelined requests
+    loop {
+        request_line.clear();
+        let n = reader.read_line(&mut request_line).await.map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Ok(None);
+        }
+        if !request_line.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().ok_or("Malformed request line")?.to_string();
+    let path = parts.next().ok_or("Malformed request line")?.to_string();
+
+    let mut headers = Vec::new();
+    let mut header_bytes = 0;
+    loop {
+        let mut line = String::new();
+        let n = reader.read_line(&mut line).await.map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Err("Connection closed inside headers".to_string());
+        }
+        header_bytes += n;
+        if header_bytes > MAX_HEADER_BYTES {
+            return Err("Headers too large".to_string());
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((k, v)) = line.split_once(':') {
+            headers.push((k.trim().to_string(), v.trim().to_string()));
+        }
+    }
+
+    let mut req = HttpRequest { method, path, headers, body: Vec::new() };
+
+    let chunked = req.header("Transfer-Encoding").map_or(false, |v| v.to_ascii_lowercase().contains("chunked"));
+    if chunked {
+        req.body = read_chunked_body(reader).await?;
+    } else if let Some(len) = req.header("Content-Length") {
+        let len: usize = len.parse().map_err(|_| "Invalid Content-Length")?;
+        if len > MAX_BODY_BYTES {
+            return Err(format!("Body of {} bytes exceeds limit", len));
+        }
+        let mut body = vec![0u8; len];
+        reader.read_exact(&mut body).await.map_err(|e| e.to_string())?;
+        req.body = body;
+    }
+
+    Ok(Some(req))
+}
+
+async fn read_chunked_body<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+    loop {
+        let mut size_line = String::new();
+        if reader.read_line(&mut size_line).await.map_err(|e| e.to_string())? == 0 {
+            return Err("Connection closed inside chunked body".to_string());
+        }
+        // Chunk extensions (";name=value") are ignored
+        let size_hex = size_line.trim().split(';').next().unwrap_or("");
+        let size = usize::from_str_radix(size_hex, 16).map_err(|_| format!("Invalid chunk size '{}'", size_hex))?;
+
+        if size == 0 {
+            // Consume optional trailers up to the terminating blank line
+            loop {
+                let mut trailer = String::new();
+                let n = reader.read_line(&mut trailer).await.map_err(|e| e.to_string())?;
+                if n == 0 || trailer.trim().is_empty() {
+                    return Ok(body);
+                }
+            }
+        }
+
+        if body.len() + size > MAX_BODY_BYTES {
+            return Err("Chunked body exceeds limit".to_string());
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..]).await.map_err(|e| e.to_string())?;
+
+        let mut crlf = [0u8; 2];
+        reader.read_exact(&mut crlf).await.map_err(|e| e.to_string())?;
+    }
+}
+
+pub fn response(status: &str, origin: Option<&str>, keep_alive: bool) -> String {
+    let mut resp = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n", status);
+    if let Some(o) = origin {
+        resp.push_str(&format!(
+            "Access-Control-Allow-Origin: {}\r\nAccess-Control-Allow-Methods: POST, OPTIONS\r\nAccess-Control-Allow-Headers: Content-Type\r\n",
+            o
+        ));
+    }
+    resp.push_str(if keep_alive { "Connection: keep-alive\r\n\r\n" } else { "Connection: close\r\n\r\n" });
+    resp
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use winapi::shared::minwindef::{DWORD, HKEY};
+use winapi::um::winnt::KEY_READ;
+use winapi::um::winreg::{RegCloseKey, RegEnumValueA, RegOpenKeyExA, HKEY_LOCAL_MACHINE};
+use crate::persistence_monitor::PersistenceFinding;
+use crate::trust_monitor::read_value_display;
+
+/// Defender exclusion lists; each excluded item is stored as a value name.
+const EXCLUSION_KEYS: &[&str] = &[
+    "SOFTWARE\\Microsoft\\Windows Defender\\Exclusions\\Paths",
+    "SOFTWARE\\Microsoft\\Windows Defender\\Exclusions\\Extensions",
+    "SOFTWARE\\Microsoft\\Windows Defender\\Exclusions\\Processes",
+    "SOFTWARE\\Microsoft\\Windows Defender\\Exclusions\\IpAddresses",
+    "SOFTWARE\\Policies\\Microsoft\\Windows Defender\\Exclusions\\Paths",
+    "SOFTWARE\\Policies\\Microsoft\\Windows Defender\\Exclusions\\Extensions",
+    "SOFTWARE\\Policies\\Microsoft\\Windows Defender\\Exclusions\\Processes",
+];
+
+/// Tamper protection and the policy switches malware flips to blind Defender.
+const TAMPER_VALUES: &[(&str, &str)] = &[
+    ("SOFTWARE\\Microsoft\\Windows Defender\\Features", "TamperProtection"),
+    ("SOFTWARE\\Policies\\Microsoft\\Windows Defender", "DisableAntiSpyware"),
+    ("SOFTWARE\\Policies\\Microsoft\\Windows Defender", "DisableAntiVirus"),
+    ("SOFTWARE\\Policies\\Microsoft\\Windows Defender\\Real-Time Protection", "DisableRealtimeMonitoring"),
+    ("SOFTWARE\\Policies\\Microsoft\\Windows Defender\\Real-Time Protection", "DisableBehaviorMonitoring"),
+    ("SOFTWARE\\Policies\\Microsoft\\Windows Defender\\Real-Time Protection", "DisableOnAccessProtection"),
+    ("SOFTWARE\\Policies\\Microsoft\\Windows Defender\\Real-Time Protection", "DisableScanOnRealtimeEnable"),
+    ("SOFTWARE\\Policies\\Microsoft\\Windows Defender\\Spynet", "SpynetReporting"),
+    ("SOFTWARE\\Policies\\Microsoft\\Windows Defender\\Spynet", "SubmitSamplesConsent"),
+];
+
+/// Diffs Defender exclusions and tamper-relevant settings against the previous poll.
+pub struct DefenseMonitor {
+    exclusions: Option<HashMap<String, Vec<String>>>,
+    tamper: Option<HashMap<String, String>>,
+}
+
+impl DefenseMonitor {
+    pub fn new() -> Self {
+        Self { exclusions: None, tamper: None }
+    }
+
+    /// Returns findings since the previous poll. The first call only records a baseline.
+    pub fn poll(&mut self) -> Vec<PersistenceFinding> {
+        let mut findings = Vec::new();
+
+        let mut current_exclusions = HashMap::new();
+        for path in EXCLUSION_KEYS {
+            current_exclusions.insert(path.to_string(), unsafe { enumerate_value_names(HKEY_LOCAL_MACHINE, path) });
+        }
+        if let Some(old) = &self.exclusions {
+            for (key, items) in &current_exclusions {
+                let previous = old.get(key).cloned().unwrap_or_default();
+                let list = key.rsplit('\\').next().unwrap_or("Exclusions");
+                for item in items.iter().filter(|i| !previous.contains(i)) {
+                    findings.push(PersistenceFinding {
+                        event_type: "DEFENDER_EXCLUSION_ADDED",
+                        details: format!("Defender {} exclusion added: {} (HKLM\\{})", list, item, key),
+                        path: None,
+                    });
+                }
+                for item in previous.iter().filter(|i| !items.contains(i)) {
+                    findings.push(PersistenceFinding {
+                        event_type: "DEFENDER_EXCLUSION_REMOVED",
+                        details: format!("Defender {} exclusion removed: {} (HKLM\\{})", list, item, key),
+                        path: None,
+                    });
+                }
+            }
+        }
+        self.exclusions = Some(current_exclusions);
+
+        let mut current_tamper = HashMap::new();
+        for (path, value) in TAMPER_VALUES {
+            if let Some(data) = unsafe { read_value_display(HKEY_LOCAL_MACHINE, path, value) } {
+                current_tamper.insert(format!("HKLM\\{}\\{}", path, value), data);
+            }
+        }
+        if let Some(old) = &self.tamper {
+            for (key, data) in &current_tamper {
+                if old.get(key) != Some(data) {
+                    findings.push(PersistenceFinding {
+                        event_type: "DEFENDER_TAMPER",
+                        details: format!("Defender setting changed: {} = '{}' (Old: '{}')", key, data, old.get(key).map(|s| s.as_str()).unwrap_or("<unset>")),
+                        path: None,
+                    });
+                }
+            }
+            for key in old.keys().filter(|k| !current_tamper.contains_key(*k)) {
+                findings.push(PersistenceFinding {
+                    event_type: "DEFENDER_TAMPER",
+                    details: format!("Defender setting removed: {}", key),
+                    path: None,
+                });
+            }
+        }
+        self.tamper = Some(current_tamper);
+
+        findings
+    }
+}
+
+/// Applies the requested Defender / firewall state (None = leave as is); returns the result as JSON.
+pub fn apply_defenses(defender: Option<bool>, firewall: Option<bool>) -> serde_json::Value {
+    let mut errors = Vec::new();
+
+    if let Some(enabled) = defender {
+        // Tamper protection silently ignores this; the reported state shows whether it stuck
+        let script = format!("Set-MpPreference -DisableRealtimeMonitoring ${} -DisableBehaviorMonitoring ${} -DisableIOAVProtection ${}", !enabled, !enabled, !enabled);
+        if let Err(e) = run_powershell(&script) {
+            errors.push(format!("Defender: {}", e));
+        }
+    }
+    if let Some(enabled) = firewall {
+        let script = format!("Set-NetFirewallProfile -Profile Domain,Public,Private -Enabled {}", if enabled { "True" } else { "False" });
+        if let Err(e) = run_powershell(&script) {
+            errors.push(format!("Firewall: {}", e));
+        }
+    }
+
+    let mut state = query_defenses();
+    state["errors"] = serde_json::json!(errors);
+    state
+}
+
+/// Current Defender status and per-profile firewall state.
+pub fn query_defenses() -> serde_json::Value {
+    let defender = run_powershell(
+        "Get-MpComputerStatus | Select-Object AMServiceEnabled,AntivirusEnabled,RealTimeProtectionEnabled,BehaviorMonitorEnabled,IsTamperProtected | ConvertTo-Json -Compress"
+    ).ok().and_then(|out| serde_json::from_str::<serde_json::Value>(&out).ok());
+
+    let firewall = run_powershell(
+        "Get-NetFirewallProfile | Select-Object Name,Enabled | ConvertTo-Json -Compress"
+    ).ok().and_then(|out| serde_json::from_str::<serde_json::Value>(&out).ok())
+        .map(|v| match v {
+            // Enabled serialises as a GpoBoolean enum (0/1) on older PowerShell
+            serde_json::Value::Array(profiles) => profiles.iter()
+                .map(|p| (p["Name"].as_str().unwrap_or("?").to_string(), serde_json::json!(p["Enabled"] == true || p["Enabled"] == 1)))
+                .collect::<serde_json::Map<_, _>>()
+                .into(),
+            other => other,
+        });
+
+    serde_json::json!({ "defender": defender, "firewall": firewall })
+}
+
+pub(crate) fn run_powershell(script: &str) -> Result<String, String> {
+    let output = std::process::Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", script])
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+unsafe fn enumerate_value_names(hive: HKEY, path: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let c_path = match CString::new(path) {
+        Ok(p) => p,
+        Err(_) => return names,
+    };
+    let mut hkey: HKEY = std::ptr::null_mut();
+    if RegOpenKeyExA(hive, c_path.as_ptr(), 0, KEY_READ, &mut hkey) != 0 {
+        return names;
+    }
+
+    let mut index = 0;
+    loop {
+        let mut name_buf = [0i8; 16383];
+        let mut name_len: DWORD = name_buf.len() as DWORD;
+        let ret = RegEnumValueA(hkey, index, name_buf.as_mut_ptr(), &mut name_len, std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::null_mut());
+        if ret != 0 {
+            break; // ERROR_NO_MORE_ITEMS
+        }
+        let name: Vec<u8> = name_buf[..name_len as usize].iter().map(|&c| c as u8).collect();
+        names.push(String::from_utf8_lossy(&name).to_string());
+        index += 1;
+    }
+
+    RegCloseKey(hkey);
+    names
+}
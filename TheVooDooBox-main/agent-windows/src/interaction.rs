@@ -0,0 +1,45 @@
+use std::time::{Duration, Instant};
+use winapi::um::winuser::{
+    GetCursorPos, GetSystemMetrics, SetCursorPos, keybd_event, mouse_event, KEYEVENTF_KEYUP, MOUSEEVENTF_WHEEL,
+    SM_CXSCREEN, SM_CYSCREEN, VK_SHIFT,
+};
+use winapi::shared::windef::POINT;
+
+/// Fakes a user at the console for `duration`: mouse drift, scrolls and Shift taps, never clicks.
+pub fn simulate(duration: Duration) {
+    let started = Instant::now();
+    let (width, height) = unsafe { (GetSystemMetrics(SM_CXSCREEN).max(1), GetSystemMetrics(SM_CYSCREEN).max(1)) };
+    // Small LCG; this only has to look irregular, not be random
+    let mut seed = (chrono::Utc::now().timestamp_millis() as u64) | 1;
+    let mut next = move |bound: i32| -> i32 {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((seed >> 33) % bound.max(1) as u64) as i32
+    };
+
+    println!("[AGENT] Simulating user interaction for {}s", duration.as_secs());
+    while started.elapsed() < duration {
+        let mut pos = POINT { x: width / 2, y: height / 2 };
+        unsafe { GetCursorPos(&mut pos) };
+        let target = (next(width), next(height));
+
+        // Glide in steps rather than teleporting; some checks look at movement deltas
+        let steps = 10 + next(20);
+        for i in 1..=steps {
+            let x = pos.x + (target.0 - pos.x) * i / steps;
+            let y = pos.y + (target.1 - pos.y) * i / steps;
+            unsafe { SetCursorPos(x, y) };
+            std::thread::sleep(Duration::from_millis(15 + next(25) as u64));
+        }
+
+        match next(6) {
+            0 => unsafe { mouse_event(MOUSEEVENTF_WHEEL, 0, 0, (-120i32) as u32, 0) },
+            1 => unsafe {
+                keybd_event(VK_SHIFT as u8, 0, 0, 0);
+                keybd_event(VK_SHIFT as u8, 0, KEYEVENTF_KEYUP, 0);
+            },
+            _ => {}
+        }
+        std::thread::sleep(Duration::from_millis(1500 + next(3500) as u64));
+    }
+    println!("[AGENT] User interaction simulation finished");
+}
@@ -2,10 +2,18 @@ mod mem_utils;
 mod kernel_bridge;
 mod decoder;
 mod signature_verifier;
+mod persistence_monitor;
+mod trust_monitor;
+mod defense_monitor;
+mod severity;
+mod browser_http;
+mod interaction;
+mod mitm;
+mod url_crawl;
 
 use sysinfo::{ProcessExt, System, SystemExt, PidExt};
 use tokio::net::TcpStream;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::time::Duration;
@@ -100,21 +108,8 @@ fn parse_sysmon_xml(xml: &str, hostname: &str) -> Option<AgentEvent> {
                 decoded_details,
                 timestamp: chrono::Utc::now().timestamp_millis(),
                 hostname: hostname.to_string(),
-                digital_signature: {
-                    let mut sig = get_sysmon_field(xml, "Signature");
-                    // Fallback to Native Check if Sysmon failed to get signature
-                    if sig.is_empty() || sig == "-" || sig == "Unsigned" {
-                        let native = signature_verifier::verify_signature(&image);
-                        // Always use native result if it's better than Sysmon's "Unsigned" or empty
-                        if !native.is_empty() && (native.starts_with("Signed") || native.contains("Error Code")) {
-                            sig = native;
-                        } else if sig.is_empty() || sig == "-" {
-                            // Fallback if Sysmon was totally empty
-                            sig = native;
-                        }
-                    }
-                    Some(sig)
-                },
+                // Sysmon's ProcessCreate carries no signature data, so verify natively
+                digital_signature: if image.is_empty() { None } else { Some(signature_verifier::describe_signature(&image)) },
             })
         },
         "2" => { // File Creation Time Changed
@@ -160,6 +155,11 @@ fn parse_sysmon_xml(xml: &str, hostname: &str) -> Option<AgentEvent> {
             let pid = get_sysmon_field(xml, "ProcessId").parse().unwrap_or(0);
             let image = get_sysmon_field(xml, "Image");
             let loaded_image = get_sysmon_field(xml, "ImageLoaded");
+            let signature = signature_verifier::from_sysmon(
+                &get_sysmon_field(xml, "Signed"),
+                &get_sysmon_field(xml, "Signature"),
+                &get_sysmon_field(xml, "SignatureStatus"),
+            ).or_else(|| if loaded_image.is_empty() { None } else { Some(signature_verifier::describe_signature(&loaded_image)) });
 
             Some(AgentEvent {
                 event_type: "IMAGE_LOAD".to_string(),
@@ -170,7 +170,7 @@ fn parse_sysmon_xml(xml: &str, hostname: &str) -> Option<AgentEvent> {
                 decoded_details: None,
                 timestamp: chrono::Utc::now().timestamp_millis(),
                 hostname: hostname.to_string(),
-                digital_signature: None,
+                digital_signature: signature,
             })
         },
         "8" => { // CreateRemoteThread
@@ -465,6 +465,22 @@ unsafe fn get_registry_values(hive: HKEY, subkey: &str) -> HashMap<String, Strin
     values
 }
 
+/// Random version-4 UUID for this agent run. RandomState keys come from the OS RNG, which
+/// saves pulling in a crate for sixteen random bytes.
+fn new_session_id() -> String {
+    use std::hash::{BuildHasher, Hasher};
+    let mut bytes = [0u8; 16];
+    for half in bytes.chunks_mut(8) {
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u128(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0));
+        half.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let h = hex::encode(bytes);
+    format!("{}-{}-{}-{}-{}", &h[0..8], &h[8..12], &h[12..16], &h[16..20], &h[20..32])
+}
+
 fn calculate_sha256(path: &Path) -> String {
     let mut file = match std::fs::File::open(path) {
         Ok(f) => f,
@@ -479,7 +495,9 @@ fn calculate_sha256(path: &Path) -> String {
     hex::encode(hasher.finalize())
 }
 
-fn take_and_upload_screenshot(backend_url: &str) {
+/// Captures every screen and uploads it. When `trigger` is set the shot was caused by a
+/// high-signal event, and its identity is sent along so the backend can tie the image to it.
+fn take_and_upload_screenshot(backend_url: &str, session_id: &str, trigger: Option<&AgentEvent>) {
     let screens = screenshots::Screen::all().unwrap_or_default();
     for (i, screen) in screens.iter().enumerate() {
         if let Ok(image) = screen.capture() {
@@ -487,9 +505,20 @@ fn take_and_upload_screenshot(backend_url: &str) {
             let mut cursor = std::io::Cursor::new(&mut buffer);
             if image.write_to(&mut cursor, image::ImageOutputFormat::Png).is_ok() {
                 let client = reqwest::blocking::Client::new();
-                let form = reqwest::blocking::multipart::Form::new()
-                    .part("file", reqwest::blocking::multipart::Part::bytes(buffer)
-                        .file_name(format!("screenshot_screen{}_{}.png", i, chrono::Utc::now().timestamp()))
+                let mut form = reqwest::blocking::multipart::Form::new().text("session_id", session_id.to_string());
+                let file_name = match trigger {
+                    Some(evt) => {
+                        // Text fields must precede the file part; the backend reads them first
+                        form = form
+                            .text("trigger_event_type", evt.event_type.clone())
+                            .text("trigger_pid", evt.process_id.to_string())
+                            .text("trigger_timestamp", evt.timestamp.to_string());
+                        format!("event_{}_{}_{}_screen{}.png", evt.event_type.to_lowercase(), evt.process_id, evt.timestamp, i)
+                    }
+                    None => format!("screenshot_screen{}_{}.png", i, chrono::Utc::now().timestamp()),
+                };
+                form = form.part("file", reqwest::blocking::multipart::Part::bytes(buffer)
+                        .file_name(file_name)
                         .mime_str("image/png").unwrap());
                 
                 let _ = client.post(format!("{}/vms/telemetry/screenshot", backend_url))
@@ -500,6 +529,53 @@ fn take_and_upload_screenshot(backend_url: &str) {
     }
 }
 
+/// Visible standard dialog windows (class #32770: MessageBox, UAC-style prompts, fake error popups).
+fn enumerate_dialog_windows() -> HashMap<usize, (u32, String)> {
+    use winapi::um::winuser::{EnumWindows, GetClassNameW, GetWindowTextW, GetWindowThreadProcessId, IsWindowVisible};
+    use winapi::shared::windef::HWND;
+    use winapi::shared::minwindef::{BOOL, LPARAM, TRUE};
+
+    unsafe extern "system" fn callback(hwnd: HWND, lparam: LPARAM) -> BOOL {
+        let found = &mut *(lparam as *mut HashMap<usize, (u32, String)>);
+        if IsWindowVisible(hwnd) == 0 {
+            return TRUE;
+        }
+        let mut class_buf = [0u16; 64];
+        let class_len = GetClassNameW(hwnd, class_buf.as_mut_ptr(), class_buf.len() as i32);
+        if class_len <= 0 || String::from_utf16_lossy(&class_buf[..class_len as usize]) != "#32770" {
+            return TRUE;
+        }
+        let mut title_buf = [0u16; 256];
+        let title_len = GetWindowTextW(hwnd, title_buf.as_mut_ptr(), title_buf.len() as i32);
+        let title = String::from_utf16_lossy(&title_buf[..title_len.max(0) as usize]);
+        let mut pid: DWORD = 0;
+        GetWindowThreadProcessId(hwnd, &mut pid);
+        found.insert(hwnd as usize, (pid, title));
+        TRUE
+    }
+
+    let mut found: HashMap<usize, (u32, String)> = HashMap::new();
+    unsafe {
+        EnumWindows(Some(callback), &mut found as *mut _ as LPARAM);
+    }
+    found
+}
+
+/// Events worth an immediate screenshot instead of waiting for the periodic capture.
+fn is_screenshot_trigger(evt: &AgentEvent, sample_pids: &mut HashSet<u32>) -> bool {
+    match evt.event_type.as_str() {
+        "MEMORY_ANOMALY" | "WINDOW_DIALOG" => true,
+        // The detonated sample itself; children are tracked from here
+        "EXEC_SUCCESS" => {
+            sample_pids.insert(evt.process_id);
+            false
+        }
+        // A child of the sample; only the first report of each PID triggers (Sysmon and the poller both emit one)
+        "PROCESS_CREATE" => sample_pids.contains(&evt.parent_process_id) && sample_pids.insert(evt.process_id),
+        _ => false,
+    }
+}
+
 fn get_dns_cache() -> HashSet<String> {
     let mut domains = HashSet::new();
     if let Ok(output) = std::process::Command::new("ipconfig").arg("/displaydns").output() {
@@ -536,9 +612,39 @@ struct AgentCommand {
     args: Option<Vec<String>>,
     url: Option<String>,
     filename: Option<String>,
+    sha256: Option<String>,
+    noise: Option<Vec<String>>,
+    allow: Option<Vec<String>>,
+    defender: Option<bool>,
+    firewall: Option<bool>,
+    rules: Option<Vec<severity::SeverityRule>>,
+    duration_seconds: Option<u64>,
+    proxy: Option<String>,
+    ca_pem: Option<String>,
 }
 
-async fn upload_pivot_file(backend_url: &str, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+/// Backend-managed process filter applied before events leave the guest.
+#[derive(Default)]
+struct NoiseFilter {
+    noise: Vec<String>,
+    allow: Vec<String>,
+}
+
+impl NoiseFilter {
+    fn should_drop(&self, evt: &AgentEvent) -> bool {
+        // Agent lifecycle and registry persistence events are always forwarded
+        if evt.event_type == "SESSION_INIT" || evt.event_type.starts_with("AGENT_") || evt.event_type.starts_with("REG") {
+            return false;
+        }
+        let name = evt.process_name.to_lowercase();
+        if self.allow.iter().any(|a| name.contains(a.as_str())) {
+            return false;
+        }
+        self.noise.iter().any(|n| name.contains(n.as_str()))
+    }
+}
+
+async fn upload_pivot_file(backend_url: &str, session_id: &str, path: &str) -> Result<(), Box<dyn std::error::Error>> {
     let file_path = std::path::Path::new(path);
     if !file_path.exists() {
         println!("[AGENT] Pivot Error: File not found: {}", path);
@@ -549,7 +655,11 @@ async fn upload_pivot_file(backend_url: &str, path: &str) -> Result<(), Box<dyn
     let part = reqwest::multipart::Part::bytes(file_content)
         .file_name(file_path.file_name().unwrap().to_str().unwrap().to_string());
     
-    let form = reqwest::multipart::Form::new().part("file", part);
+    // The guest path lets the backend tell a file the sample dropped from any other pivot
+    let form = reqwest::multipart::Form::new()
+        .text("session_id", session_id.to_string())
+        .text("path", path.to_string())
+        .part("file", part);
     
     let client = reqwest::Client::new();
     client.post(format!("{}/vms/telemetry/pivot-upload", backend_url))
@@ -561,6 +671,109 @@ async fn upload_pivot_file(backend_url: &str, path: &str) -> Result<(), Box<dyn
     Ok(())
 }
 
+/// Ships a carved hollowing dump to the backend and returns the download URL it was stored under.
+async fn upload_memory_dump(backend_url: &str, session_id: &str, path: &str, pid: u32) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let file_path = std::path::Path::new(path);
+    let file_content = tokio::fs::read(file_path).await?;
+    let part = reqwest::multipart::Part::bytes(file_content)
+        .file_name(format!("dump_{}.bin", pid))
+        .mime_str("application/octet-stream")?;
+
+    let form = reqwest::multipart::Form::new()
+        .text("session_id", session_id.to_string())
+        .text("pid", pid.to_string())
+        .part("file", part);
+
+    let client = reqwest::Client::new();
+    let resp: serde_json::Value = client.post(format!("{}/vms/telemetry/memory-dump", backend_url))
+        .multipart(form)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let url = resp["url"].as_str().unwrap_or_default().to_string();
+    println!("[AGENT] Memory dump for PID {} uploaded: {}", pid, url);
+    Ok(url)
+}
+
+/// Downloads a new agent build, checks it against the expected hash (and signature, when the running
+/// agent is itself signed), then hands off to a detached helper that swaps the binary and relaunches it.
+fn perform_self_update(url: &str, expected_sha256: &str, evt_tx: mpsc::UnboundedSender<AgentEvent>, hostname: String) {
+    let report = |event_type: &str, details: String, signature: Option<String>| {
+        println!("[AGENT] {}: {}", event_type, details);
+        let _ = evt_tx.send(AgentEvent {
+            event_type: event_type.to_string(),
+            process_id: std::process::id(),
+            parent_process_id: 0,
+            process_name: "mallab-agent".to_string(),
+            details,
+            decoded_details: None,
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            hostname: hostname.clone(),
+            digital_signature: signature,
+        });
+    };
+
+    let current_exe = match std::env::current_exe() {
+        Ok(p) => p,
+        Err(e) => return report("AGENT_UPDATE_ERROR", format!("Cannot resolve running agent path: {}", e), None),
+    };
+    let staged_exe = current_exe.with_extension("new.exe");
+
+    let bytes = match reqwest::blocking::get(url).and_then(|r| r.error_for_status()).and_then(|r| r.bytes()) {
+        Ok(b) => b,
+        Err(e) => return report("AGENT_UPDATE_ERROR", format!("Download of {} failed: {}", url, e), None),
+    };
+
+    let mut hasher = Sha256::new();
+    hasher.update(&bytes);
+    let actual_sha256 = hex::encode(hasher.finalize());
+    if !actual_sha256.eq_ignore_ascii_case(expected_sha256.trim()) {
+        return report("AGENT_UPDATE_ERROR", format!("Hash mismatch: expected {}, got {}", expected_sha256, actual_sha256), None);
+    }
+
+    if let Err(e) = std::fs::write(&staged_exe, &bytes) {
+        return report("AGENT_UPDATE_ERROR", format!("Failed to stage {}: {}", staged_exe.display(), e), None);
+    }
+
+    let current_sig = signature_verifier::verify_signature(&current_exe.to_string_lossy());
+    let staged_sig = signature_verifier::verify_signature(&staged_exe.to_string_lossy());
+    if current_sig == "Signed (Verified)" && staged_sig != "Signed (Verified)" {
+        let _ = std::fs::remove_file(&staged_exe);
+        return report("AGENT_UPDATE_ERROR", format!("Refusing unsigned update for a signed agent ({})", staged_sig), Some(staged_sig));
+    }
+
+    // The running image is locked, so a detached cmd helper waits for us to exit before replacing it.
+    let helper_path = std::env::temp_dir().join("voodoobox_agent_update.cmd");
+    let script = format!(
+        "@echo off\r\ntimeout /t 3 /nobreak > nul\r\nmove /Y \"{staged}\" \"{current}\" > nul\r\nstart \"\" \"{current}\"\r\ndel \"%~f0\"\r\n",
+        staged = staged_exe.display(),
+        current = current_exe.display()
+    );
+    if let Err(e) = std::fs::write(&helper_path, script) {
+        return report("AGENT_UPDATE_ERROR", format!("Failed to write update helper: {}", e), None);
+    }
+
+    use std::os::windows::process::CommandExt;
+    const DETACHED_PROCESS: u32 = 0x00000008;
+    const CREATE_NO_WINDOW: u32 = 0x08000000;
+    if let Err(e) = std::process::Command::new("cmd")
+        .args(["/C", &helper_path.to_string_lossy()])
+        .creation_flags(DETACHED_PROCESS | CREATE_NO_WINDOW)
+        .spawn()
+    {
+        return report("AGENT_UPDATE_ERROR", format!("Failed to launch update helper: {}", e), None);
+    }
+
+    report("AGENT_UPDATE", format!("Verified update {} staged; restarting agent", actual_sha256), Some(staged_sig));
+
+    // Give the event loop a moment to flush the update event before exiting
+    std::thread::sleep(Duration::from_secs(1));
+    std::process::exit(0);
+}
+
 #[derive(Deserialize, Debug)]
 struct BrowserEvent {
     event_type: String,
@@ -631,86 +844,106 @@ async fn start_browser_listener(evt_tx: mpsc::UnboundedSender<AgentEvent>, hostn
     println!("[AGENT] Browser Telemetry Listener active on 127.0.0.1:1337");
 
     loop {
-        if let Ok((mut socket, _)) = listener.accept().await {
+        if let Ok((socket, _)) = listener.accept().await {
             let tx = evt_tx.clone();
             let h_name = hostname.clone();
-            
             tokio::spawn(async move {
-                let mut buf = [0; 1024 * 64]; // 64KB buffer for large DOMs
-                match socket.read(&mut buf).await {
-                    Ok(n) if n > 0 => {
-                        let req = String::from_utf8_lossy(&buf[..n]);
-                        
-                        // Very basic HTTP parsing meant ONLY for this extension
-                        // We expect: POST /telemetry/browser ... \r\n\r\n{JSON}
-                        if let Some(body_start) = req.find("\r\n\r\n") {
-                            let body = &req[body_start+4..];
-                            // Handle Content-Length if needed, but for now try direct parse
-                            // Clean up null bytes if any
-                            let clean_body = body.trim_matches(char::from(0));
-                            
-                            if let Ok(browser_evt) = serde_json::from_str::<BrowserEvent>(clean_body) {
-                                // Map to AgentEvent
-                                let details = match browser_evt.event_type.as_str() {
-                                    "BROWSER_NAVIGATE" => format!("URL: {} | Title: {}", browser_evt.url, browser_evt.title.unwrap_or_default()),
-                                    "BROWSER_REDIRECT" => format!("REDIRECT: {} -> {} ({})", browser_evt.source_url.unwrap_or_default(), browser_evt.target_url.unwrap_or_default(), browser_evt.status_code.unwrap_or(0)),
-                                    "BROWSER_DOM" => format!("DOM SNAPSHOT: {} (Preview: {}...)", browser_evt.url, browser_evt.html_preview.as_deref().unwrap_or("").chars().take(100).collect::<String>()),
-                                    _ => format!("Unknown Browser Event: {:?}", browser_evt)
-                                };
-
-                                let mut decoded_details = None;
-                                
-                                // Scan details for encoded data
-                                let decodes = decoder::scan_and_decode(&details);
-                                if !decodes.is_empty() {
-                                    decoded_details = Some(decodes.iter().map(|d| format!("[{}] {}", d.method, d.decoded)).collect::<Vec<_>>().join(" | "));
-                                }
-
-                                // For DOM events, also pass the (potentially large) HTML preview as decoded context
-                                if browser_evt.event_type == "BROWSER_DOM" {
-                                    if let Some(html) = &browser_evt.html_preview {
-                                         // Scan HTML for encoded data as well
-                                         let html_decodes = decoder::scan_and_decode(html);
-                                         let mut combined = html.clone();
-                                         if !html_decodes.is_empty() {
-                                             let dec_str = html_decodes.iter().map(|d| format!("[{}] {}", d.method, d.decoded)).collect::<Vec<_>>().join(" | ");
-                                             combined = format!("DECODED DATA FOUND IN DOM: {}\n\nFULL DOM PREVIEW:\n{}", dec_str, html);
-                                         }
-                                         
-                                         // Append to any existing decoded_details
-                                         if let Some(existing) = decoded_details {
-                                             decoded_details = Some(format!("{}\n\n{}", existing, combined));
-                                         } else {
-                                             decoded_details = Some(combined);
-                                         }
-                                    }
-                                }
+                handle_browser_connection(socket, tx, h_name).await;
+            });
+        }
+    }
+}
 
-                                let _ = tx.send(AgentEvent {
-                                    event_type: browser_evt.event_type,
-                                    process_id: 0, 
-                                    parent_process_id: 0,
-                                    process_name: "chrome.exe".to_string(), // Assumed
-                                    details,
-                                    decoded_details,
-                                    timestamp: chrono::Utc::now().timestamp_millis(),
-                                    hostname: h_name,
-                                    digital_signature: None,
-                                });
+/// Serves one extension connection, which may carry several (keep-alive/pipelined) requests.
+async fn handle_browser_connection(socket: tokio::net::TcpStream, tx: mpsc::UnboundedSender<AgentEvent>, hostname: String) {
+    let (read_half, mut write_half) = socket.into_split();
+    let mut reader = tokio::io::BufReader::new(read_half);
 
-                                // Send 200 OK
-                                let response = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
-                                let _ = socket.write_all(response.as_bytes()).await;
-                            } else {
-                                // println!("[AGENT] Failed to json parse browser event body: {}", clean_body);
-                            }
-                        }
-                    },
-                    _ => {}
+    loop {
+        let req = match browser_http::read_request(&mut reader).await {
+            Ok(Some(r)) => r,
+            Ok(None) => break,
+            Err(e) => {
+                println!("[AGENT] Browser listener: bad request: {}", e);
+                let _ = write_half.write_all(browser_http::response("400 Bad Request", None, false).as_bytes()).await;
+                break;
+            }
+        };
+
+        let origin = req.header("Origin").map(|o| o.to_string());
+        let keep_alive = req.keep_alive();
+
+        let status = if !browser_http::is_allowed_origin(origin.as_deref()) {
+            println!("[AGENT] Browser listener: rejected origin {:?}", origin);
+            "403 Forbidden"
+        } else if req.method == "OPTIONS" {
+            "204 No Content" // CORS preflight from the extension
+        } else if req.method != "POST" {
+            "405 Method Not Allowed"
+        } else {
+            match serde_json::from_slice::<BrowserEvent>(&req.body) {
+                Ok(browser_evt) => {
+                    let _ = tx.send(browser_event_to_agent_event(browser_evt, &hostname));
+                    "200 OK"
                 }
-            });
+                Err(_) => "400 Bad Request",
+            }
+        };
+
+        let allow_origin = origin.as_deref().filter(|_| status != "403 Forbidden");
+        if write_half.write_all(browser_http::response(status, allow_origin, keep_alive).as_bytes()).await.is_err() || !keep_alive {
+            break;
+        }
+    }
+}
+
+fn browser_event_to_agent_event(browser_evt: BrowserEvent, hostname: &str) -> AgentEvent {
+    let details = match browser_evt.event_type.as_str() {
+        "BROWSER_NAVIGATE" => format!("URL: {} | Title: {}", browser_evt.url, browser_evt.title.clone().unwrap_or_default()),
+        "BROWSER_REDIRECT" => format!("REDIRECT: {} -> {} ({})", browser_evt.source_url.clone().unwrap_or_default(), browser_evt.target_url.clone().unwrap_or_default(), browser_evt.status_code.unwrap_or(0)),
+        "BROWSER_DOM" => format!("DOM SNAPSHOT: {} (Preview: {}...)", browser_evt.url, browser_evt.html_preview.as_deref().unwrap_or("").chars().take(100).collect::<String>()),
+        _ => format!("Unknown Browser Event: {:?}", browser_evt)
+    };
+
+    let mut decoded_details = None;
+
+    // Scan details for encoded data
+    let decodes = decoder::scan_and_decode(&details);
+    if !decodes.is_empty() {
+        decoded_details = Some(decodes.iter().map(|d| format!("[{}] {}", d.method, d.decoded)).collect::<Vec<_>>().join(" | "));
+    }
+
+    // For DOM events, also pass the (potentially large) HTML preview as decoded context
+    if browser_evt.event_type == "BROWSER_DOM" {
+        if let Some(html) = &browser_evt.html_preview {
+             // Scan HTML for encoded data as well
+             let html_decodes = decoder::scan_and_decode(html);
+             let mut combined = html.clone();
+             if !html_decodes.is_empty() {
+                 let dec_str = html_decodes.iter().map(|d| format!("[{}] {}", d.method, d.decoded)).collect::<Vec<_>>().join(" | ");
+                 combined = format!("DECODED DATA FOUND IN DOM: {}\n\nFULL DOM PREVIEW:\n{}", dec_str, html);
+             }
+             
+             // Append to any existing decoded_details
+             if let Some(existing) = decoded_details {
+                 decoded_details = Some(format!("{}\n\n{}", existing, combined));
+             } else {
+                 decoded_details = Some(combined);
+             }
         }
     }
+
+    AgentEvent {
+        event_type: browser_evt.event_type,
+        process_id: 0, 
+        parent_process_id: 0,
+        process_name: "chrome.exe".to_string(), // Assumed
+        details,
+        decoded_details,
+        timestamp: chrono::Utc::now().timestamp_millis(),
+        hostname: hostname.to_string(),
+        digital_signature: None,
+    }
 }
 
 #[tokio::main]
@@ -746,9 +979,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let mut sys = System::new_all();
     let mut known_pids: HashSet<u32> = sys.processes().keys().map(|&p| p.as_u32()).collect();
+    let mut dumped_pids: HashSet<u32> = HashSet::new();
 
     let hostname = std::env::var("COMPUTERNAME").unwrap_or_else(|_| "unknown-vm".to_string());
-    println!("[AGENT] Identity: {}", hostname);
+    let session_id = new_session_id();
+    println!("[AGENT] Identity: {} (session {})", hostname, session_id);
+
+    // Handshake first, so the backend keys this connection by our session rather than the socket
+    let handshake = serde_json::json!({
+        "type": "HANDSHAKE",
+        "session_id": session_id,
+        "hostname": hostname,
+        "pid": std::process::id(),
+    });
+    stream.write_all(format!("{}\n", handshake).as_bytes()).await?;
+    // Backend commands are newline-delimited JSON; a single read can end mid-line
+    let (read_half, mut write_half) = stream.into_split();
+    let mut commands = tokio::io::BufReader::new(read_half).lines();
     
     // Run Signature Verifier Self-Test on Startup
     // Run Signature Verifier Self-Test on Startup (Non-blocking)
@@ -792,6 +1039,32 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         start_clipboard_monitor(tx_cb, hostname_cb).await;
     });
 
+    // 5. Scheduled Task / WMI Subscription Diffing (slow cadence, or on-demand sweep from backend)
+    let tx_sched = evt_tx.clone();
+    let hostname_sched = hostname.clone();
+    let (sweep_tx, sweep_rx) = std::sync::mpsc::channel::<()>();
+    std::thread::spawn(move || {
+        let mut monitor = persistence_monitor::ScheduledPersistenceMonitor::new();
+        loop {
+            if let Err(std::sync::mpsc::RecvTimeoutError::Disconnected) = sweep_rx.recv_timeout(Duration::from_secs(30)) {
+                break;
+            }
+            for finding in monitor.poll() {
+                let _ = tx_sched.send(AgentEvent {
+                    event_type: finding.event_type.to_string(),
+                    process_id: 0,
+                    parent_process_id: 0,
+                    process_name: if finding.event_type == "WMI_SUBSCRIPTION" { "WMI".to_string() } else { "TaskScheduler".to_string() },
+                    details: finding.details,
+                    decoded_details: None,
+                    timestamp: chrono::Utc::now().timestamp_millis(),
+                    hostname: hostname_sched.clone(),
+                    digital_signature: None,
+                });
+            }
+        }
+    });
+
     // 1. File System Watcher with Hashing
     let tx_fs = evt_tx.clone();
     let hostname_fs = hostname.clone();
@@ -813,7 +1086,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     };
 
                     let sig = if is_executable {
-                        signature_verifier::verify_signature(&path_str)
+                        signature_verifier::describe_signature(&path_str)
                     } else {
                         "N/A".to_string()
                     };
@@ -857,378 +1130,511 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         "Software\\Microsoft\\Windows\\CurrentVersion\\RunOnce",
     ];
 
-    let mut buf = [0u8; 4096];
     let mut screenshot_iter = 0;
     let mut registry_state: HashMap<String, HashMap<String, String>> = HashMap::new();
     let mut dns_state: HashSet<String> = get_dns_cache(); // Initialize with baseline
+    let mut noise_filter = NoiseFilter::default();
+    let mut severity_scorer = severity::SeverityScorer::new();
+    let mut persistence_monitor = persistence_monitor::PersistenceMonitor::new();
+    let mut trust_monitor = trust_monitor::TrustMonitor::new();
+    let mut defense_monitor = defense_monitor::DefenseMonitor::new();
+    let mut sample_pids: HashSet<u32> = HashSet::new();
+    let mut known_dialogs: HashMap<usize, (u32, String)> = enumerate_dialog_windows();
 
     loop {
         tokio::select! {
             // Commands from Backend
-            n = stream.read(&mut buf) => {
-                match n {
-                    Ok(0) => break,
-                    Ok(n) => {
-                        let raw = String::from_utf8_lossy(&buf[..n]);
-                        for line in raw.lines() {
-                            if let Ok(cmd) = serde_json::from_str::<AgentCommand>(line) {
-                                match cmd.command.as_str() {
-                                    "KILL" => {
-                                        if let Some(pid) = cmd.pid {
-                                            if let Some(process) = sys.process(sysinfo::Pid::from(pid as usize)) {
-                                                process.kill();
-                                            }
+            line = commands.next_line() => {
+                match line {
+                    Ok(None) => break,
+                    Ok(Some(line)) => {
+                        if let Ok(cmd) = serde_json::from_str::<AgentCommand>(&line) {
+                            match cmd.command.as_str() {
+                                "KILL" => {
+                                    if let Some(pid) = cmd.pid {
+                                        if let Some(process) = sys.process(sysinfo::Pid::from(pid as usize)) {
+                                            process.kill();
                                         }
-                                    },
-                                    "EXEC_BINARY" => {
-                                        if let Some(path) = cmd.path {
-                                            let mut proc = std::process::Command::new(&path);
-                                            if let Some(args) = cmd.args {
-                                                proc.args(args);
+                                    }
+                                },
+                                "EXEC_BINARY" => {
+                                    if let Some(path) = cmd.path {
+                                        let mut proc = std::process::Command::new(&path);
+                                        if let Some(args) = cmd.args {
+                                            proc.args(args);
+                                        }
+                                        match proc.spawn() {
+                                            Ok(child) => {
+                                                let _ = evt_tx.send(AgentEvent {
+                                                    event_type: "EXEC_SUCCESS".to_string(),
+                                                    process_id: child.id(),
+                                                    parent_process_id: std::process::id(),
+                                                    process_name: path.clone(),
+                                                    details: "Binary execution started via remote command".to_string(),
+                                                    decoded_details: None,
+                                                    timestamp: chrono::Utc::now().timestamp_millis(),
+                                                    hostname: hostname.clone(),
+                                                    digital_signature: Some(signature_verifier::describe_signature(&path)),
+                                                });
                                             }
-                                            match proc.spawn() {
-                                                Ok(child) => {
-                                                    let _ = evt_tx.send(AgentEvent {
-                                                        event_type: "EXEC_SUCCESS".to_string(),
-                                                        process_id: child.id(),
-                                                        parent_process_id: std::process::id(),
-                                                        process_name: path.clone(),
-                                                        details: "Binary execution started via remote command".to_string(),
-                                                        decoded_details: None,
-                                                        timestamp: chrono::Utc::now().timestamp_millis(),
-                                                        hostname: hostname.clone(),
-                                                        digital_signature: Some(signature_verifier::verify_signature(&path)),
-                                                    });
-                                                }
-                                                Err(e) => {
-                                                    let _ = evt_tx.send(AgentEvent {
-                                                        event_type: "EXEC_ERROR".to_string(),
-                                                        process_id: 0,
-                                                        parent_process_id: 0,
-                                                        process_name: path,
-                                                        details: format!("Failed to execute binary: {}", e),
-                                                        decoded_details: None,
-                                                        timestamp: chrono::Utc::now().timestamp_millis(),
-                                                        hostname: hostname.clone(),
-                                                        digital_signature: None,
-                                                    });
-                                                }
+                                            Err(e) => {
+                                                let _ = evt_tx.send(AgentEvent {
+                                                    event_type: "EXEC_ERROR".to_string(),
+                                                    process_id: 0,
+                                                    parent_process_id: 0,
+                                                    process_name: path,
+                                                    details: format!("Failed to execute binary: {}", e),
+                                                    decoded_details: None,
+                                                    timestamp: chrono::Utc::now().timestamp_millis(),
+                                                    hostname: hostname.clone(),
+                                                    digital_signature: None,
+                                                });
                                             }
                                         }
-                                    },
-
-                                    "EXEC_URL" => {
-                                        if let Some(url) = cmd.url {
-                                            // Windows-specific way to open URL in default browser
-                                            let _ = std::process::Command::new("cmd")
-                                                .args(&["/C", "start", "", &url])
-                                                .spawn();
-                                            
-                                            let _ = evt_tx.send(AgentEvent {
-                                                event_type: "URL_OPEN".to_string(),
+                                    }
+                                },
+
+                                "EXEC_URL" => {
+                                    if let Some(url) = cmd.url {
+                                        // Windows-specific way to open URL in default browser
+                                        let _ = std::process::Command::new("cmd")
+                                            .args(&["/C", "start", "", &url])
+                                            .spawn();
+                                        
+                                        let _ = evt_tx.send(AgentEvent {
+                                            event_type: "URL_OPEN".to_string(),
+                                            process_id: 0,
+                                            parent_process_id: 0,
+                                            process_name: "Web Browser".to_string(),
+                                            details: format!("Opening URL: {}", url),
+                                            decoded_details: None,
+                                            timestamp: chrono::Utc::now().timestamp_millis(),
+                                            hostname: hostname.clone(),
+                                            digital_signature: None,
+                                        });
+
+                                        // Structured artifacts: crawl the URL ourselves, and shoot the page once it has rendered
+                                        let b_url = backend_url.clone();
+                                        let sid = session_id.clone();
+                                        let crawl_url = url.clone();
+                                        tokio::spawn(async move {
+                                            let report = url_crawl::crawl(&crawl_url).await;
+                                            if let Err(e) = url_crawl::upload(&b_url, &sid, report).await {
+                                                println!("[AGENT] URL artifact upload failed: {}", e);
+                                            }
+                                        });
+                                        let b_url = backend_url.clone();
+                                        let sid = session_id.clone();
+                                        let tx_page = evt_tx.clone();
+                                        let hostname_page = hostname.clone();
+                                        tokio::spawn(async move {
+                                            tokio::time::sleep(Duration::from_secs(15)).await;
+                                            let loaded = AgentEvent {
+                                                event_type: "URL_PAGE_LOADED".to_string(),
                                                 process_id: 0,
                                                 parent_process_id: 0,
                                                 process_name: "Web Browser".to_string(),
-                                                details: format!("Opening URL: {}", url),
+                                                details: format!("Page screenshot for {}", url),
                                                 decoded_details: None,
                                                 timestamp: chrono::Utc::now().timestamp_millis(),
-                                                hostname: hostname.clone(),
+                                                hostname: hostname_page,
                                                 digital_signature: None,
-                                            });
+                                            };
+                                            let _ = tx_page.send(loaded.clone());
+                                            let _ = tokio::task::spawn_blocking(move || take_and_upload_screenshot(&b_url, &sid, Some(&loaded))).await;
+                                        });
+                                    }
+                                },
+                                "SCREENSHOT" => {
+                                    take_and_upload_screenshot(&backend_url, &session_id, None);
+                                },
+                                "DUMP_PROCESS" => {
+                                    // Analyst-approved dump of one process (backend action_manager)
+                                    if let Some(pid) = cmd.pid {
+                                        let dump_path = format!("C:\\Users\\Public\\dump_{}.bin", pid);
+                                        match mem_utils::dump_process_memory(pid, &dump_path) {
+                                            Ok(_) => {
+                                                let b_url = backend_url.clone();
+                                                let sid = session_id.clone();
+                                                tokio::spawn(async move {
+                                                    if let Err(e) = upload_memory_dump(&b_url, &sid, &dump_path, pid).await {
+                                                        println!("[AGENT] Memory dump upload for PID {} failed: {}", pid, e);
+                                                    }
+                                                });
+                                            }
+                                            Err(e) => println!("[AGENT] Memory dump of PID {} failed: {}", pid, e),
                                         }
-                                    },
-                                    "SCREENSHOT" => {
-                                        take_and_upload_screenshot(&backend_url);
-                                    },
-                                    "INSTALL_VSIX" => {
-                                        // ExtensionDetox: Download VSIX and silently install via VS Code CLI
-                                        if let Some(url) = cmd.url {
-                                            let safe_filename = cmd.filename.unwrap_or_else(|| "extension.vsix".to_string());
-                                            let dest_path = format!("C:\\Users\\Public\\{}", safe_filename);
-                                            let tx_vsix = evt_tx.clone();
-                                            let hostname_vsix = hostname.clone();
-
-                                            std::thread::spawn(move || {
-                                                // 1. Download the VSIX
-                                                match reqwest::blocking::get(&url) {
-                                                    Ok(mut response) => {
-                                                        match std::fs::File::create(&dest_path) {
-                                                            Ok(mut file) => {
-                                                                if let Err(e) = response.copy_to(&mut file) {
-                                                                    let _ = tx_vsix.send(AgentEvent {
-                                                                        event_type: "VSIX_ERROR".to_string(),
-                                                                        process_id: 0, parent_process_id: 0,
-                                                                        process_name: "Agent".to_string(),
-                                                                        details: format!("Failed to write VSIX: {}", e),
-                                                                        decoded_details: None,
-                                                                        timestamp: chrono::Utc::now().timestamp_millis(),
-                                                                        hostname: hostname_vsix.clone(),
-                                                                        digital_signature: None,
-                                                                    });
-                                                                    return;
-                                                                }
-                                                                let _ = file.sync_all();
-                                                                drop(file);
-                                                                std::thread::sleep(std::time::Duration::from_millis(500));
-
-                                                                // 2. Install via VS Code CLI
-                                                                println!("[AGENT] Installing VSIX: {}", dest_path);
-                                                                match std::process::Command::new("code")
-                                                                    .args(&["--install-extension", &dest_path, "--force"])
-                                                                    .output()
-                                                                {
-                                                                    Ok(output) => {
-                                                                        let stdout = String::from_utf8_lossy(&output.stdout);
-                                                                        let stderr = String::from_utf8_lossy(&output.stderr);
-                                                                        let _ = tx_vsix.send(AgentEvent {
-                                                                            event_type: "VSIX_INSTALLED".to_string(),
-                                                                            process_id: 0,
-                                                                            parent_process_id: std::process::id(),
-                                                                            process_name: dest_path.clone(),
-                                                                            details: format!("VSIX installed. stdout: {} stderr: {}", stdout.trim(), stderr.trim()),
-                                                                            decoded_details: None,
-                                                                            timestamp: chrono::Utc::now().timestamp_millis(),
-                                                                            hostname: hostname_vsix.clone(),
-                                                                            digital_signature: None,
-                                                                        });
-                                                                    },
-                                                                    Err(e) => {
-                                                                        let _ = tx_vsix.send(AgentEvent {
-                                                                            event_type: "VSIX_ERROR".to_string(),
-                                                                            process_id: 0, parent_process_id: 0,
-                                                                            process_name: dest_path.clone(),
-                                                                            details: format!("VS Code CLI failed: {}", e),
-                                                                            decoded_details: None,
-                                                                            timestamp: chrono::Utc::now().timestamp_millis(),
-                                                                            hostname: hostname_vsix.clone(),
-                                                                            digital_signature: None,
-                                                                        });
-                                                                    }
-                                                                }
-                                                            },
-                                                            Err(e) => {
+                                    }
+                                },
+                                "INSTALL_VSIX" => {
+                                    // ExtensionDetox: Download VSIX and silently install via VS Code CLI
+                                    if let Some(url) = cmd.url {
+                                        let safe_filename = cmd.filename.unwrap_or_else(|| "extension.vsix".to_string());
+                                        let dest_path = format!("C:\\Users\\Public\\{}", safe_filename);
+                                        let tx_vsix = evt_tx.clone();
+                                        let hostname_vsix = hostname.clone();
+
+                                        std::thread::spawn(move || {
+                                            // 1. Download the VSIX
+                                            match reqwest::blocking::get(&url) {
+                                                Ok(mut response) => {
+                                                    match std::fs::File::create(&dest_path) {
+                                                        Ok(mut file) => {
+                                                            if let Err(e) = response.copy_to(&mut file) {
                                                                 let _ = tx_vsix.send(AgentEvent {
                                                                     event_type: "VSIX_ERROR".to_string(),
                                                                     process_id: 0, parent_process_id: 0,
                                                                     process_name: "Agent".to_string(),
-                                                                    details: format!("Failed to create VSIX file: {}", e),
+                                                                    details: format!("Failed to write VSIX: {}", e),
                                                                     decoded_details: None,
                                                                     timestamp: chrono::Utc::now().timestamp_millis(),
                                                                     hostname: hostname_vsix.clone(),
                                                                     digital_signature: None,
                                                                 });
+                                                                return;
                                                             }
-                                                        }
-                                                    },
-                                                    Err(e) => {
-                                                        let _ = tx_vsix.send(AgentEvent {
-                                                            event_type: "VSIX_ERROR".to_string(),
-                                                            process_id: 0, parent_process_id: 0,
-                                                            process_name: "Agent".to_string(),
-                                                            details: format!("VSIX download failed: {}", e),
-                                                            decoded_details: None,
-                                                            timestamp: chrono::Utc::now().timestamp_millis(),
-                                                            hostname: hostname_vsix.clone(),
-                                                            digital_signature: None,
-                                                        });
-                                                    }
-                                                }
-                                            });
-                                        }
-                                    },
-                                    "UPLOAD_PIVOT" => {
-                                        if let Some(path) = cmd.path {
-                                            let b_url = backend_url.clone();
-                                            tokio::spawn(async move {
-                                                let _ = upload_pivot_file(&b_url, &path).await;
-                                            });
-                                        }
-                                    },
-                                    "DOWNLOAD_EXEC" => {
-                                        if let Some(url) = cmd.url {
-                                            println!("Downloading sample from: {}", url);
-                                            let safe_filename = cmd.filename.unwrap_or_else(|| format!("sample_{}.exe", chrono::Utc::now().timestamp()));
-                                            let dest_path = format!("C:\\Users\\Public\\{}", safe_filename);
-                                            
-                                            let dest_path_clone = dest_path.clone();
-                                            let url_clone = url.clone();
-                                            let tx_dl = evt_tx.clone();
-                                            let hostname_dl = hostname.clone();
-                                            
-                                            std::thread::spawn(move || {
-                                                // 1. Attempts Download
-                                                let download_success = match reqwest::blocking::get(&url_clone) {
-                                                    Ok(mut response) => {
-                                                        println!("[AGENT] Download connection established to {}", url_clone);
-                                                        match std::fs::File::create(&dest_path_clone) {
-                                                            Ok(mut file) => {
-                                                                if let Err(e) = response.copy_to(&mut file) {
-                                                                    println!("[AGENT] ERROR: Failed to write download content: {}", e);
-                                                                    // Log to debug file
-                                                                    if let Ok(mut file) = std::fs::OpenOptions::new().create(true).append(true).open("C:\\Mallab\\voodoobox_debug.log") {
-                                                                        use std::io::Write;
-                                                                        let _ = writeln!(file, "[{}] [DOWNLOAD_ERROR] Failed to write {}: {}", chrono::Local::now(), dest_path_clone, e);
-                                                                    }
-                                                                    let _ = tx_dl.send(AgentEvent {
-                                                                        event_type: "DOWNLOAD_ERROR".to_string(),
+                                                            let _ = file.sync_all();
+                                                            drop(file);
+                                                            std::thread::sleep(std::time::Duration::from_millis(500));
+
+                                                            // 2. Install via VS Code CLI
+                                                            println!("[AGENT] Installing VSIX: {}", dest_path);
+                                                            match std::process::Command::new("code")
+                                                                .args(&["--install-extension", &dest_path, "--force"])
+                                                                .output()
+                                                            {
+                                                                Ok(output) => {
+                                                                    let stdout = String::from_utf8_lossy(&output.stdout);
+                                                                    let stderr = String::from_utf8_lossy(&output.stderr);
+                                                                    let _ = tx_vsix.send(AgentEvent {
+                                                                        event_type: "VSIX_INSTALLED".to_string(),
                                                                         process_id: 0,
-                                                                        parent_process_id: 0,
-                                                                        process_name: "Agent".to_string(),
-                                                                        details: format!("Failed to write file: {}", e),
+                                                                        parent_process_id: std::process::id(),
+                                                                        process_name: dest_path.clone(),
+                                                                        details: format!("VSIX installed. stdout: {} stderr: {}", stdout.trim(), stderr.trim()),
                                                                         decoded_details: None,
                                                                         timestamp: chrono::Utc::now().timestamp_millis(),
-                                                                        hostname: hostname_dl.clone(),
+                                                                        hostname: hostname_vsix.clone(),
+                                                                        digital_signature: None,
+                                                                    });
+                                                                },
+                                                                Err(e) => {
+                                                                    let _ = tx_vsix.send(AgentEvent {
+                                                                        event_type: "VSIX_ERROR".to_string(),
+                                                                        process_id: 0, parent_process_id: 0,
+                                                                        process_name: dest_path.clone(),
+                                                                        details: format!("VS Code CLI failed: {}", e),
+                                                                        decoded_details: None,
+                                                                        timestamp: chrono::Utc::now().timestamp_millis(),
+                                                                        hostname: hostname_vsix.clone(),
                                                                         digital_signature: None,
                                                                     });
-                                                                    false
-                                                                } else {
-                                                                    println!("[AGENT] SUCCESS: File downloaded to {}", dest_path_clone);
-                                                                    // Ensure data is flushed to disk before closing
-                                                                    let _ = file.sync_all();
-                                                                    drop(file); // explicit drop
-                                                                    std::thread::sleep(std::time::Duration::from_millis(500));
-                                                                    true
                                                                 }
-                                                            },
-                                                            Err(e) => {
-                                                                println!("[AGENT] ERROR: Failed to create file at {}: {}", dest_path_clone, e);
+                                                            }
+                                                        },
+                                                        Err(e) => {
+                                                            let _ = tx_vsix.send(AgentEvent {
+                                                                event_type: "VSIX_ERROR".to_string(),
+                                                                process_id: 0, parent_process_id: 0,
+                                                                process_name: "Agent".to_string(),
+                                                                details: format!("Failed to create VSIX file: {}", e),
+                                                                decoded_details: None,
+                                                                timestamp: chrono::Utc::now().timestamp_millis(),
+                                                                hostname: hostname_vsix.clone(),
+                                                                digital_signature: None,
+                                                            });
+                                                        }
+                                                    }
+                                                },
+                                                Err(e) => {
+                                                    let _ = tx_vsix.send(AgentEvent {
+                                                        event_type: "VSIX_ERROR".to_string(),
+                                                        process_id: 0, parent_process_id: 0,
+                                                        process_name: "Agent".to_string(),
+                                                        details: format!("VSIX download failed: {}", e),
+                                                        decoded_details: None,
+                                                        timestamp: chrono::Utc::now().timestamp_millis(),
+                                                        hostname: hostname_vsix.clone(),
+                                                        digital_signature: None,
+                                                    });
+                                                }
+                                            }
+                                        });
+                                    }
+                                },
+                                "UPLOAD_PIVOT" => {
+                                    if let Some(path) = cmd.path {
+                                        let b_url = backend_url.clone();
+                                        let sid = session_id.clone();
+                                        tokio::spawn(async move {
+                                            let _ = upload_pivot_file(&b_url, &sid, &path).await;
+                                        });
+                                    }
+                                },
+                                "DOWNLOAD_EXEC" => {
+                                    if let Some(url) = cmd.url {
+                                        println!("Downloading sample from: {}", url);
+                                        let safe_filename = cmd.filename.unwrap_or_else(|| format!("sample_{}.exe", chrono::Utc::now().timestamp()));
+                                        let dest_path = format!("C:\\Users\\Public\\{}", safe_filename);
+                                        
+                                        let dest_path_clone = dest_path.clone();
+                                        let url_clone = url.clone();
+                                        let tx_dl = evt_tx.clone();
+                                        let hostname_dl = hostname.clone();
+                                        
+                                        std::thread::spawn(move || {
+                                            // 1. Attempts Download
+                                            let download_success = match reqwest::blocking::get(&url_clone) {
+                                                Ok(mut response) => {
+                                                    println!("[AGENT] Download connection established to {}", url_clone);
+                                                    match std::fs::File::create(&dest_path_clone) {
+                                                        Ok(mut file) => {
+                                                            if let Err(e) = response.copy_to(&mut file) {
+                                                                println!("[AGENT] ERROR: Failed to write download content: {}", e);
                                                                 // Log to debug file
                                                                 if let Ok(mut file) = std::fs::OpenOptions::new().create(true).append(true).open("C:\\Mallab\\voodoobox_debug.log") {
                                                                     use std::io::Write;
-                                                                    let _ = writeln!(file, "[{}] [DOWNLOAD_ERROR] Failed to create {}: {}", chrono::Local::now(), dest_path_clone, e);
+                                                                    let _ = writeln!(file, "[{}] [DOWNLOAD_ERROR] Failed to write {}: {}", chrono::Local::now(), dest_path_clone, e);
                                                                 }
                                                                 let _ = tx_dl.send(AgentEvent {
                                                                     event_type: "DOWNLOAD_ERROR".to_string(),
                                                                     process_id: 0,
                                                                     parent_process_id: 0,
                                                                     process_name: "Agent".to_string(),
-                                                                    details: format!("Failed to create file: {}", e),
+                                                                    details: format!("Failed to write file: {}", e),
                                                                     decoded_details: None,
                                                                     timestamp: chrono::Utc::now().timestamp_millis(),
                                                                     hostname: hostname_dl.clone(),
                                                                     digital_signature: None,
                                                                 });
                                                                 false
+                                                            } else {
+                                                                println!("[AGENT] SUCCESS: File downloaded to {}", dest_path_clone);
+                                                                // Ensure data is flushed to disk before closing
+                                                                let _ = file.sync_all();
+                                                                drop(file); // explicit drop
+                                                                std::thread::sleep(std::time::Duration::from_millis(500));
+                                                                true
                                                             }
+                                                        },
+                                                        Err(e) => {
+                                                            println!("[AGENT] ERROR: Failed to create file at {}: {}", dest_path_clone, e);
+                                                            // Log to debug file
+                                                            if let Ok(mut file) = std::fs::OpenOptions::new().create(true).append(true).open("C:\\Mallab\\voodoobox_debug.log") {
+                                                                use std::io::Write;
+                                                                let _ = writeln!(file, "[{}] [DOWNLOAD_ERROR] Failed to create {}: {}", chrono::Local::now(), dest_path_clone, e);
+                                                            }
+                                                            let _ = tx_dl.send(AgentEvent {
+                                                                event_type: "DOWNLOAD_ERROR".to_string(),
+                                                                process_id: 0,
+                                                                parent_process_id: 0,
+                                                                process_name: "Agent".to_string(),
+                                                                details: format!("Failed to create file: {}", e),
+                                                                decoded_details: None,
+                                                                timestamp: chrono::Utc::now().timestamp_millis(),
+                                                                hostname: hostname_dl.clone(),
+                                                                digital_signature: None,
+                                                            });
+                                                            false
                                                         }
-                                                    },
-                                                    Err(e) => {
-                                                        println!("[AGENT] ERROR: Network request failed for {}: {}", url_clone, e);
-                                                        // Log to debug file
-                                                        if let Ok(mut file) = std::fs::OpenOptions::new().create(true).append(true).open("C:\\Mallab\\voodoobox_debug.log") {
-                                                            use std::io::Write;
-                                                            let _ = writeln!(file, "[{}] [DOWNLOAD_ERROR] Network request failed for {}: {}", chrono::Local::now(), url_clone, e);
-                                                        }
-                                                        let _ = tx_dl.send(AgentEvent {
-                                                            event_type: "DOWNLOAD_ERROR".to_string(),
-                                                            process_id: 0,
-                                                            parent_process_id: 0,
-                                                            process_name: "Agent".to_string(),
-                                                            details: format!("Network Request Failed: {}", e),
-                                                            decoded_details: None,
-                                                            timestamp: chrono::Utc::now().timestamp_millis(),
-                                                            hostname: hostname_dl.clone(),
-                                                            digital_signature: None,
-                                                        });
-                                                        false
                                                     }
-                                                };
-
-                                                if download_success {
-                                                            // 2. Explicit Verification
-                                                            if std::path::Path::new(&dest_path_clone).exists() {
-                                                                println!("[AGENT] File verified on disk: {}", dest_path_clone);
-                                                                let _ = tx_dl.send(AgentEvent {
-                                                                    event_type: "FILE_VERIFIED".to_string(),
-                                                                    process_id: 0,
-                                                                    parent_process_id: 0,
-                                                                    process_name: dest_path_clone.clone(),
-                                                                    details: "INTEGRITY: File verified on disk. Starting detonation.".to_string(),
-                                                                    decoded_details: None,
-                                                                    timestamp: chrono::Utc::now().timestamp_millis(),
-                                                                    hostname: hostname_dl.clone(),
-                                                                    digital_signature: None,
-                                                                });
-
-                                                                // 3. Detonate with Multi-Stage Logic
-                                                                let mut success = false;
-                                                                
-                                                                // Strategy A: Direct Execution (Retry loop for locking)
-                                                                println!("[AGENT] Attempting Strategy A: Direct Execution...");
-                                                                for attempt in 0..5 {
-                                                                    match std::process::Command::new(&dest_path_clone).spawn() {
-                                                                        Ok(child) => {
-                                                                            println!("[AGENT] Strategy A Successful! PID: {}", child.id());
-                                                                            let _ = tx_dl.send(AgentEvent {
-                                                                                event_type: "EXEC_SUCCESS".to_string(),
-                                                                                process_id: child.id(),
-                                                                                parent_process_id: std::process::id(),
-                                                                                process_name: dest_path_clone.clone(),
-                                                                                details: format!("Binary executed via Strategy A (Direct) - attempt {}", attempt + 1),
-                                                                                timestamp: chrono::Utc::now().timestamp_millis(),
-                                                                                hostname: hostname_dl.clone(),
-                                                                                decoded_details: None,
-                                                                                digital_signature: Some(signature_verifier::verify_signature(&dest_path_clone)),
-                                                                            });
-                                                                            success = true;
-                                                                            break;
-                                                                        },
-                                                                        Err(e) => {
-                                                                            println!("[AGENT] Strategy A (Attempt {}) Failed: {}", attempt + 1, e);
-                                                                            if e.raw_os_error() == Some(32) && attempt < 4 {
-                                                                                std::thread::sleep(std::time::Duration::from_millis(1000));
-                                                                            }
+                                                },
+                                                Err(e) => {
+                                                    println!("[AGENT] ERROR: Network request failed for {}: {}", url_clone, e);
+                                                    // Log to debug file
+                                                    if let Ok(mut file) = std::fs::OpenOptions::new().create(true).append(true).open("C:\\Mallab\\voodoobox_debug.log") {
+                                                        use std::io::Write;
+                                                        let _ = writeln!(file, "[{}] [DOWNLOAD_ERROR] Network request failed for {}: {}", chrono::Local::now(), url_clone, e);
+                                                    }
+                                                    let _ = tx_dl.send(AgentEvent {
+                                                        event_type: "DOWNLOAD_ERROR".to_string(),
+                                                        process_id: 0,
+                                                        parent_process_id: 0,
+                                                        process_name: "Agent".to_string(),
+                                                        details: format!("Network Request Failed: {}", e),
+                                                        decoded_details: None,
+                                                        timestamp: chrono::Utc::now().timestamp_millis(),
+                                                        hostname: hostname_dl.clone(),
+                                                        digital_signature: None,
+                                                    });
+                                                    false
+                                                }
+                                            };
+
+                                            if download_success {
+                                                        // 2. Explicit Verification
+                                                        if std::path::Path::new(&dest_path_clone).exists() {
+                                                            println!("[AGENT] File verified on disk: {}", dest_path_clone);
+                                                            let _ = tx_dl.send(AgentEvent {
+                                                                event_type: "FILE_VERIFIED".to_string(),
+                                                                process_id: 0,
+                                                                parent_process_id: 0,
+                                                                process_name: dest_path_clone.clone(),
+                                                                details: "INTEGRITY: File verified on disk. Starting detonation.".to_string(),
+                                                                decoded_details: None,
+                                                                timestamp: chrono::Utc::now().timestamp_millis(),
+                                                                hostname: hostname_dl.clone(),
+                                                                digital_signature: None,
+                                                            });
+
+                                                            // 3. Detonate with Multi-Stage Logic
+                                                            let mut success = false;
+                                                            
+                                                            // Strategy A: Direct Execution (Retry loop for locking)
+                                                            println!("[AGENT] Attempting Strategy A: Direct Execution...");
+                                                            for attempt in 0..5 {
+                                                                match std::process::Command::new(&dest_path_clone).spawn() {
+                                                                    Ok(child) => {
+                                                                        println!("[AGENT] Strategy A Successful! PID: {}", child.id());
+                                                                        let _ = tx_dl.send(AgentEvent {
+                                                                            event_type: "EXEC_SUCCESS".to_string(),
+                                                                            process_id: child.id(),
+                                                                            parent_process_id: std::process::id(),
+                                                                            process_name: dest_path_clone.clone(),
+                                                                            details: format!("Binary executed via Strategy A (Direct) - attempt {}", attempt + 1),
+                                                                            timestamp: chrono::Utc::now().timestamp_millis(),
+                                                                            hostname: hostname_dl.clone(),
+                                                                            decoded_details: None,
+                                                                            digital_signature: Some(signature_verifier::describe_signature(&dest_path_clone)),
+                                                                        });
+                                                                        success = true;
+                                                                        break;
+                                                                    },
+                                                                    Err(e) => {
+                                                                        println!("[AGENT] Strategy A (Attempt {}) Failed: {}", attempt + 1, e);
+                                                                        if e.raw_os_error() == Some(32) && attempt < 4 {
+                                                                            std::thread::sleep(std::time::Duration::from_millis(1000));
                                                                         }
                                                                     }
                                                                 }
+                                                            }
 
-                                                                // Strategy B: CMD Wrapper Fallback
-                                                                if !success {
-                                                                    println!("[AGENT] Strategy A Failed. Attempting Strategy B: CMD Wrapper...");
-                                                                    match std::process::Command::new("cmd")
-                                                                        .args(&["/C", "start", "", &dest_path_clone])
-                                                                        .spawn() 
-                                                                    {
-                                                                        Ok(child) => {
-                                                                            println!("[AGENT] Strategy B Successful! PID: {}", child.id());
-                                                                            let _ = tx_dl.send(AgentEvent {
-                                                                                event_type: "EXEC_SUCCESS".to_string(),
-                                                                                process_id: child.id(),
-                                                                                parent_process_id: std::process::id(),
-                                                                                process_name: dest_path_clone.clone(),
-                                                                                details: "Binary executed via Strategy B (CMD Wrapper)".to_string(),
-                                                                                timestamp: chrono::Utc::now().timestamp_millis(),
-                                                                                hostname: hostname_dl.clone(),
-                                                                                decoded_details: None,
-                                                                                digital_signature: Some(signature_verifier::verify_signature(&dest_path_clone)),
-                                                                            });
-                                                                            success = true;
-                                                                        },
-                                                                        Err(e) => {
-                                                                            println!("[AGENT] Strategy B Failed: {}", e);
-                                                                            let _ = tx_dl.send(AgentEvent {
-                                                                                event_type: "EXEC_ERROR".to_string(),
-                                                                                process_id: 0,
-                                                                                parent_process_id: 0,
-                                                                                process_name: dest_path_clone.clone(),
-                                                                                details: format!("Failed all execution strategies. Last error: {}", e),
-                                                                                timestamp: chrono::Utc::now().timestamp_millis(),
-                                                                                hostname: hostname_dl.clone(),
-                                                                                decoded_details: None,
-                                                                                digital_signature: None,
-                                                                            });
-                                                                        }
+                                                            // Strategy B: CMD Wrapper Fallback
+                                                            if !success {
+                                                                println!("[AGENT] Strategy A Failed. Attempting Strategy B: CMD Wrapper...");
+                                                                match std::process::Command::new("cmd")
+                                                                    .args(&["/C", "start", "", &dest_path_clone])
+                                                                    .spawn() 
+                                                                {
+                                                                    Ok(child) => {
+                                                                        println!("[AGENT] Strategy B Successful! PID: {}", child.id());
+                                                                        let _ = tx_dl.send(AgentEvent {
+                                                                            event_type: "EXEC_SUCCESS".to_string(),
+                                                                            process_id: child.id(),
+                                                                            parent_process_id: std::process::id(),
+                                                                            process_name: dest_path_clone.clone(),
+                                                                            details: "Binary executed via Strategy B (CMD Wrapper)".to_string(),
+                                                                            timestamp: chrono::Utc::now().timestamp_millis(),
+                                                                            hostname: hostname_dl.clone(),
+                                                                            decoded_details: None,
+                                                                            digital_signature: Some(signature_verifier::describe_signature(&dest_path_clone)),
+                                                                        });
+                                                                        success = true;
+                                                                    },
+                                                                    Err(e) => {
+                                                                        println!("[AGENT] Strategy B Failed: {}", e);
+                                                                        let _ = tx_dl.send(AgentEvent {
+                                                                            event_type: "EXEC_ERROR".to_string(),
+                                                                            process_id: 0,
+                                                                            parent_process_id: 0,
+                                                                            process_name: dest_path_clone.clone(),
+                                                                            details: format!("Failed all execution strategies. Last error: {}", e),
+                                                                            timestamp: chrono::Utc::now().timestamp_millis(),
+                                                                            hostname: hostname_dl.clone(),
+                                                                            decoded_details: None,
+                                                                            digital_signature: None,
+                                                                        });
                                                                     }
                                                                 }
-                                                            } else {
-                                                                println!("[AGENT] CRITICAL: File missing after download verification!");
                                                             }
-                                                } // Closes `if download_success`
-                                            }); // Closes `std::thread::spawn`
-                                        } // Closes `if let Some(url) = cmd.url`
-                                    }, // Closes the "DOWNLOAD_EXEC" match arm
-                                    _ => println!("Unknown command: {}", cmd.command),
-                                }
+                                                        } else {
+                                                            println!("[AGENT] CRITICAL: File missing after download verification!");
+                                                        }
+                                            } // Closes `if download_success`
+                                        }); // Closes `std::thread::spawn`
+                                    } // Closes `if let Some(url) = cmd.url`
+                                }, // Closes the "DOWNLOAD_EXEC" match arm
+                                "PERSISTENCE_SWEEP" => {
+                                    // End-of-analysis re-enumeration so late task/WMI installs are not missed
+                                    let _ = sweep_tx.send(());
+                                },
+                                "SET_NOISE_FILTER" => {
+                                    noise_filter = NoiseFilter {
+                                        noise: cmd.noise.unwrap_or_default(),
+                                        allow: cmd.allow.unwrap_or_default(),
+                                    };
+                                    println!("[AGENT] Noise filter updated: {} noise / {} allow patterns", noise_filter.noise.len(), noise_filter.allow.len());
+                                },
+                                "SET_SEVERITY_RULES" => {
+                                    if let Some(rules) = cmd.rules {
+                                        severity_scorer.set_rules(rules);
+                                        println!("[AGENT] Severity rules updated: {} rules", severity_scorer.rule_count());
+                                    }
+                                },
+                                "SET_DEFENSES" => {
+                                    // Sandbox prep: toggle Defender real-time / firewall (omitted fields are
+                                    // left alone) and report what the guest actually ended up with
+                                    let tx_def = evt_tx.clone();
+                                    let hostname_def = hostname.clone();
+                                    let (defender, firewall) = (cmd.defender, cmd.firewall);
+                                    std::thread::spawn(move || {
+                                        let state = defense_monitor::apply_defenses(defender, firewall);
+                                        println!("[AGENT] Defense state: {}", state);
+                                        let _ = tx_def.send(AgentEvent {
+                                            event_type: "AGENT_DEFENSE_STATE".to_string(),
+                                            process_id: std::process::id(),
+                                            parent_process_id: 0,
+                                            process_name: "mallab-agent".to_string(),
+                                            details: state.to_string(),
+                                            decoded_details: None,
+                                            timestamp: chrono::Utc::now().timestamp_millis(),
+                                            hostname: hostname_def,
+                                            digital_signature: None,
+                                        });
+                                    });
+                                },
+                                "CONFIGURE_MITM" => {
+                                    if let Some(proxy) = cmd.proxy {
+                                        let tx_mitm = evt_tx.clone();
+                                        let hostname_mitm = hostname.clone();
+                                        let ca_pem = cmd.ca_pem;
+                                        std::thread::spawn(move || {
+                                            let state = mitm::configure(ca_pem.as_deref(), &proxy);
+                                            println!("[AGENT] TLS interception setup: {}", state);
+                                            let _ = tx_mitm.send(AgentEvent {
+                                                event_type: "AGENT_MITM_STATE".to_string(),
+                                                process_id: std::process::id(),
+                                                parent_process_id: 0,
+                                                process_name: "mallab-agent".to_string(),
+                                                details: state.to_string(),
+                                                decoded_details: None,
+                                                timestamp: chrono::Utc::now().timestamp_millis(),
+                                                hostname: hostname_mitm,
+                                                digital_signature: None,
+                                            });
+                                        });
+                                    } else {
+                                        println!("[AGENT] CONFIGURE_MITM ignored: proxy is required");
+                                    }
+                                },
+                                "SIMULATE_INTERACTION" => {
+                                    let secs = cmd.duration_seconds.unwrap_or(300);
+                                    std::thread::spawn(move || interaction::simulate(Duration::from_secs(secs)));
+                                },
+                                "UPDATE_AGENT" => {
+                                    if let (Some(url), Some(sha256)) = (cmd.url, cmd.sha256) {
+                                        // Release URLs are relative to the backend that hosts them
+                                        let full_url = if url.starts_with('/') { format!("{}{}", backend_url, url) } else { url };
+                                        let tx_upd = evt_tx.clone();
+                                        let hostname_upd = hostname.clone();
+                                        std::thread::spawn(move || {
+                                            perform_self_update(&full_url, &sha256, tx_upd, hostname_upd);
+                                        });
+                                    } else {
+                                        println!("[AGENT] UPDATE_AGENT ignored: url and sha256 are both required");
+                                    }
+                                },
+                                _ => println!("Unknown command: {}", cmd.command),
                             }
                         }
                     }
@@ -1238,8 +1644,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
             // Events from threads (FS/Memory/Commands)
             Some(evt) = evt_rx.recv() => {
-                let msg = serde_json::to_string(&evt)? + "\n";
-                let _ = stream.write_all(msg.as_bytes()).await;
+                if noise_filter.should_drop(&evt) {
+                    continue;
+                }
+                let (severity, category) = severity_scorer.score(&evt.event_type, &evt.process_name, &evt.details);
+                let msg = serde_json::to_string(&severity::ScoredEvent { event: &evt, severity, category })? + "\n";
+                let _ = write_half.write_all(msg.as_bytes()).await;
+
+                if is_screenshot_trigger(&evt, &mut sample_pids) {
+                    let b_url = backend_url.clone();
+                    let sid = session_id.clone();
+                    std::thread::spawn(move || {
+                        take_and_upload_screenshot(&b_url, &sid, Some(&evt));
+                    });
+                }
             }
 
             // Periodic Scans (Process + Network + Memory + Registry)
@@ -1249,26 +1667,48 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                 // 1. Memory Forensic Scan (for existing processes)
                 for &pid in &current_pids {
+                    // Only carve each hollowed process once; re-dumping every tick floods the backend
+                    if dumped_pids.contains(&pid) {
+                        continue;
+                    }
                     if let Ok(true) = mem_utils::scan_process_hollowing(pid) {
+                        dumped_pids.insert(pid);
                         let dump_path = format!("C:\\Users\\Public\\dump_{}.bin", pid);
-                        let dump_msg = match mem_utils::dump_process_memory(pid, &dump_path) {
-                            Ok(_) => format!("Process Hollowing detected! Memory headers do not match disk image. Dump saved to {}.", dump_path),
-                            Err(e) => format!("Process Hollowing detected! Memory headers do not match disk image. (Dump failed: {})", e),
-                        };
-
-                        let _ = evt_tx.send(AgentEvent {
+                        let dump_result = mem_utils::dump_process_memory(pid, &dump_path);
+                        let mut event = AgentEvent {
                             event_type: "MEMORY_ANOMALY".to_string(),
                             process_id: pid,
                             parent_process_id: 0,
                             process_name: sys.process(sysinfo::Pid::from(pid as usize)).map(|p| p.name()).unwrap_or("Unknown").to_string(),
-                            details: dump_msg,
+                            details: String::new(),
                             decoded_details: None,
                             timestamp: chrono::Utc::now().timestamp_millis(),
                             hostname: hostname.clone(),
                             digital_signature: None,
-                        });
+                        };
+
+                        match dump_result {
+                            Ok(_) => {
+                                // Upload off the scan loop, then emit the event with the stored location
+                                let tx_dump = evt_tx.clone();
+                                let b_url = backend_url.clone();
+                                let sid = session_id.clone();
+                                tokio::spawn(async move {
+                                    event.details = match upload_memory_dump(&b_url, &sid, &dump_path, pid).await {
+                                        Ok(url) => format!("Process Hollowing detected! Memory headers do not match disk image. Dump saved to {} and uploaded to {}", dump_path, url),
+                                        Err(e) => format!("Process Hollowing detected! Memory headers do not match disk image. Dump saved to {}. (Upload failed: {})", dump_path, e),
+                                    };
+                                    let _ = tx_dump.send(event);
+                                });
+                            }
+                            Err(e) => {
+                                event.details = format!("Process Hollowing detected! Memory headers do not match disk image. (Dump failed: {})", e);
+                                let _ = evt_tx.send(event);
+                            }
+                        }
                     }
                 }
+                dumped_pids.retain(|pid| current_pids.contains(pid));
 
                 // 2. Process Lifecycle
                 for &pid in current_pids.difference(&known_pids) {
@@ -1276,7 +1716,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         // Capture Signature
                         let exe_path = p.exe().to_string_lossy().to_string();
                         let sig = if !exe_path.is_empty() {
-                            signature_verifier::verify_signature(&exe_path)
+                            signature_verifier::describe_signature(&exe_path)
                         } else {
                             "Unknown (No Path)".to_string()
                         };
@@ -1361,6 +1801,54 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     }
                 }
 
+                // 3b. COM Hijack + Startup Folder Persistence
+                for finding in persistence_monitor.poll() {
+                    let sig = finding.path.as_ref()
+                        .filter(|p| std::path::Path::new(p).exists())
+                        .map(|p| signature_verifier::describe_signature(p));
+                    let _ = evt_tx.send(AgentEvent {
+                        event_type: finding.event_type.to_string(),
+                        process_id: 0,
+                        parent_process_id: 0,
+                        process_name: if finding.event_type == "COM_HIJACK" { "Registry".to_string() } else { "StartupFolder".to_string() },
+                        details: finding.details,
+                        decoded_details: None,
+                        timestamp: chrono::Utc::now().timestamp_millis(),
+                        hostname: hostname.clone(),
+                        digital_signature: sig,
+                    });
+                }
+
+                // 3c. Root/CA Certificate Store + Proxy Tampering
+                for finding in trust_monitor.poll() {
+                    let _ = evt_tx.send(AgentEvent {
+                        event_type: finding.event_type.to_string(),
+                        process_id: 0,
+                        parent_process_id: 0,
+                        process_name: if finding.event_type == "CERT_INSTALLED" { "CertStore".to_string() } else { "ProxySettings".to_string() },
+                        details: finding.details,
+                        decoded_details: None,
+                        timestamp: chrono::Utc::now().timestamp_millis(),
+                        hostname: hostname.clone(),
+                        digital_signature: None,
+                    });
+                }
+
+                // 3d. Defender Exclusions + Tamper Settings
+                for finding in defense_monitor.poll() {
+                    let _ = evt_tx.send(AgentEvent {
+                        event_type: finding.event_type.to_string(),
+                        process_id: 0,
+                        parent_process_id: 0,
+                        process_name: "DefenderConfig".to_string(),
+                        details: finding.details,
+                        decoded_details: None,
+                        timestamp: chrono::Utc::now().timestamp_millis(),
+                        hostname: hostname.clone(),
+                        digital_signature: None,
+                    });
+                }
+
                 // 4. Network Scan
                 let af = netstat2::AddressFamilyFlags::IPV4;
                 let proto = netstat2::ProtocolFlags::TCP;
@@ -1413,10 +1901,30 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
                 dns_state = current_dns;
 
+                // 5b. New dialog windows (MessageBox-style popups from the sample)
+                let current_dialogs = enumerate_dialog_windows();
+                for (hwnd, (pid, title)) in &current_dialogs {
+                    if known_dialogs.contains_key(hwnd) {
+                        continue;
+                    }
+                    let _ = evt_tx.send(AgentEvent {
+                        event_type: "WINDOW_DIALOG".to_string(),
+                        process_id: *pid,
+                        parent_process_id: 0,
+                        process_name: sys.process(sysinfo::Pid::from(*pid as usize)).map(|p| p.name()).unwrap_or("Unknown").to_string(),
+                        details: format!("Dialog window appeared: '{}'", title),
+                        decoded_details: None,
+                        timestamp: chrono::Utc::now().timestamp_millis(),
+                        hostname: hostname.clone(),
+                        digital_signature: None,
+                    });
+                }
+                known_dialogs = current_dialogs;
+
                 // 6. Periodic Screenshot (every 30s approx, assuming 5s loop)
                 screenshot_iter += 1;
                 if screenshot_iter >= 6 {
-                    take_and_upload_screenshot(&backend_url);
+                    take_and_upload_screenshot(&backend_url, &session_id, None);
                     screenshot_iter = 0;
                 }
 
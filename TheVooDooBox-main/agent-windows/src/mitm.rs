@@ -0,0 +1,37 @@
+use crate::defense_monitor::run_powershell;
+use crate::trust_monitor::HarnessEdit;
+
+/// Trusts the proxy CA and points WinINET and WinHTTP at the proxy; returns what was applied.
+pub fn configure(ca_pem: Option<&str>, proxy: &str) -> serde_json::Value {
+    // Our own cert/proxy edits must not show up as CERT_INSTALLED / PROXY_HIJACK
+    let _edit = HarnessEdit::begin();
+    let mut errors = Vec::new();
+
+    let mut ca_installed = false;
+    if let Some(pem) = ca_pem {
+        let path = std::env::temp_dir().join("voodoobox-mitm-ca.pem");
+        match std::fs::write(&path, pem) {
+            Ok(()) => match std::process::Command::new("certutil").args(["-addstore", "-f", "Root"]).arg(&path).output() {
+                Ok(out) if out.status.success() => ca_installed = true,
+                Ok(out) => errors.push(format!("certutil: {}", String::from_utf8_lossy(&out.stdout).trim())),
+                Err(e) => errors.push(format!("certutil: {}", e)),
+            },
+            Err(e) => errors.push(format!("CA write: {}", e)),
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+
+    let script = format!(
+        "$k = 'HKCU:\\Software\\Microsoft\\Windows\\CurrentVersion\\Internet Settings'; \
+         Set-ItemProperty -Path $k -Name ProxyEnable -Value 1; \
+         Set-ItemProperty -Path $k -Name ProxyServer -Value '{0}'; \
+         Set-ItemProperty -Path $k -Name ProxyOverride -Value '<local>'; \
+         netsh winhttp set proxy proxy-server='{0}' bypass-list='<local>' | Out-Null",
+        proxy.replace('\'', "")
+    );
+    if let Err(e) = run_powershell(&script) {
+        errors.push(format!("Proxy: {}", e));
+    }
+
+    serde_json::json!({ "proxy": proxy, "ca_installed": ca_installed, "errors": errors })
+}
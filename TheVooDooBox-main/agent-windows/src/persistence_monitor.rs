@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::path::PathBuf;
+use winapi::shared::minwindef::{DWORD, HKEY};
+use winapi::um::winnt::{KEY_READ, REG_EXPAND_SZ, REG_SZ};
+use winapi::um::winreg::{RegCloseKey, RegEnumKeyExA, RegOpenKeyExA, RegQueryValueExA, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE};
+
+const CLSID_ROOT: &str = "Software\\Classes\\CLSID";
+
+pub struct PersistenceFinding {
+    pub event_type: &'static str,
+    pub details: String,
+    /// File on disk the finding points at (DLL or startup entry), for signature checks.
+    pub path: Option<String>,
+}
+
+/// Per-user COM InprocServer32 registrations and the Startup folders.
+pub struct PersistenceMonitor {
+    com_state: Option<HashMap<String, String>>,
+    startup_state: Option<HashMap<PathBuf, u64>>,
+    startup_dirs: Vec<PathBuf>,
+}
+
+impl PersistenceMonitor {
+    pub fn new() -> Self {
+        let mut startup_dirs = Vec::new();
+        if let Ok(appdata) = std::env::var("APPDATA") {
+            startup_dirs.push(PathBuf::from(appdata).join("Microsoft\\Windows\\Start Menu\\Programs\\Startup"));
+        }
+        let program_data = std::env::var("ProgramData").unwrap_or_else(|_| "C:\\ProgramData".to_string());
+        startup_dirs.push(PathBuf::from(program_data).join("Microsoft\\Windows\\Start Menu\\Programs\\StartUp"));
+
+        Self { com_state: None, startup_state: None, startup_dirs }
+    }
+
+    /// Returns findings since the previous poll. The first call only records a baseline.
+    pub fn poll(&mut self) -> Vec<PersistenceFinding> {
+        let mut findings = Vec::new();
+
+        let current_com = unsafe { enumerate_inproc_servers(HKEY_CURRENT_USER) };
+        if let Some(old) = &self.com_state {
+            for (clsid, dll) in &current_com {
+                if old.get(clsid) == Some(dll) {
+                    continue;
+                }
+                // A per-user registration that shadows a machine-wide one is the classic hijack
+                let shadowed = unsafe { read_inproc_server(HKEY_LOCAL_MACHINE, clsid) };
+                let change = match old.get(clsid) {
+                    Some(prev) => format!("modified (was '{}')", prev),
+                    None => "added".to_string(),
+                };
+                let details = match shadowed {
+                    Some(hklm_dll) => format!("COM Hijack: HKCU\\{}\\{}\\InprocServer32 {} -> '{}' overrides HKLM server '{}'", CLSID_ROOT, clsid, change, dll, hklm_dll),
+                    None => format!("COM Registration: HKCU\\{}\\{}\\InprocServer32 {} -> '{}'", CLSID_ROOT, clsid, change, dll),
+                };
+                findings.push(PersistenceFinding { event_type: "COM_HIJACK", details, path: Some(dll.clone()) });
+            }
+        }
+        self.com_state = Some(current_com);
+
+        let current_startup = self.snapshot_startup_dirs();
+        if let Some(old) = &self.startup_state {
+            for (path, mtime) in &current_startup {
+                let change = match old.get(path) {
+                    None => "created",
+                    Some(prev) if prev != mtime => "modified",
+                    _ => continue,
+                };
+                findings.push(PersistenceFinding {
+                    event_type: "STARTUP_PERSISTENCE",
+                    details: format!("Startup folder entry {}: {}", change, path.display()),
+                    path: Some(path.to_string_lossy().to_string()),
+                });
+            }
+            for path in old.keys().filter(|p| !current_startup.contains_key(*p)) {
+                findings.push(PersistenceFinding {
+                    event_type: "STARTUP_PERSISTENCE",
+                    details: format!("Startup folder entry removed: {}", path.display()),
+                    path: None,
+                });
+            }
+        }
+        self.startup_state = Some(current_startup);
+
+        findings
+    }
+
+    fn snapshot_startup_dirs(&self) -> HashMap<PathBuf, u64> {
+        let mut entries = HashMap::new();
+        for dir in &self.startup_dirs {
+            if let Ok(read) = std::fs::read_dir(dir) {
+                for entry in read.flatten() {
+                    let mtime = entry.metadata().ok()
+                        .and_then(|m| m.modified().ok())
+                        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                        .map(|d| d.as_secs())
+                        .unwrap_or(0);
+                    entries.insert(entry.path(), mtime);
+                }
+            }
+        }
+        entries
+    }
+}
+
+/// Scheduled tasks and WMI event subscriptions; shells out, so polled less often.
+pub struct ScheduledPersistenceMonitor {
+    tasks: HashMap<String, String>,
+    wmi: HashMap<String, String>,
+}
+
+impl ScheduledPersistenceMonitor {
+    /// Takes the baseline immediately so pre-existing gold-image tasks are never reported.
+    pub fn new() -> Self {
+        Self { tasks: enumerate_scheduled_tasks(), wmi: enumerate_wmi_subscriptions().unwrap_or_default() }
+    }
+
+    pub fn poll(&mut self) -> Vec<PersistenceFinding> {
+        let mut findings = Vec::new();
+
+        let current_tasks = enumerate_scheduled_tasks();
+        // An empty result means schtasks failed; keep the old baseline rather than reporting everything as new
+        if !current_tasks.is_empty() {
+            for (name, xml) in &current_tasks {
+                let change = match self.tasks.get(name) {
+                    None => "SCHTASK_CREATED",
+                    Some(old) if old != xml => "SCHTASK_MODIFIED",
+                    _ => continue,
+                };
+                findings.push(PersistenceFinding {
+                    event_type: change,
+                    // The full task XML follows the summary line
+                    details: format!("Scheduled Task {}: {} Action: {}\n{}", if change == "SCHTASK_CREATED" { "Created" } else { "Modified" }, name, extract_task_action(xml), xml),
+                    path: None,
+                });
+            }
+            self.tasks = current_tasks;
+        }
+
+        if let Some(current_wmi) = enumerate_wmi_subscriptions() {
+            for (key, json) in &current_wmi {
+                if self.wmi.get(key) == Some(json) {
+                    continue;
+                }
+                findings.push(PersistenceFinding {
+                    event_type: "WMI_SUBSCRIPTION",
+                    details: format!("WMI Subscription {}: {}\n{}", if self.wmi.contains_key(key) { "Modified" } else { "Created" }, key, json),
+                    path: None,
+                });
+            }
+            self.wmi = current_wmi;
+        }
+
+        findings
+    }
+}
+
+/// Parses `schtasks /query /xml ONE`, which prefixes each task with a `<!-- \Path\Name -->` comment.
+fn enumerate_scheduled_tasks() -> HashMap<String, String> {
+    let mut tasks = HashMap::new();
+    let output = match std::process::Command::new("schtasks").args(["/query", "/xml", "ONE"]).output() {
+        Ok(o) if o.status.success() => o,
+        _ => return tasks,
+    };
+    let text = String::from_utf8_lossy(&output.stdout);
+
+    for chunk in text.split("<!-- ").skip(1) {
+        if let Some((name, rest)) = chunk.split_once(" -->") {
+            let xml = match rest.find("</Task>") {
+                Some(end) => &rest[..end + 7],
+                None => rest,
+            };
+            tasks.insert(name.trim().to_string(), xml.trim().to_string());
+        }
+    }
+    tasks
+}
+
+fn extract_task_action(xml: &str) -> String {
+    let tag = |name: &str| -> Option<String> {
+        let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+        let end = xml[start..].find(&format!("</{}>", name))? + start;
+        Some(xml[start..end].trim().to_string())
+    };
+    match (tag("Command"), tag("Arguments")) {
+        (Some(cmd), Some(args)) => format!("{} {}", cmd, args),
+        (Some(cmd), None) => cmd,
+        _ => "Unknown".to_string(),
+    }
+}
+
+/// Filters, consumers and bindings in root\subscription, keyed by class and name.
+fn enumerate_wmi_subscriptions() -> Option<HashMap<String, String>> {
+    let mut subs = HashMap::new();
+    let script = "$ErrorActionPreference='SilentlyContinue'; \
+        @('__EventFilter','CommandLineEventConsumer','ActiveScriptEventConsumer','__FilterToConsumerBinding') | \
+        ForEach-Object { Get-CimInstance -Namespace root\\subscription -ClassName $_ } | \
+        Select-Object @{n='Class';e={$_.CimClass.CimClassName}},Name,Query,CommandLineTemplate,ScriptText,@{n='Filter';e={[string]$_.Filter}},@{n='Consumer';e={[string]$_.Consumer}} | \
+        ConvertTo-Json -Compress";
+
+    let output = match std::process::Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", script])
+        .output()
+    {
+        Ok(o) => o,
+        Err(_) => return None,
+    };
+
+    // No output at all means no subscriptions; anything unparseable means the query failed
+    if output.stdout.iter().all(|b| b.is_ascii_whitespace()) {
+        return Some(subs);
+    }
+    let parsed: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+    // ConvertTo-Json emits a bare object when there is exactly one result
+    let items = match parsed {
+        serde_json::Value::Array(a) => a,
+        other => vec![other],
+    };
+
+    for item in items {
+        let class = item["Class"].as_str().unwrap_or("Unknown");
+        let name = item["Name"].as_str()
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| format!("{} -> {}", item["Filter"].as_str().unwrap_or("?"), item["Consumer"].as_str().unwrap_or("?")));
+        subs.insert(format!("{}:{}", class, name), item.to_string());
+    }
+    Some(subs)
+}
+
+unsafe fn enumerate_inproc_servers(hive: HKEY) -> HashMap<String, String> {
+    let mut servers = HashMap::new();
+    for clsid in enumerate_subkeys(hive, CLSID_ROOT) {
+        if let Some(dll) = read_inproc_server(hive, &clsid) {
+            servers.insert(clsid, dll);
+        }
+    }
+    servers
+}
+
+/// Names of the immediate subkeys of `path`; empty if the key does not exist.
+pub(crate) unsafe fn enumerate_subkeys(hive: HKEY, path: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let c_path = match CString::new(path) {
+        Ok(p) => p,
+        Err(_) => return names,
+    };
+    let mut hkey: HKEY = std::ptr::null_mut();
+
+    if RegOpenKeyExA(hive, c_path.as_ptr(), 0, KEY_READ, &mut hkey) != 0 {
+        return names;
+    }
+
+    let mut index = 0;
+    loop {
+        let mut name_buf = [0i8; 256];
+        let mut name_len: DWORD = 256;
+        let ret = RegEnumKeyExA(
+            hkey,
+            index,
+            name_buf.as_mut_ptr(),
+            &mut name_len,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        );
+        if ret != 0 { break; } // ERROR_NO_MORE_ITEMS
+
+        let name_u8: Vec<u8> = name_buf[..name_len as usize].iter().map(|&c| c as u8).collect();
+        names.push(String::from_utf8_lossy(&name_u8).to_string());
+        index += 1;
+    }
+    RegCloseKey(hkey);
+    names
+}
+
+unsafe fn read_inproc_server(hive: HKEY, clsid: &str) -> Option<String> {
+    let c_subkey = CString::new(format!("{}\\{}\\InprocServer32", CLSID_ROOT, clsid)).ok()?;
+    let mut hkey: HKEY = std::ptr::null_mut();
+    if RegOpenKeyExA(hive, c_subkey.as_ptr(), 0, KEY_READ, &mut hkey) != 0 {
+        return None;
+    }
+
+    let mut type_code: DWORD = 0;
+    let mut data_buf = [0u8; 1024];
+    let mut data_len: DWORD = data_buf.len() as DWORD;
+    // NULL value name reads the key's (Default) value
+    let ret = RegQueryValueExA(hkey, std::ptr::null(), std::ptr::null_mut(), &mut type_code, data_buf.as_mut_ptr(), &mut data_len);
+    RegCloseKey(hkey);
+
+    if ret != 0 || (type_code != REG_SZ && type_code != REG_EXPAND_SZ) {
+        return None;
+    }
+    let actual_len = if data_len > 0 && data_buf[(data_len - 1) as usize] == 0 { data_len - 1 } else { data_len };
+    Some(String::from_utf8_lossy(&data_buf[..actual_len as usize]).to_string())
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+// Source-side severity scoring. The rule table lives in the backend, which pushes it
+// (SET_SEVERITY_RULES) when the session starts; until then everything scores the default.
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct SeverityRule {
+    /// Exact event type, or a prefix ending in '*' (e.g. "FILE_*").
+    pub event_type: String,
+    /// Optional case-insensitive substring that must appear in the process name or details.
+    #[serde(default)]
+    pub details_contains: Option<String>,
+    /// 0-100; 25 low, 50 medium, 75 high, 90+ critical.
+    pub severity: u8,
+    pub category: String,
+}
+
+/// Unmatched events still get a score so consumers can sort without special-casing None.
+const DEFAULT_SEVERITY: u8 = 10;
+const DEFAULT_CATEGORY: &str = "other";
+
+pub struct SeverityScorer {
+    rules: Vec<SeverityRule>,
+}
+
+impl SeverityScorer {
+    pub fn new() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    pub fn set_rules(&mut self, rules: Vec<SeverityRule>) {
+        self.rules = rules.into_iter()
+            .map(|mut r| {
+                r.details_contains = r.details_contains.map(|c| c.to_lowercase());
+                r
+            })
+            .collect();
+    }
+
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Highest-severity matching rule wins, so rule order never matters.
+    pub fn score(&self, event_type: &str, process_name: &str, details: &str) -> (u8, String) {
+        let haystack = format!("{} {}", process_name, details).to_lowercase();
+        self.rules.iter()
+            .filter(|r| match r.event_type.strip_suffix('*') {
+                Some(prefix) => event_type.starts_with(prefix),
+                None => event_type == r.event_type,
+            })
+            .filter(|r| r.details_contains.as_ref().map_or(true, |c| haystack.contains(c.as_str())))
+            .max_by_key(|r| r.severity)
+            .map(|r| (r.severity, r.category.clone()))
+            .unwrap_or_else(|| (DEFAULT_SEVERITY, DEFAULT_CATEGORY.to_string()))
+    }
+}
+
+/// Wire form of an event with its score attached; flattening keeps the base schema unchanged.
+#[derive(Serialize)]
+pub struct ScoredEvent<'a, T: Serialize> {
+    #[serde(flatten)]
+    pub event: &'a T,
+    pub severity: u8,
+    pub category: String,
+}
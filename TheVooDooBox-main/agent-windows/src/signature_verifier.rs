@@ -3,6 +3,17 @@ use winapi::shared::guiddef::GUID;
 use winapi::um::handleapi::INVALID_HANDLE_VALUE;
 use std::ffi::OsStr;
 use std::os::windows::ffi::OsStrExt;
+use winapi::shared::minwindef::{BOOL, BYTE, DWORD};
+use winapi::um::wincrypt::{
+    CryptQueryObject, CryptMsgGetParam, CryptMsgClose, CertCloseStore, CertFindCertificateInStore,
+    CertGetNameStringW, CertFreeCertificateContext, CERT_INFO, CMSG_SIGNER_INFO, HCERTSTORE, HCRYPTMSG,
+    CERT_QUERY_OBJECT_FILE, CERT_QUERY_CONTENT_FLAG_ALL, CERT_QUERY_FORMAT_FLAG_ALL, CMSG_SIGNER_INFO_PARAM,
+    CERT_FIND_SUBJECT_CERT, CERT_NAME_SIMPLE_DISPLAY_TYPE, X509_ASN_ENCODING, PKCS_7_ASN_ENCODING,
+};
+use winapi::um::winnt::{HANDLE, LPCWSTR};
+use std::collections::HashMap;
+use std::os::windows::io::AsRawHandle;
+use std::sync::{Mutex, OnceLock};
 use std::ptr;
 use chrono;
 
@@ -123,3 +134,182 @@ pub fn test_verifier() {
         println!("[VERIFIER] Self-Test Skipped: notepad.exe not found.");
     }
 }
+
+// Catalog APIs live in wintrust.dll but winapi 0.3 only ships the mscat structs, not the functions.
+#[repr(C)]
+#[allow(non_snake_case)]
+struct CATALOG_INFO {
+    cbStruct: DWORD,
+    wszCatalogFile: [u16; 260],
+}
+
+#[link(name = "wintrust")]
+extern "system" {
+    fn CryptCATAdminAcquireContext2(phCatAdmin: *mut HANDLE, pgSubsystem: *const GUID, pwszHashAlgorithm: LPCWSTR, pStrongHashPolicy: *const std::ffi::c_void, dwFlags: DWORD) -> BOOL;
+    fn CryptCATAdminCalcHashFromFileHandle2(hCatAdmin: HANDLE, hFile: HANDLE, pcbHash: *mut DWORD, pbHash: *mut BYTE, dwFlags: DWORD) -> BOOL;
+    fn CryptCATAdminEnumCatalogFromHash(hCatAdmin: HANDLE, pbHash: *mut BYTE, cbHash: DWORD, dwFlags: DWORD, phPrevCatInfo: *mut HANDLE) -> HANDLE;
+    fn CryptCATCatalogInfoFromContext(hCatInfo: HANDLE, psCatInfo: *mut CATALOG_INFO, dwFlags: DWORD) -> BOOL;
+    fn CryptCATAdminReleaseCatalogContext(hCatAdmin: HANDLE, hCatInfo: HANDLE, dwFlags: DWORD) -> BOOL;
+    fn CryptCATAdminReleaseContext(hCatAdmin: HANDLE, dwFlags: DWORD) -> BOOL;
+}
+
+fn signature_cache() -> &'static Mutex<HashMap<String, String>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Full Authenticode verdict for telemetry: validity plus the signer's display name, e.g.
+/// "Signed (Verified) | Signer: Microsoft Windows". Most inbox binaries carry no embedded
+/// signature and are only signed through a system catalog, so that is checked as a fallback.
+/// Results are cached since the same DLLs load into nearly every process; the key includes
+/// size and mtime so a dropped file that is later overwritten gets re-checked.
+pub fn describe_signature(file_path: &str) -> String {
+    let stamp = std::fs::metadata(file_path)
+        .map(|m| format!("{}:{:?}", m.len(), m.modified().ok()))
+        .unwrap_or_default();
+    let key = format!("{}|{}", file_path.to_lowercase(), stamp);
+    if let Some(hit) = signature_cache().lock().unwrap().get(&key) {
+        return hit.clone();
+    }
+
+    let mut status = verify_signature(file_path);
+    let mut signed_blob = file_path.to_string();
+    if status.starts_with("Unsigned") {
+        if let Some(catalog) = find_catalog(file_path) {
+            let catalog_status = verify_signature(&catalog);
+            if catalog_status == "Signed (Verified)" {
+                status = "Signed (Verified, Catalog)".to_string();
+                signed_blob = catalog;
+            }
+        }
+    }
+
+    let described = if status.starts_with("Signed") {
+        match signer_name(&signed_blob) {
+            Some(signer) => format!("{} | Signer: {}", status, signer),
+            None => status,
+        }
+    } else {
+        status
+    };
+
+    signature_cache().lock().unwrap().insert(key, described.clone());
+    described
+}
+
+/// Normalises Sysmon's ImageLoad Signed/Signature/SignatureStatus fields into the same
+/// format as `describe_signature`. Returns None when Sysmon had no verdict.
+pub fn from_sysmon(signed: &str, signature: &str, status: &str) -> Option<String> {
+    if signed.is_empty() || signed == "-" {
+        return None;
+    }
+    if !signed.eq_ignore_ascii_case("true") {
+        return Some(format!("Unsigned (Sysmon: {})", status));
+    }
+    let verdict = if status.eq_ignore_ascii_case("Valid") {
+        "Signed (Verified)".to_string()
+    } else {
+        format!("Signed (Invalid: {})", status)
+    };
+    if signature.is_empty() || signature == "-" {
+        Some(verdict)
+    } else {
+        Some(format!("{} | Signer: {}", verdict, signature))
+    }
+}
+
+/// Path of the system catalog that lists the file's hash, if any.
+fn find_catalog(file_path: &str) -> Option<String> {
+    let file = std::fs::File::open(file_path).ok()?;
+    let sha256: Vec<u16> = OsStr::new("SHA256").encode_wide().chain(std::iter::once(0)).collect();
+
+    unsafe {
+        let mut admin: HANDLE = ptr::null_mut();
+        if CryptCATAdminAcquireContext2(&mut admin, ptr::null(), sha256.as_ptr(), ptr::null(), 0) == 0 {
+            return None;
+        }
+
+        let mut hash = [0u8; 64];
+        let mut hash_len: DWORD = hash.len() as DWORD;
+        let mut result = None;
+        if CryptCATAdminCalcHashFromFileHandle2(admin, file.as_raw_handle() as HANDLE, &mut hash_len, hash.as_mut_ptr(), 0) != 0 {
+            let cat_ctx = CryptCATAdminEnumCatalogFromHash(admin, hash.as_mut_ptr(), hash_len, 0, ptr::null_mut());
+            if !cat_ctx.is_null() {
+                let mut info = CATALOG_INFO { cbStruct: std::mem::size_of::<CATALOG_INFO>() as DWORD, wszCatalogFile: [0; 260] };
+                if CryptCATCatalogInfoFromContext(cat_ctx, &mut info, 0) != 0 {
+                    let len = info.wszCatalogFile.iter().position(|&c| c == 0).unwrap_or(info.wszCatalogFile.len());
+                    result = Some(String::from_utf16_lossy(&info.wszCatalogFile[..len]));
+                }
+                CryptCATAdminReleaseCatalogContext(admin, cat_ctx, 0);
+            }
+        }
+        CryptCATAdminReleaseContext(admin, 0);
+        result
+    }
+}
+
+/// Display name of the first signer's certificate in a PKCS#7-signed file (PE or catalog).
+fn signer_name(file_path: &str) -> Option<String> {
+    let wide_path: Vec<u16> = OsStr::new(file_path).encode_wide().chain(std::iter::once(0)).collect();
+
+    unsafe {
+        let mut encoding: DWORD = 0;
+        let mut content_type: DWORD = 0;
+        let mut format_type: DWORD = 0;
+        let mut store: HCERTSTORE = ptr::null_mut();
+        let mut msg: HCRYPTMSG = ptr::null_mut();
+        if CryptQueryObject(
+            CERT_QUERY_OBJECT_FILE,
+            wide_path.as_ptr() as *const _,
+            CERT_QUERY_CONTENT_FLAG_ALL,
+            CERT_QUERY_FORMAT_FLAG_ALL,
+            0,
+            &mut encoding,
+            &mut content_type,
+            &mut format_type,
+            &mut store,
+            &mut msg,
+            ptr::null_mut(),
+        ) == 0 {
+            return None;
+        }
+
+        let mut name = None;
+        let mut info_len: DWORD = 0;
+        if !msg.is_null() && CryptMsgGetParam(msg, CMSG_SIGNER_INFO_PARAM, 0, ptr::null_mut(), &mut info_len) != 0 {
+            // u64 backing keeps the CMSG_SIGNER_INFO pointer fields aligned
+            let mut buf = vec![0u64; (info_len as usize + 7) / 8];
+            if CryptMsgGetParam(msg, CMSG_SIGNER_INFO_PARAM, 0, buf.as_mut_ptr() as *mut _, &mut info_len) != 0 {
+                let signer = &*(buf.as_ptr() as *const CMSG_SIGNER_INFO);
+                let mut cert_info: CERT_INFO = std::mem::zeroed();
+                cert_info.Issuer = signer.Issuer;
+                cert_info.SerialNumber = signer.SerialNumber;
+
+                let cert = CertFindCertificateInStore(
+                    store,
+                    X509_ASN_ENCODING | PKCS_7_ASN_ENCODING,
+                    0,
+                    CERT_FIND_SUBJECT_CERT,
+                    &cert_info as *const _ as *const _,
+                    ptr::null(),
+                );
+                if !cert.is_null() {
+                    let mut name_buf = [0u16; 256];
+                    let len = CertGetNameStringW(cert, CERT_NAME_SIMPLE_DISPLAY_TYPE, 0, ptr::null_mut(), name_buf.as_mut_ptr(), name_buf.len() as DWORD);
+                    if len > 1 {
+                        name = Some(String::from_utf16_lossy(&name_buf[..len as usize - 1]));
+                    }
+                    CertFreeCertificateContext(cert);
+                }
+            }
+        }
+
+        if !msg.is_null() {
+            CryptMsgClose(msg);
+        }
+        if !store.is_null() {
+            CertCloseStore(store, 0);
+        }
+        name
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use winapi::shared::minwindef::{DWORD, HKEY};
+use winapi::um::winnt::{KEY_READ, REG_BINARY, REG_DWORD, REG_EXPAND_SZ, REG_SZ};
+use winapi::um::winreg::{RegCloseKey, RegOpenKeyExA, RegQueryValueExA, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE};
+use crate::persistence_monitor::{enumerate_subkeys, PersistenceFinding};
+
+/// Certificate stores whose additions let malware intercept TLS (banker/adware MITM).
+const CERT_STORES: &[(&str, &str)] = &[
+    ("HKLM", "SOFTWARE\\Microsoft\\SystemCertificates\\ROOT\\Certificates"),
+    ("HKLM", "SOFTWARE\\Microsoft\\SystemCertificates\\AuthRoot\\Certificates"),
+    ("HKLM", "SOFTWARE\\Microsoft\\SystemCertificates\\CA\\Certificates"),
+    ("HKLM", "SOFTWARE\\Policies\\Microsoft\\SystemCertificates\\Root\\Certificates"),
+    ("HKCU", "Software\\Microsoft\\SystemCertificates\\Root\\Certificates"),
+    ("HKCU", "Software\\Microsoft\\SystemCertificates\\CA\\Certificates"),
+];
+
+/// WinINET (per-user) and WinHTTP (machine) proxy configuration.
+const PROXY_VALUES: &[(&str, &str, &str)] = &[
+    ("HKCU", "Software\\Microsoft\\Windows\\CurrentVersion\\Internet Settings", "ProxyEnable"),
+    ("HKCU", "Software\\Microsoft\\Windows\\CurrentVersion\\Internet Settings", "ProxyServer"),
+    ("HKCU", "Software\\Microsoft\\Windows\\CurrentVersion\\Internet Settings", "ProxyOverride"),
+    ("HKCU", "Software\\Microsoft\\Windows\\CurrentVersion\\Internet Settings", "AutoConfigURL"),
+    ("HKLM", "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Internet Settings\\Connections", "WinHttpSettings"),
+];
+
+static HARNESS_EDITS: AtomicUsize = AtomicUsize::new(0);
+static REBASELINE: AtomicBool = AtomicBool::new(false);
+
+/// Held while the agent edits certificates or proxy settings itself, so its own changes aren't reported.
+pub struct HarnessEdit;
+
+impl HarnessEdit {
+    pub fn begin() -> Self {
+        HARNESS_EDITS.fetch_add(1, Ordering::SeqCst);
+        HarnessEdit
+    }
+}
+
+impl Drop for HarnessEdit {
+    fn drop(&mut self) {
+        REBASELINE.store(true, Ordering::SeqCst);
+        HARNESS_EDITS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Diffs trusted root/CA certificates and proxy settings against the previous poll.
+pub struct TrustMonitor {
+    certs: Option<HashMap<String, HashSet<String>>>,
+    proxy: Option<HashMap<String, String>>,
+}
+
+impl TrustMonitor {
+    pub fn new() -> Self {
+        Self { certs: None, proxy: None }
+    }
+
+    /// Returns findings since the previous poll. The first call only records a baseline.
+    pub fn poll(&mut self) -> Vec<PersistenceFinding> {
+        let mut findings = Vec::new();
+        let silent = HARNESS_EDITS.load(Ordering::SeqCst) > 0 || REBASELINE.swap(false, Ordering::SeqCst);
+
+        let mut current_certs = HashMap::new();
+        for (hive_name, path) in CERT_STORES {
+            let thumbprints: HashSet<String> = unsafe { enumerate_subkeys(hive(hive_name), path) }.into_iter().collect();
+            current_certs.insert(format!("{}\\{}", hive_name, path), thumbprints);
+        }
+        if let (Some(old), false) = (&self.certs, silent) {
+            for (store, thumbprints) in &current_certs {
+                let previous = old.get(store);
+                for thumb in thumbprints.iter().filter(|t| previous.map_or(true, |p| !p.contains(*t))) {
+                    findings.push(PersistenceFinding {
+                        event_type: "CERT_INSTALLED",
+                        details: format!("Certificate added to {} (Thumbprint: {})", store, thumb),
+                        path: None,
+                    });
+                }
+            }
+        }
+        self.certs = Some(current_certs);
+
+        let mut current_proxy = HashMap::new();
+        for (hive_name, path, value) in PROXY_VALUES {
+            if let Some(data) = unsafe { read_value_display(hive(hive_name), path, value) } {
+                current_proxy.insert(format!("{}\\{}\\{}", hive_name, path, value), data);
+            }
+        }
+        if let (Some(old), false) = (&self.proxy, silent) {
+            for (key, data) in &current_proxy {
+                if old.get(key) == Some(data) {
+                    continue;
+                }
+                findings.push(PersistenceFinding {
+                    event_type: "PROXY_HIJACK",
+                    details: format!("Proxy setting changed: {} = '{}' (Old: '{}')", key, data, old.get(key).map(|s| s.as_str()).unwrap_or("<unset>")),
+                    path: None,
+                });
+            }
+            for key in old.keys().filter(|k| !current_proxy.contains_key(*k)) {
+                findings.push(PersistenceFinding {
+                    event_type: "PROXY_HIJACK",
+                    details: format!("Proxy setting removed: {}", key),
+                    path: None,
+                });
+            }
+        }
+        self.proxy = Some(current_proxy);
+
+        findings
+    }
+}
+
+fn hive(name: &str) -> HKEY {
+    if name == "HKCU" { HKEY_CURRENT_USER } else { HKEY_LOCAL_MACHINE }
+}
+
+/// Reads a value of any common type as a printable string (binary blobs keep their printable runs).
+pub(crate) unsafe fn read_value_display(hive: HKEY, path: &str, value: &str) -> Option<String> {
+    let c_path = CString::new(path).ok()?;
+    let c_value = CString::new(value).ok()?;
+    let mut hkey: HKEY = std::ptr::null_mut();
+    if RegOpenKeyExA(hive, c_path.as_ptr(), 0, KEY_READ, &mut hkey) != 0 {
+        return None;
+    }
+
+    let mut type_code: DWORD = 0;
+    let mut data_buf = [0u8; 4096];
+    let mut data_len: DWORD = data_buf.len() as DWORD;
+    let ret = RegQueryValueExA(hkey, c_value.as_ptr(), std::ptr::null_mut(), &mut type_code, data_buf.as_mut_ptr(), &mut data_len);
+    RegCloseKey(hkey);
+    if ret != 0 {
+        return None;
+    }
+
+    let data = &data_buf[..data_len as usize];
+    match type_code {
+        REG_DWORD if data.len() >= 4 => Some(u32::from_le_bytes([data[0], data[1], data[2], data[3]]).to_string()),
+        REG_SZ | REG_EXPAND_SZ => Some(String::from_utf8_lossy(data).trim_end_matches('\0').to_string()),
+        // WinHttpSettings is an opaque struct; the proxy host/bypass list are embedded as ASCII
+        REG_BINARY => Some(
+            data.split(|b| !b.is_ascii_graphic())
+                .filter(|run| run.len() >= 4)
+                .map(|run| String::from_utf8_lossy(run).to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        ),
+        _ => None,
+    }
+}
@@ -0,0 +1,228 @@
+use base64::Engine;
+use reqwest::Url;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Redirects (HTTP, meta refresh or script) followed before giving up.
+const MAX_HOPS: usize = 10;
+/// Pages and payloads are cut off here; enough for any kit, small enough for the upload.
+const MAX_BODY_BYTES: usize = 100 * 1024 * 1024;
+const MAX_FAVICON_BYTES: usize = 1024 * 1024;
+/// Looks like the browser the URL was opened in, so kits that cloak on user agent still answer.
+const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36 Edg/124.0.0.0";
+
+#[derive(Serialize)]
+pub struct Hop {
+    pub url: String,
+    pub status: u16,
+    /// http | meta-refresh | script
+    pub via: &'static str,
+    pub location: Option<String>,
+    pub content_type: Option<String>,
+    pub server: Option<String>,
+    pub remote_addr: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PeerCertificate {
+    pub host: String,
+    /// DER, base64; the backend does the parsing.
+    pub der: String,
+}
+
+#[derive(Serialize, Default)]
+pub struct CrawlReport {
+    pub requested_url: String,
+    pub final_url: Option<String>,
+    pub chain: Vec<Hop>,
+    pub certificates: Vec<PeerCertificate>,
+    pub favicon_url: Option<String>,
+    pub error: Option<String>,
+    #[serde(skip)]
+    pub dom: Option<Vec<u8>>,
+    #[serde(skip)]
+    pub favicon: Option<Vec<u8>>,
+    /// (file name, body) when the final response was a download rather than a page.
+    #[serde(skip)]
+    pub payload: Option<(String, Vec<u8>)>,
+}
+
+fn header(resp: &reqwest::Response, name: &str) -> Option<String> {
+    resp.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_string)
+}
+
+async fn read_capped(mut resp: reqwest::Response, cap: usize) -> Vec<u8> {
+    let mut body = Vec::new();
+    while let Ok(Some(chunk)) = resp.chunk().await {
+        let room = cap.saturating_sub(body.len());
+        body.extend_from_slice(&chunk[..chunk.len().min(room)]);
+        if body.len() >= cap {
+            break;
+        }
+    }
+    body
+}
+
+/// Client-side redirect: meta refresh or a plain `location = "..."` in script.
+fn page_redirect(html: &str) -> Option<(&'static str, String)> {
+    let meta = regex::Regex::new(r#"(?i)<meta[^>]+http-equiv\s*=\s*["']?refresh["']?[^>]*content\s*=\s*["']?\s*\d*\s*;?\s*url\s*=\s*['"]?([^"'>\s]+)"#).ok()?;
+    if let Some(c) = meta.captures(html) {
+        return Some(("meta-refresh", c[1].to_string()));
+    }
+    let script = regex::Regex::new(r#"(?i)(?:window\.|document\.|top\.)?location(?:\.href)?\s*(?:=|\.replace\(|\.assign\()\s*["']([^"']+)["']"#).ok()?;
+    script.captures(html).map(|c| ("script", c[1].to_string()))
+}
+
+fn favicon_href(html: &str) -> Option<String> {
+    let link = regex::Regex::new(r#"(?i)<link[^>]+rel\s*=\s*["'][^"']*icon[^"']*["'][^>]*>"#).ok()?;
+    let href = regex::Regex::new(r#"(?i)href\s*=\s*["']([^"']+)["']"#).ok()?;
+    let tag = link.find(html)?;
+    href.captures(tag.as_str()).map(|c| c[1].to_string())
+}
+
+fn payload_name(resp: &reqwest::Response, url: &Url) -> String {
+    let from_header = header(resp, "content-disposition").and_then(|cd| {
+        cd.split(';')
+            .filter_map(|p| p.trim().strip_prefix("filename="))
+            .next()
+            .map(|n| n.trim_matches('"').to_string())
+    });
+    from_header
+        .or_else(|| url.path_segments().and_then(|mut s| s.next_back()).map(str::to_string))
+        .filter(|n| !n.is_empty())
+        .unwrap_or_else(|| "download.bin".to_string())
+}
+
+/// Fetches `url` like a browser, recording hops, certificates, the final page and its favicon.
+pub async fn crawl(url: &str) -> CrawlReport {
+    let mut report = CrawlReport { requested_url: url.to_string(), ..Default::default() };
+    let client = match reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .tls_info(true)
+        // Phishing kits run on self-signed and expired certificates; that is worth recording, not refusing
+        .danger_accept_invalid_certs(true)
+        .user_agent(USER_AGENT)
+        .timeout(Duration::from_secs(30))
+        .build()
+    {
+        Ok(c) => c,
+        Err(e) => {
+            report.error = Some(e.to_string());
+            return report;
+        }
+    };
+
+    let mut current = match Url::parse(url) {
+        Ok(u) => u,
+        Err(e) => {
+            report.error = Some(format!("Invalid URL: {}", e));
+            return report;
+        }
+    };
+    let mut via = "http";
+    let mut seen_hosts: Vec<String> = Vec::new();
+
+    for _ in 0..=MAX_HOPS {
+        let resp = match client.get(current.clone()).send().await {
+            Ok(r) => r,
+            Err(e) => {
+                report.error = Some(format!("{}: {}", current, e));
+                break;
+            }
+        };
+
+        let host = current.host_str().unwrap_or_default().to_string();
+        if let Some(der) = resp.extensions().get::<reqwest::tls::TlsInfo>().and_then(|t| t.peer_certificate()) {
+            if !seen_hosts.contains(&host) {
+                seen_hosts.push(host.clone());
+                report.certificates.push(PeerCertificate { host, der: base64::engine::general_purpose::STANDARD.encode(der) });
+            }
+        }
+
+        let location = header(&resp, "location");
+        let content_type = header(&resp, "content-type");
+        report.chain.push(Hop {
+            url: current.to_string(),
+            status: resp.status().as_u16(),
+            via,
+            location: location.clone(),
+            content_type: content_type.clone(),
+            server: header(&resp, "server"),
+            remote_addr: resp.remote_addr().map(|a| a.to_string()),
+        });
+        report.final_url = Some(current.to_string());
+
+        if resp.status().is_redirection() {
+            match location.and_then(|l| current.join(&l).ok()) {
+                Some(next) => {
+                    current = next;
+                    via = "http";
+                    continue;
+                }
+                None => break,
+            }
+        }
+
+        let is_page = content_type.as_deref().map_or(true, |ct| {
+            let ct = ct.to_lowercase();
+            ct.contains("html") || ct.starts_with("text/")
+        });
+        if !is_page {
+            let name = payload_name(&resp, &current);
+            let body = read_capped(resp, MAX_BODY_BYTES).await;
+            report.payload = Some((name, body));
+            break;
+        }
+
+        let body = read_capped(resp, MAX_BODY_BYTES).await;
+        let html = String::from_utf8_lossy(&body).to_string();
+        let next = page_redirect(&html).and_then(|(how, target)| current.join(&target).ok().map(|u| (how, u)));
+        report.dom = Some(body);
+        match next {
+            Some((how, next)) if next != current => {
+                current = next;
+                via = how;
+            }
+            _ => {
+                let icon = favicon_href(&html)
+                    .and_then(|h| current.join(&h).ok())
+                    .or_else(|| current.join("/favicon.ico").ok());
+                if let Some(icon) = icon {
+                    if let Ok(r) = client.get(icon.clone()).send().await {
+                        if r.status().is_success() {
+                            report.favicon = Some(read_capped(r, MAX_FAVICON_BYTES).await).filter(|b| !b.is_empty());
+                            report.favicon_url = report.favicon.as_ref().map(|_| icon.to_string());
+                        }
+                    }
+                }
+                break;
+            }
+        }
+    }
+    report
+}
+
+/// Ships the report and its files to the backend, which files them under the session's task.
+pub async fn upload(backend_url: &str, session_id: &str, report: CrawlReport) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut form = reqwest::multipart::Form::new()
+        .text("session_id", session_id.to_string())
+        .text("report", serde_json::to_string(&report)?);
+    if let Some(dom) = report.dom {
+        form = form.part("dom", reqwest::multipart::Part::bytes(dom).file_name("dom.html").mime_str("text/html")?);
+    }
+    if let Some(icon) = report.favicon {
+        form = form.part("favicon", reqwest::multipart::Part::bytes(icon).file_name("favicon").mime_str("application/octet-stream")?);
+    }
+    if let Some((name, body)) = report.payload {
+        form = form.part("payload", reqwest::multipart::Part::bytes(body).file_name(name).mime_str("application/octet-stream")?);
+    }
+
+    reqwest::Client::new()
+        .post(format!("{}/vms/telemetry/url-artifacts", backend_url))
+        .multipart(form)
+        .send()
+        .await?
+        .error_for_status()?;
+    println!("[AGENT] URL artifacts for {} uploaded.", report.requested_url);
+    Ok(())
+}
@@ -0,0 +1,52 @@
+"""mitmproxy addon: one JSON line per completed HTTP(S) flow, read back by the backend.
+
+    mitmdump --mode regular --listen-port 8080 -s flow_logger.py --set flow_log=/var/log/mitmproxy/flows.jsonl
+
+The CA mitmproxy generates (~/.mitmproxy/mitmproxy-ca-cert.pem) is what MITM_CA_CERT points at.
+"""
+import json
+import time
+
+from mitmproxy import ctx, http
+
+PREVIEW_BYTES = 2048
+
+
+def _preview(message):
+    if message is None or not message.raw_content:
+        return ""
+    body = message.get_content(strict=False) or b""
+    return body[:PREVIEW_BYTES].decode("utf-8", errors="replace")
+
+
+class FlowLogger:
+    def load(self, loader):
+        loader.add_option("flow_log", str, "/var/log/mitmproxy/flows.jsonl", "JSONL file flows are appended to")
+
+    def response(self, flow: http.HTTPFlow):
+        self._write(flow)
+
+    def error(self, flow: http.HTTPFlow):
+        self._write(flow)
+
+    def _write(self, flow: http.HTTPFlow):
+        client_ip, client_port = flow.client_conn.peername[:2]
+        record = {
+            "ts": flow.request.timestamp_start or time.time(),
+            # IPv4 clients show up as ::ffff:a.b.c.d on dual-stack listeners
+            "client_ip": client_ip.removeprefix("::ffff:"),
+            "client_port": client_port,
+            "method": flow.request.method,
+            "url": flow.request.pretty_url,
+            "status": flow.response.status_code if flow.response else None,
+            "error": flow.error.msg if flow.error else None,
+            "request_headers": dict(flow.request.headers),
+            "request_body": _preview(flow.request),
+            "response_headers": dict(flow.response.headers) if flow.response else {},
+            "response_body": _preview(flow.response),
+        }
+        with open(ctx.options.flow_log, "a", encoding="utf-8") as f:
+            f.write(json.dumps(record) + "\n")
+
+
+addons = [FlowLogger()]
@@ -1,8 +1,221 @@
+use std::collections::HashMap;
 use std::sync::Arc;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use serde::Serialize;
 use sqlx::{Pool, Postgres};
 use crate::ai_analysis::RecommendedAction;
 use crate::AgentManager;
 
+// --- AI-PROPOSED SANDBOX ACTIONS ---
+// While a task is live the AI can ask for something to be done in the guest: a screenshot
+// (SCREENSHOT), a dump of one process's memory (MEM_DUMP pid), a file pulled back to the
+// backend (FETCH_FILE path) or a process killed (KILL pid). None of it reaches the agent until
+// an analyst approves it: proposals are queued per task (GET /tasks/{id}/actions), and
+// POST /tasks/{id}/actions/{action_id}/approve sends the agent command to the session running
+// the task, .../reject drops it. Proposals come from the chat's propose_action tool (ai_tools)
+// and, when a report runs with auto_response off, from its recommended_actions.
+
+/// Actions the AI may propose.
+pub const PROPOSABLE: &[&str] = &["SCREENSHOT", "MEM_DUMP", "FETCH_FILE", "KILL"];
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS ai_actions (
+            id SERIAL PRIMARY KEY,
+            task_id TEXT NOT NULL,
+            action TEXT NOT NULL,
+            params JSONB NOT NULL,
+            reasoning TEXT NOT NULL,
+            source TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            proposed_at BIGINT NOT NULL,
+            decided_by TEXT,
+            decided_at BIGINT
+        )"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_ai_actions_task ON ai_actions(task_id, proposed_at)")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct ProposedAction {
+    pub id: i32,
+    pub task_id: String,
+    pub action: String,
+    pub params: serde_json::Value,
+    pub reasoning: String,
+    /// chat | report
+    pub source: String,
+    /// pending | dispatched | rejected
+    pub status: String,
+    pub proposed_at: i64,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<i64>,
+}
+
+/// The agent command carrying out a proposable action, or why it can't be built.
+pub fn agent_command(action: &str, params: &HashMap<String, String>) -> Result<String, String> {
+    let pid = || -> Result<u32, String> {
+        params.get("pid").and_then(|p| p.trim().parse().ok()).ok_or_else(|| format!("{} needs a numeric 'pid'", action))
+    };
+    let cmd = match action {
+        "SCREENSHOT" => serde_json::json!({ "command": "SCREENSHOT" }),
+        "MEM_DUMP" => serde_json::json!({ "command": "DUMP_PROCESS", "pid": pid()? }),
+        "KILL" => serde_json::json!({ "command": "KILL", "pid": pid()? }),
+        "FETCH_FILE" => match params.get("path").map(|p| p.trim()).filter(|p| !p.is_empty()) {
+            Some(path) => serde_json::json!({ "command": "UPLOAD_PIVOT", "path": path }),
+            None => return Err("FETCH_FILE needs a 'path'".to_string()),
+        },
+        other => return Err(format!("'{}' cannot be proposed; use one of {}", other, PROPOSABLE.join(", "))),
+    };
+    Ok(cmd.to_string())
+}
+
+/// Queues `action` for analyst approval and returns its id. An identical proposal still
+/// pending is reused rather than queued twice.
+pub async fn propose(pool: &Pool<Postgres>, task_id: &str, action: &RecommendedAction, source: &str) -> Result<i32, String> {
+    let name = action.action.trim().to_uppercase();
+    agent_command(&name, &action.params)?;
+    let params = serde_json::to_value(&action.params).map_err(|e| e.to_string())?;
+
+    if let Ok(Some(id)) = sqlx::query_scalar::<_, i32>(
+        "SELECT id FROM ai_actions WHERE task_id = $1 AND action = $2 AND params = $3 AND status = 'pending'"
+    )
+    .bind(task_id)
+    .bind(&name)
+    .bind(&params)
+    .fetch_optional(pool)
+    .await
+    {
+        return Ok(id);
+    }
+
+    let id = sqlx::query_scalar::<_, i32>(
+        "INSERT INTO ai_actions (task_id, action, params, reasoning, source, proposed_at)
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING id"
+    )
+    .bind(task_id)
+    .bind(&name)
+    .bind(&params)
+    .bind(&action.reasoning)
+    .bind(source)
+    .bind(chrono::Utc::now().timestamp_millis())
+    .fetch_one(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    println!("[ACTION] AI proposed {} for task {} (#{}): {}", name, task_id, id, action.reasoning);
+    Ok(id)
+}
+
+#[utoipa::path(tag = "ai", responses((status = 200, description = "AI-proposed actions for the task, oldest first")))]
+#[get("/tasks/{id}/actions")]
+pub async fn list_task_actions(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    match sqlx::query_as::<_, ProposedAction>("SELECT * FROM ai_actions WHERE task_id = $1 ORDER BY proposed_at ASC")
+        .bind(path.into_inner())
+        .fetch_all(pool.get_ref())
+        .await
+    {
+        Ok(actions) => HttpResponse::Ok().json(actions),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "ai", responses(
+    (status = 200, description = "Action sent to the agent running the task"),
+    (status = 404, description = "No such action for the task"),
+    (status = 409, description = "Action already decided, or no agent session is running the task"),
+))]
+#[post("/tasks/{id}/actions/{action_id}/approve")]
+pub async fn approve_action(
+    pool: web::Data<Pool<Postgres>>,
+    agent_manager: web::Data<Arc<AgentManager>>,
+    req: HttpRequest,
+    path: web::Path<(String, i32)>,
+) -> impl Responder {
+    let (task_id, action_id) = path.into_inner();
+    let action = match fetch(pool.get_ref(), &task_id, action_id).await {
+        Ok(action) => action,
+        Err(resp) => return resp,
+    };
+    if action.status != "pending" {
+        return HttpResponse::Conflict().json(serde_json::json!({ "error": format!("Action is {}", action.status) }));
+    }
+    let params: HashMap<String, String> = serde_json::from_value(action.params.clone()).unwrap_or_default();
+    let cmd = match agent_command(&action.action, &params) {
+        Ok(cmd) => cmd,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+    let Some(session_id) = agent_manager.find_session_by_task(&task_id).await else {
+        return HttpResponse::Conflict().json(serde_json::json!({ "error": "No agent session is running that task" }));
+    };
+
+    // Only the first of two concurrent approvals dispatches
+    let analyst = crate::auth::current_user(&req).map(|u| u.username);
+    match decide(pool.get_ref(), action_id, "dispatched", analyst).await {
+        Ok(Some(action)) => {
+            println!("[ACTION] Analyst approved {} #{} for task {}; dispatching to {}", action.action, action.id, task_id, session_id);
+            agent_manager.send_command_to_session(&session_id, &cmd).await;
+            HttpResponse::Ok().json(action)
+        }
+        Ok(None) => HttpResponse::Conflict().json(serde_json::json!({ "error": "Action was already decided" })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "ai", responses(
+    (status = 200, description = "Action rejected"),
+    (status = 404, description = "No such action for the task"),
+    (status = 409, description = "Action already decided"),
+))]
+#[post("/tasks/{id}/actions/{action_id}/reject")]
+pub async fn reject_action(
+    pool: web::Data<Pool<Postgres>>,
+    req: HttpRequest,
+    path: web::Path<(String, i32)>,
+) -> impl Responder {
+    let (task_id, action_id) = path.into_inner();
+    if let Err(resp) = fetch(pool.get_ref(), &task_id, action_id).await {
+        return resp;
+    }
+    let analyst = crate::auth::current_user(&req).map(|u| u.username);
+    match decide(pool.get_ref(), action_id, "rejected", analyst).await {
+        Ok(Some(action)) => HttpResponse::Ok().json(action),
+        Ok(None) => HttpResponse::Conflict().json(serde_json::json!({ "error": "Action was already decided" })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+async fn fetch(pool: &Pool<Postgres>, task_id: &str, action_id: i32) -> Result<ProposedAction, HttpResponse> {
+    match sqlx::query_as::<_, ProposedAction>("SELECT * FROM ai_actions WHERE id = $1 AND task_id = $2")
+        .bind(action_id)
+        .bind(task_id)
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(Some(action)) => Ok(action),
+        Ok(None) => Err(HttpResponse::NotFound().json(serde_json::json!({ "error": "Action not found" }))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() }))),
+    }
+}
+
+/// Moves a pending action to `status`; None when it was no longer pending.
+async fn decide(pool: &Pool<Postgres>, action_id: i32, status: &str, analyst: Option<String>) -> Result<Option<ProposedAction>, sqlx::Error> {
+    sqlx::query_as::<_, ProposedAction>(
+        "UPDATE ai_actions SET status = $2, decided_by = $3, decided_at = $4
+         WHERE id = $1 AND status = 'pending' RETURNING *"
+    )
+    .bind(action_id)
+    .bind(status)
+    .bind(analyst)
+    .bind(chrono::Utc::now().timestamp_millis())
+    .fetch_optional(pool)
+    .await
+}
+
 pub struct ActionManager;
 
 impl ActionManager {
@@ -30,10 +243,9 @@ impl ActionManager {
                     }
                 },
                 "MEM_DUMP" => {
-                    if let Some(pid_str) = action.params.get("pid") {
-                        Self::send_agent_task(task_id, &format!("TASK:MEMDUMP {}", pid_str), agent_manager.clone()).await;
-                    } else {
-                        println!("[ACTION] Error: MEM_DUMP missing 'pid' parameter");
+                    match agent_command("MEM_DUMP", &action.params) {
+                        Ok(cmd) => Self::send_agent_task(task_id, &cmd, agent_manager.clone()).await,
+                        Err(e) => println!("[ACTION] Error: {}", e),
                     }
                 },
                 "TAG_EVENT" => {
@@ -55,6 +267,15 @@ impl ActionManager {
         }
     }
 
+    /// With auto-response off, the report's guest-side actions wait for an analyst instead.
+    pub async fn queue_actions(task_id: &str, actions: &[RecommendedAction], pool: &Pool<Postgres>) {
+        for action in actions {
+            if let Err(e) = propose(pool, task_id, action, "report").await {
+                println!("[ACTION] Not queueing {} for approval: {}", action.action, e);
+            }
+        }
+    }
+
     async fn send_agent_task(task_id: &str, command: &str, agent_manager: Arc<AgentManager>) {
         let sessions = agent_manager.sessions.lock().await;
         let session_id = sessions.iter()
@@ -0,0 +1,181 @@
+use actix_web::{get, post, web, HttpResponse, Responder, Error};
+use actix_multipart::Multipart;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Digest};
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use crate::AgentManager;
+
+// --- AGENT RELEASES ---
+// Agent binaries served to guests for UPDATE_AGENT.
+
+const RELEASE_DIR: &str = "./agent_releases";
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+pub struct AgentRelease {
+    pub id: i32,
+    pub version: String,
+    pub filename: String,
+    pub sha256: String,
+    pub size_bytes: i64,
+    pub created_at: i64,
+}
+
+#[derive(Deserialize)]
+pub struct PushUpdateRequest {
+    /// Release version to push; defaults to the most recently uploaded build.
+    pub version: Option<String>,
+    /// Target a single agent session; broadcasts to every connected agent when omitted.
+    pub session_id: Option<String>,
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS agent_releases (
+            id SERIAL PRIMARY KEY,
+            version TEXT UNIQUE NOT NULL,
+            filename TEXT NOT NULL,
+            sha256 TEXT NOT NULL,
+            size_bytes BIGINT NOT NULL,
+            created_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    println!("[AGENT_UPDATE] Database initialized (agent_releases).");
+    Ok(())
+}
+
+#[utoipa::path(tag = "agents", responses((status = 200, description = "Success")))]
+#[post("/agents/releases")]
+pub async fn upload_release(
+    mut payload: Multipart,
+    pool: web::Data<Pool<Postgres>>,
+) -> Result<HttpResponse, Error> {
+    let _ = tokio::fs::create_dir_all(RELEASE_DIR).await;
+
+    let mut version: Option<String> = None;
+    let mut binary: Vec<u8> = Vec::new();
+
+    while let Ok(Some(mut field)) = TryStreamExt::try_next(&mut payload).await {
+        let field_name = field.content_disposition().and_then(|cd| cd.get_name()).unwrap_or("").to_string();
+        let mut bytes = Vec::new();
+        while let Ok(Some(chunk)) = TryStreamExt::try_next(&mut field).await {
+            bytes.extend_from_slice(&chunk);
+        }
+
+        match field_name.as_str() {
+            "version" => version = Some(String::from_utf8_lossy(&bytes).trim().to_string()),
+            "file" => binary = bytes,
+            _ => {}
+        }
+    }
+
+    if binary.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": "No agent binary provided" })));
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(&binary);
+    let sha256 = format!("{:x}", hasher.finalize());
+    let now = chrono::Utc::now().timestamp();
+    let version = version.filter(|v| !v.is_empty()).unwrap_or_else(|| now.to_string());
+    let filename = format!("mallab-agent-{}.exe", &sha256[..16]);
+
+    let path = format!("{}/{}", RELEASE_DIR, filename);
+    let mut f = tokio::fs::File::create(&path).await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    f.write_all(&binary).await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let result = sqlx::query(
+        "INSERT INTO agent_releases (version, filename, sha256, size_bytes, created_at) VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (version) DO UPDATE SET filename = EXCLUDED.filename, sha256 = EXCLUDED.sha256, size_bytes = EXCLUDED.size_bytes, created_at = EXCLUDED.created_at"
+    )
+    .bind(&version)
+    .bind(&filename)
+    .bind(&sha256)
+    .bind(binary.len() as i64)
+    .bind(now)
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(_) => {
+            println!("[AGENT_UPDATE] Release {} stored ({} bytes, SHA256 {})", version, binary.len(), sha256);
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "status": "success",
+                "version": version,
+                "sha256": sha256,
+                "url": format!("/agent_releases/{}", filename)
+            })))
+        }
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() }))),
+    }
+}
+
+#[utoipa::path(tag = "agents", responses((status = 200, description = "Success")))]
+#[get("/agents/releases")]
+pub async fn list_releases(pool: web::Data<Pool<Postgres>>) -> impl Responder {
+    let releases = sqlx::query_as::<_, AgentRelease>(
+        "SELECT id, version, filename, sha256, size_bytes, created_at FROM agent_releases ORDER BY created_at DESC"
+    )
+    .fetch_all(pool.get_ref())
+    .await;
+
+    match releases {
+        Ok(r) => HttpResponse::Ok().json(r),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "agents", responses((status = 200, description = "Success")))]
+#[post("/agents/update")]
+pub async fn push_update(
+    req: web::Json<PushUpdateRequest>,
+    pool: web::Data<Pool<Postgres>>,
+    manager: web::Data<Arc<AgentManager>>,
+) -> impl Responder {
+    let release = match &req.version {
+        Some(v) => sqlx::query_as::<_, AgentRelease>(
+            "SELECT id, version, filename, sha256, size_bytes, created_at FROM agent_releases WHERE version = $1"
+        )
+        .bind(v)
+        .fetch_optional(pool.get_ref())
+        .await,
+        None => sqlx::query_as::<_, AgentRelease>(
+            "SELECT id, version, filename, sha256, size_bytes, created_at FROM agent_releases ORDER BY created_at DESC LIMIT 1"
+        )
+        .fetch_optional(pool.get_ref())
+        .await,
+    };
+
+    let release = match release {
+        Ok(Some(r)) => r,
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "No matching agent release" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+
+    // The URL is relative: the agent resolves it against the backend address it already knows.
+    let cmd = serde_json::json!({
+        "command": "UPDATE_AGENT",
+        "url": format!("/agent_releases/{}", release.filename),
+        "sha256": release.sha256,
+        "filename": release.filename,
+    }).to_string();
+
+    match &req.session_id {
+        Some(sid) => manager.send_command_to_session(sid, &cmd).await,
+        None => manager.broadcast_command(&cmd).await,
+    }
+
+    println!("[AGENT_UPDATE] Pushed release {} to {}", release.version, req.session_id.as_deref().unwrap_or("all sessions"));
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "dispatched",
+        "version": release.version,
+        "sha256": release.sha256
+    }))
+}
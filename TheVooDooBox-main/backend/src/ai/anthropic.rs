@@ -1,4 +1,4 @@
-use crate::ai::provider::{AIProvider, ChatMessage};
+use crate::ai::provider::{read_sse, AIProvider, ApiError, ChatMessage, Reply, TokenSink, TokenUsage, OutputSchema, ToolCall, ToolRound, ToolSpec};
 use async_trait::async_trait;
 use reqwest::Client;
 use serde_json::json;
@@ -32,37 +32,15 @@ impl AIProvider for AnthropicProvider {
         "Anthropic"
     }
 
-    async fn ask(&self, history: Vec<ChatMessage>, system_prompt: String) -> Result<String, Box<dyn Error + Send + Sync>> {
-        let url = "https://api.anthropic.com/v1/messages";
+    fn model(&self) -> &str {
+        &self.model
+    }
 
-        let mut messages = Vec::new();
-        for msg in history {
-            // Anthropic roles: "user" | "assistant"
-            let role = if msg.role == "model" { "assistant" } else { &msg.role };
-            messages.push(json!({
-                "role": role,
-                "content": msg.content
-            }));
-        }
-
-        let payload = json!({
-            "model": self.model,
-            "max_tokens": 8192,
-            "system": system_prompt,
-            "messages": messages
-        });
-
-        let resp = self.client.post(url)
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("content-type", "application/json")
-            .json(&payload)
-            .send()
-            .await?;
+    async fn ask(&self, history: Vec<ChatMessage>, system_prompt: String) -> Result<Reply, Box<dyn Error + Send + Sync>> {
+        let resp = self.request(messages(history), system_prompt, false, &[], None).send().await?;
 
         if !resp.status().is_success() {
-            let error_text = resp.text().await?;
-            return Err(format!("Anthropic API Error: {}", error_text).into());
+            return Err(ApiError::from_response("Anthropic", resp).await.into());
         }
 
         let body: serde_json::Value = resp.json().await?;
@@ -71,11 +49,162 @@ impl AIProvider for AnthropicProvider {
         if let Some(content_arr) = body["content"].as_array() {
             if let Some(first_block) = content_arr.first() {
                 if let Some(text) = first_block["text"].as_str() {
-                    return Ok(text.to_string());
+                    let usage = TokenUsage::from_json(&body["usage"], "input_tokens", "output_tokens");
+                    return Ok(Reply { text: text.to_string(), usage });
                 }
             }
         }
 
         Err(format!("Failed to parse Anthropic response: {:?}", body).into())
     }
+
+    async fn ask_stream(&self, history: Vec<ChatMessage>, system_prompt: String, tokens: &TokenSink) -> Result<Reply, Box<dyn Error + Send + Sync>> {
+        let resp = self.request(messages(history), system_prompt, true, &[], None).send().await?;
+
+        if !resp.status().is_success() {
+            return Err(ApiError::from_response("Anthropic", resp).await.into());
+        }
+
+        // message_start carries the input tokens, content_block_delta the text, message_delta
+        // the running output token count; an overload mid-stream comes as an error event
+        let mut text = String::new();
+        let mut usage = TokenUsage::default();
+        let mut error = None;
+        read_sse(resp, |data| {
+            let Ok(event) = serde_json::from_str::<serde_json::Value>(data) else { return true };
+            match event["type"].as_str() {
+                Some("message_start") => {
+                    usage.prompt_tokens = event["message"]["usage"]["input_tokens"].as_u64().unwrap_or(0);
+                }
+                Some("content_block_delta") => {
+                    if let Some(piece) = event["delta"]["text"].as_str() {
+                        text.push_str(piece);
+                        let _ = tokens.send(piece.to_string());
+                    }
+                }
+                Some("message_delta") => {
+                    usage.completion_tokens = event["usage"]["output_tokens"].as_u64().unwrap_or(usage.completion_tokens);
+                }
+                Some("message_stop") => return false,
+                Some("error") => {
+                    error = Some(event["error"]["message"].as_str().unwrap_or("stream error").to_string());
+                    return false;
+                }
+                _ => {}
+            }
+            true
+        }).await?;
+
+        match error {
+            Some(e) => Err(format!("Anthropic API Error: {}", e).into()),
+            None => Ok(Reply { text, usage: Some(usage) }),
+        }
+    }
+
+    async fn ask_structured(&self, history: Vec<ChatMessage>, system_prompt: String, schema: &OutputSchema) -> Result<Reply, Box<dyn Error + Send + Sync>> {
+        // No JSON mode here: the answer is made the input of a tool the model is forced to
+        // call, which the API holds to the tool's input schema
+        let tool = ToolSpec {
+            name: schema.name,
+            description: "Records the final answer.",
+            parameters: schema.schema.clone(),
+        };
+        let resp = self.request(messages(history), system_prompt, false, std::slice::from_ref(&tool), Some(schema.name)).send().await?;
+
+        if !resp.status().is_success() {
+            return Err(ApiError::from_response("Anthropic", resp).await.into());
+        }
+
+        let body: serde_json::Value = resp.json().await?;
+        let input = body["content"].as_array()
+            .and_then(|content| content.iter().find(|block| block["type"] == "tool_use"))
+            .map(|block| block["input"].clone())
+            .ok_or_else(|| format!("Failed to parse Anthropic response: {:?}", body))?;
+        let usage = TokenUsage::from_json(&body["usage"], "input_tokens", "output_tokens");
+        Ok(Reply { text: input.to_string(), usage })
+    }
+
+    async fn ask_with_tools(&self, history: Vec<ChatMessage>, rounds: &[ToolRound], system_prompt: String, tools: &[ToolSpec]) -> Result<(Reply, Vec<ToolCall>), Box<dyn Error + Send + Sync>> {
+        let mut messages = messages(history);
+        for round in rounds {
+            let uses: Vec<serde_json::Value> = round.calls.iter().map(|call| json!({
+                "type": "tool_use",
+                "id": call.id,
+                "name": call.name,
+                "input": call.arguments
+            })).collect();
+            let results: Vec<serde_json::Value> = round.calls.iter().zip(&round.results).map(|(call, result)| json!({
+                "type": "tool_result",
+                "tool_use_id": call.id,
+                "content": result
+            })).collect();
+            messages.push(json!({ "role": "assistant", "content": uses }));
+            messages.push(json!({ "role": "user", "content": results }));
+        }
+
+        let resp = self.request(messages, system_prompt, false, tools, None).send().await?;
+
+        if !resp.status().is_success() {
+            return Err(ApiError::from_response("Anthropic", resp).await.into());
+        }
+
+        let body: serde_json::Value = resp.json().await?;
+        let Some(content) = body["content"].as_array() else {
+            return Err(format!("Failed to parse Anthropic response: {:?}", body).into());
+        };
+
+        // Text and tool_use blocks can come mixed in one reply
+        let text: String = content.iter().filter_map(|block| block["text"].as_str()).collect();
+        let calls = content.iter().filter(|block| block["type"] == "tool_use").filter_map(|block| Some(ToolCall {
+            id: block["id"].as_str()?.to_string(),
+            name: block["name"].as_str()?.to_string(),
+            arguments: block["input"].clone(),
+        })).collect();
+        let usage = TokenUsage::from_json(&body["usage"], "input_tokens", "output_tokens");
+        Ok((Reply { text, usage }, calls))
+    }
+}
+
+fn messages(history: Vec<ChatMessage>) -> Vec<serde_json::Value> {
+    let mut messages = Vec::new();
+    for msg in history {
+        // Anthropic roles: "user" | "assistant"
+        let role = if msg.role == "model" { "assistant" } else { &msg.role };
+        messages.push(json!({
+            "role": role,
+            "content": msg.content
+        }));
+    }
+    messages
+}
+
+impl AnthropicProvider {
+    /// `forced_tool` makes the model answer by calling that tool.
+    fn request(&self, messages: Vec<serde_json::Value>, system_prompt: String, stream: bool, tools: &[ToolSpec], forced_tool: Option<&str>) -> reqwest::RequestBuilder {
+        let url = "https://api.anthropic.com/v1/messages";
+
+        let mut payload = json!({
+            "model": self.model,
+            "max_tokens": 8192,
+            "system": system_prompt,
+            "messages": messages,
+            "stream": stream
+        });
+        if !tools.is_empty() {
+            payload["tools"] = tools.iter().map(|tool| json!({
+                "name": tool.name,
+                "description": tool.description,
+                "input_schema": tool.parameters
+            })).collect();
+        }
+        if let Some(name) = forced_tool {
+            payload["tool_choice"] = json!({ "type": "tool", "name": name });
+        }
+
+        self.client.post(url)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("content-type", "application/json")
+            .json(&payload)
+    }
 }
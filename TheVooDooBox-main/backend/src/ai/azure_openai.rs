@@ -0,0 +1,104 @@
+use crate::ai::provider::{AIProvider, ApiError, ChatMessage, Reply, TokenUsage};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::json;
+use std::error::Error;
+
+pub const DEFAULT_API_VERSION: &str = "2024-06-01";
+
+/// OpenAI models behind an Azure OpenAI resource, addressed by deployment name.
+pub struct AzureOpenAIProvider {
+    api_key: String,
+    endpoint: String,
+    deployment: String,
+    api_version: String,
+    client: Client,
+}
+
+impl AzureOpenAIProvider {
+    pub fn new(api_key: String, endpoint: String, deployment: String, api_version: String) -> Self {
+        let api_version = if api_version.is_empty() {
+            DEFAULT_API_VERSION.to_string()
+        } else {
+            api_version
+        };
+
+        Self {
+            api_key,
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            deployment,
+            api_version,
+            client: Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl AIProvider for AzureOpenAIProvider {
+    fn name(&self) -> &str {
+        "Azure OpenAI"
+    }
+
+    fn model(&self) -> &str {
+        &self.deployment
+    }
+
+    async fn ask(&self, history: Vec<ChatMessage>, system_prompt: String) -> Result<Reply, Box<dyn Error + Send + Sync>> {
+        if self.endpoint.is_empty() || self.deployment.is_empty() {
+            return Err("Azure OpenAI endpoint and deployment must be configured".into());
+        }
+        // e.g. https://contoso.openai.azure.com/openai/deployments/gpt-4o/chat/completions?api-version=2024-06-01
+        let url = format!(
+            "{}/openai/deployments/{}/chat/completions?api-version={}",
+            self.endpoint, self.deployment, self.api_version
+        );
+
+        let mut messages = Vec::new();
+        if !system_prompt.is_empty() {
+            messages.push(json!({
+                "role": "system",
+                "content": system_prompt
+            }));
+        }
+
+        for msg in history {
+            let role = if msg.role == "model" { "assistant" } else { &msg.role };
+            messages.push(json!({
+                "role": role,
+                "content": msg.content
+            }));
+        }
+
+        // The deployment fixes the model, so none is named here
+        let payload = json!({
+            "messages": messages,
+            "max_tokens": 4096,
+            "temperature": 0.7
+        });
+
+        let resp = self.client.post(&url)
+            .header("api-key", &self.api_key)
+            .header("Content-Type", "application/json")
+            .json(&payload)
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            return Err(ApiError::from_response("Azure OpenAI", resp).await.into());
+        }
+
+        let body: serde_json::Value = resp.json().await?;
+
+        // Same shape as OpenAI: { "choices": [ { "message": { "content": "..." } } ] }
+        if let Some(choices) = body["choices"].as_array() {
+            if let Some(first_choice) = choices.first() {
+                if let Some(content) = first_choice["message"]["content"].as_str() {
+                    let usage = TokenUsage::from_json(&body["usage"], "prompt_tokens", "completion_tokens");
+                    return Ok(Reply { text: content.to_string(), usage });
+                }
+            }
+        }
+
+        Err(format!("Failed to parse Azure OpenAI response: {:?}", body).into())
+    }
+}
@@ -0,0 +1,203 @@
+use crate::ai::provider::{AIProvider, ApiError, ChatMessage, Reply, TokenUsage};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::time::Duration;
+
+pub const DEFAULT_MODEL: &str = "anthropic.claude-3-5-sonnet-20240620-v1:0";
+pub const DEFAULT_REGION: &str = "us-east-1";
+
+/// Bedrock Converse API; without an access key, credentials come from the task role or instance profile.
+pub struct BedrockProvider {
+    access_key: String,
+    secret_key: String,
+    session_token: String,
+    region: String,
+    model: String,
+    client: Client,
+}
+
+pub struct Credentials {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl BedrockProvider {
+    pub fn new(access_key: String, secret_key: String, session_token: String, region: String, model: String) -> Self {
+        let region = if region.is_empty() { DEFAULT_REGION.to_string() } else { region };
+        let model = if model.is_empty() { DEFAULT_MODEL.to_string() } else { model };
+
+        Self {
+            access_key,
+            secret_key,
+            session_token,
+            region,
+            model,
+            client: Client::new(),
+        }
+    }
+
+    /// Temporary credentials from the container or instance role.
+    async fn role_credentials(&self) -> Result<Credentials, String> {
+        let client = Client::builder().timeout(Duration::from_secs(3)).build().map_err(|e| e.to_string())?;
+        let parse = |v: serde_json::Value| -> Option<Credentials> {
+            Some(Credentials {
+                access_key: v["AccessKeyId"].as_str()?.to_string(),
+                secret_key: v["SecretAccessKey"].as_str()?.to_string(),
+                session_token: v["Token"].as_str().map(str::to_string),
+            })
+        };
+
+        // ECS / Fargate task role
+        let container_uri = std::env::var("AWS_CONTAINER_CREDENTIALS_FULL_URI").ok().or_else(|| {
+            std::env::var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI").ok().map(|p| format!("http://169.254.170.2{}", p))
+        });
+        if let Some(uri) = container_uri {
+            let mut req = client.get(&uri);
+            if let Ok(token) = std::env::var("AWS_CONTAINER_AUTHORIZATION_TOKEN") {
+                req = req.header("Authorization", token);
+            }
+            let body: serde_json::Value = req.send().await.map_err(|e| e.to_string())?.json().await.map_err(|e| e.to_string())?;
+            return parse(body).ok_or_else(|| "unreadable container credentials".to_string());
+        }
+
+        // EC2 instance profile through IMDSv2
+        let imds = "http://169.254.169.254/latest";
+        let token = client.put(format!("{}/api/token", imds))
+            .header("X-aws-ec2-metadata-token-ttl-seconds", "300")
+            .send().await.map_err(|e| format!("no AWS credentials configured and no instance metadata: {}", e))?
+            .text().await.map_err(|e| e.to_string())?;
+        let roles = client.get(format!("{}/meta-data/iam/security-credentials/", imds))
+            .header("X-aws-ec2-metadata-token", &token)
+            .send().await.map_err(|e| e.to_string())?
+            .text().await.map_err(|e| e.to_string())?;
+        let role = roles.lines().next().filter(|r| !r.is_empty()).ok_or("instance has no IAM role")?;
+        let body: serde_json::Value = client.get(format!("{}/meta-data/iam/security-credentials/{}", imds, role))
+            .header("X-aws-ec2-metadata-token", &token)
+            .send().await.map_err(|e| e.to_string())?
+            .json().await.map_err(|e| e.to_string())?;
+        parse(body).ok_or_else(|| "unreadable instance credentials".to_string())
+    }
+
+    pub async fn credentials(&self) -> Result<Credentials, String> {
+        if !self.access_key.is_empty() && !self.secret_key.is_empty() {
+            return Ok(Credentials {
+                access_key: self.access_key.clone(),
+                secret_key: self.secret_key.clone(),
+                session_token: Some(self.session_token.clone()).filter(|t| !t.is_empty()),
+            });
+        }
+        self.role_credentials().await
+    }
+}
+
+/// Converse wants alternating turns starting with the user, and the system text apart.
+fn converse_messages(history: Vec<ChatMessage>, system_prompt: String) -> (Vec<serde_json::Value>, Vec<serde_json::Value>) {
+    let mut system = Vec::new();
+    if !system_prompt.is_empty() {
+        system.push(json!({ "text": system_prompt }));
+    }
+    let mut turns: Vec<(String, String)> = Vec::new();
+    for msg in history {
+        let role = match msg.role.as_str() {
+            "system" => {
+                system.push(json!({ "text": msg.content }));
+                continue;
+            }
+            "model" | "assistant" => "assistant",
+            _ => "user",
+        };
+        if msg.content.is_empty() || (turns.is_empty() && role == "assistant") {
+            continue;
+        }
+        match turns.last_mut() {
+            Some((last, text)) if last == role => {
+                text.push_str("\n\n");
+                text.push_str(&msg.content);
+            }
+            _ => turns.push((role.to_string(), msg.content)),
+        }
+    }
+    let messages = turns.into_iter().map(|(role, text)| json!({ "role": role, "content": [{ "text": text }] })).collect();
+    (messages, system)
+}
+
+#[async_trait]
+impl AIProvider for BedrockProvider {
+    fn name(&self) -> &str {
+        "Bedrock"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn ask(&self, history: Vec<ChatMessage>, system_prompt: String) -> Result<Reply, Box<dyn Error + Send + Sync>> {
+        let credentials = self.credentials().await?;
+        let host = format!("bedrock-runtime.{}.amazonaws.com", self.region);
+        // Model IDs contain ':'; the path carries it encoded and SigV4 encodes the path again
+        let model = urlencoding::encode(&self.model).into_owned();
+        let path = format!("/model/{}/converse", model);
+        let canonical_uri = format!("/model/{}/converse", urlencoding::encode(&model));
+
+        let (messages, system) = converse_messages(history, system_prompt);
+        let payload = json!({
+            "messages": messages,
+            "system": system,
+            "inferenceConfig": {
+                "maxTokens": 4096,
+                "temperature": 0.7
+            }
+        });
+        let body = serde_json::to_vec(&payload)?;
+        let payload_hash = format!("{:x}", Sha256::digest(&body));
+
+        let amz_date = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let mut headers = vec![
+            ("content-type", "application/json".to_string()),
+            ("host", host.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let signature = crate::storage::sigv4_signature(
+            &credentials.secret_key, &self.region, "bedrock", &amz_date, "POST", &canonical_uri, "", &headers, &payload_hash,
+        );
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}/{}/bedrock/aws4_request, SignedHeaders={}, Signature={}",
+            credentials.access_key, &amz_date[..8], self.region,
+            headers.iter().map(|(k, _)| *k).collect::<Vec<_>>().join(";"), signature
+        );
+
+        let mut req = self.client.post(format!("https://{}{}", host, path))
+            .header("Content-Type", "application/json")
+            .header("x-amz-date", &amz_date)
+            .header("Authorization", authorization)
+            .body(body);
+        if let Some(token) = &credentials.session_token {
+            req = req.header("x-amz-security-token", token);
+        }
+        let resp = req.send().await?;
+
+        if !resp.status().is_success() {
+            return Err(ApiError::from_response("Bedrock", resp).await.into());
+        }
+
+        let body: serde_json::Value = resp.json().await?;
+
+        // Response format: { "output": { "message": { "content": [ { "text": "..." } ] } } }
+        if let Some(content) = body["output"]["message"]["content"].as_array() {
+            let text: String = content.iter().filter_map(|c| c["text"].as_str()).collect();
+            if !text.is_empty() {
+                let usage = TokenUsage::from_json(&body["usage"], "inputTokens", "outputTokens");
+                return Ok(Reply { text, usage });
+            }
+        }
+
+        Err(format!("Failed to parse Bedrock response: {:?}", body).into())
+    }
+}
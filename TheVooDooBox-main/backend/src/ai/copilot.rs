@@ -1,4 +1,4 @@
-use crate::ai::provider::{AIProvider, ChatMessage};
+use crate::ai::provider::{AIProvider, ApiError, ChatMessage, Reply, TokenUsage};
 use async_trait::async_trait;
 use reqwest::Client;
 use serde_json::json;
@@ -32,7 +32,11 @@ impl AIProvider for CopilotProvider {
         "Copilot"
     }
 
-    async fn ask(&self, history: Vec<ChatMessage>, system_prompt: String) -> Result<String, Box<dyn Error + Send + Sync>> {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn ask(&self, history: Vec<ChatMessage>, system_prompt: String) -> Result<Reply, Box<dyn Error + Send + Sync>> {
         // Copilot API (GitHub Models) is similar to OpenAI but with different auth/endpoint
         // Note: As of late 2024/2025, GitHub Models endpoint is likely: 
         // https://models.github.ai/inference/chat/completions (or similar based on specific integration)
@@ -73,8 +77,7 @@ impl AIProvider for CopilotProvider {
             .await?;
 
         if !resp.status().is_success() {
-            let error_text = resp.text().await?;
-            return Err(format!("Copilot API Error: {}", error_text).into());
+            return Err(ApiError::from_response("Copilot", resp).await.into());
         }
 
         let body: serde_json::Value = resp.json().await?;
@@ -82,7 +85,8 @@ impl AIProvider for CopilotProvider {
         if let Some(choices) = body["choices"].as_array() {
             if let Some(first_choice) = choices.first() {
                 if let Some(content) = first_choice["message"]["content"].as_str() {
-                    return Ok(content.to_string());
+                    let usage = TokenUsage::from_json(&body["usage"], "prompt_tokens", "completion_tokens");
+                    return Ok(Reply { text: content.to_string(), usage });
                 }
             }
         }
@@ -1,4 +1,4 @@
-use crate::ai::provider::{AIProvider, ChatMessage};
+use crate::ai::provider::{AIProvider, ApiError, ChatMessage, OutputSchema, Reply, TokenUsage};
 use async_trait::async_trait;
 use reqwest::Client;
 use serde_json::json;
@@ -29,7 +29,23 @@ impl AIProvider for GeminiProvider {
         "Gemini"
     }
 
-    async fn ask(&self, history: Vec<ChatMessage>, system_prompt: String) -> Result<String, Box<dyn Error + Send + Sync>> {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn ask(&self, history: Vec<ChatMessage>, system_prompt: String) -> Result<Reply, Box<dyn Error + Send + Sync>> {
+        self.generate(history, system_prompt, false).await
+    }
+
+    async fn ask_structured(&self, history: Vec<ChatMessage>, system_prompt: String, schema: &OutputSchema) -> Result<Reply, Box<dyn Error + Send + Sync>> {
+        // responseSchema only takes an OpenAPI subset (no open maps like the MITRE matrix), so
+        // the schema goes in the prompt and the API is held to JSON output
+        self.generate(history, format!("{}\n\n{}", system_prompt, schema.instruction()), true).await
+    }
+}
+
+impl GeminiProvider {
+    async fn generate(&self, history: Vec<ChatMessage>, system_prompt: String, json_output: bool) -> Result<Reply, Box<dyn Error + Send + Sync>> {
         let url = format!(
             "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent?key={}",
             self.model, self.api_key
@@ -60,12 +76,15 @@ impl AIProvider for GeminiProvider {
             }));
         }
 
-        let payload = json!({
+        let mut payload = json!({
             "contents": contents,
             "generationConfig": {
                 "maxOutputTokens": 65536
             }
         });
+        if json_output {
+            payload["generationConfig"]["responseMimeType"] = json!("application/json");
+        }
 
         let resp = self.client.post(&url)
             .json(&payload)
@@ -73,8 +92,7 @@ impl AIProvider for GeminiProvider {
             .await?;
 
         if !resp.status().is_success() {
-            let error_text = resp.text().await?;
-            return Err(format!("Gemini API Error: {}", error_text).into());
+            return Err(ApiError::from_response("Gemini", resp).await.into());
         }
 
         let body: serde_json::Value = resp.json().await?;
@@ -82,7 +100,8 @@ impl AIProvider for GeminiProvider {
             .as_str()
             .ok_or("Failed to parse Gemini response text")?
             .to_string();
+        let usage = TokenUsage::from_json(&body["usageMetadata"], "promptTokenCount", "candidatesTokenCount");
 
-        Ok(text)
+        Ok(Reply { text, usage })
     }
 }
@@ -1,20 +1,45 @@
-use crate::ai::provider::AIProvider;
+use crate::ai::provider::{AIProvider, OutputSchema, Reply, TokenSink, ToolRound, ToolRunner, ToolSpec};
 use crate::ai::gemini::GeminiProvider;
 use crate::ai::ollama::OllamaProvider;
 use crate::ai::anthropic::AnthropicProvider;
 use crate::ai::openai::OpenAIProvider;
 use crate::ai::copilot::CopilotProvider;
+use crate::ai::azure_openai::AzureOpenAIProvider;
+use crate::ai::bedrock::BedrockProvider;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use crate::ai_usage::{UsageRecord, UsageTag};
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+/// Tool-use rounds a chat answer may take before the model must answer with what it has.
+const MAX_TOOL_ROUNDS: usize = 6;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub enum ProviderType {
     Gemini,
     Ollama,
     Anthropic,
     OpenAI,
     Copilot,
+    AzureOpenAI,
+    Bedrock,
+}
+
+impl ProviderType {
+    /// Names accepted by /vms/ai/config and AI_FALLBACK_PROVIDERS.
+    pub fn from_str(s: &str) -> Self {
+        match s.trim().to_lowercase().as_str() {
+            "gemini" => ProviderType::Gemini,
+            "anthropic" => ProviderType::Anthropic,
+            "openai" | "openai_compatible" => ProviderType::OpenAI,
+            "copilot" => ProviderType::Copilot,
+            "azure_openai" | "azure" => ProviderType::AzureOpenAI,
+            "bedrock" | "aws_bedrock" => ProviderType::Bedrock,
+            _ => ProviderType::Ollama, // Default fallback
+        }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
@@ -58,10 +83,29 @@ pub struct AIManager {
 
     openai_key: Arc<RwLock<String>>,
     openai_model: Arc<RwLock<String>>,
+    openai_base_url: Arc<RwLock<String>>,
+    openai_headers: Arc<RwLock<HashMap<String, String>>>,
 
     copilot_token: Arc<RwLock<String>>,
     copilot_model: Arc<RwLock<String>>,
 
+    azure_openai_key: Arc<RwLock<String>>,
+    azure_openai_endpoint: Arc<RwLock<String>>,
+    azure_openai_deployment: Arc<RwLock<String>>,
+    azure_openai_api_version: Arc<RwLock<String>>,
+
+    bedrock_access_key: Arc<RwLock<String>>,
+    bedrock_secret_key: Arc<RwLock<String>>,
+    bedrock_session_token: Arc<RwLock<String>>,
+    bedrock_region: Arc<RwLock<String>>,
+    bedrock_model: Arc<RwLock<String>>,
+
+    /// Tried in order, after the provider a call was meant for has failed
+    fallback_chain: Arc<RwLock<Vec<ProviderType>>>,
+
+    /// Where each call's tokens and cost are written (ai_usage), once the database is up
+    usage_log: Option<Pool<Postgres>>,
+
     ai_mode: Arc<RwLock<AIMode>>,
 }
 
@@ -71,10 +115,40 @@ impl AIManager {
         ollama_url: String,
         anthropic_key: String,
         openai_key: String,
-        copilot_token: String
+        copilot_token: String,
+        azure_openai_key: String,
+        azure_openai_endpoint: String,
     ) -> Self {
         // Load GEMINI_MODEL from env, defaulting to gemini-3-flash-preview
         let env_gemini_model = std::env::var("GEMINI_MODEL").unwrap_or_else(|_| "gemini-3-flash-preview".to_string());
+        // OPENAI_BASE_URL points the OpenAI provider at any compatible server (vLLM, LM Studio, Groq...);
+        // OPENAI_EXTRA_HEADERS is a JSON object of headers to send along
+        let env_openai_base_url = std::env::var("OPENAI_BASE_URL")
+            .unwrap_or_else(|_| crate::ai::openai::DEFAULT_BASE_URL.to_string());
+        let env_openai_headers: HashMap<String, String> = std::env::var("OPENAI_EXTRA_HEADERS")
+            .ok()
+            .and_then(|v| serde_json::from_str(&v).ok())
+            .unwrap_or_default();
+        // Azure addresses a deployment of a model, named by whoever deployed it
+        let env_azure_deployment = std::env::var("AZURE_OPENAI_DEPLOYMENT").unwrap_or_default();
+        let env_azure_api_version = std::env::var("AZURE_OPENAI_API_VERSION")
+            .unwrap_or_else(|_| crate::ai::azure_openai::DEFAULT_API_VERSION.to_string());
+        // Bedrock uses the standard AWS variables; with no keys set it falls back to the task/instance role
+        let env_aws_access_key = std::env::var("AWS_ACCESS_KEY_ID").unwrap_or_default();
+        let env_aws_secret_key = std::env::var("AWS_SECRET_ACCESS_KEY").unwrap_or_default();
+        let env_aws_session_token = std::env::var("AWS_SESSION_TOKEN").unwrap_or_default();
+        let env_bedrock_region = std::env::var("BEDROCK_REGION")
+            .or_else(|_| std::env::var("AWS_REGION"))
+            .unwrap_or_else(|_| crate::ai::bedrock::DEFAULT_REGION.to_string());
+        let env_bedrock_model = std::env::var("BEDROCK_MODEL")
+            .unwrap_or_else(|_| crate::ai::bedrock::DEFAULT_MODEL.to_string());
+        // AI_FALLBACK_PROVIDERS: comma-separated, e.g. "anthropic,ollama"
+        let env_fallback: Vec<ProviderType> = std::env::var("AI_FALLBACK_PROVIDERS")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|p| !p.trim().is_empty())
+            .map(ProviderType::from_str)
+            .collect();
         // 1. Try to load from disk
         let saved_mode = Self::load_mode_config();
         
@@ -110,11 +184,28 @@ impl AIManager {
             anthropic_model: Arc::new(RwLock::new("claude-3-5-sonnet-latest".to_string())),
 
             openai_key: Arc::new(RwLock::new(openai_key)),
-            openai_model: Arc::new(RwLock::new("gpt-4o".to_string())),
+            openai_model: Arc::new(RwLock::new(std::env::var("OPENAI_MODEL").unwrap_or_else(|_| "gpt-4o".to_string()))),
+            openai_base_url: Arc::new(RwLock::new(env_openai_base_url)),
+            openai_headers: Arc::new(RwLock::new(env_openai_headers)),
 
             copilot_token: Arc::new(RwLock::new(copilot_token)),
             copilot_model: Arc::new(RwLock::new("gpt-4".to_string())),
 
+            azure_openai_key: Arc::new(RwLock::new(azure_openai_key)),
+            azure_openai_endpoint: Arc::new(RwLock::new(azure_openai_endpoint)),
+            azure_openai_deployment: Arc::new(RwLock::new(env_azure_deployment)),
+            azure_openai_api_version: Arc::new(RwLock::new(env_azure_api_version)),
+
+            bedrock_access_key: Arc::new(RwLock::new(env_aws_access_key)),
+            bedrock_secret_key: Arc::new(RwLock::new(env_aws_secret_key)),
+            bedrock_session_token: Arc::new(RwLock::new(env_aws_session_token)),
+            bedrock_region: Arc::new(RwLock::new(env_bedrock_region)),
+            bedrock_model: Arc::new(RwLock::new(env_bedrock_model)),
+
+            fallback_chain: Arc::new(RwLock::new(env_fallback)),
+
+            usage_log: None,
+
             ai_mode: Arc::new(RwLock::new(initial_mode.clone())),
         };
         
@@ -126,6 +217,12 @@ impl AIManager {
         manager
     }
 
+    /// Records every call in the ai_usage table of `pool`.
+    pub fn with_usage_log(mut self, pool: Pool<Postgres>) -> Self {
+        self.usage_log = Some(pool);
+        self
+    }
+
     fn load_mode_config() -> Option<AIMode> {
         if let Ok(content) = std::fs::read_to_string("ai_config.json") {
             if let Ok(json) = serde_json::from_str::<serde_json::Value>(&content) {
@@ -144,6 +241,7 @@ impl AIManager {
         std::fs::write("ai_config.json", serde_json::to_string_pretty(&json)?)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn switch_provider(
         &self, 
         provider_type: ProviderType, 
@@ -156,8 +254,20 @@ impl AIManager {
         anthropic_model: Option<String>,
         openai_key: Option<String>,
         openai_model: Option<String>,
+        openai_base_url: Option<String>,
+        openai_headers: Option<HashMap<String, String>>,
         copilot_token: Option<String>,
         copilot_model: Option<String>,
+        azure_openai_key: Option<String>,
+        azure_openai_endpoint: Option<String>,
+        azure_openai_deployment: Option<String>,
+        azure_openai_api_version: Option<String>,
+        bedrock_access_key: Option<String>,
+        bedrock_secret_key: Option<String>,
+        bedrock_session_token: Option<String>,
+        bedrock_region: Option<String>,
+        bedrock_model: Option<String>,
+        fallback: Option<Vec<ProviderType>>,
     ) {
         // Update RwLocks if values provided
         if let Some(v) = gemini_key { *self.gemini_key.write().await = v; }
@@ -170,40 +280,81 @@ impl AIManager {
         
         if let Some(v) = openai_key { *self.openai_key.write().await = v; }
         if let Some(v) = openai_model { *self.openai_model.write().await = v; }
+        if let Some(v) = openai_base_url { *self.openai_base_url.write().await = v; }
+        if let Some(v) = openai_headers { *self.openai_headers.write().await = v; }
         
         if let Some(v) = copilot_token { *self.copilot_token.write().await = v; }
         if let Some(v) = copilot_model { *self.copilot_model.write().await = v; }
+
+        if let Some(v) = azure_openai_key { *self.azure_openai_key.write().await = v; }
+        if let Some(v) = azure_openai_endpoint { *self.azure_openai_endpoint.write().await = v; }
+        if let Some(v) = azure_openai_deployment { *self.azure_openai_deployment.write().await = v; }
+        if let Some(v) = azure_openai_api_version { *self.azure_openai_api_version.write().await = v; }
+
+        if let Some(v) = bedrock_access_key { *self.bedrock_access_key.write().await = v; }
+        if let Some(v) = bedrock_secret_key { *self.bedrock_secret_key.write().await = v; }
+        if let Some(v) = bedrock_session_token { *self.bedrock_session_token.write().await = v; }
+        if let Some(v) = bedrock_region { *self.bedrock_region.write().await = v; }
+        if let Some(v) = bedrock_model { *self.bedrock_model.write().await = v; }
         
-        let mut provider_lock = self.provider.write().await;
+        if let Some(v) = fallback { *self.fallback_chain.write().await = v; }
+
+        let provider = self.build_provider(&provider_type).await;
+        *self.provider.write().await = provider;
+    }
+
+    async fn build_provider(&self, provider_type: &ProviderType) -> Box<dyn AIProvider> {
         match provider_type {
             ProviderType::Gemini => {
                 let key = self.gemini_key.read().await;
                 let model = self.gemini_model.read().await;
-                *provider_lock = Box::new(GeminiProvider::new(key.clone(), Some(model.clone())));
+                Box::new(GeminiProvider::new(key.clone(), Some(model.clone())))
             }
             ProviderType::Ollama => {
                 let url = self.ollama_url.read().await;
                 let model = self.ollama_model.read().await;
-                *provider_lock = Box::new(OllamaProvider::new(url.clone(), model.clone()));
+                Box::new(OllamaProvider::new(url.clone(), model.clone()))
             }
             ProviderType::Anthropic => {
                 let key = self.anthropic_key.read().await;
                 let model = self.anthropic_model.read().await;
-                *provider_lock = Box::new(AnthropicProvider::new(key.clone(), model.clone()));
+                Box::new(AnthropicProvider::new(key.clone(), model.clone()))
             }
             ProviderType::OpenAI => {
                 let key = self.openai_key.read().await;
                 let model = self.openai_model.read().await;
-                *provider_lock = Box::new(OpenAIProvider::new(key.clone(), model.clone()));
+                let base_url = self.openai_base_url.read().await;
+                let headers = self.openai_headers.read().await;
+                Box::new(OpenAIProvider::new(key.clone(), model.clone(), base_url.clone(), headers.clone()))
             }
             ProviderType::Copilot => {
                 let token = self.copilot_token.read().await;
                 let model = self.copilot_model.read().await;
-                *provider_lock = Box::new(CopilotProvider::new(token.clone(), model.clone()));
+                Box::new(CopilotProvider::new(token.clone(), model.clone()))
+            }
+            ProviderType::AzureOpenAI => {
+                let key = self.azure_openai_key.read().await;
+                let endpoint = self.azure_openai_endpoint.read().await;
+                let deployment = self.azure_openai_deployment.read().await;
+                let api_version = self.azure_openai_api_version.read().await;
+                Box::new(AzureOpenAIProvider::new(key.clone(), endpoint.clone(), deployment.clone(), api_version.clone()))
+            }
+            ProviderType::Bedrock => {
+                Box::new(self.bedrock_provider().await)
             }
         }
     }
 
+    async fn bedrock_provider(&self) -> BedrockProvider {
+        BedrockProvider::new(
+            self.bedrock_access_key.read().await.clone(),
+            self.bedrock_secret_key.read().await.clone(),
+            self.bedrock_session_token.read().await.clone(),
+            self.bedrock_region.read().await.clone(),
+            self.bedrock_model.read().await.clone(),
+        )
+    }
+
     // --- AI Mode ---
     pub async fn set_ai_mode(&self, mode: AIMode) {
         println!("[AI] Switching AI Mode to: {:?}", mode);
@@ -225,6 +376,50 @@ impl AIManager {
         provider.name().to_string()
     }
 
+    /// Cheap reachability check of the active provider that lists models instead of spending
+    /// tokens. Returns the provider name either way so callers can report which one failed.
+    pub async fn probe(&self) -> (String, Result<(), String>) {
+        let name = self.get_current_provider_name().await;
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(5))
+            .build()
+            .unwrap_or_default();
+        let request = match name.as_str() {
+            "Ollama" => client.get(format!("{}/api/tags", self.ollama_url.read().await.trim_end_matches('/'))),
+            "Gemini" => client.get(format!("https://generativelanguage.googleapis.com/v1beta/models?key={}", self.gemini_key.read().await)),
+            "Anthropic" => client.get("https://api.anthropic.com/v1/models")
+                .header("x-api-key", self.anthropic_key.read().await.as_str())
+                .header("anthropic-version", "2023-06-01"),
+            "OpenAI" => crate::ai::openai::authorize(
+                client.get(format!("{}/models", self.openai_base_url.read().await.trim().trim_end_matches('/'))),
+                self.openai_key.read().await.as_str(),
+                &*self.openai_headers.read().await,
+            ),
+            "Azure OpenAI" => client.get(format!(
+                    "{}/openai/models?api-version={}",
+                    self.azure_openai_endpoint.read().await.trim_end_matches('/'),
+                    self.azure_openai_api_version.read().await,
+                ))
+                .header("api-key", self.azure_openai_key.read().await.as_str()),
+            "Bedrock" => {
+                // Listing models needs its own IAM permission; resolving credentials is what usually fails
+                let result = self.bedrock_provider().await.credentials().await.map(|_| ());
+                return (name, result);
+            }
+            _ => {
+                // Copilot has no listing endpoint usable with the chat token; configured is the best we can say
+                let configured = !self.copilot_token.read().await.is_empty();
+                return (name, if configured { Ok(()) } else { Err("No token configured".to_string()) });
+            }
+        };
+        let result = match request.send().await {
+            Ok(resp) if resp.status().is_success() => Ok(()),
+            Ok(resp) => Err(format!("HTTP {}", resp.status())),
+            Err(e) => Err(e.to_string()),
+        };
+        (name, result)
+    }
+
     pub async fn get_config(&self) -> serde_json::Value {
         serde_json::json!({
             "provider": self.get_current_provider_name().await,
@@ -238,14 +433,235 @@ impl AIManager {
             "anthropic_model": self.anthropic_model.read().await.as_str(),
             "openai_key": self.openai_key.read().await.as_str(),
             "openai_model": self.openai_model.read().await.as_str(),
+            "openai_base_url": self.openai_base_url.read().await.as_str(),
+            "openai_headers": &*self.openai_headers.read().await,
             "copilot_token": self.copilot_token.read().await.as_str(),
             "copilot_model": self.copilot_model.read().await.as_str(),
+            "azure_openai_key": self.azure_openai_key.read().await.as_str(),
+            "azure_openai_endpoint": self.azure_openai_endpoint.read().await.as_str(),
+            "azure_openai_deployment": self.azure_openai_deployment.read().await.as_str(),
+            "azure_openai_api_version": self.azure_openai_api_version.read().await.as_str(),
+            "bedrock_access_key": self.bedrock_access_key.read().await.as_str(),
+            "bedrock_secret_key": self.bedrock_secret_key.read().await.as_str(),
+            "bedrock_region": self.bedrock_region.read().await.as_str(),
+            "bedrock_model": self.bedrock_model.read().await.as_str(),
+            "fallback": &*self.fallback_chain.read().await,
         })
     }
 
-    pub async fn ask(&self, history: Vec<crate::ai::provider::ChatMessage>, system_prompt: String) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    pub async fn ask(&self, history: Vec<crate::ai::provider::ChatMessage>, system_prompt: String, usage: &UsageTag) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
         let provider = self.provider.read().await;
-        provider.ask(history, system_prompt).await
+        self.ask_with_fallback(provider.as_ref(), history, system_prompt, usage, None).await
+    }
+
+    /// Asks `first`, then each provider of the fallback chain until one answers, so a quota
+    /// blip on one API doesn't fail a whole report. In LocalOnly mode only Ollama is fallen
+    /// back to: the data must not leave the lab just because the local server is down.
+    /// With `schema` the answer is asked for as JSON following it.
+    async fn ask_with_fallback(
+        &self,
+        first: &dyn AIProvider,
+        history: Vec<crate::ai::provider::ChatMessage>,
+        system_prompt: String,
+        usage: &UsageTag,
+        schema: Option<&OutputSchema>,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let mut tried = vec![first.name().to_string()];
+        let mut last_err = match self.ask_with_retry(first, history.clone(), system_prompt.clone(), usage, schema).await {
+            Ok(answer) => return Ok(answer),
+            Err(e) => e,
+        };
+
+        let local_only = *self.ai_mode.read().await == AIMode::LocalOnly;
+        let chain = self.fallback_chain.read().await.clone();
+        for provider_type in chain {
+            if local_only && provider_type != ProviderType::Ollama {
+                continue;
+            }
+            let provider = self.build_provider(&provider_type).await;
+            if tried.iter().any(|n| n == provider.name()) {
+                continue;
+            }
+            println!("[AI] {} failed ({}). Falling back to {}.", tried[tried.len() - 1], last_err, provider.name());
+            crate::metrics::inc("voodoobox_ai_fallbacks_total", &[("from", &tried[tried.len() - 1]), ("to", provider.name())], 1.0);
+            tried.push(provider.name().to_string());
+            match self.ask_with_retry(provider.as_ref(), history.clone(), system_prompt.clone(), usage, schema).await {
+                Ok(answer) => return Ok(answer),
+                Err(e) => last_err = e,
+            }
+        }
+        Err(last_err)
+    }
+
+    /// Calls one provider, retrying with exponential backoff (2s, 4s, 8s...) while it fails
+    /// with a rate limit, a 5xx, a timeout or a refused connection. AI_MAX_RETRIES (default 2)
+    /// bounds the retries; a Retry-After from the API is honoured up to a minute.
+    async fn ask_with_retry(
+        &self,
+        provider: &dyn AIProvider,
+        history: Vec<crate::ai::provider::ChatMessage>,
+        system_prompt: String,
+        usage: &UsageTag,
+        schema: Option<&OutputSchema>,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let max_retries: u32 = std::env::var("AI_MAX_RETRIES").ok().and_then(|v| v.trim().parse().ok()).unwrap_or(2);
+        let mut attempt = 0;
+        loop {
+            let err = match self.timed_ask(provider, history.clone(), system_prompt.clone(), usage, None, schema).await {
+                Ok(answer) => return Ok(answer),
+                Err(e) => e,
+            };
+            let api_error = err.downcast_ref::<crate::ai::provider::ApiError>();
+            let transient = match api_error {
+                Some(api_error) => api_error.is_transient(),
+                None => err.downcast_ref::<reqwest::Error>().is_some_and(|e| e.is_timeout() || e.is_connect()),
+            };
+            if !transient || attempt >= max_retries {
+                return Err(err);
+            }
+            let backoff = 2u64 << attempt;
+            let delay = api_error.and_then(|e| e.retry_after).map_or(backoff, |s| s.min(60));
+            attempt += 1;
+            println!("[AI] {} failed ({}). Retry {}/{} in {}s.", provider.name(), err, attempt, max_retries, delay);
+            tokio::time::sleep(std::time::Duration::from_secs(delay)).await;
+        }
+    }
+
+    /// Asks the active provider, streaming the answer to `tokens` as it is produced. A call that
+    /// fails before anything was streamed goes through the normal retry and fallback path
+    /// instead, its answer arriving in one piece.
+    pub async fn ask_streaming(
+        &self,
+        history: Vec<crate::ai::provider::ChatMessage>,
+        system_prompt: String,
+        usage: &UsageTag,
+        tokens: TokenSink,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let provider = self.provider.read().await;
+        let (piece_tx, mut piece_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+        let ask = async move {
+            self.timed_ask(provider.as_ref(), history.clone(), system_prompt.clone(), usage, Some(&piece_tx), None).await
+                .map_err(|e| (e, provider, history, system_prompt))
+        };
+        let forward = async {
+            let mut streamed = false;
+            while let Some(piece) = piece_rx.recv().await {
+                streamed = true;
+                let _ = tokens.send(piece);
+            }
+            streamed
+        };
+        let (result, streamed) = tokio::join!(ask, forward);
+        match result {
+            Ok(answer) => Ok(answer),
+            Err((e, provider, history, system_prompt)) if !streamed => {
+                println!("[AI] Streaming from {} failed ({}). Retrying without streaming.", provider.name(), e);
+                let answer = self.ask_with_fallback(provider.as_ref(), history, system_prompt, usage, None).await?;
+                let _ = tokens.send(answer.clone());
+                Ok(answer)
+            }
+            Err((e, ..)) => Err(e),
+        }
+    }
+
+    /// Calls a provider and records latency and token usage for /metrics and ai_usage. Token
+    /// counts are the API's own when it reports them, estimated otherwise. With `tokens` the
+    /// answer is streamed there as well; with `schema` it is asked for as structured output.
+    async fn timed_ask(
+        &self,
+        provider: &dyn AIProvider,
+        history: Vec<crate::ai::provider::ChatMessage>,
+        system_prompt: String,
+        usage: &UsageTag,
+        tokens: Option<&TokenSink>,
+        schema: Option<&OutputSchema>,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let estimated_prompt = crate::metrics::estimate_tokens(&system_prompt)
+            + history.iter().map(|m| crate::metrics::estimate_tokens(&m.content)).sum::<f64>();
+        let started = std::time::Instant::now();
+        let result = match (tokens, schema) {
+            (Some(tokens), _) => provider.ask_stream(history, system_prompt, tokens).await,
+            (None, Some(schema)) => provider.ask_structured(history, system_prompt, schema).await,
+            (None, None) => provider.ask(history, system_prompt).await,
+        };
+        self.account(provider, usage, estimated_prompt, started.elapsed(), result.as_ref().ok()).await;
+        result.map(|reply| reply.text)
+    }
+
+    /// Records one call's latency and tokens; `reply` is None when the call failed.
+    async fn account(&self, provider: &dyn AIProvider, usage: &UsageTag, estimated_prompt: f64, elapsed: std::time::Duration, reply: Option<&Reply>) {
+        let name = provider.name();
+        let outcome = if reply.is_some() { "success" } else { "error" };
+        crate::metrics::observe("voodoobox_ai_request_seconds", &[("provider", name), ("outcome", outcome)], elapsed.as_secs_f64());
+
+        // A failed call is not billed
+        let (prompt_tokens, completion_tokens, estimated) = match reply {
+            Some(reply) => match reply.usage {
+                Some(u) => (u.prompt_tokens, u.completion_tokens, false),
+                None => (estimated_prompt as u64, crate::metrics::estimate_tokens(&reply.text) as u64, true),
+            },
+            None => (0, 0, false),
+        };
+        crate::metrics::inc("voodoobox_ai_tokens_total", &[("provider", name), ("direction", "prompt")], prompt_tokens as f64);
+        crate::metrics::inc("voodoobox_ai_tokens_total", &[("provider", name), ("direction", "completion")], completion_tokens as f64);
+        if let Some(pool) = &self.usage_log {
+            let ai_mode = self.get_ai_mode().await;
+            crate::ai_usage::record(pool, &UsageRecord {
+                tag: usage,
+                ai_mode: ai_mode.to_str(),
+                provider: name,
+                model: provider.model(),
+                prompt_tokens,
+                completion_tokens,
+                estimated,
+                duration_ms: elapsed.as_millis() as u64,
+                success: reply.is_some(),
+            }).await;
+        }
+    }
+
+    /// Lets the active provider call `tools` (run by `runner`) before answering, for up to
+    /// MAX_TOOL_ROUNDS rounds; after that it has to answer with what it has. Each call made is
+    /// announced on `progress`. If the provider fails mid-way the question is asked again
+    /// without tools, through the fallback chain.
+    pub async fn ask_with_tools(
+        &self,
+        history: Vec<crate::ai::provider::ChatMessage>,
+        system_prompt: String,
+        usage: &UsageTag,
+        tools: &[ToolSpec],
+        runner: &dyn ToolRunner,
+        progress: &TokenSink,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let provider = self.provider.read().await;
+        let estimated_prompt = crate::metrics::estimate_tokens(&system_prompt)
+            + history.iter().map(|m| crate::metrics::estimate_tokens(&m.content)).sum::<f64>();
+        let mut rounds: Vec<ToolRound> = Vec::new();
+        loop {
+            let offered = if rounds.len() < MAX_TOOL_ROUNDS { tools } else { &[] };
+            let started = std::time::Instant::now();
+            let result = provider.ask_with_tools(history.clone(), &rounds, system_prompt.clone(), offered).await;
+            self.account(provider.as_ref(), usage, estimated_prompt, started.elapsed(), result.as_ref().ok().map(|(reply, _)| reply)).await;
+
+            let (reply, calls) = match result {
+                Ok(turn) => turn,
+                Err(e) => {
+                    println!("[AI] Tool-assisted answer from {} failed ({}). Answering without tools.", provider.name(), e);
+                    return self.ask_with_fallback(provider.as_ref(), history, system_prompt, usage, None).await;
+                }
+            };
+            if calls.is_empty() || offered.is_empty() {
+                return Ok(reply.text);
+            }
+
+            let mut results = Vec::with_capacity(calls.len());
+            for call in &calls {
+                let _ = progress.send(format!("{}({})", call.name, call.arguments));
+                crate::metrics::inc("voodoobox_ai_tool_calls_total", &[("tool", &call.name)], 1.0);
+                results.push(runner.run(call).await);
+            }
+            rounds.push(ToolRound { calls, results });
+        }
     }
 
     /// Ask using a specific provider, bypassing the active one.
@@ -255,23 +671,25 @@ impl AIManager {
         target: &str, // "local" or "cloud"
         history: Vec<crate::ai::provider::ChatMessage>,
         system_prompt: String,
+        usage: &UsageTag,
+        schema: Option<&OutputSchema>,
     ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
         match target {
             "cloud" => {
-                let g_key = self.gemini_key.read().await;
+                let g_key = self.gemini_key.read().await.clone();
                 if g_key.is_empty() {
                     return Err("Gemini API key not configured. Cannot use Cloud provider.".into());
                 }
-                let g_model = self.gemini_model.read().await;
-                let cloud_provider = GeminiProvider::new(g_key.clone(), Some(g_model.clone()));
-                cloud_provider.ask(history, system_prompt).await
+                let g_model = self.gemini_model.read().await.clone();
+                let cloud_provider = GeminiProvider::new(g_key, Some(g_model));
+                self.ask_with_fallback(&cloud_provider, history, system_prompt, usage, schema).await
             }
             _ => {
                 // "local" - use Ollama
-                let o_url = self.ollama_url.read().await;
-                let o_model = self.ollama_model.read().await;
-                let local_provider = OllamaProvider::new(o_url.clone(), o_model.clone());
-                local_provider.ask(history, system_prompt).await
+                let o_url = self.ollama_url.read().await.clone();
+                let o_model = self.ollama_model.read().await.clone();
+                let local_provider = OllamaProvider::new(o_url, o_model);
+                self.ask_with_fallback(&local_provider, history, system_prompt, usage, schema).await
             }
         }
     }
@@ -284,6 +702,33 @@ impl AIManager {
         system_prompt: String,
         mode: &AIMode,
         phase: &str, // "map" or "reduce"
+        usage: &UsageTag,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        self.ask_routed(history, system_prompt, mode, phase, usage, None).await
+    }
+
+    /// `ask_with_mode` for an answer that must be JSON following `schema` (the forensic
+    /// report), using the provider's structured output mode where it has one.
+    pub async fn ask_structured_with_mode(
+        &self,
+        history: Vec<crate::ai::provider::ChatMessage>,
+        system_prompt: String,
+        mode: &AIMode,
+        phase: &str,
+        usage: &UsageTag,
+        schema: &OutputSchema,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        self.ask_routed(history, system_prompt, mode, phase, usage, Some(schema)).await
+    }
+
+    async fn ask_routed(
+        &self,
+        history: Vec<crate::ai::provider::ChatMessage>,
+        system_prompt: String,
+        mode: &AIMode,
+        phase: &str,
+        usage: &UsageTag,
+        schema: Option<&OutputSchema>,
     ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
         let target = match mode {
             AIMode::Hybrid => {
@@ -298,14 +743,15 @@ impl AIManager {
         };
         
         println!("[AI] {} phase using {} provider (Mode: {:?})", phase, target, mode);
-        self.ask_provider(target, history, system_prompt).await
+        self.ask_provider(target, history, system_prompt, usage, schema).await
     }
 
     pub fn map_reduce_ask(
         &self, 
         _history: Vec<crate::ai::provider::ChatMessage>, 
         long_context: String,
-        prompt_instruction: String
+        prompt_instruction: String,
+        task_id: Option<String>,
     ) -> tokio_stream::wrappers::ReceiverStream<Result<StreamEvent, Box<dyn std::error::Error + Send + Sync>>> {
         let (tx, rx): (tokio::sync::mpsc::Sender<Result<StreamEvent, Box<dyn std::error::Error + Send + Sync>>>, _) = tokio::sync::mpsc::channel(100);
         let manager = self.clone();
@@ -352,7 +798,7 @@ impl AIManager {
                 }];
 
                 // Route MAP phase through mode-aware provider
-                match manager.ask_with_mode(map_history, "You are a sub-process forensic engine. Output concise findings only.".to_string(), &ai_mode, "map").await {
+                match manager.ask_with_mode(map_history, "You are a sub-process forensic engine. Output concise findings only.".to_string(), &ai_mode, "map", &UsageTag::new(task_id.as_deref(), "chat_map")).await {
                     Ok(result) => {
                         let clean_result = result.trim();
                         if !clean_result.eq_ignore_ascii_case("CLEAR") && !clean_result.is_empty() {
@@ -390,7 +836,7 @@ impl AIManager {
             }];
 
             // Route REDUCE phase through mode-aware provider
-            match manager.ask_with_mode(reduce_history, "You are a Senior Malware Researcher. Output strict JSON.".to_string(), &ai_mode, "reduce").await {
+            match manager.ask_with_mode(reduce_history, "You are a Senior Malware Researcher. Output strict JSON.".to_string(), &ai_mode, "reduce", &UsageTag::new(task_id.as_deref(), "chat_reduce")).await {
                 Ok(final_response) => {
                      let _ = tx.send(Ok(StreamEvent::Final(final_response))).await;
                 },
@@ -407,5 +853,8 @@ impl AIManager {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum StreamEvent {
     Thought(String),
+    /// A piece of the answer as the provider streams it; Final still follows with the whole,
+    /// cleaned-up answer
+    Token(String),
     Final(String),
 }
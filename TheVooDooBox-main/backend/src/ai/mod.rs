@@ -5,3 +5,5 @@ pub mod manager;
 pub mod anthropic;
 pub mod openai;
 pub mod copilot;
+pub mod azure_openai;
+pub mod bedrock;
@@ -1,4 +1,4 @@
-use crate::ai::provider::{AIProvider, ChatMessage};
+use crate::ai::provider::{AIProvider, ApiError, ChatMessage, OutputSchema, Reply, TokenSink, TokenUsage};
 use async_trait::async_trait;
 use reqwest::Client;
 use serde_json::json;
@@ -32,7 +32,50 @@ impl AIProvider for OllamaProvider {
         "Ollama"
     }
 
-    async fn ask(&self, history: Vec<ChatMessage>, system_prompt: String) -> Result<String, Box<dyn Error + Send + Sync>> {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn ask(&self, history: Vec<ChatMessage>, system_prompt: String) -> Result<Reply, Box<dyn Error + Send + Sync>> {
+        self.complete(history, system_prompt, None).await
+    }
+
+    async fn ask_structured(&self, history: Vec<ChatMessage>, system_prompt: String, schema: &OutputSchema) -> Result<Reply, Box<dyn Error + Send + Sync>> {
+        // The schema stays in the prompt too: Ollama only holds the answer to valid JSON
+        let system_prompt = format!("{}\n\n{}", system_prompt, schema.instruction());
+        self.complete(history, system_prompt, Some(schema)).await
+    }
+
+    async fn ask_stream(&self, history: Vec<ChatMessage>, system_prompt: String, tokens: &TokenSink) -> Result<Reply, Box<dyn Error + Send + Sync>> {
+        let resp = self.request(history, system_prompt, true, None).send().await?;
+
+        if !resp.status().is_success() {
+            return Err(ApiError::from_response("Llama Server", resp).await.into());
+        }
+        crate::ai::openai::read_chat_stream(resp, tokens).await
+    }
+}
+
+impl OllamaProvider {
+    async fn complete(&self, history: Vec<ChatMessage>, system_prompt: String, schema: Option<&OutputSchema>) -> Result<Reply, Box<dyn Error + Send + Sync>> {
+        let resp = self.request(history, system_prompt, false, schema).send().await?;
+
+        if !resp.status().is_success() {
+            return Err(ApiError::from_response("Llama Server", resp).await.into());
+        }
+
+        let body: serde_json::Value = resp.json().await?;
+        
+        let response_text = body["choices"][0]["message"]["content"]
+            .as_str()
+            .ok_or("Failed to parse Llama Server response")?
+            .to_string();
+        let usage = TokenUsage::from_json(&body["usage"], "prompt_tokens", "completion_tokens");
+
+        Ok(Reply { text: response_text, usage })
+    }
+
+    fn request(&self, history: Vec<ChatMessage>, system_prompt: String, stream: bool, schema: Option<&OutputSchema>) -> reqwest::RequestBuilder {
         let url = format!("{}/v1/chat/completions", self.base_url);
         println!("[OLLAMA] Sending request to: {} (Model: {})", url, self.model);
 
@@ -55,30 +98,18 @@ impl AIProvider for OllamaProvider {
         }
 
         // OpenAI-compatible Chat API payload (used by llama-server)
-        let payload = json!({
+        let mut payload = json!({
             "model": self.model,
             "messages": messages,
-            "stream": false,
+            "stream": stream,
             "max_tokens": 64000
         });
-
-        let resp = self.client.post(&url)
-            .json(&payload)
-            .send()
-            .await?;
-
-        if !resp.status().is_success() {
-            let error_text = resp.text().await?;
-            return Err(format!("Llama Server API Error: {}", error_text).into());
+        if let Some(schema) = schema {
+            // llama-server turns the schema into a grammar; Ollama's compatibility layer
+            // ignores it and constrains the answer to JSON (its format=json)
+            payload["response_format"] = json!({ "type": "json_object", "schema": schema.schema });
         }
 
-        let body: serde_json::Value = resp.json().await?;
-        
-        let response_text = body["choices"][0]["message"]["content"]
-            .as_str()
-            .ok_or("Failed to parse Llama Server response")?
-            .to_string();
-
-        Ok(response_text)
+        self.client.post(&url).json(&payload)
     }
 }
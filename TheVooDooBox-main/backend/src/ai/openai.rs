@@ -1,76 +1,74 @@
-use crate::ai::provider::{AIProvider, ChatMessage};
+use crate::ai::provider::{read_sse, AIProvider, ApiError, ChatMessage, Reply, TokenSink, TokenUsage, OutputSchema, ToolCall, ToolRound, ToolSpec};
 use async_trait::async_trait;
 use reqwest::Client;
 use serde_json::json;
+use std::collections::HashMap;
 use std::error::Error;
 
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// OpenAI's chat completions API, or anything that speaks it: vLLM, LM Studio, the llama.cpp
+/// server, Groq, OpenRouter... `base_url` is the part before `/chat/completions` (e.g.
+/// http://vllm:8000/v1), and `extra_headers` go on every request for gateways that want more
+/// than a bearer token (OpenRouter's HTTP-Referer/X-Title, a proxy's tenant header). Local
+/// servers usually need no key, in which case no Authorization header is sent.
 pub struct OpenAIProvider {
     api_key: String,
     model: String,
+    base_url: String,
+    extra_headers: HashMap<String, String>,
     client: Client,
 }
 
 impl OpenAIProvider {
-    pub fn new(api_key: String, model: String) -> Self {
+    pub fn new(api_key: String, model: String, base_url: String, extra_headers: HashMap<String, String>) -> Self {
         let model = if model.is_empty() {
             "gpt-4o".to_string()
         } else {
             model
         };
+        let base_url = if base_url.trim().is_empty() {
+            DEFAULT_BASE_URL.to_string()
+        } else {
+            base_url.trim().trim_end_matches('/').to_string()
+        };
         
         Self {
             api_key,
             model,
+            base_url,
+            extra_headers,
             client: Client::new(),
         }
     }
 }
 
+/// A request to an OpenAI-compatible endpoint with the key (if any) and the extra headers set.
+pub fn authorize(mut req: reqwest::RequestBuilder, api_key: &str, extra_headers: &HashMap<String, String>) -> reqwest::RequestBuilder {
+    if !api_key.is_empty() {
+        req = req.bearer_auth(api_key);
+    }
+    for (name, value) in extra_headers {
+        req = req.header(name.as_str(), value.as_str());
+    }
+    req
+}
+
 #[async_trait]
 impl AIProvider for OpenAIProvider {
     fn name(&self) -> &str {
         "OpenAI"
     }
 
-    async fn ask(&self, history: Vec<ChatMessage>, system_prompt: String) -> Result<String, Box<dyn Error + Send + Sync>> {
-        let url = "https://api.openai.com/v1/chat/completions";
-
-        let mut messages = Vec::new();
-        
-        // System prompt is a distinct message in OpenAI API
-        if !system_prompt.is_empty() {
-             messages.push(json!({
-                "role": "system",
-                "content": system_prompt
-            }));
-        }
-
-        for msg in history {
-            // OpenAI roles: "system" | "user" | "assistant"
-            let role = if msg.role == "model" { "assistant" } else { &msg.role };
-            messages.push(json!({
-                "role": role,
-                "content": msg.content
-            }));
-        }
-
-        let payload = json!({
-            "model": self.model,
-            "messages": messages,
-            "max_tokens": 4096, 
-            "temperature": 0.7
-        });
+    fn model(&self) -> &str {
+        &self.model
+    }
 
-        let resp = self.client.post(url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&payload)
-            .send()
-            .await?;
+    async fn ask(&self, history: Vec<ChatMessage>, system_prompt: String) -> Result<Reply, Box<dyn Error + Send + Sync>> {
+        let resp = self.request(chat_messages(history, system_prompt), false, &[], None).send().await?;
 
         if !resp.status().is_success() {
-            let error_text = resp.text().await?;
-            return Err(format!("OpenAI API Error: {}", error_text).into());
+            return Err(ApiError::from_response("OpenAI", resp).await.into());
         }
 
         let body: serde_json::Value = resp.json().await?;
@@ -79,11 +77,156 @@ impl AIProvider for OpenAIProvider {
         if let Some(choices) = body["choices"].as_array() {
             if let Some(first_choice) = choices.first() {
                 if let Some(content) = first_choice["message"]["content"].as_str() {
-                    return Ok(content.to_string());
+                    let usage = TokenUsage::from_json(&body["usage"], "prompt_tokens", "completion_tokens");
+                    return Ok(Reply { text: content.to_string(), usage });
                 }
             }
         }
 
         Err(format!("Failed to parse OpenAI response: {:?}", body).into())
     }
+
+    async fn ask_stream(&self, history: Vec<ChatMessage>, system_prompt: String, tokens: &TokenSink) -> Result<Reply, Box<dyn Error + Send + Sync>> {
+        let resp = self.request(chat_messages(history, system_prompt), true, &[], None).send().await?;
+
+        if !resp.status().is_success() {
+            return Err(ApiError::from_response("OpenAI", resp).await.into());
+        }
+        read_chat_stream(resp, tokens).await
+    }
+
+    async fn ask_structured(&self, history: Vec<ChatMessage>, system_prompt: String, schema: &OutputSchema) -> Result<Reply, Box<dyn Error + Send + Sync>> {
+        let resp = self.request(chat_messages(history, system_prompt), false, &[], Some(schema)).send().await?;
+
+        if !resp.status().is_success() {
+            return Err(ApiError::from_response("OpenAI", resp).await.into());
+        }
+
+        let body: serde_json::Value = resp.json().await?;
+        match body["choices"][0]["message"]["content"].as_str() {
+            Some(content) => {
+                let usage = TokenUsage::from_json(&body["usage"], "prompt_tokens", "completion_tokens");
+                Ok(Reply { text: content.to_string(), usage })
+            }
+            // A refusal comes back in its own field with no content
+            None => Err(format!("OpenAI returned no structured output: {}", body["choices"][0]["message"]["refusal"].as_str().unwrap_or("unparseable response")).into()),
+        }
+    }
+
+    async fn ask_with_tools(&self, history: Vec<ChatMessage>, rounds: &[ToolRound], system_prompt: String, tools: &[ToolSpec]) -> Result<(Reply, Vec<ToolCall>), Box<dyn Error + Send + Sync>> {
+        let mut messages = chat_messages(history, system_prompt);
+        for round in rounds {
+            let calls: Vec<serde_json::Value> = round.calls.iter().map(|call| json!({
+                "id": call.id,
+                "type": "function",
+                "function": { "name": call.name, "arguments": call.arguments.to_string() }
+            })).collect();
+            messages.push(json!({ "role": "assistant", "content": null, "tool_calls": calls }));
+            for (call, result) in round.calls.iter().zip(&round.results) {
+                messages.push(json!({ "role": "tool", "tool_call_id": call.id, "content": result }));
+            }
+        }
+
+        let resp = self.request(messages, false, tools, None).send().await?;
+
+        if !resp.status().is_success() {
+            return Err(ApiError::from_response("OpenAI", resp).await.into());
+        }
+
+        let body: serde_json::Value = resp.json().await?;
+        let message = &body["choices"][0]["message"];
+        if message.is_null() {
+            return Err(format!("Failed to parse OpenAI response: {:?}", body).into());
+        }
+
+        // Arguments arrive as a JSON document inside a string
+        let calls = message["tool_calls"].as_array().map(|calls| calls.iter().filter_map(|call| Some(ToolCall {
+            id: call["id"].as_str()?.to_string(),
+            name: call["function"]["name"].as_str()?.to_string(),
+            arguments: serde_json::from_str(call["function"]["arguments"].as_str().unwrap_or("{}")).unwrap_or_default(),
+        })).collect()).unwrap_or_default();
+        let text = message["content"].as_str().unwrap_or_default().to_string();
+        let usage = TokenUsage::from_json(&body["usage"], "prompt_tokens", "completion_tokens");
+        Ok((Reply { text, usage }, calls))
+    }
+}
+
+/// The system prompt and history as chat completion messages.
+fn chat_messages(history: Vec<ChatMessage>, system_prompt: String) -> Vec<serde_json::Value> {
+    let mut messages = Vec::new();
+    
+    // System prompt is a distinct message in OpenAI API
+    if !system_prompt.is_empty() {
+         messages.push(json!({
+            "role": "system",
+            "content": system_prompt
+        }));
+    }
+
+    for msg in history {
+        // OpenAI roles: "system" | "user" | "assistant"
+        let role = if msg.role == "model" { "assistant" } else { &msg.role };
+        messages.push(json!({
+            "role": role,
+            "content": msg.content
+        }));
+    }
+    messages
+}
+
+impl OpenAIProvider {
+    fn request(&self, messages: Vec<serde_json::Value>, stream: bool, tools: &[ToolSpec], schema: Option<&OutputSchema>) -> reqwest::RequestBuilder {
+        let url = format!("{}/chat/completions", self.base_url);
+
+        let mut payload = json!({
+            "model": self.model,
+            "messages": messages,
+            "max_tokens": 4096, 
+            "temperature": 0.7
+        });
+        if stream {
+            payload["stream"] = json!(true);
+            // Usage arrives in a last chunk with no choices
+            payload["stream_options"] = json!({ "include_usage": true });
+        }
+        if !tools.is_empty() {
+            payload["tools"] = tools.iter().map(|tool| json!({
+                "type": "function",
+                "function": { "name": tool.name, "description": tool.description, "parameters": tool.parameters }
+            })).collect();
+        }
+        if let Some(schema) = schema {
+            // Not strict: strict mode wants every property required and no open maps, which
+            // the report's optional fields and MITRE matrix don't fit
+            payload["response_format"] = json!({
+                "type": "json_schema",
+                "json_schema": { "name": schema.name, "schema": schema.schema, "strict": false }
+            });
+        }
+
+        authorize(self.client.post(&url), &self.api_key, &self.extra_headers)
+            .header("Content-Type", "application/json")
+            .json(&payload)
+    }
+}
+
+/// Reads a streamed chat completion (`"stream": true`), the format shared by OpenAI and the
+/// servers that mimic it: `data: {"choices":[{"delta":{"content":"..."}}]}` chunks, then
+/// `data: [DONE]`.
+pub async fn read_chat_stream(resp: reqwest::Response, tokens: &TokenSink) -> Result<Reply, Box<dyn Error + Send + Sync>> {
+    let mut text = String::new();
+    let mut usage = None;
+    read_sse(resp, |data| {
+        if data == "[DONE]" {
+            return false;
+        }
+        let Ok(chunk) = serde_json::from_str::<serde_json::Value>(data) else { return true };
+        if let Some(piece) = chunk["choices"][0]["delta"]["content"].as_str().filter(|p| !p.is_empty()) {
+            text.push_str(piece);
+            let _ = tokens.send(piece.to_string());
+        }
+        usage = TokenUsage::from_json(&chunk["usage"], "prompt_tokens", "completion_tokens").or(usage);
+        true
+    }).await?;
+    Ok(Reply { text, usage })
 }
@@ -1,5 +1,6 @@
 use async_trait::async_trait;
 use std::error::Error;
+use std::fmt;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -8,11 +9,212 @@ pub struct ChatMessage {
     pub content: String,
 }
 
+/// Token counts of one call as the API reported (and bills) them.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    /// Reads the two counters out of a response's usage object; None when the API sent none.
+    pub fn from_json(usage: &serde_json::Value, prompt_field: &str, completion_field: &str) -> Option<Self> {
+        Some(Self {
+            prompt_tokens: usage[prompt_field].as_u64()?,
+            completion_tokens: usage[completion_field].as_u64().unwrap_or(0),
+        })
+    }
+}
+
+/// The answer text, with the usage the API reported for producing it.
+#[derive(Debug, Clone)]
+pub struct Reply {
+    pub text: String,
+    pub usage: Option<TokenUsage>,
+}
+
 #[async_trait]
 pub trait AIProvider: Send + Sync {
     /// Asks the AI a question with the given history and system prompt.
-    async fn ask(&self, history: Vec<ChatMessage>, system_prompt: String) -> Result<String, Box<dyn Error + Send + Sync>>;
+    async fn ask(&self, history: Vec<ChatMessage>, system_prompt: String) -> Result<Reply, Box<dyn Error + Send + Sync>>;
     
     /// Returns the name of the provider (e.g., "Gemini", "Ollama")
     fn name(&self) -> &str;
+
+    /// The model (or Azure deployment) answering, for usage and cost accounting.
+    fn model(&self) -> &str;
+
+    /// Like `ask`, but sends the answer to `tokens` piece by piece as the API produces it.
+    /// Providers without a streaming API send the whole answer as one piece.
+    async fn ask_stream(&self, history: Vec<ChatMessage>, system_prompt: String, tokens: &TokenSink) -> Result<Reply, Box<dyn Error + Send + Sync>> {
+        let reply = self.ask(history, system_prompt).await?;
+        let _ = tokens.send(reply.text.clone());
+        Ok(reply)
+    }
+
+    /// Like `ask`, but the answer must be one JSON object following `schema`. Providers with a
+    /// structured output mode use it; the rest get the schema in the system prompt.
+    async fn ask_structured(&self, history: Vec<ChatMessage>, system_prompt: String, schema: &OutputSchema) -> Result<Reply, Box<dyn Error + Send + Sync>> {
+        self.ask(history, format!("{}\n\n{}", system_prompt, schema.instruction())).await
+    }
+
+    /// Asks with `tools` on offer. `rounds` are the calls the model made earlier in this
+    /// exchange and their results. Comes back with the reply and, when the model wants to look
+    /// something up before answering, the calls to run. Providers without function calling get
+    /// the tools described in the system prompt and call them with `TOOL_CALL` lines.
+    async fn ask_with_tools(&self, history: Vec<ChatMessage>, rounds: &[ToolRound], system_prompt: String, tools: &[ToolSpec]) -> Result<(Reply, Vec<ToolCall>), Box<dyn Error + Send + Sync>> {
+        let mut history = history;
+        for round in rounds {
+            for (call, result) in round.calls.iter().zip(&round.results) {
+                history.push(ChatMessage {
+                    role: "assistant".to_string(),
+                    content: format!("TOOL_CALL {}", serde_json::json!({ "name": call.name, "arguments": call.arguments })),
+                });
+                history.push(ChatMessage {
+                    role: "user".to_string(),
+                    content: format!("TOOL_RESULT {}:\n{}", call.name, result),
+                });
+            }
+        }
+        let system_prompt = if tools.is_empty() {
+            system_prompt
+        } else {
+            format!("{}\n\n{}", system_prompt, text_tool_protocol(tools))
+        };
+        let reply = self.ask(history, system_prompt).await?;
+        let calls = parse_text_tool_calls(&reply.text);
+        Ok((reply, calls))
+    }
+}
+
+/// The JSON schema an answer has to follow, e.g. the forensic report's.
+#[derive(Clone, Debug)]
+pub struct OutputSchema {
+    pub name: &'static str,
+    pub schema: serde_json::Value,
+}
+
+impl OutputSchema {
+    /// The schema spelled out for the prompt, for providers that can't enforce it.
+    pub fn instruction(&self) -> String {
+        format!(
+            "Respond with a single JSON object, and nothing else (no markdown fences, no commentary), that validates against this JSON schema:\n{}",
+            self.schema
+        )
+    }
+}
+
+/// A function the model may call; `parameters` is the JSON schema of its arguments.
+#[derive(Clone, Debug)]
+pub struct ToolSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Clone, Debug)]
+pub struct ToolCall {
+    /// The provider's id for the call, echoed back with its result
+    pub id: String,
+    pub name: String,
+    pub arguments: serde_json::Value,
+}
+
+/// The calls the model made in one turn and, in the same order, what they returned.
+#[derive(Clone, Debug)]
+pub struct ToolRound {
+    pub calls: Vec<ToolCall>,
+    pub results: Vec<String>,
+}
+
+/// Runs the calls a model makes.
+#[async_trait]
+pub trait ToolRunner: Send + Sync {
+    /// The result as text for the model; failures are reported the same way so it can adjust.
+    async fn run(&self, call: &ToolCall) -> String;
+}
+
+fn text_tool_protocol(tools: &[ToolSpec]) -> String {
+    let mut out = String::from(
+        "TOOLS: You can look up evidence before answering. To call tools, reply with nothing but one line per call:\n\
+         TOOL_CALL {\"name\": \"<tool>\", \"arguments\": {...}}\n\
+         Each result comes back as TOOL_RESULT. Once you have what you need, answer normally without TOOL_CALL lines.\n\n\
+         Available tools:\n",
+    );
+    for tool in tools {
+        out.push_str(&format!("- {}: {} Arguments (JSON schema): {}\n", tool.name, tool.description, tool.parameters));
+    }
+    out
+}
+
+fn parse_text_tool_calls(text: &str) -> Vec<ToolCall> {
+    text.lines()
+        .filter_map(|line| line.trim().trim_matches('`').trim().strip_prefix("TOOL_CALL"))
+        .filter_map(|json| serde_json::from_str::<serde_json::Value>(json.trim()).ok())
+        .filter_map(|call| Some((call["name"].as_str()?.to_string(), call["arguments"].clone())))
+        .enumerate()
+        .map(|(i, (name, arguments))| ToolCall { id: format!("call_{}", i), name, arguments })
+        .collect()
 }
+
+/// Receives streamed answer text as it arrives.
+pub type TokenSink = tokio::sync::mpsc::UnboundedSender<String>;
+
+/// Hands the `data:` payload of each server-sent event in `resp` to `on_data` until the
+/// body ends or `on_data` returns false.
+pub async fn read_sse(resp: reqwest::Response, mut on_data: impl FnMut(&str) -> bool + Send) -> Result<(), Box<dyn Error + Send + Sync>> {
+    use futures::StreamExt;
+    let mut body = resp.bytes_stream();
+    let mut pending: Vec<u8> = Vec::new();
+    while let Some(chunk) = body.next().await {
+        pending.extend_from_slice(&chunk?);
+        // Events can be split anywhere, even inside a UTF-8 sequence; only whole lines are read
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if let Some(data) = line.trim_end().strip_prefix("data:") {
+                if !on_data(data.trim_start()) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A provider's API answered with an error status. Kept typed rather than flattened into a
+/// string so the manager can tell a rate limit or an outage (worth retrying, then falling back)
+/// from a bad key or request.
+#[derive(Debug)]
+pub struct ApiError {
+    pub provider: String,
+    pub status: u16,
+    /// Seconds from the Retry-After header, when the API sent one
+    pub retry_after: Option<u64>,
+    pub body: String,
+}
+
+impl ApiError {
+    pub async fn from_response(provider: &str, resp: reqwest::Response) -> Self {
+        let status = resp.status().as_u16();
+        let retry_after = resp.headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok());
+        let body = resp.text().await.unwrap_or_default();
+        Self { provider: provider.to_string(), status, retry_after, body }
+    }
+
+    /// 408, 429 and 5xx: the same request may well succeed a little later.
+    pub fn is_transient(&self) -> bool {
+        self.status == 408 || self.status == 429 || self.status >= 500
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} API Error: {}", self.provider, self.body)
+    }
+}
+
+impl Error for ApiError {}
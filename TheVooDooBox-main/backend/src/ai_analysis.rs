@@ -23,6 +23,14 @@ pub struct RawEvent {
     pub decoded_details: Option<String>,
     pub timestamp: i64,
     pub digital_signature: Option<String>,
+    #[sqlx(default)]
+    pub severity: Option<i32>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub kill_chain_stage: Option<String>,
 }
 
 // --- Structured Analysis Context for LLM ---
@@ -47,6 +55,9 @@ pub struct ProcessSummary {
     pub web_activity: Vec<WebOp>,
     pub behavior_tags: Vec<String>,
     pub digital_signature: Option<String>,
+    /// Highest agent-assigned severity (0-100) across this process's events.
+    #[serde(default)]
+    pub max_severity: i32,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -146,6 +157,84 @@ pub struct ForensicReport {
     pub digital_signature: Option<String>,
     #[serde(default)]
     pub mitre_matrix: HashMap<String, Vec<MitreTechnique>>,
+    #[serde(default)]
+    pub evidence_check: Option<crate::evidence_check::EvidenceCheck>,
+}
+
+/// What the reduce phase must return: the part of ForensicReport the model writes (the rest is
+/// filled in from the database), as a JSON schema for providers' structured output modes.
+pub fn report_schema() -> crate::ai::provider::OutputSchema {
+    let strings = serde_json::json!({ "type": "array", "items": { "type": "string" } });
+    crate::ai::provider::OutputSchema {
+        name: "forensic_report",
+        schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "verdict": {
+                    "type": "string",
+                    "enum": ["Benign", "Suspicious", "Malicious", "Diagnostic Alpha", "Diagnostic Beta", "Diagnostic Gamma"]
+                },
+                "malware_family": { "type": ["string", "null"] },
+                "threat_score": { "type": "integer", "minimum": 0, "maximum": 100 },
+                "executive_summary": { "type": "string" },
+                "behavioral_timeline": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "timestamp_offset": { "type": "string" },
+                            "stage": { "type": "string" },
+                            "event_description": { "type": "string" },
+                            "technical_context": { "type": "string" },
+                            "related_pid": { "type": "integer" }
+                        },
+                        "required": ["timestamp_offset", "stage", "event_description", "technical_context", "related_pid"]
+                    }
+                },
+                "artifacts": {
+                    "type": "object",
+                    "properties": {
+                        "dropped_files": strings,
+                        "c2_ips": strings,
+                        "c2_domains": strings,
+                        "mutual_exclusions": strings,
+                        "command_lines": strings
+                    }
+                },
+                "static_analysis_insights": strings,
+                "recommended_actions": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "action": { "type": "string" },
+                            "params": { "type": "object", "additionalProperties": { "type": "string" } },
+                            "reasoning": { "type": "string" }
+                        },
+                        "required": ["action", "params", "reasoning"]
+                    }
+                },
+                "mitre_matrix": {
+                    "type": "object",
+                    "description": "Tactic name (Execution, Persistence, ...) to the techniques detected for it",
+                    "additionalProperties": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "id": { "type": "string" },
+                                "name": { "type": "string" },
+                                "evidence": strings,
+                                "status": { "type": "string" }
+                            },
+                            "required": ["id", "name", "evidence", "status"]
+                        }
+                    }
+                }
+            },
+            "required": ["verdict", "threat_score", "executive_summary", "behavioral_timeline", "artifacts", "mitre_matrix"]
+        }),
+    }
 }
 
 fn default_summary() -> String {
@@ -379,6 +468,12 @@ pub struct AnalysisContext {
     pub related_samples: Vec<crate::memory::BehavioralFingerprint>,
     pub digital_signature: Option<String>,
     pub remnux_report: Option<serde_json::Value>,
+    /// Events per ATT&CK tactic in kill-chain order, from the deterministic stage rules.
+    pub kill_chain: Vec<crate::kill_chain::StageSummary>,
+    /// YARA rules that matched the sample, dropped files or memory dumps.
+    pub yara_matches: Vec<crate::yara::YaraMatch>,
+    /// File-format properties parsed at submission (PE headers, hashes).
+    pub static_properties: Vec<crate::static_properties::StaticProperty>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -406,7 +501,7 @@ pub struct Artifacts {
 }
 
 // Fetch Ghidra analysis from the database
-async fn fetch_ghidra_analysis(task_id: &String, pool: &Pool<Postgres>) -> StaticAnalysisData {
+pub async fn fetch_ghidra_analysis(task_id: &String, pool: &Pool<Postgres>) -> StaticAnalysisData {
     let res = sqlx::query("SELECT function_name, decompiled_code FROM ghidra_findings WHERE task_id = $1")
         .bind(task_id)
         .fetch_all(pool)
@@ -490,6 +585,25 @@ async fn get_authenticode_signature(filepath: &str) -> String {
     }
 }
 
+/// The stored forensic report for a task, if one was generated and still parses.
+pub async fn stored_report(pool: &Pool<Postgres>, task_id: &str) -> Option<ForensicReport> {
+    let json: Option<String> = sqlx::query_scalar("SELECT forensic_report_json FROM analysis_reports WHERE task_id = $1")
+        .bind(task_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten();
+    let mut current = json?;
+    // Same unwrapping as the API: older rows are sometimes double-encoded
+    for _ in 0..3 {
+        match serde_json::from_str::<serde_json::Value>(&current).ok()? {
+            serde_json::Value::String(inner) => current = inner,
+            value => return serde_json::from_value(value).ok(),
+        }
+    }
+    None
+}
+
 pub async fn generate_ai_report(
     task_id: &String, 
     pool: &Pool<Postgres>,
@@ -541,12 +655,15 @@ pub async fn generate_ai_report(
 
     // 2. Fetch Raw Telemetry (Dynamic)
     let rows = sqlx::query_as::<_, RawEvent>(
-        "SELECT event_type, process_id, parent_process_id, process_name, details, decoded_details, timestamp, digital_signature 
+        "SELECT event_type, process_id, parent_process_id, process_name, details, decoded_details, timestamp, digital_signature, severity, category, kill_chain_stage
          FROM events WHERE task_id = $1 ORDER BY timestamp ASC"
     )
     .bind(task_id)
     .fetch_all(pool)
-    .await?;
+    .await?
+    .into_iter()
+    .filter(|e| !crate::noise_filters::is_noise(None, &e.event_type, &e.process_name, e.digital_signature.as_deref()))
+    .collect::<Vec<_>>();
 
     // Fetch Analyst Notes
     let analyst_notes: Vec<AnalystNote> = sqlx::query_as::<_, AnalystNote>(
@@ -624,11 +741,12 @@ pub async fn generate_ai_report(
              }
         }
 
-        // Second pass: If not found, check ANY process (e.g. if Patient Zero PID logic failed but we have other traces)
+        // Second pass: If not found, check ANY process (e.g. if Patient Zero PID logic failed but we have other traces).
+        // Inbox children like conhost.exe are Microsoft-signed and must not lend their signature to the sample.
         if found_sig.is_empty() {
             for proc in &context.processes {
                 if let Some(sig) = &proc.digital_signature {
-                    if !sig.is_empty() && sig != "Unknown" && sig != "N/A" {
+                    if !sig.is_empty() && sig != "Unknown" && sig != "N/A" && !is_microsoft_signed(sig) {
                         found_sig = sig.clone();
                         break; // Stop at first valid signature
                     }
@@ -653,6 +771,46 @@ pub async fn generate_ai_report(
     context.digital_signature = Some(digital_signature.clone());
     context.remnux_report = remnux_report;
 
+    // Signature hits from the network sensor sit with the behavioural alerts
+    for alert in crate::suricata::top_alerts(pool, task_id, 20).await {
+        context.critical_alerts.push(CriticalAlert {
+            rule_name: format!("SURICATA: {}", alert.signature),
+            severity: match alert.severity { 1 => "HIGH", 2 => "MEDIUM", _ => "LOW" }.to_string(),
+            details: format!(
+                "{}:{} -> {}:{} [{}] sid {}",
+                alert.src_ip.unwrap_or_default(), alert.src_port.unwrap_or(0),
+                alert.dest_ip.unwrap_or_default(), alert.dest_port.unwrap_or(0),
+                alert.category.unwrap_or_default(), alert.signature_id
+            ),
+        });
+    }
+
+    // Memory forensics: code regions malfind found injected into processes
+    for hit in crate::volatility::injection_hits(pool, task_id, 10).await {
+        context.critical_alerts.push(CriticalAlert {
+            rule_name: "VOLATILITY: Injected Code (malfind)".to_string(),
+            severity: "HIGH".to_string(),
+            details: hit,
+        });
+    }
+
+    context.yara_matches = crate::yara::matches(pool, task_id).await;
+    context.static_properties = crate::static_properties::properties(pool, task_id).await;
+    if let Some(office) = crate::static_properties::find(&context.static_properties, "office").filter(|o| o["risk"] == "high") {
+        context.critical_alerts.push(CriticalAlert {
+            rule_name: "OFFICE: High-Risk Document".to_string(),
+            severity: "HIGH".to_string(),
+            details: crate::office::describe(office),
+        });
+    }
+    if let Some(pdf) = crate::static_properties::find(&context.static_properties, "pdf").filter(|p| p["risk"] == "high") {
+        context.critical_alerts.push(CriticalAlert {
+            rule_name: "PDF: High-Risk Document".to_string(),
+            severity: "HIGH".to_string(),
+            details: crate::pdf::describe(pdf),
+        });
+    }
+
     // 4. Fetch Static Data (Ghidra)
     let mut static_data = fetch_ghidra_analysis(task_id, pool).await;
     
@@ -663,6 +821,12 @@ pub async fn generate_ai_report(
         b_is_suspicious.cmp(&a_is_suspicious)
     });
     static_data.functions.truncate(20); 
+    if let Some(dlls) = crate::static_properties::find(&context.static_properties, "pe").and_then(|pe| pe["imported_dlls"].as_array()) {
+        static_data.imported_dlls = dlls.iter().filter_map(|d| d.as_str().map(str::to_string)).collect();
+    }
+    if let Some(strings) = crate::static_properties::find(&context.static_properties, "strings") {
+        static_data.strings = crate::strings::lines(strings);
+    }
     
     context.static_analysis = static_data;
 
@@ -752,6 +916,9 @@ pub async fn generate_ai_report(
              score += (p.file_activity.len() as i32) * 50;
              score += (p.registry_mods.len() as i32) * 50;
              if p.image_name.to_lowercase().contains("powershell") || p.image_name.to_lowercase().contains("cmd") { score += 1000; }
+             score += p.max_severity * 10;
+             // Verified Microsoft binaries are rarely the payload; LOLBins still surface via the bonus above
+             if p.pid != root_pid_num && p.digital_signature.as_deref().is_some_and(is_microsoft_signed) { score -= 1500; }
              score
         };
         get_score(b).cmp(&get_score(a))
@@ -785,40 +952,30 @@ pub async fn generate_ai_report(
         let target_filename = target_filename.to_string();
         let digital_signature = digital_signature.clone();
         let total_chunks = chunks.len();
+        let usage_map = crate::ai_usage::UsageTag::new(Some(task_id.as_str()), "report_map");
 
         async move {
             println!("[AI] Processing Chunk {}/{} via Local LLM...", i+1, total_chunks);
             
             let chunk_json = serde_json::to_string(&chunk).unwrap_or_default();
-            let map_prompt = format!(
-                "Analyze this telemetry chunk (Part {}/{}). Identify suspicious behavior.
-                 Target File: {}
-                 Digital Signature: {}
-                 
-                 PROCESS DATA:
-                 {}
-                 
-                 OUTPUT FORMAT:
-                 Return a JSON array of strings, where each string is a concise insight about a specific suspicious action.
-                 Example: [\"Process powershell.exe (PID 454) established network connection to 45.33.2.1\", \"Process cmd.exe deleted shadow copies\"]
-                 If nothing suspicious is found, return empty array [].
-                 DO NOT produce a full report. Only precise insights.
-                 
-                 CONTEXT:
-                 - If SIGNED by a reputable vendor (Microsoft, EA, Adobe, Google, etc.), treat System Queries, File Creation, and Registry Mods as NORMAL installer behavior. 
-                 - ONLY flag behavior as suspicious if it is clearly malicious (e.g. Process Injection, Shadow Copy Deletion, Ransomware Extensions).", 
-                 i+1, total_chunks, target_filename, digital_signature, chunk_json
-            );
-
-            let system_prompt = "You are a Forensic Pre-Processor. Your job is to extract raw technical facts from telemetry chunks.";
+            let map_prompt = crate::prompts::render(pool, "report_map", serde_json::json!({
+                "part": i + 1,
+                "total": total_chunks,
+                "target_filename": target_filename,
+                "digital_signature": digital_signature,
+                "process_data": chunk_json,
+            })).await;
+            let system_prompt = crate::prompts::render(pool, "report_map_system", serde_json::json!({})).await;
+            let usage_map = usage_map.with_prompts(&[&system_prompt, &map_prompt]);
             
             // We force "map" phase to use Local provider in Hybrid mode via manager.rs logic
             // We use a blank history for each chunk to keep it stateless
             let response = ai_manager.ask_with_mode(
-                vec![crate::ai::provider::ChatMessage { role: "user".to_string(), content: map_prompt }], 
-                system_prompt.to_string(),
+                vec![crate::ai::provider::ChatMessage { role: "user".to_string(), content: map_prompt.text }], 
+                system_prompt.text,
                 &ai_mode, // Respect User Selection
-                "map"
+                "map",
+                &usage_map
             ).await;
 
             match response {
@@ -903,68 +1060,47 @@ pub async fn generate_ai_report(
     };
     
     let vt_summary = serde_json::to_string(&vt_data).unwrap_or("None".to_string());
+    let yara_summary = crate::yara::describe(&context.yara_matches);
+    let file_summary = crate::static_properties::describe(&context.static_properties);
+
+    let kill_chain_summary = if context.kill_chain.is_empty() {
+        "No events matched a kill-chain stage.".to_string()
+    } else {
+        let start = context.kill_chain.iter().map(|s| s.first_seen).min().unwrap_or(0);
+        context.kill_chain.iter().map(|s| format!("{}: {} events (first +{}s, last +{}s, max severity {})",
+            s.label, s.events, (s.first_seen - start) / 1000, (s.last_seen - start) / 1000, s.max_severity)).collect::<Vec<_>>().join("\n")
+    };
 
-    let reduce_prompt = format!(
-        "GENERATE COMPREHENSIVE FORENSIC REPORT.
-         
-         TARGET: {} (Hash: '{}')
-         VERDICT: Decide if Malicious, Suspicious, or Benign (Use 'Diagnostic Gamma' for Malicious).
-         
-         --- AGGREGATED TELEMETRY INSIGHTS ---
-         {}
-         
-         --- STATIC ANALYSIS (Ghidra) ---
-         {}
-         
-         --- VIRUSTOTAL ---
-         {}
-         
-         --- DIGITAL SIGNATURE ---
-         {}
-         
-         --- RAG CONTEXT ---
-         {}
-         
-         REQUIRED JSON SCHEMA:
-         {{
-           \"verdict\": \"Malicious\" | \"Suspicious\" | \"Benign\",
-           \"malware_family\": \"string or null\",
-           \"threat_score\": 0-100,
-           \"executive_summary\": \"High-level technical overview (1-2 paragraphs)\",
-           \"behavioral_timeline\": [
-             {{ \"timestamp_offset\": \"+2s\", \"stage\": \"Persistence\", \"event_description\": \"...\", \"technical_context\": \"...\", \"related_pid\": 123 }}
-           ],
-           \"artifacts\": {{
-             \"dropped_files\": [], \"c2_ips\": [], \"c2_domains\": [], \"mutual_exclusions\": [], \"command_lines\": []
-           }},
-           \"mitre_matrix\": {{
-             \"Execution\": [{{ \"id\": \"T1059\", \"name\": \"Command and Scripting Interpreter\", \"evidence\": [\"...\"], \"status\": \"Detected\" }}],
-             \"Persistence\": [...],
-             \"Defense Evasion\": [...],
-             \"Discovery\": [...],
-             \"Lateral Movement\": [...],
-             \"Command and Control\": [...]
-           }}
-         }}
-
-         STRICT VERDICT RULES:
-         1. IF THE FILE HAS A VERIFIED DIGITAL SIGNATURE from a known vendor (Microsoft, EA, Adobe, Google, etc.):
-            - Default to BENIGN or SUSPICIOUS (Score < 50).
-            - WMI Queries, System Info Discovery, and Dropping Files are NORMAL behavior for installers. DO NOT flag as malicious.
-            - ONLY verdict as MALICIOUS if there is conclusive evidence of Process Injection (Hollowing, Doppelganging), Shellcode Execution, or Ransomware activity.
-         2. IF UNSIGNED or INVALID SIGNATURE:
-            - Treat evasion and persistence as high-risk indicators.
-
-         STRICT OUTPUT RULES:
-         1. OUTPUT RAW JSON ONLY.
-         2. DO NOT USE MARKDOWN BLOCKS (```json).
-         3. DO NOT INCLUDE PREAMBLE, COMMENTARY, OR EXPLANATIONS.
-         4. ENSURE EVERY MITRE TACTIC DETECTED IS IN THE `mitre_matrix`.
-         ",
-         target_filename, file_hash, consolidated_insights, static_summary, vt_summary, digital_signature, rag_context
-    );
+    let deterministic_summary = match crate::scoring::load(pool, task_id).await {
+        Ok(Some(score)) => crate::scoring::describe(&score),
+        _ => "Not scored.".to_string(),
+    };
+
+    let sightings_summary = match crate::ioc_correlation::correlate(pool, task_id, false).await {
+        Ok(correlations) => crate::ioc_correlation::describe(&correlations),
+        Err(_) => "Not correlated.".to_string(),
+    };
+
+    let screen_summary = crate::ocr::describe(&crate::ocr::texts(pool, task_id).await);
+
+    let reduce_prompt = crate::prompts::render(pool, "report_reduce", serde_json::json!({
+        "target_filename": target_filename,
+        "file_hash": file_hash,
+        "insights": consolidated_insights,
+        "deterministic_score": deterministic_summary,
+        "kill_chain": kill_chain_summary,
+        "sightings": sightings_summary,
+        "file_properties": file_summary,
+        "static_analysis": static_summary,
+        "yara": yara_summary,
+        "screen_text": screen_summary,
+        "virustotal": vt_summary,
+        "digital_signature": digital_signature,
+        "rag_context": rag_context,
+    })).await;
         
-    let system_reduce = "You are the Lead Digital Forensics Expert. Synthesize the provided technical insights into a final comprehensive report.";
+    let system_reduce = crate::prompts::render(pool, "report_reduce_system", serde_json::json!({})).await;
+    let usage_reduce = crate::ai_usage::UsageTag::new(Some(task_id.as_str()), "report_reduce").with_prompts(&[&system_reduce, &reduce_prompt]);
 
     println!("[AI] Starting Reduce Phase (Cloud LLM)...");
     
@@ -972,11 +1108,13 @@ pub async fn generate_ai_report(
     // We strictly limit the Reduce phase to 10 minutes to prevent indefinite hangs.
     let response_result = match tokio::time::timeout(
         std::time::Duration::from_secs(600),
-        ai_manager.ask_with_mode(
-            vec![crate::ai::provider::ChatMessage { role: "user".to_string(), content: reduce_prompt }],
-            system_reduce.to_string(),
+        ai_manager.ask_structured_with_mode(
+            vec![crate::ai::provider::ChatMessage { role: "user".to_string(), content: reduce_prompt.text }],
+            system_reduce.text,
             &ai_mode,
-            "reduce"
+            "reduce",
+            &usage_reduce,
+            &report_schema()
         )
     ).await {
         Ok(res) => res,
@@ -999,6 +1137,13 @@ pub async fn generate_ai_report(
     // 6. Extraction & Parsing
     let mut extracted_thinking = None;
 
+    // Structured output normally hands back exactly the schema; the cleanup and repair
+    // passes below are for models that still wrap or break it
+    let direct_report = serde_json::from_str::<ForensicReport>(response_text.trim()).ok();
+    if direct_report.is_some() {
+        println!("[AI] Structured report parsed directly.");
+    }
+
     // STEP A: CRITICAL FIX - Detect if the response is a JSON string containing escaped JSON
     // Check this FIRST, because if the whole response is double-encoded, <think> tags and JSON are hidden inside
     if response_text.trim().starts_with('"') && response_text.trim().ends_with('"') {
@@ -1061,9 +1206,11 @@ pub async fn generate_ai_report(
 
     // --- ROBUST JSON PARSING PIPELINE ---
     let mut current_json = response_text.clone();
-    let mut report_result: Option<ForensicReport> = None;
+    let mut report_result: Option<ForensicReport> = direct_report;
+    let parse_outcome = if report_result.is_some() { "direct" } else { "repaired" };
 
     for pass in 0..4 {
+        if report_result.is_some() { break; }
         let trimmed = current_json.trim();
         if trimmed.is_empty() { break; }
 
@@ -1130,6 +1277,7 @@ pub async fn generate_ai_report(
     
     let mut report = match report_result {
         Some(mut r) => {
+            crate::metrics::inc("voodoobox_ai_report_parse_total", &[("outcome", parse_outcome)], 1.0);
             if extracted_thinking.is_some() {
                 r.thinking = extracted_thinking;
             }
@@ -1137,6 +1285,7 @@ pub async fn generate_ai_report(
         },
         None => {
             // Regex Fallback
+            crate::metrics::inc("voodoobox_ai_report_parse_total", &[("outcome", "salvaged")], 1.0);
             println!("[AI] JSON Parsing Failed. Attempting Regex Fallback and Salvage...");
             
             // Regex Extraction Patterns for Summary
@@ -1221,12 +1370,25 @@ pub async fn generate_ai_report(
                 recommended_actions: vec![],
                 digital_signature: Some(digital_signature.clone()),
                 mitre_matrix: HashMap::new(),
+                evidence_check: None,
             }
         }
     };
 
+    // 6b. Evidence Check: claims the telemetry doesn't back are marked or dropped
+    match crate::evidence_check::check(pool, task_id, &mut report).await {
+        Ok(check) => {
+            if !check.unverified.is_empty() {
+                println!("[AI] Evidence check: {}/{} claims unverified (hallucination score {})", check.unverified.len(), check.checked, check.hallucination_score);
+            }
+            report.evidence_check = Some(check);
+        }
+        Err(e) => println!("[AI] Evidence check failed: {}", e),
+    }
+
     // 7. DB Mapping (Best Effort)
     let mut suspicious_pids: Vec<i32> = report.behavioral_timeline.iter()
+        .filter(|e| !e.technical_context.starts_with(crate::evidence_check::UNVERIFIED))
         .map(|e| e.related_pid)
         .collect();
     suspicious_pids.sort();
@@ -1246,8 +1408,8 @@ pub async fn generate_ai_report(
         .unwrap_or_else(|_| "{}".to_string());
     
     sqlx::query(
-        "INSERT INTO analysis_reports (task_id, risk_score, threat_level, summary, suspicious_pids, mitre_tactics, recommendations, forensic_report_json, created_at)
-         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        "INSERT INTO analysis_reports (task_id, risk_score, threat_level, summary, suspicious_pids, mitre_tactics, recommendations, forensic_report_json, created_at, hallucination_score)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
          ON CONFLICT (task_id) DO UPDATE SET
          risk_score = EXCLUDED.risk_score,
          threat_level = EXCLUDED.threat_level,
@@ -1256,7 +1418,8 @@ pub async fn generate_ai_report(
          mitre_tactics = EXCLUDED.mitre_tactics,
          recommendations = EXCLUDED.recommendations,
          forensic_report_json = EXCLUDED.forensic_report_json,
-         created_at = EXCLUDED.created_at"
+         created_at = EXCLUDED.created_at,
+         hallucination_score = EXCLUDED.hallucination_score"
     )
     .bind(task_id)
     .bind(report.threat_score as i32)
@@ -1267,9 +1430,21 @@ pub async fn generate_ai_report(
     .bind(&recommendations)
     .bind(&forensic_json)
     .bind(Utc::now().timestamp_millis())
+    .bind(report.evidence_check.as_ref().map(|c| c.hallucination_score))
     .execute(pool)
     .await?;
     
+    crate::siem::forward(crate::siem::Record {
+        kind: "verdict",
+        task_id: Some(task_id.to_string()),
+        timestamp: Utc::now().timestamp_millis(),
+        name: report.verdict.to_string(),
+        category: "verdict".to_string(),
+        severity: report.threat_score.clamp(0, 100),
+        details: report.executive_summary.clone(),
+        ..Default::default()
+    });
+
     // 8. Update Task Verdict
     let verdict_str = report.verdict.to_string(); 
     sqlx::query("UPDATE tasks SET verdict=$2, risk_score=$3 WHERE id=$1")
@@ -1311,6 +1486,7 @@ pub async fn generate_ai_report(
                         println!("[AI] Failed to write PDF to disk: {}", e);
                     } else {
                         println!("[AI] PDF Report saved to: {}", file_path);
+                        crate::storage::persist(&file_path).await;
                     }
                 },
                 Err(e) => println!("[AI] Failed to create PDF file: {}", e),
@@ -1333,8 +1509,9 @@ pub async fn generate_ai_report(
                 ActionManager::execute_actions(&task_id_clone, actions, agent_manager_clone, &pool_clone).await;
             });
         }
-    } else {
-        println!("[AI] Auto-Response disabled. Skipping {} actions.", report.recommended_actions.len());
+    } else if !report.recommended_actions.is_empty() {
+        println!("[AI] Auto-Response disabled. Queueing {} actions for analyst approval.", report.recommended_actions.len());
+        ActionManager::queue_actions(&task_id, &report.recommended_actions, pool).await;
     }
 
     // 10. Store Fingerprint in The Hive Mind (Async, don't block heavily but wait for result)
@@ -1375,10 +1552,8 @@ fn build_process_lineage(events: &[RawEvent], target_filename: &str) -> (std::co
     let root_pid = if let Some(pz) = patient_zero {
         pz.process_id
     } else {
-        // Fallback: If no direct match, find the first non-noise PID
-        // Improved Noise Filter: Use contains() to catch full paths (e.g. C:\Windows\System32\svchost.exe)
+        // Fallback: If no direct match, the first PID left after noise filtering (see noise_filters)
         events.iter()
-            .filter(|e| !NOISE_PROCESSES.iter().any(|np| e.process_name.to_lowercase().contains(&np.to_lowercase())))
             .map(|e| e.process_id)
             .next()
             .unwrap_or(0)
@@ -1404,36 +1579,22 @@ fn build_process_lineage(events: &[RawEvent], target_filename: &str) -> (std::co
     (relevant_pids, root_pid)
 }
 
-const NOISE_PROCESSES: &[&str] = &[
-    "voodoobox-agent.exe",
-    "voodoobox-agent-windows.exe",
-    "mallab-agent-windows.exe",
-    "mallab-agent", // Linux/Generic variant
-    "sysmon.exe",
-    "sysmon64.exe",
-    "conhost.exe",
-    "searchindexer.exe",
-    "taskhostw.exe",
-    "mpcmdrun.exe",
-    "msmpeng.exe",
-    "chrome.exe",         // Often background noise unless specifically testing browser
-    "discord.exe",
-    "explorer.exe",       // Desktop noise
-    "backgroundtaskhost.exe",
-    "runtimebroker.exe",
-    "svchost.exe",        // Although sometimes injected, usually noise unless directly spawned by malware
-    "sihost.exe",
-    "ctfmon.exe",
-];
+/// Agent signature strings look like "Signed (Verified) | Signer: Microsoft Windows"
+/// (or "Signed (Verified, Catalog) | ..." for inbox binaries signed via a system catalog).
+fn is_microsoft_signed(sig: &str) -> bool {
+    sig.starts_with("Signed (Verified")
+        && sig.split("| Signer:").nth(1).is_some_and(|s| s.trim().starts_with("Microsoft"))
+}
 
 fn aggregate_telemetry(task_id: &String, raw_events: Vec<RawEvent>, target_filename: &str, exclude_ips: Vec<String>) -> AnalysisContext {
     let mut process_map: HashMap<i32, ProcessSummary> = HashMap::new();
     let mut critical_alerts: Vec<CriticalAlert> = Vec::new();
 
     let (relevant_pids, root_pid) = build_process_lineage(&raw_events, target_filename);
+    let kill_chain = stage_summary(&raw_events);
 
     for evt in &raw_events {
-        let is_critical = matches!(evt.event_type.as_str(), "MEMORY_ANOMALY" | "PROCESS_TAMPER" | "REMOTE_THREAD");
+        let is_critical = matches!(evt.event_type.as_str(), "MEMORY_ANOMALY" | "PROCESS_TAMPER" | "REMOTE_THREAD" | "COM_HIJACK" | "STARTUP_PERSISTENCE" | "SCHTASK_CREATED" | "SCHTASK_MODIFIED" | "WMI_SUBSCRIPTION" | "CERT_INSTALLED" | "PROXY_HIJACK" | "DEFENDER_EXCLUSION_ADDED" | "DEFENDER_TAMPER");
         let is_relevant = relevant_pids.contains(&evt.process_id);
 
         // Logic Fix:
@@ -1448,7 +1609,9 @@ fn aggregate_telemetry(task_id: &String, raw_events: Vec<RawEvent>, target_filen
                               || name_lower.contains("wscript") 
                               || name_lower.contains("cscript")
                               || name_lower.contains("rundll32")
-                              || name_lower.contains("regsvr32");
+                              || name_lower.contains("regsvr32")
+                              // High-severity events (agent-scored) survive even outside the lineage
+                              || evt.severity.unwrap_or(0) >= crate::severity_rules::SEVERITY_HIGH;
 
             if !is_interesting {
                 continue; 
@@ -1466,12 +1629,15 @@ fn aggregate_telemetry(task_id: &String, raw_events: Vec<RawEvent>, target_filen
             web_activity: Vec::new(),
             behavior_tags: Vec::new(),
             digital_signature: None,
+            max_severity: 0,
         });
 
         let proc = process_map.get_mut(&evt.process_id).unwrap();
+        proc.max_severity = proc.max_severity.max(evt.severity.unwrap_or(0));
 
-        // Capture Digital Signature from Process Start events
-        if let Some(sig) = &evt.digital_signature {
+        // Capture Digital Signature from Process Start events (IMAGE_LOAD signatures belong to the DLL, not the process)
+        let is_start = matches!(evt.event_type.as_str(), "PROCESS_CREATE" | "EXEC_SUCCESS");
+        if let (true, Some(sig)) = (is_start, &evt.digital_signature) {
             if !sig.is_empty() && sig != "N/A" && sig != "Unknown" && proc.digital_signature.is_none() {
                 proc.digital_signature = Some(sig.clone());
             }
@@ -1489,7 +1655,7 @@ fn aggregate_telemetry(task_id: &String, raw_events: Vec<RawEvent>, target_filen
                    proc.command_line = evt.details.clone();
                }
             },
-            "NETWORK_CONNECT" | "NETWORK_DNS" => {
+            "NETWORK_CONNECT" | "NETWORK_DNS" | "NETWORK_HTTP" | "NETWORK_FAKENET" => {
                 // Parse details: "SYSMON: TCP 192.168.1.5:5433 -> 142.250.1.1:443" OR "SYSMON: DNS: query -> result"
                 // Simplified fuzzy parsing for robustness
                 let mut dest = if evt.details.contains("->") {
@@ -1520,7 +1686,11 @@ fn aggregate_telemetry(task_id: &String, raw_events: Vec<RawEvent>, target_filen
                     proc.network_activity.push(NetworkOp {
                         dest,
                         port,
-                        protocol: if evt.event_type.contains("DNS") { "DNS".into() } else { "TCP".into() },
+                        protocol: match evt.event_type.as_str() {
+                            "NETWORK_DNS" => "DNS".into(),
+                            "NETWORK_HTTP" => "HTTP".into(),
+                            _ => "TCP".into(),
+                        },
                         count: 1
                     });
                 }
@@ -1565,6 +1735,44 @@ fn aggregate_telemetry(task_id: &String, raw_events: Vec<RawEvent>, target_filen
                 });
                 proc.behavior_tags.push(evt.event_type.clone());
             },
+            "COM_HIJACK" | "STARTUP_PERSISTENCE" | "SCHTASK_CREATED" | "SCHTASK_MODIFIED" | "WMI_SUBSCRIPTION" => {
+                // Persistence outside the Run keys; agent reports these with PID 0
+                critical_alerts.push(CriticalAlert {
+                    rule_name: evt.event_type.clone(),
+                    severity: if evt.details.starts_with("COM Hijack") || evt.event_type == "WMI_SUBSCRIPTION" { "HIGH".to_string() } else { "MEDIUM".to_string() },
+                    // Task XML / WMI object follows the summary line; the alert only needs the summary
+                    details: evt.details.lines().next().unwrap_or_default().to_string()
+                });
+            },
+            "CERT_INSTALLED" | "PROXY_HIJACK" => {
+                // Trust/proxy tampering enables TLS interception; agent reports these with PID 0
+                critical_alerts.push(CriticalAlert {
+                    rule_name: evt.event_type.clone(),
+                    severity: "HIGH".to_string(),
+                    details: evt.details.clone()
+                });
+            },
+            "DEFENDER_EXCLUSION_ADDED" | "DEFENDER_TAMPER" => {
+                // The harness's own SET_DEFENSES uses Set-MpPreference, which the agent does not watch,
+                // so anything here came from the guest (i.e. the sample)
+                critical_alerts.push(CriticalAlert {
+                    rule_name: evt.event_type.clone(),
+                    severity: "HIGH".to_string(),
+                    details: evt.details.clone()
+                });
+            },
+            "HTTP_REQUEST" => {
+                // Decrypted by the interception proxy: "HTTP_REQUEST: GET https://host/path -> 200"
+                let url = evt.details.split_whitespace().nth(2).unwrap_or("unknown").to_string();
+                proc.web_activity.push(WebOp {
+                    url,
+                    event_type: "REQUEST".to_string(),
+                    details: match &evt.decoded_details {
+                        Some(decoded) => format!("{} | {}", evt.details, decoded.chars().take(500).collect::<String>()),
+                        None => evt.details.clone(),
+                    },
+                });
+            },
             "BROWSER_NAVIGATE" | "BROWSER_REDIRECT" | "BROWSER_DOM" => {
                 // Parse details - format depends on Agent implementation
                 // Agent sends: "URL: ... | Title: ..." OR "REDIRECT: ... -> ..." OR "DOM SNAPSHOT: ... (Preview: ...)"
@@ -1593,11 +1801,19 @@ fn aggregate_telemetry(task_id: &String, raw_events: Vec<RawEvent>, target_filen
         }
     }
 
+    // Tag verified Microsoft binaries so the model (and the relevance sort) can discount them
+    let mut processes: Vec<ProcessSummary> = process_map.into_values().collect();
+    for proc in processes.iter_mut() {
+        if proc.digital_signature.as_deref().is_some_and(is_microsoft_signed) {
+            proc.behavior_tags.push("Verified Microsoft Binary".to_string());
+        }
+    }
+
     AnalysisContext {
         scan_id: task_id.clone(),
         generated_at: Utc::now().to_rfc3339(),
         critical_alerts,
-        processes: process_map.into_values().collect(),
+        processes,
         static_analysis: StaticAnalysisData {
             functions: vec![],
             imported_dlls: vec![],
@@ -1611,5 +1827,37 @@ fn aggregate_telemetry(task_id: &String, raw_events: Vec<RawEvent>, target_filen
         related_samples: vec![],
         digital_signature: None,
         remnux_report: None,
+        kill_chain,
+        yara_matches: vec![],
+        static_properties: vec![],
+    }
+}
+
+/// Stage structure of the whole run, before the noise cut; events stored before stages were
+/// assigned at ingest are classified here.
+fn stage_summary(events: &[RawEvent]) -> Vec<crate::kill_chain::StageSummary> {
+    let mut stages: HashMap<String, crate::kill_chain::StageSummary> = HashMap::new();
+    for evt in events {
+        let stage = evt.kill_chain_stage.clone().or_else(|| {
+            crate::kill_chain::classify(&evt.event_type, &evt.process_name, &evt.details, evt.decoded_details.as_deref(), evt.category.as_deref())
+                .map(str::to_string)
+        });
+        let Some(stage) = stage else { continue };
+        let entry = stages.entry(stage.clone()).or_insert_with(|| crate::kill_chain::StageSummary {
+            label: crate::kill_chain::label(&stage),
+            stage,
+            events: 0,
+            first_seen: evt.timestamp,
+            last_seen: evt.timestamp,
+            max_severity: 0,
+            example_event_ids: Vec::new(),
+        });
+        entry.events += 1;
+        entry.first_seen = entry.first_seen.min(evt.timestamp);
+        entry.last_seen = entry.last_seen.max(evt.timestamp);
+        entry.max_severity = entry.max_severity.max(evt.severity.unwrap_or(0));
     }
+    let mut out: Vec<_> = stages.into_values().collect();
+    out.sort_by_key(|s| crate::kill_chain::STAGES.iter().position(|(k, _)| *k == s.stage).unwrap_or(crate::kill_chain::STAGES.len()));
+    out
 }
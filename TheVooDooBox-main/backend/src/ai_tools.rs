@@ -0,0 +1,386 @@
+use crate::ai::manager::{AIManager, StreamEvent};
+use crate::ai::provider::{ChatMessage, ToolCall, ToolRunner, ToolSpec};
+use async_trait::async_trait;
+use serde_json::json;
+use sqlx::{Pool, Postgres, Row};
+use std::fmt::Write;
+
+// --- AI CHAT TOOLS ---
+// Tools the chat model calls to look up a task's evidence; AI_CHAT_TOOLS=false disables.
+
+/// A tool result longer than this is cut, so one broad query can't flood the context.
+const MAX_RESULT_CHARS: usize = 12000;
+
+pub fn enabled() -> bool {
+    std::env::var("AI_CHAT_TOOLS").map(|v| v != "false" && v != "0").unwrap_or(true)
+}
+
+pub fn specs() -> Vec<ToolSpec> {
+    vec![
+        ToolSpec {
+            name: "query_events",
+            description: "Searches the task's telemetry (benign system noise excluded). Returns matching events oldest first.",
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "event_type": { "type": "string", "description": "Exact type, e.g. PROCESS_CREATE, FILE_CREATE, NETWORK_CONNECT, REG_SETVALUE" },
+                    "process_name": { "type": "string", "description": "Substring of the process image, case-insensitive" },
+                    "pid": { "type": "integer", "description": "Events of this process ID" },
+                    "text": { "type": "string", "description": "Substring of the event details, case-insensitive" },
+                    "min_severity": { "type": "integer", "description": "Only events scored at least this (0-100)" },
+                    "limit": { "type": "integer", "description": "At most this many events (default 50, max 200)" }
+                }
+            }),
+        },
+        ToolSpec {
+            name: "get_process_tree",
+            description: "The task's process lineage: every PID with its parent, image, command line, event count and highest severity.",
+            parameters: json!({ "type": "object", "properties": {} }),
+        },
+        ToolSpec {
+            name: "get_ghidra_function",
+            description: "Decompiled code of one function from the static analysis, by name or entry point. With neither, lists all functions.",
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string", "description": "Function name, e.g. FUN_00401000" },
+                    "address": { "type": "string", "description": "Entry point, e.g. 00401000" }
+                }
+            }),
+        },
+        ToolSpec {
+            name: "vt_lookup",
+            description: "The VirusTotal report cached for a hash: detections, threat label, families, behaviour tags.",
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "sha256": { "type": "string", "description": "Hash to look up; defaults to the analysed sample" }
+                }
+            }),
+        },
+    ]
+}
+
+/// Offered only while an agent session is running the task.
+pub fn propose_action_spec() -> ToolSpec {
+    ToolSpec {
+        name: "propose_action",
+        description: "Proposes an action in the running sandbox. It is queued for an analyst to approve; it does not run by itself.",
+        parameters: json!({
+            "type": "object",
+            "properties": {
+                "action": { "type": "string", "enum": crate::action_manager::PROPOSABLE, "description": "SCREENSHOT, MEM_DUMP (pid), FETCH_FILE (path in the guest) or KILL (pid)" },
+                "pid": { "type": "integer" },
+                "path": { "type": "string" },
+                "reasoning": { "type": "string", "description": "Why, for the analyst deciding" }
+            },
+            "required": ["action", "reasoning"]
+        }),
+    }
+}
+
+/// Runs tool calls against one task's data.
+pub struct TaskTools {
+    pool: Pool<Postgres>,
+    task_id: String,
+}
+
+impl TaskTools {
+    pub fn new(pool: Pool<Postgres>, task_id: String) -> Self {
+        Self { pool, task_id }
+    }
+
+    async fn query_events(&self, args: &serde_json::Value) -> Result<String, sqlx::Error> {
+        let limit = args["limit"].as_i64().unwrap_or(50).clamp(1, 200);
+        let mut qb = sqlx::QueryBuilder::<Postgres>::new(
+            "SELECT event_type, process_id, parent_process_id, process_name, details, decoded_details, timestamp, digital_signature, severity
+             FROM events WHERE task_id = "
+        );
+        qb.push_bind(&self.task_id);
+        if let Some(event_type) = args["event_type"].as_str() {
+            qb.push(" AND event_type = ").push_bind(event_type.to_uppercase());
+        }
+        if let Some(process_name) = args["process_name"].as_str() {
+            qb.push(" AND process_name ILIKE ").push_bind(format!("%{}%", process_name));
+        }
+        if let Some(pid) = args["pid"].as_i64() {
+            qb.push(" AND process_id = ").push_bind(pid as i32);
+        }
+        if let Some(text) = args["text"].as_str() {
+            qb.push(" AND (details ILIKE ").push_bind(format!("%{}%", text))
+                .push(" OR decoded_details ILIKE ").push_bind(format!("%{}%", text)).push(")");
+        }
+        if let Some(min) = args["min_severity"].as_i64() {
+            qb.push(" AND COALESCE(severity, 0) >= ").push_bind(min as i32);
+        }
+        // Noise is dropped after the query, so fetch some slack
+        qb.push(" ORDER BY timestamp ASC LIMIT ").push_bind(limit * 4);
+
+        let rows = qb.build().fetch_all(&self.pool).await?;
+        let mut out = String::new();
+        let mut shown = 0;
+        for row in &rows {
+            let event_type: String = row.get("event_type");
+            let process_name: String = row.get("process_name");
+            let signature: Option<String> = row.get("digital_signature");
+            if crate::noise_filters::is_noise(None, &event_type, &process_name, signature.as_deref()) {
+                continue;
+            }
+            if shown == limit {
+                let _ = writeln!(out, "... more events match; narrow the query");
+                break;
+            }
+            shown += 1;
+            let details: String = row.get("details");
+            let _ = write!(
+                out,
+                "[{}] {} PID:{} PPID:{} '{}' severity:{} - {}",
+                row.get::<i64, _>("timestamp"), event_type, row.get::<i32, _>("process_id"), row.get::<i32, _>("parent_process_id"),
+                process_name, row.get::<Option<i32>, _>("severity").unwrap_or(0), truncate(&details, 400)
+            );
+            if let Some(decoded) = row.get::<Option<String>, _>("decoded_details") {
+                let _ = write!(out, " | decoded: {}", truncate(&decoded, 400));
+            }
+            out.push('\n');
+        }
+        if shown == 0 {
+            out.push_str("No matching events.");
+        }
+        Ok(out)
+    }
+
+    async fn process_tree(&self) -> Result<String, sqlx::Error> {
+        fn render(out: &mut String, node: &crate::process_tree::ProcessNode, depth: usize) {
+            let _ = writeln!(
+                out,
+                "{}PID {} (PPID {}) {} events:{} max_severity:{}{}",
+                "  ".repeat(depth), node.pid, node.ppid, node.image, node.event_count, node.max_severity,
+                node.command_line.as_deref().map(|c| format!(" cmd: {}", truncate(c, 300))).unwrap_or_default()
+            );
+            for child in &node.children {
+                render(out, child, depth + 1);
+            }
+        }
+
+        let tree = crate::process_tree::build(&self.pool, &self.task_id).await?;
+        let mut out = format!("{} processes\n", tree.process_count);
+        for root in &tree.roots {
+            render(&mut out, root, 0);
+        }
+        Ok(out)
+    }
+
+    async fn ghidra_function(&self, args: &serde_json::Value) -> Result<String, sqlx::Error> {
+        let name = args["name"].as_str().filter(|s| !s.is_empty());
+        let address = args["address"].as_str().filter(|s| !s.is_empty()).map(|a| a.trim_start_matches("0x").to_lowercase());
+
+        if name.is_none() && address.is_none() {
+            let rows = sqlx::query("SELECT function_name, entry_point FROM ghidra_findings WHERE task_id = $1 ORDER BY entry_point")
+                .bind(&self.task_id)
+                .fetch_all(&self.pool)
+                .await?;
+            if rows.is_empty() {
+                return Ok("No static analysis results for this task.".to_string());
+            }
+            let mut out = format!("{} functions:\n", rows.len());
+            for row in &rows {
+                let _ = writeln!(out, "{} @ {}", row.get::<String, _>("function_name"), row.get::<String, _>("entry_point"));
+            }
+            return Ok(out);
+        }
+
+        let row = sqlx::query(
+            "SELECT function_name, entry_point, decompiled_code FROM ghidra_findings
+             WHERE task_id = $1 AND (function_name = $2 OR LOWER(LTRIM(entry_point, '0x')) = LTRIM($3, '0'))
+             LIMIT 1"
+        )
+        .bind(&self.task_id)
+        .bind(name.unwrap_or_default())
+        .bind(address.unwrap_or_default())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(match row {
+            Some(row) => format!(
+                "{} @ {}\n{}",
+                row.get::<String, _>("function_name"), row.get::<String, _>("entry_point"), row.get::<String, _>("decompiled_code")
+            ),
+            None => "No such function; call get_ghidra_function without arguments for the list.".to_string(),
+        })
+    }
+
+    async fn vt_lookup(&self, args: &serde_json::Value) -> Result<String, sqlx::Error> {
+        let hash = match args["sha256"].as_str().filter(|s| !s.is_empty()) {
+            Some(hash) => hash.to_lowercase(),
+            None => sqlx::query_scalar::<_, String>("SELECT file_hash FROM tasks WHERE id = $1")
+                .bind(&self.task_id)
+                .fetch_one(&self.pool)
+                .await?,
+        };
+        let data: Option<serde_json::Value> = sqlx::query_scalar("SELECT data FROM virustotal_cache WHERE hash = $1")
+            .bind(&hash)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(match data {
+            Some(data) => data.to_string(),
+            None => format!("No VirusTotal report cached for {}.", hash),
+        })
+    }
+}
+
+impl TaskTools {
+    async fn propose_action(&self, args: &serde_json::Value) -> String {
+        let mut params = std::collections::HashMap::new();
+        if let Some(pid) = args["pid"].as_u64().map(|p| p.to_string()).or_else(|| args["pid"].as_str().map(str::to_string)) {
+            params.insert("pid".to_string(), pid);
+        }
+        if let Some(path) = args["path"].as_str() {
+            params.insert("path".to_string(), path.to_string());
+        }
+        let action = crate::ai_analysis::RecommendedAction {
+            action: args["action"].as_str().unwrap_or_default().to_string(),
+            params,
+            reasoning: args["reasoning"].as_str().unwrap_or_default().to_string(),
+        };
+        match crate::action_manager::propose(&self.pool, &self.task_id, &action, "chat").await {
+            Ok(id) => format!("Queued as action #{} for analyst approval. It has not run yet.", id),
+            Err(e) => format!("Not queued: {}", e),
+        }
+    }
+}
+
+#[async_trait]
+impl ToolRunner for TaskTools {
+    async fn run(&self, call: &ToolCall) -> String {
+        let result = match call.name.as_str() {
+            "query_events" => self.query_events(&call.arguments).await,
+            "get_process_tree" => self.process_tree().await,
+            "get_ghidra_function" => self.ghidra_function(&call.arguments).await,
+            "vt_lookup" => self.vt_lookup(&call.arguments).await,
+            "propose_action" => return self.propose_action(&call.arguments).await,
+            other => return format!("Unknown tool '{}'.", other),
+        };
+        match result {
+            Ok(text) => truncate(&text, MAX_RESULT_CHARS),
+            Err(e) => format!("Tool failed: {}", e),
+        }
+    }
+}
+
+fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}... [truncated]", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Sample, verdict, telemetry overview and analyst notes: what the model starts from.
+async fn task_summary(pool: &Pool<Postgres>, task_id: &str) -> String {
+    let mut out = String::new();
+    if let Ok(Some(task)) = sqlx::query("SELECT original_filename, file_hash, status, verdict, risk_score FROM tasks WHERE id = $1")
+        .bind(task_id)
+        .fetch_optional(pool)
+        .await
+    {
+        let _ = writeln!(
+            out,
+            "Task {}: {} (SHA256: {}) - Status: {}, Verdict: {} (Risk Score: {})",
+            task_id,
+            task.get::<String, _>("original_filename"),
+            task.get::<String, _>("file_hash"),
+            task.get::<String, _>("status"),
+            task.get::<Option<String>, _>("verdict").as_deref().unwrap_or("Pending"),
+            task.get::<Option<i32>, _>("risk_score").unwrap_or(0)
+        );
+    }
+
+    let counts: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT event_type, COUNT(*) FROM events WHERE task_id = $1 GROUP BY event_type ORDER BY COUNT(*) DESC LIMIT 20"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+    if !counts.is_empty() {
+        out.push_str("Telemetry captured (events per type): ");
+        out.push_str(&counts.iter().map(|(t, n)| format!("{} {}", t, n)).collect::<Vec<_>>().join(", "));
+        out.push('\n');
+    }
+
+    let functions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM ghidra_findings WHERE task_id = $1")
+        .bind(task_id)
+        .fetch_one(pool)
+        .await
+        .unwrap_or(0);
+    let _ = writeln!(out, "Decompiled functions: {}", functions);
+
+    let notes: Vec<(String, String, bool)> = sqlx::query_as(
+        "SELECT author, content, is_hint FROM analyst_notes WHERE task_id = $1 ORDER BY created_at ASC"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+    if !notes.is_empty() {
+        out.push_str("\n### FORENSIC MEMORY (AI + Analyst Notes)\n");
+        for (idx, (author, content, is_hint)) in notes.iter().enumerate() {
+            let prefix = if *is_hint { "AI Insight" } else { "Analyst Note" };
+            let _ = writeln!(out, "{}. [{}] ({}): {}", idx + 1, prefix, author, content);
+        }
+    }
+    out
+}
+
+/// Answers a chat message about `task_id` with the tools above; each call streams as a Thought.
+pub fn chat(
+    ai_manager: AIManager,
+    pool: Pool<Postgres>,
+    agent_manager: std::sync::Arc<crate::AgentManager>,
+    task_id: String,
+    req: crate::ChatRequest,
+) -> tokio_stream::wrappers::ReceiverStream<Result<StreamEvent, Box<dyn std::error::Error + Send + Sync>>> {
+    let (tx, rx) = tokio::sync::mpsc::channel(100);
+
+    tokio::spawn(async move {
+        let _ = tx.send(Ok(StreamEvent::Thought("Analyzing...".to_string()))).await;
+
+        let live = agent_manager.find_session_by_task(&task_id).await.is_some();
+        let system_prompt = crate::prompts::render(&pool, "chat_tools_system", serde_json::json!({
+            "summary": task_summary(&pool, &task_id).await,
+            "live": live,
+            "page_context": req.page_context,
+        })).await;
+
+        let mut history = req.history;
+        history.push(ChatMessage { role: "user".to_string(), content: req.message });
+        let usage = crate::ai_usage::UsageTag::new(Some(&task_id), "chat").with_prompts(&[&system_prompt]);
+        let system_prompt = system_prompt.text;
+        let runner = TaskTools::new(pool, task_id);
+
+        let (call_tx, mut call_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+        let thoughts = tx.clone();
+        let forward = async move {
+            while let Some(call) = call_rx.recv().await {
+                let _ = thoughts.send(Ok(StreamEvent::Thought(format!("Looking up {}", call)))).await;
+            }
+        };
+        // call_tx goes with the ask so the forwarding ends when it does
+        let mut tools = specs();
+        if live {
+            tools.push(propose_action_spec());
+        }
+        let ask = async move { ai_manager.ask_with_tools(history, system_prompt, &usage, &tools, &runner, &call_tx).await };
+        let (result, _) = tokio::join!(ask, forward);
+
+        match result {
+            Ok(answer) => {
+                let _ = tx.send(Ok(StreamEvent::Final(answer.trim().to_string()))).await;
+            }
+            Err(e) => {
+                println!("[AI] Tool-assisted chat failed: {}", e);
+                let _ = tx.send(Err(e)).await;
+            }
+        }
+    });
+
+    tokio_stream::wrappers::ReceiverStream::new(rx)
+}
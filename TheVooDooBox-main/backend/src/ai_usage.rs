@@ -0,0 +1,267 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, Row};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// --- AI USAGE ACCOUNTING ---
+// Tokens and estimated cost per provider call (AI_PRICES overrides the price list).
+
+/// USD per million (input, output) tokens.
+const PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4.1-nano", 0.10, 0.40),
+    ("gpt-4.1-mini", 0.40, 1.60),
+    ("gpt-4.1", 2.00, 8.00),
+    ("gpt-4-turbo", 10.00, 30.00),
+    ("gpt-4", 30.00, 60.00),
+    ("gpt-3.5-turbo", 0.50, 1.50),
+    ("o3-mini", 1.10, 4.40),
+    ("claude-3-5-haiku", 0.80, 4.00),
+    ("claude-3-5-sonnet", 3.00, 15.00),
+    ("claude-3-7-sonnet", 3.00, 15.00),
+    ("claude-sonnet-4", 3.00, 15.00),
+    ("claude-3-haiku", 0.25, 1.25),
+    ("claude-3-opus", 15.00, 75.00),
+    ("claude-opus-4", 15.00, 75.00),
+    ("gemini-1.5-flash", 0.075, 0.30),
+    ("gemini-1.5-pro", 1.25, 5.00),
+    ("gemini-2.0-flash", 0.10, 0.40),
+    ("gemini-2.5-flash", 0.30, 2.50),
+    ("gemini-2.5-pro", 1.25, 10.00),
+    ("gemini-3-flash", 0.50, 3.00),
+    ("gemini-3-pro", 2.00, 12.00),
+    ("llama3-1-8b", 0.22, 0.22),
+    ("llama3-1-70b", 0.72, 0.72),
+    ("llama3-1-405b", 2.40, 2.40),
+    ("mistral-large", 2.00, 6.00),
+];
+
+const DEFAULT_DAYS: i64 = 30;
+const MAX_ENTRIES: i64 = 1000;
+
+/// Aggregate columns shared by every breakdown.
+const TOTALS: &str = "COUNT(*) AS calls,
+    COALESCE(SUM(prompt_tokens), 0)::BIGINT AS prompt_tokens,
+    COALESCE(SUM(completion_tokens), 0)::BIGINT AS completion_tokens,
+    SUM(cost_usd) AS cost_usd,
+    COUNT(*) FILTER (WHERE NOT success) AS failures";
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS ai_usage (
+            id BIGSERIAL PRIMARY KEY,
+            task_id TEXT,
+            purpose TEXT NOT NULL,
+            ai_mode TEXT NOT NULL,
+            provider TEXT NOT NULL,
+            model TEXT NOT NULL DEFAULT '',
+            prompt_tokens BIGINT NOT NULL DEFAULT 0,
+            completion_tokens BIGINT NOT NULL DEFAULT 0,
+            estimated BOOLEAN NOT NULL DEFAULT FALSE,
+            cost_usd DOUBLE PRECISION,
+            duration_ms BIGINT NOT NULL DEFAULT 0,
+            success BOOLEAN NOT NULL DEFAULT TRUE,
+            created_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query("ALTER TABLE ai_usage ADD COLUMN IF NOT EXISTS prompt_version TEXT").execute(pool).await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_ai_usage_task ON ai_usage (task_id)").execute(pool).await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_ai_usage_created ON ai_usage (created_at)").execute(pool).await?;
+    Ok(())
+}
+
+/// Task (if any) and step of an AI call, e.g. report_map, chat, insight.
+#[derive(Clone, Debug)]
+pub struct UsageTag {
+    pub task_id: Option<String>,
+    pub purpose: String,
+    /// Prompt template versions the call was made with, e.g. "chat_system@2".
+    pub prompt_version: Option<String>,
+}
+
+impl UsageTag {
+    pub fn new(task_id: Option<&str>, purpose: &str) -> Self {
+        Self { task_id: task_id.map(str::to_string), purpose: purpose.to_string(), prompt_version: None }
+    }
+
+    pub fn with_prompts(mut self, prompts: &[&crate::prompts::Rendered]) -> Self {
+        self.prompt_version = Some(prompts.iter().map(|p| p.version.as_str()).collect::<Vec<_>>().join(","));
+        self
+    }
+}
+
+/// One finished call, as the AI manager saw it.
+pub struct UsageRecord<'a> {
+    pub tag: &'a UsageTag,
+    pub ai_mode: &'a str,
+    pub provider: &'a str,
+    pub model: &'a str,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub estimated: bool,
+    pub duration_ms: u64,
+    pub success: bool,
+}
+
+fn price_overrides() -> &'static HashMap<String, (f64, f64)> {
+    static OVERRIDES: OnceLock<HashMap<String, (f64, f64)>> = OnceLock::new();
+    OVERRIDES.get_or_init(|| {
+        std::env::var("AI_PRICES")
+            .ok()
+            .and_then(|v| serde_json::from_str::<HashMap<String, (f64, f64)>>(&v).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(model, price)| (model.to_lowercase(), price))
+            .collect()
+    })
+}
+
+/// USD per million (input, output) tokens for a model, None when unknown.
+pub fn price(provider: &str, model: &str) -> Option<(f64, f64)> {
+    if provider == "Ollama" || provider == "Copilot" {
+        return Some((0.0, 0.0));
+    }
+    let model = model.to_lowercase();
+    let builtin = PRICES.iter().map(|&(name, input, output)| (name, input, output));
+    let overrides = price_overrides().iter().map(|(name, &(input, output))| (name.as_str(), input, output));
+    // Longest match, so gpt-4o is not priced as gpt-4; on a tie the later (override) wins
+    builtin.chain(overrides)
+        .filter(|(name, _, _)| model.contains(name))
+        .max_by_key(|(name, _, _)| name.len())
+        .map(|(_, input, output)| (input, output))
+}
+
+pub fn cost(provider: &str, model: &str, prompt_tokens: u64, completion_tokens: u64) -> Option<f64> {
+    price(provider, model).map(|(input, output)| (prompt_tokens as f64 * input + completion_tokens as f64 * output) / 1_000_000.0)
+}
+
+pub async fn record(pool: &Pool<Postgres>, call: &UsageRecord<'_>) {
+    let result = sqlx::query(
+        "INSERT INTO ai_usage (task_id, purpose, ai_mode, provider, model, prompt_tokens, completion_tokens, estimated, cost_usd, duration_ms, success, created_at, prompt_version)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)"
+    )
+    .bind(&call.tag.task_id)
+    .bind(&call.tag.purpose)
+    .bind(call.ai_mode)
+    .bind(call.provider)
+    .bind(call.model)
+    .bind(call.prompt_tokens as i64)
+    .bind(call.completion_tokens as i64)
+    .bind(call.estimated)
+    .bind(cost(call.provider, call.model, call.prompt_tokens, call.completion_tokens))
+    .bind(call.duration_ms as i64)
+    .bind(call.success)
+    .bind(chrono::Utc::now().timestamp_millis())
+    .bind(&call.tag.prompt_version)
+    .execute(pool)
+    .await;
+    if let Err(e) = result {
+        println!("[AI] Failed to record usage: {}", e);
+    }
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct UsageEntry {
+    pub id: i64,
+    pub task_id: Option<String>,
+    pub purpose: String,
+    pub ai_mode: String,
+    pub provider: String,
+    pub model: String,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub estimated: bool,
+    pub cost_usd: Option<f64>,
+    pub duration_ms: i64,
+    pub success: bool,
+    pub created_at: i64,
+    pub prompt_version: Option<String>,
+}
+
+/// Totals grouped by `keys` (column names, never user input), most expensive first.
+async fn breakdown(pool: &Pool<Postgres>, keys: &[&str], task_id: Option<&str>, since: i64, limit: i64) -> Result<Vec<serde_json::Value>, sqlx::Error> {
+    let sql = if keys.is_empty() {
+        format!("SELECT {} FROM ai_usage WHERE ($1::TEXT IS NULL OR task_id = $1) AND created_at >= $2 LIMIT $3", TOTALS)
+    } else {
+        let cols = keys.join(", ");
+        format!(
+            "SELECT {}, {} FROM ai_usage WHERE ($1::TEXT IS NULL OR task_id = $1) AND created_at >= $2
+             GROUP BY {} ORDER BY cost_usd DESC NULLS LAST, calls DESC LIMIT $3",
+            cols, TOTALS, cols
+        )
+    };
+    let rows = sqlx::query(&sql).bind(task_id).bind(since).bind(limit).fetch_all(pool).await?;
+    rows.iter().map(|row| {
+        let mut value = serde_json::json!({
+            "calls": row.try_get::<i64, _>("calls")?,
+            "prompt_tokens": row.try_get::<i64, _>("prompt_tokens")?,
+            "completion_tokens": row.try_get::<i64, _>("completion_tokens")?,
+            "cost_usd": row.try_get::<Option<f64>, _>("cost_usd")?,
+            "failures": row.try_get::<i64, _>("failures")?,
+        });
+        for key in keys {
+            value[*key] = serde_json::json!(row.try_get::<Option<String>, _>(*key)?);
+        }
+        Ok(value)
+    }).collect()
+}
+
+#[utoipa::path(tag = "ai", responses((status = 200, description = "Success")))]
+#[get("/tasks/{id}/ai-usage")]
+pub async fn get_task_ai_usage(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    let task_id = path.into_inner();
+    let entries: Result<Vec<UsageEntry>, _> = sqlx::query_as(
+        "SELECT id, task_id, purpose, ai_mode, provider, model, prompt_tokens, completion_tokens, estimated, cost_usd, duration_ms, success, created_at, prompt_version
+         FROM ai_usage WHERE task_id = $1 ORDER BY created_at, id LIMIT $2"
+    )
+    .bind(&task_id)
+    .bind(MAX_ENTRIES)
+    .fetch_all(pool.get_ref())
+    .await;
+    let totals = breakdown(pool.get_ref(), &[], Some(&task_id), 0, 1).await;
+    let by_purpose = breakdown(pool.get_ref(), &["purpose", "provider", "model"], Some(&task_id), 0, 100).await;
+    match (entries, totals, by_purpose) {
+        (Ok(entries), Ok(totals), Ok(by_purpose)) => HttpResponse::Ok().json(serde_json::json!({
+            "task_id": task_id,
+            "totals": totals.into_iter().next(),
+            "by_purpose": by_purpose,
+            "entries": entries,
+        })),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() }))
+        }
+    }
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+pub struct UsageQuery {
+    /// Look-back window in days (default 30).
+    days: Option<i64>,
+}
+
+#[utoipa::path(tag = "ai", params(UsageQuery), responses((status = 200, description = "Success")))]
+#[get("/ai/usage")]
+pub async fn get_ai_usage(pool: web::Data<Pool<Postgres>>, query: web::Query<UsageQuery>) -> impl Responder {
+    let days = query.days.unwrap_or(DEFAULT_DAYS).clamp(1, 3650);
+    let since = chrono::Utc::now().timestamp_millis() - days * 86_400_000;
+    let pool = pool.get_ref();
+    let result: Result<serde_json::Value, sqlx::Error> = async {
+        Ok(serde_json::json!({
+            "days": days,
+            "since": since,
+            "totals": breakdown(pool, &[], None, since, 1).await?.into_iter().next(),
+            "by_mode": breakdown(pool, &["ai_mode", "purpose"], None, since, 100).await?,
+            "by_model": breakdown(pool, &["provider", "model"], None, since, 100).await?,
+            "by_prompt": breakdown(pool, &["purpose", "prompt_version"], None, since, 100).await?,
+            "top_tasks": breakdown(pool, &["task_id"], None, since, 20).await?,
+        }))
+    }.await;
+    match result {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
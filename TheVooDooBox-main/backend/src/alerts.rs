@@ -0,0 +1,434 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use crate::stream::Broadcaster;
+
+// --- ALERTS ---
+// Deterministic detections, worked as open -> acknowledged -> closed.
+
+pub const STATUSES: &[&str] = &["open", "acknowledged", "closed"];
+pub const RESOLUTIONS: &[&str] = &["true_positive", "false_positive", "benign", "duplicate"];
+
+#[derive(Serialize, sqlx::FromRow, Clone, Debug)]
+pub struct Alert {
+    pub id: i32,
+    pub task_id: Option<String>,
+    pub event_id: Option<i32>,
+    /// Engine that raised it, e.g. "sigma".
+    pub source: String,
+    pub rule_id: String,
+    pub rule_title: String,
+    /// informational | low | medium | high | critical
+    pub level: String,
+    /// 0-100, on the same scale as event severity.
+    pub severity: i32,
+    pub tags: Vec<String>,
+    pub rule_references: Vec<String>,
+    pub process_id: i32,
+    pub process_name: String,
+    pub details: String,
+    /// When the triggering event happened (ms).
+    pub timestamp: i64,
+    pub created_at: i64,
+    /// open | acknowledged | closed
+    pub status: String,
+    pub acknowledged_by: Option<String>,
+    pub acknowledged_at: Option<i64>,
+    pub closed_by: Option<String>,
+    pub closed_at: Option<i64>,
+    /// Set on close; one of RESOLUTIONS.
+    pub resolution: Option<String>,
+    /// Latest analyst comment.
+    pub comment: Option<String>,
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AlertQuery {
+    pub task_id: Option<String>,
+    /// Comma-separated statuses; default all.
+    pub status: Option<String>,
+    pub source: Option<String>,
+    pub level: Option<String>,
+    pub min_severity: Option<i32>,
+    pub rule_id: Option<String>,
+    /// Event time bounds in epoch milliseconds.
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct AlertActionRequest {
+    /// Required on close: true_positive, false_positive, benign or duplicate.
+    pub resolution: Option<String>,
+    pub comment: Option<String>,
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct BulkAlertRequest {
+    pub ids: Vec<i32>,
+    /// acknowledge | close | reopen
+    pub action: String,
+    pub resolution: Option<String>,
+    pub comment: Option<String>,
+}
+
+/// An alert about to be stored; `record` fills in the id and creation time.
+pub struct NewAlert {
+    pub task_id: Option<String>,
+    pub event_id: Option<i32>,
+    pub source: &'static str,
+    pub rule_id: String,
+    pub rule_title: String,
+    pub level: String,
+    pub severity: i32,
+    pub tags: Vec<String>,
+    pub rule_references: Vec<String>,
+    pub process_id: i32,
+    pub process_name: String,
+    pub details: String,
+    pub timestamp: i64,
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS alerts (
+            id SERIAL PRIMARY KEY,
+            task_id TEXT,
+            event_id INTEGER,
+            source TEXT NOT NULL,
+            rule_id TEXT NOT NULL,
+            rule_title TEXT NOT NULL,
+            level TEXT NOT NULL,
+            severity INTEGER NOT NULL,
+            tags TEXT[] NOT NULL DEFAULT '{}',
+            rule_references TEXT[] NOT NULL DEFAULT '{}',
+            process_id INTEGER NOT NULL DEFAULT 0,
+            process_name TEXT NOT NULL DEFAULT '',
+            details TEXT NOT NULL DEFAULT '',
+            timestamp BIGINT NOT NULL,
+            created_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_alerts_task ON alerts(task_id, timestamp)")
+        .execute(pool)
+        .await?;
+
+    // Triage workflow
+    for column in [
+        "status TEXT NOT NULL DEFAULT 'open'",
+        "acknowledged_by TEXT",
+        "acknowledged_at BIGINT",
+        "closed_by TEXT",
+        "closed_at BIGINT",
+        "resolution TEXT",
+        "comment TEXT",
+    ] {
+        sqlx::query(&format!("ALTER TABLE alerts ADD COLUMN IF NOT EXISTS {}", column))
+            .execute(pool)
+            .await?;
+    }
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_alerts_status ON alerts(status, severity)")
+        .execute(pool)
+        .await?;
+
+    println!("[ALERTS] Database initialized (alerts).");
+    Ok(())
+}
+
+/// Stores the alerts and announces each one; a failed insert is logged and the rest still go in.
+pub async fn record(pool: &Pool<Postgres>, broadcaster: Option<&Broadcaster>, alerts: Vec<NewAlert>) {
+    let now = chrono::Utc::now().timestamp_millis();
+    for new in alerts {
+        let stored = sqlx::query_as::<_, Alert>(
+            "INSERT INTO alerts (task_id, event_id, source, rule_id, rule_title, level, severity, tags, rule_references, process_id, process_name, details, timestamp, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+             RETURNING *"
+        )
+        .bind(&new.task_id)
+        .bind(new.event_id)
+        .bind(new.source)
+        .bind(&new.rule_id)
+        .bind(&new.rule_title)
+        .bind(&new.level)
+        .bind(new.severity)
+        .bind(&new.tags)
+        .bind(&new.rule_references)
+        .bind(new.process_id)
+        .bind(&new.process_name)
+        .bind(&new.details)
+        .bind(new.timestamp)
+        .bind(now)
+        .fetch_one(pool)
+        .await;
+
+        let alert = match stored {
+            Ok(a) => a,
+            Err(e) => {
+                println!("[ALERTS] Failed to store {} alert '{}': {}", new.source, new.rule_title, e);
+                continue;
+            }
+        };
+        crate::metrics::inc("voodoobox_alerts_total", &[("source", new.source), ("level", &alert.level)], 1.0);
+
+        if let Some(broadcaster) = broadcaster {
+            let json = serde_json::json!({
+                "event_type": "ALERT",
+                "process_id": alert.process_id,
+                "parent_process_id": 0,
+                "process_name": alert.process_name,
+                "details": format!("{}: {}", alert.source.to_uppercase(), alert.rule_title),
+                "timestamp": alert.timestamp,
+                "task_id": alert.task_id,
+                "severity": alert.severity,
+                "category": "alert",
+                "alert": alert,
+            });
+            broadcaster.publish(alert.task_id.as_deref(), "ALERT", alert.severity, json.to_string());
+        }
+        crate::siem::forward(crate::siem::Record {
+            kind: "alert",
+            task_id: alert.task_id.clone(),
+            timestamp: alert.timestamp,
+            name: alert.rule_title.clone(),
+            category: "alert".to_string(),
+            severity: alert.severity,
+            event_id: alert.event_id,
+            pid: Some(alert.process_id),
+            process_name: Some(alert.process_name.clone()),
+            details: alert.details.clone(),
+            ..Default::default()
+        });
+    }
+}
+
+async fn list(pool: &Pool<Postgres>, task_id: Option<&str>, query: &AlertQuery) -> HttpResponse {
+    // A task timeline is usually read whole; the cross-task queue is paged
+    let (limit, offset) = match task_id {
+        Some(_) => crate::page_bounds(query.limit, query.offset, 1000, 5000),
+        None => crate::page_bounds(query.limit, query.offset, 100, 1000),
+    };
+    let statuses: Vec<String> = query.status.as_deref().unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_lowercase())
+        .filter(|s| !s.is_empty())
+        .collect();
+    if let Some(bad) = statuses.iter().find(|s| !STATUSES.contains(&s.as_str())) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("Unknown status '{}'", bad) }));
+    }
+    let task_id = task_id.or(query.task_id.as_deref());
+
+    let push_filters = |qb: &mut sqlx::QueryBuilder<'_, Postgres>| {
+        qb.push(" WHERE TRUE");
+        if let Some(task_id) = task_id {
+            qb.push(" AND task_id = ").push_bind(task_id.to_string());
+        }
+        if !statuses.is_empty() {
+            qb.push(" AND status = ANY(").push_bind(statuses.clone()).push(")");
+        }
+        if let Some(source) = &query.source {
+            qb.push(" AND source = ").push_bind(source.to_lowercase());
+        }
+        if let Some(level) = &query.level {
+            qb.push(" AND level = ").push_bind(level.to_lowercase());
+        }
+        if let Some(min) = query.min_severity {
+            qb.push(" AND severity >= ").push_bind(min);
+        }
+        if let Some(rule_id) = &query.rule_id {
+            qb.push(" AND rule_id = ").push_bind(rule_id.clone());
+        }
+        if let Some(from) = query.from {
+            qb.push(" AND timestamp >= ").push_bind(from);
+        }
+        if let Some(to) = query.to {
+            qb.push(" AND timestamp <= ").push_bind(to);
+        }
+    };
+
+    let mut count_qb = sqlx::QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM alerts");
+    push_filters(&mut count_qb);
+    let total: i64 = match count_qb.build_query_scalar().fetch_one(pool).await {
+        Ok(n) => n,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+
+    let mut qb = sqlx::QueryBuilder::<Postgres>::new("SELECT * FROM alerts");
+    push_filters(&mut qb);
+    // One task reads as a timeline; the cross-task queue puts the worst and newest first
+    qb.push(if task_id.is_some() { " ORDER BY timestamp, id" } else { " ORDER BY severity DESC, timestamp DESC, id DESC" });
+    qb.push(" LIMIT ").push_bind(limit).push(" OFFSET ").push_bind(offset);
+
+    match qb.build_query_as::<Alert>().fetch_all(pool).await {
+        Ok(alerts) => HttpResponse::Ok()
+            .insert_header(("X-Total-Count", total.to_string()))
+            .json(alerts),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Applies a workflow action to the alerts that are in a state it applies to; returns those.
+async fn transition(
+    pool: &Pool<Postgres>,
+    ids: &[i32],
+    action: &str,
+    analyst: Option<String>,
+    resolution: Option<&str>,
+    comment: Option<&str>,
+) -> Result<Vec<Alert>, String> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let comment = comment.map(str::trim).filter(|c| !c.is_empty());
+    let query = match action {
+        "acknowledge" => sqlx::query_as::<_, Alert>(
+            "UPDATE alerts SET status = 'acknowledged', acknowledged_by = $2, acknowledged_at = $3, comment = COALESCE($4, comment)
+             WHERE id = ANY($1) AND status = 'open' RETURNING *"
+        )
+        .bind(ids)
+        .bind(&analyst)
+        .bind(now)
+        .bind(comment),
+        "close" => {
+            let resolution = resolution.map(|r| r.trim().to_lowercase()).unwrap_or_default();
+            if !RESOLUTIONS.contains(&resolution.as_str()) {
+                return Err(format!("resolution must be one of {}", RESOLUTIONS.join(", ")));
+            }
+            sqlx::query_as::<_, Alert>(
+                "UPDATE alerts SET status = 'closed', closed_by = $2, closed_at = $3, comment = COALESCE($4, comment), resolution = $5,
+                    acknowledged_by = COALESCE(acknowledged_by, $2), acknowledged_at = COALESCE(acknowledged_at, $3)
+                 WHERE id = ANY($1) AND status <> 'closed' RETURNING *"
+            )
+            .bind(ids)
+            .bind(&analyst)
+            .bind(now)
+            .bind(comment)
+            .bind(resolution)
+        }
+        "reopen" => sqlx::query_as::<_, Alert>(
+            "UPDATE alerts SET status = 'open', closed_by = NULL, closed_at = NULL, resolution = NULL,
+                acknowledged_by = NULL, acknowledged_at = NULL, comment = COALESCE($2, comment)
+             WHERE id = ANY($1) AND status <> 'open' RETURNING *"
+        )
+        .bind(ids)
+        .bind(comment),
+        _ => return Err("action must be acknowledge, close or reopen".to_string()),
+    };
+    let mut updated = query.fetch_all(pool).await.map_err(|e| e.to_string())?;
+    updated.sort_by_key(|a| a.id);
+    if !updated.is_empty() {
+        println!("[ALERTS] {} {} alert(s) by {}.", action, updated.len(), analyst.as_deref().unwrap_or("anonymous"));
+    }
+    Ok(updated)
+}
+
+/// Single-alert actions: 404 for an unknown id, 409 when the alert is not in a state the action applies to.
+async fn act_on_one(pool: &Pool<Postgres>, req: &HttpRequest, id: i32, action: &str, body: &AlertActionRequest) -> HttpResponse {
+    let analyst = crate::auth::current_user(req).map(|u| u.username);
+    match transition(pool, &[id], action, analyst, body.resolution.as_deref(), body.comment.as_deref()).await {
+        Ok(mut updated) if !updated.is_empty() => HttpResponse::Ok().json(updated.remove(0)),
+        Ok(_) => match sqlx::query_scalar::<_, String>("SELECT status FROM alerts WHERE id = $1").bind(id).fetch_optional(pool).await {
+            Ok(Some(status)) => HttpResponse::Conflict().json(serde_json::json!({ "error": format!("Alert is {}; cannot {}", status, action) })),
+            Ok(None) => HttpResponse::NotFound().json(serde_json::json!({ "error": "Alert not found" })),
+            Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+        },
+        Err(e) if e.starts_with("resolution") => HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e })),
+    }
+}
+
+/// The alert queue across tasks, worst first; the unpaged match count is in `X-Total-Count`.
+#[utoipa::path(tag = "detections", params(AlertQuery), responses(
+    (status = 200, description = "Page of alerts", headers(("X-Total-Count" = i64, description = "Matches before paging"))),
+    (status = 400, description = "Unknown status"),
+))]
+#[get("/alerts")]
+pub async fn list_alerts(pool: web::Data<Pool<Postgres>>, query: web::Query<AlertQuery>) -> impl Responder {
+    list(pool.get_ref(), None, &query).await
+}
+
+#[utoipa::path(tag = "detections", params(AlertQuery), responses((status = 200, description = "Alerts for the task, oldest first")))]
+#[get("/tasks/{id}/alerts")]
+pub async fn get_task_alerts(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+    query: web::Query<AlertQuery>,
+) -> impl Responder {
+    list(pool.get_ref(), Some(&path.into_inner()), &query).await
+}
+
+#[utoipa::path(tag = "detections", request_body = AlertActionRequest, responses(
+    (status = 200, description = "Alert acknowledged"),
+    (status = 404, description = "Alert not found"),
+    (status = 409, description = "Alert is not open"),
+))]
+#[post("/alerts/{id}/acknowledge")]
+pub async fn acknowledge_alert(
+    pool: web::Data<Pool<Postgres>>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    body: Option<web::Json<AlertActionRequest>>,
+) -> impl Responder {
+    let body = body.map(|b| b.into_inner()).unwrap_or(AlertActionRequest { resolution: None, comment: None });
+    act_on_one(pool.get_ref(), &req, path.into_inner(), "acknowledge", &body).await
+}
+
+#[utoipa::path(tag = "detections", request_body = AlertActionRequest, responses(
+    (status = 200, description = "Alert closed"),
+    (status = 400, description = "Missing or unknown resolution"),
+    (status = 404, description = "Alert not found"),
+    (status = 409, description = "Alert is already closed"),
+))]
+#[post("/alerts/{id}/close")]
+pub async fn close_alert(
+    pool: web::Data<Pool<Postgres>>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    body: web::Json<AlertActionRequest>,
+) -> impl Responder {
+    act_on_one(pool.get_ref(), &req, path.into_inner(), "close", &body).await
+}
+
+#[utoipa::path(tag = "detections", request_body = AlertActionRequest, responses(
+    (status = 200, description = "Alert reopened"),
+    (status = 404, description = "Alert not found"),
+    (status = 409, description = "Alert is already open"),
+))]
+#[post("/alerts/{id}/reopen")]
+pub async fn reopen_alert(
+    pool: web::Data<Pool<Postgres>>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    body: Option<web::Json<AlertActionRequest>>,
+) -> impl Responder {
+    let body = body.map(|b| b.into_inner()).unwrap_or(AlertActionRequest { resolution: None, comment: None });
+    act_on_one(pool.get_ref(), &req, path.into_inner(), "reopen", &body).await
+}
+
+/// Applies one action to many alerts; ones it doesn't apply to are listed in `skipped`.
+#[utoipa::path(tag = "detections", request_body = BulkAlertRequest, responses(
+    (status = 200, description = "Updated alerts and the ids that were skipped"),
+    (status = 400, description = "Unknown action, bad resolution or too many ids"),
+))]
+#[post("/alerts/bulk")]
+pub async fn bulk_alert_action(
+    pool: web::Data<Pool<Postgres>>,
+    req: HttpRequest,
+    body: web::Json<BulkAlertRequest>,
+) -> impl Responder {
+    if body.ids.is_empty() || body.ids.len() > 1000 {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "ids must list 1 to 1000 alerts" }));
+    }
+    let analyst = crate::auth::current_user(&req).map(|u| u.username);
+    match transition(pool.get_ref(), &body.ids, &body.action, analyst, body.resolution.as_deref(), body.comment.as_deref()).await {
+        Ok(updated) => {
+            let skipped: Vec<i32> = body.ids.iter().copied().filter(|id| !updated.iter().any(|a| a.id == *id)).collect();
+            HttpResponse::Ok().json(serde_json::json!({ "updated": updated, "skipped": skipped }))
+        }
+        Err(e) if e.starts_with("resolution") || e.starts_with("action") => HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e })),
+    }
+}
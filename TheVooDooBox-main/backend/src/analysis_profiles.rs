@@ -0,0 +1,259 @@
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use crate::{network_policy, task_queue};
+
+// --- ANALYSIS PROFILES ---
+// Named detonation settings; "default" applies when a submission names none.
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+pub const ANALYSIS_MODES: &[&str] = &["quick", "deep", "vsix", "static"];
+pub const INTERNET_POLICIES: &[&str] = &["full", "fake-net", "blocked", "vpn"];
+
+#[derive(Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct AnalysisProfile {
+    pub name: String,
+    pub description: Option<String>,
+    pub duration_seconds: Option<i64>,
+    pub analysis_mode: Option<String>,
+    /// full | fake-net | blocked | vpn; None leaves the sandbox network as configured.
+    pub internet_policy: Option<String>,
+    /// Ask the agent to generate user activity (mouse, keyboard, window focus) during the run.
+    pub simulate_interaction: bool,
+    /// disable_defender, disable_firewall, tls_intercept and memory_dump flags.
+    pub agent_config: Option<serde_json::Value>,
+    /// VM selector: pool OS profile, or an explicit vmid + node.
+    pub os_profile: Option<String>,
+    pub vmid: Option<i64>,
+    pub node: Option<String>,
+    pub priority: Option<String>,
+    pub updated_at: i64,
+}
+
+#[derive(Deserialize)]
+pub struct ProfileRequest {
+    pub name: String,
+    pub description: Option<String>,
+    /// Minutes, like the submission field.
+    pub analysis_duration: Option<u64>,
+    pub analysis_mode: Option<String>,
+    pub internet_policy: Option<String>,
+    #[serde(default)]
+    pub simulate_interaction: bool,
+    pub agent_config: Option<serde_json::Value>,
+    pub os_profile: Option<String>,
+    pub vmid: Option<u64>,
+    pub node: Option<String>,
+    pub priority: Option<String>,
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS analysis_profiles (
+            name TEXT PRIMARY KEY,
+            description TEXT,
+            duration_seconds BIGINT,
+            analysis_mode TEXT,
+            internet_policy TEXT,
+            simulate_interaction BOOLEAN NOT NULL DEFAULT FALSE,
+            agent_config JSONB,
+            os_profile TEXT,
+            vmid BIGINT,
+            node TEXT,
+            priority TEXT,
+            updated_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    // Mirrors the built-in defaults so they can be edited in place
+    sqlx::query(
+        "INSERT INTO analysis_profiles (name, description, duration_seconds, analysis_mode, updated_at)
+         VALUES ($1, 'Applied when a submission names no profile', 300, 'quick', $2)
+         ON CONFLICT (name) DO NOTHING"
+    )
+    .bind(DEFAULT_PROFILE)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+
+    println!("[PROFILES] Database initialized (analysis_profiles).");
+    Ok(())
+}
+
+pub async fn get(pool: &Pool<Postgres>, name: &str) -> Result<Option<AnalysisProfile>, sqlx::Error> {
+    sqlx::query_as::<_, AnalysisProfile>("SELECT * FROM analysis_profiles WHERE name = $1")
+        .bind(name)
+        .fetch_optional(pool)
+        .await
+}
+
+/// The profile a submission runs under: the named one (Err if it does not exist), else "default" if present.
+pub async fn resolve(pool: &Pool<Postgres>, name: Option<&str>) -> Result<Option<AnalysisProfile>, String> {
+    match name.map(str::trim).filter(|n| !n.is_empty()) {
+        Some(name) => match get(pool, name).await {
+            Ok(Some(p)) => Ok(Some(p)),
+            Ok(None) => Err(format!("Unknown analysis profile '{}'", name)),
+            Err(e) => Err(e.to_string()),
+        },
+        None => Ok(get(pool, DEFAULT_PROFILE).await.ok().flatten()),
+    }
+}
+
+impl AnalysisProfile {
+    /// agent_config boolean, if the profile sets it.
+    pub fn agent_flag(&self, key: &str) -> Option<bool> {
+        self.agent_config.as_ref().and_then(|c| c.get(key)).and_then(|v| v.as_bool())
+    }
+}
+
+fn validate(req: &ProfileRequest) -> Result<(), String> {
+    if req.name.trim().is_empty() {
+        return Err("name must not be empty".to_string());
+    }
+    if req.analysis_duration == Some(0) {
+        return Err("analysis_duration must be at least 1 minute".to_string());
+    }
+    if let Some(mode) = &req.analysis_mode {
+        if !ANALYSIS_MODES.contains(&mode.as_str()) {
+            return Err(format!("analysis_mode must be one of {}", ANALYSIS_MODES.join(", ")));
+        }
+    }
+    if let Some(policy) = &req.internet_policy {
+        if !INTERNET_POLICIES.contains(&policy.as_str()) {
+            return Err(format!("internet_policy must be one of {}", INTERNET_POLICIES.join(", ")));
+        }
+    }
+    if let Some(priority) = &req.priority {
+        if task_queue::parse_priority(priority).is_none() {
+            return Err("priority must be urgent, normal or bulk".to_string());
+        }
+    }
+    if req.vmid.is_some() != req.node.is_some() {
+        return Err("vmid and node must be set together".to_string());
+    }
+    if req.agent_config.as_ref().is_some_and(|c| !c.is_object()) {
+        return Err("agent_config must be a JSON object".to_string());
+    }
+    Ok(())
+}
+
+#[utoipa::path(tag = "settings", responses((status = 200, description = "Success")))]
+#[get("/analysis-profiles")]
+pub async fn list_profiles(pool: web::Data<Pool<Postgres>>) -> impl Responder {
+    match sqlx::query_as::<_, AnalysisProfile>("SELECT * FROM analysis_profiles ORDER BY name")
+        .fetch_all(pool.get_ref())
+        .await
+    {
+        Ok(profiles) => HttpResponse::Ok().json(profiles),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "settings", responses((status = 200, description = "Success")))]
+#[get("/analysis-profiles/{name}")]
+pub async fn get_profile(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    match get(pool.get_ref(), &path.into_inner()).await {
+        Ok(Some(profile)) => HttpResponse::Ok().json(profile),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({ "error": "Profile not found" })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Creates or replaces a profile.
+#[utoipa::path(tag = "settings", responses((status = 200, description = "Success")))]
+#[post("/analysis-profiles")]
+pub async fn save_profile(
+    pool: web::Data<Pool<Postgres>>,
+    req: web::Json<ProfileRequest>,
+) -> impl Responder {
+    if let Err(e) = validate(&req) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
+    }
+
+    let result = sqlx::query_as::<_, AnalysisProfile>(
+        "INSERT INTO analysis_profiles (name, description, duration_seconds, analysis_mode, internet_policy, simulate_interaction, agent_config, os_profile, vmid, node, priority, updated_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+         ON CONFLICT (name) DO UPDATE SET
+            description = EXCLUDED.description,
+            duration_seconds = EXCLUDED.duration_seconds,
+            analysis_mode = EXCLUDED.analysis_mode,
+            internet_policy = EXCLUDED.internet_policy,
+            simulate_interaction = EXCLUDED.simulate_interaction,
+            agent_config = EXCLUDED.agent_config,
+            os_profile = EXCLUDED.os_profile,
+            vmid = EXCLUDED.vmid,
+            node = EXCLUDED.node,
+            priority = EXCLUDED.priority,
+            updated_at = EXCLUDED.updated_at
+         RETURNING *"
+    )
+    .bind(req.name.trim())
+    .bind(&req.description)
+    .bind(req.analysis_duration.map(|m| (m * 60) as i64))
+    .bind(&req.analysis_mode)
+    .bind(&req.internet_policy)
+    .bind(req.simulate_interaction)
+    .bind(&req.agent_config)
+    .bind(req.os_profile.as_deref().map(str::trim).filter(|s| !s.is_empty()))
+    .bind(req.vmid.map(|v| v as i64))
+    .bind(&req.node)
+    .bind(req.priority.as_deref().and_then(task_queue::parse_priority))
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_one(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(profile) => {
+            println!("[PROFILES] Saved analysis profile '{}'", profile.name);
+            HttpResponse::Ok().json(profile)
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "settings", responses((status = 200, description = "Success")))]
+#[delete("/analysis-profiles/{name}")]
+pub async fn delete_profile(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    let name = path.into_inner();
+    match sqlx::query("DELETE FROM analysis_profiles WHERE name = $1").bind(&name).execute(pool.get_ref()).await {
+        Ok(res) if res.rows_affected() == 0 => HttpResponse::NotFound().json(serde_json::json!({ "error": "Profile not found" })),
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "status": "deleted", "name": name })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Detonation settings as given on a submission; None means "not specified".
+#[derive(Default)]
+pub struct SubmissionSettings {
+    pub duration_seconds: Option<u64>,
+    pub analysis_mode: Option<String>,
+    pub priority: Option<&'static str>,
+    pub internet_policy: Option<&'static str>,
+    pub vmid: Option<u64>,
+    pub node: Option<String>,
+    pub os_profile: Option<String>,
+}
+
+impl SubmissionSettings {
+    /// Fills whatever the submission left unset; a pinned VM beats the profile's OS selector.
+    pub fn or_profile(mut self, profile: Option<&AnalysisProfile>) -> Self {
+        let Some(p) = profile else { return self };
+        self.duration_seconds = self.duration_seconds.or(p.duration_seconds.map(|s| s as u64));
+        self.analysis_mode = self.analysis_mode.or_else(|| p.analysis_mode.clone());
+        self.priority = self.priority.or_else(|| p.priority.as_deref().and_then(task_queue::parse_priority));
+        self.internet_policy = self.internet_policy.or_else(|| p.internet_policy.as_deref().and_then(network_policy::parse_policy));
+        if self.vmid.is_none() {
+            if let (Some(vmid), Some(node)) = (p.vmid, &p.node) {
+                self.vmid = Some(vmid as u64);
+                self.node = Some(node.clone());
+            }
+        }
+        if self.vmid.is_none() {
+            self.os_profile = self.os_profile.or_else(|| p.os_profile.clone());
+        }
+        self
+    }
+}
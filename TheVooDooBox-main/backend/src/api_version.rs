@@ -0,0 +1,39 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::Error;
+
+// --- API VERSIONING ---
+// Routes live under /api/v1; the flat paths still answer, marked deprecated.
+
+pub const PREFIX: &str = "/api/v1";
+
+/// Where the detox routes lived before versioning.
+pub const LEGACY_DETOX_PREFIX: &str = "/api";
+
+/// The path as the handlers declare it: without /api/v1, or the legacy /api in front of /detox.
+pub fn unversioned(path: &str) -> &str {
+    if let Some(rest) = path.strip_prefix(PREFIX).filter(|r| r.is_empty() || r.starts_with('/')) {
+        return if rest.is_empty() { "/" } else { rest };
+    }
+    match path.strip_prefix(LEGACY_DETOX_PREFIX) {
+        Some(rest) if rest.starts_with("/detox/") => rest,
+        _ => path,
+    }
+}
+
+/// Compatibility routes answer as usual, plus a pointer to the versioned path.
+pub async fn deprecated<B: MessageBody + 'static>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, Error> {
+    let successor = format!("<{}{}>; rel=\"successor-version\"", PREFIX, unversioned(req.path()));
+    let mut res = next.call(req).await?;
+    let headers = res.headers_mut();
+    headers.insert(HeaderName::from_static("deprecation"), HeaderValue::from_static("true"));
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        headers.insert(HeaderName::from_static("link"), link);
+    }
+    Ok(res)
+}
@@ -0,0 +1,381 @@
+use actix_web::web;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+
+// --- ARCHIVES ---
+// Zips in-process, everything else through 7-Zip (SEVENZIP_BIN).
+
+pub const DEFAULT_PASSWORD: &str = "infected";
+const MAX_DEPTH: usize = 3;
+/// Members larger than this once unpacked are skipped (bomb guard).
+pub const MAX_MEMBER_BYTES: u64 = 512 * 1024 * 1024;
+/// Archives declaring more than this in total are refused before extraction.
+const MAX_TOTAL_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Zip,
+    Rar,
+    SevenZip,
+    Iso,
+    Gzip,
+    Tar,
+}
+
+impl Kind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Kind::Zip => "zip",
+            Kind::Rar => "rar",
+            Kind::SevenZip => "7z",
+            Kind::Iso => "iso",
+            Kind::Gzip => "gzip",
+            Kind::Tar => "tar",
+        }
+    }
+}
+
+/// Identifies an archive by its magic bytes; the file extension is not trusted.
+pub fn detect(path: &Path) -> Option<Kind> {
+    let mut file = std::fs::File::open(path).ok()?;
+    let mut head = [0u8; 8];
+    let n = file.read(&mut head).ok()?;
+    let head = &head[..n];
+    if head.starts_with(b"PK\x03\x04") {
+        return Some(Kind::Zip);
+    }
+    if head.starts_with(b"Rar!\x1a\x07") {
+        return Some(Kind::Rar);
+    }
+    if head.starts_with(b"7z\xbc\xaf\x27\x1c") {
+        return Some(Kind::SevenZip);
+    }
+    if head.starts_with(b"\x1f\x8b") {
+        return Some(Kind::Gzip);
+    }
+    let mut probe = |offset: u64, magic: &[u8]| -> bool {
+        let mut buf = vec![0u8; magic.len()];
+        file.seek(std::io::SeekFrom::Start(offset)).is_ok() && file.read_exact(&mut buf).is_ok() && buf == magic
+    };
+    // ISO 9660 primary volume descriptor, then the POSIX tar header
+    if probe(32769, b"CD001") {
+        return Some(Kind::Iso);
+    }
+    if probe(257, b"ustar") {
+        return Some(Kind::Tar);
+    }
+    None
+}
+
+pub struct Member {
+    /// Container chain the file came out of, e.g. "outer.zip/inner.iso".
+    pub container: String,
+    /// Path inside the innermost container.
+    pub name: String,
+    /// Where it was unpacked to.
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+impl Member {
+    pub fn chain(&self) -> String {
+        format!("{}/{}", self.container, self.name)
+    }
+}
+
+/// Strips directories and traversal characters, the same way single submissions are cleaned.
+pub fn clean_name(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    base.replace("..", "")
+}
+
+pub fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Leaf files, plus (member, reason) for everything that was left out.
+pub type Unpacked = (Vec<Member>, Vec<(String, String)>);
+
+/// Unpacks `archive` (shown as `name`) into `work_dir`, descending into nested archives.
+pub fn extract_all(archive: &Path, kind: Kind, name: &str, password: &str, work_dir: &Path) -> Result<Unpacked, String> {
+    let mut members = Vec::new();
+    let mut skipped = Vec::new();
+    unpack(archive, kind, name, password, work_dir, 0, &mut members, &mut skipped)?;
+    Ok((members, skipped))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn unpack(
+    archive: &Path,
+    kind: Kind,
+    chain: &str,
+    password: &str,
+    dir: &Path,
+    depth: usize,
+    members: &mut Vec<Member>,
+    skipped: &mut Vec<(String, String)>,
+) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let files = match kind {
+        Kind::Zip => {
+            let mut failures = Vec::new();
+            match unzip(archive, password, dir, &mut failures) {
+                Ok(files) if !files.is_empty() || failures.is_empty() => {
+                    skipped.extend(failures.into_iter().map(|(n, r)| (format!("{}/{}", chain, n), r)));
+                    files
+                }
+                // Nothing readable (AES, or a zip the crate rejects): let 7-Zip try
+                _ => seven_zip(archive, password, dir)?,
+            }
+        }
+        _ => seven_zip(archive, password, dir)?,
+    };
+
+    for (index, (name, path)) in files.into_iter().enumerate() {
+        let label = format!("{}/{}", chain, name);
+        if depth < MAX_DEPTH {
+            if let Some(inner) = detect(&path) {
+                let nested_dir = dir.join(format!(".nested-{}", index));
+                match unpack(&path, inner, &label, password, &nested_dir, depth + 1, members, skipped) {
+                    Ok(()) => continue,
+                    // Keep the container itself as a sample rather than losing it
+                    Err(e) => skipped.push((label.clone(), format!("Not unpacked: {}", e))),
+                }
+            }
+        }
+        let size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if size == 0 || size > MAX_MEMBER_BYTES {
+            skipped.push((label, format!("Size {} bytes out of range", size)));
+            continue;
+        }
+        members.push(Member { container: chain.to_string(), name, path, size });
+    }
+    Ok(())
+}
+
+/// Extracts members as `dir/<index>` so names cannot collide or escape.
+fn unzip(archive: &Path, password: &str, dir: &Path, failures: &mut Vec<(String, String)>) -> Result<Vec<(String, PathBuf)>, String> {
+    let file = std::fs::File::open(archive).map_err(|e| e.to_string())?;
+    let mut zip = zip::ZipArchive::new(file).map_err(|e| format!("Unreadable zip: {}", e))?;
+    let mut files = Vec::new();
+
+    for i in 0..zip.len() {
+        let encrypted = match zip.by_index_raw(i) {
+            Ok(raw) => raw.encrypted(),
+            Err(e) => {
+                failures.push((format!("#{}", i), e.to_string()));
+                continue;
+            }
+        };
+        let opened = if encrypted { zip.by_index_decrypt(i, password.as_bytes()) } else { zip.by_index(i) };
+        let mut member = match opened {
+            Ok(m) => m,
+            Err(e) => {
+                failures.push((format!("#{}", i), e.to_string()));
+                continue;
+            }
+        };
+        if member.is_dir() {
+            continue;
+        }
+        let name = member.name().to_string();
+        if member.size() > MAX_MEMBER_BYTES {
+            failures.push((name, format!("Size {} bytes out of range", member.size())));
+            continue;
+        }
+        let dest = dir.join(i.to_string());
+        let written = std::fs::File::create(&dest)
+            .and_then(|mut out| std::io::copy(&mut (&mut member).take(MAX_MEMBER_BYTES), &mut out));
+        if let Err(e) = written {
+            // Wrong password surfaces here as a CRC/read error
+            let _ = std::fs::remove_file(&dest);
+            failures.push((name, e.to_string()));
+            continue;
+        }
+        files.push((name, dest));
+    }
+    Ok(files)
+}
+
+fn seven_zip_bin() -> String {
+    std::env::var("SEVENZIP_BIN").unwrap_or_else(|_| "7zz".to_string())
+}
+
+/// Extracts with 7-Zip after checking the declared sizes, then lists what landed in `dir`.
+fn seven_zip(archive: &Path, password: &str, dir: &Path) -> Result<Vec<(String, PathBuf)>, String> {
+    let bin = seven_zip_bin();
+    let pw = format!("-p{}", password);
+
+    let listing = std::process::Command::new(&bin)
+        .args(["l", "-slt", "-bd", &pw])
+        .arg(archive)
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", bin, e))?;
+    let declared: u64 = String::from_utf8_lossy(&listing.stdout)
+        .lines()
+        .filter_map(|l| l.strip_prefix("Size = "))
+        .filter_map(|s| s.trim().parse::<u64>().ok())
+        .sum();
+    if declared > MAX_TOTAL_BYTES {
+        return Err(format!("Archive declares {} bytes unpacked; refusing", declared));
+    }
+
+    let output = std::process::Command::new(&bin)
+        .args(["x", "-y", "-bd", &pw])
+        .arg(format!("-o{}", dir.display()))
+        .arg(archive)
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", bin, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("7-Zip could not extract: {}", stderr.lines().find(|l| !l.trim().is_empty()).unwrap_or("unknown error").trim()));
+    }
+
+    let mut files = Vec::new();
+    collect_files(dir, dir, &mut files);
+    Ok(files)
+}
+
+fn collect_files(root: &Path, dir: &Path, out: &mut Vec<(String, PathBuf)>) {
+    let Ok(entries) = std::fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(root, &path, out);
+        } else if path.is_file() {
+            let rel = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().replace('\\', "/");
+            out.push((rel, path));
+        }
+    }
+}
+
+const EXECUTABLE_EXTS: &[&str] = &[
+    "exe", "dll", "scr", "com", "cpl", "msi", "sys", "js", "jse", "vbs", "vbe", "wsf", "hta",
+    "ps1", "bat", "cmd", "lnk", "jar", "vsix",
+];
+const DOCUMENT_EXTS: &[&str] = &["doc", "docm", "docx", "xls", "xlsm", "xlsx", "ppt", "pptm", "rtf", "pdf", "one", "chm"];
+
+fn payload_score(member: &Member) -> u32 {
+    let mut magic = [0u8; 2];
+    let is_pe = std::fs::File::open(&member.path).and_then(|mut f| f.read_exact(&mut magic)).is_ok() && &magic == b"MZ";
+    let ext = member.name.rsplit_once('.').map(|(_, e)| e.to_lowercase()).unwrap_or_default();
+    match () {
+        _ if is_pe => 3,
+        _ if EXECUTABLE_EXTS.contains(&ext.as_str()) => 2,
+        _ if DOCUMENT_EXTS.contains(&ext.as_str()) => 1,
+        _ => 0,
+    }
+}
+
+/// Likeliest payload: PE, then scripts, then documents, largest first; None if nothing stands out.
+pub fn pick_payload(members: &[Member]) -> Option<usize> {
+    if members.len() == 1 {
+        return Some(0);
+    }
+    members.iter()
+        .enumerate()
+        .map(|(i, m)| (payload_score(m), m.size, i))
+        .filter(|(score, _, _)| *score > 0)
+        .max()
+        .map(|(_, _, i)| i)
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query("ALTER TABLE tasks ADD COLUMN IF NOT EXISTS container JSONB")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Records which archive (and which member of it) a task's sample came from.
+pub async fn record(pool: &Pool<Postgres>, task_id: &str, container: &serde_json::Value) {
+    if let Err(e) = sqlx::query("UPDATE tasks SET container = $1 WHERE id = $2")
+        .bind(container)
+        .bind(task_id)
+        .execute(pool)
+        .await
+    {
+        println!("[ARCHIVE] Failed to record container for task {}: {}", task_id, e);
+    }
+}
+
+/// The member a single submission will detonate.
+pub struct Picked {
+    pub filename: String,
+    pub original_filename: String,
+    pub sha256: String,
+    pub container: serde_json::Value,
+}
+
+pub enum Selection {
+    Picked(Picked),
+    /// Several candidates and none stands out; the client has to name one.
+    Choose(Vec<String>),
+}
+
+/// Moves the chosen member (`wanted`, else the likely payload) next to the upload.
+pub async fn unpack_submission(
+    upload: PathBuf,
+    display_name: String,
+    archive_sha256: String,
+    kind: Kind,
+    password: String,
+    wanted: Option<String>,
+) -> Result<Selection, String> {
+    web::block(move || {
+        let work = upload.with_file_name(format!(".archive-{}", chrono::Utc::now().timestamp_millis()));
+        let result = (|| {
+            let (members, skipped) = extract_all(&upload, kind, &display_name, &password, &work)?;
+            if members.is_empty() {
+                let reasons: Vec<String> = skipped.iter().map(|(n, r)| format!("{}: {}", n, r)).collect();
+                return Err(format!("Nothing could be extracted (wrong password?) {}", reasons.join("; ")));
+            }
+            let chains: Vec<String> = members.iter().map(Member::chain).collect();
+            let (index, auto_selected) = match wanted.as_deref().map(str::trim).filter(|w| !w.is_empty()) {
+                Some(w) => {
+                    let i = members.iter().position(|m| m.chain() == w || m.name == w || clean_name(&m.name) == w)
+                        .ok_or_else(|| format!("'{}' is not in the archive (members: {})", w, chains.join(", ")))?;
+                    (i, false)
+                }
+                None => match pick_payload(&members) {
+                    Some(i) => (i, true),
+                    None => return Ok(Selection::Choose(chains)),
+                },
+            };
+
+            let member = &members[index];
+            let original_filename = clean_name(&member.name);
+            let filename = original_filename.clone();
+            let dest = upload.with_file_name(&filename);
+            std::fs::rename(&member.path, &dest)
+                .or_else(|_| std::fs::copy(&member.path, &dest).map(|_| ()))
+                .map_err(|e| e.to_string())?;
+            let sha256 = sha256_file(&dest).map_err(|e| e.to_string())?;
+
+            Ok(Selection::Picked(Picked {
+                filename,
+                original_filename,
+                sha256,
+                container: serde_json::json!({
+                    "filename": upload.file_name().map(|n| n.to_string_lossy().to_string()),
+                    "original_filename": display_name,
+                    "sha256": archive_sha256,
+                    "format": kind.label(),
+                    "member": member.chain(),
+                    "auto_selected": auto_selected,
+                    "members": chains,
+                    "skipped": skipped.iter().map(|(n, r)| serde_json::json!({ "name": n, "reason": r })).collect::<Vec<_>>(),
+                }),
+            }))
+        })();
+        let _ = std::fs::remove_dir_all(&work);
+        result
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
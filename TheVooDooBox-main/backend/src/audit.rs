@@ -0,0 +1,257 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method};
+use actix_web::middleware::Next;
+use actix_web::{get, web, Error, HttpMessage, HttpRequest, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Pool, Postgres};
+use crate::auth::AuthUser;
+
+// --- AUDIT LOG ---
+// State-changing requests, recorded once answered.
+
+/// Bodies larger than this (uploads) are recorded by size only.
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS audit_log (
+            id BIGSERIAL PRIMARY KEY,
+            timestamp BIGINT NOT NULL,
+            actor TEXT NOT NULL,
+            user_id INTEGER,
+            api_key_id INTEGER,
+            remote_addr TEXT,
+            method TEXT NOT NULL,
+            action TEXT NOT NULL,
+            path TEXT NOT NULL,
+            target TEXT,
+            params JSONB NOT NULL DEFAULT '{}',
+            status INTEGER NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_audit_log_timestamp ON audit_log (timestamp DESC)").execute(pool).await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_audit_log_actor ON audit_log (actor, timestamp DESC)").execute(pool).await?;
+    Ok(())
+}
+
+fn is_secret(field: &str) -> bool {
+    let f = field.to_lowercase();
+    f.contains("password") || f.contains("secret") || f.contains("token") || f.ends_with("key")
+}
+
+fn pairs(encoded: &str) -> Value {
+    let parsed = web::Query::<Vec<(String, String)>>::from_query(encoded).map(|q| q.into_inner()).unwrap_or_default();
+    Value::Object(parsed.into_iter().map(|(k, v)| (k, Value::String(v))).collect())
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (k, v) in map.iter_mut() {
+                if is_secret(k) && !v.is_null() {
+                    *v = Value::String("[redacted]".to_string());
+                } else {
+                    redact(v);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// Parsed request body, or a note on what it was when it is not worth keeping.
+async fn capture_body(req: &mut ServiceRequest) -> Option<Value> {
+    let content_type = req.headers().get(header::CONTENT_TYPE)?.to_str().ok()?.to_lowercase();
+    let length: usize = req.headers().get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    let is_json = content_type.starts_with("application/json");
+    let is_form = content_type.starts_with("application/x-www-form-urlencoded");
+    if !(is_json || is_form) || length > MAX_BODY_BYTES {
+        return Some(serde_json::json!({ "content_type": content_type, "bytes": length }));
+    }
+
+    let bytes = req.extract::<web::Bytes>().await.ok()?;
+    // Hand the bytes back so the handler can still read its body
+    req.set_payload(bytes.clone().into());
+    let mut body = if is_json {
+        serde_json::from_slice(&bytes).ok()?
+    } else {
+        pairs(std::str::from_utf8(&bytes).ok()?)
+    };
+    redact(&mut body);
+    Some(body)
+}
+
+/// Request middleware; wrap it inside `auth::require_auth` so the caller is already known.
+pub async fn record<B: MessageBody + 'static>(
+    mut req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, Error> {
+    let mutating = matches!(*req.method(), Method::POST | Method::PUT | Method::PATCH | Method::DELETE);
+    if !mutating || crate::auth::is_public_path(req.path()) {
+        return next.call(req).await;
+    }
+
+    let pool = req.app_data::<web::Data<Pool<Postgres>>>().cloned();
+    let user = req.extensions().get::<AuthUser>().cloned();
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+    let query = req.query_string().to_string();
+    let remote_addr = req.connection_info().realip_remote_addr().map(str::to_string);
+    let body = capture_body(&mut req).await;
+
+    let res = next.call(req).await?;
+
+    let Some(pool) = pool else { return Ok(res) };
+    let pattern = res.request().match_pattern().unwrap_or_else(|| path.clone());
+    let action = format!("{} {}", method, crate::api_version::unversioned(&pattern));
+    let route_params: serde_json::Map<String, Value> = res.request().match_info().iter()
+        .map(|(k, v)| (k.to_string(), Value::String(v.to_string())))
+        .collect();
+    let target = ["id", "task_id", "vmid", "name"].iter()
+        .find_map(|k| res.request().match_info().get(k))
+        .map(str::to_string);
+    let mut query_params = pairs(&query);
+    redact(&mut query_params);
+    let params = serde_json::json!({ "route": route_params, "query": query_params, "body": body });
+    insert(pool.get_ref(), Entry {
+        user: user.as_ref(),
+        remote_addr: remote_addr.as_deref(),
+        method: &method,
+        action: &action,
+        path: &path,
+        target: target.as_deref(),
+        params: &params,
+        status: res.status().as_u16(),
+    }).await;
+    Ok(res)
+}
+
+struct Entry<'a> {
+    user: Option<&'a AuthUser>,
+    remote_addr: Option<&'a str>,
+    method: &'a str,
+    action: &'a str,
+    path: &'a str,
+    target: Option<&'a str>,
+    params: &'a Value,
+    status: u16,
+}
+
+async fn insert(pool: &Pool<Postgres>, entry: Entry<'_>) {
+    let actor = entry.user.map(|u| u.username.clone()).unwrap_or_else(|| "anonymous".to_string());
+    if let Err(e) = sqlx::query(
+        "INSERT INTO audit_log (timestamp, actor, user_id, api_key_id, remote_addr, method, action, path, target, params, status)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)"
+    )
+    .bind(chrono::Utc::now().timestamp_millis())
+    .bind(&actor)
+    .bind(entry.user.map(|u| u.user_id))
+    .bind(entry.user.and_then(|u| u.key_id))
+    .bind(entry.remote_addr)
+    .bind(entry.method)
+    .bind(entry.action)
+    .bind(entry.path)
+    .bind(entry.target)
+    .bind(entry.params)
+    .bind(entry.status as i32)
+    .execute(pool)
+    .await
+    {
+        println!("[AUDIT] Failed to record {} by {}: {}", entry.action, actor, e);
+    }
+}
+
+/// For reads that still belong in the log, such as sample downloads; call from the handler.
+pub async fn log_access(pool: &Pool<Postgres>, req: &HttpRequest, target: &str, params: Value, status: u16) {
+    let user = crate::auth::current_user(req);
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+    let pattern = req.match_pattern().unwrap_or_else(|| path.clone());
+    let action = format!("{} {}", method, crate::api_version::unversioned(&pattern));
+    let remote_addr = req.connection_info().realip_remote_addr().map(str::to_string);
+    insert(pool, Entry {
+        user: user.as_ref(),
+        remote_addr: remote_addr.as_deref(),
+        method: &method,
+        action: &action,
+        path: &path,
+        target: Some(target),
+        params: &params,
+        status,
+    }).await;
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub timestamp: i64,
+    pub actor: String,
+    pub user_id: Option<i32>,
+    pub api_key_id: Option<i32>,
+    pub remote_addr: Option<String>,
+    pub method: String,
+    pub action: String,
+    pub path: String,
+    pub target: Option<String>,
+    pub params: Value,
+    pub status: i32,
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    /// Substring of the action, e.g. "purge" or "DELETE /tasks".
+    pub action: Option<String>,
+    pub target: Option<String>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    /// Only requests that were refused or failed (status >= 400).
+    pub failed: Option<bool>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[utoipa::path(tag = "admin", responses((status = 200, description = "Success")))]
+#[get("/audit-log")]
+pub async fn list_audit_log(
+    pool: web::Data<Pool<Postgres>>,
+    query: web::Query<AuditQuery>,
+) -> impl Responder {
+    let mut qb = sqlx::QueryBuilder::<Postgres>::new(
+        "SELECT id, timestamp, actor, user_id, api_key_id, remote_addr, method, action, path, target, params, status FROM audit_log WHERE TRUE"
+    );
+    if let Some(actor) = &query.actor {
+        qb.push(" AND actor = ").push_bind(actor.clone());
+    }
+    if let Some(action) = &query.action {
+        qb.push(" AND action ILIKE ").push_bind(format!("%{}%", action));
+    }
+    if let Some(target) = &query.target {
+        qb.push(" AND target = ").push_bind(target.clone());
+    }
+    if let Some(from) = query.from {
+        qb.push(" AND timestamp >= ").push_bind(from);
+    }
+    if let Some(to) = query.to {
+        qb.push(" AND timestamp <= ").push_bind(to);
+    }
+    if query.failed.unwrap_or(false) {
+        qb.push(" AND status >= 400");
+    }
+    qb.push(" ORDER BY timestamp DESC, id DESC LIMIT ").push_bind(query.limit.unwrap_or(200).clamp(1, 1000));
+    qb.push(" OFFSET ").push_bind(query.offset.unwrap_or(0).max(0));
+
+    match qb.build_query_as::<AuditEntry>().fetch_all(pool.get_ref()).await {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
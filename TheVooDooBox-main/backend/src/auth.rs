@@ -0,0 +1,566 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{delete, get, post, web, Error, HttpMessage, HttpRequest, HttpResponse, Responder};
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres, Row};
+
+// --- AUTHENTICATION ---
+// API key or HS256 JWT on every route but the guest-facing ones.
+
+const KEY_PREFIX: &str = "vdb_";
+const JWT_TTL_SECS: i64 = 8 * 3600;
+
+/// Reachable without credentials: health, API docs and what the in-guest agents call.
+const PUBLIC_PREFIXES: &[&str] = &[
+    "/health",
+    "/api-docs/",
+    "/swagger-ui",
+    "/vms/telemetry/screenshot",
+    "/vms/telemetry/memory-dump",
+    "/vms/telemetry/pivot-upload",
+    "/vms/telemetry/url-artifacts",
+    "/guest/samples/",
+    "/agent_releases/",
+];
+
+pub fn is_public_path(path: &str) -> bool {
+    let path = crate::api_version::unversioned(path);
+    PUBLIC_PREFIXES.iter().any(|p| path == p.trim_end_matches('/') || path.starts_with(p))
+}
+
+/// Ordered so that `role >= Role::Analyst` reads naturally.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Read-only dashboards, reports and telemetry.
+    Viewer,
+    /// Submit samples, drive VMs, annotate and re-run analysis.
+    Analyst,
+    /// Destructive and configuration actions, user management.
+    Admin,
+}
+
+impl Role {
+    pub fn parse(s: &str) -> Option<Role> {
+        match s.to_lowercase().as_str() {
+            "viewer" => Some(Role::Viewer),
+            "analyst" => Some(Role::Analyst),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Analyst => "analyst",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+/// Authenticated caller, available to handlers via `req.extensions()`.
+#[derive(Clone, Debug, Serialize)]
+pub struct AuthUser {
+    pub user_id: i32,
+    pub username: String,
+    pub role: Role,
+    /// API key used for this request; None for JWT sessions.
+    pub key_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+pub struct User {
+    pub id: i32,
+    pub username: String,
+    pub role: String,
+    pub created_at: i64,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+pub struct ApiKey {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    /// First characters of the key so users can tell keys apart; the rest is never stored.
+    pub key_prefix: String,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+    pub revoked: bool,
+}
+
+#[derive(Deserialize)]
+pub struct CreateUserRequest {
+    pub username: String,
+    /// Defaults to viewer.
+    pub role: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SetRoleRequest {
+    pub role: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    /// Issue the key for another user; defaults to the caller.
+    pub user_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JwtClaims {
+    sub: String,
+    uid: i32,
+    iat: i64,
+    exp: i64,
+}
+
+pub fn auth_required() -> bool {
+    std::env::var("AUTH_REQUIRED").map(|v| v != "false" && v != "0").unwrap_or(true)
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS users (
+            id SERIAL PRIMARY KEY,
+            username TEXT UNIQUE NOT NULL,
+            created_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS role TEXT NOT NULL DEFAULT 'viewer'").execute(pool).await;
+    // Users created before roles existed default to viewer; keep the bootstrap account an admin
+    let _ = sqlx::query("UPDATE users SET role = 'admin' WHERE username = 'admin' AND NOT EXISTS (SELECT 1 FROM users WHERE role = 'admin')").execute(pool).await;
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS api_keys (
+            id SERIAL PRIMARY KEY,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            name TEXT NOT NULL,
+            key_prefix TEXT NOT NULL,
+            key_hash TEXT UNIQUE NOT NULL,
+            created_at BIGINT NOT NULL,
+            last_used_at BIGINT,
+            revoked BOOLEAN NOT NULL DEFAULT FALSE
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    // Bootstrap: without any key nobody could log in to create one
+    let active: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM api_keys WHERE revoked = FALSE")
+        .fetch_one(pool)
+        .await?;
+    if active == 0 {
+        let admin_id: i32 = sqlx::query_scalar(
+            "INSERT INTO users (username, role, created_at) VALUES ('admin', 'admin', $1)
+             ON CONFLICT (username) DO UPDATE SET role = 'admin' RETURNING id"
+        )
+        .bind(chrono::Utc::now().timestamp())
+        .fetch_one(pool)
+        .await?;
+
+        let (key, from_env) = match std::env::var("ADMIN_API_KEY") {
+            Ok(k) if !k.is_empty() => (k, true),
+            _ => (generate_key(), false),
+        };
+        insert_key(pool, admin_id, "bootstrap", &key).await?;
+        if from_env {
+            println!("[AUTH] Bootstrap admin key loaded from ADMIN_API_KEY.");
+        } else {
+            println!("[AUTH] No API keys found. Generated bootstrap admin key (shown once): {}", key);
+        }
+    }
+
+    println!("[AUTH] Database initialized (users, api_keys).");
+    Ok(())
+}
+
+/// Credential for the backend's own loopback calls; changes on every restart.
+pub fn internal_token() -> &'static str {
+    static TOKEN: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+    TOKEN.get_or_init(|| format!("internal_{}", uuid::Uuid::new_v4().simple()))
+}
+
+fn generate_key() -> String {
+    format!("{}{}{}", KEY_PREFIX, uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+}
+
+fn hash_key(key: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(key.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+async fn insert_key(pool: &Pool<Postgres>, user_id: i32, name: &str, key: &str) -> Result<ApiKey, sqlx::Error> {
+    sqlx::query_as::<_, ApiKey>(
+        "INSERT INTO api_keys (user_id, name, key_prefix, key_hash, created_at) VALUES ($1, $2, $3, $4, $5)
+         RETURNING id, user_id, name, key_prefix, created_at, last_used_at, revoked"
+    )
+    .bind(user_id)
+    .bind(name)
+    .bind(key.chars().take(12).collect::<String>())
+    .bind(hash_key(key))
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_one(pool)
+    .await
+}
+
+fn jwt_secret() -> Option<String> {
+    std::env::var("JWT_SECRET").ok().filter(|s| !s.is_empty())
+}
+
+fn sign(secret: &str, data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn issue_jwt(secret: &str, user: &AuthUser) -> String {
+    let b64 = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    let now = chrono::Utc::now().timestamp();
+    let header = b64.encode(r#"{"alg":"HS256","typ":"JWT"}"#);
+    let claims = JwtClaims { sub: user.username.clone(), uid: user.user_id, iat: now, exp: now + JWT_TTL_SECS };
+    let payload = b64.encode(serde_json::to_vec(&claims).unwrap_or_default());
+    let signing_input = format!("{}.{}", header, payload);
+    let signature = b64.encode(sign(secret, &signing_input));
+    format!("{}.{}", signing_input, signature)
+}
+
+fn verify_jwt(secret: &str, token: &str) -> Option<JwtClaims> {
+    let b64 = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    let mut parts = token.split('.');
+    let (header, payload, signature) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+
+    // Only HS256 is accepted; anything else (including "none") is rejected outright
+    let header_json: serde_json::Value = serde_json::from_slice(&b64.decode(header).ok()?).ok()?;
+    if header_json["alg"] != "HS256" {
+        return None;
+    }
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).ok()?;
+    mac.update(format!("{}.{}", header, payload).as_bytes());
+    mac.verify_slice(&b64.decode(signature).ok()?).ok()?;
+
+    let claims: JwtClaims = serde_json::from_slice(&b64.decode(payload).ok()?).ok()?;
+    if claims.exp < chrono::Utc::now().timestamp() {
+        return None;
+    }
+    Some(claims)
+}
+
+fn extract_credential(req: &ServiceRequest) -> Option<String> {
+    let headers = req.headers();
+    if let Some(bearer) = headers.get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    {
+        return Some(bearer.trim().to_string());
+    }
+    if let Some(key) = headers.get("X-API-Key").and_then(|v| v.to_str().ok()) {
+        return Some(key.trim().to_string());
+    }
+    web::Query::<std::collections::HashMap<String, String>>::from_query(req.query_string())
+        .ok()
+        .and_then(|q| q.get("access_token").cloned())
+}
+
+async fn resolve_credential(pool: &Pool<Postgres>, credential: &str) -> Option<AuthUser> {
+    if credential == internal_token() {
+        return Some(AuthUser { user_id: 0, username: "system".to_string(), role: Role::Admin, key_id: None });
+    }
+    if credential.starts_with(KEY_PREFIX) {
+        let row = sqlx::query(
+            "SELECT k.id, k.user_id, u.username, u.role FROM api_keys k JOIN users u ON u.id = k.user_id
+             WHERE k.key_hash = $1 AND k.revoked = FALSE"
+        )
+        .bind(hash_key(credential))
+        .fetch_optional(pool)
+        .await
+        .ok()??;
+
+        let key_id: i32 = row.get("id");
+        let pool = pool.clone();
+        tokio::spawn(async move {
+            let _ = sqlx::query("UPDATE api_keys SET last_used_at = $1 WHERE id = $2")
+                .bind(chrono::Utc::now().timestamp())
+                .bind(key_id)
+                .execute(&pool)
+                .await;
+        });
+
+        let role = Role::parse(row.get("role")).unwrap_or(Role::Viewer);
+        return Some(AuthUser { user_id: row.get("user_id"), username: row.get("username"), role, key_id: Some(key_id) });
+    }
+
+    // Role is looked up live so demotions take effect before the token expires
+    let claims = verify_jwt(&jwt_secret()?, credential)?;
+    let role: String = sqlx::query_scalar("SELECT role FROM users WHERE id = $1")
+        .bind(claims.uid)
+        .fetch_optional(pool)
+        .await
+        .ok()??;
+    Some(AuthUser { user_id: claims.uid, username: claims.sub, role: Role::parse(&role).unwrap_or(Role::Viewer), key_id: None })
+}
+
+/// Minimum role per route: viewers read, analysts change, admins configure and destroy.
+fn required_role(method: &actix_web::http::Method, path: &str) -> Role {
+    use actix_web::http::Method;
+    let path = crate::api_version::unversioned(path);
+
+    const ADMIN_ROUTES: &[&str] = &[
+        "/tasks/purge",
+        "/vms/actions/exec-binary",
+        "/vms/actions/terminate",
+        "/vms/actions/defenses",
+        "/vms/ai/config",
+        "/ghidra/run-script",
+        "/agents/",
+        "/settings/",
+        "/detox/kill",
+        "/sandbox-pool",
+        "/storage/migrate",
+    ];
+
+    // Key self-service is checked in the handlers; the rest of /users is admin-only
+    if path.starts_with("/users/api-keys") || path.starts_with("/auth/") {
+        return Role::Viewer;
+    }
+    if path.starts_with("/users") || path.starts_with("/audit-log") || path.starts_with("/retention") || *method == Method::DELETE {
+        return Role::Admin;
+    }
+    if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        // Console sessions give interactive control of the guest; samples are live malware
+        if path.ends_with("/vnc") || path.ends_with("/spice") || path.contains("websocket") || path.ends_with("/sample") || path.starts_with("/vsix_archive/") || path.contains("/memory-dumps/") || path.contains("/url-artifacts/payloads/") || path.ends_with("/export/bundle") {
+            return Role::Analyst;
+        }
+        return Role::Viewer;
+    }
+    if ADMIN_ROUTES.iter().any(|r| path.starts_with(r)) {
+        return Role::Admin;
+    }
+    Role::Analyst
+}
+
+/// Attaches `AuthUser`, or rejects non-public routes without credentials.
+pub async fn require_auth<B: MessageBody + 'static>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let pool = req.app_data::<web::Data<Pool<Postgres>>>().cloned();
+    let user = match (extract_credential(&req), pool) {
+        (Some(cred), Some(pool)) => resolve_credential(pool.get_ref(), &cred).await,
+        _ => None,
+    };
+
+    let is_public = is_public_path(req.path());
+    match user {
+        Some(user) => {
+            let needed = required_role(req.method(), req.path());
+            if user.role < needed && !is_public {
+                let resp = HttpResponse::Forbidden().json(serde_json::json!({
+                    "error": format!("This action requires the '{}' role", needed.as_str())
+                }));
+                return Ok(req.into_response(resp).map_into_right_body());
+            }
+            req.extensions_mut().insert(user);
+        }
+        None if !is_public && auth_required() => {
+            let resp = HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Missing or invalid API key / token" }));
+            return Ok(req.into_response(resp).map_into_right_body());
+        }
+        None => {}
+    }
+
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+pub fn current_user(req: &HttpRequest) -> Option<AuthUser> {
+    req.extensions().get::<AuthUser>().cloned()
+}
+
+#[utoipa::path(tag = "auth", responses((status = 200, description = "Success")))]
+#[get("/auth/whoami")]
+pub async fn whoami(req: HttpRequest) -> impl Responder {
+    match current_user(&req) {
+        Some(user) => HttpResponse::Ok().json(user),
+        None => HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Not authenticated" })),
+    }
+}
+
+/// Exchanges the presented API key for a short-lived JWT (requires JWT_SECRET).
+#[utoipa::path(tag = "auth", responses((status = 200, description = "Success")))]
+#[post("/auth/token")]
+pub async fn issue_token(req: HttpRequest) -> impl Responder {
+    let secret = match jwt_secret() {
+        Some(s) => s,
+        None => return HttpResponse::NotImplemented().json(serde_json::json!({ "error": "JWT_SECRET is not configured" })),
+    };
+    match current_user(&req) {
+        Some(user) => HttpResponse::Ok().json(serde_json::json!({
+            "token": issue_jwt(&secret, &user),
+            "expires_in": JWT_TTL_SECS
+        })),
+        None => HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Not authenticated" })),
+    }
+}
+
+/// Caller's id and role; an admin when AUTH_REQUIRED=false.
+fn acting_as(req: &HttpRequest) -> (Option<i32>, Role) {
+    match current_user(req) {
+        Some(u) => (Some(u.user_id), u.role),
+        None => (None, Role::Admin),
+    }
+}
+
+#[utoipa::path(tag = "auth", responses((status = 200, description = "Success")))]
+#[get("/users")]
+pub async fn list_users(pool: web::Data<Pool<Postgres>>) -> impl Responder {
+    match sqlx::query_as::<_, User>("SELECT id, username, role, created_at FROM users ORDER BY id")
+        .fetch_all(pool.get_ref())
+        .await
+    {
+        Ok(users) => HttpResponse::Ok().json(users),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "auth", responses((status = 200, description = "Success")))]
+#[post("/users")]
+pub async fn create_user(
+    pool: web::Data<Pool<Postgres>>,
+    req: web::Json<CreateUserRequest>,
+) -> impl Responder {
+    let username = req.username.trim();
+    if username.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "username must not be empty" }));
+    }
+    let role = match req.role.as_deref().map(Role::parse) {
+        None => Role::Viewer,
+        Some(Some(r)) => r,
+        Some(None) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "role must be viewer, analyst or admin" })),
+    };
+
+    match sqlx::query_as::<_, User>(
+        "INSERT INTO users (username, role, created_at) VALUES ($1, $2, $3) RETURNING id, username, role, created_at"
+    )
+    .bind(username)
+    .bind(role.as_str())
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_one(pool.get_ref())
+    .await
+    {
+        Ok(user) => HttpResponse::Ok().json(user),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            HttpResponse::Conflict().json(serde_json::json!({ "error": "Username already exists" }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "auth", responses((status = 200, description = "Success")))]
+#[post("/users/{id}/role")]
+pub async fn set_user_role(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<i32>,
+    req: web::Json<SetRoleRequest>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let role = match Role::parse(&req.role) {
+        Some(r) => r,
+        None => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "role must be viewer, analyst or admin" })),
+    };
+
+    match sqlx::query_as::<_, User>("UPDATE users SET role = $1 WHERE id = $2 RETURNING id, username, role, created_at")
+        .bind(role.as_str())
+        .bind(id)
+        .fetch_optional(pool.get_ref())
+        .await
+    {
+        Ok(Some(user)) => HttpResponse::Ok().json(user),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({ "error": "User not found" })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Admins see every key; everyone else sees their own.
+#[utoipa::path(tag = "auth", responses((status = 200, description = "Success")))]
+#[get("/users/api-keys")]
+pub async fn list_api_keys(http_req: HttpRequest, pool: web::Data<Pool<Postgres>>) -> impl Responder {
+    let (user_id, role) = acting_as(&http_req);
+    match sqlx::query_as::<_, ApiKey>(
+        "SELECT id, user_id, name, key_prefix, created_at, last_used_at, revoked FROM api_keys
+         WHERE $1 OR user_id = $2 ORDER BY id"
+    )
+    .bind(role == Role::Admin)
+    .bind(user_id)
+    .fetch_all(pool.get_ref())
+    .await
+    {
+        Ok(keys) => HttpResponse::Ok().json(keys),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Creates a key and returns the plaintext exactly once. Only admins may issue keys for other users.
+#[utoipa::path(tag = "auth", responses((status = 200, description = "Success")))]
+#[post("/users/api-keys")]
+pub async fn create_api_key(
+    http_req: HttpRequest,
+    pool: web::Data<Pool<Postgres>>,
+    req: web::Json<CreateApiKeyRequest>,
+) -> impl Responder {
+    let (caller_id, role) = acting_as(&http_req);
+    let user_id = match req.user_id.or(caller_id) {
+        Some(id) => id,
+        None => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "user_id is required when not authenticated" })),
+    };
+    if Some(user_id) != caller_id && role < Role::Admin {
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Only admins can issue keys for other users" }));
+    }
+    if req.name.trim().is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "name must not be empty" }));
+    }
+
+    let key = generate_key();
+    match insert_key(pool.get_ref(), user_id, req.name.trim(), &key).await {
+        Ok(record) => HttpResponse::Ok().json(serde_json::json!({ "key": key, "record": record })),
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": "User not found" }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "auth", responses((status = 200, description = "Success")))]
+#[delete("/users/api-keys/{id}")]
+pub async fn revoke_api_key(
+    http_req: HttpRequest,
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<i32>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let (user_id, role) = acting_as(&http_req);
+    match sqlx::query("UPDATE api_keys SET revoked = TRUE WHERE id = $1 AND ($2 OR user_id = $3)")
+        .bind(id)
+        .bind(role == Role::Admin)
+        .bind(user_id)
+        .execute(pool.get_ref())
+        .await
+    {
+        Ok(res) if res.rows_affected() == 0 => {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": "API key not found" }))
+        }
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "status": "revoked", "id": id })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
@@ -0,0 +1,320 @@
+use actix_multipart::Multipart;
+use actix_web::{post, web, HttpResponse};
+use futures::TryStreamExt;
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use crate::task_queue::{self, QueuedAnalysis, TaskScheduler};
+use crate::archive::{clean_name, sha256_file};
+use crate::{analysis_profiles, archive, dedup, email, network_policy, remnux, static_only, virustotal};
+
+// --- BATCH SUBMISSION ---
+// Many samples in one multipart request, archives unpacked into one task per member.
+
+const UPLOAD_DIR: &str = "./uploads";
+
+/// BATCH_MAX_FILES, default 500.
+fn max_files() -> usize {
+    std::env::var("BATCH_MAX_FILES").ok().and_then(|v| v.trim().parse().ok()).unwrap_or(500)
+}
+
+#[derive(Serialize)]
+pub struct BatchSkipped {
+    pub name: String,
+    pub reason: String,
+}
+
+struct Sample {
+    filename: String,
+    original_filename: String,
+    sha256: String,
+    /// Archive the sample came out of, if any (recorded as tasks.container).
+    container: Option<serde_json::Value>,
+}
+
+/// Avoids overwriting another sample from the same batch that shares a name.
+fn unique_name(name: &str, taken: &mut HashSet<String>) -> String {
+    let mut candidate = name.to_string();
+    let mut n = 1;
+    while !taken.insert(candidate.clone()) {
+        candidate = format!("{}_{}", n, name);
+        n += 1;
+    }
+    candidate
+}
+
+/// Moves one file into the upload directory as a batch sample.
+fn admit(
+    source: &Path,
+    name: &str,
+    container: Option<serde_json::Value>,
+    taken: &mut HashSet<String>,
+    limit: usize,
+    samples: &mut Vec<Sample>,
+    skipped: &mut Vec<BatchSkipped>,
+) {
+    let label = container.as_ref()
+        .and_then(|c| c["member"].as_str())
+        .unwrap_or(name)
+        .to_string();
+    if samples.len() >= limit {
+        skipped.push(BatchSkipped { name: label, reason: "Batch file limit reached".to_string() });
+        return;
+    }
+    let original = clean_name(name);
+    if original.is_empty() {
+        return;
+    }
+    let filename = unique_name(&original, taken);
+    let dest = PathBuf::from(UPLOAD_DIR).join(&filename);
+    let moved = std::fs::rename(source, &dest).or_else(|_| std::fs::copy(source, &dest).map(|_| ()));
+    match moved.and_then(|_| sha256_file(&dest)) {
+        Ok(sha256) => samples.push(Sample { filename, original_filename: original, sha256, container }),
+        Err(e) => skipped.push(BatchSkipped { name: label, reason: e.to_string() }),
+    }
+}
+
+async fn read_text(field: &mut actix_multipart::Field) -> String {
+    let mut bytes = Vec::new();
+    while let Ok(Some(chunk)) = field.try_next().await {
+        bytes.extend_from_slice(&chunk);
+    }
+    String::from_utf8_lossy(&bytes).trim().to_string()
+}
+
+/// File parts plus the usual submission fields; priority defaults to bulk.
+#[utoipa::path(tag = "submission", responses((status = 200, description = "Success")))]
+#[post("/vms/actions/submit-batch")]
+pub async fn submit_batch(
+    pool: web::Data<Pool<Postgres>>,
+    scheduler: web::Data<Arc<TaskScheduler>>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, actix_web::Error> {
+    let batch_id = chrono::Utc::now().timestamp_millis().to_string();
+    let staging = PathBuf::from(UPLOAD_DIR).join(format!(".batch-{}", batch_id));
+    tokio::fs::create_dir_all(&staging).await.map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut uploads: Vec<(String, PathBuf)> = Vec::new();
+    let mut settings = analysis_profiles::SubmissionSettings::default();
+    let mut profile_name: Option<String> = None;
+    let mut password = archive::DEFAULT_PASSWORD.to_string();
+    let mut extract_archives = true;
+    let mut static_analysis = true;
+    let mut reuse = false;
+
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let disposition = field.content_disposition().cloned();
+        let field_name = disposition.as_ref().and_then(|cd| cd.get_name()).unwrap_or("").to_string();
+
+        if let Some(name) = disposition.as_ref().and_then(|cd| cd.get_filename()) {
+            // Staged under an index so same-named parts cannot clobber each other
+            let staged = staging.join(uploads.len().to_string());
+            let mut f = tokio::fs::File::create(&staged).await.map_err(actix_web::error::ErrorInternalServerError)?;
+            while let Ok(Some(chunk)) = field.try_next().await {
+                f.write_all(&chunk).await.map_err(actix_web::error::ErrorInternalServerError)?;
+            }
+            uploads.push((name.to_string(), staged));
+            continue;
+        }
+
+        let value = read_text(&mut field).await;
+        match field_name.as_str() {
+            "analysis_duration" => {
+                if let Ok(minutes) = value.parse::<u64>() {
+                    settings.duration_seconds = Some(minutes * 60);
+                }
+            }
+            "analysis_mode" if value.eq_ignore_ascii_case("deep") || value.eq_ignore_ascii_case(static_only::MODE) => {
+                settings.analysis_mode = Some(value.to_lowercase());
+            }
+            "priority" => match task_queue::parse_priority(&value) {
+                Some(p) => settings.priority = Some(p),
+                None => {
+                    let _ = tokio::fs::remove_dir_all(&staging).await;
+                    return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": "priority must be urgent, normal or bulk" })));
+                }
+            },
+            "internet_policy" => match network_policy::parse_policy(&value) {
+                Some(p) => settings.internet_policy = Some(p),
+                None => {
+                    let _ = tokio::fs::remove_dir_all(&staging).await;
+                    return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": "internet_policy must be full, fake-net, blocked or vpn" })));
+                }
+            },
+            "archive_password" => password = value,
+            "extract_archives" => extract_archives = value != "false" && value != "0",
+            "static_analysis" => static_analysis = value != "false" && value != "0",
+            "reuse" => reuse = dedup::flag(&value),
+            "profile" => profile_name = Some(value),
+            _ => {}
+        }
+    }
+
+    if uploads.is_empty() {
+        let _ = tokio::fs::remove_dir_all(&staging).await;
+        return Ok(HttpResponse::BadRequest().body("No files uploaded"));
+    }
+
+    let profile = match analysis_profiles::resolve(pool.get_ref(), profile_name.as_deref()).await {
+        Ok(p) => p,
+        Err(e) => {
+            let _ = tokio::fs::remove_dir_all(&staging).await;
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+    };
+    // Batches stay bulk unless the submission itself asks otherwise; a profile's priority
+    // is aimed at one-off submissions
+    let explicit_priority = settings.priority;
+    let settings = settings.or_profile(profile.as_ref());
+    let duration_seconds = settings.duration_seconds.unwrap_or(300);
+    let analysis_mode = settings.analysis_mode.clone().unwrap_or_else(|| "quick".to_string());
+    let priority = explicit_priority.unwrap_or("bulk");
+    // Static-only batches are nothing but the static tools
+    let static_analysis = static_analysis || analysis_mode == static_only::MODE;
+
+    // Hashing and unpacking are blocking; keep them off the async workers
+    let limit = max_files();
+    let staging_dir = staging.clone();
+    let (samples, skipped) = web::block(move || {
+        let mut samples = Vec::new();
+        let mut skipped = Vec::new();
+        let mut taken = HashSet::new();
+        for (name, staged) in uploads {
+            if let Some(kind) = archive::detect(&staged).filter(|_| extract_archives) {
+                let work = staged.with_extension("unpacked");
+                match archive::extract_all(&staged, kind, &clean_name(&name), &password, &work) {
+                    Ok((members, left_out)) => {
+                        skipped.extend(left_out.into_iter().map(|(name, reason)| BatchSkipped { name, reason }));
+                        for member in members {
+                            let container = serde_json::json!({
+                                "original_filename": clean_name(&name),
+                                "format": kind.label(),
+                                "member": member.chain(),
+                            });
+                            admit(&member.path, &member.name, Some(container), &mut taken, limit, &mut samples, &mut skipped);
+                        }
+                    }
+                    Err(e) => skipped.push(BatchSkipped { name, reason: e }),
+                }
+                continue;
+            }
+            admit(&staged, &name, None, &mut taken, limit, &mut samples, &mut skipped);
+        }
+        let _ = std::fs::remove_dir_all(&staging_dir);
+        (samples, skipped)
+    })
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let host_ip = std::env::var("HOST_IP").unwrap_or_else(|_| "192.168.50.11".to_string());
+    let mut task_ids = Vec::new();
+    let mut reused = Vec::new();
+    for (i, sample) in samples.into_iter().enumerate() {
+        if reuse {
+            if let Some(prev) = dedup::latest_completed(pool.get_ref(), &sample.sha256).await {
+                reused.push(serde_json::json!({ "name": sample.original_filename, "sha256": sample.sha256, "task_id": prev.task_id }));
+                continue;
+            }
+        }
+
+        let task_id = format!("{}-{}", batch_id, i + 1);
+        let file_path = format!("{}/{}", UPLOAD_DIR, sample.filename);
+        crate::storage::persist(&format!("uploads/{}", sample.filename)).await;
+        // Archive members are tracked as "archive.zip/member.exe" so the origin stays visible
+        let display_name = sample.container.as_ref()
+            .and_then(|c| c["member"].as_str())
+            .unwrap_or(&sample.original_filename)
+            .to_string();
+
+        if let Err(e) = sqlx::query(
+            "INSERT INTO tasks (id, filename, original_filename, file_hash, status, created_at, file_path) VALUES ($1, $2, $3, $4, 'Queued', $5, $6)"
+        )
+        .bind(&task_id)
+        .bind(&sample.filename)
+        .bind(&display_name)
+        .bind(&sample.sha256)
+        .bind(chrono::Utc::now().timestamp_millis())
+        .bind(&file_path)
+        .execute(pool.get_ref())
+        .await {
+            println!("[BATCH] Failed to create task for {}: {}", display_name, e);
+            continue;
+        }
+        network_policy::record(pool.get_ref(), &task_id, settings.internet_policy).await;
+        if let Some(container) = &sample.container {
+            archive::record(pool.get_ref(), &task_id, container).await;
+        }
+
+        let job = QueuedAnalysis {
+            task_id: task_id.clone(),
+            target_url: format!("http://{}:8080/uploads/{}", host_ip, sample.filename),
+            original_filename: sample.original_filename,
+            duration_seconds,
+            vmid: settings.vmid,
+            node: settings.node.clone(),
+            is_url_task: false,
+            analysis_mode: analysis_mode.clone(),
+            os_profile: settings.os_profile.clone(),
+            priority: priority.to_string(),
+            profile: profile.as_ref().map(|p| p.name.clone()),
+        };
+
+        // Emails fan out into their attachments and links rather than being detonated
+        if let Some(format) = email::detect(Path::new(&file_path)) {
+            let child = email::ChildSettings { base: &job, host_ip: &host_ip, static_analysis };
+            match email::fan_out(pool.get_ref(), scheduler.get_ref(), &task_id, Path::new(&file_path), format, child).await {
+                Ok(_) => task_ids.push(task_id),
+                Err(e) => {
+                    println!("[BATCH] Could not parse email {}: {}", display_name, e);
+                    let _ = sqlx::query("UPDATE tasks SET status='Failed (Email Parse)' WHERE id=$1")
+                        .bind(&task_id).execute(pool.get_ref()).await;
+                }
+            }
+            continue;
+        }
+
+        let vt_pool = pool.get_ref().clone();
+        let vt_hash = sample.sha256.clone();
+        actix_web::rt::spawn(async move {
+            let _ = virustotal::get_cached_or_fetch(&vt_pool, &vt_hash).await;
+        });
+
+        if static_analysis {
+            let (ghidra_pool, ghidra_name, ghidra_task) = (pool.get_ref().clone(), sample.filename.clone(), task_id.clone());
+            actix_web::rt::spawn(async move {
+                crate::trigger_ghidra_background(ghidra_name, ghidra_task, ghidra_pool).await;
+            });
+            let (remnux_pool, remnux_name, remnux_task, remnux_path) = (pool.get_ref().clone(), sample.filename.clone(), task_id.clone(), file_path.clone());
+            actix_web::rt::spawn(async move {
+                remnux::trigger_scan(remnux_pool, remnux_task, remnux_name, remnux_path).await;
+            });
+            crate::yara::queue_task(pool.get_ref(), &task_id);
+            crate::static_properties::queue(pool.get_ref(), &task_id);
+        }
+
+        if analysis_mode == static_only::MODE {
+            scheduler.run_static(task_id.clone());
+            task_ids.push(task_id);
+            continue;
+        }
+        if let Err(e) = scheduler.enqueue(job).await {
+            println!("[BATCH] Failed to queue task {}: {}", task_id, e);
+            continue;
+        }
+        task_ids.push(task_id);
+    }
+
+    println!("[BATCH] Batch {}: {} task(s) queued, {} reused, {} item(s) skipped.", batch_id, task_ids.len(), reused.len(), skipped.len());
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "batch_queued",
+        "batch_id": batch_id,
+        "task_ids": task_ids,
+        "reused": reused,
+        "skipped": skipped,
+        "mode": analysis_mode,
+        "priority": priority,
+    })))
+}
@@ -0,0 +1,262 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use regex::Regex;
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// --- BEACONING DETECTION ---
+// Regularly spaced connections to one destination.
+
+pub const MIN_CONNECTIONS: usize = 5;
+/// Gaps within this fraction of the median gap count as on schedule.
+const BEACON_TOLERANCE: f64 = 0.25;
+const MIN_REGULARITY: f64 = 0.8;
+/// Connections closer together than this are one connection retried.
+const RETRY_WINDOW_MS: i64 = 1000;
+/// Payload sizes vary by at most this much (stddev / mean) to count as uniform.
+const UNIFORM_SIZE_CV: f64 = 0.2;
+
+/// One connection as the detector sees it.
+pub struct Connection {
+    pub event_id: i32,
+    pub timestamp: i64,
+    /// "ip:port" or the URL host.
+    pub destination: String,
+    /// Local endpoint when the event names one; repeated sightings of it are one socket.
+    pub local: Option<String>,
+    pub process_name: String,
+    /// Request plus response payload size, when known.
+    pub bytes: Option<u64>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct Beacon {
+    pub destination: String,
+    pub process_names: Vec<String>,
+    pub connections: usize,
+    /// Median gap between connections, in seconds.
+    pub period_secs: f64,
+    /// Share of gaps on schedule, 0-1.
+    pub regularity: f64,
+    /// Median absolute deviation of the gaps over the period, 0 = clockwork.
+    pub jitter: f64,
+    /// Coefficient of variation of payload sizes; None when sizes are not known.
+    pub size_cv: Option<f64>,
+    pub beacon: bool,
+    /// high when the schedule is tight and payloads are uniform (or unknown), else medium.
+    pub confidence: &'static str,
+    pub first_event_id: i32,
+    pub first_seen: i64,
+    pub last_seen: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct EventRow {
+    id: i32,
+    event_type: String,
+    process_name: String,
+    details: String,
+    decoded_details: Option<String>,
+    timestamp: i64,
+}
+
+fn endpoints() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?:(\S+)\s+)?->\s*([^\s,]+:\d+)").unwrap())
+}
+
+fn url_host() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?i)\b(?:https?|wss?)://([^/\s:?#]+(?::\d+)?)").unwrap())
+}
+
+fn sizes() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?i)\b(\d+)\s*bytes\b|\bbytes[=:]\s*(\d+)").unwrap())
+}
+
+/// Reads the destination, local endpoint and payload size out of a network event.
+pub fn connection(event_type: &str, details: &str, decoded: Option<&str>) -> Option<(String, Option<String>, Option<u64>)> {
+    let bytes = sizes().captures_iter(details)
+        .filter_map(|c| c.get(1).or(c.get(2)).and_then(|m| m.as_str().parse::<u64>().ok()))
+        .reduce(|a, b| a + b);
+    match event_type {
+        "NETWORK_CONNECT" => {
+            let caps = endpoints().captures(details)?;
+            let destination = caps.get(2)?.as_str().to_lowercase();
+            if destination.starts_with("127.") || destination.starts_with("[::1]") {
+                return None;
+            }
+            // Only a local endpoint with a port identifies a socket; Sysmon gives the bare address
+            let local = caps.get(1).map(|m| m.as_str()).filter(|l| l.parse::<std::net::SocketAddr>().is_ok()).map(str::to_string);
+            Some((destination, local, bytes))
+        }
+        "HTTP_REQUEST" | "NETWORK_HTTP" => {
+            let host = url_host().captures(details)
+                .or_else(|| decoded.and_then(|d| url_host().captures(d)))
+                .map(|c| c[1].to_lowercase())
+                .or_else(|| endpoints().captures(details).and_then(|c| c.get(2)).map(|m| m.as_str().to_lowercase()))?;
+            // MITM flows carry the bodies; their lengths stand in for the payload size
+            let body_bytes = decoded
+                .and_then(|d| serde_json::from_str::<serde_json::Value>(d).ok())
+                .map(|v| ["request_body", "response_body"].iter()
+                    .map(|k| v[*k].as_str().map(|s| s.len() as u64).unwrap_or(0))
+                    .sum::<u64>());
+            Some((host, None, bytes.or(body_bytes)))
+        }
+        _ => None,
+    }
+}
+
+fn median(sorted: &[f64]) -> f64 {
+    let n = sorted.len();
+    if n % 2 == 1 { sorted[n / 2] } else { (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0 }
+}
+
+/// Measures every destination with at least MIN_CONNECTIONS connections; beacons first.
+pub fn detect(connections: &[Connection]) -> Vec<Beacon> {
+    let mut by_dest: HashMap<&str, Vec<&Connection>> = HashMap::new();
+    for c in connections {
+        by_dest.entry(c.destination.as_str()).or_default().push(c);
+    }
+
+    let mut out: Vec<Beacon> = by_dest.into_iter().filter_map(|(destination, mut conns)| {
+        conns.sort_by_key(|c| (c.timestamp, c.event_id));
+        // One socket polled several times, or a burst of retries, is a single check-in
+        let mut seen_sockets: Vec<&str> = Vec::new();
+        let mut kept: Vec<&Connection> = Vec::new();
+        for c in conns {
+            if let Some(local) = c.local.as_deref() {
+                if seen_sockets.contains(&local) {
+                    continue;
+                }
+                seen_sockets.push(local);
+            }
+            if kept.last().is_some_and(|prev| c.timestamp - prev.timestamp < RETRY_WINDOW_MS) {
+                continue;
+            }
+            kept.push(c);
+        }
+        if kept.len() < MIN_CONNECTIONS {
+            return None;
+        }
+
+        let mut gaps: Vec<f64> = kept.windows(2).map(|w| (w[1].timestamp - w[0].timestamp) as f64).collect();
+        gaps.sort_by(|a, b| a.total_cmp(b));
+        let period = median(&gaps);
+        let regular = gaps.iter().filter(|g| (**g - period).abs() <= period * BEACON_TOLERANCE).count();
+        let regularity = regular as f64 / gaps.len() as f64;
+        let mut deviations: Vec<f64> = gaps.iter().map(|g| (g - period).abs()).collect();
+        deviations.sort_by(|a, b| a.total_cmp(b));
+        let jitter = if period > 0.0 { median(&deviations) / period } else { 1.0 };
+
+        let sizes: Vec<f64> = kept.iter().filter_map(|c| c.bytes).map(|b| b as f64).collect();
+        let size_cv = (sizes.len() >= MIN_CONNECTIONS).then(|| {
+            let mean = sizes.iter().sum::<f64>() / sizes.len() as f64;
+            let variance = sizes.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / sizes.len() as f64;
+            if mean > 0.0 { variance.sqrt() / mean } else { 0.0 }
+        });
+
+        let beacon = regularity >= MIN_REGULARITY;
+        let uniform = size_cv.is_none_or(|cv| cv <= UNIFORM_SIZE_CV);
+        let confidence = if beacon && jitter <= 0.1 && uniform && kept.len() >= MIN_CONNECTIONS + 3 { "high" } else { "medium" };
+
+        let mut process_names: Vec<String> = kept.iter().map(|c| c.process_name.clone()).filter(|n| !n.is_empty()).collect();
+        process_names.sort();
+        process_names.dedup();
+
+        Some(Beacon {
+            destination: destination.to_string(),
+            process_names,
+            connections: kept.len(),
+            period_secs: (period / 100.0).round() / 10.0,
+            regularity: (regularity * 100.0).round() / 100.0,
+            jitter: (jitter * 100.0).round() / 100.0,
+            size_cv: size_cv.map(|cv| (cv * 100.0).round() / 100.0),
+            beacon,
+            confidence,
+            first_event_id: kept[0].event_id,
+            first_seen: kept[0].timestamp,
+            last_seen: kept[kept.len() - 1].timestamp,
+        })
+    }).collect();
+
+    out.sort_by(|a, b| b.beacon.cmp(&a.beacon).then(b.connections.cmp(&a.connections)).then(a.destination.cmp(&b.destination)));
+    out
+}
+
+async fn analyze(pool: &Pool<Postgres>, task_id: &str) -> Result<Vec<Beacon>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, EventRow>(
+        "SELECT id, event_type, process_name, details, decoded_details, timestamp FROM events
+         WHERE task_id = $1 AND event_type IN ('NETWORK_CONNECT', 'HTTP_REQUEST', 'NETWORK_HTTP')
+         ORDER BY timestamp, id"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+
+    let connections: Vec<Connection> = rows.into_iter().filter_map(|r| {
+        let (destination, local, bytes) = connection(&r.event_type, &r.details, r.decoded_details.as_deref())?;
+        Some(Connection { event_id: r.id, timestamp: r.timestamp, destination, local, process_name: r.process_name, bytes })
+    }).collect();
+    Ok(detect(&connections))
+}
+
+/// Post-run pass: raises a BEACONING alert for each beacon not already alerted on for the task.
+pub async fn run(pool: &Pool<Postgres>, task_id: &str) {
+    let beacons = match analyze(pool, task_id).await {
+        Ok(b) => b,
+        Err(e) => {
+            println!("[BEACONING] Failed to analyze task {}: {}", task_id, e);
+            return;
+        }
+    };
+    let alerted: Vec<String> = sqlx::query_scalar("SELECT rule_id FROM alerts WHERE task_id = $1 AND source = 'beaconing'")
+        .bind(task_id)
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default();
+
+    let new: Vec<crate::alerts::NewAlert> = beacons.iter()
+        .filter(|b| b.beacon && !alerted.contains(&format!("beaconing:{}", b.destination)))
+        .map(|b| crate::alerts::NewAlert {
+            task_id: Some(task_id.to_string()),
+            event_id: Some(b.first_event_id),
+            source: "beaconing",
+            rule_id: format!("beaconing:{}", b.destination),
+            rule_title: format!("BEACONING: {} every ~{}s", b.destination, b.period_secs),
+            level: if b.confidence == "high" { "high" } else { "medium" }.to_string(),
+            severity: if b.confidence == "high" { 80 } else { 60 },
+            tags: vec!["attack.command_and_control".to_string(), "attack.t1071".to_string()],
+            rule_references: Vec::new(),
+            process_id: 0,
+            process_name: b.process_names.join(", "),
+            details: format!(
+                "{} connections to {} with a period of ~{}s (regularity {:.0}%, jitter {:.0}%{})",
+                b.connections, b.destination, b.period_secs, b.regularity * 100.0, b.jitter * 100.0,
+                b.size_cv.map(|cv| format!(", payload size CV {:.2}", cv)).unwrap_or_default(),
+            ),
+            timestamp: b.first_seen,
+        })
+        .collect();
+    if !new.is_empty() {
+        println!("[BEACONING] Task {}: {} beacon(s) found.", task_id, new.len());
+        crate::alerts::record(pool, None, new).await;
+    }
+}
+
+/// Per-destination schedule analysis for the task, beacons first.
+#[utoipa::path(tag = "detections", responses((status = 200, description = "Destinations with enough connections to measure")))]
+#[get("/tasks/{id}/beacons")]
+pub async fn get_beacons(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    let task_id = path.into_inner();
+    match analyze(pool.get_ref(), &task_id).await {
+        Ok(destinations) => HttpResponse::Ok().json(serde_json::json!({
+            "task_id": task_id,
+            "beacons": destinations.iter().filter(|b| b.beacon).count(),
+            "destinations": destinations,
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
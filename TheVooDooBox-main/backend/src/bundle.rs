@@ -0,0 +1,611 @@
+use actix_multipart::Multipart;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use futures::TryStreamExt;
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::io::{BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use zip::unstable::write::FileOptionsExt;
+use crate::{archive, limits, storage};
+
+// --- TASK BUNDLES ---
+// Whole-task zip export and import.
+
+const FORMAT: &str = "voodoobox-task-bundle";
+const VERSION: u64 = 1;
+/// Where bundles are built before streaming and unpacked before import.
+const WORK_DIR: &str = "./exports";
+const CHUNK_SIZE: usize = 256 * 1024;
+/// Rows per INSERT on import.
+const BATCH: usize = 1000;
+/// Decompressed size caps on import, per zip member and for the whole bundle.
+const DEFAULT_MEMBER_MB: u64 = 4096;
+const DEFAULT_UNPACKED_MB: u64 = 16384;
+
+/// Per-task tables and their export order column; events first, the next three refer to them.
+const TABLES: &[(&str, &str)] = &[
+    ("events", "id"),
+    ("screenshots", "id"),
+    ("telemetry_tags", "event_id"),
+    ("alerts", "id"),
+    ("analyst_notes", "created_at"),
+    ("network_alerts", "id"),
+    ("ghidra_findings", "id"),
+    ("analysis_reports", "id"),
+    ("memory_analyses", "id"),
+    ("email_analyses", "task_id"),
+    ("url_artifacts", "task_id"),
+];
+
+/// Directories below files/ that an import restores; anything else in the zip is ignored.
+const FILE_KINDS: &[&str] = &["screenshots", "url_artifacts", "reports", "pcap", "memory_dumps", "sample"];
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BundleQuery {
+    /// Also pack the sample, memory dumps and downloaded URL payloads (password-protected).
+    #[serde(default)]
+    pub include_sample: bool,
+}
+
+fn entry_name(table: &str) -> String {
+    if table == "events" {
+        "events.jsonl".to_string()
+    } else {
+        format!("tables/{}.jsonl", table)
+    }
+}
+
+fn is_malware(entry: &str) -> bool {
+    entry.starts_with("files/sample/") || entry.starts_with("files/memory_dumps/") || entry.starts_with("files/url_artifacts/payload_")
+}
+
+/// The task's rows of `table` as JSON Lines, with their count.
+async fn table_rows(pool: &Pool<Postgres>, table: &str, order: &str, task_id: &str) -> Result<(usize, Vec<u8>), sqlx::Error> {
+    let sql = format!("SELECT to_jsonb(t)::text FROM {} t WHERE task_id = $1 ORDER BY {}", table, order);
+    let mut rows = sqlx::query_scalar::<_, String>(&sql).bind(task_id).fetch(pool);
+    let mut out = Vec::new();
+    let mut count = 0;
+    while let Some(row) = rows.try_next().await? {
+        out.extend_from_slice(row.as_bytes());
+        out.push(b'\n');
+        count += 1;
+    }
+    Ok((count, out))
+}
+
+/// Files directly in `dir` as (bundle entry, local path); `rename` picks and names them.
+fn dir_files(dir: &str, kind: &str, rename: impl Fn(&str) -> Option<String>) -> Vec<(String, PathBuf)> {
+    let Ok(entries) = std::fs::read_dir(dir) else { return Vec::new() };
+    let mut files: Vec<(String, PathBuf)> = entries
+        .flatten()
+        .filter(|e| e.path().is_file())
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            if name.ends_with(".part") {
+                return None;
+            }
+            rename(&name).map(|n| (format!("files/{}/{}", kind, n), e.path()))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+fn write_bundle(dest: &Path, manifest: &Value, task: &str, tables: &[(String, Vec<u8>)], files: &[(String, PathBuf)]) -> Result<(), String> {
+    let out = std::fs::File::create(dest).map_err(|e| e.to_string())?;
+    let mut zip = zip::ZipWriter::new(out);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .large_file(true);
+
+    zip.start_file("manifest.json", options).map_err(|e| e.to_string())?;
+    zip.write_all(&serde_json::to_vec_pretty(manifest).unwrap_or_default()).map_err(|e| e.to_string())?;
+    zip.start_file("task.json", options).map_err(|e| e.to_string())?;
+    zip.write_all(task.as_bytes()).map_err(|e| e.to_string())?;
+    for (name, rows) in tables {
+        zip.start_file(name.as_str(), options).map_err(|e| e.to_string())?;
+        zip.write_all(rows).map_err(|e| e.to_string())?;
+    }
+    for (name, path) in files {
+        let mut f = match std::fs::File::open(path) {
+            Ok(f) => f,
+            Err(e) => {
+                println!("[BUNDLE] Skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let opts = if is_malware(name) {
+            options.with_deprecated_encryption(archive::DEFAULT_PASSWORD.as_bytes())
+        } else {
+            options
+        };
+        zip.start_file(name.as_str(), opts).map_err(|e| e.to_string())?;
+        std::io::copy(&mut f, &mut zip).map_err(|e| format!("{}: {}", name, e))?;
+    }
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Streams a finished bundle and deletes it once sent or abandoned.
+fn stream_bundle(path: PathBuf, stem: String, encrypted: bool) -> HttpResponse {
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<web::Bytes, std::io::Error>>(8);
+    let disposition = format!("attachment; filename=\"{}.zip\"", stem);
+    tokio::spawn(async move {
+        let sent: std::io::Result<()> = async {
+            let mut file = tokio::fs::File::open(&path).await?;
+            let mut buf = vec![0u8; CHUNK_SIZE];
+            loop {
+                let n = file.read(&mut buf).await?;
+                if n == 0 || tx.send(Ok(web::Bytes::copy_from_slice(&buf[..n]))).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+        .await;
+        if let Err(e) = sent {
+            println!("[BUNDLE] Download of {} aborted: {}", stem, e);
+            let _ = tx.send(Err(e)).await;
+        }
+        let _ = tokio::fs::remove_file(&path).await;
+    });
+
+    let mut resp = HttpResponse::Ok();
+    resp.content_type("application/zip")
+        .insert_header(("Content-Disposition", disposition));
+    if encrypted {
+        resp.insert_header(("X-Archive-Password", archive::DEFAULT_PASSWORD));
+    }
+    resp.streaming(tokio_stream::wrappers::ReceiverStream::new(rx))
+}
+
+#[utoipa::path(tag = "tasks", params(BundleQuery), responses(
+    (status = 200, description = "Zip bundle of the task"),
+    (status = 404, description = "Task not found"),
+))]
+#[get("/tasks/{id}/export/bundle")]
+pub async fn export_bundle(
+    req: HttpRequest,
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+    query: web::Query<BundleQuery>,
+) -> impl Responder {
+    let task_id = path.into_inner();
+    let task: Option<String> = match sqlx::query_scalar("SELECT to_jsonb(t)::text FROM tasks t WHERE id = $1")
+        .bind(&task_id)
+        .fetch_optional(pool.get_ref())
+        .await
+    {
+        Ok(t) => t,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    let Some(task) = task else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" }));
+    };
+
+    let mut tables = Vec::new();
+    let mut counts = serde_json::Map::new();
+    for (table, order) in TABLES {
+        match table_rows(pool.get_ref(), table, order, &task_id).await {
+            Ok((count, rows)) => {
+                counts.insert(table.to_string(), count.into());
+                tables.push((entry_name(table), rows));
+            }
+            Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": format!("{}: {}", table, e) })),
+        }
+    }
+
+    let include_sample = query.include_sample;
+    storage::ensure_local_prefix(&format!("screenshots/{}/", task_id)).await;
+    storage::ensure_local_prefix(&format!("url_artifacts/{}/", task_id)).await;
+    let mut files = dir_files(&format!("./screenshots/{}", task_id), "screenshots", |n| Some(n.to_string()));
+    files.extend(dir_files(&format!("./url_artifacts/{}", task_id), "url_artifacts", |n| {
+        (include_sample || !n.starts_with("payload_")).then(|| n.to_string())
+    }));
+    let pcap_prefix = format!("{}-", task_id);
+    files.extend(dir_files("./uploads/pcap", "pcap", |n| n.strip_prefix(&pcap_prefix).map(str::to_string)));
+    let report = format!("reports/{}.pdf", task_id);
+    if storage::ensure_local(&report).await {
+        files.push(("files/reports/report.pdf".to_string(), storage::local_path(&report)));
+    }
+
+    if include_sample {
+        files.extend(dir_files(&format!("./memory_dumps/{}", task_id), "memory_dumps", |n| Some(n.to_string())));
+        let row: Value = serde_json::from_str(&task).unwrap_or_default();
+        let filename = row["filename"].as_str().unwrap_or_default();
+        let key = format!("uploads/{}", filename);
+        // URL tasks have no file; neither do tasks whose sample was deleted
+        let packed = !filename.is_empty() && !filename.contains("..") && !filename.contains('/') && storage::ensure_local(&key).await;
+        if packed {
+            files.push((format!("files/sample/{}", filename), storage::local_path(&key)));
+        }
+        crate::audit::log_access(pool.get_ref(), &req, &task_id, serde_json::json!({ "bundle": true, "sha256": row["file_hash"], "sample": packed }), 200).await;
+    }
+
+    let manifest = serde_json::json!({
+        "format": FORMAT,
+        "version": VERSION,
+        "task_id": task_id,
+        "exported_at": Utc::now().timestamp_millis(),
+        "includes_sample": include_sample,
+        "rows": counts,
+        "files": files.len(),
+    });
+    let _ = tokio::fs::create_dir_all(WORK_DIR).await;
+    let dest = Path::new(WORK_DIR).join(format!("{}-{}.zip", task_id, uuid::Uuid::new_v4()));
+    let target = dest.clone();
+    let file_count = files.len();
+    let written = tokio::task::spawn_blocking(move || write_bundle(&target, &manifest, &task, &tables, &files))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| r);
+    if let Err(e) = written {
+        let _ = tokio::fs::remove_file(&dest).await;
+        println!("[BUNDLE] Export of task {} failed: {}", task_id, e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e }));
+    }
+    println!("[BUNDLE] Task {} exported with {} files{}.", task_id, file_count, if include_sample { " (sample included)" } else { "" });
+
+    stream_bundle(dest, format!("task-{}-bundle", task_id), include_sample)
+}
+
+/// An unpacked import: rows parsed, files staged on disk.
+struct Bundle {
+    manifest: Value,
+    task: Value,
+    tables: HashMap<String, Vec<Value>>,
+    /// (kind, file name, staged path)
+    files: Vec<(String, String, PathBuf)>,
+}
+
+fn mb_from_env(var: &str, default: u64) -> u64 {
+    std::env::var(var).ok().and_then(|v| v.trim().parse::<u64>().ok()).filter(|n| *n > 0).unwrap_or(default) * 1024 * 1024
+}
+
+/// Remaining decompression budget of an import (MAX_BUNDLE_MEMBER_MB, MAX_BUNDLE_UNPACKED_MB).
+struct Unpacked {
+    member_max: u64,
+    left: u64,
+}
+
+impl Unpacked {
+    fn from_env() -> Self {
+        Self {
+            member_max: mb_from_env("MAX_BUNDLE_MEMBER_MB", DEFAULT_MEMBER_MB),
+            left: mb_from_env("MAX_BUNDLE_UNPACKED_MB", DEFAULT_UNPACKED_MB),
+        }
+    }
+
+    fn copy(&mut self, name: &str, member: impl Read, out: &mut impl Write) -> Result<(), String> {
+        let cap = self.member_max.min(self.left);
+        let n = std::io::copy(&mut member.take(cap + 1), out).map_err(|e| format!("{}: {}", name, e))?;
+        if n > cap {
+            return Err(if cap == self.member_max {
+                format!("{} unpacks to more than {} MB (MAX_BUNDLE_MEMBER_MB)", name, self.member_max / 1024 / 1024)
+            } else {
+                "Bundle unpacks to more than MAX_BUNDLE_UNPACKED_MB".to_string()
+            });
+        }
+        self.left -= n;
+        Ok(())
+    }
+
+    fn read(&mut self, name: &str, member: impl Read) -> Result<Vec<u8>, String> {
+        let mut buf = Vec::new();
+        self.copy(name, member, &mut buf)?;
+        Ok(buf)
+    }
+}
+
+fn read_json(zip: &mut zip::ZipArchive<std::fs::File>, unpacked: &mut Unpacked, name: &str) -> Result<Value, String> {
+    let member = zip.by_name(name).map_err(|_| format!("{} missing; not a task bundle", name))?;
+    let bytes = unpacked.read(name, member)?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("{}: {}", name, e))
+}
+
+/// Task ids and file names from a bundle end up in storage keys and paths.
+fn safe_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+}
+
+fn read_bundle(archive: &Path, staging: &Path) -> Result<Bundle, String> {
+    let file = std::fs::File::open(archive).map_err(|e| e.to_string())?;
+    let mut zip = zip::ZipArchive::new(file).map_err(|e| format!("Unreadable zip: {}", e))?;
+    let mut unpacked = Unpacked::from_env();
+
+    let manifest = read_json(&mut zip, &mut unpacked, "manifest.json")?;
+    if manifest["format"] != FORMAT {
+        return Err("manifest.json is not a task bundle manifest".to_string());
+    }
+    let version = manifest["version"].as_u64().unwrap_or(0);
+    if version > VERSION {
+        return Err(format!("Bundle version {} is newer than this instance reads ({})", version, VERSION));
+    }
+    let task = read_json(&mut zip, &mut unpacked, "task.json")?;
+    let mut bundle = Bundle { manifest, task, tables: HashMap::new(), files: Vec::new() };
+
+    for (table, _) in TABLES {
+        let name = entry_name(table);
+        let Ok(member) = zip.by_name(&name) else { continue };
+        let bytes = unpacked.read(&name, member)?;
+        let mut rows = Vec::new();
+        for line in bytes.as_slice().lines() {
+            let line = line.map_err(|e| format!("{}: {}", name, e))?;
+            if !line.trim().is_empty() {
+                rows.push(serde_json::from_str(&line).map_err(|e| format!("{}: {}", name, e))?);
+            }
+        }
+        bundle.tables.insert(table.to_string(), rows);
+    }
+
+    for i in 0..zip.len() {
+        let encrypted = zip.by_index_raw(i).map(|m| m.encrypted()).map_err(|e| e.to_string())?;
+        let opened = if encrypted { zip.by_index_decrypt(i, archive::DEFAULT_PASSWORD.as_bytes()) } else { zip.by_index(i) };
+        let member = opened.map_err(|e| e.to_string())?;
+        if member.is_dir() {
+            continue;
+        }
+        // Only files/<kind>/<name>; enclosed_name already refuses anything climbing out
+        let Some(path) = member.enclosed_name() else { continue };
+        let parts: Vec<String> = path.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+        let [root, kind, name] = parts.as_slice() else { continue };
+        if root != "files" || !FILE_KINDS.contains(&kind.as_str()) {
+            continue;
+        }
+        let staged = staging.join(bundle.files.len().to_string());
+        let mut out = std::fs::File::create(&staged).map_err(|e| e.to_string())?;
+        unpacked.copy(&format!("{}/{}", kind, name), member, &mut out)?;
+        bundle.files.push((kind.clone(), name.clone(), staged));
+    }
+    Ok(bundle)
+}
+
+/// Inserts `rows` by shared columns; returns old id -> new id.
+async fn insert_rows(tx: &mut sqlx::Transaction<'_, Postgres>, table: &str, rows: &[Value]) -> Result<HashMap<i64, i64>, sqlx::Error> {
+    let mut ids = HashMap::new();
+    let Some(first) = rows.first().and_then(Value::as_object) else { return Ok(ids) };
+    let info: Vec<(String, bool)> = sqlx::query_as(
+        "SELECT column_name::text, COALESCE(column_default, '') LIKE 'nextval%'
+         FROM information_schema.columns
+         WHERE table_schema = current_schema() AND table_name = $1
+         ORDER BY ordinal_position"
+    )
+    .bind(table)
+    .fetch_all(&mut **tx)
+    .await?;
+    let columns: Vec<String> = info.iter()
+        .filter(|(name, serial)| !serial && first.contains_key(name))
+        .map(|(name, _)| format!("\"{}\"", name))
+        .collect();
+    let serial_id = info.iter().any(|(name, serial)| *serial && name == "id");
+    let list = columns.join(", ");
+    let sql = format!(
+        "INSERT INTO {table} ({list}) SELECT {list} FROM jsonb_populate_recordset(NULL::{table}, $1::jsonb) WITH ORDINALITY AS r ORDER BY r.ordinality{}",
+        if serial_id { " RETURNING id" } else { "" }
+    );
+
+    for batch in rows.chunks(BATCH) {
+        let json = Value::Array(batch.to_vec()).to_string();
+        if !serial_id {
+            sqlx::query(&sql).bind(json).execute(&mut **tx).await?;
+            continue;
+        }
+        let mut new: Vec<i32> = sqlx::query_scalar(&sql).bind(json).fetch_all(&mut **tx).await?;
+        // Ids come from one sequence in ORDER BY order, so sorted ids line up with the batch
+        new.sort_unstable();
+        for (row, id) in batch.iter().zip(new) {
+            if let Some(old) = row.get("id").and_then(Value::as_i64) {
+                ids.insert(old, id as i64);
+            }
+        }
+    }
+    Ok(ids)
+}
+
+/// Points `column` at the renumbered row; false if that row did not come along.
+fn remap(row: &mut serde_json::Map<String, Value>, column: &str, ids: &HashMap<i64, i64>) -> bool {
+    let Some(old) = row.get(column).and_then(Value::as_i64) else { return true };
+    match ids.get(&old) {
+        Some(new) => {
+            row.insert(column.to_string(), (*new).into());
+            true
+        }
+        None => {
+            row.insert(column.to_string(), Value::Null);
+            false
+        }
+    }
+}
+
+async fn restore(pool: &Pool<Postgres>, mut bundle: Bundle) -> HttpResponse {
+    let Some(original) = bundle.task.get("id").and_then(Value::as_str).map(str::to_string) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "task.json has no task id" }));
+    };
+    if !safe_id(&original) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "task.json has an invalid task id" }));
+    }
+    let filename = bundle.task.get("filename").and_then(Value::as_str).unwrap_or_default();
+    if filename.contains(['/', '\\']) || filename.contains("..") {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "task.json has an invalid filename" }));
+    }
+    let taken: bool = match sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM tasks WHERE id = $1)")
+        .bind(&original)
+        .fetch_one(pool)
+        .await
+    {
+        Ok(t) => t,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    let task_id = if taken { Utc::now().timestamp_millis().to_string() } else { original.clone() };
+    if let Some(task) = bundle.task.as_object_mut() {
+        task.insert("id".to_string(), task_id.clone().into());
+        // The schedule and sandbox it ran under belong to the other instance
+        task.insert("schedule_id".to_string(), Value::Null);
+        task.insert("sandbox_id".to_string(), Value::Null);
+    }
+
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    if let Err(e) = insert_rows(&mut tx, "tasks", std::slice::from_ref(&bundle.task)).await {
+        return HttpResponse::UnprocessableEntity().json(serde_json::json!({ "error": format!("tasks: {}", e) }));
+    }
+
+    let mut counts = serde_json::Map::new();
+    let mut event_ids = HashMap::new();
+    let mut duplicates = Vec::new();
+    for (table, _) in TABLES {
+        let Some(mut rows) = bundle.tables.remove(*table) else { continue };
+        for row in rows.iter_mut() {
+            let Some(obj) = row.as_object_mut() else { continue };
+            obj.insert("task_id".to_string(), task_id.clone().into());
+            match *table {
+                "screenshots" => {
+                    remap(obj, "event_id", &event_ids);
+                    // Near-duplicate frames point at an earlier frame; relinked once it has its new id
+                    if let (Some(id), Some(dup)) = (obj.get("id").and_then(Value::as_i64), obj.get("duplicate_of").and_then(Value::as_i64)) {
+                        duplicates.push((id, dup));
+                    }
+                    obj.insert("duplicate_of".to_string(), Value::Null);
+                }
+                "alerts" => {
+                    remap(obj, "event_id", &event_ids);
+                }
+                "analyst_notes" if taken => {
+                    obj.insert("id".to_string(), uuid::Uuid::new_v4().to_string().into());
+                }
+                _ => {}
+            }
+        }
+        // A tag on an event that was not exported would land on an unrelated one
+        if *table == "telemetry_tags" {
+            rows.retain_mut(|row| row.as_object_mut().is_some_and(|obj| remap(obj, "event_id", &event_ids)));
+        }
+        let ids = match insert_rows(&mut tx, table, &rows).await {
+            Ok(ids) => ids,
+            Err(e) => return HttpResponse::UnprocessableEntity().json(serde_json::json!({ "error": format!("{}: {}", table, e) })),
+        };
+        match *table {
+            "events" => event_ids = ids,
+            "screenshots" => {
+                for (frame, dup) in &duplicates {
+                    if let (Some(frame), Some(dup)) = (ids.get(frame), ids.get(dup)) {
+                        let relinked = sqlx::query("UPDATE screenshots SET duplicate_of = $1 WHERE id = $2")
+                            .bind(*dup as i32)
+                            .bind(*frame as i32)
+                            .execute(&mut *tx)
+                            .await;
+                        if let Err(e) = relinked {
+                            return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() }));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        counts.insert(table.to_string(), rows.len().into());
+    }
+    if let Err(e) = tx.commit().await {
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() }));
+    }
+
+    let mut restored = 0;
+    for (kind, name, staged) in bundle.files {
+        let key = match kind.as_str() {
+            "screenshots" | "url_artifacts" | "memory_dumps" => format!("{}/{}/{}", kind, task_id, name),
+            "reports" => format!("reports/{}.pdf", task_id),
+            "pcap" => format!("uploads/pcap/{}-{}", task_id, name),
+            _ => format!("uploads/{}", name),
+        };
+        let dest = storage::local_path(&key);
+        // Samples are shared by every task of the same file
+        if kind == "sample" && dest.exists() {
+            continue;
+        }
+        if let Some(dir) = dest.parent() {
+            let _ = tokio::fs::create_dir_all(dir).await;
+        }
+        // The work directory may sit on another volume than the destination
+        let moved = match tokio::fs::rename(&staged, &dest).await {
+            Ok(()) => Ok(()),
+            Err(_) => tokio::fs::copy(&staged, &dest).await.map(|_| ()),
+        };
+        if let Err(e) = moved {
+            println!("[BUNDLE] Failed to restore {}: {}", key, e);
+            continue;
+        }
+        if matches!(kind.as_str(), "screenshots" | "url_artifacts" | "reports" | "sample") {
+            storage::persist(&key).await;
+        }
+        restored += 1;
+    }
+
+    println!("[BUNDLE] Imported task {} as {} ({} files).", original, task_id, restored);
+    HttpResponse::Ok().json(serde_json::json!({
+        "task_id": task_id,
+        "original_task_id": original,
+        "exported_at": bundle.manifest["exported_at"],
+        "rows": counts,
+        "files": restored,
+    }))
+}
+
+#[utoipa::path(tag = "tasks", responses(
+    (status = 200, description = "Bundle imported; returns the task id it was filed under"),
+    (status = 400, description = "Not a task bundle"),
+    (status = 413, description = "Upload over MAX_BUNDLE_UPLOAD_MB"),
+    (status = 422, description = "Rows the database refused"),
+))]
+#[post("/tasks/import")]
+pub async fn import_bundle(
+    req: HttpRequest,
+    mut payload: Multipart,
+    pool: web::Data<Pool<Postgres>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut limit = limits::UploadLimit::bundle();
+    if let Err(resp) = limit.check_declared(&req) {
+        return Ok(resp);
+    }
+    let staging = Path::new(WORK_DIR).join(format!("import-{}", uuid::Uuid::new_v4()));
+    tokio::fs::create_dir_all(&staging).await.map_err(actix_web::error::ErrorInternalServerError)?;
+    let archive_path = staging.join("bundle.zip");
+
+    let mut received = false;
+    while let Ok(Some(mut field)) = TryStreamExt::try_next(&mut payload).await {
+        if field.content_disposition().and_then(|cd| cd.get_name()) != Some("bundle") {
+            continue;
+        }
+        let mut f = tokio::fs::File::create(&archive_path).await.map_err(actix_web::error::ErrorInternalServerError)?;
+        while let Ok(Some(chunk)) = TryStreamExt::try_next(&mut field).await {
+            if let Err(resp) = limit.add(chunk.len()) {
+                drop(f);
+                let _ = tokio::fs::remove_dir_all(&staging).await;
+                return Ok(resp);
+            }
+            f.write_all(&chunk).await.map_err(actix_web::error::ErrorInternalServerError)?;
+        }
+        f.flush().await.map_err(actix_web::error::ErrorInternalServerError)?;
+        received = true;
+    }
+    if !received {
+        let _ = tokio::fs::remove_dir_all(&staging).await;
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": "Multipart field 'bundle' required" })));
+    }
+
+    let dir = staging.clone();
+    let unpacked = tokio::task::spawn_blocking(move || read_bundle(&dir.join("bundle.zip"), &dir))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| r);
+    let resp = match unpacked {
+        Ok(bundle) => restore(pool.get_ref(), bundle).await,
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+    let _ = tokio::fs::remove_dir_all(&staging).await;
+    Ok(resp)
+}
@@ -0,0 +1,227 @@
+use actix_web::{delete, get, web, HttpResponse, Responder};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use tokio_stream::StreamExt;
+use crate::ai::manager::StreamEvent;
+use crate::ai::provider::ChatMessage;
+use crate::ChatRequest;
+
+// --- CHAT CONVERSATIONS ---
+
+/// Stored turns replayed to the model when a conversation is resumed.
+const MAX_HISTORY: i64 = 40;
+const MAX_TITLE_CHARS: usize = 80;
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct ChatSession {
+    pub id: String,
+    pub task_id: Option<String>,
+    /// The first line of the opening message
+    pub title: String,
+    pub created_by: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub messages: i64,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct StoredMessage {
+    pub role: String,
+    pub content: String,
+    pub created_at: i64,
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS chat_sessions (
+            id TEXT PRIMARY KEY,
+            task_id TEXT,
+            title TEXT NOT NULL,
+            created_by TEXT,
+            created_at BIGINT NOT NULL,
+            updated_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS chat_messages (
+            id SERIAL PRIMARY KEY,
+            session_id TEXT NOT NULL REFERENCES chat_sessions(id) ON DELETE CASCADE,
+            role TEXT NOT NULL,
+            content TEXT NOT NULL,
+            created_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_chat_sessions_task ON chat_sessions (task_id)")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_chat_messages_session ON chat_messages (session_id, id)")
+        .execute(pool)
+        .await?;
+    println!("[CHAT] Database initialized (chat_sessions, chat_messages).");
+    Ok(())
+}
+
+async fn add_message(pool: &Pool<Postgres>, session_id: &str, role: &str, content: &str) -> Result<(), sqlx::Error> {
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query("INSERT INTO chat_messages (session_id, role, content, created_at) VALUES ($1, $2, $3, $4)")
+        .bind(session_id)
+        .bind(role)
+        .bind(content)
+        .bind(now)
+        .execute(pool)
+        .await?;
+    sqlx::query("UPDATE chat_sessions SET updated_at = $2 WHERE id = $1")
+        .bind(session_id)
+        .bind(now)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Loads or starts `req`'s conversation and stores the message; None for an unknown session.
+pub async fn open(pool: &Pool<Postgres>, req: &mut ChatRequest, created_by: Option<String>) -> Result<Option<String>, sqlx::Error> {
+    let session_id = match &req.session_id {
+        Some(id) => {
+            let Some(task_id) = sqlx::query_scalar::<_, Option<String>>("SELECT task_id FROM chat_sessions WHERE id = $1")
+                .bind(id)
+                .fetch_optional(pool)
+                .await?
+            else {
+                return Ok(None);
+            };
+            if task_id.is_some() {
+                req.task_id = task_id;
+            }
+            req.history = sqlx::query_as::<_, (String, String)>(
+                "SELECT role, content FROM (
+                    SELECT id, role, content FROM chat_messages WHERE session_id = $1 ORDER BY id DESC LIMIT $2
+                 ) recent ORDER BY id"
+            )
+            .bind(id)
+            .bind(MAX_HISTORY)
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .map(|(role, content)| ChatMessage { role, content })
+            .collect();
+            id.clone()
+        }
+        None => {
+            let id = uuid::Uuid::new_v4().to_string();
+            let title: String = req.message.trim().lines().next().unwrap_or_default().chars().take(MAX_TITLE_CHARS).collect();
+            let now = chrono::Utc::now().timestamp();
+            sqlx::query(
+                "INSERT INTO chat_sessions (id, task_id, title, created_by, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $5)"
+            )
+            .bind(&id)
+            .bind(&req.task_id)
+            .bind(title)
+            .bind(created_by)
+            .bind(now)
+            .execute(pool)
+            .await?;
+            for msg in &req.history {
+                add_message(pool, &id, &msg.role, &msg.content).await?;
+            }
+            id
+        }
+    };
+    add_message(pool, &session_id, "user", &req.message).await?;
+    Ok(Some(session_id))
+}
+
+/// Passes `stream` through, storing its Final answer in the session.
+pub fn record<S>(pool: Pool<Postgres>, session_id: String, stream: S) -> impl futures::Stream<Item = S::Item>
+where
+    S: futures::Stream<Item = Result<StreamEvent, Box<dyn std::error::Error + Send + Sync>>>,
+{
+    stream.map(move |event| {
+        if let Ok(StreamEvent::Final(answer)) = &event {
+            let pool = pool.clone();
+            let session_id = session_id.clone();
+            let answer = answer.clone();
+            tokio::spawn(async move {
+                if let Err(e) = add_message(&pool, &session_id, "assistant", &answer).await {
+                    println!("[CHAT] Failed to store answer for session {}: {}", session_id, e);
+                }
+            });
+        }
+        event
+    })
+}
+
+const SESSIONS: &str = "SELECT s.*, (SELECT COUNT(*) FROM chat_messages m WHERE m.session_id = s.id) AS messages FROM chat_sessions s";
+
+#[utoipa::path(tag = "ai", responses((status = 200, description = "The task's conversations, latest first")))]
+#[get("/tasks/{id}/chat-sessions")]
+pub async fn list_task_sessions(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    let sessions = sqlx::query_as::<_, ChatSession>(&format!("{} WHERE s.task_id = $1 ORDER BY s.updated_at DESC", SESSIONS))
+        .bind(path.into_inner())
+        .fetch_all(pool.get_ref())
+        .await;
+    match sessions {
+        Ok(sessions) => HttpResponse::Ok().json(sessions),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "ai", responses((status = 200, description = "Conversations about no task, latest first")))]
+#[get("/chat-sessions")]
+pub async fn list_sessions(pool: web::Data<Pool<Postgres>>) -> impl Responder {
+    let sessions = sqlx::query_as::<_, ChatSession>(&format!("{} WHERE s.task_id IS NULL ORDER BY s.updated_at DESC LIMIT 100", SESSIONS))
+        .fetch_all(pool.get_ref())
+        .await;
+    match sessions {
+        Ok(sessions) => HttpResponse::Ok().json(sessions),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "ai", responses(
+    (status = 200, description = "The conversation with all of its messages"),
+    (status = 404, description = "No such conversation"),
+))]
+#[get("/chat-sessions/{id}")]
+pub async fn get_session(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    let id = path.into_inner();
+    let session = match sqlx::query_as::<_, ChatSession>(&format!("{} WHERE s.id = $1", SESSIONS))
+        .bind(&id)
+        .fetch_optional(pool.get_ref())
+        .await
+    {
+        Ok(Some(session)) => session,
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Chat session not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    match sqlx::query_as::<_, StoredMessage>("SELECT role, content, created_at FROM chat_messages WHERE session_id = $1 ORDER BY id")
+        .bind(&id)
+        .fetch_all(pool.get_ref())
+        .await
+    {
+        Ok(messages) => HttpResponse::Ok().json(serde_json::json!({ "session": session, "messages": messages })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "ai", responses(
+    (status = 200, description = "Deleted"),
+    (status = 404, description = "No such conversation"),
+))]
+#[delete("/chat-sessions/{id}")]
+pub async fn delete_session(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    match sqlx::query("DELETE FROM chat_sessions WHERE id = $1")
+        .bind(path.into_inner())
+        .execute(pool.get_ref())
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": "Chat session not found" }))
+        }
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "status": "deleted" })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
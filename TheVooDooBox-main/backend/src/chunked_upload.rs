@@ -0,0 +1,464 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::task_queue::{self, TaskScheduler};
+use crate::{analysis_profiles, archive, limits, network_policy, virustotal};
+
+// --- RESUMABLE UPLOADS ---
+// Resumable sample uploads in hashed chunks.
+
+const DEFAULT_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+const MIN_CHUNK_SIZE: u64 = 1024 * 1024;
+const MAX_CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+const DEFAULT_TTL_HOURS: i64 = 24;
+const CHUNK_DIR: &str = "./upload_chunks";
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS upload_sessions (
+            id TEXT PRIMARY KEY,
+            filename TEXT NOT NULL,
+            size BIGINT NOT NULL,
+            chunk_size BIGINT NOT NULL,
+            sha256 TEXT,
+            options JSONB NOT NULL,
+            received INTEGER[] NOT NULL DEFAULT '{}',
+            created_by TEXT,
+            created_at BIGINT NOT NULL,
+            updated_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+fn ttl_hours() -> i64 {
+    std::env::var("UPLOAD_SESSION_TTL_HOURS")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|h| *h > 0)
+        .unwrap_or(DEFAULT_TTL_HOURS)
+}
+
+/// Submission settings, as the multipart form takes them.
+#[derive(Serialize, Deserialize, Default, utoipa::ToSchema)]
+pub struct UploadOptions {
+    /// Minutes.
+    pub analysis_duration: Option<u64>,
+    pub vmid: Option<u64>,
+    pub node: Option<String>,
+    /// quick | deep | static
+    pub analysis_mode: Option<String>,
+    /// urgent | normal | bulk
+    pub priority: Option<String>,
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub compare_profiles: Vec<String>,
+    /// full | fake-net | blocked | vpn
+    pub internet_policy: Option<String>,
+    #[serde(default)]
+    pub reuse: bool,
+    #[serde(default)]
+    pub force: bool,
+    pub archive_password: Option<String>,
+    pub archive_member: Option<String>,
+    pub extract_archives: Option<bool>,
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct UploadRequest {
+    pub filename: String,
+    /// Total bytes.
+    pub size: u64,
+    /// Of the whole file; checked on completion when given.
+    pub sha256: Option<String>,
+    /// Bytes per chunk (1-64 MB, default 8 MB); every chunk but the last has exactly this size.
+    pub chunk_size: Option<u64>,
+    #[serde(flatten)]
+    pub options: UploadOptions,
+}
+
+#[derive(sqlx::FromRow)]
+struct UploadSession {
+    id: String,
+    filename: String,
+    size: i64,
+    chunk_size: i64,
+    sha256: Option<String>,
+    options: serde_json::Value,
+    received: Vec<i32>,
+    updated_at: i64,
+}
+
+impl UploadSession {
+    fn chunk_count(&self) -> i64 {
+        (self.size + self.chunk_size - 1) / self.chunk_size
+    }
+
+    /// Exact length chunk `index` must have.
+    fn chunk_len(&self, index: i64) -> i64 {
+        (self.size - index * self.chunk_size).min(self.chunk_size)
+    }
+
+    fn missing(&self) -> Vec<i64> {
+        (0..self.chunk_count()).filter(|i| !self.received.contains(&(*i as i32))).collect()
+    }
+
+    fn status(&self) -> serde_json::Value {
+        let missing = self.missing();
+        serde_json::json!({
+            "upload_id": self.id,
+            "filename": self.filename,
+            "size": self.size,
+            "chunk_size": self.chunk_size,
+            "chunk_count": self.chunk_count(),
+            "received": self.received.len(),
+            "missing": missing.iter().take(1000).collect::<Vec<_>>(),
+            "complete": missing.is_empty(),
+            "expires_at": self.updated_at + ttl_hours() * 3_600_000,
+        })
+    }
+}
+
+fn chunk_path(upload_id: &str, index: i64) -> PathBuf {
+    PathBuf::from(CHUNK_DIR).join(upload_id).join(index.to_string())
+}
+
+async fn load(pool: &Pool<Postgres>, id: &str) -> Result<UploadSession, HttpResponse> {
+    match sqlx::query_as::<_, UploadSession>("SELECT * FROM upload_sessions WHERE id = $1")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(Some(s)) => Ok(s),
+        Ok(None) => Err(HttpResponse::NotFound().json(serde_json::json!({ "error": "Upload not found or expired" }))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() }))),
+    }
+}
+
+async fn discard(pool: &Pool<Postgres>, id: &str) {
+    let _ = sqlx::query("DELETE FROM upload_sessions WHERE id = $1").bind(id).execute(pool).await;
+    let _ = tokio::fs::remove_dir_all(PathBuf::from(CHUNK_DIR).join(id)).await;
+}
+
+/// Hourly sweep of sessions nobody has touched within the TTL.
+pub async fn start(pool: Pool<Postgres>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(3600));
+    loop {
+        interval.tick().await;
+        let cutoff = chrono::Utc::now().timestamp_millis() - ttl_hours() * 3_600_000;
+        let stale: Vec<String> = sqlx::query_scalar("SELECT id FROM upload_sessions WHERE updated_at < $1")
+            .bind(cutoff)
+            .fetch_all(&pool)
+            .await
+            .unwrap_or_default();
+        for id in &stale {
+            discard(&pool, id).await;
+        }
+        if !stale.is_empty() {
+            println!("[UPLOAD] Swept {} abandoned upload(s).", stale.len());
+        }
+    }
+}
+
+#[utoipa::path(tag = "submission", request_body = UploadRequest, responses(
+    (status = 200, description = "Upload session opened"),
+    (status = 400, description = "Invalid settings or chunk size"),
+    (status = 413, description = "Declared size over MAX_SAMPLE_UPLOAD_MB"),
+))]
+#[post("/submissions/uploads")]
+pub async fn create_upload(
+    http_req: HttpRequest,
+    pool: web::Data<Pool<Postgres>>,
+    req: web::Json<UploadRequest>,
+) -> impl Responder {
+    let req = req.into_inner();
+    let filename = req.filename.replace("..", "").replace(['/', '\\'], "");
+    if filename.trim().is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "filename is required" }));
+    }
+    if req.size == 0 {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "size must be greater than zero" }));
+    }
+    if let Err(resp) = limits::UploadLimit::sample().add(req.size as usize) {
+        return resp;
+    }
+    let chunk_size = req.chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE);
+    if !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&chunk_size) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "chunk_size must be between 1 MB and 64 MB" }));
+    }
+    let sha256 = req.sha256.as_deref().map(|h| h.trim().to_lowercase());
+    if sha256.as_ref().is_some_and(|h| h.len() != 64 || !h.chars().all(|c| c.is_ascii_hexdigit())) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "sha256 must be 64 hex characters" }));
+    }
+    // Refuse bad settings now rather than after the last chunk
+    let opts = &req.options;
+    if opts.priority.as_deref().is_some_and(|p| task_queue::parse_priority(p).is_none()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "priority must be urgent, normal or bulk" }));
+    }
+    if opts.internet_policy.as_deref().is_some_and(|p| network_policy::parse_policy(p).is_none()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "internet_policy must be full, fake-net, blocked or vpn" }));
+    }
+    if let Err(e) = analysis_profiles::resolve(pool.get_ref(), opts.profile.as_deref()).await {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().timestamp_millis();
+    let result = sqlx::query_as::<_, UploadSession>(
+        "INSERT INTO upload_sessions (id, filename, size, chunk_size, sha256, options, created_by, created_at, updated_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $8) RETURNING *"
+    )
+    .bind(&id)
+    .bind(&filename)
+    .bind(req.size as i64)
+    .bind(chunk_size as i64)
+    .bind(&sha256)
+    .bind(serde_json::to_value(&req.options).unwrap_or_default())
+    .bind(crate::auth::current_user(&http_req).map(|u| u.username))
+    .bind(now)
+    .fetch_one(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(session) => {
+            let _ = tokio::fs::create_dir_all(PathBuf::from(CHUNK_DIR).join(&id)).await;
+            println!("[UPLOAD] Upload {} opened for {} ({} bytes in {} chunks).", id, filename, req.size, session.chunk_count());
+            HttpResponse::Ok().json(session.status())
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "submission", responses(
+    (status = 200, description = "Received and missing chunks"),
+    (status = 404, description = "Upload not found or expired"),
+))]
+#[get("/submissions/uploads/{id}")]
+pub async fn get_upload(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    match load(pool.get_ref(), &path.into_inner()).await {
+        Ok(session) => HttpResponse::Ok().json(session.status()),
+        Err(resp) => resp,
+    }
+}
+
+/// One chunk as the raw request body. Sending a chunk again replaces it.
+#[utoipa::path(tag = "submission", request_body(content = Vec<u8>, content_type = "application/octet-stream"), params(
+    ("X-Chunk-SHA256" = String, Header, description = "SHA-256 of this chunk"),
+), responses(
+    (status = 200, description = "Chunk stored"),
+    (status = 400, description = "Bad index, length or missing hash header"),
+    (status = 404, description = "Upload not found or expired"),
+    (status = 422, description = "Chunk does not match its hash; send it again"),
+))]
+#[post("/submissions/uploads/{id}/chunks/{index}")]
+pub async fn upload_chunk(
+    req: HttpRequest,
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<(String, i64)>,
+    mut body: web::Payload,
+) -> impl Responder {
+    let (id, index) = path.into_inner();
+    let session = match load(pool.get_ref(), &id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    if index < 0 || index >= session.chunk_count() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("index must be 0-{}", session.chunk_count() - 1) }));
+    }
+    let Some(expected_hash) = req.headers().get("X-Chunk-SHA256").and_then(|v| v.to_str().ok()).map(|v| v.trim().to_lowercase()) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "X-Chunk-SHA256 header required" }));
+    };
+    let expected_len = session.chunk_len(index);
+
+    let final_path = chunk_path(&id, index);
+    let part_path = final_path.with_extension("part");
+    if let Some(dir) = final_path.parent() {
+        let _ = tokio::fs::create_dir_all(dir).await;
+    }
+    let mut f = match tokio::fs::File::create(&part_path).await {
+        Ok(f) => f,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    let mut hasher = Sha256::new();
+    let mut written: i64 = 0;
+    while let Some(chunk) = body.next().await {
+        let Ok(chunk) = chunk else {
+            let _ = tokio::fs::remove_file(&part_path).await;
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Connection dropped mid-chunk; send it again" }));
+        };
+        written += chunk.len() as i64;
+        if written > expected_len {
+            drop(f);
+            let _ = tokio::fs::remove_file(&part_path).await;
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("chunk {} must be {} bytes", index, expected_len) }));
+        }
+        if let Err(e) = f.write_all(&chunk).await {
+            let _ = tokio::fs::remove_file(&part_path).await;
+            return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() }));
+        }
+        hasher.update(&chunk);
+    }
+    let _ = f.flush().await;
+    drop(f);
+
+    if written != expected_len {
+        let _ = tokio::fs::remove_file(&part_path).await;
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("chunk {} must be {} bytes, got {}", index, expected_len, written) }));
+    }
+    let actual_hash = format!("{:x}", hasher.finalize());
+    if actual_hash != expected_hash {
+        let _ = tokio::fs::remove_file(&part_path).await;
+        return HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": format!("chunk {} does not match its SHA-256; send it again", index),
+            "expected": expected_hash,
+            "actual": actual_hash,
+        }));
+    }
+    if let Err(e) = tokio::fs::rename(&part_path, &final_path).await {
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() }));
+    }
+
+    let updated = sqlx::query_as::<_, UploadSession>(
+        "UPDATE upload_sessions SET
+            received = CASE WHEN $2 = ANY(received) THEN received ELSE array_append(received, $2) END,
+            updated_at = $3
+         WHERE id = $1 RETURNING *"
+    )
+    .bind(&id)
+    .bind(index as i32)
+    .bind(chrono::Utc::now().timestamp_millis())
+    .fetch_one(pool.get_ref())
+    .await;
+    match updated {
+        Ok(session) => HttpResponse::Ok().json(session.status()),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Joins the chunks into ./uploads and returns the whole file's SHA-256.
+async fn assemble(session: &UploadSession, dest: &PathBuf) -> Result<String, String> {
+    let mut out = tokio::fs::File::create(dest).await.map_err(|e| e.to_string())?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 256 * 1024];
+    for index in 0..session.chunk_count() {
+        let mut chunk = tokio::fs::File::open(chunk_path(&session.id, index)).await
+            .map_err(|e| format!("chunk {}: {}", index, e))?;
+        loop {
+            let n = chunk.read(&mut buf).await.map_err(|e| e.to_string())?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            out.write_all(&buf[..n]).await.map_err(|e| e.to_string())?;
+        }
+    }
+    out.flush().await.map_err(|e| e.to_string())?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Assembles the file and submits it exactly like a multipart upload with the same settings.
+#[utoipa::path(tag = "submission", responses(
+    (status = 200, description = "Submitted; same response as /vms/actions/submit"),
+    (status = 404, description = "Upload not found or expired"),
+    (status = 409, description = "Chunks still missing"),
+    (status = 422, description = "Assembled file does not match the declared SHA-256"),
+))]
+#[post("/submissions/uploads/{id}/complete")]
+pub async fn complete_upload(
+    pool: web::Data<Pool<Postgres>>,
+    scheduler: web::Data<Arc<TaskScheduler>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let session = match load(pool.get_ref(), &id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    let missing = session.missing();
+    if !missing.is_empty() {
+        return HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("{} chunk(s) still missing", missing.len()),
+            "missing": missing.iter().take(1000).collect::<Vec<_>>(),
+        }));
+    }
+
+    let _ = std::fs::create_dir_all("./uploads");
+    let dest = PathBuf::from("./uploads").join(&session.filename);
+    let sha256 = match assemble(&session, &dest).await {
+        Ok(h) => h,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&dest).await;
+            return HttpResponse::InternalServerError().json(serde_json::json!({ "error": format!("Could not assemble upload: {}", e) }));
+        }
+    };
+    if let Some(expected) = session.sha256.as_deref().filter(|h| *h != sha256) {
+        let _ = tokio::fs::remove_file(&dest).await;
+        return HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": "Assembled file does not match the declared SHA-256",
+            "expected": expected,
+            "actual": sha256,
+        }));
+    }
+    discard(pool.get_ref(), &id).await;
+    println!("[UPLOAD] Upload {} assembled: {} ({}).", id, session.filename, sha256);
+
+    let vt_pool = pool.get_ref().clone();
+    let vt_hash = sha256.clone();
+    actix_web::rt::spawn(async move {
+        let _ = virustotal::get_cached_or_fetch(&vt_pool, &vt_hash).await;
+    });
+
+    let opts: UploadOptions = serde_json::from_value(session.options).unwrap_or_default();
+    let mut compare_profiles: Vec<String> = opts.compare_profiles.iter().map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect();
+    compare_profiles.sort();
+    compare_profiles.dedup();
+    let options = crate::SubmitOptions {
+        settings: analysis_profiles::SubmissionSettings {
+            duration_seconds: opts.analysis_duration.map(|m| m * 60),
+            analysis_mode: opts.analysis_mode.map(|m| m.trim().to_lowercase())
+                .filter(|m| m == "quick" || m == "deep" || m == crate::static_only::MODE),
+            priority: opts.priority.as_deref().and_then(task_queue::parse_priority),
+            internet_policy: opts.internet_policy.as_deref().and_then(network_policy::parse_policy),
+            vmid: opts.vmid,
+            node: opts.node,
+            ..Default::default()
+        },
+        profile_name: opts.profile,
+        compare_profiles,
+        reuse: opts.reuse,
+        force: opts.force,
+        archive_password: opts.archive_password.unwrap_or_else(|| archive::DEFAULT_PASSWORD.to_string()),
+        archive_member: opts.archive_member.filter(|m| !m.is_empty()),
+        extract_archives: opts.extract_archives.unwrap_or(true),
+    };
+    let upload = crate::SampleUpload { filename: session.filename.clone(), original_filename: session.filename, sha256 };
+    crate::finish_submission(pool.get_ref(), scheduler.get_ref(), upload, options).await
+}
+
+#[utoipa::path(tag = "submission", responses(
+    (status = 200, description = "Upload discarded"),
+    (status = 404, description = "Upload not found or expired"),
+))]
+#[post("/submissions/uploads/{id}/cancel")]
+pub async fn cancel_upload(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let id = path.into_inner();
+    if let Err(resp) = load(pool.get_ref(), &id).await {
+        return resp;
+    }
+    discard(pool.get_ref(), &id).await;
+    HttpResponse::Ok().json(serde_json::json!({ "status": "cancelled", "upload_id": id }))
+}
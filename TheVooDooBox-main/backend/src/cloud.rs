@@ -0,0 +1,505 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::error::Error;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use crate::hypervisor::{Hypervisor, Node, Snapshot, Vm, VncTicket};
+use crate::task_queue::AssignedVm;
+
+// --- CLOUD BURST SANDBOXES ---
+// Throwaway cloud instances when the local pool is exhausted (CLOUD_PROVIDER=aws|azure).
+
+/// Cloud slots get synthetic VM IDs far above anything the local backends report.
+pub const CLOUD_VMID_BASE: u64 = 9_000_000_000;
+
+/// Cloud guests boot from cold, so the agent gets longer than a local revert-and-start.
+const DEFAULT_AGENT_TIMEOUT_SECS: u64 = 600;
+
+pub fn is_cloud_vmid(vmid: u64) -> bool {
+    vmid >= CLOUD_VMID_BASE
+}
+
+pub struct CloudInstance {
+    pub id: String,
+    pub name: String,
+}
+
+#[async_trait(?Send)]
+pub trait CloudProvider: Send + Sync {
+    fn kind(&self) -> &'static str;
+
+    /// The pre-baked image every instance is launched from.
+    fn image(&self) -> &str;
+
+    async fn launch(&self, name: &str) -> Result<CloudInstance, Box<dyn Error>>;
+
+    async fn terminate(&self, instance_id: &str) -> Result<(), Box<dyn Error>>;
+}
+
+/// Burst configuration held by the scheduler.
+pub struct CloudBurst {
+    provider: Arc<dyn CloudProvider>,
+    max_instances: u64,
+    os_profile: String,
+    agent_timeout_secs: u64,
+}
+
+impl CloudBurst {
+    /// None unless CLOUD_PROVIDER is set. Panics on missing provider credentials, like the rest of startup.
+    pub fn from_env() -> Option<Self> {
+        let provider: Arc<dyn CloudProvider> = match std::env::var("CLOUD_PROVIDER").ok()?.to_lowercase().as_str() {
+            "aws" | "ec2" => Arc::new(AwsProvider::from_env()),
+            "azure" => Arc::new(AzureProvider::from_env()),
+            "" | "none" => return None,
+            other => {
+                println!("[CLOUD] Unknown CLOUD_PROVIDER '{}'; cloud burst disabled.", other);
+                return None;
+            }
+        };
+        let env_u64 = |key: &str, default: u64| {
+            std::env::var(key).ok().and_then(|v| v.trim().parse().ok()).unwrap_or(default)
+        };
+        let burst = Self {
+            max_instances: env_u64("CLOUD_MAX_INSTANCES", 2),
+            os_profile: std::env::var("CLOUD_OS_PROFILE").unwrap_or_else(|_| "windows10".to_string()),
+            agent_timeout_secs: env_u64("CLOUD_AGENT_TIMEOUT", DEFAULT_AGENT_TIMEOUT_SECS),
+            provider,
+        };
+        println!(
+            "[CLOUD] Burst enabled: {} image {} (profile {}, up to {} instance(s)).",
+            burst.provider.kind(), burst.provider.image(), burst.os_profile, burst.max_instances
+        );
+        Some(burst)
+    }
+
+    pub fn kind(&self) -> &'static str {
+        self.provider.kind()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.max_instances as usize
+    }
+
+    /// Whether a job asking for `os_profile` (None = any) may run on the cloud image.
+    pub fn serves(&self, os_profile: Option<&str>) -> bool {
+        os_profile.is_none_or(|p| p == self.os_profile)
+    }
+
+    /// First cloud slot not present in `busy`, if under the instance cap.
+    pub fn free_slot(&self, busy: &HashSet<u64>) -> Option<u64> {
+        (CLOUD_VMID_BASE..CLOUD_VMID_BASE + self.max_instances).find(|v| !busy.contains(v))
+    }
+
+    /// Launches the instance for `task_id` and wraps it so orchestration can drive it like any other sandbox.
+    pub async fn launch(&self, task_id: &str, slot: u64) -> Result<(Arc<EphemeralVm>, AssignedVm), Box<dyn Error>> {
+        let name = format!("voodoobox-{}", task_id);
+        let instance = self.provider.launch(&name).await?;
+        println!("[CLOUD] Launched {} instance {} ({}) for task {}", self.provider.kind(), instance.id, instance.name, task_id);
+
+        let vm = AssignedVm {
+            vmid: slot,
+            node: self.provider.kind().to_string(),
+            name: instance.name.clone(),
+            snapshot: self.provider.image().to_string(),
+            agent_timeout_secs: self.agent_timeout_secs,
+            preflight_secs: None,
+        };
+        let ephemeral = Arc::new(EphemeralVm {
+            provider: self.provider.clone(),
+            vmid: slot,
+            instance,
+            terminated: Mutex::new(false),
+        });
+        Ok((ephemeral, vm))
+    }
+
+    /// Tears down an instance recorded before a restart.
+    pub async fn terminate_orphan(&self, instance_id: &str) {
+        match self.provider.terminate(instance_id).await {
+            Ok(()) => println!("[CLOUD] Terminated orphaned instance {}", instance_id),
+            Err(e) => println!("[CLOUD] Warning: Failed to terminate orphaned instance {}: {}", instance_id, e),
+        }
+    }
+}
+
+/// A launched instance as a single-VM hypervisor; power-off terminates, revert is a no-op.
+pub struct EphemeralVm {
+    provider: Arc<dyn CloudProvider>,
+    vmid: u64,
+    pub instance: CloudInstance,
+    terminated: Mutex<bool>,
+}
+
+impl EphemeralVm {
+    /// Idempotent; the scheduler calls it after every run whatever happened inside.
+    pub async fn terminate(&self) -> Result<(), Box<dyn Error>> {
+        let mut terminated = self.terminated.lock().await;
+        if *terminated {
+            return Ok(());
+        }
+        self.provider.terminate(&self.instance.id).await?;
+        *terminated = true;
+        println!("[CLOUD] Terminated {} instance {}", self.provider.kind(), self.instance.id);
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl Hypervisor for EphemeralVm {
+    fn kind(&self) -> &'static str {
+        self.provider.kind()
+    }
+
+    async fn get_nodes(&self) -> Result<Vec<Node>, Box<dyn Error>> {
+        Ok(vec![Node { node: self.provider.kind().to_string(), status: "online".to_string(), maxcpu: None, maxmem: None }])
+    }
+
+    async fn get_vms(&self, _node: &str) -> Result<Vec<Vm>, Box<dyn Error>> {
+        let status = if *self.terminated.lock().await { "stopped" } else { "running" };
+        Ok(vec![Vm { vmid: self.vmid, name: Some(self.instance.name.clone()), status: status.to_string(), cpus: None, maxmem: None }])
+    }
+
+    async fn vm_action(&self, _node: &str, _vmid: u64, action: &str) -> Result<(), Box<dyn Error>> {
+        match action {
+            // Instances boot as part of launch
+            "start" | "resume" => Ok(()),
+            "stop" | "shutdown" => self.terminate().await,
+            other => Err(format!("Unsupported action '{}' on a cloud instance", other).into()),
+        }
+    }
+
+    async fn rollback_snapshot(&self, _node: &str, _vmid: u64, _snapshot: &str) -> Result<(), Box<dyn Error>> {
+        // Fresh from the image every time
+        Ok(())
+    }
+
+    async fn list_snapshots(&self, _node: &str, _vmid: u64) -> Result<Vec<Snapshot>, Box<dyn Error>> {
+        Ok(vec![Snapshot { name: self.provider.image().to_string(), description: None, snaptime: None, parent: None }])
+    }
+
+    async fn create_vnc_proxy(&self, _node: &str, _vmid: u64) -> Result<VncTicket, Box<dyn Error>> {
+        Err("Consoles are not available for cloud burst instances".into())
+    }
+}
+
+/// First `<tag>value</tag>` in an EC2 XML response.
+fn xml_value(xml: &str, tag: &str) -> Option<String> {
+    crate::storage::xml_values(xml, tag).into_iter().next()
+}
+
+// --- AWS EC2 ---
+// Query API signed with SigV4, so no SDK is needed.
+
+pub struct AwsProvider {
+    region: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+    /// AWS_SANDBOX_AMI
+    ami: String,
+    instance_type: String,
+    subnet_id: Option<String>,
+    security_group_id: Option<String>,
+    http: Client,
+}
+
+impl AwsProvider {
+    pub fn from_env() -> Self {
+        Self {
+            region: std::env::var("AWS_REGION").expect("AWS_REGION must be set"),
+            access_key: std::env::var("AWS_ACCESS_KEY_ID").expect("AWS_ACCESS_KEY_ID must be set"),
+            secret_key: std::env::var("AWS_SECRET_ACCESS_KEY").expect("AWS_SECRET_ACCESS_KEY must be set"),
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+            ami: std::env::var("AWS_SANDBOX_AMI").expect("AWS_SANDBOX_AMI must be set"),
+            instance_type: std::env::var("AWS_INSTANCE_TYPE").unwrap_or_else(|_| "t3.medium".to_string()),
+            subnet_id: std::env::var("AWS_SUBNET_ID").ok(),
+            security_group_id: std::env::var("AWS_SECURITY_GROUP_ID").ok(),
+            http: Client::builder().timeout(std::time::Duration::from_secs(30)).build().unwrap(),
+        }
+    }
+
+    async fn call(&self, params: &[(&str, String)]) -> Result<String, Box<dyn Error>> {
+        let host = format!("ec2.{}.amazonaws.com", self.region);
+        let mut body = "Version=2016-11-15".to_string();
+        for (k, v) in params {
+            body.push_str(&format!("&{}={}", k, urlencoding::encode(v)));
+        }
+
+        let amz_date = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let content_type = "application/x-www-form-urlencoded; charset=utf-8";
+        let mut headers = vec![
+            ("content-type", content_type.to_string()),
+            ("host", host.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let payload_hash = format!("{:x}", Sha256::digest(body.as_bytes()));
+        let signature = crate::storage::sigv4_signature(
+            &self.secret_key, &self.region, "ec2", &amz_date, "POST", "/", "", &headers, &payload_hash,
+        );
+
+        let mut req = self.http.post(format!("https://{}/", host))
+            .header("content-type", content_type)
+            .header("x-amz-date", &amz_date)
+            .header("authorization", format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}/{}/ec2/aws4_request, SignedHeaders={}, Signature={}",
+                self.access_key, &amz_date[..8], self.region,
+                headers.iter().map(|(k, _)| *k).collect::<Vec<_>>().join(";"), signature
+            ));
+        if let Some(token) = &self.session_token {
+            req = req.header("x-amz-security-token", token);
+        }
+        let resp = req.body(body).send().await?;
+        let status = resp.status();
+        let text = resp.text().await.unwrap_or_default();
+        if !status.is_success() {
+            let message = xml_value(&text, "Message").unwrap_or(text);
+            return Err(format!("EC2 API Error {}: {}", status, message).into());
+        }
+        Ok(text)
+    }
+}
+
+#[async_trait(?Send)]
+impl CloudProvider for AwsProvider {
+    fn kind(&self) -> &'static str {
+        "aws"
+    }
+
+    fn image(&self) -> &str {
+        &self.ami
+    }
+
+    async fn launch(&self, name: &str) -> Result<CloudInstance, Box<dyn Error>> {
+        let mut params = vec![
+            ("Action", "RunInstances".to_string()),
+            ("ImageId", self.ami.clone()),
+            ("InstanceType", self.instance_type.clone()),
+            ("MinCount", "1".to_string()),
+            ("MaxCount", "1".to_string()),
+            // Guest-initiated shutdown must not leave a stopped instance behind
+            ("InstanceInitiatedShutdownBehavior", "terminate".to_string()),
+            ("TagSpecification.1.ResourceType", "instance".to_string()),
+            ("TagSpecification.1.Tag.1.Key", "Name".to_string()),
+            ("TagSpecification.1.Tag.1.Value", name.to_string()),
+            ("TagSpecification.1.Tag.2.Key", "voodoobox".to_string()),
+            ("TagSpecification.1.Tag.2.Value", "sandbox".to_string()),
+        ];
+        if let Some(subnet) = &self.subnet_id {
+            params.push(("SubnetId", subnet.clone()));
+        }
+        if let Some(sg) = &self.security_group_id {
+            params.push(("SecurityGroupId.1", sg.clone()));
+        }
+
+        let xml = self.call(&params).await?;
+        let id = xml_value(&xml, "instanceId").ok_or("RunInstances response had no instanceId")?;
+        Ok(CloudInstance { id, name: name.to_string() })
+    }
+
+    async fn terminate(&self, instance_id: &str) -> Result<(), Box<dyn Error>> {
+        self.call(&[
+            ("Action", "TerminateInstances".to_string()),
+            ("InstanceId.1", instance_id.to_string()),
+        ]).await?;
+        Ok(())
+    }
+}
+
+// --- AZURE ---
+// ARM REST with a service principal. The VM is created with its NIC and OS disk set to
+// delete alongside it, so terminating is a single DELETE.
+
+const ARM: &str = "https://management.azure.com";
+const ARM_NETWORK_API: &str = "2023-09-01";
+const ARM_COMPUTE_API: &str = "2024-03-01";
+
+pub struct AzureProvider {
+    tenant_id: String,
+    client_id: String,
+    client_secret: String,
+    subscription_id: String,
+    resource_group: String,
+    location: String,
+    /// AZURE_IMAGE_ID: managed image or gallery image version resource ID.
+    image_id: String,
+    subnet_id: String,
+    vm_size: String,
+    /// Only for generalized images; specialized (snapshot-derived) images keep their own account.
+    admin_password: Option<String>,
+    http: Client,
+    token: Mutex<Option<(String, std::time::Instant)>>,
+}
+
+#[derive(serde::Deserialize)]
+struct AzureToken {
+    access_token: String,
+    expires_in: u64,
+}
+
+impl AzureProvider {
+    pub fn from_env() -> Self {
+        let var = |key: &str| std::env::var(key).unwrap_or_else(|_| panic!("{} must be set", key));
+        Self {
+            tenant_id: var("AZURE_TENANT_ID"),
+            client_id: var("AZURE_CLIENT_ID"),
+            client_secret: var("AZURE_CLIENT_SECRET"),
+            subscription_id: var("AZURE_SUBSCRIPTION_ID"),
+            resource_group: var("AZURE_RESOURCE_GROUP"),
+            location: var("AZURE_LOCATION"),
+            image_id: var("AZURE_IMAGE_ID"),
+            subnet_id: var("AZURE_SUBNET_ID"),
+            vm_size: std::env::var("AZURE_VM_SIZE").unwrap_or_else(|_| "Standard_D2s_v3".to_string()),
+            admin_password: std::env::var("AZURE_ADMIN_PASSWORD").ok(),
+            http: Client::builder().timeout(std::time::Duration::from_secs(60)).build().unwrap(),
+            token: Mutex::new(None),
+        }
+    }
+
+    async fn token(&self) -> Result<String, Box<dyn Error>> {
+        let mut cached = self.token.lock().await;
+        if let Some((token, expires)) = cached.as_ref() {
+            if std::time::Instant::now() < *expires {
+                return Ok(token.clone());
+            }
+        }
+        let resp = self.http.post(format!("https://login.microsoftonline.com/{}/oauth2/v2.0/token", self.tenant_id))
+            .form(&[
+                ("grant_type", "client_credentials"),
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.client_secret.as_str()),
+                ("scope", "https://management.azure.com/.default"),
+            ])
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(format!("Azure login failed: {}", resp.status()).into());
+        }
+        let token: AzureToken = resp.json().await?;
+        // Refresh a minute early
+        let expires = std::time::Instant::now() + std::time::Duration::from_secs(token.expires_in.saturating_sub(60));
+        *cached = Some((token.access_token.clone(), expires));
+        Ok(token.access_token)
+    }
+
+    fn resource(&self, provider: &str, name: &str) -> String {
+        format!(
+            "/subscriptions/{}/resourceGroups/{}/providers/{}/{}",
+            self.subscription_id, self.resource_group, provider, name
+        )
+    }
+
+    async fn arm(&self, method: reqwest::Method, id: &str, api: &str, body: Option<serde_json::Value>) -> Result<serde_json::Value, Box<dyn Error>> {
+        let sep = if id.contains('?') { '&' } else { '?' };
+        let mut req = self.http.request(method, format!("{}{}{}api-version={}", ARM, id, sep, api))
+            .bearer_auth(self.token().await?);
+        if let Some(b) = body {
+            req = req.json(&b);
+        }
+        let resp = req.send().await?;
+        let status = resp.status();
+        let text = resp.text().await.unwrap_or_default();
+        if !status.is_success() {
+            return Err(format!("Azure API Error {}: {}", status, text).into());
+        }
+        Ok(serde_json::from_str(&text).unwrap_or(serde_json::Value::Null))
+    }
+
+    async fn wait_provisioned(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        for _ in 0..60 {
+            let vm = self.arm(reqwest::Method::GET, id, ARM_COMPUTE_API, None).await?;
+            match vm["properties"]["provisioningState"].as_str() {
+                Some("Succeeded") => return Ok(()),
+                Some("Failed") => return Err(format!("Azure VM {} failed to provision", id).into()),
+                _ => tokio::time::sleep(std::time::Duration::from_secs(5)).await,
+            }
+        }
+        Err(format!("Azure VM {} was not provisioned within 300s", id).into())
+    }
+}
+
+#[async_trait(?Send)]
+impl CloudProvider for AzureProvider {
+    fn kind(&self) -> &'static str {
+        "azure"
+    }
+
+    fn image(&self) -> &str {
+        &self.image_id
+    }
+
+    async fn launch(&self, name: &str) -> Result<CloudInstance, Box<dyn Error>> {
+        let nic_id = self.resource("Microsoft.Network", &format!("networkInterfaces/{}-nic", name));
+        self.arm(reqwest::Method::PUT, &nic_id, ARM_NETWORK_API, Some(serde_json::json!({
+            "location": self.location,
+            "tags": { "voodoobox": "sandbox" },
+            "properties": {
+                "ipConfigurations": [{
+                    "name": "ipconfig1",
+                    "properties": {
+                        "subnet": { "id": self.subnet_id },
+                        "privateIPAllocationMethod": "Dynamic"
+                    }
+                }]
+            }
+        }))).await?;
+
+        // Windows computer names: at most 15 characters and not all digits
+        let tail: String = name.chars().rev().take(13).collect::<Vec<_>>().into_iter().rev().collect();
+        let computer_name = format!("vb{}", tail);
+        let mut properties = serde_json::json!({
+            "hardwareProfile": { "vmSize": self.vm_size },
+            "storageProfile": {
+                "imageReference": { "id": self.image_id },
+                "osDisk": {
+                    "createOption": "FromImage",
+                    "deleteOption": "Delete",
+                    "managedDisk": { "storageAccountType": "StandardSSD_LRS" }
+                }
+            },
+            "networkProfile": {
+                "networkInterfaces": [{ "id": nic_id, "properties": { "deleteOption": "Delete" } }]
+            }
+        });
+        if let Some(password) = &self.admin_password {
+            properties["osProfile"] = serde_json::json!({
+                "computerName": computer_name,
+                "adminUsername": "voodoo",
+                "adminPassword": password
+            });
+        }
+
+        let vm_id = self.resource("Microsoft.Compute", &format!("virtualMachines/{}", name));
+        if let Err(e) = self.arm(reqwest::Method::PUT, &vm_id, ARM_COMPUTE_API, Some(serde_json::json!({
+            "location": self.location,
+            "tags": { "voodoobox": "sandbox" },
+            "properties": properties
+        }))).await {
+            let _ = self.arm(reqwest::Method::DELETE, &nic_id, ARM_NETWORK_API, None).await;
+            return Err(e);
+        }
+        if let Err(e) = self.wait_provisioned(&vm_id).await {
+            let _ = self.terminate(&vm_id).await;
+            return Err(e);
+        }
+
+        Ok(CloudInstance {
+            id: vm_id,
+            name: if self.admin_password.is_some() { computer_name } else { name.to_string() },
+        })
+    }
+
+    async fn terminate(&self, instance_id: &str) -> Result<(), Box<dyn Error>> {
+        let id = format!("{}?forceDeletion=true", instance_id);
+        match self.arm(reqwest::Method::DELETE, &id, ARM_COMPUTE_API, None).await {
+            Ok(_) => Ok(()),
+            // Already gone
+            Err(e) if e.to_string().contains("404") => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use std::collections::{BTreeMap, BTreeSet};
+use crate::task_queue::{QueuedAnalysis, TaskScheduler};
+
+// --- COMPARATIVE DETONATION ---
+// One submission detonated on several OS profiles as child tasks.
+
+/// Behaviours reported per bucket; keeps the response bounded for noisy samples.
+const MAX_BEHAVIORS: usize = 300;
+
+#[derive(Serialize)]
+pub struct ComparisonRun {
+    pub task_id: String,
+    pub os_profile: Option<String>,
+    pub status: String,
+    pub verdict: Option<String>,
+    pub risk_score: Option<i32>,
+    pub event_count: i64,
+    pub max_severity: Option<i32>,
+    /// Behaviours seen on this run and no other.
+    pub unique_behaviors: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct ComparisonView {
+    pub parent_task_id: String,
+    pub status: String,
+    pub runs: Vec<ComparisonRun>,
+    /// Behaviours seen on every run.
+    pub common_behaviors: Vec<String>,
+    /// Behaviours seen on some runs but not all, with the profiles they appeared on.
+    pub partial_behaviors: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ChildRow {
+    id: String,
+    status: String,
+    verdict: Option<String>,
+    risk_score: Option<i32>,
+    os_profile: Option<String>,
+}
+
+/// Creates one child task per profile under `parent_task_id` and queues them.
+pub async fn fan_out(
+    pool: &Pool<Postgres>,
+    scheduler: &TaskScheduler,
+    parent_task_id: &str,
+    base: &QueuedAnalysis,
+    profiles: &[String],
+) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query("UPDATE tasks SET status='Comparison' WHERE id=$1")
+        .bind(parent_task_id)
+        .execute(pool)
+        .await?;
+
+    let mut children = Vec::new();
+    for (i, profile) in profiles.iter().enumerate() {
+        let child_id = format!("{}-{}", parent_task_id, i + 1);
+        sqlx::query(
+            "INSERT INTO tasks (id, filename, original_filename, file_hash, status, created_at, file_path, parent_task_id, internet_policy, container)
+             SELECT $1, filename, original_filename, file_hash, 'Queued', $2, file_path, id, internet_policy, container FROM tasks WHERE id = $3"
+        )
+        .bind(&child_id)
+        .bind(chrono::Utc::now().timestamp_millis())
+        .bind(parent_task_id)
+        .execute(pool)
+        .await?;
+
+        scheduler.enqueue(QueuedAnalysis {
+            task_id: child_id.clone(),
+            target_url: base.target_url.clone(),
+            original_filename: base.original_filename.clone(),
+            duration_seconds: base.duration_seconds,
+            vmid: None,
+            node: None,
+            is_url_task: base.is_url_task,
+            analysis_mode: base.analysis_mode.clone(),
+            os_profile: Some(profile.clone()),
+            priority: base.priority.clone(),
+            profile: base.profile.clone(),
+        }).await?;
+        children.push(child_id);
+    }
+
+    println!("[COMPARE] Task {} fanned out to {} profile(s): {}", parent_task_id, profiles.len(), profiles.join(", "));
+    Ok(children)
+}
+
+/// Marks a comparison (or email) parent Completed once none of its children are still queued or running.
+pub async fn refresh_parent(pool: &Pool<Postgres>, child_task_id: &str) {
+    let _ = sqlx::query(
+        "UPDATE tasks p SET status='Completed', completed_at=$2
+         WHERE p.id = (SELECT parent_task_id FROM tasks WHERE id = $1)
+           AND p.status IN ('Comparison', 'Email')
+           AND NOT EXISTS (
+               SELECT 1 FROM tasks c JOIN task_queue q ON q.task_id = c.id
+               WHERE c.parent_task_id = p.id AND q.state IN ('queued', 'running')
+           )"
+    )
+    .bind(child_task_id)
+    .bind(chrono::Utc::now().timestamp_millis())
+    .execute(pool)
+    .await;
+}
+
+/// An event as a comparable behaviour, with digit/hex runs masked.
+fn behavior_key(event_type: &str, process_name: &str, details: &str) -> String {
+    let mut masked = String::with_capacity(details.len().min(160));
+    let mut in_run = false;
+    for c in details.chars().take(160) {
+        if c.is_ascii_digit() {
+            if !in_run {
+                masked.push('#');
+            }
+            in_run = true;
+        } else {
+            masked.push(c.to_ascii_lowercase());
+            in_run = false;
+        }
+    }
+    format!("{} | {} | {}", event_type, process_name.to_lowercase(), masked.trim())
+}
+
+/// A run's comparable behaviours, medium severity and up.
+pub async fn behaviors(pool: &Pool<Postgres>, task_id: &str) -> BTreeSet<String> {
+    let rows: Vec<(String, String, String)> = sqlx::query_as(
+        "SELECT DISTINCT event_type, process_name, details FROM events
+         WHERE task_id = $1 AND COALESCE(severity, 0) >= 50 LIMIT 5000"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+    rows.iter().map(|(t, p, d)| behavior_key(t, p, d)).collect()
+}
+
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
+#[get("/tasks/{id}/comparison")]
+pub async fn get_comparison(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let parent_id = path.into_inner();
+    let parent_status: Option<String> = match sqlx::query_scalar("SELECT status FROM tasks WHERE id = $1")
+        .bind(&parent_id)
+        .fetch_optional(pool.get_ref())
+        .await
+    {
+        Ok(s) => s,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    let Some(parent_status) = parent_status else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" }));
+    };
+
+    let children = match sqlx::query_as::<_, ChildRow>(
+        "SELECT t.id, t.status, t.verdict, t.risk_score, q.os_profile
+         FROM tasks t LEFT JOIN task_queue q ON q.task_id = t.id
+         WHERE t.parent_task_id = $1 ORDER BY t.id"
+    )
+    .bind(&parent_id)
+    .fetch_all(pool.get_ref())
+    .await {
+        Ok(rows) => rows,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    if children.is_empty() {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task has no comparison runs" }));
+    }
+
+    let mut per_run: Vec<BTreeSet<String>> = Vec::new();
+    let mut stats: Vec<(i64, Option<i32>)> = Vec::new();
+    for child in &children {
+        per_run.push(behaviors(pool.get_ref(), &child.id).await);
+
+        let stat: (i64, Option<i32>) = sqlx::query_as("SELECT COUNT(*), MAX(severity) FROM events WHERE task_id = $1")
+            .bind(&child.id)
+            .fetch_one(pool.get_ref())
+            .await
+            .unwrap_or((0, None));
+        stats.push(stat);
+    }
+
+    let label = |i: usize| children[i].os_profile.clone().unwrap_or_else(|| children[i].id.clone());
+    let mut seen_on: BTreeMap<&String, Vec<usize>> = BTreeMap::new();
+    for (i, set) in per_run.iter().enumerate() {
+        for key in set {
+            seen_on.entry(key).or_default().push(i);
+        }
+    }
+
+    let mut common = Vec::new();
+    let mut partial = BTreeMap::new();
+    let mut unique: Vec<Vec<String>> = vec![Vec::new(); children.len()];
+    for (key, runs) in &seen_on {
+        if runs.len() == children.len() {
+            if common.len() < MAX_BEHAVIORS {
+                common.push((*key).clone());
+            }
+        } else {
+            if runs.len() == 1 && unique[runs[0]].len() < MAX_BEHAVIORS {
+                unique[runs[0]].push((*key).clone());
+            }
+            if partial.len() < MAX_BEHAVIORS {
+                partial.insert((*key).clone(), runs.iter().map(|&i| label(i)).collect());
+            }
+        }
+    }
+
+    let runs = children.into_iter().zip(stats).zip(unique)
+        .map(|((child, (event_count, max_severity)), unique_behaviors)| ComparisonRun {
+            task_id: child.id,
+            os_profile: child.os_profile,
+            status: child.status,
+            verdict: child.verdict,
+            risk_score: child.risk_score,
+            event_count,
+            max_severity,
+            unique_behaviors,
+        })
+        .collect();
+
+    HttpResponse::Ok().json(ComparisonView {
+        parent_task_id: parent_id,
+        status: parent_status,
+        runs,
+        common_behaviors: common,
+        partial_behaviors: partial,
+    })
+}
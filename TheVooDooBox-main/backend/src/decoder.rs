@@ -0,0 +1,273 @@
+use actix_web::{post, web, HttpResponse, Responder};
+use base64::Engine;
+use regex::Regex;
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+use std::sync::OnceLock;
+
+// --- SERVER-SIDE DECODER ---
+// Decodes payloads for events the agent didn't decode; bump DECODER_VERSION on changes.
+
+/// Stored with every event decoded here; rows below it are re-decoded on request.
+pub const DECODER_VERSION: i32 = 1;
+/// Details longer than this are not scanned (DOM snapshots, dumped buffers).
+const MAX_SCAN_CHARS: usize = 64 * 1024;
+/// A decoded payload is cut to this many characters.
+const MAX_DECODED_CHARS: usize = 4096;
+/// Payloads that decode to another encoded payload are unwrapped this many times.
+const MAX_DEPTH: usize = 3;
+
+const INTERESTING: &[&str] = &[
+    "http", "ftp", "invoke-", "powershell", "cmd.exe", "iex", "downloadstring", "downloadfile", "frombase64string",
+    "new-object", "net.webclient", "virtualalloc", "writeprocessmemory", "createremotethread", "temp", "appdata",
+    "reg add", "schtasks", "net user", "user-agent", "mozilla", "content-type", ".exe", ".dll", ".vbs", ".js", ".ps1",
+];
+
+pub struct DecodeResult {
+    pub original: String,
+    pub decoded: String,
+    pub method: String,
+}
+
+fn encoded_command() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    // -e, -ec, -enc ... -EncodedCommand; PowerShell accepts any unambiguous prefix
+    RE.get_or_init(|| Regex::new(r#"(?i)(?:^|[\s"'])[-/](e[a-z]*)\s+["']?([A-Za-z0-9+/]{8,}={0,2})"#).unwrap())
+}
+
+fn base64_run() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"[A-Za-z0-9+/]{16,}={0,2}").unwrap())
+}
+
+fn hex_run() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?:[0-9a-fA-F]{2}){10,}").unwrap())
+}
+
+fn is_interesting(s: &str) -> bool {
+    let lower = s.to_lowercase();
+    INTERESTING.iter().any(|kw| lower.contains(kw))
+}
+
+fn is_printable(s: &str) -> bool {
+    let total = s.chars().count();
+    total > 0 && s.chars().filter(|c| c.is_ascii_graphic() || c.is_ascii_whitespace()).count() as f32 / total as f32 > 0.9
+}
+
+fn is_encoded_command_flag(flag: &str) -> bool {
+    let flag = flag.to_lowercase();
+    flag == "ec" || "encodedcommand".starts_with(&flag)
+}
+
+/// PowerShell encodes as UTF-16LE; mostly-zero odd bytes give it away.
+fn utf16le(bytes: &[u8]) -> Option<String> {
+    let pairs = bytes.chunks_exact(2);
+    if bytes.len() < 4 || !pairs.remainder().is_empty() {
+        return None;
+    }
+    let zeros = pairs.clone().filter(|c| c[1] == 0).count();
+    if zeros * 4 < bytes.len() {
+        return None;
+    }
+    let units: Vec<u16> = pairs.map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+    String::from_utf16(&units).ok()
+}
+
+fn text(bytes: &[u8]) -> Option<String> {
+    utf16le(bytes).or_else(|| String::from_utf8(bytes.to_vec()).ok())
+}
+
+fn hex_bytes(s: &str) -> Option<Vec<u8>> {
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect()
+}
+
+fn xor_brute_force(data: &[u8]) -> Option<String> {
+    if data.len() < 10 {
+        return None;
+    }
+    (1..=255u8).find_map(|key| {
+        let xored: Vec<u8> = data.iter().map(|b| b ^ key).collect();
+        String::from_utf8(xored).ok()
+            .filter(|s| is_printable(s) && is_interesting(s))
+            .map(|s| format!("[Key: 0x{:02X}] {}", key, s))
+    })
+}
+
+fn clip(s: &str) -> String {
+    match s.char_indices().nth(MAX_DECODED_CHARS) {
+        Some((cut, _)) => format!("{}... [truncated]", &s[..cut]),
+        None => s.to_string(),
+    }
+}
+
+fn scan(input: &str, depth: usize, results: &mut Vec<DecodeResult>) {
+    let b64 = base64::engine::general_purpose::STANDARD;
+    let mut seen: Vec<String> = Vec::new();
+
+    // 1. PowerShell -EncodedCommand: decoded whatever it says, the encoding is the point
+    for cap in encoded_command().captures_iter(input) {
+        let (flag, blob) = (&cap[1], &cap[2]);
+        if !is_encoded_command_flag(flag) {
+            continue;
+        }
+        let Some(decoded) = b64.decode(blob).ok().and_then(|b| text(&b)) else { continue };
+        seen.push(blob.to_string());
+        if depth < MAX_DEPTH {
+            scan(&decoded, depth + 1, results);
+        }
+        results.push(DecodeResult { original: blob.to_string(), decoded: clip(decoded.trim_end_matches('\0')), method: "PowerShell-Enc".to_string() });
+    }
+
+    // 2. Other base64 runs, kept when the plaintext looks like something
+    for mat in base64_run().find_iter(input) {
+        let candidate = mat.as_str();
+        if seen.iter().any(|s| s.contains(candidate) || candidate.contains(s.as_str())) {
+            continue;
+        }
+        let Ok(bytes) = b64.decode(candidate) else { continue };
+        match text(&bytes).filter(|s| is_printable(s)) {
+            Some(decoded) if is_interesting(&decoded) => {
+                if depth < MAX_DEPTH {
+                    scan(&decoded, depth + 1, results);
+                }
+                results.push(DecodeResult { original: candidate.to_string(), decoded: clip(&decoded), method: "Base64".to_string() });
+            }
+            Some(_) => {}
+            None => {
+                if bytes.starts_with(b"MZ") {
+                    results.push(DecodeResult {
+                        original: candidate.to_string(),
+                        decoded: "[BINARY: PE/MZ Header Detected]".to_string(),
+                        method: "Base64".to_string(),
+                    });
+                }
+                if let Some(xored) = xor_brute_force(&bytes) {
+                    results.push(DecodeResult { original: candidate.to_string(), decoded: clip(&xored), method: "Base64+XOR".to_string() });
+                }
+            }
+        }
+    }
+
+    // 3. Hex blobs hiding a single-byte XOR
+    for mat in hex_run().find_iter(input) {
+        let Some(bytes) = hex_bytes(mat.as_str()) else { continue };
+        if let Some(xored) = xor_brute_force(&bytes) {
+            results.push(DecodeResult { original: mat.as_str().to_string(), decoded: clip(&xored), method: "Hex+XOR".to_string() });
+        }
+    }
+}
+
+/// Every payload found in `input`, innermost layers first.
+pub fn scan_and_decode(input: &str) -> Vec<DecodeResult> {
+    let mut results = Vec::new();
+    if input.len() <= MAX_SCAN_CHARS {
+        scan(input, 0, &mut results);
+    }
+    results
+}
+
+/// decoded_details for an event, in the agent's format: "[method] plaintext | ...".
+pub fn decode(details: &str) -> Option<String> {
+    let decodes = scan_and_decode(details);
+    if decodes.is_empty() {
+        return None;
+    }
+    Some(decodes.iter().map(|d| format!("[{}] {}", d.method, d.decoded)).collect::<Vec<_>>().join(" | "))
+}
+
+#[derive(sqlx::FromRow)]
+struct Pending {
+    id: i32,
+    event_type: String,
+    process_name: String,
+    details: String,
+    category: Option<String>,
+}
+
+/// Re-decodes events not seen at this version (all with `force`); returns (scanned, decoded).
+pub async fn redecode(pool: &Pool<Postgres>, task_id: &str, force: bool) -> Result<(usize, usize), sqlx::Error> {
+    let rows = sqlx::query_as::<_, Pending>(
+        "SELECT id, event_type, process_name, details, category FROM events
+         WHERE task_id = $1
+           AND ((decoded_details IS NULL AND decoder_version IS NULL)
+                OR decoder_version < $2
+                OR ($3 AND decoder_version IS NOT NULL))"
+    )
+    .bind(task_id)
+    .bind(DECODER_VERSION)
+    .bind(force)
+    .fetch_all(pool)
+    .await?;
+
+    let mut ids = Vec::with_capacity(rows.len());
+    let mut decoded = Vec::with_capacity(rows.len());
+    let mut stages = Vec::with_capacity(rows.len());
+    let mut obfuscation = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let payload = decode(&row.details);
+        // The stage and obfuscation score may change now that the payload is visible
+        stages.push(crate::kill_chain::classify(&row.event_type, &row.process_name, &row.details, payload.as_deref(), row.category.as_deref())
+            .map(str::to_string));
+        obfuscation.push(crate::obfuscation::score(&row.event_type, &row.details, payload.as_deref()));
+        ids.push(row.id);
+        decoded.push(payload);
+    }
+    let found = decoded.iter().filter(|d| d.is_some()).count();
+    if ids.is_empty() {
+        return Ok((0, 0));
+    }
+
+    sqlx::query(
+        "UPDATE events SET decoded_details = u.decoded, decoder_version = $5, kill_chain_stage = COALESCE(u.stage, events.kill_chain_stage),
+             obfuscation_score = u.obfuscation
+         FROM UNNEST($1::int[], $2::text[], $3::text[], $4::int[]) AS u(id, decoded, stage, obfuscation)
+         WHERE events.id = u.id AND events.task_id = $6"
+    )
+    .bind(&ids)
+    .bind(&decoded)
+    .bind(&stages)
+    .bind(&obfuscation)
+    .bind(DECODER_VERSION)
+    .bind(task_id)
+    .execute(pool)
+    .await?;
+
+    println!("[DECODER] Task {}: scanned {} events, {} with decoded payloads.", task_id, ids.len(), found);
+    Ok((ids.len(), found))
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DecodeQuery {
+    /// Also re-decode events already decoded at the current version.
+    pub force: Option<bool>,
+}
+
+/// Runs the server-side decoder over a task's events that lack agent-decoded payloads.
+#[utoipa::path(tag = "tasks", params(DecodeQuery), responses(
+    (status = 200, description = "Events re-decoded"),
+    (status = 404, description = "Task not found"),
+))]
+#[post("/tasks/{id}/decode")]
+pub async fn redecode_task(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+    query: web::Query<DecodeQuery>,
+) -> impl Responder {
+    let task_id = path.into_inner();
+    match sqlx::query_scalar::<_, String>("SELECT status FROM tasks WHERE id = $1").bind(&task_id).fetch_optional(pool.get_ref()).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+    match redecode(pool.get_ref(), &task_id, query.force.unwrap_or(false)).await {
+        Ok((scanned, decoded)) => HttpResponse::Ok().json(serde_json::json!({
+            "task_id": task_id,
+            "decoder_version": DECODER_VERSION,
+            "scanned": scanned,
+            "decoded": decoded,
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
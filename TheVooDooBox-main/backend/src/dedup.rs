@@ -0,0 +1,50 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+
+// --- HASH DEDUPLICATION ---
+// Reuse the earlier analysis of an already-seen SHA-256.
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct PreviousAnalysis {
+    pub task_id: String,
+    pub verdict: Option<String>,
+    pub risk_score: Option<i32>,
+    pub completed_at: Option<i64>,
+}
+
+/// Most recent completed task for this hash, preferring ones that produced a verdict.
+pub async fn latest_completed(pool: &Pool<Postgres>, sha256: &str) -> Option<PreviousAnalysis> {
+    if sha256.is_empty() {
+        return None;
+    }
+    sqlx::query_as::<_, PreviousAnalysis>(
+        "SELECT id AS task_id, verdict, risk_score, completed_at FROM tasks
+         WHERE LOWER(file_hash) = LOWER($1) AND status = 'Completed'
+         ORDER BY (verdict IS NOT NULL) DESC, completed_at DESC NULLS LAST
+         LIMIT 1"
+    )
+    .bind(sha256)
+    .fetch_optional(pool)
+    .await
+    .ok()
+    .flatten()
+}
+
+/// "true"/"1"/"yes" form and query flags.
+pub fn flag(value: &str) -> bool {
+    matches!(value.trim().to_lowercase().as_str(), "true" | "1" | "yes")
+}
+
+/// Lets a client check before uploading.
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
+#[get("/tasks/by-hash/{sha256}")]
+pub async fn lookup_hash(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    match latest_completed(pool.get_ref(), &path.into_inner()).await {
+        Some(previous) => HttpResponse::Ok().json(previous),
+        None => HttpResponse::NotFound().json(serde_json::json!({ "error": "No completed analysis for this hash" })),
+    }
+}
@@ -11,8 +11,7 @@ use sqlx::{FromRow, Pool, Postgres};
 use std::sync::Arc;
 use chrono::Utc;
 
-use crate::progress_stream::ProgressBroadcaster;
-use crate::{orchestrate_sandbox, AgentManager, AIManager};
+use crate::task_queue::{QueuedAnalysis, TaskScheduler};
 
 // ── Data Types ──────────────────────────────────────────────────────────────
 
@@ -60,14 +59,16 @@ pub struct DetoxExtensionDetail {
     pub scans: Vec<DetoxScanHistoryRow>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ExtensionQuery {
     pub state: Option<String>,
 }
 
 // ── Dashboard Stats ─────────────────────────────────────────────────────────
 
-#[get("/api/detox/dashboard")]
+#[utoipa::path(tag = "detox", responses((status = 200, description = "Success")))]
+#[get("/detox/dashboard")]
 pub async fn detox_dashboard(pool: web::Data<Pool<Postgres>>) -> HttpResponse {
     let total: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM detox_extensions")
         .fetch_one(pool.get_ref())
@@ -119,7 +120,8 @@ pub async fn detox_dashboard(pool: web::Data<Pool<Postgres>>) -> HttpResponse {
 
 // ── Extension List ──────────────────────────────────────────────────────────
 
-#[get("/api/detox/extensions")]
+#[utoipa::path(tag = "detox", responses((status = 200, description = "Success")))]
+#[get("/detox/extensions")]
 pub async fn detox_extensions(
     pool: web::Data<Pool<Postgres>>,
     query: web::Query<ExtensionQuery>,
@@ -155,7 +157,8 @@ pub async fn detox_extensions(
 
 // ── Extension Detail ────────────────────────────────────────────────────────
 
-#[get("/api/detox/extension/{id}")]
+#[utoipa::path(tag = "detox", responses((status = 200, description = "Success")))]
+#[get("/detox/extension/{id}")]
 pub async fn detox_extension_detail(
     pool: web::Data<Pool<Postgres>>,
     path: web::Path<i32>,
@@ -203,7 +206,8 @@ pub struct ScanTriggerRequest {
     pub force: Option<bool>,
 }
 
-#[post("/api/detox/scan")]
+#[utoipa::path(tag = "detox", responses((status = 200, description = "Success")))]
+#[post("/detox/scan")]
 pub async fn detox_trigger_scan(body: web::Json<ScanTriggerRequest>) -> HttpResponse {
     let bouncer_url = std::env::var("DETOX_BOUNCER_URL")
         .unwrap_or_else(|_| "http://detox-bouncer:8000".to_string());
@@ -252,7 +256,8 @@ pub struct ScanPendingRequest {
     pub limit: Option<i32>,
 }
 
-#[post("/api/detox/scan-pending")]
+#[utoipa::path(tag = "detox", responses((status = 200, description = "Success")))]
+#[post("/detox/scan-pending")]
 pub async fn detox_trigger_scan_pending(body: web::Json<ScanPendingRequest>) -> HttpResponse {
     let bouncer_url = std::env::var("DETOX_BOUNCER_URL")
         .unwrap_or_else(|_| "http://detox-bouncer:8000".to_string());
@@ -299,7 +304,8 @@ pub struct ScrapeRequest {
     pub sort_by: Option<String>,
 }
 
-#[post("/api/detox/scrape")]
+#[utoipa::path(tag = "detox", responses((status = 200, description = "Success")))]
+#[post("/detox/scrape")]
 pub async fn detox_trigger_scrape(body: web::Json<ScrapeRequest>) -> HttpResponse {
     let bouncer_url = std::env::var("DETOX_BOUNCER_URL")
         .unwrap_or_else(|_| "http://detox-bouncer:8000".to_string());
@@ -341,7 +347,8 @@ pub async fn detox_trigger_scrape(body: web::Json<ScrapeRequest>) -> HttpRespons
 
 // ── Blocklist ───────────────────────────────────────────────────────────────
 
-#[get("/api/detox/blocklist")]
+#[utoipa::path(tag = "detox", responses((status = 200, description = "Success")))]
+#[get("/detox/blocklist")]
 pub async fn detox_blocklist(pool: web::Data<Pool<Postgres>>) -> HttpResponse {
     let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM detox_blocklist")
         .fetch_one(pool.get_ref())
@@ -366,15 +373,14 @@ pub struct DetoxSandboxRequest {
     pub analysis_mode: Option<String>,
     #[allow(dead_code)]
     pub ai_strategy: Option<String>,
+    pub priority: Option<String>,
 }
 
-#[post("/api/detox/sandbox")]
+#[utoipa::path(tag = "detox", responses((status = 200, description = "Success")))]
+#[post("/detox/sandbox")]
 pub async fn detox_submit_sandbox(
     pool: web::Data<Pool<Postgres>>,
-    manager: web::Data<Arc<AgentManager>>,
-    ai_manager: web::Data<AIManager>,
-    client: web::Data<crate::proxmox::ProxmoxClient>,
-    progress: web::Data<Arc<ProgressBroadcaster>>,
+    scheduler: web::Data<Arc<TaskScheduler>>,
     body: web::Json<DetoxSandboxRequest>,
 ) -> HttpResponse {
     let ext = match sqlx::query_as::<_, crate::detox_api::DetoxExtensionRow>(
@@ -392,6 +398,10 @@ pub async fn detox_submit_sandbox(
         }
     };
 
+    let Some(priority) = crate::task_queue::parse_priority(body.priority.as_deref().unwrap_or_default()) else {
+        return HttpResponse::BadRequest().body("priority must be urgent, normal or bulk");
+    };
+
     let filename = format!("{}_{}.vsix", body.extension_id, body.version);
     let vsix_dir = std::env::var("VSIX_ARCHIVE_DIR").unwrap_or_else(|_| "/vsix_archive".to_string());
     let filepath = format!("{}/{}", vsix_dir, filename);
@@ -426,31 +436,22 @@ pub async fn detox_submit_sandbox(
     let host_ip = std::env::var("HOST_IP").unwrap_or_else(|_| "192.168.50.11".to_string());
     let download_url = format!("http://{}:8080/vsix_archive/{}", host_ip, filename);
 
-    let client_clone = client.get_ref().clone();
-    let manager_clone = manager.get_ref().clone();
-    let pool_clone = pool.get_ref().clone();
-    let ai_manager_clone = ai_manager.get_ref().clone();
-    let duration = body.duration_minutes.unwrap_or(5) * 60;
-    let progress_clone = progress.get_ref().clone();
-    let task_id_clone = task_id.clone();
-
-    actix_web::rt::spawn(async move {
-        orchestrate_sandbox(
-            client_clone,
-            manager_clone,
-            pool_clone,
-            ai_manager_clone,
-            task_id_clone,
-            download_url,
-            filename,
-            duration,
-            body.vmid,
-            body.node.clone(),
-            false,
-            "vsix".to_string(),
-            progress_clone,
-        ).await;
-    });
+    let job = QueuedAnalysis {
+        task_id: task_id.clone(),
+        target_url: download_url,
+        original_filename: filename,
+        duration_seconds: body.duration_minutes.unwrap_or(5) * 60,
+        vmid: body.vmid,
+        node: body.node.clone(),
+        is_url_task: false,
+        analysis_mode: "vsix".to_string(),
+        os_profile: None,
+        priority: priority.to_string(),
+        profile: None,
+    };
+    if let Err(e) = scheduler.enqueue(job).await {
+        return HttpResponse::InternalServerError().body(format!("Queue Error: {}", e));
+    }
 
     HttpResponse::Ok().json(serde_json::json!({
         "status": "queued",
@@ -461,7 +462,8 @@ pub async fn detox_submit_sandbox(
 
 // ── Delete Extension ────────────────────────────────────────────────────────
 
-#[delete("/api/detox/extension/{id}")]
+#[utoipa::path(tag = "detox", responses((status = 200, description = "Success")))]
+#[delete("/detox/extension/{id}")]
 pub async fn detox_delete_extension(
     path: web::Path<i32>,
 ) -> HttpResponse {
@@ -498,7 +500,8 @@ pub async fn detox_delete_extension(
 
 // ── Purge All Data (proxy to bouncer) ───────────────────────────────────────
 
-#[delete("/api/detox/purge-all")]
+#[utoipa::path(tag = "detox", responses((status = 200, description = "Success")))]
+#[delete("/detox/purge-all")]
 pub async fn detox_purge_all() -> HttpResponse {
     let bouncer_url = std::env::var("DETOX_BOUNCER_URL")
         .unwrap_or_else(|_| "http://detox-bouncer:8000".to_string());
@@ -533,7 +536,8 @@ pub async fn detox_purge_all() -> HttpResponse {
 
 // ── Kill Switch (proxy to bouncer) ──────────────────────────────────────────
 
-#[post("/api/detox/kill")]
+#[utoipa::path(tag = "detox", responses((status = 200, description = "Success")))]
+#[post("/detox/kill")]
 pub async fn detox_kill_processing() -> HttpResponse {
     let bouncer_url = std::env::var("DETOX_BOUNCER_URL")
         .unwrap_or_else(|_| "http://detox-bouncer:8000".to_string());
@@ -0,0 +1,566 @@
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, OnceLock, RwLock};
+use crate::alerts::NewAlert;
+use crate::sigma::EventRef;
+use crate::stream::Broadcaster;
+
+// --- DNS REPUTATION ---
+// Blocklisted and DGA-looking domains, optionally young ones (DNS_RDAP=true).
+
+/// DGA score from which a domain is alerted on.
+const DGA_AT: i32 = 60;
+/// Labels shorter than this are too short to judge by their letters.
+const MIN_DGA_LABEL: usize = 8;
+const RDAP_CACHE_MS: i64 = 7 * 24 * 3600 * 1000;
+
+const SUSPICIOUS_TLDS: &[&str] = &[
+    "tk", "ml", "ga", "cf", "gq", "top", "xyz", "pw", "buzz", "cyou", "icu", "rest", "monster", "bit", "su", "zip", "mov",
+];
+
+const TWO_PART_SUFFIXES: &[&str] = &[
+    "co.uk", "org.uk", "ac.uk", "com.au", "net.au", "co.jp", "com.br", "com.cn", "co.in", "com.tr", "co.za", "com.mx", "com.ru",
+];
+
+/// Infrastructure the guest talks to on its own; never scored as DGA (the blocklist still applies).
+const ALLOWED_SUFFIXES: &[&str] = &[
+    "microsoft.com", "windows.com", "windowsupdate.com", "msftncsi.com", "msftconnecttest.com", "office.com", "office365.com",
+    "live.com", "bing.com", "msn.com", "msedge.net", "azure.com", "azureedge.net", "trafficmanager.net", "akamai.net",
+    "akamaiedge.net", "akamaized.net", "cloudfront.net", "amazonaws.com", "google.com", "googleapis.com", "gstatic.com",
+    "digicert.com", "verisign.com", "in-addr.arpa", "ip6.arpa", "local", "localdomain", "lan",
+];
+
+/// The most frequent English letter pairs; a label made mostly of other pairs reads as random.
+const COMMON_BIGRAMS: &str = "th he in er an re on at en nd ti es or te of ed is it al ar st to nt ng se ha as ou io le ve co me de \
+    hi ri ro ic ne ea ra ce li ch ll be ma si om ur ca el ta la ns di fo ho pe ec pr no ct us ac ot il tr ly nc et ut ss so rs un \
+    lo wa ge ie wh ee wi em ad ol rt po we na ul ni ts mo ow pa im mi ai sh ir su id os iv ia am fi ci vi pl ig tu ev ld ry mp fe \
+    bl ab gh ty op wo sa ay ex ke fr oo av ag if ap gr od bo sp rd do uc bu ei ov by rm ep tt oc fa ef cu rn sc gi da yo cr cl du \
+    ga qu ue ff ba ey ls va um pp ua up lu go ht ru ug ds lt pi rc rr eg au ck ew mu br bi pt ak pu ui rg ib tl ny ki rk ys ob mm \
+    fu ph og ms ye ud mb ip ub oi rl gu dr hr cc tw ft wn nu af hu nn eo vo rv nf xp gn sm fl iz ok nl my gl aw ju oa eq sy sl ps \
+    jo lf nv je nk kn gs dy hy ze ks xt";
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct BlocklistEntry {
+    pub id: i32,
+    pub domain: String,
+    pub reason: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct BlocklistRequest {
+    pub domain: String,
+    pub reason: Option<String>,
+}
+
+/// How a domain looks, from its name alone.
+#[derive(Serialize, Clone)]
+pub struct Assessment {
+    pub domain: String,
+    /// The label that was scored: the one left of the public suffix.
+    pub label: String,
+    pub entropy: f64,
+    /// Share of letter pairs outside COMMON_BIGRAMS, 0-1.
+    pub rare_bigrams: f64,
+    pub dga_score: i32,
+    pub blocklisted: bool,
+    pub reasons: Vec<String>,
+}
+
+fn bigrams() -> &'static HashSet<&'static str> {
+    static SET: OnceLock<HashSet<&'static str>> = OnceLock::new();
+    SET.get_or_init(|| COMMON_BIGRAMS.split_whitespace().collect())
+}
+
+fn blocklist() -> &'static RwLock<Arc<Vec<String>>> {
+    static LIST: OnceLock<RwLock<Arc<Vec<String>>>> = OnceLock::new();
+    LIST.get_or_init(|| RwLock::new(Arc::new(Vec::new())))
+}
+
+fn is_blocklisted(domain: &str) -> bool {
+    let list = blocklist().read().map(|l| Arc::clone(&l)).unwrap_or_default();
+    list.iter().any(|b| domain == b || domain.ends_with(&format!(".{}", b)))
+}
+
+fn env_number(key: &str, default: i64) -> i64 {
+    std::env::var(key).ok().and_then(|v| v.trim().parse().ok()).unwrap_or(default)
+}
+
+/// Queried name from a Sysmon, DNS cache or fake-net DNS event.
+pub fn queried_domain(details: &str) -> Option<String> {
+    let rest = if let Some(pos) = details.find("DNS Query Resolved: ") {
+        &details[pos + 20..]
+    } else if let Some(pos) = details.find("DNS: ") {
+        &details[pos + 5..]
+    } else if details.contains("FAKENET: DNS") {
+        details.rsplit_once("->")?.1
+    } else {
+        return None;
+    };
+    let name = rest.split("| IPs:").next()?.trim().trim_end_matches('.').to_lowercase();
+    let valid = name.contains('.') && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+    valid.then_some(name)
+}
+
+/// (registrable domain, its label, tld): "a.b.evil.co.uk" -> ("evil.co.uk", "evil", "uk").
+fn registrable(domain: &str) -> Option<(String, String, String)> {
+    let labels: Vec<&str> = domain.split('.').filter(|l| !l.is_empty()).collect();
+    if labels.len() < 2 {
+        return None;
+    }
+    let tld = labels[labels.len() - 1].to_string();
+    let two_part = labels.len() >= 3 && TWO_PART_SUFFIXES.contains(&labels[labels.len() - 2..].join(".").as_str());
+    let suffix_len = if two_part { 2 } else { 1 };
+    let label = labels[labels.len() - suffix_len - 1];
+    Some((labels[labels.len() - suffix_len - 1..].join("."), label.to_string(), tld))
+}
+
+fn entropy(s: &str) -> f64 {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in s.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+    let len = s.chars().count() as f64;
+    counts.values().map(|&n| {
+        let p = n as f64 / len;
+        -p * p.log2()
+    }).sum()
+}
+
+pub fn assess(domain: &str) -> Assessment {
+    let blocklisted = is_blocklisted(domain);
+    let mut reasons = Vec::new();
+    if blocklisted {
+        reasons.push("blocklisted".to_string());
+    }
+    let Some((base, label, tld)) = registrable(domain) else {
+        return Assessment { domain: domain.to_string(), label: String::new(), entropy: 0.0, rare_bigrams: 0.0, dga_score: 0, blocklisted, reasons };
+    };
+    let letters: String = label.chars().filter(|c| c.is_ascii_alphabetic()).collect();
+    let pairs: Vec<String> = letters.as_bytes().windows(2).map(|w| String::from_utf8_lossy(w).into_owned()).collect();
+    let rare_bigrams = if pairs.is_empty() {
+        0.0
+    } else {
+        pairs.iter().filter(|p| !bigrams().contains(p.as_str())).count() as f64 / pairs.len() as f64
+    };
+    let entropy = entropy(&label);
+
+    let allowed = ALLOWED_SUFFIXES.iter().any(|s| base == *s || domain.ends_with(&format!(".{}", s)));
+    let mut score = 0;
+    if !allowed && label.len() >= MIN_DGA_LABEL {
+        if entropy >= 3.5 {
+            score += 30;
+            reasons.push(format!("entropy {:.2}", entropy));
+        } else if entropy >= 3.2 {
+            score += 15;
+        }
+        if rare_bigrams >= 0.75 {
+            score += 40;
+            reasons.push(format!("{:.0}% rare letter pairs", rare_bigrams * 100.0));
+        } else if rare_bigrams >= 0.6 {
+            score += 30;
+            reasons.push(format!("{:.0}% rare letter pairs", rare_bigrams * 100.0));
+        } else if rare_bigrams >= 0.45 {
+            score += 15;
+        }
+        let digits = label.chars().filter(|c| c.is_ascii_digit()).count() as f64 / label.len() as f64;
+        if (0.15..0.8).contains(&digits) && !letters.is_empty() {
+            score += 15;
+            reasons.push("letters mixed with digits".to_string());
+        }
+        let consonant_run = letters.split(['a', 'e', 'i', 'o', 'u', 'y']).map(str::len).max().unwrap_or(0);
+        if consonant_run >= 5 {
+            score += if consonant_run >= 6 { 20 } else { 15 };
+            reasons.push(format!("{} consonants in a row", consonant_run));
+        }
+        if label.len() >= 16 {
+            score += 10;
+        }
+        if SUSPICIOUS_TLDS.contains(&tld.as_str()) {
+            score += 10;
+            reasons.push(format!(".{} TLD", tld));
+        }
+    }
+
+    Assessment {
+        domain: domain.to_string(),
+        label,
+        entropy: (entropy * 100.0).round() / 100.0,
+        rare_bigrams: (rare_bigrams * 100.0).round() / 100.0,
+        dga_score: score.min(100),
+        blocklisted,
+        reasons,
+    }
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS dns_blocklist (
+            id SERIAL PRIMARY KEY,
+            domain TEXT UNIQUE NOT NULL,
+            reason TEXT,
+            created_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS domain_registrations (
+            domain TEXT PRIMARY KEY,
+            registered_at BIGINT,
+            checked_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    if let Ok(path) = std::env::var("DNS_BLOCKLIST_FILE") {
+        match tokio::fs::read_to_string(&path).await {
+            Ok(text) => {
+                let now = chrono::Utc::now().timestamp();
+                let mut imported = 0;
+                for line in text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+                    let domain = line.split_whitespace().next().unwrap_or(line).trim_end_matches('.').to_lowercase();
+                    let res = sqlx::query("INSERT INTO dns_blocklist (domain, reason, created_at) VALUES ($1, $2, $3) ON CONFLICT (domain) DO NOTHING")
+                        .bind(&domain)
+                        .bind("DNS_BLOCKLIST_FILE")
+                        .bind(now)
+                        .execute(pool)
+                        .await?;
+                    imported += res.rows_affected();
+                }
+                println!("[DNS] Imported {} new blocklist entries from {}.", imported, path);
+            }
+            Err(e) => println!("[DNS] Cannot read DNS_BLOCKLIST_FILE {}: {}", path, e),
+        }
+    }
+
+    let loaded = reload(pool).await?;
+    println!("[DNS] Database initialized (dns_blocklist, domain_registrations), {} blocklisted domains.", loaded);
+    Ok(())
+}
+
+pub async fn reload(pool: &Pool<Postgres>) -> Result<usize, sqlx::Error> {
+    let domains: Vec<String> = sqlx::query_scalar("SELECT domain FROM dns_blocklist").fetch_all(pool).await?;
+    let count = domains.len();
+    if let Ok(mut current) = blocklist().write() {
+        *current = Arc::new(domains);
+    }
+    Ok(count)
+}
+
+fn alert_for(evt: &EventRef<'_>, rule: &str, title: String, level: &str, severity: i32, details: String) -> NewAlert {
+    NewAlert {
+        task_id: evt.task_id.map(str::to_string),
+        event_id: evt.id,
+        source: "dns",
+        rule_id: format!("dns:{}", rule),
+        rule_title: title,
+        level: level.to_string(),
+        severity,
+        tags: vec!["attack.command_and_control".to_string(), "attack.t1568.002".to_string()],
+        rule_references: Vec::new(),
+        process_id: evt.process_id,
+        process_name: evt.process_name.to_string(),
+        details,
+        timestamp: evt.timestamp,
+    }
+}
+
+/// Scores the DNS queries among freshly stored events; each task alerts once per domain.
+pub async fn evaluate(pool: &Pool<Postgres>, broadcaster: Option<&Broadcaster>, events: &[EventRef<'_>]) {
+    let dns: Vec<(&EventRef, Assessment)> = events.iter()
+        .filter(|e| e.event_type == "NETWORK_DNS")
+        .filter_map(|e| queried_domain(e.details).map(|d| (e, assess(&d))))
+        .filter(|(_, a)| a.blocklisted || a.dga_score >= DGA_AT)
+        .collect();
+    if dns.is_empty() {
+        return;
+    }
+
+    let mut by_task: HashMap<Option<&str>, Vec<(&EventRef, Assessment)>> = HashMap::new();
+    for (evt, assessment) in dns {
+        by_task.entry(evt.task_id).or_default().push((evt, assessment));
+    }
+
+    for (task_id, hits) in by_task {
+        let existing: Vec<(String, String)> = sqlx::query_as(
+            "SELECT rule_id, rule_title FROM alerts WHERE task_id IS NOT DISTINCT FROM $1 AND source = 'dns'"
+        )
+        .bind(task_id)
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default();
+        let mut titles: HashSet<String> = existing.iter().map(|(_, t)| t.clone()).collect();
+        let mut dga_domains = existing.iter().filter(|(r, _)| r == "dns:dga").count();
+        let flood_raised = existing.iter().any(|(r, _)| r == "dns:dga_flood");
+
+        let mut new = Vec::new();
+        let mut last_dga: Option<&EventRef> = None;
+        for (evt, a) in hits {
+            let (rule, level, severity) = if a.blocklisted {
+                ("blocklist", "high", 85)
+            } else if a.dga_score >= 80 {
+                ("dga", "high", 75)
+            } else {
+                ("dga", "medium", 60)
+            };
+            let title = if a.blocklisted {
+                format!("SUSPICIOUS_DNS: {} (blocklisted)", a.domain)
+            } else {
+                format!("SUSPICIOUS_DNS: {} (DGA-like)", a.domain)
+            };
+            if !titles.insert(title.clone()) {
+                continue;
+            }
+            if rule == "dga" {
+                dga_domains += 1;
+                last_dga = Some(evt);
+            }
+            let details = format!("{} (DGA score {}, entropy {:.2}): {}", a.domain, a.dga_score, a.entropy, a.reasons.join(", "));
+            new.push(alert_for(evt, rule, title, level, severity, details));
+        }
+
+        let flood_at = env_number("DNS_DGA_FLOOD", 5).max(1) as usize;
+        if let Some(evt) = last_dga.filter(|_| !flood_raised && dga_domains >= flood_at) {
+            new.push(alert_for(evt, "dga_flood",
+                format!("SUSPICIOUS_DNS: DGA flood ({} random-looking domains)", dga_domains),
+                "high", 85,
+                format!("{} DGA-like domains queried; the sample is likely hunting for a live C2 domain", dga_domains)));
+        }
+        if !new.is_empty() {
+            crate::alerts::record(pool, broadcaster, new).await;
+        }
+    }
+}
+
+/// Registration date over RDAP, cached; None when unknown or lookups are off.
+async fn registered_at(pool: &Pool<Postgres>, client: &reqwest::Client, base_url: &str, domain: &str) -> Option<i64> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let cached: Option<(Option<i64>, i64)> = sqlx::query_as("SELECT registered_at, checked_at FROM domain_registrations WHERE domain = $1")
+        .bind(domain)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten();
+    if let Some((registered, checked)) = cached {
+        if now - checked < RDAP_CACHE_MS {
+            return registered;
+        }
+    }
+
+    let url = format!("{}/{}", base_url.trim_end_matches('/'), domain);
+    let body: Option<serde_json::Value> = match client.get(&url).header("Accept", "application/rdap+json").send().await {
+        Ok(resp) if resp.status().is_success() => resp.json().await.ok(),
+        // 404: not registered as asked (or not known to RDAP); remember that too
+        Ok(resp) if resp.status() == reqwest::StatusCode::NOT_FOUND => None,
+        _ => return None,
+    };
+    let registered = body.as_ref()
+        .and_then(|b| b["events"].as_array())
+        .and_then(|events| events.iter().find(|e| e["eventAction"].as_str() == Some("registration")))
+        .and_then(|e| e["eventDate"].as_str())
+        .and_then(|d| chrono::DateTime::parse_from_rfc3339(d).ok())
+        .map(|d| d.timestamp_millis());
+
+    let _ = sqlx::query(
+        "INSERT INTO domain_registrations (domain, registered_at, checked_at) VALUES ($1, $2, $3)
+         ON CONFLICT (domain) DO UPDATE SET registered_at = EXCLUDED.registered_at, checked_at = EXCLUDED.checked_at"
+    )
+    .bind(domain)
+    .bind(registered)
+    .bind(now)
+    .execute(pool)
+    .await;
+    registered
+}
+
+#[derive(sqlx::FromRow)]
+struct DnsRow {
+    id: i32,
+    process_id: i32,
+    process_name: String,
+    details: String,
+    timestamp: i64,
+}
+
+/// Post-run pass: with DNS_RDAP on, flags queried domains registered in the last DNS_YOUNG_DOMAIN_DAYS days.
+pub async fn run(pool: &Pool<Postgres>, task_id: &str) {
+    if !std::env::var("DNS_RDAP").map(|v| v == "true" || v == "1").unwrap_or(false) {
+        return;
+    }
+    let base_url = std::env::var("DNS_RDAP_URL").unwrap_or_else(|_| "https://rdap.org/domain".to_string());
+    let young_ms = env_number("DNS_YOUNG_DOMAIN_DAYS", 30) * 24 * 3600 * 1000;
+    let max_lookups = env_number("DNS_RDAP_MAX_LOOKUPS", 25).max(0) as usize;
+
+    let rows = sqlx::query_as::<_, DnsRow>(
+        "SELECT id, process_id, process_name, details, timestamp FROM events
+         WHERE task_id = $1 AND event_type = 'NETWORK_DNS' ORDER BY timestamp, id"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+
+    // First query of each registrable domain, skipping the guest's own infrastructure
+    let mut seen = HashSet::new();
+    let mut candidates = Vec::new();
+    for row in &rows {
+        let Some(domain) = queried_domain(&row.details) else { continue };
+        let Some((base, _, _)) = registrable(&domain) else { continue };
+        if ALLOWED_SUFFIXES.iter().any(|s| base == *s || base.ends_with(&format!(".{}", s))) || !seen.insert(base.clone()) {
+            continue;
+        }
+        candidates.push((base, row));
+    }
+    if candidates.is_empty() {
+        return;
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(5))
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new());
+    let alerted: HashSet<String> = sqlx::query_scalar("SELECT rule_title FROM alerts WHERE task_id = $1 AND rule_id = 'dns:young_domain'")
+        .bind(task_id)
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut new = Vec::new();
+    for (domain, row) in candidates.into_iter().take(max_lookups) {
+        let Some(registered) = registered_at(pool, &client, &base_url, &domain).await else { continue };
+        let age_days = (now - registered) / (24 * 3600 * 1000);
+        let title = format!("SUSPICIOUS_DNS: {} (registered {} days ago)", domain, age_days);
+        if now - registered > young_ms || alerted.iter().any(|t| t.starts_with(&format!("SUSPICIOUS_DNS: {} ", domain))) {
+            continue;
+        }
+        let evt = EventRef {
+            id: Some(row.id),
+            task_id: Some(task_id),
+            event_type: "NETWORK_DNS",
+            process_id: row.process_id,
+            parent_process_id: 0,
+            process_name: &row.process_name,
+            details: &row.details,
+            decoded_details: None,
+            category: None,
+            timestamp: row.timestamp,
+        };
+        new.push(alert_for(&evt, "young_domain", title, "medium", 55,
+            format!("{} was registered {} days before this run (RDAP)", domain, age_days)));
+    }
+    if !new.is_empty() {
+        println!("[DNS] Task {}: {} recently registered domain(s).", task_id, new.len());
+        crate::alerts::record(pool, None, new).await;
+    }
+}
+
+/// Every domain the task queried, with how it scores and when it was registered (if looked up).
+#[utoipa::path(tag = "detections", responses((status = 200, description = "Queried domains, most suspicious first")))]
+#[get("/tasks/{id}/dns")]
+pub async fn get_task_dns(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    let task_id = path.into_inner();
+    let rows = match sqlx::query_as::<_, (String, i64)>(
+        "SELECT details, timestamp FROM events WHERE task_id = $1 AND event_type = 'NETWORK_DNS' ORDER BY timestamp, id"
+    )
+    .bind(&task_id)
+    .fetch_all(pool.get_ref())
+    .await
+    {
+        Ok(rows) => rows,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+
+    let mut domains: Vec<(String, usize, i64)> = Vec::new();
+    for (details, ts) in rows {
+        let Some(domain) = queried_domain(&details) else { continue };
+        match domains.iter_mut().find(|(d, _, _)| *d == domain) {
+            Some(entry) => entry.1 += 1,
+            None => domains.push((domain, 1, ts)),
+        }
+    }
+    let registrations: HashMap<String, Option<i64>> = sqlx::query_as::<_, (String, Option<i64>)>(
+        "SELECT domain, registered_at FROM domain_registrations"
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .unwrap_or_default()
+    .into_iter()
+    .collect();
+
+    let mut assessed: Vec<serde_json::Value> = domains.into_iter().map(|(domain, queries, first_seen)| {
+        let a = assess(&domain);
+        let registered_at = registrable(&domain).and_then(|(base, _, _)| registrations.get(&base).copied().flatten());
+        serde_json::json!({
+            "queries": queries,
+            "first_seen": first_seen,
+            "registered_at": registered_at,
+            "suspicious": a.blocklisted || a.dga_score >= DGA_AT,
+            "assessment": a,
+        })
+    }).collect();
+    assessed.sort_by_key(|v| std::cmp::Reverse((v["suspicious"].as_bool(), v["assessment"]["dga_score"].as_i64())));
+
+    HttpResponse::Ok().json(serde_json::json!({ "task_id": task_id, "count": assessed.len(), "domains": assessed }))
+}
+
+#[utoipa::path(tag = "detections", responses((status = 200, description = "Success")))]
+#[get("/settings/dns-blocklist")]
+pub async fn list_dns_blocklist(pool: web::Data<Pool<Postgres>>) -> impl Responder {
+    match sqlx::query_as::<_, BlocklistEntry>("SELECT * FROM dns_blocklist ORDER BY domain").fetch_all(pool.get_ref()).await {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Blocklists a domain and everything under it.
+#[utoipa::path(tag = "detections", request_body = BlocklistRequest, responses(
+    (status = 200, description = "Domain blocklisted"),
+    (status = 400, description = "Not a domain name"),
+))]
+#[post("/settings/dns-blocklist")]
+pub async fn add_dns_blocklist(pool: web::Data<Pool<Postgres>>, req: web::Json<BlocklistRequest>) -> impl Responder {
+    let domain = req.domain.trim().trim_start_matches("*.").trim_end_matches('.').to_lowercase();
+    if !domain.contains('.') || !domain.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_')) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "domain must be a domain name, e.g. evil.example" }));
+    }
+    let stored = sqlx::query_as::<_, BlocklistEntry>(
+        "INSERT INTO dns_blocklist (domain, reason, created_at) VALUES ($1, $2, $3)
+         ON CONFLICT (domain) DO UPDATE SET reason = EXCLUDED.reason RETURNING *"
+    )
+    .bind(&domain)
+    .bind(req.reason.as_deref().map(str::trim).filter(|r| !r.is_empty()))
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_one(pool.get_ref())
+    .await;
+    match stored {
+        Ok(entry) => {
+            let _ = reload(pool.get_ref()).await;
+            HttpResponse::Ok().json(entry)
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "detections", responses((status = 200, description = "Success")))]
+#[delete("/settings/dns-blocklist/{id}")]
+pub async fn delete_dns_blocklist(pool: web::Data<Pool<Postgres>>, path: web::Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    match sqlx::query("DELETE FROM dns_blocklist WHERE id = $1").bind(id).execute(pool.get_ref()).await {
+        Ok(res) if res.rows_affected() == 0 => {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": "Blocklist entry not found" }))
+        }
+        Ok(_) => {
+            let _ = reload(pool.get_ref()).await;
+            HttpResponse::Ok().json(serde_json::json!({ "status": "deleted", "id": id }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
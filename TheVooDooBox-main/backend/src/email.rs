@@ -0,0 +1,657 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use mail_parser::{MessageParser, MimeHeaders};
+use regex::Regex;
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use crate::archive::{self, clean_name, sha256_file};
+use crate::task_queue::{QueuedAnalysis, TaskScheduler};
+use crate::{remnux, virustotal};
+
+// --- EMAIL SUBMISSIONS ---
+// .eml / .msg submissions: attachments and links become child tasks.
+
+const MAX_URLS: usize = 200;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Eml,
+    Msg,
+}
+
+/// An OLE file with MAPI streams, or text opening with RFC 822 headers.
+pub fn detect(path: &Path) -> Option<Format> {
+    let mut head = vec![0u8; 4096];
+    let n = std::fs::File::open(path).and_then(|mut f| f.read(&mut head)).ok()?;
+    head.truncate(n);
+
+    if head.starts_with(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]) {
+        let cf = cfb::open(path).ok()?;
+        let is_msg = cf.read_root_storage().any(|e| e.name() == "__properties_version1.0")
+            && cf.read_root_storage().any(|e| e.name().starts_with("__substg1.0_"));
+        return is_msg.then_some(Format::Msg);
+    }
+
+    let text = String::from_utf8_lossy(&head);
+    let first = text.lines().find(|l| !l.trim().is_empty())?;
+    let looks_like_header = first.split_once(':').is_some_and(|(name, _)| {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    });
+    if !looks_like_header {
+        return None;
+    }
+    const HEADERS: &[&str] = &["from:", "to:", "subject:", "date:", "received:", "message-id:", "mime-version:", "return-path:"];
+    let seen = text.lines()
+        .map(|l| l.to_ascii_lowercase())
+        .filter(|l| HEADERS.iter().any(|h| l.starts_with(h)))
+        .count();
+    (seen >= 3).then_some(Format::Eml)
+}
+
+pub struct Attachment {
+    pub name: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct ParsedEmail {
+    pub subject: Option<String>,
+    pub from: Option<String>,
+    pub from_name: Option<String>,
+    pub reply_to: Option<String>,
+    pub return_path: Option<String>,
+    pub to: Vec<String>,
+    pub date: Option<String>,
+    pub message_id: Option<String>,
+    /// Received headers, newest hop first.
+    pub received: Vec<String>,
+    pub authentication: Option<String>,
+    pub urls: Vec<String>,
+    pub attachments: Vec<Attachment>,
+}
+
+fn url_regex() -> Regex {
+    Regex::new(r#"(?i)\bhttps?://[^\s"'<>()\[\]{}\\]+"#).unwrap()
+}
+
+/// Links from the bodies, de-duplicated in order of appearance.
+fn extract_urls(bodies: &[String]) -> Vec<String> {
+    let re = url_regex();
+    let mut seen = HashSet::new();
+    let mut urls = Vec::new();
+    for body in bodies {
+        for m in re.find_iter(body) {
+            let url = m.as_str().replace("&amp;", "&");
+            let url = url.trim_end_matches(['.', ',', ';', ':', '!', '?']).to_string();
+            if seen.insert(url.clone()) {
+                urls.push(url);
+            }
+            if urls.len() >= MAX_URLS {
+                return urls;
+            }
+        }
+    }
+    urls
+}
+
+/// Fills the header fields from a parsed RFC 822 message (an .eml, or a .msg's transport headers).
+fn apply_headers(email: &mut ParsedEmail, message: &mail_parser::Message) {
+    email.subject = email.subject.take().or_else(|| message.subject().map(str::to_string));
+    if let Some(from) = message.from().and_then(|a| a.first()) {
+        email.from = from.address().map(str::to_string);
+        email.from_name = from.name().map(str::to_string);
+    }
+    email.reply_to = message.reply_to().and_then(|a| a.first()).and_then(|a| a.address()).map(str::to_string);
+    email.return_path = message.return_address().map(str::to_string);
+    if let Some(to) = message.to() {
+        email.to = to.iter().filter_map(|a| a.address()).map(str::to_string).collect();
+    }
+    email.date = message.date().map(|d| d.to_rfc3339());
+    email.message_id = message.message_id().map(str::to_string);
+    for (name, value) in message.headers_raw() {
+        let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+        if name.eq_ignore_ascii_case("Received") {
+            email.received.push(value);
+        } else if name.eq_ignore_ascii_case("Authentication-Results") && email.authentication.is_none() {
+            email.authentication = Some(value);
+        }
+    }
+}
+
+pub fn parse_eml(raw: &[u8]) -> Result<ParsedEmail, String> {
+    let message = MessageParser::default().parse(raw).ok_or("Not a parseable RFC 822 message")?;
+    let mut email = ParsedEmail::default();
+    apply_headers(&mut email, &message);
+
+    let mut bodies: Vec<String> = message.text_bodies().filter_map(|p| p.text_contents()).map(str::to_string).collect();
+    bodies.extend(message.html_bodies().filter_map(|p| p.text_contents()).map(str::to_string));
+    email.urls = extract_urls(&bodies);
+
+    for (i, part) in message.attachments().enumerate() {
+        let content_type = part.content_type()
+            .map(|c| format!("{}/{}", c.ctype(), c.subtype().unwrap_or("octet-stream")))
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+        // Forwarded messages come through as message/rfc822 parts; keep their raw bytes
+        let name = part.attachment_name()
+            .map(str::to_string)
+            .unwrap_or_else(|| if content_type == "message/rfc822" { format!("attached-{}.eml", i + 1) } else { format!("attachment-{}", i + 1) });
+        let data = match part.message() {
+            Some(inner) => inner.raw_message().to_vec(),
+            None => part.contents().to_vec(),
+        };
+        email.attachments.push(Attachment { name, content_type, data });
+    }
+    Ok(email)
+}
+
+/// A MAPI string property: Unicode (001F) or 8-bit (001E) variant of `tag` under `storage`.
+fn msg_string<F: Read + std::io::Seek>(cf: &mut cfb::CompoundFile<F>, storage: &str, tag: &str) -> Option<String> {
+    if let Some(raw) = msg_stream(cf, storage, &format!("{}001F", tag)) {
+        let units: Vec<u16> = raw.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+        return Some(String::from_utf16_lossy(&units).trim_end_matches('\0').to_string());
+    }
+    msg_stream(cf, storage, &format!("{}001E", tag))
+        .map(|raw| String::from_utf8_lossy(&raw).trim_end_matches('\0').to_string())
+}
+
+fn msg_stream<F: Read + std::io::Seek>(cf: &mut cfb::CompoundFile<F>, storage: &str, prop: &str) -> Option<Vec<u8>> {
+    let mut stream = cf.open_stream(format!("{}/__substg1.0_{}", storage.trim_end_matches('/'), prop)).ok()?;
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw).ok()?;
+    Some(raw)
+}
+
+/// Outlook .msg: MAPI properties, plus transport headers when present.
+pub fn parse_msg(path: &Path) -> Result<ParsedEmail, String> {
+    let mut cf = cfb::open(path).map_err(|e| format!("Not a readable .msg: {}", e))?;
+    let mut email = ParsedEmail::default();
+
+    if let Some(headers) = msg_string(&mut cf, "/", "007D") {
+        let with_body = format!("{}\r\n\r\n", headers.trim_end());
+        if let Some(message) = MessageParser::default().parse(with_body.as_bytes()) {
+            apply_headers(&mut email, &message);
+        }
+    }
+    email.subject = msg_string(&mut cf, "/", "0037").or(email.subject);
+    if email.from.is_none() {
+        email.from = msg_string(&mut cf, "/", "0C1F").or_else(|| msg_string(&mut cf, "/", "5D01"));
+        email.from_name = msg_string(&mut cf, "/", "0C1A");
+    }
+
+    let mut bodies = Vec::new();
+    bodies.extend(msg_string(&mut cf, "/", "1000"));
+    if let Some(html) = msg_stream(&mut cf, "/", "10130102") {
+        bodies.push(String::from_utf8_lossy(&html).into_owned());
+    }
+    email.urls = extract_urls(&bodies);
+
+    let storages: Vec<String> = cf.read_root_storage()
+        .filter(|e| e.is_storage() && e.name().starts_with("__attach_version1.0_"))
+        .map(|e| format!("/{}", e.name()))
+        .collect();
+    for (i, storage) in storages.iter().enumerate() {
+        // Embedded messages/OLE objects (no 3701 binary) are not pulled out
+        let Some(data) = msg_stream(&mut cf, storage, "37010102") else { continue };
+        let name = msg_string(&mut cf, storage, "3707")
+            .or_else(|| msg_string(&mut cf, storage, "3704"))
+            .filter(|n| !n.is_empty())
+            .unwrap_or_else(|| format!("attachment-{}", i + 1));
+        let content_type = msg_string(&mut cf, storage, "370E").unwrap_or_else(|| "application/octet-stream".to_string());
+        email.attachments.push(Attachment { name, content_type, data });
+    }
+    Ok(email)
+}
+
+fn domain_of(address: &str) -> Option<String> {
+    address.rsplit_once('@').map(|(_, d)| d.trim_end_matches('>').to_lowercase())
+}
+
+fn url_host(url: &str) -> Option<String> {
+    let rest = url.split_once("://")?.1;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit_once('@').map(|(_, h)| h).unwrap_or(authority);
+    let host = host.rsplit_once(':').filter(|(_, p)| p.chars().all(|c| c.is_ascii_digit())).map(|(h, _)| h).unwrap_or(host);
+    Some(host.to_lowercase()).filter(|h| !h.is_empty())
+}
+
+const RISKY_ATTACHMENT_EXTS: &[&str] = &[
+    "exe", "scr", "com", "pif", "cpl", "dll", "js", "jse", "vbs", "vbe", "wsf", "hta", "ps1", "bat",
+    "cmd", "lnk", "jar", "iso", "img", "vhd", "vhdx", "one", "docm", "xlsm", "pptm", "html", "htm",
+    "svg", "zip", "rar", "7z",
+];
+
+/// Header and content findings that usually mean phishing, for the combined view.
+pub fn indicators(email: &ParsedEmail) -> Vec<String> {
+    let mut found = Vec::new();
+    let auth = email.authentication.as_deref().unwrap_or("").to_lowercase();
+    for check in ["spf", "dkim", "dmarc"] {
+        for verdict in ["fail", "softfail", "permerror"] {
+            if auth.contains(&format!("{}={}", check, verdict)) {
+                found.push(format!("{} {}", check.to_uppercase(), verdict));
+                break;
+            }
+        }
+    }
+
+    let from_domain = email.from.as_deref().and_then(domain_of);
+    if let Some(from_domain) = &from_domain {
+        if let Some(reply) = email.reply_to.as_deref().and_then(domain_of).filter(|d| d != from_domain) {
+            found.push(format!("Reply-To domain {} differs from From domain {}", reply, from_domain));
+        }
+        if let Some(ret) = email.return_path.as_deref().and_then(domain_of).filter(|d| d != from_domain) {
+            found.push(format!("Return-Path domain {} differs from From domain {}", ret, from_domain));
+        }
+    }
+    if let (Some(name), Some(from)) = (&email.from_name, &email.from) {
+        if name.contains('@') && !name.to_lowercase().contains(&from.to_lowercase()) {
+            found.push(format!("Display name '{}' shows a different address than {}", name, from));
+        }
+    }
+
+    for url in &email.urls {
+        if let Some(host) = url_host(url) {
+            if host.parse::<std::net::IpAddr>().is_ok() || host.trim_matches(['[', ']']).parse::<std::net::Ipv6Addr>().is_ok() {
+                found.push(format!("Link to a bare IP address: {}", url));
+            } else if host.starts_with("xn--") || host.contains(".xn--") {
+                found.push(format!("Link to a punycode domain: {}", host));
+            }
+        }
+    }
+
+    for attachment in &email.attachments {
+        let lower = attachment.name.to_lowercase();
+        let parts: Vec<&str> = lower.split('.').collect();
+        let ext = parts.last().copied().unwrap_or("");
+        if parts.len() > 2 && RISKY_ATTACHMENT_EXTS.contains(&ext) {
+            found.push(format!("Double extension on attachment {}", attachment.name));
+        } else if RISKY_ATTACHMENT_EXTS.contains(&ext) {
+            found.push(format!("Risky attachment type: {}", attachment.name));
+        }
+    }
+    found
+}
+
+/// The links worth detonating: one per host, skipping ignored domains and static assets.
+fn notable_urls(urls: &[String]) -> Vec<String> {
+    let limit = std::env::var("EMAIL_MAX_URL_TASKS").ok().and_then(|v| v.trim().parse().ok()).unwrap_or(5);
+    let ignored: Vec<String> = std::env::var("EMAIL_URL_IGNORE").unwrap_or_default()
+        .split(',')
+        .map(|d| d.trim().to_lowercase())
+        .filter(|d| !d.is_empty())
+        .collect();
+    const STATIC_EXTS: &[&str] = &[".png", ".jpg", ".jpeg", ".gif", ".css", ".woff", ".woff2", ".ico", ".bmp"];
+
+    let mut hosts = HashSet::new();
+    urls.iter()
+        .filter(|u| {
+            let path = u.split(['?', '#']).next().unwrap_or(u).to_lowercase();
+            !STATIC_EXTS.iter().any(|ext| path.ends_with(ext))
+        })
+        .filter_map(|u| url_host(u).map(|h| (u, h)))
+        .filter(|(_, h)| !ignored.iter().any(|d| h == d || h.ends_with(&format!(".{}", d))))
+        .filter(|(_, h)| hosts.insert(h.clone()))
+        .map(|(u, _)| u.clone())
+        .take(limit)
+        .collect()
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS email_analyses (
+            task_id TEXT PRIMARY KEY,
+            format TEXT NOT NULL,
+            subject TEXT,
+            sender TEXT,
+            sender_name TEXT,
+            reply_to TEXT,
+            return_path TEXT,
+            recipients JSONB,
+            sent_at TEXT,
+            message_id TEXT,
+            received JSONB,
+            authentication TEXT,
+            urls JSONB,
+            attachments JSONB,
+            indicators JSONB,
+            created_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+    println!("[EMAIL] Database initialized (email_analyses).");
+    Ok(())
+}
+
+/// Everything a child task inherits from the email submission.
+pub struct ChildSettings<'a> {
+    pub base: &'a QueuedAnalysis,
+    pub host_ip: &'a str,
+    pub static_analysis: bool,
+}
+
+/// Avoids one attachment overwriting another (or an earlier upload) of the same name.
+fn attachment_filename(task_id: &str, name: &str, taken: &mut HashSet<String>) -> String {
+    let clean = clean_name(name);
+    let clean = if clean.is_empty() { "attachment".to_string() } else { clean };
+    let mut candidate = format!("{}_{}", task_id, clean);
+    let mut n = 1;
+    while !taken.insert(candidate.clone()) {
+        candidate = format!("{}_{}_{}", task_id, n, clean);
+        n += 1;
+    }
+    candidate
+}
+
+fn child_job(base: &QueuedAnalysis, task_id: &str, target_url: String, original_filename: String, is_url_task: bool) -> QueuedAnalysis {
+    QueuedAnalysis {
+        task_id: task_id.to_string(),
+        target_url,
+        original_filename,
+        duration_seconds: base.duration_seconds,
+        vmid: None,
+        node: None,
+        is_url_task,
+        analysis_mode: base.analysis_mode.clone(),
+        os_profile: base.os_profile.clone(),
+        priority: base.priority.clone(),
+        profile: base.profile.clone(),
+    }
+}
+
+/// Parses the email and queues a child task per attachment and notable link.
+pub async fn fan_out(
+    pool: &Pool<Postgres>,
+    scheduler: &TaskScheduler,
+    parent_task_id: &str,
+    path: &Path,
+    format: Format,
+    child: ChildSettings<'_>,
+) -> Result<serde_json::Value, String> {
+    let parse_path = path.to_path_buf();
+    let email = web::block(move || match format {
+        Format::Eml => std::fs::read(&parse_path).map_err(|e| e.to_string()).and_then(|raw| parse_eml(&raw)),
+        Format::Msg => parse_msg(&parse_path),
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    let findings = indicators(&email);
+
+    sqlx::query("UPDATE tasks SET status='Email' WHERE id=$1")
+        .bind(parent_task_id)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let upload_dir = PathBuf::from("./uploads");
+    let mut taken = HashSet::new();
+    let mut attachments_json = Vec::new();
+    let mut child_ids = Vec::new();
+
+    for (i, attachment) in email.attachments.iter().enumerate() {
+        let task_id = format!("{}-a{}", parent_task_id, i + 1);
+        let mut filename = attachment_filename(parent_task_id, &attachment.name, &mut taken);
+        let mut original_filename = clean_name(&attachment.name);
+        let dest = upload_dir.join(&filename);
+        if let Err(e) = tokio::fs::write(&dest, &attachment.data).await {
+            println!("[EMAIL] Failed to save attachment {}: {}", attachment.name, e);
+            continue;
+        }
+        let hash_path = dest.clone();
+        let mut sha256 = web::block(move || sha256_file(&hash_path)).await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())?;
+        let mut container = None;
+
+        // Zipped/ISO'd payloads are the norm; detonate what is inside when it is obvious
+        if let Some(kind) = archive::detect(&dest) {
+            let picked = archive::unpack_submission(dest.clone(), original_filename.clone(), sha256.clone(), kind, archive::DEFAULT_PASSWORD.to_string(), None).await;
+            if let Ok(archive::Selection::Picked(picked)) = picked {
+                filename = picked.filename;
+                original_filename = picked.original_filename;
+                sha256 = picked.sha256;
+                container = Some(picked.container);
+            }
+        }
+
+        let file_path = format!("./uploads/{}", filename);
+        crate::storage::persist(&format!("uploads/{}", filename)).await;
+        if let Err(e) = sqlx::query(
+            "INSERT INTO tasks (id, filename, original_filename, file_hash, status, created_at, file_path, parent_task_id, internet_policy)
+             SELECT $1, $2, $3, $4, 'Queued', $5, $6, id, internet_policy FROM tasks WHERE id = $7"
+        )
+        .bind(&task_id)
+        .bind(&filename)
+        .bind(&original_filename)
+        .bind(&sha256)
+        .bind(chrono::Utc::now().timestamp_millis())
+        .bind(&file_path)
+        .bind(parent_task_id)
+        .execute(pool)
+        .await {
+            println!("[EMAIL] Failed to create task for attachment {}: {}", attachment.name, e);
+            continue;
+        }
+        if let Some(container) = &container {
+            archive::record(pool, &task_id, container).await;
+        }
+        crate::relationships::link(pool, parent_task_id, &task_id, "email_attachment", Some(&attachment.name)).await;
+
+        let vt_pool = pool.clone();
+        let vt_hash = sha256.clone();
+        actix_web::rt::spawn(async move {
+            let _ = virustotal::get_cached_or_fetch(&vt_pool, &vt_hash).await;
+        });
+        if child.static_analysis {
+            let (ghidra_pool, ghidra_name, ghidra_task) = (pool.clone(), filename.clone(), task_id.clone());
+            actix_web::rt::spawn(async move {
+                crate::trigger_ghidra_background(ghidra_name, ghidra_task, ghidra_pool).await;
+            });
+            let (remnux_pool, remnux_name, remnux_task, remnux_path) = (pool.clone(), filename.clone(), task_id.clone(), file_path.clone());
+            actix_web::rt::spawn(async move {
+                remnux::trigger_scan(remnux_pool, remnux_task, remnux_name, remnux_path).await;
+            });
+            crate::yara::queue_task(pool, &task_id);
+            crate::static_properties::queue(pool, &task_id);
+        }
+
+        let target_url = format!("http://{}:8080/uploads/{}", child.host_ip, filename);
+        let queued = scheduler.enqueue(child_job(child.base, &task_id, target_url, original_filename.clone(), false)).await;
+        if let Err(e) = queued {
+            println!("[EMAIL] Failed to queue attachment task {}: {}", task_id, e);
+            continue;
+        }
+        attachments_json.push(serde_json::json!({
+            "name": attachment.name,
+            "content_type": attachment.content_type,
+            "size": attachment.data.len(),
+            "sha256": sha256,
+            "detonated_as": container.as_ref().map(|_| original_filename.clone()),
+            "task_id": task_id,
+        }));
+        child_ids.push(task_id);
+    }
+
+    let notable = notable_urls(&email.urls);
+    let mut url_tasks = std::collections::HashMap::new();
+    for (i, url) in notable.iter().enumerate() {
+        let task_id = format!("{}-u{}", parent_task_id, i + 1);
+        let display = if url.len() > 100 { format!("{}...", url.chars().take(97).collect::<String>()) } else { url.clone() };
+        if let Err(e) = sqlx::query(
+            "INSERT INTO tasks (id, filename, original_filename, file_hash, status, created_at, parent_task_id, internet_policy)
+             SELECT $1, $2, $3, 'N/A', 'Queued', $4, id, internet_policy FROM tasks WHERE id = $5"
+        )
+        .bind(&task_id)
+        .bind(format!("URL: {}", display))
+        .bind(url)
+        .bind(chrono::Utc::now().timestamp_millis())
+        .bind(parent_task_id)
+        .execute(pool)
+        .await {
+            println!("[EMAIL] Failed to create task for link {}: {}", url, e);
+            continue;
+        }
+        crate::relationships::link(pool, parent_task_id, &task_id, "email_link", Some(url)).await;
+        if let Err(e) = scheduler.enqueue(child_job(child.base, &task_id, url.clone(), "URL_Detonation".to_string(), true)).await {
+            println!("[EMAIL] Failed to queue link task {}: {}", task_id, e);
+            continue;
+        }
+        url_tasks.insert(url.clone(), task_id.clone());
+        child_ids.push(task_id);
+    }
+    let urls_json: Vec<serde_json::Value> = email.urls.iter()
+        .map(|u| serde_json::json!({ "url": u, "task_id": url_tasks.get(u) }))
+        .collect();
+
+    let res = sqlx::query(
+        "INSERT INTO email_analyses (task_id, format, subject, sender, sender_name, reply_to, return_path, recipients, sent_at, message_id, received, authentication, urls, attachments, indicators, created_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+         ON CONFLICT (task_id) DO NOTHING"
+    )
+    .bind(parent_task_id)
+    .bind(if format == Format::Msg { "msg" } else { "eml" })
+    .bind(&email.subject)
+    .bind(&email.from)
+    .bind(&email.from_name)
+    .bind(&email.reply_to)
+    .bind(&email.return_path)
+    .bind(serde_json::json!(email.to))
+    .bind(&email.date)
+    .bind(&email.message_id)
+    .bind(serde_json::json!(email.received))
+    .bind(&email.authentication)
+    .bind(serde_json::json!(urls_json))
+    .bind(serde_json::json!(attachments_json))
+    .bind(serde_json::json!(findings))
+    .bind(chrono::Utc::now().timestamp_millis())
+    .execute(pool)
+    .await;
+    if let Err(e) = res {
+        println!("[EMAIL] Failed to store analysis for task {}: {}", parent_task_id, e);
+    }
+
+    // Nothing to detonate: the header analysis is the whole result
+    if child_ids.is_empty() {
+        let _ = sqlx::query("UPDATE tasks SET status='Completed', completed_at=$2 WHERE id=$1")
+            .bind(parent_task_id)
+            .bind(chrono::Utc::now().timestamp_millis())
+            .execute(pool)
+            .await;
+    }
+
+    println!("[EMAIL] Task {}: {} attachment(s), {} link(s) ({} detonated), {} indicator(s).",
+        parent_task_id, email.attachments.len(), email.urls.len(), url_tasks.len(), findings.len());
+    Ok(serde_json::json!({
+        "status": "email_queued",
+        "task_id": parent_task_id,
+        "subject": email.subject,
+        "from": email.from,
+        "child_task_ids": child_ids,
+        "indicators": findings,
+        "message": "Email parsed; attachments and notable links were queued as child tasks"
+    }))
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct EmailChild {
+    pub task_id: String,
+    pub target: Option<String>,
+    pub status: String,
+    pub verdict: Option<String>,
+    pub risk_score: Option<i32>,
+}
+
+#[derive(sqlx::FromRow)]
+struct EmailRow {
+    format: String,
+    subject: Option<String>,
+    sender: Option<String>,
+    sender_name: Option<String>,
+    reply_to: Option<String>,
+    return_path: Option<String>,
+    recipients: Option<serde_json::Value>,
+    sent_at: Option<String>,
+    message_id: Option<String>,
+    received: Option<serde_json::Value>,
+    authentication: Option<String>,
+    urls: Option<serde_json::Value>,
+    attachments: Option<serde_json::Value>,
+    indicators: Option<serde_json::Value>,
+}
+
+fn verdict_rank(verdict: Option<&str>) -> u8 {
+    match verdict.map(|v| v.to_lowercase()) {
+        Some(v) if v.contains("malicious") => 3,
+        Some(v) if v.contains("suspicious") => 2,
+        Some(v) if v.contains("benign") || v.contains("clean") => 1,
+        _ => 0,
+    }
+}
+
+/// Header findings, links, attachments and the verdicts of their child tasks.
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
+#[get("/tasks/{id}/email")]
+pub async fn get_email_analysis(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let task_id = path.into_inner();
+    let row = match sqlx::query_as::<_, EmailRow>(
+        "SELECT format, subject, sender, sender_name, reply_to, return_path, recipients, sent_at, message_id, received, authentication, urls, attachments, indicators
+         FROM email_analyses WHERE task_id = $1"
+    )
+    .bind(&task_id)
+    .fetch_optional(pool.get_ref())
+    .await {
+        Ok(Some(r)) => r,
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "No email analysis for this task" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+
+    let status = sqlx::query_scalar::<_, String>("SELECT status FROM tasks WHERE id = $1")
+        .bind(&task_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| "Unknown".to_string());
+    let children = sqlx::query_as::<_, EmailChild>(
+        "SELECT id AS task_id, original_filename AS target, status, verdict, risk_score FROM tasks WHERE parent_task_id = $1 ORDER BY id"
+    )
+    .bind(&task_id)
+    .fetch_all(pool.get_ref())
+    .await
+    .unwrap_or_default();
+
+    let worst = children.iter().max_by_key(|c| (verdict_rank(c.verdict.as_deref()), c.risk_score.unwrap_or(0)));
+    let verdict = worst.and_then(|c| c.verdict.clone()).or_else(|| {
+        let findings = row.indicators.as_ref().and_then(|i| i.as_array()).map(|a| a.len()).unwrap_or(0);
+        (findings > 0).then(|| "Suspicious".to_string())
+    });
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "task_id": task_id,
+        "status": status,
+        "format": row.format,
+        "verdict": verdict,
+        "risk_score": children.iter().filter_map(|c| c.risk_score).max(),
+        "headers": {
+            "subject": row.subject,
+            "from": row.sender,
+            "from_name": row.sender_name,
+            "reply_to": row.reply_to,
+            "return_path": row.return_path,
+            "to": row.recipients,
+            "date": row.sent_at,
+            "message_id": row.message_id,
+            "received": row.received,
+            "authentication_results": row.authentication,
+        },
+        "indicators": row.indicators,
+        "urls": row.urls,
+        "attachments": row.attachments,
+        "children": children,
+    }))
+}
@@ -0,0 +1,208 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::StreamExt;
+use serde::Deserialize;
+use sqlx::postgres::PgRow;
+use sqlx::{Pool, Postgres, Row};
+use std::io::Write;
+
+// --- RAW TELEMETRY EXPORT ---
+// Streams a task's full event set as CSV or JSON Lines.
+
+/// Exportable columns in their default order, with how to read each from a row.
+const COLUMNS: &[(&str, Kind)] = &[
+    ("id", Kind::Int),
+    ("timestamp", Kind::BigInt),
+    ("event_type", Kind::Text),
+    ("process_id", Kind::Int),
+    ("parent_process_id", Kind::Int),
+    ("process_name", Kind::Text),
+    ("details", Kind::Text),
+    ("decoded_details", Kind::Text),
+    ("severity", Kind::Int),
+    ("category", Kind::Text),
+    ("digital_signature", Kind::Text),
+    ("session_id", Kind::Text),
+    ("task_id", Kind::Text),
+];
+
+/// Bytes buffered before a chunk is handed to the response.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Int,
+    BigInt,
+    Text,
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    /// csv (default) or jsonl
+    pub format: Option<String>,
+    /// Comma-separated subset of columns, in the order wanted.
+    pub columns: Option<String>,
+    pub gzip: Option<bool>,
+    pub event_type: Option<String>,
+    pub min_severity: Option<i32>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+fn value(row: &PgRow, column: &str, kind: Kind) -> serde_json::Value {
+    match kind {
+        Kind::Int => row.try_get::<Option<i32>, _>(column).ok().flatten().into(),
+        Kind::BigInt => row.try_get::<Option<i64>, _>(column).ok().flatten().into(),
+        Kind::Text => row.try_get::<Option<String>, _>(column).ok().flatten().into(),
+    }
+}
+
+fn csv_field(value: &serde_json::Value) -> String {
+    let text = match value {
+        serde_json::Value::Null => return String::new(),
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+/// Plain or gzip output that hands back whatever is ready to send.
+enum Sink {
+    Plain(Vec<u8>),
+    Gzip(GzEncoder<Vec<u8>>),
+}
+
+impl Sink {
+    fn write(&mut self, data: &[u8]) {
+        match self {
+            Sink::Plain(buf) => buf.extend_from_slice(data),
+            Sink::Gzip(encoder) => { let _ = encoder.write_all(data); }
+        }
+    }
+
+    fn take(&mut self, min: usize) -> Option<Vec<u8>> {
+        let buf = match self {
+            Sink::Plain(buf) => buf,
+            Sink::Gzip(encoder) => encoder.get_mut(),
+        };
+        (buf.len() >= min.max(1)).then(|| std::mem::take(buf))
+    }
+
+    fn finish(self) -> Vec<u8> {
+        match self {
+            Sink::Plain(buf) => buf,
+            Sink::Gzip(encoder) => encoder.finish().unwrap_or_default(),
+        }
+    }
+}
+
+#[utoipa::path(tag = "telemetry", responses((status = 200, description = "Success")))]
+#[get("/tasks/{id}/events/export")]
+pub async fn export_events(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+    query: web::Query<ExportQuery>,
+) -> impl Responder {
+    let task_id = path.into_inner();
+    let jsonl = match query.format.as_deref().unwrap_or("csv") {
+        "csv" => false,
+        "jsonl" | "ndjson" => true,
+        other => return HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("Unsupported format '{}' (csv or jsonl)", other) })),
+    };
+    let columns: Vec<(&'static str, Kind)> = match &query.columns {
+        Some(list) => {
+            let mut picked = Vec::new();
+            for name in list.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                match COLUMNS.iter().find(|(c, _)| *c == name) {
+                    Some(column) => picked.push(*column),
+                    None => return HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("Unknown column '{}'", name) })),
+                }
+            }
+            picked
+        }
+        None => COLUMNS.to_vec(),
+    };
+    if columns.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "No columns selected" }));
+    }
+
+    let mut qb = sqlx::QueryBuilder::<Postgres>::new("SELECT ");
+    qb.push(columns.iter().map(|(c, _)| *c).collect::<Vec<_>>().join(", "));
+    qb.push(" FROM events WHERE task_id = ").push_bind(task_id.clone());
+    if let Some(types) = &query.event_type {
+        let types: Vec<String> = types.split(',').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect();
+        qb.push(" AND event_type = ANY(").push_bind(types).push(")");
+    }
+    if let Some(min) = query.min_severity {
+        qb.push(" AND COALESCE(severity, 0) >= ").push_bind(min);
+    }
+    if let Some(from) = query.from {
+        qb.push(" AND timestamp >= ").push_bind(from);
+    }
+    if let Some(to) = query.to {
+        qb.push(" AND timestamp <= ").push_bind(to);
+    }
+    qb.push(" ORDER BY timestamp ASC, id");
+
+    let gzip = query.gzip.unwrap_or(false);
+    let extension = if jsonl { "jsonl" } else { "csv" };
+    let (content_type, filename) = if gzip {
+        ("application/gzip", format!("{}_events.{}.gz", task_id, extension))
+    } else if jsonl {
+        ("application/x-ndjson", format!("{}_events.jsonl", task_id))
+    } else {
+        ("text/csv; charset=utf-8", format!("{}_events.csv", task_id))
+    };
+    let pool = pool.get_ref().clone();
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<web::Bytes, std::io::Error>>(8);
+    tokio::spawn(async move {
+        let mut sink = if gzip { Sink::Gzip(GzEncoder::new(Vec::new(), Compression::default())) } else { Sink::Plain(Vec::new()) };
+        if !jsonl {
+            sink.write(columns.iter().map(|(c, _)| *c).collect::<Vec<_>>().join(",").as_bytes());
+            sink.write(b"\n");
+        }
+
+        let mut rows = qb.build().fetch(&pool);
+        let mut count = 0u64;
+        while let Some(row) = rows.next().await {
+            let row = match row {
+                Ok(row) => row,
+                Err(e) => {
+                    // Headers are long gone; all that is left is to cut the stream short
+                    println!("[EXPORT] Event export for task {} failed after {} rows: {}", task_id, count, e);
+                    let _ = tx.send(Err(std::io::Error::other(e.to_string()))).await;
+                    return;
+                }
+            };
+            let line = if jsonl {
+                let object: serde_json::Map<String, serde_json::Value> = columns.iter()
+                    .map(|(c, k)| (c.to_string(), value(&row, c, *k)))
+                    .collect();
+                serde_json::Value::Object(object).to_string()
+            } else {
+                columns.iter().map(|(c, k)| csv_field(&value(&row, c, *k))).collect::<Vec<_>>().join(",")
+            };
+            sink.write(line.as_bytes());
+            sink.write(b"\n");
+            count += 1;
+            if let Some(chunk) = sink.take(CHUNK_SIZE) {
+                if tx.send(Ok(web::Bytes::from(chunk))).await.is_err() {
+                    return; // client went away
+                }
+            }
+        }
+        drop(rows);
+        let _ = tx.send(Ok(web::Bytes::from(sink.finish()))).await;
+    });
+
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .streaming(tokio_stream::wrappers::ReceiverStream::new(rx))
+}
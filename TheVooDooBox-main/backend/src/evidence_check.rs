@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::collections::HashSet;
+use crate::ai_analysis::ForensicReport;
+
+// --- AI REPORT EVIDENCE CHECK ---
+// AI report claims checked against the recorded telemetry.
+
+/// Prefixed to the technical_context of timeline entries whose PID was never seen.
+pub const UNVERIFIED: &str = "[UNVERIFIED]";
+
+const NETWORK_EVENTS: &[&str] = &[
+    "NETWORK_CONNECT", "NETWORK_DNS", "NETWORK_HTTP", "NETWORK_FAKENET", "LATERAL_MOVEMENT", "HTTP_REQUEST",
+    "BROWSER_NAVIGATE", "BROWSER_REDIRECT", "URL_OPEN", "DOWNLOAD_DETECTED",
+];
+
+const FILE_EVENTS: &[&str] = &[
+    "FILE_CREATE", "FILE_MODIFY", "FILE_VERIFIED", "DOWNLOAD_DETECTED", "ADS_CREATED", "TIMESTOMP_DETECTED",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct EvidenceCheck {
+    /// Claims looked up in the telemetry
+    pub checked: usize,
+    /// The ones with nothing behind them, e.g. "C2 domain: evil.example"
+    pub unverified: Vec<String>,
+    /// Share of checked claims that were unverified, 0-100
+    pub hallucination_score: i32,
+}
+
+/// Host part of a claimed domain or IP: no scheme, path or port.
+fn host(claim: &str) -> String {
+    let claim = claim.trim();
+    let claim = claim.split_once("://").map_or(claim, |(_, rest)| rest);
+    let claim = claim.split('/').next().unwrap_or_default();
+    // host:port, but not an IPv6 address
+    let claim = match claim.rsplit_once(':') {
+        Some((h, port)) if !h.contains(':') && port.chars().all(|c| c.is_ascii_digit()) => h,
+        _ => claim,
+    };
+    claim.to_lowercase()
+}
+
+/// Last component of a Windows or POSIX path.
+fn file_name(claim: &str) -> String {
+    claim.trim().rsplit(['\\', '/']).next().unwrap_or_default().to_lowercase()
+}
+
+/// Whether `needle` occurs in the details of any of the task's `event_types` events.
+async fn seen(pool: &Pool<Postgres>, task_id: &str, event_types: &[&str], needle: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM events WHERE task_id = $1 AND event_type = ANY($2)
+           AND (POSITION($3 IN LOWER(details)) > 0 OR POSITION($3 IN LOWER(COALESCE(decoded_details, ''))) > 0))"
+    )
+    .bind(task_id)
+    .bind(event_types)
+    .bind(needle)
+    .fetch_one(pool)
+    .await
+}
+
+/// Checks `report` against the task's telemetry, marking or dropping what isn't supported.
+pub async fn check(pool: &Pool<Postgres>, task_id: &str, report: &mut ForensicReport) -> Result<EvidenceCheck, sqlx::Error> {
+    let mut result = EvidenceCheck::default();
+
+    let pids: HashSet<i32> = sqlx::query_scalar::<_, i32>(
+        "SELECT process_id FROM events WHERE task_id = $1 UNION SELECT parent_process_id FROM events WHERE task_id = $1"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+    for event in report.behavioral_timeline.iter_mut().filter(|e| e.related_pid > 0) {
+        result.checked += 1;
+        if !pids.contains(&event.related_pid) {
+            result.unverified.push(format!("PID {}: {}", event.related_pid, event.event_description));
+            crate::metrics::inc("voodoobox_ai_unverified_claims_total", &[("kind", "pid")], 1.0);
+            event.technical_context = format!("{} PID {} does not appear in the telemetry. {}", UNVERIFIED, event.related_pid, event.technical_context);
+        }
+    }
+
+    let artifacts = &mut report.artifacts;
+    for (kind, label, claims, event_types, normalize) in [
+        ("c2_domain", "C2 domain", &mut artifacts.c2_domains, NETWORK_EVENTS, host as fn(&str) -> String),
+        ("c2_ip", "C2 IP", &mut artifacts.c2_ips, NETWORK_EVENTS, host),
+        ("dropped_file", "Dropped file", &mut artifacts.dropped_files, FILE_EVENTS, file_name),
+    ] {
+        let mut kept = Vec::with_capacity(claims.len());
+        for claim in claims.drain(..) {
+            let needle = normalize(&claim);
+            if needle.is_empty() {
+                continue;
+            }
+            result.checked += 1;
+            if seen(pool, task_id, event_types, &needle).await? {
+                kept.push(claim);
+            } else {
+                result.unverified.push(format!("{}: {}", label, claim));
+                crate::metrics::inc("voodoobox_ai_unverified_claims_total", &[("kind", kind)], 1.0);
+            }
+        }
+        *claims = kept;
+    }
+
+    result.hallucination_score = (result.unverified.len() * 100).checked_div(result.checked).unwrap_or(0) as i32;
+    Ok(result)
+}
@@ -0,0 +1,203 @@
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+// --- FAKE-NET (INetSim) CORRELATION ---
+// INetSim service.log (FAKENET_LOG) folded into the task's network events.
+
+const MAX_EVENTS_PER_TASK: usize = 2000;
+
+fn log_path() -> String {
+    std::env::var("FAKENET_LOG").unwrap_or_else(|_| "/var/log/inetsim/service.log".to_string())
+}
+
+/// Log position when the detonation started.
+pub struct Capture {
+    offset: u64,
+}
+
+impl Capture {
+    pub fn begin() -> Self {
+        let offset = std::fs::metadata(log_path()).map(|m| m.len()).unwrap_or(0);
+        Capture { offset }
+    }
+}
+
+/// One INetSim service.log line.
+struct Exchange<'a> {
+    timestamp: i64,
+    service: &'a str,
+    port: &'a str,
+    client_ip: &'a str,
+    client_port: &'a str,
+    kind: &'a str,
+    message: &'a str,
+}
+
+fn parse_line(line: &str) -> Option<Exchange<'_>> {
+    let mut groups = Vec::with_capacity(4);
+    let mut rest = line;
+    for _ in 0..4 {
+        let inner = rest.trim_start().strip_prefix('[')?;
+        let end = inner.find(']')?;
+        groups.push(&inner[..end]);
+        rest = &inner[end + 1..];
+    }
+    let (kind, message) = rest.trim().split_once(": ")?;
+    // "http_80_tcp 4251" -> service http, port 80
+    let mut service_parts = groups[2].split_whitespace().next()?.split('_');
+    let service = service_parts.next()?;
+    let port = service_parts.next().unwrap_or("");
+    let (client_ip, client_port) = groups[3].rsplit_once(':').unwrap_or((groups[3], ""));
+    let timestamp = chrono::NaiveDateTime::parse_from_str(groups[0], "%Y-%m-%d %H:%M:%S")
+        .map(|t| t.and_utc().timestamp_millis())
+        .unwrap_or_else(|_| chrono::Utc::now().timestamp_millis());
+    Some(Exchange { timestamp, service, port, client_ip, client_port, kind, message })
+}
+
+/// `key=value` out of a stat line ("stat: 1 method=GET url=http://x/y sent=... postdata=").
+fn stat_field<'a>(message: &'a str, key: &str) -> Option<&'a str> {
+    message.split_whitespace()
+        .find_map(|part| part.strip_prefix(key).and_then(|v| v.strip_prefix('=')))
+        .filter(|v| !v.is_empty())
+}
+
+/// (event_type, details, decoded_details) for DNS queries, HTTP summaries and client commands.
+fn to_event(x: &Exchange) -> Option<(&'static str, String, Option<String>)> {
+    let service = x.service.to_uppercase();
+    match (x.service, x.kind) {
+        ("dns", "recv") => {
+            // "Query Type A, Class IN, Name www.example.com"
+            let name = x.message.rsplit_once("Name ")?.1.trim();
+            let qtype = x.message.split(',').next()?.trim_start_matches("Query Type").trim();
+            Some(("NETWORK_DNS", format!("FAKENET: DNS {} -> {}", qtype, name), None))
+        }
+        ("http" | "https", "stat") => {
+            let url = stat_field(x.message, "url")?;
+            let method = stat_field(x.message, "method").unwrap_or("GET");
+            let host = url.split("://").nth(1).unwrap_or(url).split('/').next().unwrap_or(url);
+            let mut decoded = format!("{} {}", method, url);
+            if let Some(post) = stat_field(x.message, "postdata") {
+                decoded.push_str(&format!(" postdata={}", post));
+            }
+            if let Some(sent) = stat_field(x.message, "sent") {
+                decoded.push_str(&format!(" served={}", sent.rsplit('/').next().unwrap_or(sent)));
+            }
+            let dest = if host.contains(':') { host.to_string() } else { format!("{}:{}", host, x.port) };
+            Some(("NETWORK_HTTP", format!("FAKENET: {} {} -> {}", service, method, dest), Some(decoded)))
+        }
+        ("dns" | "http" | "https", _) => None,
+        (_, "recv") => Some((
+            "NETWORK_FAKENET",
+            format!("FAKENET: {} -> {}:{}", service, x.service, x.port),
+            Some(x.message.to_string()),
+        )),
+        _ => None,
+    }
+}
+
+/// Appended since `offset`; a rotated log is read whole.
+pub async fn read_log_since(path: &str, offset: u64) -> std::io::Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let len = file.metadata().await?.len();
+    file.seek(std::io::SeekFrom::Start(if len < offset { 0 } else { offset })).await?;
+    let mut raw = Vec::new();
+    file.read_to_end(&mut raw).await?;
+    Ok(String::from_utf8_lossy(&raw).into_owned())
+}
+
+/// The guest process behind a connection from `ip:port`, from the agent's socket telemetry.
+pub async fn connection_owner(pool: &Pool<Postgres>, task_id: &str, ip: &str, port: &str) -> Option<(i32, String)> {
+    sqlx::query_as::<_, (i32, String)>(
+        "SELECT process_id, process_name FROM events
+         WHERE task_id = $1 AND event_type = 'NETWORK_CONNECT' AND details LIKE $2
+         ORDER BY timestamp DESC LIMIT 1"
+    )
+    .bind(task_id)
+    .bind(format!("% {}:{} ->%", ip, port))
+    .fetch_optional(pool)
+    .await
+    .ok()
+    .flatten()
+}
+
+/// Stores `sandbox_ip`'s exchanges since `capture` as task events; returns the count.
+pub async fn collect(pool: &Pool<Postgres>, task_id: &str, capture: &Capture, sandbox_ip: &str) -> usize {
+    let path = log_path();
+    let text = match read_log_since(&path, capture.offset).await {
+        Ok(t) => t,
+        Err(e) => {
+            println!("[FAKENET] Cannot read INetSim log {}: {}", path, e);
+            return 0;
+        }
+    };
+
+    // client port -> (pid, process) from the agent's connection telemetry
+    let mut owners: HashMap<String, Option<(i32, String)>> = HashMap::new();
+    let mut recorded = 0;
+
+    for line in text.lines() {
+        if recorded >= MAX_EVENTS_PER_TASK {
+            println!("[FAKENET] Task {} hit the {} event cap; remaining exchanges dropped", task_id, MAX_EVENTS_PER_TASK);
+            break;
+        }
+        let Some(x) = parse_line(line) else { continue };
+        if x.client_ip != sandbox_ip {
+            continue;
+        }
+        let Some((event_type, details, decoded)) = to_event(&x) else { continue };
+
+        if !owners.contains_key(x.client_port) {
+            let owner = connection_owner(pool, task_id, sandbox_ip, x.client_port).await;
+            owners.insert(x.client_port.to_string(), owner);
+        }
+        let (pid, process_name) = owners[x.client_port].clone().unwrap_or((0, "fakenet".to_string()));
+
+        let res: Result<i32, sqlx::Error> = sqlx::query_scalar(
+            "INSERT INTO events (event_type, process_id, parent_process_id, process_name, details, decoded_details, timestamp, task_id, severity, category, kill_chain_stage)
+             VALUES ($1, $2, 0, $3, $4, $5, $6, $7, 30, 'network', $8)
+             RETURNING id"
+        )
+        .bind(event_type)
+        .bind(pid)
+        .bind(&process_name)
+        .bind(&details)
+        .bind(&decoded)
+        .bind(x.timestamp)
+        .bind(task_id)
+        .bind(crate::kill_chain::classify(event_type, &process_name, &details, decoded.as_deref(), Some("network")))
+        .fetch_one(pool)
+        .await;
+        match res {
+            Ok(id) => {
+                recorded += 1;
+                crate::metrics::inc("voodoobox_events_ingested_total", &[("source", "fakenet")], 1.0);
+                crate::siem::forward(crate::siem::Record {
+                    task_id: Some(task_id.to_string()),
+                    pid: Some(pid),
+                    process_name: Some(process_name.clone()),
+                    severity: 30,
+                    ..crate::siem::Record::event(event_type, &details, x.timestamp)
+                });
+                let refs = [crate::sigma::EventRef {
+                    id: Some(id),
+                    task_id: Some(task_id),
+                    event_type: event_type,
+                    process_id: pid,
+                    parent_process_id: 0,
+                    process_name: &process_name,
+                    details: &details,
+                    decoded_details: decoded.as_deref(),
+                    category: Some("network"),
+                    timestamp: x.timestamp,
+                }];
+                crate::sigma::evaluate(pool, None, &refs).await;
+                crate::dns_reputation::evaluate(pool, None, &refs).await;
+            }
+            Err(e) => println!("[FAKENET] Failed to store exchange for task {}: {}", task_id, e),
+        }
+    }
+
+    println!("[FAKENET] Task {}: {} simulated-service exchanges from {} recorded", task_id, recorded, sandbox_ip);
+    recorded
+}
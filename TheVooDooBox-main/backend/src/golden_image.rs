@@ -0,0 +1,398 @@
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::hypervisor::Hypervisor;
+use crate::{guest_agent, sandbox_pool, AgentManager};
+
+// --- GOLDEN IMAGE LIFECYCLE ---
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct GoldenImageJob {
+    pub id: i32,
+    pub vmid: i64,
+    pub node: String,
+    pub snapshot: String,
+    pub base_snapshot: Option<String>,
+    /// queued | running | completed | failed
+    pub state: String,
+    pub step: Option<String>,
+    /// Output of each preparation script, in order.
+    pub log: serde_json::Value,
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Deserialize)]
+pub struct GoldenImageRequest {
+    /// Required for VMs not in the sandbox pool.
+    pub node: Option<String>,
+    /// Snapshot to write; defaults to the pool entry's snapshot (or SANDBOX_SNAPSHOT).
+    pub snapshot: Option<String>,
+    /// Revert to this snapshot before booting; omit to build from the VM's current disk.
+    pub base_snapshot: Option<String>,
+    /// PowerShell run as SYSTEM in the guest, in order; a non-zero exit aborts the build.
+    #[serde(default)]
+    pub prep_scripts: Vec<String>,
+    /// Seconds each script may run (default 1800).
+    pub script_timeout: Option<u64>,
+    pub description: Option<String>,
+    /// Overwrite an existing snapshot with the same name.
+    #[serde(default)]
+    pub replace: bool,
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS golden_image_jobs (
+            id SERIAL PRIMARY KEY,
+            vmid BIGINT NOT NULL,
+            node TEXT NOT NULL,
+            snapshot TEXT NOT NULL,
+            base_snapshot TEXT,
+            state TEXT NOT NULL DEFAULT 'queued',
+            step TEXT,
+            log JSONB NOT NULL DEFAULT '[]',
+            error TEXT,
+            created_at BIGINT NOT NULL,
+            updated_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    // Builds run in-process; anything still marked active was cut off by a restart
+    sqlx::query("UPDATE golden_image_jobs SET state='failed', error='Interrupted by backend restart' WHERE state IN ('queued', 'running')")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+const SELECT_COLUMNS: &str = "id, vmid, node, snapshot, base_snapshot, state, step, log, error, created_at, updated_at";
+
+fn agent_timeout() -> u64 {
+    std::env::var("GOLDEN_AGENT_TIMEOUT").ok().and_then(|v| v.trim().parse().ok()).unwrap_or(600)
+}
+
+async fn set_step(pool: &Pool<Postgres>, job_id: i32, step: &str) {
+    println!("[GOLDEN] Job {}: {}", job_id, step);
+    let _ = sqlx::query("UPDATE golden_image_jobs SET state='running', step=$2, updated_at=$3 WHERE id=$1")
+        .bind(job_id)
+        .bind(step)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(pool)
+        .await;
+}
+
+async fn append_log(pool: &Pool<Postgres>, job_id: i32, entry: serde_json::Value) {
+    let _ = sqlx::query("UPDATE golden_image_jobs SET log = log || $2, updated_at=$3 WHERE id=$1")
+        .bind(job_id)
+        .bind(serde_json::Value::Array(vec![entry]))
+        .bind(chrono::Utc::now().timestamp())
+        .execute(pool)
+        .await;
+}
+
+async fn vm_status(client: &dyn Hypervisor, node: &str, vmid: u64) -> Option<(String, Option<String>)> {
+    client.get_vms(node).await.ok()?
+        .into_iter()
+        .find(|v| v.vmid == vmid)
+        .map(|v| (v.status, v.name))
+}
+
+/// Waits for an agent that connected after `since` and, if it reports a hostname, is `vm_name`.
+async fn wait_for_agent(manager: &AgentManager, vm_name: Option<&str>, since: Instant, timeout: u64) -> Result<(), String> {
+    while since.elapsed().as_secs() < timeout {
+        {
+            let sessions = manager.sessions.lock().await;
+            let connected = sessions.values().any(|s| {
+                s.connected_at >= since
+                    && vm_name.is_none_or(|n| s.hostname.as_ref().is_none_or(|h| h.eq_ignore_ascii_case(n)))
+            });
+            if connected {
+                return Ok(());
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(3)).await;
+    }
+    Err(format!("No agent checked in within {}s", timeout))
+}
+
+/// Graceful shutdown so the snapshot holds a consistent disk; hard stop if the guest hangs.
+async fn shut_down(client: &dyn Hypervisor, node: &str, vmid: u64) -> Result<(), String> {
+    client.vm_action(node, vmid, "shutdown").await.map_err(|e| e.to_string())?;
+    for _ in 0..60 {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        if vm_status(client, node, vmid).await.is_some_and(|(status, _)| status == "stopped") {
+            return Ok(());
+        }
+    }
+    println!("[GOLDEN] VM {} ignored the shutdown request; stopping it", vmid);
+    client.stop(node, vmid).await.map_err(|e| e.to_string())
+}
+
+/// Everything up to the snapshot; `removed_old` tells whether the old one is already gone.
+#[allow(clippy::too_many_arguments)]
+async fn build(
+    client: &dyn Hypervisor,
+    pool: &Pool<Postgres>,
+    manager: &AgentManager,
+    job_id: i32,
+    node: &str,
+    vmid: u64,
+    req: &GoldenImageRequest,
+    snapshot: &str,
+    removed_old: &mut bool,
+) -> Result<(), String> {
+    if let Some(base) = &req.base_snapshot {
+        set_step(pool, job_id, &format!("Reverting to '{}'", base)).await;
+        sandbox_pool::ensure_snapshot(client, node, vmid, base).await?;
+        client.rollback_snapshot(node, vmid, base).await.map_err(|e| e.to_string())?;
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+
+    // The agent only counts if it connected after this boot
+    let (status, vm_name) = vm_status(client, node, vmid).await.unwrap_or_default();
+    if status == "running" {
+        let _ = client.stop(node, vmid).await;
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+    set_step(pool, job_id, "Booting VM").await;
+    let booted = Instant::now();
+    client.start(node, vmid).await.map_err(|e| e.to_string())?;
+
+    set_step(pool, job_id, "Waiting for agent").await;
+    wait_for_agent(manager, vm_name.as_deref(), booted, agent_timeout()).await?;
+
+    let timeout = req.script_timeout.unwrap_or(1800);
+    for (i, script) in req.prep_scripts.iter().enumerate() {
+        set_step(pool, job_id, &format!("Running preparation script {}/{}", i + 1, req.prep_scripts.len())).await;
+        let out = client.guest_exec(node, vmid, &guest_agent::powershell(script), timeout)
+            .await
+            .map_err(|e| format!("Script {} could not run: {}", i + 1, e))?;
+        append_log(pool, job_id, serde_json::json!({
+            "script": i + 1,
+            "exit_code": out.exit_code,
+            "stdout": out.stdout,
+            "stderr": out.stderr,
+        })).await;
+        match out.exit_code {
+            Some(0) => {}
+            Some(code) => return Err(format!("Script {} exited with {}", i + 1, code)),
+            None => return Err(format!("Script {} did not finish within {}s", i + 1, timeout)),
+        }
+    }
+
+    set_step(pool, job_id, "Shutting down guest").await;
+    shut_down(client, node, vmid).await?;
+
+    if client.list_snapshots(node, vmid).await.map_err(|e| e.to_string())?.iter().any(|s| s.name == snapshot) {
+        set_step(pool, job_id, &format!("Removing old '{}' snapshot", snapshot)).await;
+        client.delete_snapshot(node, vmid, snapshot).await.map_err(|e| e.to_string())?;
+        *removed_old = true;
+    }
+
+    set_step(pool, job_id, &format!("Creating '{}' snapshot", snapshot)).await;
+    let description = req.description.clone()
+        .unwrap_or_else(|| format!("VoodooBox golden image, built {}", chrono::Utc::now().format("%Y-%m-%d %H:%M UTC")));
+    client.create_snapshot(node, vmid, snapshot, &description).await.map_err(|e| e.to_string())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_job(
+    client: Arc<dyn Hypervisor>,
+    pool: Pool<Postgres>,
+    manager: Arc<AgentManager>,
+    job_id: i32,
+    node: String,
+    vmid: u64,
+    req: GoldenImageRequest,
+    snapshot: String,
+    registered: bool,
+) {
+    let mut removed_old = false;
+    let result = build(client.as_ref(), &pool, &manager, job_id, &node, vmid, &req, &snapshot, &mut removed_old).await;
+
+    let (state, error) = match &result {
+        Ok(()) => ("completed", None),
+        Err(e) => ("failed", Some(e.clone())),
+    };
+    let _ = sqlx::query("UPDATE golden_image_jobs SET state=$2, step=NULL, error=$3, updated_at=$4 WHERE id=$1")
+        .bind(job_id)
+        .bind(state)
+        .bind(&error)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&pool)
+        .await;
+
+    match result {
+        Ok(()) => {
+            println!("[GOLDEN] Job {}: VM {} snapshot '{}' rebuilt", job_id, vmid, snapshot);
+            if registered {
+                let _ = sqlx::query("UPDATE sandbox_pool SET snapshot=$2, last_error=NULL, updated_at=$3 WHERE vmid=$1")
+                    .bind(vmid as i64)
+                    .bind(&snapshot)
+                    .bind(chrono::Utc::now().timestamp())
+                    .execute(&pool)
+                    .await;
+                sandbox_pool::release(&pool, vmid).await;
+            }
+        }
+        Err(e) => {
+            println!("[GOLDEN] Job {} failed: {}", job_id, e);
+            let _ = client.stop(&node, vmid).await;
+            if registered {
+                if removed_old {
+                    sandbox_pool::mark_broken(&pool, vmid, &format!("Golden image rebuild failed after removing '{}': {}", snapshot, e)).await;
+                } else {
+                    // The old image is intact; put it back the way the scheduler expects it
+                    let _ = client.rollback_snapshot(&node, vmid, &snapshot).await;
+                    sandbox_pool::release(&pool, vmid).await;
+                }
+            }
+        }
+    }
+}
+
+/// Builds (or, with replace, rebuilds) a VM's golden snapshot in the background.
+#[utoipa::path(tag = "sandbox pool", responses((status = 200, description = "Success")))]
+#[post("/sandbox-pool/{vmid}/golden-image")]
+pub async fn build_golden_image(
+    pool: web::Data<Pool<Postgres>>,
+    client: web::Data<dyn Hypervisor>,
+    manager: web::Data<Arc<AgentManager>>,
+    path: web::Path<u64>,
+    req: web::Json<GoldenImageRequest>,
+) -> impl Responder {
+    let vmid = path.into_inner();
+    let req = req.into_inner();
+    let entry = match sandbox_pool::get(pool.get_ref(), vmid).await {
+        Ok(e) => e,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    let node = match (req.node.as_deref().map(str::trim).filter(|s| !s.is_empty()), &entry) {
+        (Some(n), _) => n.to_string(),
+        (None, Some(vm)) => vm.node.clone(),
+        (None, None) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "node is required for VMs not in the sandbox pool" })),
+    };
+    let snapshot = req.snapshot.as_deref().map(str::trim).filter(|s| !s.is_empty()).map(str::to_string)
+        .or_else(|| entry.as_ref().map(|vm| vm.snapshot.clone()))
+        .unwrap_or_else(sandbox_pool::default_snapshot);
+
+    match client.list_snapshots(&node, vmid).await {
+        Ok(snaps) if !req.replace && snaps.iter().any(|s| s.name == snapshot) => {
+            return HttpResponse::Conflict().json(serde_json::json!({ "error": format!("Snapshot '{}' already exists; set replace to rebuild it", snapshot) }));
+        }
+        Ok(_) => {}
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("Cannot reach VM {} on {}: {}", vmid, node, e) })),
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let job = match sqlx::query_as::<_, GoldenImageJob>(&format!(
+        "INSERT INTO golden_image_jobs (vmid, node, snapshot, base_snapshot, state, created_at, updated_at)
+         VALUES ($1, $2, $3, $4, 'queued', $5, $5) RETURNING {}", SELECT_COLUMNS
+    ))
+    .bind(vmid as i64)
+    .bind(&node)
+    .bind(&snapshot)
+    .bind(&req.base_snapshot)
+    .bind(now)
+    .fetch_one(pool.get_ref())
+    .await {
+        Ok(job) => job,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+
+    // Registered VMs are taken out of rotation for the duration of the build
+    let registered = entry.is_some();
+    if registered {
+        match sandbox_pool::claim(pool.get_ref(), vmid, &format!("golden-image-{}", job.id)).await {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                let _ = sqlx::query("UPDATE golden_image_jobs SET state='failed', error='VM is busy or broken' WHERE id=$1")
+                    .bind(job.id).execute(pool.get_ref()).await;
+                return HttpResponse::Conflict().json(serde_json::json!({ "error": "VM is not free; wait for its task or set it free first" }));
+            }
+            Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+        }
+    }
+
+    println!("[GOLDEN] Job {}: building '{}' on VM {} ({})", job.id, snapshot, vmid, node);
+    actix_web::rt::spawn(run_job(
+        client.into_inner(),
+        pool.get_ref().clone(),
+        manager.get_ref().clone(),
+        job.id,
+        node,
+        vmid,
+        req,
+        snapshot,
+        registered,
+    ));
+    HttpResponse::Accepted().json(job)
+}
+
+/// Deletes a snapshot. Removing a pool VM's golden snapshot takes that VM out of rotation.
+#[utoipa::path(tag = "sandbox pool", responses((status = 200, description = "Success")))]
+#[delete("/sandbox-pool/{vmid}/golden-image/{snapshot}")]
+pub async fn delete_golden_image(
+    pool: web::Data<Pool<Postgres>>,
+    client: web::Data<dyn Hypervisor>,
+    path: web::Path<(u64, String)>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> impl Responder {
+    let (vmid, snapshot) = path.into_inner();
+    let entry = sandbox_pool::get(pool.get_ref(), vmid).await.ok().flatten();
+    let node = match (query.get("node"), &entry) {
+        (Some(n), _) => n.clone(),
+        (None, Some(vm)) => vm.node.clone(),
+        (None, None) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "node is required for VMs not in the sandbox pool" })),
+    };
+    if entry.as_ref().is_some_and(|vm| vm.state == "busy") {
+        return HttpResponse::Conflict().json(serde_json::json!({ "error": "VM is busy" }));
+    }
+    if let Err(e) = client.delete_snapshot(&node, vmid, &snapshot).await {
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() }));
+    }
+    if entry.is_some_and(|vm| vm.snapshot == snapshot) {
+        sandbox_pool::mark_broken(pool.get_ref(), vmid, &format!("Golden snapshot '{}' was deleted", snapshot)).await;
+    }
+    println!("[GOLDEN] Deleted snapshot '{}' of VM {}", snapshot, vmid);
+    HttpResponse::Ok().json(serde_json::json!({ "status": "deleted", "vmid": vmid, "snapshot": snapshot }))
+}
+
+#[utoipa::path(tag = "sandbox pool", responses((status = 200, description = "Success")))]
+#[get("/sandbox-pool/{vmid}/golden-image/jobs")]
+pub async fn list_golden_image_jobs(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<u64>,
+) -> impl Responder {
+    match sqlx::query_as::<_, GoldenImageJob>(&format!(
+        "SELECT {} FROM golden_image_jobs WHERE vmid = $1 ORDER BY id DESC LIMIT 50", SELECT_COLUMNS
+    ))
+    .bind(path.into_inner() as i64)
+    .fetch_all(pool.get_ref())
+    .await {
+        Ok(jobs) => HttpResponse::Ok().json(jobs),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "sandbox pool", responses((status = 200, description = "Success")))]
+#[get("/golden-image-jobs/{id}")]
+pub async fn get_golden_image_job(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<i32>,
+) -> impl Responder {
+    match sqlx::query_as::<_, GoldenImageJob>(&format!("SELECT {} FROM golden_image_jobs WHERE id = $1", SELECT_COLUMNS))
+        .bind(path.into_inner())
+        .fetch_optional(pool.get_ref())
+        .await
+    {
+        Ok(Some(job)) => HttpResponse::Ok().json(job),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({ "error": "Job not found" })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
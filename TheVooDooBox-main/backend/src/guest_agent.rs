@@ -0,0 +1,179 @@
+use base64::Engine;
+use sqlx::{Pool, Postgres};
+use std::time::Duration;
+use crate::hypervisor::Hypervisor;
+use crate::progress_stream::ProgressBroadcaster;
+
+// --- QEMU GUEST AGENT FALLBACK ---
+// Detonates through the QEMU guest agent when no VoodooBox agent checks in.
+
+const SHOT_PATH: &str = r"C:\Users\Public\voodoobox-shot.b64";
+
+pub fn enabled() -> bool {
+    std::env::var("SANDBOX_QGA_FALLBACK").map(|v| v != "false" && v != "0").unwrap_or(true)
+}
+
+fn screenshot_interval() -> u64 {
+    std::env::var("QGA_SCREENSHOT_INTERVAL").ok().and_then(|v| v.trim().parse().ok()).unwrap_or(30)
+}
+
+/// Quotes a value for a single-quoted PowerShell string.
+fn ps_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// powershell.exe -EncodedCommand takes base64 of UTF-16LE.
+fn encode_ps(script: &str) -> String {
+    let utf16: Vec<u8> = script.encode_utf16().flat_map(|u| u.to_le_bytes()).collect();
+    base64::engine::general_purpose::STANDARD.encode(utf16)
+}
+
+/// argv for running `script` through powershell.exe via the guest agent.
+pub fn powershell(script: &str) -> Vec<String> {
+    ["powershell.exe", "-NoProfile", "-NonInteractive", "-ExecutionPolicy", "Bypass", "-EncodedCommand"]
+        .iter()
+        .map(|s| s.to_string())
+        .chain(std::iter::once(encode_ps(script)))
+        .collect()
+}
+
+/// Runs `inner` on the logged-on user's desktop, or directly when nobody is.
+fn on_desktop(task_name: &str, inner: &str) -> String {
+    let args = format!("-NoProfile -WindowStyle Hidden -ExecutionPolicy Bypass -EncodedCommand {}", encode_ps(inner));
+    format!(
+        "$u = (Get-CimInstance Win32_ComputerSystem).UserName\n\
+         $a = New-ScheduledTaskAction -Execute 'powershell.exe' -Argument {args}\n\
+         if ($u) {{\n\
+           $p = New-ScheduledTaskPrincipal -UserId $u -LogonType Interactive -RunLevel Highest\n\
+           Register-ScheduledTask -TaskName {name} -Action $a -Principal $p -Force | Out-Null\n\
+           Start-ScheduledTask -TaskName {name}\n\
+         }} else {{\n\
+           Start-Process powershell.exe -ArgumentList {args}\n\
+         }}",
+        args = ps_quote(&args),
+        name = ps_quote(task_name),
+    )
+}
+
+fn launch_script(target_url: &str, filename: &str, is_url_task: bool) -> String {
+    if is_url_task {
+        return on_desktop("VoodooBoxDetonate", &format!("Start-Process {}", ps_quote(target_url)));
+    }
+    let name = filename.replace(['\\', '/'], "_");
+    let path = format!(r"C:\Users\Public\{}", name);
+    format!(
+        "[Net.ServicePointManager]::SecurityProtocol = 'Tls12'\n\
+         (New-Object Net.WebClient).DownloadFile({url}, {path})\n\
+         Unblock-File -Path {path} -ErrorAction SilentlyContinue\n\
+         {launch}",
+        url = ps_quote(target_url),
+        path = ps_quote(&path),
+        launch = on_desktop("VoodooBoxDetonate", &format!("Start-Process -FilePath {}", ps_quote(&path))),
+    )
+}
+
+fn screenshot_script() -> String {
+    let capture = format!(
+        "Add-Type -AssemblyName System.Windows.Forms, System.Drawing\n\
+         $b = [System.Windows.Forms.SystemInformation]::VirtualScreen\n\
+         $bmp = New-Object System.Drawing.Bitmap $b.Width, $b.Height\n\
+         $g = [System.Drawing.Graphics]::FromImage($bmp)\n\
+         $g.CopyFromScreen($b.Left, $b.Top, 0, 0, $bmp.Size)\n\
+         $ms = New-Object IO.MemoryStream\n\
+         $bmp.Save($ms, [System.Drawing.Imaging.ImageFormat]::Png)\n\
+         [IO.File]::WriteAllText({path}, [Convert]::ToBase64String($ms.ToArray()))",
+        path = ps_quote(SHOT_PATH),
+    );
+    format!(
+        "Remove-Item -Path {} -ErrorAction SilentlyContinue\n{}",
+        ps_quote(SHOT_PATH),
+        on_desktop("VoodooBoxScreenshot", &capture),
+    )
+}
+
+/// One desktop capture into ./screenshots/<task>/, like the agent's own uploads.
+async fn capture_screenshot(pool: &Pool<Postgres>, client: &dyn Hypervisor, node: &str, vmid: u64, task_id: &str) -> Result<String, String> {
+    client.guest_exec(node, vmid, &powershell(&screenshot_script()), 30).await.map_err(|e| e.to_string())?;
+    // The scheduled task runs asynchronously; give it a moment to write the file
+    let mut encoded = String::new();
+    for _ in 0..5 {
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        if let Ok(content) = client.guest_read_file(node, vmid, SHOT_PATH).await {
+            if !content.trim().is_empty() {
+                encoded = content;
+                break;
+            }
+        }
+    }
+    let png = base64::engine::general_purpose::STANDARD.decode(encoded.trim()).map_err(|e| format!("Screenshot not captured: {}", e))?;
+    let dir = format!("./screenshots/{}", task_id);
+    let _ = tokio::fs::create_dir_all(&dir).await;
+    let taken_at = chrono::Utc::now().timestamp_millis();
+    let name = format!("screenshot_{}.png", taken_at);
+    tokio::fs::write(format!("{}/{}", dir, name), png).await.map_err(|e| e.to_string())?;
+    crate::storage::persist(&format!("screenshots/{}/{}", task_id, name)).await;
+    if let Err(e) = crate::screenshots::record(pool, task_id, &name, None, taken_at).await {
+        println!("[QGA] Task {}: failed to index {}: {}", task_id, name, e);
+    }
+    Ok(name)
+}
+
+async fn record_event(pool: &Pool<Postgres>, task_id: &str, details: &str) {
+    let _ = sqlx::query(
+        "INSERT INTO events (event_type, process_id, parent_process_id, process_name, details, timestamp, task_id, severity, category, kill_chain_stage)
+         VALUES ('GUEST_AGENT_EXEC', 0, 0, 'qemu-ga', $1, $2, $3, 10, 'system', 'execution')"
+    )
+    .bind(details)
+    .bind(chrono::Utc::now().timestamp_millis())
+    .bind(task_id)
+    .execute(pool)
+    .await;
+}
+
+/// Starts the sample through QGA and screenshots until `duration_seconds` is up.
+#[allow(clippy::too_many_arguments)]
+pub async fn detonate(
+    client: &dyn Hypervisor,
+    pool: &Pool<Postgres>,
+    progress: &ProgressBroadcaster,
+    node: &str,
+    vmid: u64,
+    task_id: &str,
+    target_url: &str,
+    original_filename: &str,
+    is_url_task: bool,
+    duration_seconds: u64,
+) -> Result<(), String> {
+    client.guest_ping(node, vmid).await.map_err(|e| format!("QEMU guest agent unreachable: {}", e))?;
+    println!("[QGA] Task {}: VoodooBox agent absent, detonating through the QEMU guest agent on VM {}", task_id, vmid);
+
+    let _ = sqlx::query("UPDATE tasks SET status='Running (Guest Agent Fallback)' WHERE id=$1")
+        .bind(task_id).execute(pool).await;
+    progress.send_progress(task_id, "running", "No agent connected; running the sample through the QEMU guest agent", 50);
+
+    let out = client.guest_exec(node, vmid, &powershell(&launch_script(target_url, original_filename, is_url_task)), 120)
+        .await
+        .map_err(|e| format!("Guest agent exec failed: {}", e))?;
+    if out.exit_code.is_some_and(|c| c != 0) {
+        return Err(format!("Launch script exited with {:?}: {}", out.exit_code, out.stderr.trim()));
+    }
+    let target = if is_url_task { target_url } else { original_filename };
+    record_event(pool, task_id, &format!("Sample started via QEMU guest agent (no VoodooBox agent telemetry): {}", target)).await;
+
+    let interval = screenshot_interval();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(duration_seconds);
+    let mut shots = 0;
+    while tokio::time::Instant::now() < deadline {
+        if interval == 0 {
+            tokio::time::sleep_until(deadline).await;
+            break;
+        }
+        match capture_screenshot(pool, client, node, vmid, task_id).await {
+            Ok(_) => shots += 1,
+            Err(e) => println!("[QGA] Task {}: screenshot failed: {}", task_id, e),
+        }
+        tokio::time::sleep_until(deadline.min(tokio::time::Instant::now() + Duration::from_secs(interval))).await;
+    }
+    println!("[QGA] Task {}: guest agent run finished ({} screenshot(s)).", task_id, shots);
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use std::env;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use crate::ai::manager::AIManager;
+use crate::hypervisor::Hypervisor;
+
+// --- HEALTH & READINESS ---
+
+/// Upper bound per probe; a hung service is reported as down rather than stalling the check.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize)]
+pub struct Component {
+    pub name: &'static str,
+    /// ok | down
+    pub status: &'static str,
+    /// Critical components fail readiness; the rest only degrade it.
+    pub critical: bool,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+async fn probe<F>(name: &'static str, critical: bool, check: F) -> Component
+where
+    F: Future<Output = Result<Option<String>, String>>,
+{
+    let started = Instant::now();
+    let result = match tokio::time::timeout(PROBE_TIMEOUT, check).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("No answer within {}s", PROBE_TIMEOUT.as_secs())),
+    };
+    let latency_ms = started.elapsed().as_millis() as u64;
+    match result {
+        Ok(detail) => Component { name, status: "ok", critical, latency_ms, detail, error: None },
+        Err(e) => Component { name, status: "down", critical, latency_ms, detail: None, error: Some(e) },
+    }
+}
+
+/// Any HTTP answer below 500 means the service is up; these APIs have no dedicated health route.
+async fn http_reachable(url: String) -> Result<Option<String>, String> {
+    let client = reqwest::Client::builder().timeout(PROBE_TIMEOUT).build().map_err(|e| e.to_string())?;
+    match client.get(&url).send().await {
+        Ok(resp) if resp.status().is_server_error() => Err(format!("HTTP {}", resp.status())),
+        Ok(_) => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+async fn database(pool: &Pool<Postgres>) -> Component {
+    probe("database", true, async {
+        sqlx::query("SELECT 1").execute(pool).await.map(|_| None).map_err(|e| e.to_string())
+    }).await
+}
+
+async fn hypervisor(client: &dyn Hypervisor) -> Component {
+    probe("hypervisor", true, async {
+        client.get_nodes().await
+            .map(|nodes| Some(format!("{} ({} node(s))", client.kind(), nodes.len())))
+            .map_err(|e| format!("{}: {}", client.kind(), e))
+    }).await
+}
+
+pub async fn check_all(pool: &Pool<Postgres>, client: &dyn Hypervisor, ai_manager: &AIManager) -> Vec<Component> {
+    let ghidra = env::var("GHIDRA_API_INTERNAL").unwrap_or_else(|_| "http://ghidra:8000".to_string());
+    let remnux = env::var("REMNUX_MCP_URL").unwrap_or_else(|_| "http://192.168.50.199:8090".to_string());
+    let chroma = env::var("CHROMADB_URL").unwrap_or_else(|_| "http://chromadb:8000".to_string());
+
+    let (database, hypervisor, ghidra, remnux, chromadb, ai) = tokio::join!(
+        database(pool),
+        hypervisor(client),
+        probe("ghidra", false, http_reachable(format!("{}/", ghidra.trim_end_matches('/')))),
+        probe("remnux", false, http_reachable(format!("{}/", remnux.trim_end_matches("/sse").trim_end_matches('/')))),
+        probe("chromadb", false, http_reachable(format!("{}/api/v2/heartbeat", chroma.trim_end_matches('/')))),
+        probe("ai_provider", false, async {
+            let (provider, result) = ai_manager.probe().await;
+            result.map(|_| Some(provider.clone())).map_err(|e| format!("{}: {}", provider, e))
+        }),
+    );
+    vec![database, hypervisor, ghidra, remnux, chromadb, ai]
+}
+
+#[utoipa::path(tag = "health", responses((status = 200, description = "Success")))]
+#[get("/health")]
+pub async fn health_check(
+    pool: web::Data<Pool<Postgres>>,
+    hypervisor: web::Data<dyn Hypervisor>,
+    ai_manager: web::Data<AIManager>,
+) -> impl Responder {
+    let components = check_all(pool.get_ref(), hypervisor.get_ref(), ai_manager.get_ref()).await;
+    let status = if components.iter().all(|c| c.status == "ok") {
+        "ok"
+    } else if components.iter().any(|c| c.critical && c.status != "ok") {
+        "unavailable"
+    } else {
+        "degraded"
+    };
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": status,
+        "service": "hyper-bridge",
+        "components": components,
+    }))
+}
+
+#[utoipa::path(tag = "health", responses(
+    (status = 200, description = "Database and hypervisor reachable"),
+    (status = 503, description = "A critical dependency is failing"),
+))]
+#[get("/health/ready")]
+pub async fn readiness(
+    pool: web::Data<Pool<Postgres>>,
+    client: web::Data<dyn Hypervisor>,
+) -> impl Responder {
+    let (database, hypervisor) = tokio::join!(database(pool.get_ref()), hypervisor(client.get_ref()));
+    let failing: Vec<Component> = [database, hypervisor].into_iter().filter(|c| c.status != "ok").collect();
+    if failing.is_empty() {
+        HttpResponse::Ok().json(serde_json::json!({ "ready": true }))
+    } else {
+        HttpResponse::ServiceUnavailable().json(serde_json::json!({ "ready": false, "failing": failing }))
+    }
+}
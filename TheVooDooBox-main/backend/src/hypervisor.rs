@@ -0,0 +1,190 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::sync::Arc;
+
+// --- HYPERVISOR ABSTRACTION ---
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Node {
+    pub node: String,
+    pub status: String,
+    pub maxcpu: Option<u64>,
+    pub maxmem: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Vm {
+    pub vmid: u64,
+    pub name: Option<String>,
+    pub status: String,
+    pub cpus: Option<u64>,
+    pub maxmem: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub name: String,
+    pub description: Option<String>,
+    pub snaptime: Option<u64>,
+    pub parent: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VncTicket {
+    pub ticket: String,
+    pub port: String,
+    pub upid: String,
+    pub cert: Option<String>,
+    pub password: Option<String>,
+    pub host: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SpiceTicket {
+    pub ticket: Option<String>, // Sometimes missing if password is used
+    pub password: Option<String>, // This is often the ticket for SPICE
+    pub host: Option<String>,
+    pub port: Option<u16>, // Standard port
+    pub proxy: String,
+    #[serde(rename = "tls-port")]
+    pub tls_port: Option<u16>,
+    pub ca: Option<String>,
+    #[serde(rename = "host-subject")]
+    pub host_subject: Option<String>,
+    pub title: Option<String>,
+}
+
+/// NIC attachment, re-applied after every revert.
+#[derive(Debug, Default)]
+pub struct NetworkPlan {
+    /// Bridge for the primary NIC; None keeps whatever the VM has.
+    pub bridge: Option<String>,
+    pub vlan_tag: Option<u16>,
+    /// Only DHCP and these addresses get through; None lifts the lockdown.
+    pub allow_only: Option<Vec<String>>,
+}
+
+/// Result of a command run through the in-guest QEMU agent.
+#[derive(Debug, Default)]
+pub struct GuestExecOutput {
+    /// None when the command was still running at the timeout.
+    pub exit_code: Option<i64>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// How the VNC websocket endpoint reaches a console returned by `create_vnc_proxy`.
+pub enum ConsoleTransport {
+    /// Proxmox-style vncwebsocket endpoint, authenticated with `upstream_auth()`.
+    ProxmoxWebsocket,
+    /// Plain RFB over TCP at the ticket's host:port.
+    RawTcp,
+    /// wss://host:port/ticket/<ticket> (VMware WebMKS); the ticket is the credential.
+    TicketWebsocket,
+}
+
+// Futures are !Send (Box<dyn Error>), matching how the orchestrator already runs on the
+// main arbiter; the trait object itself is shared across workers.
+#[async_trait(?Send)]
+pub trait Hypervisor: Send + Sync {
+    /// Short backend name for logs and error messages.
+    fn kind(&self) -> &'static str;
+
+    async fn get_nodes(&self) -> Result<Vec<Node>, Box<dyn Error>>;
+
+    async fn get_vms(&self, node: &str) -> Result<Vec<Vm>, Box<dyn Error>>;
+
+    /// Power action using Proxmox verbs: start, stop (hard), shutdown, reboot, reset, suspend, resume.
+    async fn vm_action(&self, node: &str, vmid: u64, action: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn rollback_snapshot(&self, node: &str, vmid: u64, snapshot: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn list_snapshots(&self, node: &str, vmid: u64) -> Result<Vec<Snapshot>, Box<dyn Error>>;
+
+    /// Takes a disk snapshot named `name`, returning once the hypervisor has finished.
+    async fn create_snapshot(&self, _node: &str, _vmid: u64, _name: &str, _description: &str) -> Result<(), Box<dyn Error>> {
+        Err(format!("Creating snapshots is not supported by the {} backend", self.kind()).into())
+    }
+
+    async fn delete_snapshot(&self, _node: &str, _vmid: u64, _name: &str) -> Result<(), Box<dyn Error>> {
+        Err(format!("Deleting snapshots is not supported by the {} backend", self.kind()).into())
+    }
+
+    async fn create_vnc_proxy(&self, node: &str, vmid: u64) -> Result<VncTicket, Box<dyn Error>>;
+
+    async fn create_spice_proxy(&self, _node: &str, _vmid: u64) -> Result<SpiceTicket, Box<dyn Error>> {
+        Err(format!("SPICE consoles are not supported by the {} backend", self.kind()).into())
+    }
+
+    fn console_transport(&self) -> ConsoleTransport {
+        ConsoleTransport::ProxmoxWebsocket
+    }
+
+    async fn apply_network(&self, _node: &str, _vmid: u64, _plan: &NetworkPlan) -> Result<(), Box<dyn Error>> {
+        Err(format!("Network policies are not supported by the {} backend", self.kind()).into())
+    }
+
+    /// Succeeds when the QEMU guest agent inside the VM answers.
+    async fn guest_ping(&self, _node: &str, _vmid: u64) -> Result<(), Box<dyn Error>> {
+        Err(format!("QEMU guest agent access is not supported by the {} backend", self.kind()).into())
+    }
+
+    /// Runs `command` through the QEMU guest agent, waiting up to `timeout_secs`.
+    async fn guest_exec(&self, _node: &str, _vmid: u64, _command: &[String], _timeout_secs: u64) -> Result<GuestExecOutput, Box<dyn Error>> {
+        Err(format!("QEMU guest agent access is not supported by the {} backend", self.kind()).into())
+    }
+
+    /// Reads a (text) file from the guest through the QEMU guest agent.
+    async fn guest_read_file(&self, _node: &str, _vmid: u64, _path: &str) -> Result<String, Box<dyn Error>> {
+        Err(format!("QEMU guest agent access is not supported by the {} backend", self.kind()).into())
+    }
+
+    /// Dumps guest RAM as an ELF core to `path` on the hypervisor host.
+    async fn dump_memory(&self, _node: &str, _vmid: u64, _path: &str) -> Result<(), Box<dyn Error>> {
+        Err(format!("Memory dumps are not supported by the {} backend", self.kind()).into())
+    }
+
+    /// Credential the console relays present upstream (empty when not applicable).
+    fn upstream_auth(&self) -> String {
+        String::new()
+    }
+
+    async fn start(&self, node: &str, vmid: u64) -> Result<(), Box<dyn Error>> {
+        self.vm_action(node, vmid, "start").await
+    }
+
+    async fn stop(&self, node: &str, vmid: u64) -> Result<(), Box<dyn Error>> {
+        self.vm_action(node, vmid, "stop").await
+    }
+}
+
+/// Builds the configured backend. Panics on missing required settings, like the rest of startup.
+pub fn from_env() -> Arc<dyn Hypervisor> {
+    let backend = std::env::var("HYPERVISOR").unwrap_or_else(|_| "proxmox".to_string()).to_lowercase();
+    match backend.as_str() {
+        "libvirt" | "kvm" | "qemu" => {
+            let client = crate::libvirt::LibvirtClient::from_env();
+            println!("[HYPERVISOR] Using libvirt backend ({})", client.uri);
+            Arc::new(client)
+        }
+        "vsphere" | "vcenter" | "esxi" => {
+            let client = crate::vsphere::VsphereClient::from_env();
+            println!("[HYPERVISOR] Using vSphere backend ({})", client.base_url);
+            Arc::new(client)
+        }
+        _ => {
+            let proxmox_url = std::env::var("PROXMOX_URL").expect("PROXMOX_URL must be set");
+            let proxmox_user = std::env::var("PROXMOX_USER").expect("PROXMOX_USER must be set");
+            let proxmox_token_id = std::env::var("PROXMOX_TOKEN_ID").expect("PROXMOX_TOKEN_ID must be set");
+            let proxmox_token_secret = std::env::var("PROXMOX_TOKEN_SECRET").expect("PROXMOX_TOKEN_SECRET must be set");
+            println!("[HYPERVISOR] Using Proxmox backend ({})", proxmox_url);
+            Arc::new(crate::proxmox::ProxmoxClient::new(
+                proxmox_url,
+                proxmox_user,
+                proxmox_token_id,
+                proxmox_token_secret,
+            ))
+        }
+    }
+}
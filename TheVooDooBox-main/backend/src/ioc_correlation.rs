@@ -0,0 +1,315 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+
+// --- IOC CORRELATION ---
+// Exact-match IOC sightings across tasks.
+
+const CORRELATED_TYPES: &[&str] = &["ip", "domain", "url", "mutex", "sha256", "sha1", "md5"];
+/// Other tasks listed per indicator; `task_count` has the full number.
+const MAX_SIGHTINGS: usize = 10;
+/// Indicators quoted to the AI.
+const MAX_PROMPT_LINES: usize = 15;
+
+#[derive(Serialize, sqlx::FromRow, Clone)]
+pub struct Sighting {
+    pub task_id: String,
+    pub filename: String,
+    pub verdict: Option<String>,
+    pub created_at: i64,
+    /// When the indicator first showed up in that task's telemetry (ms), if known.
+    pub first_seen: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct Correlation {
+    pub ioc_type: String,
+    pub value: String,
+    pub confidence: String,
+    /// Other tasks that saw this indicator.
+    pub task_count: usize,
+    pub common: bool,
+    /// Most recent first, at most MAX_SIGHTINGS.
+    pub seen_in: Vec<Sighting>,
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CorrelationQuery {
+    /// Also match tasks submitted after this one (default: earlier tasks only).
+    pub include_later: Option<bool>,
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SightingQuery {
+    pub value: String,
+    /// Restrict to one ioc type, e.g. ip or sha256.
+    #[serde(rename = "type")]
+    pub ioc_type: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct MatchRow {
+    ioc_type: String,
+    value: String,
+    confidence: String,
+    task_id: String,
+    filename: String,
+    verdict: Option<String>,
+    created_at: i64,
+    first_seen: Option<i64>,
+}
+
+fn common_threshold() -> usize {
+    std::env::var("IOC_COMMON_TASKS").ok().and_then(|v| v.trim().parse().ok()).unwrap_or(20)
+}
+
+/// Windows mutex names are case-sensitive; everything else here is compared lowercased.
+fn normalize(ioc_type: &str, value: &str) -> String {
+    if ioc_type == "mutex" { value.to_string() } else { value.to_lowercase() }
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS ioc_sightings (
+            id SERIAL PRIMARY KEY,
+            task_id TEXT NOT NULL,
+            ioc_type TEXT NOT NULL,
+            value TEXT NOT NULL,
+            confidence TEXT NOT NULL,
+            first_seen BIGINT,
+            recorded_at BIGINT NOT NULL,
+            UNIQUE (task_id, ioc_type, value)
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_ioc_sightings_value ON ioc_sightings(ioc_type, value)")
+        .execute(pool)
+        .await?;
+
+    println!("[CORRELATION] Database initialized (ioc_sightings).");
+    Ok(())
+}
+
+/// Replaces the task's rows in ioc_sightings with its current indicators; returns how many.
+pub async fn record(pool: &Pool<Postgres>, task_id: &str) -> Result<usize, sqlx::Error> {
+    let iocs: Vec<_> = crate::iocs::extract(pool, task_id).await?
+        .into_iter()
+        .filter(|i| i.confidence != "low" && CORRELATED_TYPES.contains(&i.ioc_type))
+        .collect();
+    let now = chrono::Utc::now().timestamp_millis();
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM ioc_sightings WHERE task_id = $1")
+        .bind(task_id)
+        .execute(&mut *tx)
+        .await?;
+    for ioc in &iocs {
+        sqlx::query(
+            "INSERT INTO ioc_sightings (task_id, ioc_type, value, confidence, first_seen, recorded_at)
+             VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (task_id, ioc_type, value) DO NOTHING"
+        )
+        .bind(task_id)
+        .bind(ioc.ioc_type)
+        .bind(normalize(ioc.ioc_type, &ioc.value))
+        .bind(ioc.confidence)
+        .bind(ioc.first_seen)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    println!("[CORRELATION] Recorded {} indicators for task {}.", iocs.len(), task_id);
+    Ok(iocs.len())
+}
+
+/// The task's recorded indicators that other tasks also saw, linked ones first.
+pub async fn correlate(pool: &Pool<Postgres>, task_id: &str, include_later: bool) -> Result<Vec<Correlation>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, MatchRow>(
+        "SELECT s.ioc_type, s.value, s.confidence, o.task_id, t.original_filename AS filename, t.verdict, t.created_at, o.first_seen
+         FROM ioc_sightings s
+         JOIN ioc_sightings o ON o.ioc_type = s.ioc_type AND o.value = s.value AND o.task_id <> s.task_id
+         JOIN tasks t ON t.id = o.task_id
+         WHERE s.task_id = $1
+           AND ($2 OR t.created_at < COALESCE((SELECT created_at FROM tasks WHERE id = $1), 0))
+         ORDER BY s.ioc_type, s.value, t.created_at DESC"
+    )
+    .bind(task_id)
+    .bind(include_later)
+    .fetch_all(pool)
+    .await?;
+
+    let common_at = common_threshold();
+    let mut order: Vec<(String, String)> = Vec::new();
+    let mut grouped: HashMap<(String, String), Correlation> = HashMap::new();
+    for row in rows {
+        let key = (row.ioc_type.clone(), row.value.clone());
+        let entry = grouped.entry(key.clone()).or_insert_with(|| {
+            order.push(key);
+            Correlation {
+                ioc_type: row.ioc_type,
+                value: row.value,
+                confidence: row.confidence,
+                task_count: 0,
+                common: false,
+                seen_in: Vec::new(),
+            }
+        });
+        entry.task_count += 1;
+        if entry.seen_in.len() < MAX_SIGHTINGS {
+            entry.seen_in.push(Sighting {
+                task_id: row.task_id,
+                filename: row.filename,
+                verdict: row.verdict,
+                created_at: row.created_at,
+                first_seen: row.first_seen,
+            });
+        }
+    }
+
+    let mut correlations: Vec<Correlation> = order.into_iter().filter_map(|k| grouped.remove(&k)).collect();
+    for c in &mut correlations {
+        c.common = c.task_count > common_at;
+    }
+    correlations.sort_by_key(|c| (c.common, std::cmp::Reverse(c.task_count)));
+    Ok(correlations)
+}
+
+/// Prompt text for the reduce phase: one line per linked indicator, common ones only counted.
+pub fn describe(correlations: &[Correlation]) -> String {
+    let linked: Vec<&Correlation> = correlations.iter().filter(|c| !c.common).collect();
+    let common = correlations.len() - linked.len();
+    if linked.is_empty() {
+        return match common {
+            0 => "No indicator was seen in an earlier task.".to_string(),
+            n => format!("No distinctive overlap; {} common indicator(s) shared with many tasks omitted.", n),
+        };
+    }
+
+    let mut lines: Vec<String> = linked.iter().take(MAX_PROMPT_LINES).map(|c| {
+        let latest = &c.seen_in[0];
+        let date = chrono::DateTime::from_timestamp_millis(latest.created_at)
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        let malicious = c.seen_in.iter().filter(|s| s.verdict.as_deref() == Some("Malicious")).count();
+        format!("{} {} ({}): seen in {} earlier task(s), {} of them Malicious; latest task {} '{}' on {} (verdict {})",
+            c.ioc_type, c.value, c.confidence, c.task_count, malicious,
+            latest.task_id, latest.filename, date, latest.verdict.as_deref().unwrap_or("none"))
+    }).collect();
+    if linked.len() > MAX_PROMPT_LINES {
+        lines.push(format!("... and {} more linked indicator(s).", linked.len() - MAX_PROMPT_LINES));
+    }
+    if common > 0 {
+        lines.push(format!("{} common indicator(s) shared with many tasks omitted.", common));
+    }
+    lines.join("\n")
+}
+
+/// This task's indicators seen in other tasks; older tasks are recorded on first request.
+#[utoipa::path(tag = "tasks", params(CorrelationQuery), responses(
+    (status = 200, description = "Indicators shared with other tasks"),
+    (status = 404, description = "Task not found"),
+))]
+#[get("/tasks/{id}/correlations")]
+pub async fn get_correlations(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+    query: web::Query<CorrelationQuery>,
+) -> impl Responder {
+    let task_id = path.into_inner();
+    let pool = pool.get_ref();
+    match sqlx::query_scalar::<_, String>("SELECT status FROM tasks WHERE id = $1").bind(&task_id).fetch_optional(pool).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+
+    let recorded: i64 = match sqlx::query_scalar("SELECT COUNT(*) FROM ioc_sightings WHERE task_id = $1").bind(&task_id).fetch_one(pool).await {
+        Ok(n) => n,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    let recorded = if recorded == 0 {
+        match record(pool, &task_id).await {
+            Ok(n) => n,
+            Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+        }
+    } else {
+        recorded as usize
+    };
+
+    match correlate(pool, &task_id, query.include_later.unwrap_or(false)).await {
+        Ok(correlations) => HttpResponse::Ok().json(serde_json::json!({
+            "task_id": task_id,
+            "indicators": recorded,
+            "linked": correlations.iter().filter(|c| !c.common).count(),
+            "correlations": correlations,
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Re-extracts the task's indicators (e.g. after memory analysis finished late) and correlates again.
+#[utoipa::path(tag = "tasks", responses(
+    (status = 200, description = "Indicators recorded"),
+    (status = 404, description = "Task not found"),
+))]
+#[post("/tasks/{id}/correlations")]
+pub async fn recompute_correlations(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    let task_id = path.into_inner();
+    match sqlx::query_scalar::<_, String>("SELECT status FROM tasks WHERE id = $1").bind(&task_id).fetch_optional(pool.get_ref()).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+    match record(pool.get_ref(), &task_id).await {
+        Ok(n) => HttpResponse::Ok().json(serde_json::json!({ "task_id": task_id, "indicators": n })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Every task that saw an indicator, newest first: the pivot from one IP, domain or hash.
+#[utoipa::path(tag = "tasks", params(SightingQuery), responses((status = 200, description = "Tasks that saw the indicator")))]
+#[get("/iocs/sightings")]
+pub async fn get_sightings(pool: web::Data<Pool<Postgres>>, query: web::Query<SightingQuery>) -> impl Responder {
+    let value = query.value.trim();
+    if value.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "value is required" }));
+    }
+    let ioc_type = query.ioc_type.as_deref().map(|t| t.trim().to_lowercase());
+    let rows = sqlx::query_as::<_, MatchRow>(
+        "SELECT s.ioc_type, s.value, s.confidence, s.task_id, t.original_filename AS filename, t.verdict, t.created_at, s.first_seen
+         FROM ioc_sightings s JOIN tasks t ON t.id = s.task_id
+         WHERE (s.value = $1 OR s.value = $2) AND ($3::TEXT IS NULL OR s.ioc_type = $3)
+         ORDER BY t.created_at DESC"
+    )
+    .bind(value)
+    .bind(value.to_lowercase())
+    .bind(&ioc_type)
+    .fetch_all(pool.get_ref())
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let sightings: Vec<serde_json::Value> = rows.into_iter().map(|r| serde_json::json!({
+                "ioc_type": r.ioc_type,
+                "confidence": r.confidence,
+                "task_id": r.task_id,
+                "filename": r.filename,
+                "verdict": r.verdict,
+                "created_at": r.created_at,
+                "first_seen": r.first_seen,
+            })).collect();
+            HttpResponse::Ok().json(serde_json::json!({
+                "value": value,
+                "task_count": sightings.len(),
+                "sightings": sightings,
+            }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
@@ -0,0 +1,385 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+
+// --- IOC EXTRACTION ---
+
+#[derive(Serialize, Clone)]
+pub struct Ioc {
+    /// md5 | sha1 | sha256 | ip | domain | url | mutex | registry_key | file_path
+    pub ioc_type: &'static str,
+    pub value: String,
+    /// high | medium | low
+    pub confidence: &'static str,
+    /// Where it was seen: event types or artifact names.
+    pub sources: Vec<String>,
+    pub occurrences: u32,
+    pub first_seen: Option<i64>,
+}
+
+#[derive(Deserialize, Default, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct IocQuery {
+    /// Rewrite network indicators so they cannot be clicked or resolved (hxxp, [.]).
+    pub defang: Option<bool>,
+    /// Comma-separated ioc types to keep.
+    pub types: Option<String>,
+    /// low (default) | medium | high
+    pub min_confidence: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct EventRow {
+    event_type: String,
+    details: String,
+    decoded_details: Option<String>,
+    timestamp: i64,
+}
+
+struct Patterns {
+    url: Regex,
+    ipv4: Regex,
+    domain: Regex,
+    hash: Regex,
+    registry: Regex,
+    file_path: Regex,
+    mutex: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        url: Regex::new(r#"(?i)\b(?:https?|ftp)://[^\s'"<>|()\[\]{}]+"#).unwrap(),
+        ipv4: Regex::new(r"\b(?:(?:25[0-5]|2[0-4]\d|1?\d?\d)\.){3}(?:25[0-5]|2[0-4]\d|1?\d?\d)\b").unwrap(),
+        domain: Regex::new(r"(?i)\b(?:[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?\.)+[a-z]{2,24}\b").unwrap(),
+        hash: Regex::new(r"\b(?:[A-Fa-f0-9]{64}|[A-Fa-f0-9]{40}|[A-Fa-f0-9]{32})\b").unwrap(),
+        registry: Regex::new(r#"(?i)\b(?:HKLM|HKCU|HKCR|HKU|HKCC|HKEY_[A-Z_]+)\\[^'"\r\n|=]+"#).unwrap(),
+        // Directories may contain spaces, the file name itself may not, so command-line
+        // arguments after the image are not swallowed
+        file_path: Regex::new(r#"(?i)\b[a-z]:\\(?:[^\\/:*?"<>|\r\n]+\\)*[^\\/:*?"<>|\s]+\.[a-z0-9]{1,8}\b"#).unwrap(),
+        mutex: Regex::new(r#"(?i)\\(?:Sessions\\\d+\\)?BaseNamedObjects\\([^\s'"\\]+)"#).unwrap(),
+    })
+}
+
+/// Things that look like domains in free text but are file names.
+const FILE_EXTENSIONS: &[&str] = &[
+    "exe", "dll", "sys", "drv", "ocx", "cpl", "scr", "bat", "cmd", "ps1", "psm1", "vbs", "vbe", "js",
+    "jse", "wsf", "hta", "lnk", "tmp", "log", "txt", "ini", "dat", "db", "xml", "json", "yml", "cfg",
+    "config", "manifest", "mui", "etl", "evtx", "pf", "msi", "msp", "cab", "zip", "rar", "7z", "iso",
+    "img", "doc", "docx", "docm", "xls", "xlsx", "xlsm", "ppt", "pptx", "pdf", "rtf", "png", "jpg",
+    "jpeg", "gif", "bmp", "ico", "htm", "html", "css", "php", "aspx", "jar", "class", "py", "pyc",
+    "bin", "pdb", "lib", "obj", "nls", "ttf", "dmp", "pak", "ldb", "sqlite", "local", "lock",
+];
+
+const NETWORK_EVENTS: &[&str] = &[
+    "NETWORK_CONNECT", "NETWORK_DNS", "LATERAL_MOVEMENT", "HTTP_REQUEST", "BROWSER_NAVIGATE",
+    "BROWSER_REDIRECT", "URL_OPEN", "DOWNLOAD_DETECTED",
+];
+
+const FILE_EVENTS: &[&str] = &[
+    "FILE_CREATE", "FILE_VERIFIED", "DOWNLOAD_DETECTED", "ADS_CREATED", "TIMESTOMP_DETECTED",
+    "PROCESS_CREATE", "EXEC_SUCCESS", "STARTUP_PERSISTENCE",
+];
+
+fn rank(confidence: &str) -> u8 {
+    match confidence {
+        "high" => 2,
+        "medium" => 1,
+        _ => 0,
+    }
+}
+
+struct Collector {
+    iocs: Vec<Ioc>,
+    index: HashMap<(&'static str, String), usize>,
+    exclude_ips: Vec<String>,
+}
+
+impl Collector {
+    fn new() -> Self {
+        let exclude_ips = std::env::var("EXCLUDE_IPS").unwrap_or_default()
+            .split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
+        Collector { iocs: Vec::new(), index: HashMap::new(), exclude_ips }
+    }
+
+    fn add(&mut self, ioc_type: &'static str, value: &str, confidence: &'static str, source: &str, ts: Option<i64>) {
+        let value = value.trim().trim_end_matches(['.', ',', ';', '\'', '"']).to_string();
+        if value.is_empty() {
+            return;
+        }
+        // Hashes, hosts and URL schemes are case-insensitive; paths and keys keep their case
+        // for display but are compared without it
+        let key_value = value.to_lowercase();
+        let value = match ioc_type {
+            "md5" | "sha1" | "sha256" | "domain" => key_value.clone(),
+            _ => value,
+        };
+        if ioc_type == "ip" {
+            let Ok(ip) = value.parse::<IpAddr>() else { return };
+            if !crate::network_graph::is_public(&ip) || self.exclude_ips.contains(&value) {
+                return;
+            }
+        }
+
+        let i = match self.index.get(&(ioc_type, key_value.clone())) {
+            Some(&i) => i,
+            None => {
+                self.index.insert((ioc_type, key_value), self.iocs.len());
+                self.iocs.push(Ioc { ioc_type, value, confidence, sources: Vec::new(), occurrences: 0, first_seen: ts });
+                self.iocs.len() - 1
+            }
+        };
+        let ioc = &mut self.iocs[i];
+        ioc.occurrences += 1;
+        if rank(confidence) > rank(ioc.confidence) {
+            ioc.confidence = confidence;
+        }
+        if let Some(ts) = ts {
+            ioc.first_seen = Some(ioc.first_seen.map_or(ts, |f| f.min(ts)));
+        }
+        if !ioc.sources.iter().any(|s| s == source) {
+            ioc.sources.push(source.to_string());
+        }
+    }
+
+    fn url(&mut self, url: &str, confidence: &'static str, source: &str, ts: Option<i64>) {
+        let url = url.trim().trim_end_matches(['.', ',', ';', '\'', '"']);
+        self.add("url", url, confidence, source, ts);
+        if let Some(host) = reqwest::Url::parse(url).ok().and_then(|u| u.host_str().map(str::to_string)) {
+            let host = host.trim_matches(|c| c == '[' || c == ']');
+            let kind = if host.parse::<IpAddr>().is_ok() { "ip" } else { "domain" };
+            self.add(kind, host, confidence, source, ts);
+        }
+    }
+
+    /// Regex pass over free text; `confidence` covers the kinds the event type vouches for.
+    fn scan(&mut self, text: &str, event_type: &str, decoded: bool, ts: Option<i64>) {
+        let p = patterns();
+        let network = !decoded && NETWORK_EVENTS.contains(&event_type);
+        let files = !decoded && FILE_EVENTS.contains(&event_type);
+        let registry = !decoded && (event_type.starts_with("REG") || event_type == "COM_HIJACK" || event_type == "STARTUP_PERSISTENCE");
+        let source = if decoded { format!("{} (decoded)", event_type) } else { event_type.to_string() };
+        let level = |vouched: bool| if vouched { "medium" } else { "low" };
+
+        for m in p.url.find_iter(text) {
+            self.url(m.as_str(), level(network), &source, ts);
+        }
+        // URLs were handled above; strip them so their hosts and paths are not found again
+        let rest = p.url.replace_all(text, " ");
+        for m in p.ipv4.find_iter(&rest) {
+            self.add("ip", m.as_str(), level(network), &source, ts);
+        }
+        for m in p.hash.find_iter(&rest) {
+            let kind = match m.as_str().len() {
+                64 => "sha256",
+                40 => "sha1",
+                _ => "md5",
+            };
+            // The agent labels hashes it computed itself: "(SHA256: ...)"
+            let labelled = rest[..m.start()].ends_with("SHA256: ");
+            self.add(kind, m.as_str(), if labelled { "high" } else { "low" }, &source, ts);
+        }
+        for m in p.registry.find_iter(&rest) {
+            let key = [" Value:", " = ", " Data:", " (Old", " New Data"].iter()
+                .fold(m.as_str(), |acc, sep| acc.split(sep).next().unwrap_or(acc));
+            self.add("registry_key", key, level(registry), &source, ts);
+        }
+        for m in p.file_path.find_iter(&rest) {
+            self.add("file_path", m.as_str(), level(files), &source, ts);
+        }
+        for c in p.mutex.captures_iter(&rest) {
+            self.add("mutex", &c[1], "medium", &source, ts);
+        }
+        let without_paths = p.file_path.replace_all(&rest, " ");
+        for m in p.domain.find_iter(&without_paths) {
+            let domain = m.as_str();
+            let tld = domain.rsplit('.').next().unwrap_or("").to_lowercase();
+            if FILE_EXTENSIONS.contains(&tld.as_str()) || domain.parse::<IpAddr>().is_ok() {
+                continue;
+            }
+            self.add("domain", domain, level(network), &source, ts);
+        }
+    }
+
+    /// Structured fields the agent reports in a fixed format.
+    fn structured(&mut self, row: &EventRow) {
+        let details = row.details.as_str();
+        let ts = Some(row.timestamp);
+        match row.event_type.as_str() {
+            "NETWORK_CONNECT" | "LATERAL_MOVEMENT" => {
+                if let Some(dest) = details.split("->").nth(1).and_then(|d| d.split_whitespace().next()) {
+                    let ip = dest.rsplit_once(':').map(|(ip, _)| ip).unwrap_or(dest);
+                    self.add("ip", ip.trim_matches(|c| c == '[' || c == ']'), "high", &row.event_type, ts);
+                }
+            }
+            "NETWORK_DNS" => {
+                let name = details.find("DNS: ").map(|p| &details[p + 5..])
+                    .or_else(|| details.find("DNS Query Resolved: ").map(|p| &details[p + 20..]));
+                if let Some(name) = name {
+                    let (query, ips) = name.split_once("| IPs:").unwrap_or((name, ""));
+                    self.add("domain", query.trim().trim_end_matches('.'), "high", &row.event_type, ts);
+                    for ip in ips.split(',') {
+                        self.add("ip", ip.trim(), "high", &row.event_type, ts);
+                    }
+                }
+            }
+            "HTTP_REQUEST" => {
+                if let Some(url) = details.split_whitespace().nth(2) {
+                    self.url(url, "high", &row.event_type, ts);
+                }
+            }
+            "FILE_CREATE" | "DOWNLOAD_DETECTED" | "ADS_CREATED" => {
+                let path = details.find("File Activity: ").map(|p| &details[p + 15..])
+                    .or_else(|| details.find("File Created: ").map(|p| &details[p + 14..]));
+                if let Some(path) = path {
+                    self.add("file_path", path.split(" (SHA256").next().unwrap_or(path), "high", &row.event_type, ts);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn defang(ioc: &mut Ioc) {
+    match ioc.ioc_type {
+        "url" => {
+            let (scheme, rest) = ioc.value.split_once("://").unwrap_or(("", &ioc.value));
+            let scheme = scheme.to_lowercase().replace("http", "hxxp").replace("ftp", "fxp");
+            let (host, path) = rest.split_once('/').map(|(h, p)| (h, format!("/{}", p))).unwrap_or((rest, String::new()));
+            ioc.value = format!("{}[://]{}{}", scheme, host.replace('.', "[.]"), path);
+        }
+        "domain" => ioc.value = ioc.value.replace('.', "[.]"),
+        "ip" => {
+            ioc.value = match ioc.value.rfind(['.', ':']) {
+                Some(pos) => format!("{}[{}]{}", &ioc.value[..pos], &ioc.value[pos..pos + 1], &ioc.value[pos + 1..]),
+                None => ioc.value.clone(),
+            }
+        }
+        _ => {}
+    }
+}
+
+pub async fn extract(pool: &Pool<Postgres>, task_id: &str) -> Result<Vec<Ioc>, sqlx::Error> {
+    let mut collector = Collector::new();
+
+    let task: Option<(String, Option<serde_json::Value>)> = sqlx::query_as(
+        "SELECT file_hash, container FROM tasks WHERE id = $1"
+    )
+    .bind(task_id)
+    .fetch_optional(pool)
+    .await?;
+    if let Some((file_hash, container)) = task {
+        if file_hash.len() == 64 {
+            collector.add("sha256", &file_hash, "high", "submission", None);
+        }
+        if let Some(sha256) = container.as_ref().and_then(|c| c["sha256"].as_str()) {
+            collector.add("sha256", sha256, "high", "container", None);
+        }
+    }
+
+    let events = sqlx::query_as::<_, EventRow>(
+        "SELECT event_type, details, decoded_details, timestamp FROM events WHERE task_id = $1 ORDER BY timestamp"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+    for row in &events {
+        collector.structured(row);
+        collector.scan(&row.details, &row.event_type, false, Some(row.timestamp));
+        if let Some(decoded) = row.decoded_details.as_deref().filter(|d| !d.is_empty()) {
+            collector.scan(decoded, &row.event_type, true, Some(row.timestamp));
+        }
+    }
+
+    let alerts: Vec<(Option<String>, Option<String>, i64)> = sqlx::query_as(
+        "SELECT src_ip, dest_ip, timestamp FROM network_alerts WHERE task_id = $1"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+    for (src, dest, ts) in alerts {
+        for ip in [src, dest].into_iter().flatten() {
+            collector.add("ip", &ip, "high", "suricata", Some(ts));
+        }
+    }
+
+    let email: Option<(Option<String>, Option<String>, Option<serde_json::Value>)> = sqlx::query_as(
+        "SELECT sender, reply_to, urls FROM email_analyses WHERE task_id = $1"
+    )
+    .bind(task_id)
+    .fetch_optional(pool)
+    .await
+    .unwrap_or(None);
+    if let Some((sender, reply_to, urls)) = email {
+        for address in [sender, reply_to].into_iter().flatten() {
+            if let Some((_, domain)) = address.rsplit_once('@') {
+                collector.add("domain", domain.trim_end_matches('>'), "medium", "email", None);
+            }
+        }
+        if let Some(serde_json::Value::Array(urls)) = urls {
+            for url in urls.iter().filter_map(|u| u.as_str()) {
+                collector.url(url, "high", "email", None);
+            }
+        }
+    }
+
+    let memory: Vec<(String, serde_json::Value)> = sqlx::query_as(
+        "SELECT plugin, rows FROM memory_analyses WHERE task_id = $1 AND status = 'completed'
+           AND (plugin LIKE '%netscan' OR plugin LIKE '%handles')"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+    for (plugin, rows) in memory {
+        let serde_json::Value::Array(rows) = rows else { continue };
+        for row in rows {
+            if plugin.ends_with("netscan") {
+                if let Some(ip) = row["ForeignAddr"].as_str() {
+                    collector.add("ip", ip, "high", &plugin, None);
+                }
+            } else if row["Type"].as_str() == Some("Mutant") {
+                if let Some(name) = row["Name"].as_str().filter(|n| !n.is_empty()) {
+                    collector.add("mutex", name.rsplit('\\').next().unwrap_or(name), "high", &plugin, None);
+                }
+            }
+        }
+    }
+
+    Ok(collector.iocs)
+}
+
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
+#[get("/tasks/{id}/iocs")]
+pub async fn get_iocs(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+    query: web::Query<IocQuery>,
+) -> impl Responder {
+    let task_id = path.into_inner();
+    let mut iocs = match extract(pool.get_ref(), &task_id).await {
+        Ok(iocs) => iocs,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+
+    let min = rank(query.min_confidence.as_deref().unwrap_or("low"));
+    let types: Option<Vec<String>> = query.types.as_ref()
+        .map(|t| t.split(',').map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty()).collect());
+    iocs.retain(|i| rank(i.confidence) >= min && types.as_ref().is_none_or(|t| t.iter().any(|t| t == i.ioc_type)));
+    iocs.sort_by(|a, b| rank(b.confidence).cmp(&rank(a.confidence)).then(a.ioc_type.cmp(b.ioc_type)).then(a.value.cmp(&b.value)));
+    if query.defang.unwrap_or(false) {
+        iocs.iter_mut().for_each(defang);
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "task_id": task_id,
+        "count": iocs.len(),
+        "defanged": query.defang.unwrap_or(false),
+        "iocs": iocs,
+    }))
+}
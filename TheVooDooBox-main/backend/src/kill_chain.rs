@@ -0,0 +1,235 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+
+// --- KILL-CHAIN STAGES ---
+// ATT&CK tactic per event, first matching rule wins.
+
+/// Tactics in kill-chain order, with their display names.
+pub const STAGES: &[(&str, &str)] = &[
+    ("initial_access", "Initial Access"),
+    ("execution", "Execution"),
+    ("persistence", "Persistence"),
+    ("privilege_escalation", "Privilege Escalation"),
+    ("defense_evasion", "Defense Evasion"),
+    ("credential_access", "Credential Access"),
+    ("discovery", "Discovery"),
+    ("lateral_movement", "Lateral Movement"),
+    ("collection", "Collection"),
+    ("command_and_control", "Command and Control"),
+    ("exfiltration", "Exfiltration"),
+    ("impact", "Impact"),
+];
+
+/// Example events listed per stage in the summary.
+const EXAMPLES_PER_STAGE: i32 = 5;
+
+struct Rule {
+    /// Exact event type, or a prefix ending in '*'.
+    event_type: &'static str,
+    /// Lower-case substrings of process name + details, any of which matches; empty matches all.
+    any: &'static [&'static str],
+    stage: &'static str,
+}
+
+const fn rule(event_type: &'static str, any: &'static [&'static str], stage: &'static str) -> Rule {
+    Rule { event_type, any, stage }
+}
+
+const RULES: &[Rule] = &[
+    // Impact
+    rule("PROCESS_CREATE", &["vssadmin delete shadows", "vssadmin.exe delete shadows", "shadowcopy delete", "wbadmin delete", "recoveryenabled no", "bootstatuspolicy ignoreallfailures", "cipher /w"], "impact"),
+    rule("FILE_*", &["readme_for_decrypt", "how_to_decrypt", "decrypt_instructions", "restore_files", ".locked", ".encrypted"], "impact"),
+    rule("SCREEN_TEXT", &["(ransom note)"], "impact"),
+    // Credential access
+    rule("PROCESS_ACCESS", &["lsass"], "credential_access"),
+    rule("SCREEN_TEXT", &["(credential prompt)"], "credential_access"),
+    rule("PROCESS_CREATE", &["mimikatz", "sekurlsa", "procdump", "comsvcs.dll minidump", "comsvcs.dll, minidump", "reg save hklm\\sam", "reg save hklm\\security", "ntds.dit", "lazagne"], "credential_access"),
+    // Privilege escalation (UAC bypasses hijack these handlers before persistence would)
+    rule("REG*", &["\\ms-settings\\shell\\open\\command", "\\mscfile\\shell\\open\\command", "\\exefile\\shell\\runas\\command"], "privilege_escalation"),
+    rule("PROCESS_CREATE", &["fodhelper", "computerdefaults", "eventvwr", "sdclt", "printspoofer", "juicypotato", "getsystem"], "privilege_escalation"),
+    // Lateral movement
+    rule("LATERAL_MOVEMENT", &[], "lateral_movement"),
+    rule("PROCESS_CREATE", &["psexec", "wmic /node", "winrs ", "invoke-command -computername", "enter-pssession", "\\admin$", "\\c$"], "lateral_movement"),
+    // Persistence
+    rule("STARTUP_PERSISTENCE", &[], "persistence"),
+    rule("PERSISTENCE_SWEEP", &[], "persistence"),
+    rule("SCHTASK_*", &[], "persistence"),
+    rule("WMI_SUBSCRIPTION", &[], "persistence"),
+    rule("COM_HIJACK", &[], "persistence"),
+    rule("REG*", &["\\currentversion\\run", "\\currentversion\\winlogon", "\\currentversion\\policies\\explorer\\run", "\\system\\currentcontrolset\\services\\", "\\image file execution options\\", "appinit_dlls", "\\userinit"], "persistence"),
+    rule("FILE_*", &["\\start menu\\programs\\startup\\"], "persistence"),
+    rule("PROCESS_CREATE", &["schtasks /create", "schtasks.exe /create", "sc create", "sc.exe create", "new-service", "register-scheduledtask", "\\currentversion\\run"], "persistence"),
+    // Defense evasion
+    rule("DEFENDER_*", &[], "defense_evasion"),
+    rule("TIMESTOMP_DETECTED", &[], "defense_evasion"),
+    rule("CERT_INSTALLED", &[], "defense_evasion"),
+    rule("PROXY_HIJACK", &[], "defense_evasion"),
+    rule("ADS_CREATED", &[], "defense_evasion"),
+    rule("REMOTE_THREAD", &[], "defense_evasion"),
+    rule("MEMORY_ANOMALY", &[], "defense_evasion"),
+    rule("PROCESS_TAMPER", &[], "defense_evasion"),
+    rule("PROCESS_CREATE", &["wevtutil cl", "clear-eventlog", "set-mppreference", "add-mppreference", "-executionpolicy bypass", "-ep bypass", "-encodedcommand", "-enc ", "frombase64string", "attrib +h", "fsutil usn deletejournal", "netsh advfirewall set", "bcdedit"], "defense_evasion"),
+    rule("PROCESS_CREATE", &["rundll32", "regsvr32", "mshta", "installutil", "regasm", "regsvcs", "msbuild", "cmstp", "odbcconf"], "defense_evasion"),
+    // Discovery
+    rule("PROCESS_CREATE", &["whoami", "systeminfo", "ipconfig", "net user", "net group", "net localgroup", "net view", "net share", "nltest", "tasklist", "quser", "netstat", "arp -a", "route print", "wmic os", "wmic computersystem", "wmic process", "reg query", "dsquery", "adfind", "get-wmiobject win32_", "get-ciminstance win32_"], "discovery"),
+    // Collection
+    rule("CLIPBOARD_CAPTURE", &[], "collection"),
+    rule("PROCESS_CREATE", &["compress-archive", "7z.exe a", "7z a ", "rar.exe a", "makecab", "get-clipboard"], "collection"),
+    // Exfiltration
+    rule("PROCESS_CREATE", &["rclone", "--upload-file", "curl -f ", "curl.exe -f ", "ftp -s:"], "exfiltration"),
+    rule("HTTP_REQUEST", &["api.telegram.org", "discord.com/api/webhooks", "pastebin.com", "transfer.sh", "file.io", "anonfiles", "mega.nz"], "exfiltration"),
+    rule("NETWORK_*", &["api.telegram.org", "discord.com/api/webhooks", "pastebin.com", "transfer.sh", "file.io", "anonfiles", "mega.nz"], "exfiltration"),
+    // Initial access: how the sample got onto the box
+    rule("URL_OPEN", &[], "initial_access"),
+    rule("BROWSER_NAVIGATE", &[], "initial_access"),
+    rule("BROWSER_REDIRECT", &[], "initial_access"),
+    rule("DOWNLOAD_DETECTED", &[], "initial_access"),
+    rule("FILE_*", &["\\content.outlook\\", "\\inetcache\\", ":zone.identifier"], "initial_access"),
+    // Command and control: whatever else the guest said on the wire
+    rule("HTTP_REQUEST", &[], "command_and_control"),
+    rule("NETWORK_*", &[], "command_and_control"),
+    // Execution
+    rule("EXEC_SUCCESS", &[], "execution"),
+    rule("GUEST_AGENT_EXEC", &[], "execution"),
+    rule("VSIX_INSTALLED", &[], "execution"),
+    rule("PROCESS_CREATE", &[], "execution"),
+];
+
+fn type_matches(pattern: &str, event_type: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => event_type.starts_with(prefix),
+        None => event_type == pattern,
+    }
+}
+
+/// Severity rule categories that already name a tactic.
+fn from_category(category: &str) -> Option<&'static str> {
+    match category {
+        "injection" | "defense_evasion" => Some("defense_evasion"),
+        "persistence" => Some("persistence"),
+        "credential_access" => Some("credential_access"),
+        "lateral_movement" => Some("lateral_movement"),
+        "execution" => Some("execution"),
+        "network" => Some("command_and_control"),
+        _ => None,
+    }
+}
+
+/// The stage of one event, or None when no rule says anything about it.
+pub fn classify(event_type: &str, process_name: &str, details: &str, decoded: Option<&str>, category: Option<&str>) -> Option<&'static str> {
+    let haystack = format!("{} {} {}", process_name, details, decoded.unwrap_or_default()).to_lowercase();
+    RULES.iter()
+        .find(|r| type_matches(r.event_type, event_type) && (r.any.is_empty() || r.any.iter().any(|s| haystack.contains(s))))
+        .map(|r| r.stage)
+        .or_else(|| category.and_then(from_category))
+}
+
+pub fn label(stage: &str) -> &'static str {
+    STAGES.iter().find(|(s, _)| *s == stage).map(|(_, l)| *l).unwrap_or("Unknown")
+}
+
+#[derive(sqlx::FromRow)]
+struct Untagged {
+    id: i32,
+    event_type: String,
+    process_name: String,
+    details: String,
+    decoded_details: Option<String>,
+    category: Option<String>,
+}
+
+/// Tags the task's events stored before the column existed. Returns how many got a stage.
+pub async fn backfill(pool: &Pool<Postgres>, task_id: &str) -> Result<usize, sqlx::Error> {
+    let rows = sqlx::query_as::<_, Untagged>(
+        "SELECT id, event_type, process_name, details, decoded_details, category
+         FROM events WHERE task_id = $1 AND kill_chain_stage IS NULL"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+    let (ids, stages): (Vec<i32>, Vec<String>) = rows.iter()
+        .filter_map(|r| {
+            classify(&r.event_type, &r.process_name, &r.details, r.decoded_details.as_deref(), r.category.as_deref())
+                .map(|s| (r.id, s.to_string()))
+        })
+        .unzip();
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    sqlx::query(
+        "UPDATE events SET kill_chain_stage = u.stage
+         FROM UNNEST($1::int[], $2::text[]) AS u(id, stage)
+         WHERE events.id = u.id AND events.task_id = $3"
+    )
+    .bind(&ids)
+    .bind(&stages)
+    .bind(task_id)
+    .execute(pool)
+    .await?;
+    Ok(ids.len())
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct StageSummary {
+    pub stage: String,
+    pub label: &'static str,
+    pub events: i64,
+    pub first_seen: i64,
+    pub last_seen: i64,
+    pub max_severity: i32,
+    /// Highest-severity events of the stage.
+    pub example_event_ids: Vec<i32>,
+}
+
+#[derive(sqlx::FromRow)]
+struct StageRow {
+    stage: String,
+    events: i64,
+    first_seen: i64,
+    last_seen: i64,
+    max_severity: i32,
+    example_event_ids: Vec<i32>,
+}
+
+/// Per-stage counts for a task, in kill-chain order.
+pub async fn summary(pool: &Pool<Postgres>, task_id: &str) -> Result<Vec<StageSummary>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, StageRow>(
+        "SELECT kill_chain_stage AS stage, COUNT(*) AS events, MIN(timestamp) AS first_seen, MAX(timestamp) AS last_seen,
+                COALESCE(MAX(severity), 0) AS max_severity,
+                (array_agg(id ORDER BY severity DESC NULLS LAST, timestamp))[1:$2] AS example_event_ids
+         FROM events WHERE task_id = $1 AND kill_chain_stage IS NOT NULL
+         GROUP BY kill_chain_stage"
+    )
+    .bind(task_id)
+    .bind(EXAMPLES_PER_STAGE)
+    .fetch_all(pool)
+    .await?;
+    let mut out: Vec<StageSummary> = rows.into_iter().map(|r| StageSummary {
+        label: label(&r.stage),
+        stage: r.stage,
+        events: r.events,
+        first_seen: r.first_seen,
+        last_seen: r.last_seen,
+        max_severity: r.max_severity,
+        example_event_ids: r.example_event_ids,
+    }).collect();
+    out.sort_by_key(|s| STAGES.iter().position(|(k, _)| *k == s.stage).unwrap_or(STAGES.len()));
+    Ok(out)
+}
+
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Event counts per kill-chain stage, in kill-chain order")))]
+#[get("/tasks/{id}/kill-chain")]
+pub async fn get_kill_chain(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let task_id = path.into_inner();
+    if let Err(e) = backfill(pool.get_ref(), &task_id).await {
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() }));
+    }
+    match summary(pool.get_ref(), &task_id).await {
+        Ok(stages) => HttpResponse::Ok().json(serde_json::json!({ "task_id": task_id, "stages": stages })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
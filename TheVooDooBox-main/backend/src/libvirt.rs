@@ -0,0 +1,216 @@
+use async_trait::async_trait;
+use std::error::Error;
+use tokio::process::Command;
+use crate::hypervisor::{ConsoleTransport, Hypervisor, Node, Snapshot, Vm, VncTicket};
+
+// --- LIBVIRT / QEMU BACKEND ---
+// KVM hosts through virsh; VM ids are derived from domain names.
+
+pub struct LibvirtClient {
+    /// LIBVIRT_URI, default qemu:///system.
+    pub uri: String,
+    /// Reported as the single "node"; LIBVIRT_NODE_NAME, default the URI host or "localhost".
+    node_name: String,
+    /// Where VNC consoles are reachable from the backend; LIBVIRT_CONSOLE_HOST, default the URI host.
+    console_host: String,
+}
+
+struct Domain {
+    vmid: u64,
+    name: String,
+    state: String,
+}
+
+impl LibvirtClient {
+    pub fn from_env() -> Self {
+        let uri = std::env::var("LIBVIRT_URI").unwrap_or_else(|_| "qemu:///system".to_string());
+        // qemu+ssh://user@host:22/system -> host
+        let uri_host = uri.split("://").nth(1)
+            .and_then(|rest| rest.split('/').next())
+            .map(|authority| authority.rsplit('@').next().unwrap_or(authority))
+            .map(|host| host.split(':').next().unwrap_or(host).to_string())
+            .filter(|h| !h.is_empty());
+
+        let node_name = std::env::var("LIBVIRT_NODE_NAME").ok()
+            .or_else(|| uri_host.clone())
+            .unwrap_or_else(|| "localhost".to_string());
+        let console_host = std::env::var("LIBVIRT_CONSOLE_HOST").ok()
+            .or(uri_host)
+            .unwrap_or_else(|| "127.0.0.1".to_string());
+
+        Self { uri, node_name, console_host }
+    }
+
+    async fn virsh(&self, args: &[&str]) -> Result<String, Box<dyn Error>> {
+        let output = Command::new("virsh")
+            .arg("-c")
+            .arg(&self.uri)
+            .args(args)
+            .output()
+            .await
+            .map_err(|e| format!("Failed to run virsh: {}", e))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            return Err(format!("virsh {}: {}", args.first().unwrap_or(&""), stderr).into());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    async fn domains(&self) -> Result<Vec<Domain>, Box<dyn Error>> {
+        // " Id   Name          State"
+        // "-----------------------------"
+        // " 3    sandbox-301   running"
+        // " -    win11-302     shut off"
+        let listing = self.virsh(&["list", "--all"]).await?;
+        let mut domains = Vec::new();
+        for line in listing.lines().skip(2) {
+            let mut parts = line.split_whitespace();
+            let (Some(_id), Some(name)) = (parts.next(), parts.next()) else { continue };
+            let state = parts.collect::<Vec<_>>().join(" ");
+            let vmid = match trailing_number(name) {
+                Some(n) => n,
+                None => {
+                    let uuid = self.virsh(&["domuuid", name]).await?;
+                    uuid_vmid(uuid.trim())
+                }
+            };
+            domains.push(Domain { vmid, name: name.to_string(), state });
+        }
+        Ok(domains)
+    }
+
+    async fn domain_name(&self, vmid: u64) -> Result<String, Box<dyn Error>> {
+        self.domains().await?
+            .into_iter()
+            .find(|d| d.vmid == vmid)
+            .map(|d| d.name)
+            .ok_or_else(|| format!("No libvirt domain maps to VM ID {}", vmid).into())
+    }
+}
+
+fn trailing_number(name: &str) -> Option<u64> {
+    name.rsplit(['-', '_']).next()?.parse().ok()
+}
+
+/// Stable ID from the UUID, offset clear of name-derived IDs.
+fn uuid_vmid(uuid: &str) -> u64 {
+    let hex: String = uuid.chars().filter(|c| c.is_ascii_hexdigit()).take(8).collect();
+    1_000_000 + u64::from_str_radix(&hex, 16).unwrap_or(0) % 1_000_000_000
+}
+
+/// Proxmox status strings, so the frontend needs no per-backend handling.
+fn proxmox_status(state: &str) -> String {
+    match state {
+        "running" | "idle" => "running",
+        "paused" | "pmsuspended" => "paused",
+        _ => "stopped",
+    }.to_string()
+}
+
+#[async_trait(?Send)]
+impl Hypervisor for LibvirtClient {
+    fn kind(&self) -> &'static str {
+        "libvirt"
+    }
+
+    fn console_transport(&self) -> ConsoleTransport {
+        ConsoleTransport::RawTcp
+    }
+
+    async fn get_nodes(&self) -> Result<Vec<Node>, Box<dyn Error>> {
+        // Surfaces connection problems here rather than as an empty VM list
+        self.virsh(&["uri"]).await?;
+        Ok(vec![Node {
+            node: self.node_name.clone(),
+            status: "online".to_string(),
+            maxcpu: None,
+            maxmem: None,
+        }])
+    }
+
+    async fn get_vms(&self, node: &str) -> Result<Vec<Vm>, Box<dyn Error>> {
+        if node != self.node_name {
+            return Err(format!("Unknown libvirt node '{}' (expected '{}')", node, self.node_name).into());
+        }
+        Ok(self.domains().await?
+            .into_iter()
+            .map(|d| Vm {
+                vmid: d.vmid,
+                name: Some(d.name),
+                status: proxmox_status(&d.state),
+                cpus: None,
+                maxmem: None,
+            })
+            .collect())
+    }
+
+    async fn vm_action(&self, _node: &str, vmid: u64, action: &str) -> Result<(), Box<dyn Error>> {
+        let verb = match action {
+            "start" => "start",
+            "stop" => "destroy", // Proxmox "stop" is a hard power-off
+            "shutdown" => "shutdown",
+            "reboot" => "reboot",
+            "reset" => "reset",
+            "suspend" => "suspend",
+            "resume" => "resume",
+            other => return Err(format!("Unsupported VM action '{}'", other).into()),
+        };
+        let name = self.domain_name(vmid).await?;
+        match self.virsh(&[verb, &name]).await {
+            Ok(_) => Ok(()),
+            // Already in the requested state; Proxmox treats these as no-ops too
+            Err(e) if e.to_string().contains("already active") || e.to_string().contains("not running") => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn rollback_snapshot(&self, _node: &str, vmid: u64, snapshot: &str) -> Result<(), Box<dyn Error>> {
+        let name = self.domain_name(vmid).await?;
+        self.virsh(&["snapshot-revert", &name, snapshot, "--force"]).await?;
+        Ok(())
+    }
+
+    async fn create_snapshot(&self, _node: &str, vmid: u64, name: &str, description: &str) -> Result<(), Box<dyn Error>> {
+        let domain = self.domain_name(vmid).await?;
+        self.virsh(&["snapshot-create-as", &domain, name, description]).await?;
+        Ok(())
+    }
+
+    async fn delete_snapshot(&self, _node: &str, vmid: u64, name: &str) -> Result<(), Box<dyn Error>> {
+        let domain = self.domain_name(vmid).await?;
+        self.virsh(&["snapshot-delete", &domain, name]).await?;
+        Ok(())
+    }
+
+    async fn list_snapshots(&self, _node: &str, vmid: u64) -> Result<Vec<Snapshot>, Box<dyn Error>> {
+        let name = self.domain_name(vmid).await?;
+        let listing = self.virsh(&["snapshot-list", &name, "--name"]).await?;
+        Ok(listing.lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(|l| Snapshot { name: l.to_string(), description: None, snaptime: None, parent: None })
+            .collect())
+    }
+
+    async fn create_vnc_proxy(&self, _node: &str, vmid: u64) -> Result<VncTicket, Box<dyn Error>> {
+        let name = self.domain_name(vmid).await?;
+        // "127.0.0.1:0" or ":0" -> display 0 -> TCP 5900
+        let display = self.virsh(&["vncdisplay", &name]).await?;
+        let display = display.trim();
+        let (host, num) = display.rsplit_once(':').ok_or_else(|| format!("Unexpected vncdisplay output: '{}'", display))?;
+        let port = 5900 + num.parse::<u64>().map_err(|_| format!("Unexpected vncdisplay output: '{}'", display))?;
+        let host = match host {
+            "" | "127.0.0.1" | "localhost" | "0.0.0.0" => self.console_host.clone(),
+            h => h.to_string(),
+        };
+
+        Ok(VncTicket {
+            ticket: String::new(),
+            port: port.to_string(),
+            upid: format!("libvirt:{}", name),
+            cert: None,
+            password: None,
+            host: Some(host),
+        })
+    }
+}
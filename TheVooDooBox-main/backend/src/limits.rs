@@ -0,0 +1,208 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage, HttpRequest, HttpResponse};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use crate::auth::AuthUser;
+
+// --- RATE AND UPLOAD LIMITS ---
+
+const DEFAULT_IP_PER_MINUTE: u32 = 300;
+const DEFAULT_KEY_PER_MINUTE: u32 = 1200;
+
+/// Buckets are swept once there are this many, dropping those that have refilled.
+const MAX_BUCKETS: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+struct RateLimits {
+    ip_per_minute: u32,
+    key_per_minute: u32,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+fn per_minute_from_env(name: &str, default: u32) -> u32 {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.trim().parse::<u32>().ok())
+        .unwrap_or(default)
+}
+
+fn rate_limits() -> &'static RateLimits {
+    static LIMITS: OnceLock<RateLimits> = OnceLock::new();
+    LIMITS.get_or_init(|| RateLimits {
+        ip_per_minute: per_minute_from_env("RATE_LIMIT_PER_MINUTE", DEFAULT_IP_PER_MINUTE),
+        key_per_minute: per_minute_from_env("RATE_LIMIT_KEY_PER_MINUTE", DEFAULT_KEY_PER_MINUTE),
+        buckets: Mutex::new(HashMap::new()),
+    })
+}
+
+/// Refills the caller's bucket and takes a token when `consume`; Err is the seconds until the next one.
+fn take_token(key: &str, per_minute: u32, consume: bool) -> Result<(), u64> {
+    let limits = rate_limits();
+    let Ok(mut buckets) = limits.buckets.lock() else { return Ok(()) };
+    let now = Instant::now();
+    let capacity = per_minute as f64;
+    let refill_per_sec = capacity / 60.0;
+
+    if buckets.len() >= MAX_BUCKETS {
+        buckets.retain(|_, b| b.tokens + now.duration_since(b.updated).as_secs_f64() * refill_per_sec < capacity);
+    }
+
+    let bucket = buckets.entry(key.to_string()).or_insert(Bucket { tokens: capacity, updated: now });
+    bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * refill_per_sec).min(capacity);
+    bucket.updated = now;
+    if bucket.tokens >= 1.0 {
+        if consume {
+            bucket.tokens -= 1.0;
+        }
+        Ok(())
+    } else {
+        Err(((1.0 - bucket.tokens) / refill_per_sec).ceil().max(1.0) as u64)
+    }
+}
+
+fn too_many_requests(scope: &str, per_minute: u32, retry_after: u64) -> HttpResponse {
+    crate::metrics::inc("voodoobox_rate_limited_total", &[("scope", scope)], 1.0);
+    HttpResponse::TooManyRequests()
+        .insert_header(("Retry-After", retry_after.to_string()))
+        .json(serde_json::json!({
+            "error": "Rate limit exceeded",
+            "limit_per_minute": per_minute,
+            "retry_after": retry_after,
+        }))
+}
+
+fn exempt(req: &ServiceRequest) -> bool {
+    crate::api_version::unversioned(req.path()).starts_with("/health")
+}
+
+/// Wrap outside `auth::require_auth`: an IP whose unauthenticated or rejected requests used up
+/// its bucket is turned away before credentials are looked up.
+pub async fn ip_rate_limit<B: MessageBody + 'static>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let per_minute = rate_limits().ip_per_minute;
+    if per_minute == 0 || exempt(&req) {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    }
+    let ip = req.peer_addr().map(|a| a.ip().to_string()).unwrap_or_else(|| "unknown".to_string());
+    let key = format!("ip:{}", ip);
+    if let Err(retry_after) = take_token(&key, per_minute, false) {
+        return Ok(req.into_response(too_many_requests("ip", per_minute, retry_after)).map_into_right_body());
+    }
+
+    let res = next.call(req).await?;
+    // Authenticated callers are metered by key instead
+    if res.request().extensions().get::<AuthUser>().is_none() {
+        let _ = take_token(&key, per_minute, true);
+    }
+    Ok(res.map_into_left_body())
+}
+
+/// Wrap inside `auth::require_auth` so authenticated callers are metered by key.
+pub async fn rate_limit<B: MessageBody + 'static>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let per_minute = rate_limits().key_per_minute;
+    let caller = req.extensions().get::<AuthUser>().map(|u| (u.user_id, u.key_id));
+    let key = match caller {
+        // The internal loopback credential
+        Some((0, None)) => None,
+        Some((_, Some(key_id))) => Some(format!("key:{}", key_id)),
+        Some((user_id, None)) => Some(format!("user:{}", user_id)),
+        None => None,
+    };
+    let Some(key) = key.filter(|_| per_minute > 0 && !exempt(&req)) else {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    };
+
+    if let Err(retry_after) = take_token(&key, per_minute, true) {
+        return Ok(req.into_response(too_many_requests("key", per_minute, retry_after)).map_into_right_body());
+    }
+
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+/// Running byte count for one multipart upload, checked against a per-endpoint cap in MB.
+pub struct UploadLimit {
+    what: &'static str,
+    max_bytes: u64,
+    received: u64,
+}
+
+impl UploadLimit {
+    fn from_env(what: &'static str, var: &str, default_mb: u64) -> Self {
+        let mb = std::env::var(var)
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(default_mb);
+        Self { what, max_bytes: mb * 1024 * 1024, received: 0 }
+    }
+
+    /// Sample submissions, archives included (MAX_SAMPLE_UPLOAD_MB, default 512).
+    pub fn sample() -> Self {
+        Self::from_env("Sample upload", "MAX_SAMPLE_UPLOAD_MB", 512)
+    }
+
+    /// Files an agent pivots back from the guest (MAX_PIVOT_UPLOAD_MB, default 512).
+    pub fn pivot() -> Self {
+        Self::from_env("Pivot upload", "MAX_PIVOT_UPLOAD_MB", 512)
+    }
+
+    /// An agent's URL crawl: DOM, favicon and any payload (MAX_URL_ARTIFACT_UPLOAD_MB, default 128).
+    pub fn url_artifacts() -> Self {
+        Self::from_env("URL artifact upload", "MAX_URL_ARTIFACT_UPLOAD_MB", 128)
+    }
+
+    /// A task bundle moved in from another instance (MAX_BUNDLE_UPLOAD_MB, default 4096).
+    pub fn bundle() -> Self {
+        Self::from_env("Bundle import", "MAX_BUNDLE_UPLOAD_MB", 4096)
+    }
+
+    /// One agent screenshot request, all monitors (MAX_SCREENSHOT_UPLOAD_MB, default 25).
+    pub fn screenshot() -> Self {
+        Self::from_env("Screenshot upload", "MAX_SCREENSHOT_UPLOAD_MB", 25)
+    }
+
+    /// A process dump the agent uploads from the guest (MAX_MEMORY_DUMP_UPLOAD_MB, default 2048).
+    pub fn memory_dump() -> Self {
+        Self::from_env("Memory dump upload", "MAX_MEMORY_DUMP_UPLOAD_MB", 2048)
+    }
+
+    /// Refuses early when the declared Content-Length is already over the cap.
+    pub fn check_declared(&self, req: &HttpRequest) -> Result<(), HttpResponse> {
+        let declared = req.headers().get(actix_web::http::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        match declared {
+            Some(len) if len > self.max_bytes => Err(self.too_large()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts a received chunk; Err once the body as a whole passes the cap.
+    pub fn add(&mut self, len: usize) -> Result<(), HttpResponse> {
+        self.received += len as u64;
+        if self.received > self.max_bytes {
+            Err(self.too_large())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn too_large(&self) -> HttpResponse {
+        HttpResponse::PayloadTooLarge().json(serde_json::json!({
+            "error": format!("{} exceeds the {} MB limit", self.what, self.max_bytes / (1024 * 1024)),
+            "limit_bytes": self.max_bytes,
+        }))
+    }
+}
@@ -0,0 +1,436 @@
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use crate::alerts::NewAlert;
+use crate::sigma::EventRef;
+use crate::stream::Broadcaster;
+
+// --- PROCESS LINEAGE RULES ---
+// Parent -> child process rules checked on every PROCESS_CREATE.
+
+/// (name, parent, child, mode, level, description, tags)
+const DEFAULT_RULES: &[(&str, &str, &str, &str, &str, &str, &[&str])] = &[
+    (
+        "office_spawns_shell",
+        "winword.exe|excel.exe|powerpnt.exe|outlook.exe|msaccess.exe|mspub.exe|onenote.exe|visio.exe",
+        "cmd.exe|powershell.exe|pwsh.exe|wscript.exe|cscript.exe|mshta.exe|rundll32.exe|regsvr32.exe|certutil.exe|bitsadmin.exe|schtasks.exe|msiexec.exe",
+        "deny", "high", "Office application started a shell or LOLBin",
+        &["attack.execution", "attack.t1204.002"],
+    ),
+    (
+        "pdf_reader_spawns_shell",
+        "acrord32.exe|acrobat.exe|foxitreader.exe|foxitpdfreader.exe",
+        "cmd.exe|powershell.exe|pwsh.exe|wscript.exe|cscript.exe|mshta.exe|rundll32.exe",
+        "deny", "high", "PDF reader started a shell",
+        &["attack.execution", "attack.t1204.002"],
+    ),
+    (
+        "browser_spawns_shell",
+        "chrome.exe|msedge.exe|firefox.exe|iexplore.exe|brave.exe|opera.exe",
+        "cmd.exe|powershell.exe|pwsh.exe|wscript.exe|cscript.exe|mshta.exe",
+        "deny", "high", "Web browser started a shell",
+        &["attack.initial_access", "attack.t1189"],
+    ),
+    (
+        "wmi_spawns_shell",
+        "wmiprvse.exe",
+        "cmd.exe|powershell.exe|pwsh.exe|wscript.exe|cscript.exe|mshta.exe|rundll32.exe",
+        "deny", "medium", "WMI provider host started a shell",
+        &["attack.execution", "attack.t1047"],
+    ),
+    (
+        "lsass_child",
+        "lsass.exe",
+        "*",
+        "deny", "critical", "LSASS started a process",
+        &["attack.credential_access", "attack.t1003.001"],
+    ),
+    (
+        "svchost_wrong_parent",
+        "services.exe|svchost.exe|msmpeng.exe",
+        "svchost.exe",
+        "require", "high", "svchost.exe not started by services.exe",
+        &["attack.defense_evasion", "attack.t1036.005"],
+    ),
+    (
+        "system_process_wrong_parent",
+        "wininit.exe",
+        "services.exe|lsass.exe|lsaiso.exe",
+        "require", "critical", "Core system process not started by wininit.exe",
+        &["attack.defense_evasion", "attack.t1036.005"],
+    ),
+];
+
+/// Per-task pid -> image maps kept in memory; the least recently used are dropped beyond this.
+const MAX_TRACKED_TASKS: usize = 64;
+
+#[derive(Serialize, sqlx::FromRow, Clone)]
+pub struct LineageRule {
+    pub id: i32,
+    pub name: String,
+    pub parent: String,
+    pub child: String,
+    /// deny | require
+    pub mode: String,
+    pub level: String,
+    pub description: String,
+    pub tags: Vec<String>,
+    pub enabled: bool,
+    pub created_at: i64,
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct LineageRuleRequest {
+    /// Required when creating; a rule with an existing name is replaced.
+    pub name: Option<String>,
+    pub parent: Option<String>,
+    pub child: Option<String>,
+    /// deny (default) | require
+    pub mode: Option<String>,
+    /// informational | low | medium (default) | high | critical
+    pub level: Option<String>,
+    pub description: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub enabled: Option<bool>,
+}
+
+struct Names {
+    images: HashMap<i32, String>,
+    used: u64,
+}
+
+fn loaded() -> &'static RwLock<Arc<Vec<LineageRule>>> {
+    static RULES: OnceLock<RwLock<Arc<Vec<LineageRule>>>> = OnceLock::new();
+    RULES.get_or_init(|| RwLock::new(Arc::new(Vec::new())))
+}
+
+fn rules() -> Arc<Vec<LineageRule>> {
+    loaded().read().map(|r| Arc::clone(&r)).unwrap_or_default()
+}
+
+fn names() -> &'static Mutex<(u64, HashMap<String, Names>)> {
+    static NAMES: OnceLock<Mutex<(u64, HashMap<String, Names>)>> = OnceLock::new();
+    NAMES.get_or_init(|| Mutex::new((0, HashMap::new())))
+}
+
+/// "C:\Windows\System32\cmd.exe" -> "cmd.exe"
+fn image_name(path: &str) -> String {
+    path.trim().trim_matches('"').rsplit(['\\', '/']).next().unwrap_or("").to_lowercase()
+}
+
+fn matches_pattern(pattern: &str, image: &str) -> bool {
+    pattern.split('|').map(str::trim).any(|p| {
+        if p == "*" {
+            return true;
+        }
+        match p.split_once('*') {
+            Some((prefix, suffix)) => image.len() >= prefix.len() + suffix.len() && image.starts_with(prefix) && image.ends_with(suffix),
+            None => image == p,
+        }
+    })
+}
+
+/// Normalizes a pattern: lowercase, no paths, no empty alternatives.
+fn clean_pattern(pattern: &str) -> Option<String> {
+    let parts: Vec<String> = pattern.split('|').map(image_name).filter(|p| !p.is_empty()).collect();
+    (!parts.is_empty()).then(|| parts.join("|"))
+}
+
+/// True when the pair breaks the rule.
+fn violates(rule: &LineageRule, parent: &str, child: &str) -> bool {
+    if !matches_pattern(&rule.child, child) {
+        return false;
+    }
+    match rule.mode.as_str() {
+        "require" => !matches_pattern(&rule.parent, parent),
+        _ => matches_pattern(&rule.parent, parent),
+    }
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS lineage_rules (
+            id SERIAL PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            parent TEXT NOT NULL,
+            child TEXT NOT NULL,
+            mode TEXT NOT NULL DEFAULT 'deny',
+            level TEXT NOT NULL DEFAULT 'medium',
+            description TEXT NOT NULL,
+            tags TEXT[] NOT NULL DEFAULT '{}',
+            enabled BOOLEAN NOT NULL DEFAULT TRUE,
+            created_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM lineage_rules")
+        .fetch_one(pool)
+        .await?;
+    if count == 0 {
+        let now = chrono::Utc::now().timestamp();
+        for (name, parent, child, mode, level, description, tags) in DEFAULT_RULES {
+            sqlx::query(
+                "INSERT INTO lineage_rules (name, parent, child, mode, level, description, tags, created_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT (name) DO NOTHING"
+            )
+            .bind(name)
+            .bind(parent)
+            .bind(child)
+            .bind(mode)
+            .bind(level)
+            .bind(description)
+            .bind(tags.iter().map(|t| t.to_string()).collect::<Vec<_>>())
+            .bind(now)
+            .execute(pool)
+            .await?;
+        }
+        println!("[LINEAGE] Seeded {} default lineage rules.", DEFAULT_RULES.len());
+    }
+
+    let loaded = reload(pool).await?;
+    println!("[LINEAGE] Database initialized (lineage_rules), {} rules active.", loaded);
+    Ok(())
+}
+
+pub async fn reload(pool: &Pool<Postgres>) -> Result<usize, sqlx::Error> {
+    let active = sqlx::query_as::<_, LineageRule>("SELECT * FROM lineage_rules WHERE enabled ORDER BY id")
+        .fetch_all(pool)
+        .await?;
+    let count = active.len();
+    if let Ok(mut current) = loaded().write() {
+        *current = Arc::new(active);
+    }
+    Ok(count)
+}
+
+/// Remembers which image each pid runs, from events that name a real image.
+fn learn(events: &[EventRef<'_>]) {
+    let Ok(mut guard) = names().lock() else { return };
+    let (clock, tasks) = &mut *guard;
+    for evt in events {
+        let Some(task_id) = evt.task_id else { continue };
+        if evt.process_id <= 0 || !(evt.event_type == "PROCESS_CREATE" || evt.process_name.contains(['.', '\\', '/'])) {
+            continue;
+        }
+        *clock += 1;
+        let entry = tasks.entry(task_id.to_string()).or_insert_with(|| Names { images: HashMap::new(), used: 0 });
+        entry.used = *clock;
+        entry.images.insert(evt.process_id, image_name(evt.process_name));
+    }
+    if tasks.len() > MAX_TRACKED_TASKS {
+        let mut by_age: Vec<(u64, String)> = tasks.iter().map(|(k, v)| (v.used, k.clone())).collect();
+        by_age.sort();
+        for (_, task_id) in by_age.into_iter().take(tasks.len() - MAX_TRACKED_TASKS) {
+            tasks.remove(&task_id);
+        }
+    }
+}
+
+fn known_parent(task_id: &str, ppid: i32) -> Option<String> {
+    names().lock().ok()?.1.get(task_id)?.images.get(&ppid).cloned()
+}
+
+/// Runs the enabled rules over the process creations among freshly stored events.
+pub async fn evaluate(pool: &Pool<Postgres>, broadcaster: Option<&Broadcaster>, events: &[EventRef<'_>]) {
+    let rules = rules();
+    learn(events);
+    if rules.is_empty() {
+        return;
+    }
+    let creates: Vec<&EventRef> = events.iter()
+        .filter(|e| e.event_type == "PROCESS_CREATE" && e.task_id.is_some() && e.parent_process_id > 0)
+        .collect();
+    if creates.is_empty() {
+        return;
+    }
+
+    let mut hits = Vec::new();
+    for evt in creates {
+        let task_id = evt.task_id.unwrap_or_default();
+        let parent = match known_parent(task_id, evt.parent_process_id) {
+            Some(p) => p,
+            // Started before the agent was watching, or in an earlier batch we no longer hold
+            None => match sqlx::query_scalar::<_, String>(
+                "SELECT process_name FROM events WHERE task_id = $1 AND process_id = $2 AND process_name <> '' ORDER BY id DESC LIMIT 1"
+            )
+            .bind(task_id)
+            .bind(evt.parent_process_id)
+            .fetch_optional(pool)
+            .await
+            {
+                Ok(Some(name)) => image_name(&name),
+                _ => continue,
+            },
+        };
+        let child = image_name(evt.process_name);
+        if parent.is_empty() || child.is_empty() {
+            continue;
+        }
+
+        for rule in rules.iter().filter(|r| violates(r, &parent, &child)) {
+            hits.push(NewAlert {
+                task_id: Some(task_id.to_string()),
+                event_id: evt.id,
+                source: "lineage",
+                rule_id: format!("lineage:{}", rule.name),
+                rule_title: rule.description.clone(),
+                level: rule.level.clone(),
+                severity: crate::sigma::level_severity(&rule.level).unwrap_or(50),
+                tags: rule.tags.clone(),
+                rule_references: Vec::new(),
+                process_id: evt.process_id,
+                process_name: evt.process_name.to_string(),
+                details: format!("{} (pid {}) -> {} (pid {}): {}", parent, evt.parent_process_id, child, evt.process_id, evt.details),
+                timestamp: evt.timestamp,
+            });
+        }
+    }
+    if !hits.is_empty() {
+        crate::alerts::record(pool, broadcaster, hits).await;
+    }
+}
+
+/// Checks and normalizes a request; `existing` supplies the fields an update leaves out.
+fn validate(req: &LineageRuleRequest, existing: Option<&LineageRule>) -> Result<(String, String, String, String, String, String, Vec<String>), String> {
+    let name = req.name.as_deref().map(|n| n.trim().to_lowercase()).or_else(|| existing.map(|e| e.name.clone()))
+        .filter(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-')))
+        .ok_or("name is required (letters, digits, _ and -)")?;
+    let parent = match req.parent.as_deref() {
+        Some(p) => clean_pattern(p).ok_or("parent must name at least one image")?,
+        None => existing.map(|e| e.parent.clone()).ok_or("parent is required")?,
+    };
+    let child = match req.child.as_deref() {
+        Some(c) => clean_pattern(c).ok_or("child must name at least one image")?,
+        None => existing.map(|e| e.child.clone()).ok_or("child is required")?,
+    };
+    let mode = req.mode.as_deref().map(|m| m.trim().to_lowercase())
+        .or_else(|| existing.map(|e| e.mode.clone()))
+        .unwrap_or_else(|| "deny".to_string());
+    if mode != "deny" && mode != "require" {
+        return Err("mode must be deny or require".to_string());
+    }
+    let level = req.level.as_deref().map(|l| l.trim().to_lowercase())
+        .or_else(|| existing.map(|e| e.level.clone()))
+        .unwrap_or_else(|| "medium".to_string());
+    if crate::sigma::level_severity(&level).is_none() {
+        return Err(format!("Unknown level '{}'", level));
+    }
+    let description = req.description.as_deref().map(str::trim).filter(|d| !d.is_empty()).map(str::to_string)
+        .or_else(|| existing.map(|e| e.description.clone()))
+        .unwrap_or_else(|| format!("{} -> {}", parent, child));
+    let tags = req.tags.clone().or_else(|| existing.map(|e| e.tags.clone())).unwrap_or_default();
+    Ok((name, parent, child, mode, level, description, tags))
+}
+
+#[utoipa::path(tag = "detections", responses((status = 200, description = "Success")))]
+#[get("/settings/lineage-rules")]
+pub async fn list_lineage_rules(pool: web::Data<Pool<Postgres>>) -> impl Responder {
+    match sqlx::query_as::<_, LineageRule>("SELECT * FROM lineage_rules ORDER BY id").fetch_all(pool.get_ref()).await {
+        Ok(rules) => HttpResponse::Ok().json(rules),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Adds a rule; one with the same name is replaced.
+#[utoipa::path(tag = "detections", request_body = LineageRuleRequest, responses(
+    (status = 200, description = "Rule stored and active"),
+    (status = 400, description = "Missing or invalid field"),
+))]
+#[post("/settings/lineage-rules")]
+pub async fn add_lineage_rule(pool: web::Data<Pool<Postgres>>, req: web::Json<LineageRuleRequest>) -> impl Responder {
+    let (name, parent, child, mode, level, description, tags) = match validate(&req, None) {
+        Ok(fields) => fields,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+    let stored = sqlx::query_as::<_, LineageRule>(
+        "INSERT INTO lineage_rules (name, parent, child, mode, level, description, tags, enabled, created_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+         ON CONFLICT (name) DO UPDATE SET parent = EXCLUDED.parent, child = EXCLUDED.child, mode = EXCLUDED.mode,
+             level = EXCLUDED.level, description = EXCLUDED.description, tags = EXCLUDED.tags, enabled = EXCLUDED.enabled
+         RETURNING *"
+    )
+    .bind(&name)
+    .bind(&parent)
+    .bind(&child)
+    .bind(&mode)
+    .bind(&level)
+    .bind(&description)
+    .bind(&tags)
+    .bind(req.enabled.unwrap_or(true))
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_one(pool.get_ref())
+    .await;
+    match stored {
+        Ok(rule) => {
+            let _ = reload(pool.get_ref()).await;
+            HttpResponse::Ok().json(rule)
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Changes any of a rule's fields, e.g. `{"enabled": false}` to turn a built-in rule off.
+#[utoipa::path(tag = "detections", request_body = LineageRuleRequest, responses(
+    (status = 200, description = "Rule updated"),
+    (status = 400, description = "Invalid field"),
+    (status = 404, description = "Rule not found"),
+))]
+#[post("/settings/lineage-rules/{id}")]
+pub async fn update_lineage_rule(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<i32>,
+    req: web::Json<LineageRuleRequest>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let existing = match sqlx::query_as::<_, LineageRule>("SELECT * FROM lineage_rules WHERE id = $1").bind(id).fetch_optional(pool.get_ref()).await {
+        Ok(Some(rule)) => rule,
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Lineage rule not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    let (name, parent, child, mode, level, description, tags) = match validate(&req, Some(&existing)) {
+        Ok(fields) => fields,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+    let updated = sqlx::query_as::<_, LineageRule>(
+        "UPDATE lineage_rules SET name = $2, parent = $3, child = $4, mode = $5, level = $6, description = $7, tags = $8, enabled = $9
+         WHERE id = $1 RETURNING *"
+    )
+    .bind(id)
+    .bind(&name)
+    .bind(&parent)
+    .bind(&child)
+    .bind(&mode)
+    .bind(&level)
+    .bind(&description)
+    .bind(&tags)
+    .bind(req.enabled.unwrap_or(existing.enabled))
+    .fetch_one(pool.get_ref())
+    .await;
+    match updated {
+        Ok(rule) => {
+            let _ = reload(pool.get_ref()).await;
+            HttpResponse::Ok().json(rule)
+        }
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "detections", responses((status = 200, description = "Success")))]
+#[delete("/settings/lineage-rules/{id}")]
+pub async fn delete_lineage_rule(pool: web::Data<Pool<Postgres>>, path: web::Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    match sqlx::query("DELETE FROM lineage_rules WHERE id = $1").bind(id).execute(pool.get_ref()).await {
+        Ok(res) if res.rows_affected() == 0 => {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": "Lineage rule not found" }))
+        }
+        Ok(_) => {
+            let _ = reload(pool.get_ref()).await;
+            HttpResponse::Ok().json(serde_json::json!({ "status": "deleted", "id": id }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
@@ -2,17 +2,27 @@ use actix_web::{get, post, delete, web, App, HttpResponse, HttpServer, Responder
 use dotenv::dotenv;
 use std::env;
 use std::fs;
-use sqlx::{postgres::PgPoolOptions, Pool, Postgres, Row};
+use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use sha2::{Sha256, Digest};
 
+mod hypervisor;
 mod proxmox;
+mod libvirt;
+mod vsphere;
+mod cloud;
 mod stream;
 mod spice_relay;
 mod vnc_relay;
 mod ai;
 mod ai_analysis;
+mod ai_usage;
+mod ai_tools;
+mod evidence_check;
+mod rule_drafts;
+mod chat_sessions;
+mod prompts;
 mod reports;
 mod virustotal; // Registered
 mod remnux;
@@ -21,6 +31,71 @@ mod notes;
 mod detox_api;
 mod memory;
 mod action_manager;
+mod agent_updates;
+mod noise_filters;
+mod severity_rules;
+mod auth;
+mod task_queue;
+mod sandbox_pool;
+mod comparison;
+mod batch;
+mod dedup;
+mod analysis_profiles;
+mod network_policy;
+mod fakenet;
+mod suricata;
+mod mitm;
+mod archive;
+mod email;
+mod static_only;
+mod guest_agent;
+mod volatility;
+mod golden_image;
+mod process_tree;
+mod timeline;
+mod network_graph;
+mod iocs;
+mod stix;
+mod misp;
+mod event_export;
+mod siem;
+mod metrics;
+mod health;
+mod audit;
+mod retention;
+mod storage;
+mod sample_download;
+mod screenshots;
+mod ocr;
+mod openapi;
+mod api_version;
+mod limits;
+mod schedules;
+mod url_analysis;
+mod chunked_upload;
+mod bundle;
+mod relationships;
+mod kill_chain;
+mod alerts;
+mod sigma;
+mod scoring;
+mod ioc_correlation;
+mod beaconing;
+mod dns_reputation;
+mod ransomware;
+mod lineage;
+mod decoder;
+mod mitre;
+mod obfuscation;
+mod rollup;
+mod yara;
+mod tlsh;
+mod pe;
+mod static_properties;
+mod office;
+mod pdf;
+mod strings;
+use hypervisor::{ConsoleTransport, Hypervisor};
 use ai_analysis::{AnalysisRequest, AIReport, ManualAnalysisRequest};
 use ai::manager::{AIManager, ProviderType};
 use ai::provider::{ChatMessage};
@@ -31,45 +106,21 @@ use ai::manager::StreamEvent;
 #[derive(serde::Deserialize)]
 pub struct ChatRequest {
     pub message: String,
+    // Only read when starting a conversation; a resumed one uses its stored history
+    #[serde(default)]
     pub history: Vec<ChatMessage>,
     pub task_id: Option<String>,
     pub page_context: Option<String>,
+    // Conversation to continue (see chat_sessions.rs); a new one is started without it
+    pub session_id: Option<String>,
 }
 
 
 // ConfigRequest moved down to line ~1350 for better grouping with its handlers
 
-const NOISE_PROCESSES: &[&str] = &[
-    "voodoobox-agent-windows.exe",
-    "voodoobox-agent.exe",
-    "officeclicktorun.exe",
-    "conhost.exe",
-    "svchost.exe",
-    "lsass.exe",
-    "services.exe",
-    "wininit.exe",
-    "smss.exe",
-    "csrss.exe",
-    "winlogon.exe",
-    "spoolsv.exe",
-    "searchindexer.exe",
-    "taskhostw.exe",
-    "sppsvc.exe",
-    "fontdrvhost.exe",
-    "dwm.exe",
-    "ctfmon.exe",
-    "taskmgr.exe",
-    "mallab-agent.exe",
-    "mallab-agent"
-];
-
-#[get("/health")]
-async fn health_check() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!({ "status": "ok", "service": "hyper-bridge" }))
-}
-
+#[utoipa::path(tag = "vms", responses((status = 200, description = "Success")))]
 #[get("/vms")]
-async fn list_all_vms(client: web::Data<proxmox::ProxmoxClient>) -> impl Responder {
+async fn list_all_vms(client: web::Data<dyn Hypervisor>) -> impl Responder {
     match client.get_nodes().await {
         Ok(nodes) => {
             println!("[PROXMOX] Found {} nodes", nodes.len());
@@ -103,9 +154,10 @@ async fn list_all_vms(client: web::Data<proxmox::ProxmoxClient>) -> impl Respond
     }
 }
 
+#[utoipa::path(tag = "vms", responses((status = 200, description = "Success")))]
 #[post("/vms/{node}/{vmid}/status")]
 async fn vm_control(
-    client: web::Data<proxmox::ProxmoxClient>,
+    client: web::Data<dyn Hypervisor>,
     path: web::Path<(String, u64)>,
     req: web::Json<serde_json::Value>
 ) -> impl Responder {
@@ -117,23 +169,45 @@ async fn vm_control(
     }
 }
 
+#[utoipa::path(tag = "vms", responses((status = 200, description = "Success")))]
 #[post("/vms/{node}/{vmid}/revert")]
 async fn vm_revert(
-    client: web::Data<proxmox::ProxmoxClient>,
+    client: web::Data<dyn Hypervisor>,
+    pool: web::Data<Pool<Postgres>>,
     path: web::Path<(String, u64)>,
     req: web::Json<serde_json::Value>
 ) -> impl Responder {
     let (node, vmid) = path.into_inner();
-    let snapshot = req["snapshot"].as_str().unwrap_or("GOLD_IMAGE");
-    match client.rollback_snapshot(&node, vmid, snapshot).await {
+    let snapshot = match req["snapshot"].as_str() {
+        Some(s) => s.to_string(),
+        None => sandbox_pool::snapshot_for(pool.get_ref(), vmid).await,
+    };
+    if let Err(e) = sandbox_pool::ensure_snapshot(client.get_ref(), &node, vmid, &snapshot).await {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": e }));
+    }
+    match client.rollback_snapshot(&node, vmid, &snapshot).await {
         Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "status": "success", "snapshot": snapshot })),
         Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
     }
 }
 
+#[utoipa::path(tag = "vms", responses((status = 200, description = "Success")))]
+#[get("/vms/{node}/{vmid}/snapshots")]
+async fn vm_snapshots(
+    client: web::Data<dyn Hypervisor>,
+    path: web::Path<(String, u64)>,
+) -> impl Responder {
+    let (node, vmid) = path.into_inner();
+    match client.list_snapshots(&node, vmid).await {
+        Ok(snaps) => HttpResponse::Ok().json(snaps),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "vms", responses((status = 200, description = "Success")))]
 #[post("/vms/{node}/{vmid}/vnc")]
 async fn vnc_proxy(
-    client: web::Data<proxmox::ProxmoxClient>, 
+    client: web::Data<dyn Hypervisor>, 
     path: web::Path<(String, u64)>
 ) -> impl Responder {
     let (node, vmid) = path.into_inner();
@@ -143,9 +217,10 @@ async fn vnc_proxy(
     }
 }
 
+#[utoipa::path(tag = "vms", responses((status = 200, description = "Success")))]
 #[post("/vms/{node}/{vmid}/spice")]
 async fn spice_proxy(
-    client: web::Data<proxmox::ProxmoxClient>, 
+    client: web::Data<dyn Hypervisor>, 
     path: web::Path<(String, u64)>
 ) -> impl Responder {
     let (node, vmid) = path.into_inner();
@@ -158,16 +233,18 @@ async fn spice_proxy(
 use actix_web::{HttpRequest, Error};
 use actix_web_actors::ws;
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 struct SpiceWsQuery {
     host: Option<String>,
 }
 
+#[utoipa::path(tag = "vms", responses((status = 200, description = "Success")))]
 #[get("/vms/{node}/{vmid}/spice-ws")]
 async fn spice_websocket(
     req: HttpRequest,
     stream: web::Payload,
-    client: web::Data<proxmox::ProxmoxClient>,
+    client: web::Data<dyn Hypervisor>,
     path: web::Path<(String, u64)>,
     query: web::Query<SpiceWsQuery>,
 ) -> Result<HttpResponse, Error> {
@@ -205,7 +282,7 @@ async fn spice_websocket(
     // Use the API Token (auth_header) for the Proxy Authentication
     // The "password" variable previously held the Spice Password, which is useless for the Proxy.
     // We repurpose the 4th argument of SpiceRelay::new to be the PROXY credentials.
-    let proxy_auth = client.auth_header.clone(); 
+    let proxy_auth = client.upstream_auth();
 
     let relay = spice_relay::SpiceRelay::new(proxy_addr, target_host, target_port, proxy_auth);
     ws::WsResponseBuilder::new(relay, &req, stream)
@@ -213,18 +290,20 @@ async fn spice_websocket(
         .start()
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 struct VncWsQuery {
     port: String,
     ticket: String,
     host: Option<String>,
 }
 
+#[utoipa::path(tag = "vms", responses((status = 200, description = "Success")))]
 #[get("/vms/{node}/{vmid}/vnc-ws")]
 async fn vnc_websocket(
     req: HttpRequest,
     stream: web::Payload,
-    client: web::Data<proxmox::ProxmoxClient>,
+    client: web::Data<dyn Hypervisor>,
     path: web::Path<(String, u64)>,
     query: web::Query<VncWsQuery>,
 ) -> Result<HttpResponse, Error> {
@@ -259,8 +338,13 @@ async fn vnc_websocket(
     println!("[VNC_WS] Proxying to: wss://{}:8006/... (Port {})", host, port);
     
     // 3. Start Relay with API Token for Upstream Auth
-    let api_auth = client.auth_header.clone();
-    let relay = vnc_relay::VncRelay::new(target_wss, api_auth);
+    let relay = match client.console_transport() {
+        ConsoleTransport::RawTcp => vnc_relay::VncRelay::tcp(format!("{}:{}", host, port)),
+        ConsoleTransport::TicketWebsocket => {
+            vnc_relay::VncRelay::new(format!("wss://{}:{}/ticket/{}", host, port, auth_ticket), client.upstream_auth())
+        }
+        ConsoleTransport::ProxmoxWebsocket => vnc_relay::VncRelay::new(target_wss, client.upstream_auth()),
+    };
     
     ws::WsResponseBuilder::new(relay, &req, stream)
         .protocols(&["binary"])
@@ -300,8 +384,37 @@ impl AgentManager {
         });
     }
 
-    async fn remove(&self, id: &str) {
-        self.sessions.lock().await.remove(id);
+    // The key may already belong to a reconnected agent; only drop it if it is still ours
+    async fn remove(&self, id: &str, tx: &mpsc::UnboundedSender<String>) {
+        let mut sessions = self.sessions.lock().await;
+        if sessions.get(id).is_some_and(|s| s.tx.same_channel(tx)) {
+            sessions.remove(id);
+        }
+    }
+
+    // Re-keys a connection from its socket address to the handshake's session ID, keeping any task binding
+    async fn adopt_identity(&self, provisional: &str, session_id: &str, hostname: Option<String>) {
+        let mut sessions = self.sessions.lock().await;
+        let Some(mut session) = sessions.remove(provisional) else { return };
+        if let Some(previous) = sessions.remove(session_id) {
+            if session.active_task_id.is_none() {
+                session.active_task_id = previous.active_task_id;
+            }
+            println!("[AGENT] Session {} reconnected from {}", session_id, provisional);
+        }
+        session.hostname = hostname;
+        sessions.insert(session_id.to_string(), session);
+    }
+
+    // `None` for an unknown session, `Some(None)` for a connected agent with no task yet.
+    async fn task_for_session(&self, session_id: &str) -> Option<Option<String>> {
+        self.sessions.lock().await.get(session_id).map(|s| s.active_task_id.clone())
+    }
+
+    async fn find_session_by_task(&self, task_id: &str) -> Option<String> {
+        self.sessions.lock().await.iter()
+            .find(|(_, s)| s.active_task_id.as_deref() == Some(task_id))
+            .map(|(id, _)| id.clone())
     }
 
     // Set task ID for a specific session (by ID or first available if none assigned)
@@ -313,7 +426,7 @@ impl AgentManager {
         }
     }
 
-    // Helper to get the first active task ID found (for legacy global endpoints)
+    // Helper to get the first active task ID found (AI chat context when no task is given)
     async fn get_any_active_task_id(&self) -> Option<String> {
         let sessions = self.sessions.lock().await;
         for session in sessions.values() {
@@ -371,9 +484,35 @@ pub struct RawAgentEvent {
     pub timestamp: i64,
     pub task_id: Option<String>,
     pub digital_signature: Option<String>,
+    // Agent-assigned 0-100 score and category (see severity_rules); absent from older agents.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub severity: Option<i32>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub category: Option<String>,
+    // ATT&CK tactic assigned at ingest (see kill_chain).
+    #[serde(default)]
+    #[sqlx(default)]
+    pub kill_chain_stage: Option<String>,
+    // Set when decoded_details came from the server-side decoder rather than the agent.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub decoder_version: Option<i32>,
+    // 0-100 for command lines and script blocks (see obfuscation); NULL for other events.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub obfuscation_score: Option<i32>,
+    // Reports rolled into this row (see rollup); `timestamp` is the first, `last_seen` the latest.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub occurrences: Option<i32>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub last_seen: Option<i64>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow, utoipa::ToSchema)]
 pub struct Task {
     pub id: String,
     pub filename: String,
@@ -388,7 +527,183 @@ pub struct Task {
     pub verdict_manual: Option<bool>,
     pub sandbox_id: Option<String>,
     pub remnux_status: Option<String>,
+    #[schema(value_type = Option<Object>)]
     pub remnux_report: Option<serde_json::Value>,
+    /// Set on re-detonations: the task this one was re-run from.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub parent_task_id: Option<String>,
+    /// Queue priority, for tasks that went through the scheduler.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub priority: Option<String>,
+    /// full / fake-net / blocked / vpn, when the task ran under an internet policy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub internet_policy: Option<String>,
+    /// Archive the sample was unpacked from: archive name/hash/format and the member chain.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    #[schema(value_type = Option<Object>)]
+    pub container: Option<serde_json::Value>,
+    /// Only while queued: 1-based position and estimated start (unix millis).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(skip)]
+    pub queue_position: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(skip)]
+    pub estimated_start: Option<i64>,
+}
+
+// Agent events are written in batches of up to this many rows per session...
+const EVENT_BATCH_SIZE: usize = 100;
+// ...or whatever has accumulated after this long, so a quiet agent still shows up live.
+const EVENT_FLUSH_INTERVAL: Duration = Duration::from_millis(200);
+
+
+// Polls for the agent's reply events to this task's prep commands; false if they don't all arrive in time
+async fn await_agent_replies(pool: &Pool<Postgres>, task_id: &str, event_types: &[&str], timeout: Duration) -> bool {
+    let started = std::time::Instant::now();
+    while started.elapsed() < timeout {
+        let seen: i64 = sqlx::query_scalar("SELECT COUNT(DISTINCT event_type) FROM events WHERE task_id = $1 AND event_type = ANY($2)")
+            .bind(task_id)
+            .bind(event_types)
+            .fetch_one(pool)
+            .await
+            .unwrap_or(0);
+        if seen as usize >= event_types.len() {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    false
+}
+
+// One UNNEST insert per batch, then broadcast, SIEM and the ingest-time detectors, in agent order
+async fn flush_agent_events(
+    pool: &Pool<Postgres>,
+    broadcaster: &stream::Broadcaster,
+    session_id: &str,
+    pending: &mut Vec<RawAgentEvent>,
+) {
+    if pending.is_empty() {
+        return;
+    }
+    let mut batch = std::mem::take(pending);
+    let repeats = rollup::fold(session_id, &mut batch);
+    if !repeats.is_empty() {
+        metrics::inc("voodoobox_events_rolled_up_total", &[], repeats.iter().map(|r| r.count as f64).sum());
+        if let Err(e) = rollup::record(pool, &repeats).await {
+            println!("[DATABASE] Error rolling up {} repeated events from {}: {}", repeats.len(), session_id, e);
+        }
+    }
+    if batch.is_empty() {
+        return;
+    }
+    for evt in batch.iter_mut() {
+        if evt.decoded_details.is_none() {
+            evt.decoded_details = decoder::decode(&evt.details);
+            evt.decoder_version = Some(decoder::DECODER_VERSION);
+        }
+        evt.obfuscation_score = obfuscation::score(&evt.event_type, &evt.details, evt.decoded_details.as_deref());
+        evt.kill_chain_stage = kill_chain::classify(&evt.event_type, &evt.process_name, &evt.details, evt.decoded_details.as_deref(), evt.category.as_deref())
+            .map(str::to_string);
+    }
+
+    let insert_started = std::time::Instant::now();
+    // Ids come from one sequence in ORDER BY ord order, so sorted ids line up with the batch
+    let db_res: Result<Vec<i32>, sqlx::Error> = sqlx::query_scalar(
+        "INSERT INTO events (event_type, process_id, parent_process_id, process_name, details, decoded_details, timestamp, task_id, session_id, digital_signature, severity, category, kill_chain_stage, decoder_version, obfuscation_score, occurrences, last_seen)
+         SELECT event_type, process_id, parent_process_id, process_name, details, decoded_details, timestamp, task_id, $9, digital_signature, severity, category, kill_chain_stage, decoder_version, obfuscation_score, COALESCE(occurrences, 1), last_seen
+         FROM UNNEST($1::text[], $2::int[], $3::int[], $4::text[], $5::text[], $6::text[], $7::bigint[], $8::text[], $10::text[], $11::int[], $12::text[], $13::text[], $14::int[], $15::int[], $16::int[], $17::bigint[])
+             WITH ORDINALITY AS b(event_type, process_id, parent_process_id, process_name, details, decoded_details, timestamp, task_id, digital_signature, severity, category, kill_chain_stage, decoder_version, obfuscation_score, occurrences, last_seen, ord)
+         ORDER BY ord
+         RETURNING id"
+    )
+    .bind(batch.iter().map(|e| e.event_type.clone()).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.process_id).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.parent_process_id).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.process_name.clone()).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.details.clone()).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.decoded_details.clone()).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.timestamp).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.task_id.clone()).collect::<Vec<_>>())
+    .bind(session_id)
+    .bind(batch.iter().map(|e| e.digital_signature.clone()).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.severity).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.category.clone()).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.kill_chain_stage.clone()).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.decoder_version).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.obfuscation_score).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.occurrences).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.last_seen).collect::<Vec<_>>())
+    .fetch_all(pool)
+    .await;
+    metrics::observe("voodoobox_db_insert_seconds", &[("table", "events")], insert_started.elapsed().as_secs_f64());
+
+    match db_res {
+        Ok(mut ids) => {
+            metrics::inc("voodoobox_events_ingested_total", &[("source", "agent")], ids.len() as f64);
+            ids.sort_unstable();
+            for (evt, id) in batch.iter_mut().zip(ids) {
+                evt.id = Some(id);
+            }
+            rollup::remember(session_id, &batch);
+        }
+        // Broadcast without ids if the DB fails (unlikely, but preserves liveness)
+        Err(e) => println!("[DATABASE] Error inserting {} events from {}: {}", batch.len(), session_id, e),
+    }
+
+    for evt in &batch {
+        if let Ok(json) = serde_json::to_string(evt) {
+            broadcaster.publish(evt.task_id.as_deref(), &evt.event_type, evt.severity.unwrap_or(0), json);
+        }
+        let is_control = evt.event_type == "SESSION_INIT" || evt.event_type.starts_with("AGENT_");
+        if !is_control {
+            siem::forward(siem::Record {
+                task_id: evt.task_id.clone(),
+                event_id: evt.id,
+                pid: Some(evt.process_id),
+                ppid: Some(evt.parent_process_id),
+                process_name: Some(evt.process_name.clone()),
+                severity: evt.severity.unwrap_or(0),
+                ..siem::Record::event(&evt.event_type, &evt.details, evt.timestamp)
+            });
+        }
+    }
+
+    let refs: Vec<sigma::EventRef> = batch.iter().map(|evt| sigma::EventRef {
+        id: evt.id,
+        task_id: evt.task_id.as_deref(),
+        event_type: &evt.event_type,
+        process_id: evt.process_id,
+        parent_process_id: evt.parent_process_id,
+        process_name: &evt.process_name,
+        details: &evt.details,
+        decoded_details: evt.decoded_details.as_deref(),
+        category: evt.category.as_deref(),
+        timestamp: evt.timestamp,
+    }).collect();
+    sigma::evaluate(pool, Some(broadcaster), &refs).await;
+    dns_reputation::evaluate(pool, Some(broadcaster), &refs).await;
+    ransomware::evaluate(pool, Some(broadcaster), &refs).await;
+    lineage::evaluate(pool, Some(broadcaster), &refs).await;
+}
+
+// First line of a current agent: a UUID it generated at startup, and its computer name.
+#[derive(Deserialize)]
+struct AgentHandshake {
+    #[serde(rename = "type")]
+    kind: String,
+    session_id: String,
+    hostname: Option<String>,
+}
+
+fn parse_handshake(line: &str) -> Option<AgentHandshake> {
+    let handshake: AgentHandshake = serde_json::from_str(line).ok()?;
+    // Only a well-formed UUID may become a session key
+    let valid = handshake.kind == "HANDSHAKE" && uuid::Uuid::parse_str(&handshake.session_id).is_ok();
+    valid.then_some(handshake)
 }
 
 async fn start_tcp_listener(
@@ -404,39 +719,60 @@ async fn start_tcp_listener(
         let broadcaster = broadcaster.clone();
         let manager = manager.clone();
         let pool = pool.clone();
-        let session_id = addr.to_string();
+        // Provisional key until the agent's handshake names its session
+        let mut session_id = addr.to_string();
         
         tokio::spawn(async move {
             let (rx_socket, mut tx_socket) = tokio::io::split(socket);
             let (tx_cmd, mut rx_cmd) = mpsc::unbounded_channel::<String>();
             
-            manager.register(session_id.clone(), tx_cmd).await;
+            manager.register(session_id.clone(), tx_cmd.clone()).await;
             println!("Agent connected: {}", session_id);
 
-            let mut reader = BufReader::new(rx_socket);
-            let mut line = String::new();
-            
+            // next_line is cancel-safe, unlike read_line, so a command or flush tick never drops a partial line
+            let mut lines = BufReader::new(rx_socket).lines();
+            let mut pending: Vec<RawAgentEvent> = Vec::with_capacity(EVENT_BATCH_SIZE);
+            let mut flush_tick = tokio::time::interval(EVENT_FLUSH_INTERVAL);
+            flush_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
             loop {
                 tokio::select! {
-                    res = reader.read_line(&mut line) => {
+                    res = lines.next_line() => {
                         match res {
-                            Ok(0) => break, 
-                            Ok(_) => {
+                            Ok(Some(line)) => {
                                 let trimmed = line.trim();
+                                if let Some(handshake) = parse_handshake(trimmed) {
+                                    manager.adopt_identity(&session_id, &handshake.session_id, handshake.hostname).await;
+                                    println!("Agent {} identified as session {}", session_id, handshake.session_id);
+                                    session_id = handshake.session_id;
+                                    continue;
+                                }
                                 if let Ok(mut evt) = serde_json::from_str::<RawAgentEvent>(trimmed) {
-                                    let p_name = evt.process_name.to_lowercase();
-                                    let is_registry = evt.event_type.starts_with("REG_");
-
-                                    if !is_registry && NOISE_PROCESSES.iter().any(|&n| p_name.contains(n)) {
-                                        line.clear();
-                                        continue;
+                                    if evt.event_type == "SESSION_INIT" {
+                                        // Record the guest identity and hand it the filter set for its gold image
+                                        let hostname = serde_json::from_str::<serde_json::Value>(trimmed).ok()
+                                            .and_then(|v| v["hostname"].as_str().map(|h| h.to_string()));
+                                        if let Some(session) = manager.sessions.lock().await.get_mut(&session_id) {
+                                            session.hostname = hostname.clone();
+                                        }
+                                        match noise_filters::build_agent_command(&pool, hostname.as_deref()).await {
+                                            Ok(cmd) => manager.send_command_to_session(&session_id, &cmd).await,
+                                            Err(e) => println!("[NOISE] Failed to push filters to {}: {}", session_id, e),
+                                        }
+                                        match severity_rules::build_agent_command(&pool).await {
+                                            Ok(cmd) => manager.send_command_to_session(&session_id, &cmd).await,
+                                            Err(e) => println!("[SEVERITY] Failed to push rules to {}: {}", session_id, e),
+                                        }
                                     }
 
-                                // Get the current active task for THIS session
-                                let current_task_id = {
+                                // Get the current active task and gold image for THIS session
+                                let (current_task_id, image) = {
                                     let sessions = manager.sessions.lock().await;
-                                    sessions.get(&session_id).and_then(|s| s.active_task_id.clone())
+                                    sessions.get(&session_id).map(|s| (s.active_task_id.clone(), s.hostname.clone())).unwrap_or_default()
                                 };
+                                if noise_filters::is_noise(image.as_deref(), &evt.event_type, &evt.process_name, evt.digital_signature.as_deref()) {
+                                    continue;
+                                }
                                 evt.task_id = current_task_id.clone();
 
                                     if let Some(ref tid) = evt.task_id {
@@ -445,47 +781,18 @@ async fn start_tcp_listener(
                                         println!("[TELEMETRY] Captured global event (No Task ID): {} ({})", evt.event_type, evt.process_name);
                                     }
 
-                                    let db_res = sqlx::query(
-                                        "INSERT INTO events (event_type, process_id, parent_process_id, process_name, details, decoded_details, timestamp, task_id, session_id, digital_signature) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING id"
-                                    )
-                                    .bind(&evt.event_type)
-                                    .bind(&evt.process_id)
-                                    .bind(&evt.parent_process_id)
-                                    .bind(&evt.process_name)
-                                    .bind(&evt.details)
-                                    .bind(&evt.decoded_details)
-                                    .bind(&evt.timestamp)
-                                    .bind(&evt.task_id)
-                                    .bind(&session_id)
-                                    .bind(&evt.digital_signature)
-                                    .fetch_one(&pool)
-                                    .await;
-
-                                    match db_res {
-                                        Ok(row) => {
-                                            // 2. Update event with generated ID
-                                            let generated_id: i32 = row.get("id");
-                                            evt.id = Some(generated_id);
-
-                                            // 3. Broadcast enriched event WITH ID
-                                            if let Ok(json) = serde_json::to_string(&evt) {
-                                                broadcaster.send_message(&json);
-                                            }
-                                        },
-                                        Err(e) => {
-                                            println!("[DATABASE] Error inserting event: {}", e);
-                                            // Fallback: Broadcast without ID if DB fails (unlikely, but preserves liveness)
-                                            if let Ok(json) = serde_json::to_string(&evt) {
-                                                broadcaster.send_message(&json);
-                                            }
-                                        }
+                                    pending.push(evt);
+                                    if pending.len() >= EVENT_BATCH_SIZE {
+                                        flush_agent_events(&pool, &broadcaster, &session_id, &mut pending).await;
                                     }
                                 }
-                                line.clear();
                             }
-                            Err(_) => break,
+                            Ok(None) | Err(_) => break,
                         }
                     }
+                    _ = flush_tick.tick(), if !pending.is_empty() => {
+                        flush_agent_events(&pool, &broadcaster, &session_id, &mut pending).await;
+                    }
                     Some(cmd) = rx_cmd.recv() => {
                         if let Err(_) = tx_socket.write_all(format!("{}\n", cmd).as_bytes()).await {
                             break;
@@ -493,7 +800,8 @@ async fn start_tcp_listener(
                     }
                 }
             }
-            manager.remove(&session_id).await;
+            flush_agent_events(&pool, &broadcaster, &session_id, &mut pending).await;
+            manager.remove(&session_id, &tx_cmd).await;
             println!("Agent disconnected: {}", session_id);
         });
     }
@@ -512,9 +820,18 @@ struct ExecRequest {
     node: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct DefensesRequest {
+    defender: Option<bool>,
+    firewall: Option<bool>,
+    session_id: Option<String>,
+}
+
 #[derive(Deserialize)]
 pub struct PivotRequest {
     pub path: String,
+    // Ask only the agent running this task; every agent otherwise.
+    pub task_id: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -523,8 +840,15 @@ struct UrlRequest {
     analysis_duration: Option<u64>,
     vmid: Option<u64>,
     node: Option<String>,
+    // urgent | normal | bulk
+    priority: Option<String>,
+    // Analysis profile name; explicit fields above override it.
+    profile: Option<String>,
+    // full | fake-net | blocked | vpn
+    internet_policy: Option<String>,
 }
 
+#[utoipa::path(tag = "vms", responses((status = 200, description = "Success")))]
 #[post("/vms/actions/terminate")]
 async fn terminate_process(
     manager: web::Data<Arc<AgentManager>>,
@@ -539,7 +863,8 @@ async fn terminate_process(
     HttpResponse::Ok().json(serde_json::json!({ "status": "sent", "pid": req.pid }))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 struct TaskQuery {
     task_id: Option<String>,
     search: Option<String>,
@@ -549,22 +874,38 @@ use actix_multipart::Multipart;
 use futures::TryStreamExt;
 use std::time::Duration;
 
+#[utoipa::path(
+    tag = "submission",
+    request_body(content = openapi::SubmitForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Task created and queued, or an earlier analysis reused"),
+        (status = 400, description = "No file, or an invalid setting or archive"),
+        (status = 413, description = "Upload over MAX_SAMPLE_UPLOAD_MB"),
+    ),
+)]
 #[post("/vms/actions/submit")]
 async fn submit_sample(
-    ai_manager: web::Data<AIManager>,
-    manager: web::Data<Arc<AgentManager>>,
-    client: web::Data<proxmox::ProxmoxClient>,
+    req: HttpRequest,
     pool: web::Data<Pool<Postgres>>,
-    progress_broadcaster: web::Data<Arc<progress_stream::ProgressBroadcaster>>,
+    scheduler: web::Data<Arc<task_queue::TaskScheduler>>,
     mut payload: Multipart,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let mut limit = limits::UploadLimit::sample();
+    if let Err(resp) = limit.check_declared(&req) {
+        return Ok(resp);
+    }
     let mut filename = String::new();
     let mut original_filename = String::new();
     let mut sha256_hash = String::new();
-    let mut analysis_duration_seconds = 300; // Default 5 minutes
-    let mut target_vmid: Option<u64> = None;
-    let mut target_node: Option<String> = None;
-    let mut analysis_mode = "quick".to_string(); // Default to quick
+    // Unset fields fall back to the analysis profile, then to 5 minutes / quick / normal
+    let mut settings = analysis_profiles::SubmissionSettings::default();
+    let mut profile_name: Option<String> = None;
+    let mut compare_profiles: Vec<String> = Vec::new();
+    let mut reuse = false;
+    let mut force = false;
+    let mut archive_password = archive::DEFAULT_PASSWORD.to_string();
+    let mut archive_member: Option<String> = None;
+    let mut extract_archives = true;
     
     // Iterate over multipart stream
     while let Ok(Some(mut field)) = TryStreamExt::try_next(&mut payload).await {
@@ -588,6 +929,11 @@ async fn submit_sample(
             let mut hasher = Sha256::new();
 
             while let Ok(Some(chunk)) = TryStreamExt::try_next(&mut field).await {
+                if let Err(resp) = limit.add(chunk.len()) {
+                    drop(f);
+                    let _ = tokio::fs::remove_file(&filepath).await;
+                    return Ok(resp);
+                }
                 f.write_all(&chunk).await
                     .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
                 hasher.update(&chunk);
@@ -609,8 +955,8 @@ async fn submit_sample(
             }
             if let Ok(value_str) = String::from_utf8(value_bytes) {
                  if let Ok(minutes) = value_str.trim().parse::<u64>() {
-                     analysis_duration_seconds = minutes * 60;
-                     println!("[SUBMISSION] Setting analysis duration to {} seconds ({} minutes)", analysis_duration_seconds, minutes);
+                     settings.duration_seconds = Some(minutes * 60);
+                     println!("[SUBMISSION] Setting analysis duration to {} seconds ({} minutes)", minutes * 60, minutes);
                  }
             }
         } else if field_name == "vmid" {
@@ -622,7 +968,7 @@ async fn submit_sample(
                 let trimmed = value_str.trim();
                 println!("[SUBMISSION] Received vmid field: '{}'", trimmed);
                 if let Ok(vmid) = trimmed.parse::<u64>() {
-                    target_vmid = Some(vmid);
+                    settings.vmid = Some(vmid);
                 }
             }
         } else if field_name == "node" {
@@ -632,7 +978,21 @@ async fn submit_sample(
             }
             if let Ok(value_str) = String::from_utf8(value_bytes) {
                 let node = value_str.trim().to_string();
-                target_node = Some(node);
+                settings.node = Some(node);
+            }
+        } else if field_name == "compare_profiles" {
+            let mut value_bytes = Vec::new();
+            while let Ok(Some(chunk)) = TryStreamExt::try_next(&mut field).await {
+                value_bytes.extend_from_slice(&chunk);
+            }
+            if let Ok(value_str) = String::from_utf8(value_bytes) {
+                compare_profiles = value_str.split(',')
+                    .map(|p| p.trim().to_string())
+                    .filter(|p| !p.is_empty())
+                    .collect();
+                compare_profiles.sort();
+                compare_profiles.dedup();
+                println!("[SUBMISSION] Comparative detonation across profiles: {:?}", compare_profiles);
             }
         } else if field_name == "analysis_mode" {
             let mut value_bytes = Vec::new();
@@ -641,20 +1001,161 @@ async fn submit_sample(
             }
             if let Ok(value_str) = String::from_utf8(value_bytes) {
                 let mode = value_str.trim().to_lowercase();
-                if mode == "deep" {
-                    analysis_mode = "deep".to_string();
+                if mode == "deep" || mode == "quick" || mode == static_only::MODE {
+                    settings.analysis_mode = Some(mode.clone());
                 }
                 println!("[SUBMISSION] Received analysis_mode field: '{}'", mode);
             }
+        } else if field_name == "priority" {
+            let mut value_bytes = Vec::new();
+            while let Ok(Some(chunk)) = TryStreamExt::try_next(&mut field).await {
+                value_bytes.extend_from_slice(&chunk);
+            }
+            let value_str = String::from_utf8_lossy(&value_bytes);
+            match task_queue::parse_priority(&value_str) {
+                Some(p) => settings.priority = Some(p),
+                None => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": "priority must be urgent, normal or bulk" }))),
+            }
+        } else if field_name == "profile" {
+            let mut value_bytes = Vec::new();
+            while let Ok(Some(chunk)) = TryStreamExt::try_next(&mut field).await {
+                value_bytes.extend_from_slice(&chunk);
+            }
+            profile_name = Some(String::from_utf8_lossy(&value_bytes).trim().to_string());
+        } else if field_name == "internet_policy" {
+            let mut value_bytes = Vec::new();
+            while let Ok(Some(chunk)) = TryStreamExt::try_next(&mut field).await {
+                value_bytes.extend_from_slice(&chunk);
+            }
+            match network_policy::parse_policy(&String::from_utf8_lossy(&value_bytes)) {
+                Some(p) => settings.internet_policy = Some(p),
+                None => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": "internet_policy must be full, fake-net, blocked or vpn" }))),
+            }
+        } else if field_name == "reuse" {
+            let mut value_bytes = Vec::new();
+            while let Ok(Some(chunk)) = TryStreamExt::try_next(&mut field).await {
+                value_bytes.extend_from_slice(&chunk);
+            }
+            reuse = dedup::flag(&String::from_utf8_lossy(&value_bytes));
+        } else if field_name == "force" {
+            let mut value_bytes = Vec::new();
+            while let Ok(Some(chunk)) = TryStreamExt::try_next(&mut field).await {
+                value_bytes.extend_from_slice(&chunk);
+            }
+            force = dedup::flag(&String::from_utf8_lossy(&value_bytes));
+        } else if field_name == "archive_password" || field_name == "archive_member" || field_name == "extract_archives" {
+            let key = field_name.to_string();
+            let mut value_bytes = Vec::new();
+            while let Ok(Some(chunk)) = TryStreamExt::try_next(&mut field).await {
+                value_bytes.extend_from_slice(&chunk);
+            }
+            let value = String::from_utf8_lossy(&value_bytes).trim().to_string();
+            match key.as_str() {
+                "archive_password" => archive_password = value,
+                "archive_member" => archive_member = Some(value).filter(|v| !v.is_empty()),
+                _ => extract_archives = value != "false" && value != "0",
+            }
         }
     }
     
-    println!("[SUBMISSION] Final selection - VMID: {:?}, Node: {:?}", target_vmid, target_node);
+    let upload = SampleUpload { filename, original_filename, sha256: sha256_hash };
+    let options = SubmitOptions { settings, profile_name, compare_profiles, reuse, force, archive_password, archive_member, extract_archives };
+    Ok(finish_submission(pool.get_ref(), scheduler.get_ref(), upload, options).await)
+}
+
+// A sample written to ./uploads, whichever way it arrived.
+pub struct SampleUpload {
+    pub filename: String,
+    pub original_filename: String,
+    pub sha256: String,
+}
+
+// Everything a submission may set besides the file itself.
+pub struct SubmitOptions {
+    pub settings: analysis_profiles::SubmissionSettings,
+    pub profile_name: Option<String>,
+    pub compare_profiles: Vec<String>,
+    pub reuse: bool,
+    pub force: bool,
+    pub archive_password: String,
+    pub archive_member: Option<String>,
+    pub extract_archives: bool,
+}
+
+// Uploaded sample -> task: unpacking, dedup, static analysis and queueing
+pub async fn finish_submission(
+    pool: &Pool<Postgres>,
+    scheduler: &task_queue::TaskScheduler,
+    upload: SampleUpload,
+    options: SubmitOptions,
+) -> HttpResponse {
+    let SampleUpload { mut filename, mut original_filename, sha256: mut sha256_hash } = upload;
+    let SubmitOptions { settings, profile_name, compare_profiles, reuse, force, archive_password, archive_member, extract_archives } = options;
+    let profile = match analysis_profiles::resolve(pool, profile_name.as_deref()).await {
+        Ok(p) => p,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+    let settings = settings.or_profile(profile.as_ref());
+    let analysis_duration_seconds = settings.duration_seconds.unwrap_or(300);
+    let analysis_mode = settings.analysis_mode.unwrap_or_else(|| "quick".to_string());
+    let priority = settings.priority.unwrap_or(task_queue::DEFAULT_PRIORITY);
+    let (target_vmid, target_node) = (settings.vmid, settings.node);
+
+    println!("[SUBMISSION] Final selection - VMID: {:?}, Node: {:?}, Profile: {:?}", target_vmid, target_node, profile.as_ref().map(|p| &p.name));
     
     if filename.is_empty() {
-        return Ok(HttpResponse::BadRequest().body("No file uploaded"));
+        return HttpResponse::BadRequest().body("No file uploaded");
+    }
+
+    // Packed submission: detonate the payload inside it, keeping the archive as the container
+    let mut container: Option<serde_json::Value> = None;
+    let upload_path = std::path::PathBuf::from("./uploads").join(&filename);
+    if let Some(kind) = archive::detect(&upload_path).filter(|_| extract_archives) {
+        println!("[SUBMISSION] {} is a {} archive; unpacking.", filename, kind.label());
+        match archive::unpack_submission(upload_path, original_filename.clone(), sha256_hash.clone(), kind, archive_password, archive_member).await {
+            Ok(archive::Selection::Picked(picked)) => {
+                println!("[SUBMISSION] Selected {} from {}.", picked.container["member"], filename);
+                filename = picked.filename;
+                original_filename = picked.original_filename;
+                sha256_hash = picked.sha256;
+                container = Some(picked.container);
+
+                let vt_pool = pool.clone();
+                let vt_hash = sha256_hash.clone();
+                actix_web::rt::spawn(async move {
+                    let _ = virustotal::get_cached_or_fetch(&vt_pool, &vt_hash).await;
+                });
+            }
+            // Nothing stands out: let the submitter pick and resubmit with archive_member
+            Ok(archive::Selection::Choose(members)) => {
+                return HttpResponse::Ok().json(serde_json::json!({
+                    "status": "archive_selection_required",
+                    "filename": filename,
+                    "members": members,
+                    "message": "Archive holds several candidates; resubmit with archive_member set to the one to detonate"
+                }));
+            }
+            Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("Could not unpack {}: {}", filename, e) })),
+        }
     }
     
+    let email_format = email::detect(&std::path::PathBuf::from("./uploads").join(&filename));
+
+    // Same bytes already analysed: hand back that report when asked to, instead of detonating again
+    let previous = dedup::latest_completed(pool, &sha256_hash).await;
+    if let Some(prev) = previous.as_ref().filter(|_| reuse && !force && compare_profiles.is_empty()) {
+        println!("[SUBMISSION] {} matches completed task {}; reusing its report.", filename, prev.task_id);
+        return HttpResponse::Ok().json(serde_json::json!({
+            "status": "cached",
+            "task_id": prev.task_id,
+            "verdict": prev.verdict,
+            "risk_score": prev.risk_score,
+            "completed_at": prev.completed_at,
+            "filename": filename,
+            "message": "Identical sample already analysed; returning the existing report (submit with force=true to re-run)"
+        }));
+    }
+
     let host_ip = std::env::var("HOST_IP").unwrap_or_else(|_| "192.168.50.11".to_string()); // Default to local host
     let download_url = format!("http://{}:8080/uploads/{}", host_ip, filename);
     
@@ -664,6 +1165,7 @@ async fn submit_sample(
     let task_id = created_at.to_string();
     
     let filepath = format!("{}/{}", "./uploads", filename);
+    storage::persist(&format!("uploads/{}", filename)).await;
     
     let _ = sqlx::query(
         "INSERT INTO tasks (id, filename, original_filename, file_hash, status, created_at, sandbox_id, file_path) VALUES ($1, $2, $3, $4, 'Queued', $5, $6, $7)"
@@ -675,63 +1177,125 @@ async fn submit_sample(
     .bind(created_at)
     .bind(target_vmid.map(|id| id.to_string()))
     .bind(&filepath)
-    .execute(pool.get_ref())
+    .execute(pool)
     .await;
     
     // Check if task exists (debugging)
     let check = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM tasks WHERE id = $1")
         .bind(&task_id)
-        .fetch_one(pool.get_ref())
+        .fetch_one(pool)
         .await
         .unwrap_or(0);
         
     println!("[DEBUG] Task {} created. DB Row Count: {}", task_id, check);
+    network_policy::record(pool, &task_id, settings.internet_policy).await;
+    if let Some(container) = &container {
+        archive::record(pool, &task_id, container).await;
+    }
+    relationships::link_url_payloads(pool, &task_id, &sha256_hash).await;
 
     println!("Sample uploaded: {}. Initiating Sandbox Orchestration (Task: {})...", filename, task_id);
     
-    // Trigger Ghidra Static Analysis (Parallel Background)
-    let ghidra_filename = filename.clone();
-    let ghidra_task_id = task_id.clone();
-    let ghidra_pool = pool.get_ref().clone(); 
-    actix_web::rt::spawn(async move {
-        trigger_ghidra_background(ghidra_filename, ghidra_task_id, ghidra_pool).await;
-    });
+    // An email itself is only parsed; its attachments get the static analysis instead
+    if email_format.is_none() {
+        // Trigger Ghidra Static Analysis (Parallel Background)
+        let ghidra_filename = filename.clone();
+        let ghidra_task_id = task_id.clone();
+        let ghidra_pool = pool.clone(); 
+        actix_web::rt::spawn(async move {
+            trigger_ghidra_background(ghidra_filename, ghidra_task_id, ghidra_pool).await;
+        });
 
-    // Trigger Remnux Analysis (Parallel Background)
-    let remnux_filename = filename.clone();
-    let remnux_task_id = task_id.clone();
-    let remnux_pool = pool.get_ref().clone();
-    let remnux_filepath = format!("./uploads/{}", filename);
-    actix_web::rt::spawn(async move {
-        remnux::trigger_scan(remnux_pool, remnux_task_id, remnux_filename, remnux_filepath).await;
-    });
+        // Trigger Remnux Analysis (Parallel Background)
+        let remnux_filename = filename.clone();
+        let remnux_task_id = task_id.clone();
+        let remnux_pool = pool.clone();
+        let remnux_filepath = format!("./uploads/{}", filename);
+        actix_web::rt::spawn(async move {
+            remnux::trigger_scan(remnux_pool, remnux_task_id, remnux_filename, remnux_filepath).await;
+        });
 
-    // Spawn Analysis Job
-    let manager = manager.get_ref().clone(); 
-    let client = client.get_ref().clone();
-    let pool = pool.get_ref().clone();
-    let ai_manager = ai_manager.get_ref().clone();
-    let url_clone = download_url.clone();
-    let task_id_clone = task_id.clone();
-    let mode_clone = analysis_mode.clone();
-    let progress_bc: Arc<progress_stream::ProgressBroadcaster> = progress_broadcaster.get_ref().clone();
-    
-    actix_web::rt::spawn(async move {
-        orchestrate_sandbox(client, manager, pool, ai_manager, task_id_clone, url_clone, original_filename.clone(), analysis_duration_seconds, target_vmid, target_node, false, mode_clone, progress_bc).await;
-    });
+        yara::queue_task(pool, &task_id);
+        static_properties::queue(pool, &task_id);
+    }
+
+    let job = task_queue::QueuedAnalysis {
+        task_id: task_id.clone(),
+        target_url: download_url,
+        original_filename,
+        duration_seconds: analysis_duration_seconds,
+        vmid: target_vmid,
+        node: target_node,
+        is_url_task: false,
+        analysis_mode: analysis_mode.clone(),
+        os_profile: settings.os_profile,
+        priority: priority.to_string(),
+        profile: profile.map(|p| p.name),
+    };
+
+    // Emails are not detonated: attachments and notable links become child tasks instead
+    if let Some(format) = email_format {
+        let child = email::ChildSettings { base: &job, host_ip: &host_ip, static_analysis: true };
+        return match email::fan_out(pool, scheduler, &task_id, std::path::Path::new(&filepath), format, child).await {
+            Ok(summary) => HttpResponse::Ok().json(summary),
+            Err(e) => {
+                let _ = sqlx::query("UPDATE tasks SET status='Failed (Email Parse)' WHERE id=$1")
+                    .bind(&task_id).execute(pool).await;
+                HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("Could not parse email: {}", e), "task_id": task_id }))
+            }
+        };
+    }
+
+    // Static-only: no sandbox, the report is built from the static tools' output
+    if analysis_mode == static_only::MODE {
+        scheduler.run_static(task_id.clone());
+        return HttpResponse::Ok().json(serde_json::json!({
+            "status": "static_analysis",
+            "task_id": task_id,
+            "filename": filename,
+            "mode": analysis_mode,
+            "message": "Static-only analysis started: VirusTotal, Ghidra and REMnux, then the report (no detonation)"
+        }));
+    }
+
+    // Comparative run: one child task per OS profile instead of a single detonation
+    if !compare_profiles.is_empty() {
+        return match comparison::fan_out(pool, scheduler, &task_id, &job, &compare_profiles).await {
+            Ok(children) => HttpResponse::Ok().json(serde_json::json!({
+                "status": "comparison_queued",
+                "task_id": task_id,
+                "child_task_ids": children,
+                "profiles": compare_profiles,
+                "filename": filename,
+                "mode": analysis_mode,
+                "url": format!("/tasks/{}/sample", task_id),
+                "message": "Queued one detonation per OS profile"
+            })),
+            Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+        };
+    }
+
+    // Queue Analysis Job
+    if let Err(e) = scheduler.enqueue(job).await {
+        println!("[QUEUE] Failed to queue task {}: {}", task_id, e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() }));
+    }
     
-    Ok(HttpResponse::Ok().json(serde_json::json!({
+    HttpResponse::Ok().json(serde_json::json!({
         "status": "analysis_queued",
         "task_id": task_id,
         "filename": filename,
         "mode": analysis_mode,
-        "url": download_url,
-        "message": "Orchestration started: Reverting VM -> Starting -> Detonating"
-    })))
+        "priority": priority,
+        "previous_task_id": previous.map(|p| p.task_id),
+        "container": container,
+        "url": format!("/tasks/{}/sample", task_id),
+        "message": "Queued: Waiting for sandbox -> Reverting VM -> Starting -> Detonating"
+    }))
 }
 
 pub async fn orchestrate_sandbox(
-    client: proxmox::ProxmoxClient,
+    client: Arc<dyn Hypervisor>,
     manager: Arc<AgentManager>,
     pool: Pool<Postgres>,
     ai_manager: AIManager,
@@ -739,65 +1303,28 @@ pub async fn orchestrate_sandbox(
     target_url: String, // Can be download URL or Detonation URL
     original_filename: String,
     duration_seconds: u64,
-    manual_vmid: Option<u64>,
-    manual_node: Option<String>,
+    vm: task_queue::AssignedVm,
     is_url_task: bool,
     analysis_mode: String,
+    profile: Option<analysis_profiles::AnalysisProfile>,
     progress: Arc<progress_stream::ProgressBroadcaster>,
 ) {
 
-    // 1. Identify Sandbox VM
-    let mut node_name = String::new();
-    let mut vmid = 0;
-    let mut vm_name = String::new();
-    let snapshot = "clean_sand";
-
-
-
-    if let (Some(mvmid), Some(mnode)) = (manual_vmid, manual_node) {
-        println!("[ORCHESTRATOR] Using MANUALLY selected VM: {} on node {}", mvmid, mnode);
-        vmid = mvmid;
-        node_name = mnode;
-        vm_name = format!("vm{}", vmid); // Fallback name
-    } else {
-        println!("[ORCHESTRATOR] Searching for available Sandbox VM (Pattern: 'sand/sandbox' or ID 300-399)...");
-        // Try to discover an available sandbox VM
-        if let Ok(nodes) = client.get_nodes().await {
-            'discovery: for node in nodes {
-                if let Ok(vms) = client.get_vms(&node.node).await {
-                    for vm in vms {
-                        let is_sandbox_range = vm.vmid >= 300 && vm.vmid < 400;
-                        let has_sandbox_name = if let Some(name) = &vm.name {
-                            let lower_name = name.to_lowercase();
-                            lower_name.contains("sand") || lower_name.contains("sandbox")
-                        } else {
-                            false
-                        };
-
-                        if is_sandbox_range || has_sandbox_name {
-                            node_name = node.node.clone();
-                            vmid = vm.vmid;
-                            vm_name = vm.name.clone().unwrap_or_else(|| format!("vm{}", vmid));
-                            println!("[ORCHESTRATOR] Auto-selected VM: {} ({}) on node {}", vmid, vm_name, node_name);
-                            break 'discovery;
-                        }
-                    }
-                }
-            }
+    // 1. Sandbox VM was picked by the queue scheduler, which guarantees nobody else holds it
+    let task_queue::AssignedVm { vmid, node: node_name, name: vm_name, snapshot, agent_timeout_secs, preflight_secs } = vm;
+    if !is_url_task {
+        // The guest downloads the sample from ./uploads; bring it back if only the bucket has it
+        if let Some(name) = target_url.rsplit('/').next() {
+            storage::ensure_local(&format!("uploads/{}", name)).await;
         }
     }
+    let snapshot = snapshot.as_str();
 
-    if vmid == 0 {
-        println!("[ORCHESTRATOR] CRITICAL ERROR: No Sandbox VM found or specified. Aborting.");
-        let _ = sqlx::query("UPDATE tasks SET status='Failed (No VM Available)' WHERE id=$1")
-            .bind(&task_id).execute(&pool).await;
-        return;
-    }
-    
     let node = &node_name;
     println!("[ORCHESTRATOR] Starting analysis for Task {} on VM {} ({})", task_id, vmid, vm_name);
 
     // Update Sandbox Identity in DB
+    task_queue::checkpoint_vm(&pool, &task_id, vmid, node).await;
     let sandbox_label = format!("{} [{}]", vm_name, vmid);
     let _ = sqlx::query("UPDATE tasks SET sandbox_id=$2 WHERE id=$1")
         .bind(&task_id)
@@ -810,47 +1337,102 @@ pub async fn orchestrate_sandbox(
         .bind(&task_id).execute(&pool).await;
     progress.send_progress(&task_id, "preparing", "Preparing sandbox environment", 5);
 
-    // 2. Revert to 'clean' snapshot
+    // 2. Revert to the VM's golden snapshot
+    if let Err(e) = sandbox_pool::ensure_snapshot(client.as_ref(), node, vmid, snapshot).await {
+        if preflight_secs.is_some() {
+            task_queue::fail_over(&pool, &progress, &task_id, vmid, &e).await;
+            return;
+        }
+        println!("[ORCHESTRATOR] CRITICAL ERROR: {}. Aborting.", e);
+        let _ = sqlx::query("UPDATE tasks SET status='Failed (Snapshot Missing)' WHERE id=$1")
+            .bind(&task_id).execute(&pool).await;
+        progress.send_progress(&task_id, "failed", &e, 100);
+        sandbox_pool::mark_broken(&pool, vmid, &e).await;
+        return;
+    }
     println!("[ORCHESTRATOR] Step 1: Reverting to '{}' snapshot...", snapshot);
     let _ = sqlx::query("UPDATE tasks SET status='Reverting Sandbox' WHERE id=$1").bind(&task_id).execute(&pool).await;
+    task_queue::checkpoint(&pool, &task_id, "reverting").await;
     progress.send_progress(&task_id, "reverting", "Reverting to clean snapshot", 10);
     if let Err(e) = client.rollback_snapshot(node, vmid, snapshot).await {
         println!("[ORCHESTRATOR] Warning: Snapshot rollback failed: {}. Attempting to Stop/Start instead.", e);
-        let _ = client.vm_action(node, vmid, "stop").await;
+        let _ = client.stop(node, vmid).await;
         tokio::time::sleep(Duration::from_secs(5)).await;
     } else {
         // Wait for rollback to process
         tokio::time::sleep(Duration::from_secs(5)).await;
     }
-    
+
+    // The revert restored the snapshot's NIC settings; re-apply the task's internet policy
+    let internet_policy = network_policy::for_task(&pool, &task_id).await;
+    if let Some(policy) = &internet_policy {
+        let _ = sqlx::query("UPDATE tasks SET status='Configuring Network' WHERE id=$1").bind(&task_id).execute(&pool).await;
+        progress.send_progress(&task_id, "configuring_network", &format!("Applying '{}' internet policy", policy), 12);
+        if let Err(e) = network_policy::enforce(client.as_ref(), node, vmid, policy).await {
+            if policy == "full" {
+                println!("[ORCHESTRATOR] Warning: could not apply 'full' internet policy: {}. Using the VM's own network.", e);
+            } else {
+                // Detonating with more network access than was asked for is not acceptable
+                println!("[ORCHESTRATOR] CRITICAL ERROR: internet policy '{}' not enforced: {}. Aborting.", policy, e);
+                let _ = sqlx::query("UPDATE tasks SET status='Failed (Network Policy)' WHERE id=$1")
+                    .bind(&task_id).execute(&pool).await;
+                progress.send_progress(&task_id, "failed", &e, 100);
+                return;
+            }
+        }
+    }
+
     // 3. Start VM
     println!("[ORCHESTRATOR] Step 2: Starting VM...");
     let _ = sqlx::query("UPDATE tasks SET status='Starting VM' WHERE id=$1").bind(&task_id).execute(&pool).await;
+    task_queue::checkpoint(&pool, &task_id, "starting_vm").await;
     progress.send_progress(&task_id, "starting_vm", "Booting sandbox VM", 15);
     
     // Environment selection or validation could happen here
     let orchestration_start = std::time::Instant::now();
 
-    if let Err(e) = client.vm_action(node, vmid, "start").await {
+    if let Err(e) = client.start(node, vmid).await {
         println!("[ORCHESTRATOR] Error starting VM: {}", e);
     }
+
+    // Pre-flight: a pool VM that will not power on is swapped out now rather than after the agent wait
+    if let Some(probe) = preflight_secs {
+        let mut running = false;
+        while !running && orchestration_start.elapsed().as_secs() < probe {
+            running = client.get_vms(node).await.ok()
+                .and_then(|vms| vms.into_iter().find(|v| v.vmid == vmid))
+                .is_some_and(|v| v.status == "running");
+            if !running {
+                tokio::time::sleep(Duration::from_secs(3)).await;
+            }
+        }
+        if !running {
+            let _ = client.stop(node, vmid).await;
+            task_queue::fail_over(&pool, &progress, &task_id, vmid, "VM did not start").await;
+            return;
+        }
+    }
     
     // 4. Wait for Agent Handshake
-    println!("[ORCHESTRATOR] Step 3: Waiting for Agent connection (max 90s)...");
+    println!("[ORCHESTRATOR] Step 3: Waiting for Agent connection (max {}s)...", agent_timeout_secs);
     let _ = sqlx::query("UPDATE tasks SET status='Waiting for Agent' WHERE id=$1").bind(&task_id).execute(&pool).await;
+    task_queue::checkpoint(&pool, &task_id, "waiting_agent").await;
     progress.send_progress(&task_id, "waiting_agent", "Waiting for agent handshake", 25);
     
     let mut bound_session_id: Option<String> = None;
     
-    while orchestration_start.elapsed().as_secs() < 90 {
+    while orchestration_start.elapsed().as_secs() < agent_timeout_secs {
         // Find a session that connected AFTER orchestration started and isn't busy
+        // With several sandboxes booting at once, prefer the agent reporting this VM's hostname
         let sessions = manager.sessions.lock().await;
-        for (id, session) in sessions.iter() {
-            if session.active_task_id.is_none() && session.connected_at >= orchestration_start {
-                bound_session_id = Some(id.clone());
-                break;
-            }
-        }
+        // Only agents that have introduced themselves; binding before the handshake would bind a key that is about to change
+        let candidates: Vec<(&String, &AgentSession)> = sessions.iter()
+            .filter(|(_, s)| s.active_task_id.is_none() && s.connected_at >= orchestration_start && s.hostname.is_some())
+            .collect();
+        bound_session_id = candidates.iter()
+            .find(|(_, s)| s.hostname.as_ref().is_some_and(|h| h.eq_ignore_ascii_case(&vm_name)))
+            .or_else(|| candidates.first())
+            .map(|(id, _)| (*id).clone());
         
         if let Some(ref sid) = bound_session_id {
             // Found our session!
@@ -862,12 +1444,18 @@ pub async fn orchestrate_sandbox(
              println!("[ORCHESTRATOR] Still waiting for agent to connect... ({}s elapsed)", orchestration_start.elapsed().as_secs());
         }
         drop(sessions);
+        if preflight_secs.is_some_and(|probe| orchestration_start.elapsed().as_secs() >= probe) {
+            let _ = client.stop(node, vmid).await;
+            task_queue::fail_over(&pool, &progress, &task_id, vmid, "agent did not connect").await;
+            return;
+        }
         tokio::time::sleep(Duration::from_secs(2)).await;
     }
     
     let session_id = match bound_session_id {
         Some(sid) => {
             manager.bind_task_to_session(sid.clone(), task_id.clone()).await;
+            task_queue::checkpoint_session(&pool, &task_id, &sid).await;
             
             // BACKFILL TELEMETRY:
             // Ensure any events that arrived from this session BEFORE the task was bound 
@@ -882,6 +1470,18 @@ pub async fn orchestrate_sandbox(
             sid
         },
         None => {
+            // Without an agent there is no telemetry, but the guest agent can still run the
+            // sample and capture the desktop, which beats failing the task outright
+            if guest_agent::enabled() {
+                let url = if is_url_task { target_url.clone() } else { sample_download::guest_url(&task_id, &target_url) };
+                match guest_agent::detonate(client.as_ref(), &pool, &progress, node, vmid, &task_id, &url, &original_filename, is_url_task, duration_seconds).await {
+                    Ok(()) => {
+                        finish_sandbox_run(client.as_ref(), &pool, &ai_manager, manager.clone(), &progress, &task_id, node, vmid, &vm_name, snapshot, &analysis_mode, volatility::requested(profile.as_ref())).await;
+                        return;
+                    }
+                    Err(e) => println!("[ORCHESTRATOR] Guest agent fallback unavailable: {}", e),
+                }
+            }
             println!("[ORCHESTRATOR] CRITICAL ERROR: No free agent connected within timeout. Aborting analysis.");
             let _ = sqlx::query("UPDATE tasks SET status='Failed (Agent Timeout)' WHERE id=$1")
                 .bind(&task_id).execute(&pool).await;
@@ -889,9 +1489,45 @@ pub async fn orchestrate_sandbox(
         }
     };
     
+    // 4b. SANDBOX PREP: Defender would quarantine most samples before they run.
+    // SANDBOX_DISABLE_DEFENDER and SANDBOX_DISABLE_FIREWALL both default off.
+    // The analysis profile's agent_config overrides both.
+    let env_flag = |name: &str, default: bool| env::var(name).map(|v| v == "true" || v == "1").unwrap_or(default);
+    let profile_flag = |key: &str| profile.as_ref().and_then(|p| p.agent_flag(key));
+    let disable_defender = profile_flag("disable_defender").unwrap_or_else(|| env_flag("SANDBOX_DISABLE_DEFENDER", false));
+    let disable_firewall = profile_flag("disable_firewall").unwrap_or_else(|| env_flag("SANDBOX_DISABLE_FIREWALL", false));
+    let mut replies = Vec::new();
+    if disable_defender || disable_firewall {
+        let prep = serde_json::json!({
+            "command": "SET_DEFENSES",
+            "defender": if disable_defender { Some(false) } else { None },
+            "firewall": if disable_firewall { Some(false) } else { None }
+        }).to_string();
+        manager.send_command_to_session(&session_id, &prep).await;
+        replies.push("AGENT_DEFENSE_STATE");
+    }
+
+    // TLS interception: trust the proxy CA and route the guest through it before anything runs
+    let mut mitm_capture = None;
+    if mitm::requested(profile.as_ref()) {
+        match mitm::agent_command() {
+            Ok(cmd) => {
+                manager.send_command_to_session(&session_id, &cmd).await;
+                replies.push("AGENT_MITM_STATE");
+                mitm_capture = Some(mitm::FlowCapture::begin());
+            }
+            Err(e) => println!("[ORCHESTRATOR] Warning: {}. Detonating without interception.", e),
+        }
+    }
+    // The agent applies these via PowerShell on a worker thread and reports back; hold the download until it has
+    if !replies.is_empty() && !await_agent_replies(&pool, &task_id, &replies, Duration::from_secs(30)).await {
+        println!("[ORCHESTRATOR] Warning: agent did not confirm sandbox prep for Task {}. Detonating anyway.", task_id);
+    }
+
     // 5. DETONATION PHASE: Send payload only to the bound session
     println!("[ORCHESTRATOR] Step 3.1: Sending detonation command to agent...");
     let _ = sqlx::query("UPDATE tasks SET status='Detonating Sample' WHERE id=$1").bind(&task_id).execute(&pool).await;
+    task_queue::checkpoint(&pool, &task_id, "detonating").await;
     progress.send_progress(&task_id, "detonating", "Executing payload in sandbox", 40);
     
     // Update Status: Running
@@ -903,7 +1539,7 @@ pub async fn orchestrate_sandbox(
     let cmd = if analysis_mode == "vsix" {
         serde_json::json!({
             "command": "INSTALL_VSIX",
-            "url": target_url,
+            "url": sample_download::guest_url(&task_id, &target_url),
             "filename": original_filename,
             "task_id": task_id
         }).to_string()
@@ -916,71 +1552,180 @@ pub async fn orchestrate_sandbox(
     } else {
         serde_json::json!({
             "command": "DOWNLOAD_EXEC",
-            "url": target_url,
+            "url": sample_download::guest_url(&task_id, &target_url),
             "filename": original_filename,
             "vm_id": vmid,
             "vm_name": vm_name
         }).to_string()
     };
     
+    // Simulated services log everything they answer; remember where this run starts
+    let fakenet_capture = (internet_policy.as_deref() == Some("fake-net")).then(fakenet::Capture::begin);
+    let ids_capture = suricata::LiveCapture::begin();
+
     // Send ONLY to the session assigned to this VM/Task
     manager.send_command_to_session(&session_id, &cmd).await;
     println!("[ORCHESTRATOR] Detonation command sent to VM {} (Session {}): {}", vm_name, session_id, cmd);
+
+    if profile.as_ref().is_some_and(|p| p.simulate_interaction) {
+        let interact = serde_json::json!({ "command": "SIMULATE_INTERACTION", "duration_seconds": duration_seconds }).to_string();
+        manager.send_command_to_session(&session_id, &interact).await;
+    }
     
     // 6. Monitor Phase
     println!("[ORCHESTRATOR] Step 4: Monitoring Analysis Phase Initiated ({}s)...", duration_seconds); 
-    tokio::time::sleep(Duration::from_secs(duration_seconds)).await;
+    let early_stop = ransomware::watch(&task_id);
+    tokio::select! {
+        _ = tokio::time::sleep(Duration::from_secs(duration_seconds)) => {}
+        _ = early_stop.notified() => {
+            println!("[ORCHESTRATOR] Ransomware detected in Task {}; ending the monitor phase early.", task_id);
+            progress.send_progress(&task_id, "running", "Ransomware detected - stopping the sandbox early", 70);
+        }
+    }
+    ransomware::release(&task_id);
+
+    // Final persistence sweep (scheduled tasks / WMI) before the guest goes away
+    manager.send_command_to_session(&session_id, &serde_json::json!({ "command": "PERSISTENCE_SWEEP" }).to_string()).await;
     
     // 7. Cleanup - STOP VM IMMEDIATELY after analysis duration
     println!("[ORCHESTRATOR] Step 5: Analysis Complete. Waiting 5s for trailing telemetry...");
+    task_queue::checkpoint(&pool, &task_id, "collecting").await;
     progress.send_progress(&task_id, "collecting", "Collecting trailing telemetry", 75);
     tokio::time::sleep(Duration::from_secs(5)).await;
 
+    // The session key is the agent's socket address, i.e. the sandbox IP as seen on the bridge
+    let sandbox_ip = session_id.rsplit_once(':').map(|(ip, _)| ip).unwrap_or(&session_id);
+    if let Some(capture) = &fakenet_capture {
+        fakenet::collect(&pool, &task_id, capture, sandbox_ip).await;
+    }
+    if let Some(capture) = &ids_capture {
+        suricata::collect_live(&pool, &task_id, capture, sandbox_ip).await;
+    }
+    if let Some(capture) = &mitm_capture {
+        mitm::collect(&pool, &task_id, capture, sandbox_ip).await;
+    }
+
+    finish_sandbox_run(client.as_ref(), &pool, &ai_manager, manager.clone(), &progress, &task_id, node, vmid, &vm_name, snapshot, &analysis_mode, volatility::requested(profile.as_ref())).await;
+
+    // Clear active task binding for this session
+    {
+        let mut sessions = manager.sessions.lock().await;
+        if let Some(session) = sessions.get_mut(&session_id) {
+            session.active_task_id = None;
+            println!("[AGENT] Task {} cleared from session {}", task_id, session_id);
+        }
+    }
+}
+
+// Stop → revert → report → Completed; shared by the agent run and the guest agent fallback.
+#[allow(clippy::too_many_arguments)]
+async fn finish_sandbox_run(
+    client: &dyn Hypervisor,
+    pool: &Pool<Postgres>,
+    ai_manager: &AIManager,
+    manager: Arc<AgentManager>,
+    progress: &progress_stream::ProgressBroadcaster,
+    task_id: &str,
+    node: &str,
+    vmid: u64,
+    vm_name: &str,
+    snapshot: &str,
+    analysis_mode: &str,
+    memory_dump: bool,
+) {
+    // RAM has to be taken while the guest is still running
+    let memory_image = if memory_dump {
+        task_queue::checkpoint(pool, task_id, "memory_dump").await;
+        progress.send_progress(task_id, "memory_dump", "Dumping sandbox memory", 78);
+        match volatility::capture(client, node, vmid, task_id).await {
+            Ok(file_name) => Some(file_name),
+            Err(e) => {
+                println!("[ORCHESTRATOR] Warning: memory dump failed: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
     println!("[ORCHESTRATOR] Step 6: Stopping and reverting VM...");
-    progress.send_progress(&task_id, "stopping_vm", "Cleaning up sandbox", 80);
-    if let Err(e) = client.vm_action(node, vmid, "stop").await {
+    task_queue::checkpoint(pool, task_id, "stopping_vm").await;
+    progress.send_progress(task_id, "stopping_vm", "Cleaning up sandbox", 80);
+    if let Err(e) = client.stop(node, vmid).await {
         println!("[ORCHESTRATOR] Warning: Failed to stop VM {}: {}", vmid, e);
     }
     
     if let Err(e) = client.rollback_snapshot(node, vmid, snapshot).await {
         println!("[ORCHESTRATOR] CRITICAL: Failed to rollback VM {} ({}) to {}: {}", vmid, vm_name, snapshot, e);
+        // Leaving it in rotation would detonate the next sample on a dirty guest
+        sandbox_pool::mark_broken(pool, vmid, &format!("Rollback to '{}' failed: {}", snapshot, e)).await;
     } else {
         println!("[ORCHESTRATOR] SUCCESS: VM {} ({}) reverted to {} state.", vmid, vm_name, snapshot);
     }
 
+    if let Some(file_name) = &memory_image {
+        task_queue::checkpoint(pool, task_id, "memory_analysis").await;
+        progress.send_progress(task_id, "memory_analysis", "Running Volatility on the memory dump", 82);
+        volatility::analyze(pool, task_id, file_name).await;
+    }
+
+    write_final_report(pool, ai_manager, manager, progress, task_id, analysis_mode).await;
+}
 
+// AI report → Completed. Also where a run interrupted after the VM phase picks up on restart.
+pub async fn write_final_report(
+    pool: &Pool<Postgres>,
+    ai_manager: &AIManager,
+    manager: Arc<AgentManager>,
+    progress: &progress_stream::ProgressBroadcaster,
+    task_id: &str,
+    analysis_mode: &str,
+) {
+    // Post-run detectors that need the whole capture
+    beaconing::run(pool, task_id).await;
+    dns_reputation::run(pool, task_id).await;
+
+    // Deterministic score first: the AI prompt quotes it, and it is the verdict if the AI gives none
+    let deterministic = match scoring::score_task(pool, task_id).await {
+        Ok(score) => Some(score),
+        Err(e) => {
+            println!("[ORCHESTRATOR] Failed to score task {}: {}", task_id, e);
+            None
+        }
+    };
+    // Indicators into ioc_sightings, so the prompt can say where they were seen before
+    if let Err(e) = ioc_correlation::record(pool, task_id).await {
+        println!("[ORCHESTRATOR] Failed to record IOC sightings for task {}: {}", task_id, e);
+    }
 
     // 8. Generate AI Report (can take up to 10 minutes - VM is already stopped)
     println!("[ORCHESTRATOR] Step 7: Generating AI Analysis Report (Mode: {})...", analysis_mode);
-    progress.send_progress(&task_id, "ai_analysis", "Generating AI forensic report", 85);
-    if let Err(e) = ai_analysis::generate_ai_report(&task_id, &pool, &ai_manager, manager.clone(), true, &analysis_mode).await {
+    task_queue::checkpoint(pool, task_id, "ai_analysis").await;
+    progress.send_progress(task_id, "ai_analysis", "Generating AI forensic report", 85);
+    if let Err(e) = ai_analysis::generate_ai_report(&task_id.to_string(), pool, ai_manager, manager, true, analysis_mode).await {
         println!("[ORCHESTRATOR] Failed to generate AI report: {}", e);
     } else {
         println!("[ORCHESTRATOR] AI Analysis Report generated successfully.");
     }
+    if let Some(score) = &deterministic {
+        scoring::fill_verdict(pool, score).await;
+    }
 
     // Update Status: Completed
     let _ = sqlx::query("UPDATE tasks SET status='Completed', completed_at=$2 WHERE id=$1")
-        .bind(&task_id)
+        .bind(task_id)
         .bind(Utc::now().timestamp_millis())
-        .execute(&pool)
+        .execute(pool)
         .await;
-    progress.send_progress(&task_id, "completed", "Analysis complete", 100);
-
-    // Clear active task binding for this session
-    {
-        let mut sessions = manager.sessions.lock().await;
-        if let Some(session) = sessions.get_mut(&session_id) {
-            session.active_task_id = None;
-            println!("[AGENT] Task {} cleared from session {}", task_id, session_id);
-        }
-    }
+    progress.send_progress(task_id, "completed", "Analysis complete", 100);
+    misp::on_completed(pool, task_id).await;
 }
 
+#[utoipa::path(tag = "vms", responses((status = 200, description = "Success")))]
 #[post("/vms/actions/exec-binary")]
 async fn exec_binary(
     manager: web::Data<Arc<AgentManager>>,
-    client: web::Data<proxmox::ProxmoxClient>,
+    client: web::Data<dyn Hypervisor>,
     req: web::Json<ExecRequest>
 ) -> impl Responder {
     let cmd = serde_json::json!({
@@ -1010,6 +1755,29 @@ async fn exec_binary(
     HttpResponse::Ok().json(serde_json::json!({ "status": "broadcast", "path": req.path }))
 }
 
+/// Toggles Defender real-time protection / the guest firewall; an empty body just reports the current state.
+#[utoipa::path(tag = "vms", responses((status = 200, description = "Success")))]
+#[post("/vms/actions/defenses")]
+async fn set_defenses(
+    manager: web::Data<Arc<AgentManager>>,
+    req: web::Json<DefensesRequest>
+) -> impl Responder {
+    let cmd = serde_json::json!({
+        "command": "SET_DEFENSES",
+        "defender": req.defender,
+        "firewall": req.firewall
+    }).to_string();
+
+    if let Some(session_id) = &req.session_id {
+        manager.send_command_to_session(session_id, &cmd).await;
+        return HttpResponse::Ok().json(serde_json::json!({ "status": "sent", "target": session_id }));
+    }
+
+    manager.broadcast_command(&cmd).await;
+    HttpResponse::Ok().json(serde_json::json!({ "status": "broadcast" }))
+}
+
+#[utoipa::path(tag = "vms", responses((status = 200, description = "Success")))]
 #[post("/vms/actions/pivot")]
 pub async fn pivot_binary(
     manager: web::Data<Arc<AgentManager>>,
@@ -1019,29 +1787,68 @@ pub async fn pivot_binary(
         "command": "UPLOAD_PIVOT",
         "path": req.path
     }).to_string();
+
+    if let Some(task_id) = &req.task_id {
+        return match manager.find_session_by_task(task_id).await {
+            Some(session_id) => {
+                manager.send_command_to_session(&session_id, &cmd).await;
+                HttpResponse::Ok().json(serde_json::json!({ "status": "sent", "path": req.path, "target": session_id }))
+            }
+            None => HttpResponse::NotFound().json(serde_json::json!({ "error": "No agent session is running that task" })),
+        };
+    }
     
     manager.broadcast_command(&cmd).await;
     HttpResponse::Ok().json(serde_json::json!({ "status": "sent", "path": req.path }))
 }
 
+#[utoipa::path(tag = "telemetry", responses(
+    (status = 200, description = "Pivoted file queued as a child task"),
+    (status = 413, description = "Upload over MAX_PIVOT_UPLOAD_MB"),
+))]
 #[post("/vms/telemetry/pivot-upload")]
 pub async fn pivot_upload(
-    ai_manager: web::Data<AIManager>,
-    manager: web::Data<Arc<AgentManager>>,
-    client: web::Data<proxmox::ProxmoxClient>,
+    req: HttpRequest,
     pool: web::Data<Pool<Postgres>>,
-    progress_broadcaster: web::Data<Arc<progress_stream::ProgressBroadcaster>>,
+    scheduler: web::Data<Arc<task_queue::TaskScheduler>>,
+    manager: web::Data<Arc<AgentManager>>,
     mut payload: Multipart,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let mut limit = limits::UploadLimit::pivot();
+    if let Err(resp) = limit.check_declared(&req) {
+        return Ok(resp);
+    }
     // This is similar to submit_sample but used for pivoting
     // I can reuse the logic by refactoring later, but for now I'll just write it
     let mut filename = String::new();
     let mut original_filename = String::new();
     let mut sha256_hash = String::new();
+    let mut parent_task_id: Option<String> = None;
+    let mut guest_path: Option<String> = None;
+    let mut identified = false;
     
     while let Ok(Some(mut field)) = TryStreamExt::try_next(&mut payload).await {
+        if field.name() == Some("session_id") {
+            let session_id = read_text_field(&mut field).await;
+            // The pivoted file is a child of whatever that sandbox was analysing
+            match manager.task_for_session(&session_id).await {
+                Some(task) => {
+                    parent_task_id = task;
+                    identified = true;
+                }
+                None => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": "Unknown agent session" }))),
+            }
+            continue;
+        }
+        if field.name() == Some("path") {
+            guest_path = Some(read_text_field(&mut field).await);
+            continue;
+        }
         let content_disposition = field.content_disposition();
         if let Some(name) = content_disposition.and_then(|cd| cd.get_filename()) {
+            if !identified {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": "session_id field required before the file" })));
+            }
             original_filename = name.to_string();
             filename = format!("pivot_{}_{}", Utc::now().timestamp_millis(), name.replace("..", "").replace("/", "").replace("\\", ""));
             
@@ -1054,6 +1861,11 @@ pub async fn pivot_upload(
             
             let mut hasher = Sha256::new();
             while let Ok(Some(chunk)) = TryStreamExt::try_next(&mut field).await {
+                if let Err(resp) = limit.add(chunk.len()) {
+                    drop(f);
+                    let _ = tokio::fs::remove_file(&filepath).await;
+                    return Ok(resp);
+                }
                 f.write_all(&chunk).await
                     .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
                 hasher.update(&chunk);
@@ -1079,10 +1891,11 @@ pub async fn pivot_upload(
     let task_id = Utc::now().timestamp_millis().to_string();
 
     let filepath = format!("{}/{}", "./uploads", filename);
+    storage::persist(&format!("uploads/{}", filename)).await;
 
     // Insert task
     let _ = sqlx::query(
-        "INSERT INTO tasks (id, filename, original_filename, file_hash, status, created_at, file_path) VALUES ($1, $2, $3, $4, 'Queued', $5, $6)"
+        "INSERT INTO tasks (id, filename, original_filename, file_hash, status, created_at, file_path, parent_task_id) VALUES ($1, $2, $3, $4, 'Queued', $5, $6, $7)"
     )
     .bind(&task_id)
     .bind(&filename)
@@ -1090,34 +1903,72 @@ pub async fn pivot_upload(
     .bind(&sha256_hash)
     .bind(Utc::now().timestamp_millis())
     .bind(&filepath)
+    .bind(&parent_task_id)
     .execute(pool.get_ref())
     .await;
-
-    // Spawn analysis
-    let manager = manager.get_ref().clone();
-    let client = client.get_ref().clone();
-    let pool = pool.get_ref().clone();
-    let ai_manager = ai_manager.get_ref().clone();
-    let url_clone = download_url.clone();
-    let task_id_clone = task_id.clone();
-    let progress_bc: Arc<progress_stream::ProgressBroadcaster> = progress_broadcaster.get_ref().clone();
-    
-    actix_web::rt::spawn(async move {
-        orchestrate_sandbox(client, manager, pool, ai_manager, task_id_clone, url_clone, original_filename.clone(), 300, None, None, false, "quick".to_string(), progress_bc).await;
-    });
+    if let Some(parent) = &parent_task_id {
+        relationships::link_pivot(pool.get_ref(), parent, &task_id, guest_path.as_deref()).await;
+        // A dropped file: the parent's matches list it alongside its own sample
+        yara::queue_file(pool.get_ref(), parent, yara::Target {
+            kind: "dropped",
+            path: storage::local_path(&format!("uploads/{}", filename)),
+            name: original_filename.clone(),
+        });
+    }
+    yara::queue_task(pool.get_ref(), &task_id);
+    static_properties::queue(pool.get_ref(), &task_id);
+
+    // Queue analysis
+    if let Err(e) = scheduler.enqueue(task_queue::QueuedAnalysis {
+        task_id: task_id.clone(),
+        target_url: download_url,
+        original_filename,
+        duration_seconds: 300,
+        vmid: None,
+        node: None,
+        is_url_task: false,
+        analysis_mode: "quick".to_string(),
+        os_profile: None,
+        priority: task_queue::DEFAULT_PRIORITY.to_string(),
+        profile: None,
+    }).await {
+        return Ok(HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })));
+    }
 
     Ok(HttpResponse::Ok().json(serde_json::json!({ "status": "pivoted", "task_id": task_id })))
 }
 
+#[utoipa::path(tag = "vms", responses((status = 200, description = "Success")))]
 #[post("/vms/actions/exec-url")]
 async fn exec_url(
-    ai_manager: web::Data<AIManager>,
-    manager: web::Data<Arc<AgentManager>>,
-    client: web::Data<proxmox::ProxmoxClient>,
     pool: web::Data<Pool<Postgres>>,
-    progress_broadcaster: web::Data<Arc<progress_stream::ProgressBroadcaster>>,
+    scheduler: web::Data<Arc<task_queue::TaskScheduler>>,
     req: web::Json<UrlRequest>
 ) -> impl Responder {
+    let explicit_priority = match req.priority.as_deref().map(task_queue::parse_priority) {
+        Some(Some(p)) => Some(p),
+        Some(None) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "priority must be urgent, normal or bulk" })),
+        None => None,
+    };
+    let explicit_policy = match req.internet_policy.as_deref().map(network_policy::parse_policy) {
+        Some(Some(p)) => Some(p),
+        Some(None) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "internet_policy must be full, fake-net, blocked or vpn" })),
+        None => None,
+    };
+    let profile = match analysis_profiles::resolve(pool.get_ref(), req.profile.as_deref()).await {
+        Ok(p) => p,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+    let settings = analysis_profiles::SubmissionSettings {
+        duration_seconds: req.analysis_duration.map(|m| m * 60),
+        priority: explicit_priority,
+        internet_policy: explicit_policy,
+        vmid: req.vmid,
+        node: req.node.clone(),
+        ..Default::default()
+    }.or_profile(profile.as_ref());
+    let priority = settings.priority.unwrap_or(task_queue::DEFAULT_PRIORITY);
+
     // Create Task Record for URL Analysis
     let created_at = Utc::now().timestamp_millis();
     let task_id = created_at.to_string();
@@ -1129,7 +1980,7 @@ async fn exec_url(
         req.url.clone()
     };
     
-    let vmid = req.vmid;
+    let vmid = settings.vmid;
     let _ = sqlx::query(
         "INSERT INTO tasks (id, filename, original_filename, file_hash, status, created_at, sandbox_id) VALUES ($1, $2, $3, $4, 'Queued', $5, $6)"
     )
@@ -1143,28 +1994,33 @@ async fn exec_url(
     .await;
     
     println!("[URL Analysis] Task {} created for URL: {}", task_id, req.url);
+    network_policy::record(pool.get_ref(), &task_id, settings.internet_policy).await;
     
-    let duration = req.analysis_duration.unwrap_or(5) * 60;
+    let duration = settings.duration_seconds.unwrap_or(300);
     
-    // Spawn Analysis Job
-    let manager_clone = manager.get_ref().clone(); 
-    let client_clone = client.get_ref().clone();
-    let pool_clone = pool.get_ref().clone();
-    let ai_manager = ai_manager.get_ref().clone();
-    let url = req.url.clone();
-    let task_id_clone = task_id.clone();
-    let node = req.node.clone();
-    let progress_bc: Arc<progress_stream::ProgressBroadcaster> = progress_broadcaster.get_ref().clone();
-    
-    actix_web::rt::spawn(async move {
-        orchestrate_sandbox(client_clone, manager_clone, pool_clone, ai_manager, task_id_clone, url, "URL_Detonation".to_string(), duration, vmid, node, true, "quick".to_string(), progress_bc).await;
-    });
+    // Queue Analysis Job
+    if let Err(e) = scheduler.enqueue(task_queue::QueuedAnalysis {
+        task_id: task_id.clone(),
+        target_url: req.url.clone(),
+        original_filename: "URL_Detonation".to_string(),
+        duration_seconds: duration,
+        vmid,
+        node: settings.node,
+        is_url_task: true,
+        analysis_mode: settings.analysis_mode.unwrap_or_else(|| "quick".to_string()),
+        os_profile: settings.os_profile,
+        priority: priority.to_string(),
+        profile: profile.map(|p| p.name),
+    }).await {
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() }));
+    }
 
     HttpResponse::Ok().json(serde_json::json!({ 
         "status": "analysis_queued", 
         "url": req.url,
         "task_id": task_id,
-        "message": "URL analysis task created and orchestration initiated"
+        "priority": priority,
+        "message": "URL analysis task created and queued for a sandbox"
     }))
 }
 
@@ -1173,6 +2029,7 @@ struct VerdictOverride {
     verdict: String,
 }
 
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
 #[post("/tasks/{id}/verdict")]
 async fn update_task_verdict(
     pool: web::Data<Pool<Postgres>>,
@@ -1195,20 +2052,109 @@ async fn update_task_verdict(
     }
 }
 
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct TaskListQuery {
+    /// Without limit or offset every matching task is returned.
+    limit: Option<i64>,
+    offset: Option<i64>,
+    /// created_at (default), completed_at, risk_score, filename or status.
+    sort: Option<String>,
+    /// asc or desc (default).
+    order: Option<String>,
+    status: Option<String>,
+    verdict: Option<String>,
+    /// created_at bounds in epoch milliseconds.
+    from: Option<i64>,
+    to: Option<i64>,
+    /// Substring of the original filename, or a hash prefix.
+    search: Option<String>,
+}
+
+// Clamps client-supplied paging so one request can't pull the whole table.
+fn page_bounds(limit: Option<i64>, offset: Option<i64>, default: i64, max: i64) -> (i64, i64) {
+    (limit.unwrap_or(default).clamp(1, max), offset.unwrap_or(0).max(0))
+}
+
+/// Returns the tasks (one page of them when paged) as a JSON array; the unpaged match count is in `X-Total-Count`.
+#[utoipa::path(tag = "tasks", responses(
+    (status = 200, description = "Page of tasks", body = [Task], headers(("X-Total-Count" = i64, description = "Matches before paging"))),
+))]
 #[get("/tasks")]
-async fn list_tasks(pool: web::Data<Pool<Postgres>>) -> impl Responder {
-    let tasks = sqlx::query_as::<_, Task>(
-        "SELECT id, filename, original_filename, file_hash, status, verdict, risk_score, created_at, completed_at, ghidra_status, verdict_manual, sandbox_id, remnux_status, remnux_report FROM tasks ORDER BY created_at DESC"
-    )
-    .fetch_all(pool.get_ref())
-    .await;
+async fn list_tasks(
+    pool: web::Data<Pool<Postgres>>,
+    scheduler: web::Data<Arc<task_queue::TaskScheduler>>,
+    query: web::Query<TaskListQuery>,
+) -> impl Responder {
+    let (limit, offset) = page_bounds(query.limit, query.offset, 100, 1000);
+    let paged = query.limit.is_some() || query.offset.is_some();
+    // Whitelisted so the column name can be spliced into ORDER BY safely
+    let sort_col = match query.sort.as_deref() {
+        Some("completed_at") => "completed_at",
+        Some("risk_score") => "risk_score",
+        Some("filename") => "original_filename",
+        Some("status") => "status",
+        _ => "created_at",
+    };
+    let direction = if query.order.as_deref() == Some("asc") { "ASC" } else { "DESC" };
 
-    match tasks {
-        Ok(t) => HttpResponse::Ok().json(t),
+    let push_filters = |qb: &mut sqlx::QueryBuilder<'_, Postgres>| {
+        qb.push(" WHERE TRUE");
+        if let Some(status) = &query.status {
+            qb.push(" AND status = ").push_bind(status.clone());
+        }
+        if let Some(verdict) = &query.verdict {
+            qb.push(" AND LOWER(verdict) = LOWER(").push_bind(verdict.clone()).push(")");
+        }
+        if let Some(from) = query.from {
+            qb.push(" AND created_at >= ").push_bind(from);
+        }
+        if let Some(to) = query.to {
+            qb.push(" AND created_at <= ").push_bind(to);
+        }
+        if let Some(search) = query.search.as_ref().filter(|s| !s.is_empty()) {
+            qb.push(" AND (original_filename ILIKE ").push_bind(format!("%{}%", search))
+                .push(" OR file_hash ILIKE ").push_bind(format!("{}%", search)).push(")");
+        }
+    };
+
+    let mut count_qb = sqlx::QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM tasks");
+    push_filters(&mut count_qb);
+    let total: i64 = match count_qb.build_query_scalar().fetch_one(pool.get_ref()).await {
+        Ok(n) => n,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+
+    let mut qb = sqlx::QueryBuilder::<Postgres>::new(
+        "SELECT id, filename, original_filename, file_hash, status, verdict, risk_score, created_at, completed_at, ghidra_status, verdict_manual, sandbox_id, remnux_status, remnux_report, parent_task_id, internet_policy, container,
+         (SELECT q.priority FROM task_queue q WHERE q.task_id = tasks.id) AS priority FROM tasks"
+    );
+    push_filters(&mut qb);
+    qb.push(format!(" ORDER BY {} {} NULLS LAST, id", sort_col, direction));
+    if paged {
+        qb.push(" LIMIT ").push_bind(limit).push(" OFFSET ").push_bind(offset);
+    }
+
+    match qb.build_query_as::<Task>().fetch_all(pool.get_ref()).await {
+        Ok(mut t) => {
+            if t.iter().any(|task| task.status == "Queued") {
+                let estimates = scheduler.queue_estimates().await;
+                for task in t.iter_mut() {
+                    if let Some(est) = estimates.get(&task.id) {
+                        task.queue_position = Some(est.position);
+                        task.estimated_start = Some(est.estimated_start);
+                    }
+                }
+            }
+            HttpResponse::Ok()
+                .insert_header(("X-Total-Count", total.to_string()))
+                .json(t)
+        }
         Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
     }
 }
 
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
 #[delete("/tasks/{id}")]
 async fn delete_task(
     pool: web::Data<Pool<Postgres>>,
@@ -1233,6 +2179,13 @@ async fn delete_task(
             // Delete Associatied Screenshots Folder
             let screenshot_dir = format!("./screenshots/{}", id);
             let _ = tokio::fs::remove_dir_all(&screenshot_dir).await;
+            let _ = tokio::fs::remove_file(format!("reports/{}.pdf", id)).await;
+            let _ = tokio::fs::remove_dir_all(format!("./url_artifacts/{}", id)).await;
+            let _ = tokio::fs::remove_dir_all(format!("./static_artifacts/{}", id)).await;
+            storage::remove(&[format!("uploads/{}", t.filename), format!("reports/{}.pdf", id)]).await;
+            storage::remove_prefix(&format!("screenshots/{}/", id)).await;
+            storage::remove_prefix(&format!("url_artifacts/{}/", id)).await;
+            storage::remove_prefix(&format!("static_artifacts/{}/", id)).await;
             
             // Delete from Database
             if let Err(e) = sqlx::query("DELETE FROM tasks WHERE id = $1")
@@ -1245,6 +2198,13 @@ async fn delete_task(
             
             // Also delete associated events
             let _ = sqlx::query("DELETE FROM events WHERE task_id = $1").bind(&id).execute(pool.get_ref()).await;
+            let _ = sqlx::query("DELETE FROM screenshots WHERE task_id = $1").bind(&id).execute(pool.get_ref()).await;
+            let _ = sqlx::query("DELETE FROM url_artifacts WHERE task_id = $1").bind(&id).execute(pool.get_ref()).await;
+            let _ = sqlx::query("DELETE FROM static_properties WHERE task_id = $1").bind(&id).execute(pool.get_ref()).await;
+            let _ = sqlx::query("DELETE FROM alerts WHERE task_id = $1").bind(&id).execute(pool.get_ref()).await;
+            let _ = sqlx::query("DELETE FROM task_scores WHERE task_id = $1").bind(&id).execute(pool.get_ref()).await;
+            let _ = sqlx::query("DELETE FROM ioc_sightings WHERE task_id = $1").bind(&id).execute(pool.get_ref()).await;
+            relationships::forget(pool.get_ref(), &id).await;
             
             println!("[DATABASE] Task {} and associated data deleted.", id);
             HttpResponse::Ok().json(serde_json::json!({ "status": "success", "message": "Task and data deleted" }))
@@ -1257,6 +2217,7 @@ async fn delete_task(
     }
 }
 
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
 #[post("/tasks/purge")]
 async fn purge_all(pool: web::Data<Pool<Postgres>>) -> impl Responder {
     println!("[SYSTEM] Purge All initiated...");
@@ -1264,6 +2225,13 @@ async fn purge_all(pool: web::Data<Pool<Postgres>>) -> impl Responder {
     // 1. Clear Database Tables
     let _ = sqlx::query("DELETE FROM tasks").execute(pool.get_ref()).await;
     let _ = sqlx::query("DELETE FROM events").execute(pool.get_ref()).await;
+    let _ = sqlx::query("DELETE FROM screenshots").execute(pool.get_ref()).await;
+    let _ = sqlx::query("DELETE FROM url_artifacts").execute(pool.get_ref()).await;
+    let _ = sqlx::query("DELETE FROM static_properties").execute(pool.get_ref()).await;
+    let _ = sqlx::query("DELETE FROM task_relationships").execute(pool.get_ref()).await;
+    let _ = sqlx::query("DELETE FROM alerts").execute(pool.get_ref()).await;
+    let _ = sqlx::query("DELETE FROM task_scores").execute(pool.get_ref()).await;
+    let _ = sqlx::query("DELETE FROM ioc_sightings").execute(pool.get_ref()).await;
     
     // 2. Clear Files
     let _ = tokio::fs::remove_dir_all("./uploads").await;
@@ -1273,6 +2241,11 @@ async fn purge_all(pool: web::Data<Pool<Postgres>>) -> impl Responder {
     
     let _ = tokio::fs::remove_dir_all("./screenshots").await;
     let _ = tokio::fs::create_dir_all("./screenshots").await;
+    let _ = tokio::fs::remove_dir_all("./url_artifacts").await;
+    let _ = tokio::fs::remove_dir_all("./static_artifacts").await;
+    for root in storage::ROOTS {
+        storage::remove_prefix(&format!("{}/", root)).await;
+    }
     
     println!("[SYSTEM] Purge complete: Database and files cleared.");
     HttpResponse::Ok().json(serde_json::json!({ "status": "success", "message": "All data cleared" }))
@@ -1333,53 +2306,285 @@ async fn get_history(
     }
 }
 
+#[utoipa::path(tag = "telemetry", responses(
+    (status = 200, description = "Screenshots stored"),
+    (status = 413, description = "Upload over MAX_SCREENSHOT_UPLOAD_MB"),
+))]
 #[post("/vms/telemetry/screenshot")]
 async fn upload_screenshot(
+    req: HttpRequest,
     mut payload: Multipart,
-    manager: web::Data<Arc<AgentManager>>
+    manager: web::Data<Arc<AgentManager>>,
+    pool: web::Data<Pool<Postgres>>
 ) -> Result<HttpResponse, Error> {
-    let task_id = manager.get_any_active_task_id().await.unwrap_or_else(|| "unsorted".to_string());
-    let task_dir = format!("./screenshots/{}", task_id);
-    let _ = tokio::fs::create_dir_all(&task_dir).await;
+    let mut limit = limits::UploadLimit::screenshot();
+    if let Err(resp) = limit.check_declared(&req) {
+        return Ok(resp);
+    }
+    // Event-triggered captures carry the identity of the event that caused them
+    let mut session_id: Option<String> = None;
+    let mut trigger_type: Option<String> = None;
+    let mut trigger_pid: Option<i32> = None;
+    let mut trigger_ts: Option<i64> = None;
+    let mut task_id: Option<String> = None;
+    let mut saved = Vec::new();
     
     while let Ok(Some(mut field)) = TryStreamExt::try_next(&mut payload).await {
-        let name = match field.content_disposition().and_then(|cd| cd.get_filename()) {
-            Some(n) => n.to_string(),
-            None => format!("screenshot_{}.png", Utc::now().timestamp_millis()),
+        let field_name = field.content_disposition().and_then(|cd| cd.get_name()).unwrap_or("").to_string();
+        if field_name == "session_id" || field_name.starts_with("trigger_") {
+            let value = read_text_field(&mut field).await;
+            match field_name.as_str() {
+                "session_id" => session_id = Some(value),
+                "trigger_event_type" => trigger_type = Some(value),
+                "trigger_pid" => trigger_pid = value.parse().ok(),
+                "trigger_timestamp" => trigger_ts = value.parse().ok(),
+                _ => {}
+            }
+            continue;
+        }
+
+        let task_id = match &task_id {
+            Some(t) => t.clone(),
+            None => match upload_task_id(&manager, session_id.as_deref()).await {
+                Ok(t) => task_id.insert(t).clone(),
+                Err(resp) => return Ok(resp),
+            },
         };
+        let task_dir = format!("./screenshots/{}", task_id);
+        let _ = tokio::fs::create_dir_all(&task_dir).await;
+
+        // The guest's file name only tells us the monitor; the stored name is always built here
+        let screen = screenshots::monitor_of(field.content_disposition().and_then(|cd| cd.get_filename()).unwrap_or_default());
+        let mut name = format!("screenshot_{}_screen{}.png", trigger_ts.unwrap_or_else(|| Utc::now().timestamp_millis()), screen);
+
+        // Name triggered shots after the stored event ID so the UI can pin them to the timeline
+        let mut event_id = None;
+        if let (Some(etype), Some(pid), Some(ts)) = (&trigger_type, trigger_pid, trigger_ts) {
+            event_id = find_trigger_event_id(pool.get_ref(), &task_id, etype, pid, ts).await;
+            if let Some(id) = event_id {
+                name = format!("event_{}_screen{}.png", id, screen);
+            }
+        }
+
         let path = format!("{}/{}", task_dir, name);
         let mut f = tokio::fs::File::create(&path).await
             .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
 
         while let Ok(Some(chunk)) = TryStreamExt::try_next(&mut field).await {
+            if let Err(resp) = limit.add(chunk.len()) {
+                drop(f);
+                let _ = tokio::fs::remove_file(&path).await;
+                return Ok(resp);
+            }
             f.write_all(&chunk).await
                 .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
         }
+        drop(f);
+        storage::persist(&format!("screenshots/{}/{}", task_id, name)).await;
+        let taken_at = trigger_ts.unwrap_or_else(|| Utc::now().timestamp_millis());
+        if let Err(e) = screenshots::record(pool.get_ref(), &task_id, &name, event_id, taken_at).await {
+            println!("[SCREENSHOTS] Failed to index {}/{}: {}", task_id, name, e);
+        }
+        saved.push(name);
     }
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({ "status": "success" })))
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "status": "success", "files": saved })))
+}
+
+async fn read_text_field(field: &mut actix_multipart::Field) -> String {
+    let mut bytes = Vec::new();
+    while let Ok(Some(chunk)) = TryStreamExt::try_next(field).await {
+        bytes.extend_from_slice(&chunk);
+    }
+    String::from_utf8_lossy(&bytes).trim().to_string()
 }
 
+// Agent uploads land in the task bound to their session ("unsorted" until one is bound)
+async fn upload_task_id(manager: &AgentManager, session_id: Option<&str>) -> Result<String, HttpResponse> {
+    let Some(session_id) = session_id.filter(|s| !s.is_empty()) else {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({ "error": "session_id field required before the file" })));
+    };
+    match manager.task_for_session(session_id).await {
+        Some(task) => Ok(task.unwrap_or_else(|| "unsorted".to_string())),
+        None => Err(HttpResponse::BadRequest().json(serde_json::json!({ "error": "Unknown agent session" }))),
+    }
+}
+
+// The screenshot can race the event over the TCP channel, so retry briefly before giving up.
+async fn find_trigger_event_id(pool: &Pool<Postgres>, task_id: &str, event_type: &str, pid: i32, timestamp: i64) -> Option<i32> {
+    for _ in 0..3 {
+        let row: Option<(i32,)> = sqlx::query_as(
+            "SELECT id FROM events WHERE event_type = $1 AND process_id = $2 AND timestamp = $3 AND (task_id = $4 OR task_id IS NULL) ORDER BY id DESC LIMIT 1"
+        )
+        .bind(event_type)
+        .bind(pid)
+        .bind(timestamp)
+        .bind(task_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten();
+
+        if let Some((id,)) = row {
+            return Some(id);
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    None
+}
+
+#[utoipa::path(tag = "telemetry", responses((status = 200, description = "Success")))]
 #[get("/vms/telemetry/screenshots")]
-async fn list_screenshots(query: web::Query<TaskQuery>) -> impl Responder {
-    let mut files = Vec::new();
-    let base_path = if let Some(tid) = &query.task_id {
-        format!("./screenshots/{}", tid)
-    } else {
-        "./screenshots".to_string()
+async fn list_screenshots(pool: web::Data<Pool<Postgres>>, query: web::Query<TaskQuery>) -> impl Responder {
+    // File names in capture order; /tasks/{id}/screenshots has the full metadata
+    let files: Result<Vec<String>, sqlx::Error> = match &query.task_id {
+        Some(tid) => screenshots::list(pool.get_ref(), tid, &screenshots::ScreenshotQuery { limit: Some(5000), ..Default::default() })
+            .await
+            .map(|shots| shots.into_iter().map(|s| s.filename).collect()),
+        None => sqlx::query_scalar("SELECT DISTINCT task_id FROM screenshots ORDER BY task_id")
+            .fetch_all(pool.get_ref())
+            .await,
     };
+    match files {
+        Ok(files) => HttpResponse::Ok().json(files),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
 
-    if let Ok(entries) = std::fs::read_dir(&base_path) {
+#[utoipa::path(tag = "telemetry", responses(
+    (status = 200, description = "Success"),
+    (status = 413, description = "Upload over MAX_MEMORY_DUMP_UPLOAD_MB"),
+))]
+#[post("/vms/telemetry/memory-dump")]
+async fn upload_memory_dump(
+    req: HttpRequest,
+    mut payload: Multipart,
+    pool: web::Data<Pool<Postgres>>,
+    manager: web::Data<Arc<AgentManager>>
+) -> Result<HttpResponse, Error> {
+    let mut limit = limits::UploadLimit::memory_dump();
+    if let Err(resp) = limit.check_declared(&req) {
+        return Ok(resp);
+    }
+    let mut session_id: Option<String> = None;
+    let mut pid: Option<u32> = None;
+    let mut stored: Option<(String, String)> = None;
+
+    while let Ok(Some(mut field)) = TryStreamExt::try_next(&mut payload).await {
+        let field_name = field.content_disposition().and_then(|cd| cd.get_name()).unwrap_or("").to_string();
+
+        if field_name == "session_id" {
+            session_id = Some(read_text_field(&mut field).await);
+            continue;
+        }
+        if field_name == "pid" {
+            pid = read_text_field(&mut field).await.parse().ok();
+            continue;
+        }
+
+        if field_name == "file" {
+            let task_id = match upload_task_id(&manager, session_id.as_deref()).await {
+                Ok(t) => t,
+                Err(resp) => return Ok(resp),
+            };
+            let task_dir = format!("./memory_dumps/{}", task_id);
+            let _ = tokio::fs::create_dir_all(&task_dir).await;
+            // Never trust the guest-supplied filename; it ends up on the host filesystem
+            let name = match pid {
+                Some(p) => format!("dump_{}_{}.bin", p, Utc::now().timestamp_millis()),
+                None => format!("dump_{}.bin", Utc::now().timestamp_millis()),
+            };
+            let path = format!("{}/{}", task_dir, name);
+            let mut f = tokio::fs::File::create(&path).await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+
+            while let Ok(Some(chunk)) = TryStreamExt::try_next(&mut field).await {
+                if let Err(resp) = limit.add(chunk.len()) {
+                    drop(f);
+                    let _ = tokio::fs::remove_file(&path).await;
+                    return Ok(resp);
+                }
+                f.write_all(&chunk).await
+                    .map_err(actix_web::error::ErrorInternalServerError)?;
+            }
+            stored = Some((task_id, name));
+        }
+    }
+
+    match stored {
+        Some((task_id, name)) => {
+            let url = format!("/tasks/{}/memory-dumps/{}", task_id, name);
+            println!("[MEMORY] Stored hollowing dump for task {} (PID {:?}): {}", task_id, pid, url);
+            yara::queue_file(pool.get_ref(), &task_id, yara::Target {
+                kind: "memory",
+                path: std::path::PathBuf::from(format!("./memory_dumps/{}/{}", task_id, name)),
+                name: name.clone(),
+            });
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "status": "success",
+                "task_id": task_id,
+                "pid": pid,
+                "url": url
+            })))
+        }
+        None => Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": "No dump file in request" }))),
+    }
+}
+
+// Whether `task_id` names a task, so it can be used in a path on disk.
+pub async fn task_exists(pool: &Pool<Postgres>, task_id: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM tasks WHERE id = $1)")
+        .bind(task_id)
+        .fetch_one(pool)
+        .await
+}
+
+#[utoipa::path(tag = "telemetry", responses((status = 200, description = "Success"), (status = 404, description = "Task not found")))]
+#[get("/tasks/{task_id}/memory-dumps")]
+async fn list_memory_dumps(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    let task_id = path.into_inner();
+    match task_exists(pool.get_ref(), &task_id).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+    let mut dumps = Vec::new();
+
+    if let Ok(entries) = std::fs::read_dir(format!("./memory_dumps/{}", task_id)) {
         for entry in entries.flatten() {
             if let Ok(name) = entry.file_name().into_string() {
-                // If we are listing the root, don't show directories
-                if query.task_id.is_some() || !entry.path().is_dir() {
-                    files.push(name);
-                }
+                let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+                dumps.push(serde_json::json!({
+                    "filename": name,
+                    "size": size,
+                    "url": format!("/tasks/{}/memory-dumps/{}", task_id, name)
+                }));
             }
         }
     }
-    HttpResponse::Ok().json(files)
+    HttpResponse::Ok().json(dumps)
+}
+
+#[utoipa::path(tag = "telemetry", responses(
+    (status = 200, description = "The dump in a zip protected with the default password"),
+    (status = 404, description = "No such task or dump"),
+))]
+#[get("/tasks/{task_id}/memory-dumps/{name}")]
+async fn download_memory_dump(req: HttpRequest, pool: web::Data<Pool<Postgres>>, path: web::Path<(String, String)>) -> impl Responder {
+    let (task_id, name) = path.into_inner();
+    match task_exists(pool.get_ref(), &task_id).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+    let local = std::path::PathBuf::from(format!("./memory_dumps/{}/{}", task_id, name));
+    if name.contains(['/', '\\']) || name.contains("..") || !local.is_file() {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Memory dump not found" }));
+    }
+    // Carved memory holds the unpacked payload; it leaves like a sample
+    audit::log_access(pool.get_ref(), &req, &task_id, serde_json::json!({ "memory_dump": name }), 200).await;
+    let stem = name.trim_end_matches(".bin").to_string();
+    sample_download::encrypted_zip(local, name.clone(), &stem, format!("memory dump {}/{}", task_id, name))
 }
 
 // Vector Search Helper
@@ -1438,22 +2643,35 @@ struct ConfigRequest {
     anthropic_model: Option<String>,
     openai_key: Option<String>,
     openai_model: Option<String>,
+    // Any OpenAI-compatible server; defaults to https://api.openai.com/v1
+    openai_base_url: Option<String>,
+    // Sent with every request to it, e.g. OpenRouter's HTTP-Referer and X-Title
+    openai_headers: Option<std::collections::HashMap<String, String>>,
     copilot_token: Option<String>,
     copilot_model: Option<String>,
+    azure_openai_key: Option<String>,
+    // https://<resource>.openai.azure.com
+    azure_openai_endpoint: Option<String>,
+    azure_openai_deployment: Option<String>,
+    azure_openai_api_version: Option<String>,
+    // Left empty, Bedrock signs with the ECS task role or EC2 instance profile
+    bedrock_access_key: Option<String>,
+    bedrock_secret_key: Option<String>,
+    bedrock_session_token: Option<String>,
+    bedrock_region: Option<String>,
+    // Model ID, e.g. anthropic.claude-3-5-sonnet-20240620-v1:0 or meta.llama3-1-70b-instruct-v1:0
+    bedrock_model: Option<String>,
+    // Providers to fall back to, in order, when the selected one keeps failing
+    fallback: Option<Vec<String>>,
 }
 
+#[utoipa::path(tag = "ai", responses((status = 200, description = "Success")))]
 #[post("/vms/ai/config")]
 async fn set_ai_config(
     req: web::Json<ConfigRequest>,
     ai_manager: web::Data<AIManager>
 ) -> impl Responder {
-    let provider = match req.provider.to_lowercase().as_str() {
-        "gemini" => ProviderType::Gemini,
-        "anthropic" => ProviderType::Anthropic,
-        "openai" => ProviderType::OpenAI,
-        "copilot" => ProviderType::Copilot,
-        _ => ProviderType::Ollama, // Default fallback
-    };
+    let provider = ProviderType::from_str(&req.provider);
 
     ai_manager.switch_provider(
         provider, 
@@ -1465,13 +2683,26 @@ async fn set_ai_config(
         req.anthropic_model.clone(),
         req.openai_key.clone(),
         req.openai_model.clone(),
+        req.openai_base_url.clone(),
+        req.openai_headers.clone(),
         req.copilot_token.clone(),
-        req.copilot_model.clone()
+        req.copilot_model.clone(),
+        req.azure_openai_key.clone(),
+        req.azure_openai_endpoint.clone(),
+        req.azure_openai_deployment.clone(),
+        req.azure_openai_api_version.clone(),
+        req.bedrock_access_key.clone(),
+        req.bedrock_secret_key.clone(),
+        req.bedrock_session_token.clone(),
+        req.bedrock_region.clone(),
+        req.bedrock_model.clone(),
+        req.fallback.as_ref().map(|f| f.iter().map(|p| ProviderType::from_str(p)).collect())
     ).await;
     
     HttpResponse::Ok().json(serde_json::json!({ "status": "success", "provider": req.provider }))
 }
 
+#[utoipa::path(tag = "ai", responses((status = 200, description = "Success")))]
 #[get("/vms/ai/config")]
 async fn get_ai_config(ai_manager: web::Data<AIManager>) -> impl Responder {
     let config = ai_manager.get_config().await;
@@ -1483,6 +2714,7 @@ struct AIModeRequest {
     mode: String,
 }
 
+#[utoipa::path(tag = "ai", responses((status = 200, description = "Success")))]
 #[post("/vms/ai/mode")]
 async fn set_ai_mode(
     req: web::Json<AIModeRequest>,
@@ -1496,6 +2728,7 @@ async fn set_ai_mode(
     }))
 }
 
+#[utoipa::path(tag = "ai", responses((status = 200, description = "Success")))]
 #[get("/vms/ai/mode")]
 async fn get_ai_mode_handler(ai_manager: web::Data<AIManager>) -> impl Responder {
     let mode = ai_manager.get_ai_mode().await;
@@ -1504,13 +2737,26 @@ async fn get_ai_mode_handler(ai_manager: web::Data<AIManager>) -> impl Responder
     }))
 }
 
+#[utoipa::path(tag = "ai", responses(
+    (status = 200, description = "The answer as server-sent events; X-Chat-Session names the conversation"),
+    (status = 404, description = "The session_id names no conversation"),
+))]
 #[post("/vms/ai/chat")]
 async fn chat_handler(
+    http_req: HttpRequest,
     req: web::Json<ChatRequest>,
     ai_manager: web::Data<AIManager>,
     manager: web::Data<Arc<AgentManager>>,
     pool: web::Data<Pool<Postgres>>
 ) -> impl Responder {
+    // Pick up the stored conversation (or start one) before anything reads the history
+    let mut req = req.into_inner();
+    let user = crate::auth::current_user(&http_req).map(|u| u.username);
+    let session_id = match chat_sessions::open(pool.get_ref(), &mut req, user).await {
+        Ok(Some(id)) => id,
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Chat session not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
 
     // Fetch recent analysis context
     let recent_tasks = sqlx::query_as::<_, Task>(
@@ -1526,6 +2772,12 @@ async fn chat_handler(
     } else {
         manager.get_any_active_task_id().await
     };
+
+    // With a task in focus the model looks up the evidence itself rather than getting it all up front
+    if let Some(tid) = target_task_id.clone().filter(|_| ai_tools::enabled()) {
+        let stream = ai_tools::chat(ai_manager.get_ref().clone(), pool.get_ref().clone(), manager.get_ref().clone(), tid, req);
+        return chat_sse(&session_id, chat_sessions::record(pool.get_ref().clone(), session_id.clone(), stream));
+    }
     
     // Fetch Task Filename if we have a Task ID
     let mut target_filename = String::new();
@@ -1543,14 +2795,19 @@ async fn chat_handler(
 
     let telemetry_events = if let Some(tid) = &target_task_id {
         sqlx::query_as::<_, RawAgentEvent>(
-            "SELECT id, event_type, process_id, parent_process_id, process_name, details, decoded_details, timestamp, task_id, digital_signature 
+            "SELECT id, event_type, process_id, parent_process_id, process_name, details, decoded_details, timestamp, task_id, digital_signature, severity, category 
              FROM events 
              WHERE task_id = $1 
-             ORDER BY timestamp ASC LIMIT 500"
+             ORDER BY COALESCE(severity, 0) DESC, timestamp ASC LIMIT 500"
         )
         .bind(tid)
         .fetch_all(pool.get_ref())
         .await
+        .map(|mut evts| {
+            // The cap keeps the highest-severity events; restore chronological order for the model
+            evts.sort_by_key(|e| e.timestamp);
+            evts
+        })
         .unwrap_or_default()
     } else {
         Vec::new()
@@ -1567,6 +2824,9 @@ async fn chat_handler(
     } else {
         telemetry_events
     };
+    let telemetry_events: Vec<RawAgentEvent> = telemetry_events.into_iter()
+        .filter(|e| !noise_filters::is_noise(None, &e.event_type, &e.process_name, e.digital_signature.as_deref()))
+        .collect();
 
     // Patient Zero / Lineage Filtering
     let filtered_events: Vec<&RawAgentEvent> = if target_task_id.is_some() && !target_filename.is_empty() {
@@ -1582,9 +2842,8 @@ async fn chat_handler(
          let root_pid = if let Some(pz) = patient_zero {
              pz.process_id
          } else {
-             // Fallback: If no direct match, find the first non-noise PID
+             // Fallback: If no direct match, the first PID left after noise filtering
              telemetry_events.iter()
-                .filter(|e| !NOISE_PROCESSES.iter().any(|np| e.process_name.to_lowercase().contains(np)))
                 .map(|e| e.process_id)
                 .next()
                 .unwrap_or(0)
@@ -1610,9 +2869,8 @@ async fn chat_handler(
             .filter(|e| relevant_pids.contains(&e.process_id) || relevant_pids.contains(&e.parent_process_id))
             .collect()
     } else {
-        // Global / No Task Fallback: Use standard noise filtering
+        // Global / No Task Fallback: noise is already filtered out above
         telemetry_events.iter()
-            .filter(|e| !NOISE_PROCESSES.iter().any(|np| e.process_name.to_lowercase().contains(np)))
             .take(100)
             .collect()
     };
@@ -1783,24 +3041,7 @@ async fn chat_handler(
     }
 
     // SYSTEM PROMPT
-    let system_prompt = format!(
-"## VooDooBox Intelligence Core | System Prompt
-You are the VooDooBox AI, a high-fidelity forensic analysis node. 
-Analyze the provided context and respond to the user's query.
-
-FORMATTING RULES:
-1. You MUST enclose your internal reasoning in <think> tags before your final answer.
-2. The final answer should be clear and concise.
-
-Example:
-<think>
-User asks about file X. I see it in the context...
-</think>
-The file X appears to be malicious...
-
-CONTEXT SUMMARY:
-{}
-", context_summary);
+    let system_prompt = prompts::render(pool.get_ref(), "chat_system", serde_json::json!({ "context": context_summary })).await;
 
     let use_map_reduce = context_summary.len() > 10000;
     let ai_manager_clone = ai_manager.get_ref().clone();
@@ -1811,12 +3052,14 @@ CONTEXT SUMMARY:
          ai_manager_clone.map_reduce_ask(
              history_clone,
              context_summary,
-             message_clone
+             message_clone,
+             req.task_id.clone()
          )
     } else {
-        let (tx, rx): (tokio::sync::mpsc::Sender<Result<StreamEvent, Box<dyn std::error::Error + Send + Sync>>>, _) = tokio::sync::mpsc::channel(1);
+        let (tx, rx): (tokio::sync::mpsc::Sender<Result<StreamEvent, Box<dyn std::error::Error + Send + Sync>>>, _) = tokio::sync::mpsc::channel(100);
         
-        let sys_prompt_final = system_prompt; 
+        let usage = crate::ai_usage::UsageTag::new(req.task_id.as_deref(), "chat").with_prompts(&[&system_prompt]);
+        let sys_prompt_final = system_prompt.text; 
         let mut history_final = req.history.clone();
         history_final.push(crate::ai::provider::ChatMessage {
             role: "user".to_string(),
@@ -1828,7 +3071,17 @@ CONTEXT SUMMARY:
             let _ = tx.send(Ok(StreamEvent::Thought("Analyzing...".to_string()))).await;
             println!("[AI] Sent 'Analyzing' event to stream");
 
-            match ai_manager_clone.ask(history_final, sys_prompt_final).await {
+            // Pieces of the answer go out as Token events while the provider writes it
+            let (token_tx, mut token_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+            let token_events = tx.clone();
+            let forward = async move {
+                while let Some(piece) = token_rx.recv().await {
+                    let _ = token_events.send(Ok(StreamEvent::Token(piece))).await;
+                }
+            };
+            let (result, _) = tokio::join!(ai_manager_clone.ask_streaming(history_final, sys_prompt_final, &usage, token_tx), forward);
+
+            match result {
                 Ok(response) => {
                     println!("[AI] Received response from provider (len: {})", response.len());
                     
@@ -1876,7 +3129,12 @@ CONTEXT SUMMARY:
         });
         tokio_stream::wrappers::ReceiverStream::new(rx)
     };
-    
+
+    chat_sse(&session_id, chat_sessions::record(pool.get_ref().clone(), session_id.clone(), stream))
+}
+
+// Chat StreamEvents as SSE, naming the conversation in X-Chat-Session
+fn chat_sse(session_id: &str, stream: impl futures::Stream<Item = Result<StreamEvent, Box<dyn std::error::Error + Send + Sync>>> + 'static) -> HttpResponse {
     let sse_stream = stream.map(|result| {
         match result {
             Ok(event) => {
@@ -1893,31 +3151,24 @@ CONTEXT SUMMARY:
 
     HttpResponse::Ok()
         .content_type("text/event-stream")
+        .insert_header(("X-Chat-Session", session_id))
         .streaming(sse_stream)
 }
 
 
+#[utoipa::path(tag = "ai", responses((status = 200, description = "Success")))]
 #[post("/vms/analysis/ai-insight")]
 async fn ai_insight_handler(
     req: web::Json<AnalysisRequest>,
     ai_manager: web::Data<AIManager>,
+    pool: web::Data<Pool<Postgres>>,
 ) -> impl Responder {
-    let prompt = format!(
-        "## Forensic Insight Protocol\n\
-\n\
-Analyze the evidence according to the following rules:\n\
-1. FOCUS: Anomalous parent-child relations, LOLBins (certutil, etc), and registry persistence.\n\
-2. ACCURACY: Extract EXACT PIDs. Citations required.\n\
-3. VERDICT: Use Risk Score (0-100) and MITRE TTP IDs (e.g., T1055).\n\n\
-<EVIDENCE>\n\
-{}\n\
-</EVIDENCE>\n\
-\n\
-Return ONLY RAW JSON.",
-        serde_json::to_string(&req.into_inner()).unwrap_or_default()
-    );
+    let prompt = prompts::render(pool.get_ref(), "insight", serde_json::json!({
+        "evidence": serde_json::to_string(&req.into_inner()).unwrap_or_default()
+    })).await;
 
-    match ai_manager.ask(vec![], prompt).await {
+    let usage = crate::ai_usage::UsageTag::new(None, "insight").with_prompts(&[&prompt]);
+    match ai_manager.ask(vec![], prompt.text, &usage).await {
         Ok(ai_text) => {
             let clean_json = ai_text.trim_matches(|c| c == '`' || c == '\n' || c == ' ');
             let clean_json = clean_json.strip_prefix("json").unwrap_or(clean_json).trim();
@@ -1967,6 +3218,7 @@ async fn trigger_ghidra_background(filename: String, task_id: String, pool: Pool
         }
 }
 
+#[utoipa::path(tag = "ghidra", responses((status = 200, description = "Success")))]
 #[post("/ghidra/analyze")]
 async fn ghidra_analyze(req: web::Json<serde_json::Value>) -> impl Responder {
     let client = reqwest::Client::new();
@@ -1987,6 +3239,7 @@ async fn ghidra_analyze(req: web::Json<serde_json::Value>) -> impl Responder {
     }
 }
 
+#[utoipa::path(tag = "ghidra", responses((status = 200, description = "Success")))]
 #[get("/ghidra/binary/{name}/functions")]
 async fn ghidra_functions(path: web::Path<String>) -> impl Responder {
     let name = path.into_inner();
@@ -2007,6 +3260,7 @@ async fn ghidra_functions(path: web::Path<String>) -> impl Responder {
     }
 }
 
+#[utoipa::path(tag = "ghidra", responses((status = 200, description = "Success")))]
 #[get("/ghidra/binary/{name}/decompile/{address}")]
 async fn ghidra_decompile(path: web::Path<(String, String)>) -> impl Responder {
     let (name, address) = path.into_inner();
@@ -2042,6 +3296,7 @@ pub struct GhidraFunction {
     pub assembly: String,
 }
 
+#[utoipa::path(tag = "ghidra", responses((status = 200, description = "Success")))]
 #[post("/ghidra/ingest")]
 async fn ghidra_ingest(
     req: web::Json<GhidraIngestBatch>,
@@ -2107,6 +3362,7 @@ struct GhidraIngestComplete {
     task_id: String,
 }
 
+#[utoipa::path(tag = "ghidra", responses((status = 200, description = "Success")))]
 #[post("/ghidra/ingest/complete")]
 async fn ghidra_ingest_complete(
     req: web::Json<GhidraIngestComplete>,
@@ -2126,6 +3382,7 @@ async fn ghidra_ingest_complete(
     }
 }
 
+#[utoipa::path(tag = "ghidra", responses((status = 200, description = "Success")))]
 #[get("/ghidra/scripts")]
 async fn ghidra_list_scripts() -> impl Responder {
     // Proxy to Ghidra service
@@ -2148,6 +3405,7 @@ async fn ghidra_list_scripts() -> impl Responder {
     }
 }
 
+#[utoipa::path(tag = "ghidra", responses((status = 200, description = "Success")))]
 #[post("/ghidra/run-script")]
 async fn ghidra_run_script(req: web::Json<serde_json::Value>) -> impl Responder {
     let client = reqwest::Client::new();
@@ -2168,6 +3426,7 @@ async fn ghidra_run_script(req: web::Json<serde_json::Value>) -> impl Responder
     }
 }
 
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
 #[get("/tasks/{id}/ghidra-findings")]
 async fn get_ghidra_findings(
     path: web::Path<String>,
@@ -2196,6 +3455,7 @@ async fn get_ghidra_findings(
     }
 }
 
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
 #[get("/tasks/{id}/ai-report")]
 async fn get_ai_report(
     path: web::Path<String>,
@@ -2246,6 +3506,7 @@ async fn get_ai_report(
     }
 }
 
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
 #[post("/tasks/{id}/analyze")]
 async fn trigger_task_analysis(
     path: web::Path<String>,
@@ -2308,6 +3569,7 @@ async fn trigger_task_analysis(
 
 
 
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
 #[post("/tasks/{id}/report/pdf")]
 async fn generate_pdf_report(
     path: web::Path<String>,
@@ -2318,6 +3580,7 @@ async fn generate_pdf_report(
 
     // Ensure reports directory exists
     let _ = std::fs::create_dir_all("reports");
+    storage::ensure_local(&format!("reports/{}.pdf", task_id)).await;
 
     // Check if pre-generated (high quality) report exists
     if std::path::Path::new(&file_path).exists() {
@@ -2419,7 +3682,19 @@ async fn init_db() -> Pool<Postgres> {
     let _ = sqlx::query("ALTER TABLE events ADD COLUMN IF NOT EXISTS decoded_details TEXT").execute(&pool).await;
     let _ = sqlx::query("ALTER TABLE events ADD COLUMN IF NOT EXISTS session_id TEXT").execute(&pool).await;
     let _ = sqlx::query("ALTER TABLE events ADD COLUMN IF NOT EXISTS digital_signature TEXT").execute(&pool).await;
+    let _ = sqlx::query("ALTER TABLE events ADD COLUMN IF NOT EXISTS severity INTEGER").execute(&pool).await;
+    let _ = sqlx::query("ALTER TABLE events ADD COLUMN IF NOT EXISTS category TEXT").execute(&pool).await;
+    let _ = sqlx::query("ALTER TABLE events ADD COLUMN IF NOT EXISTS kill_chain_stage TEXT").execute(&pool).await;
+    let _ = sqlx::query("ALTER TABLE events ADD COLUMN IF NOT EXISTS decoder_version INTEGER").execute(&pool).await;
+    let _ = sqlx::query("ALTER TABLE events ADD COLUMN IF NOT EXISTS obfuscation_score INTEGER").execute(&pool).await;
+    let _ = sqlx::query("ALTER TABLE events ADD COLUMN IF NOT EXISTS occurrences INTEGER NOT NULL DEFAULT 1").execute(&pool).await;
+    let _ = sqlx::query("ALTER TABLE events ADD COLUMN IF NOT EXISTS last_seen BIGINT").execute(&pool).await;
+    if let Err(e) = retention::partition_events(&pool).await {
+        println!("[RETENTION] Failed to partition events, keeping the plain table: {}", e);
+    }
+    let _ = sqlx::query("CREATE INDEX IF NOT EXISTS idx_events_task_severity ON events (task_id, severity)").execute(&pool).await;
     let _ = sqlx::query("CREATE INDEX IF NOT EXISTS idx_events_search ON events USING GIN (to_tsvector('english', process_name || ' ' || details || ' ' || COALESCE(decoded_details, '')))").execute(&pool).await;
+    let _ = sqlx::query("CREATE INDEX IF NOT EXISTS idx_events_task_timestamp ON events (task_id, timestamp)").execute(&pool).await;
 
     sqlx::query(
         "CREATE TABLE IF NOT EXISTS tasks (
@@ -2454,6 +3729,8 @@ async fn init_db() -> Pool<Postgres> {
     let _ = sqlx::query("ALTER TABLE tasks ADD COLUMN IF NOT EXISTS verdict_manual BOOLEAN DEFAULT FALSE").execute(&pool).await;
     let _ = sqlx::query("ALTER TABLE tasks ADD COLUMN IF NOT EXISTS remnux_status TEXT DEFAULT 'Not Started'").execute(&pool).await;
     let _ = sqlx::query("ALTER TABLE tasks ADD COLUMN IF NOT EXISTS remnux_report JSONB").execute(&pool).await;
+    let _ = sqlx::query("CREATE INDEX IF NOT EXISTS idx_tasks_created_at ON tasks (created_at DESC)").execute(&pool).await;
+    let _ = sqlx::query("ALTER TABLE tasks ADD COLUMN IF NOT EXISTS parent_task_id TEXT").execute(&pool).await;
 
     println!("[DATABASE] Task table migrations complete.");
 
@@ -2529,6 +3806,7 @@ async fn init_db() -> Pool<Postgres> {
     
     // Migration for forensic_report_json
     let _ = sqlx::query("ALTER TABLE analysis_reports ADD COLUMN IF NOT EXISTS forensic_report_json TEXT DEFAULT '{}'").execute(&pool).await;
+    let _ = sqlx::query("ALTER TABLE analysis_reports ADD COLUMN IF NOT EXISTS hallucination_score INTEGER").execute(&pool).await;
 
     // Enforce UNIQUE constraint on task_id for existing tables
     // 1. Clean up duplicates (keep most recent)
@@ -2677,48 +3955,101 @@ async fn init_db() -> Pool<Postgres> {
     pool
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 struct HistoryQuery {
     task_id: String,
     search: Option<String>,
+    /// Drop events the agent scored below this (0-100).
+    min_severity: Option<i32>,
+    /// Keep command lines and script blocks scored at least this obfuscated (0-100).
+    min_obfuscation: Option<i32>,
+    /// Comma-separated event types to keep.
+    event_type: Option<String>,
+    /// Event timestamp bounds in epoch milliseconds.
+    from: Option<i64>,
+    to: Option<i64>,
+    /// Also return events the current noise filters match (hidden by default).
+    include_noise: Option<bool>,
+    /// timestamp (default) or severity.
+    sort: Option<String>,
+    /// asc or desc; defaults to asc for timestamp, desc for severity.
+    order: Option<String>,
+    /// Without limit or offset every matching event is returned.
+    limit: Option<i64>,
+    offset: Option<i64>,
 }
 
+/// A task's events (one page of them when paged) as a JSON array; the unpaged match count is in `X-Total-Count`.
+#[utoipa::path(tag = "telemetry", responses((status = 200, description = "Success")))]
 #[get("/vms/telemetry/history")]
 async fn get_telemetry_history(
     query: web::Query<HistoryQuery>,
     pool_data: web::Data<Pool<Postgres>>,
 ) -> impl Responder {
-    let task_id = &query.task_id;
     let pool = pool_data.get_ref();
+    let (limit, offset) = page_bounds(query.limit, query.offset, 2000, 10000);
+    let paged = query.limit.is_some() || query.offset.is_some();
+
+    let push_filters = |qb: &mut sqlx::QueryBuilder<'_, Postgres>| {
+        qb.push(" WHERE task_id = ").push_bind(query.task_id.clone());
+        if let Some(search) = query.search.as_ref().filter(|s| !s.is_empty()) {
+            // Same expression as idx_events_search so the GIN index is used
+            qb.push(" AND to_tsvector('english', process_name || ' ' || details || ' ' || COALESCE(decoded_details, '')) @@ websearch_to_tsquery('english', ")
+                .push_bind(search.clone()).push(")");
+        }
+        if let Some(min) = query.min_severity {
+            qb.push(" AND COALESCE(severity, 0) >= ").push_bind(min);
+        }
+        if let Some(min) = query.min_obfuscation {
+            qb.push(" AND obfuscation_score >= ").push_bind(min);
+        }
+        if let Some(types) = &query.event_type {
+            let types: Vec<String> = types.split(',').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect();
+            qb.push(" AND event_type = ANY(").push_bind(types).push(")");
+        }
+        if let Some(from) = query.from {
+            qb.push(" AND timestamp >= ").push_bind(from);
+        }
+        if let Some(to) = query.to {
+            qb.push(" AND timestamp <= ").push_bind(to);
+        }
+        if !query.include_noise.unwrap_or(false) {
+            noise_filters::push_exclusion(qb);
+        }
+    };
 
-    let rows = if let Some(search_term) = &query.search {
-        if search_term.is_empty() {
-             sqlx::query_as::<_, RawAgentEvent>(
-                "SELECT * FROM events WHERE task_id = $1 ORDER BY timestamp ASC"
-            )
-            .bind(task_id)
-            .fetch_all(pool)
-            .await
-        } else {
-            sqlx::query_as::<_, RawAgentEvent>(
-                "SELECT * FROM events WHERE task_id = $1 AND to_tsvector('english', process_name || ' ' || details) @@ websearch_to_tsquery('english', $2) ORDER BY timestamp ASC"
-            )
-            .bind(task_id)
-            .bind(search_term)
-            .fetch_all(pool)
-            .await
+    let mut count_qb = sqlx::QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM events");
+    push_filters(&mut count_qb);
+    let total: i64 = match count_qb.build_query_scalar().fetch_one(pool).await {
+        Ok(n) => n,
+        Err(e) => {
+            eprintln!("History count error: {}", e);
+            return HttpResponse::InternalServerError().body(e.to_string());
         }
-    } else {
-        sqlx::query_as::<_, RawAgentEvent>(
-            "SELECT * FROM events WHERE task_id = $1 ORDER BY timestamp ASC"
-        )
-        .bind(task_id)
-        .fetch_all(pool)
-        .await
     };
 
-    match rows {
-        Ok(events) => HttpResponse::Ok().json(events),
+    let mut qb = sqlx::QueryBuilder::<Postgres>::new("SELECT * FROM events");
+    push_filters(&mut qb);
+    let (sort, order) = match query.order.as_deref() {
+        Some("severity") => (Some("severity"), None),
+        order => (query.sort.as_deref(), order),
+    };
+    let order_by = match (sort, order) {
+        (Some("severity"), Some("asc")) => " ORDER BY COALESCE(severity, 0) ASC, timestamp ASC, id",
+        (Some("severity"), _) => " ORDER BY COALESCE(severity, 0) DESC, timestamp ASC, id",
+        (_, Some("desc")) => " ORDER BY timestamp DESC, id DESC",
+        _ => " ORDER BY timestamp ASC, id",
+    };
+    qb.push(order_by);
+    if paged {
+        qb.push(" LIMIT ").push_bind(limit).push(" OFFSET ").push_bind(offset);
+    }
+
+    match qb.build_query_as::<RawAgentEvent>().fetch_all(pool).await {
+        Ok(events) => HttpResponse::Ok()
+            .insert_header(("X-Total-Count", total.to_string()))
+            .json(events),
         Err(e) => {
             eprintln!("History fetch error: {}", e);
             HttpResponse::InternalServerError().body(e.to_string())
@@ -2727,6 +4058,210 @@ async fn get_telemetry_history(
 }
 
 
+// Every REST route; mounted under /api/v1 and, for compatibility, at the root.
+fn api_routes(cfg: &mut web::ServiceConfig) {
+    cfg
+        .service(health::health_check)
+        .service(health::readiness)
+        .service(auth::whoami)
+        .service(auth::issue_token)
+        .service(auth::list_users)
+        .service(auth::create_user)
+        .service(auth::set_user_role)
+        .service(auth::list_api_keys)
+        .service(auth::create_api_key)
+        .service(auth::revoke_api_key)
+        .service(list_all_vms)
+        .service(vm_control)
+        .service(vm_revert)
+        .service(vm_snapshots)
+        .service(vnc_proxy)
+        .service(vnc_websocket)
+        .service(spice_proxy)
+        .service(spice_websocket)
+        .service(terminate_process)
+        .service(exec_url)
+        .service(ai_insight_handler)
+        .service(chat_handler)
+        .service(list_tasks)
+        .service(delete_task)
+        .service(purge_all)
+        .service(pivot_binary)
+        .service(pivot_upload)
+        .service(exec_binary)
+        .service(set_defenses)
+        .service(submit_sample)
+        .service(upload_screenshot)
+        .service(list_screenshots)
+        .service(upload_memory_dump)
+        .service(list_memory_dumps)
+        .service(download_memory_dump)
+        .service(ghidra_analyze)
+        .service(ghidra_functions)
+        .service(ghidra_decompile)
+        .service(ghidra_ingest)
+        .service(ghidra_ingest_complete)
+        .service(ghidra_list_scripts)
+        .service(ghidra_run_script)
+        .service(get_ghidra_findings)
+        .service(get_ai_report)
+        .service(trigger_task_analysis)
+        .service(get_telemetry_history)
+        .service(update_task_verdict)
+        .service(generate_pdf_report)
+        .service(notes::add_note)
+        .service(notes::get_notes)
+        .service(notes::add_tag)
+        .service(notes::get_tags)
+        .service(noise_filters::list_noise_filters)
+        .service(noise_filters::add_noise_filter)
+        .service(noise_filters::delete_noise_filter)
+        .service(severity_rules::list_severity_rules)
+        .service(severity_rules::add_severity_rule)
+        .service(severity_rules::delete_severity_rule)
+        .service(agent_updates::upload_release)
+        .service(agent_updates::list_releases)
+        .service(agent_updates::push_update)
+        .service(set_ai_config)
+        .service(get_ai_config)
+        .service(set_ai_mode)
+        .service(get_ai_mode_handler)
+        .service(ai_usage::get_ai_usage)
+        .service(ai_usage::get_task_ai_usage)
+        .service(action_manager::list_task_actions)
+        .service(action_manager::approve_action)
+        .service(action_manager::reject_action)
+        .service(task_queue::list_queue)
+        .service(task_queue::cancel_task)
+        .service(task_queue::rerun_task)
+        .service(comparison::get_comparison)
+        .service(batch::submit_batch)
+        .service(dedup::lookup_hash)
+        .service(analysis_profiles::list_profiles)
+        .service(analysis_profiles::get_profile)
+        .service(analysis_profiles::save_profile)
+        .service(analysis_profiles::delete_profile)
+        .service(suricata::list_network_alerts)
+        .service(suricata::upload_pcap)
+        .service(email::get_email_analysis)
+        .service(volatility::get_memory_analysis)
+        .service(volatility::dump_memory)
+        .service(process_tree::get_process_tree)
+        .service(timeline::get_timeline)
+        .service(network_graph::get_network_graph)
+        .service(iocs::get_iocs)
+        .service(stix::export_stix)
+        .service(misp::export_misp)
+        .service(event_export::export_events)
+        .service(metrics::get_metrics)
+        .service(audit::list_audit_log)
+        .service(retention::get_retention)
+        .service(retention::run_retention)
+        .service(storage::presign)
+        .service(storage::migrate)
+        .service(sample_download::download_sample)
+        .service(sample_download::guest_sample)
+        .service(screenshots::get_task_screenshots)
+        .service(screenshots::get_screenshot_file)
+        .service(sandbox_pool::list_pool)
+        .service(sandbox_pool::register_pool_vm)
+        .service(golden_image::build_golden_image)
+        .service(golden_image::delete_golden_image)
+        .service(golden_image::list_golden_image_jobs)
+        .service(golden_image::get_golden_image_job)
+        .service(sandbox_pool::update_pool_vm)
+        .service(sandbox_pool::remove_pool_vm)
+        .service(schedules::list_schedules)
+        .service(schedules::create_schedule)
+        .service(schedules::get_schedule)
+        .service(schedules::update_schedule)
+        .service(schedules::delete_schedule)
+        .service(schedules::run_schedule_now)
+        .service(url_analysis::upload_url_artifacts)
+        .service(url_analysis::get_url_artifacts)
+        .service(url_analysis::download_url_payload)
+        .service(url_analysis::download_url_artifact)
+        .service(chunked_upload::create_upload)
+        .service(chunked_upload::get_upload)
+        .service(chunked_upload::upload_chunk)
+        .service(chunked_upload::complete_upload)
+        .service(chunked_upload::cancel_upload)
+        .service(bundle::export_bundle)
+        .service(bundle::import_bundle)
+        .service(relationships::get_relationships)
+        .service(relationships::get_family)
+        .service(relationships::create_relationship)
+        .service(relationships::delete_relationship)
+        .service(kill_chain::get_kill_chain)
+        .service(sigma::list_sigma_rules)
+        .service(sigma::add_sigma_rule)
+        .service(sigma::update_sigma_rule)
+        .service(sigma::delete_sigma_rule)
+        .service(alerts::get_task_alerts)
+        .service(alerts::list_alerts)
+        .service(alerts::bulk_alert_action)
+        .service(alerts::acknowledge_alert)
+        .service(alerts::close_alert)
+        .service(alerts::reopen_alert)
+        .service(scoring::get_task_score)
+        .service(scoring::recompute_task_score)
+        .service(ioc_correlation::get_correlations)
+        .service(ioc_correlation::recompute_correlations)
+        .service(ioc_correlation::get_sightings)
+        .service(beaconing::get_beacons)
+        .service(dns_reputation::get_task_dns)
+        .service(dns_reputation::list_dns_blocklist)
+        .service(dns_reputation::add_dns_blocklist)
+        .service(dns_reputation::delete_dns_blocklist)
+        .service(lineage::list_lineage_rules)
+        .service(lineage::add_lineage_rule)
+        .service(lineage::update_lineage_rule)
+        .service(lineage::delete_lineage_rule)
+        .service(decoder::redecode_task)
+        .service(mitre::get_task_mitre)
+        .service(mitre::get_campaign_mitre)
+        .service(obfuscation::get_obfuscation)
+        .service(yara::get_yara_matches)
+        .service(yara::rescan_task)
+        .service(yara::list_yara_rules)
+        .service(yara::add_yara_rule)
+        .service(yara::update_yara_rule)
+        .service(yara::delete_yara_rule)
+        .service(rule_drafts::generate_yara)
+        .service(rule_drafts::generate_sigma)
+        .service(rule_drafts::list_sigma_drafts)
+        .service(rule_drafts::export_sigma)
+        .service(prompts::list_prompts)
+        .service(prompts::prompt_stats)
+        .service(prompts::add_prompt_version)
+        .service(prompts::update_prompt_version)
+        .service(chat_sessions::list_task_sessions)
+        .service(chat_sessions::list_sessions)
+        .service(chat_sessions::get_session)
+        .service(chat_sessions::delete_session)
+        .service(static_properties::get_static_properties)
+        .service(static_properties::reanalyze_task)
+        .service(static_properties::download_static_artifact)
+        .route("/ws", web::get().to(stream::ws_route))
+        .route("/ws/progress", web::get().to(progress_stream::ws_progress_route))
+        .configure(detox_routes);
+}
+
+fn detox_routes(cfg: &mut web::ServiceConfig) {
+    cfg
+        .service(detox_api::detox_dashboard)
+        .service(detox_api::detox_extensions)
+        .service(detox_api::detox_extension_detail)
+        .service(detox_api::detox_trigger_scan)
+        .service(detox_api::detox_trigger_scrape)
+        .service(detox_api::detox_trigger_scan_pending)
+        .service(detox_api::detox_blocklist)
+        .service(detox_api::detox_submit_sandbox)
+        .service(detox_api::detox_delete_extension)
+        .service(detox_api::detox_purge_all)
+        .service(detox_api::detox_kill_processing);
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
@@ -2735,6 +4270,8 @@ async fn main() -> std::io::Result<()> {
     // Ensure uploads directory exists
     std::fs::create_dir_all("./uploads")?;
     std::fs::create_dir_all("./screenshots")?;
+    std::fs::create_dir_all("./memory_dumps")?;
+    std::fs::create_dir_all("./agent_releases")?;
 
     let pool = init_db().await;
     
@@ -2742,20 +4279,119 @@ async fn main() -> std::io::Result<()> {
     if let Err(e) = virustotal::init_db(&pool).await {
         println!("[VIRUSTOTAL] Failed to initialize VT cache: {}", e);
     }
+
+    if let Err(e) = noise_filters::init_db(&pool).await {
+        println!("[NOISE] Failed to initialize noise filter table: {}", e);
+    }
+    if let Err(e) = auth::init_db(&pool).await {
+        println!("[AUTH] Failed to initialize auth tables: {}", e);
+    }
+    if !auth::auth_required() {
+        println!("[AUTH] WARNING: AUTH_REQUIRED=false - every endpoint is reachable without credentials.");
+    }
+    if let Err(e) = severity_rules::init_db(&pool).await {
+        println!("[SEVERITY] Failed to initialize severity rule table: {}", e);
+    }
+
+    if let Err(e) = agent_updates::init_db(&pool).await {
+        println!("[AGENT_UPDATE] Failed to initialize release table: {}", e);
+    }
+    if let Err(e) = sandbox_pool::init_db(&pool).await {
+        println!("[POOL] Failed to initialize sandbox pool: {}", e);
+    }
+    if let Err(e) = task_queue::init_db(&pool).await {
+        println!("[QUEUE] Failed to initialize task queue: {}", e);
+    }
+    if let Err(e) = analysis_profiles::init_db(&pool).await {
+        println!("[PROFILES] Failed to initialize analysis profiles: {}", e);
+    }
+    if let Err(e) = network_policy::init_db(&pool).await {
+        println!("[NETPOLICY] Failed to initialize internet policy column: {}", e);
+    }
+    if let Err(e) = suricata::init_db(&pool).await {
+        println!("[SURICATA] Failed to initialize network alerts: {}", e);
+    }
+    if let Err(e) = archive::init_db(&pool).await {
+        println!("[ARCHIVE] Failed to initialize container column: {}", e);
+    }
+    if let Err(e) = email::init_db(&pool).await {
+        println!("[EMAIL] Failed to initialize email analyses: {}", e);
+    }
+    if let Err(e) = volatility::init_db(&pool).await {
+        println!("[VOLATILITY] Failed to initialize memory analyses: {}", e);
+    }
+    if let Err(e) = golden_image::init_db(&pool).await {
+        println!("[GOLDEN] Failed to initialize golden image jobs: {}", e);
+    }
+    if let Err(e) = misp::init_db(&pool).await {
+        println!("[MISP] Failed to initialize MISP exports: {}", e);
+    }
+    if let Err(e) = audit::init_db(&pool).await {
+        println!("[AUDIT] Failed to initialize audit log: {}", e);
+    }
+    if let Err(e) = retention::init_db(&pool).await {
+        println!("[RETENTION] Failed to initialize retention columns: {}", e);
+    }
+    if let Err(e) = screenshots::init_db(&pool).await {
+        println!("[SCREENSHOTS] Failed to initialize screenshot index: {}", e);
+    }
+    if let Err(e) = action_manager::init_db(&pool).await {
+        println!("[ACTION] Failed to initialize ai_actions table: {}", e);
+    }
+    if let Err(e) = ai_usage::init_db(&pool).await {
+        println!("[AI] Failed to initialize AI usage table: {}", e);
+    }
+    if let Err(e) = prompts::init_db(&pool).await {
+        println!("[AI] Failed to initialize prompt_templates table: {}", e);
+    }
+    if let Err(e) = chat_sessions::init_db(&pool).await {
+        println!("[CHAT] Failed to initialize chat session tables: {}", e);
+    }
+    if let Err(e) = url_analysis::init_db(&pool).await {
+        println!("[URL] Failed to initialize url_artifacts table: {}", e);
+    }
+    if let Err(e) = chunked_upload::init_db(&pool).await {
+        println!("[UPLOAD] Failed to initialize upload_sessions table: {}", e);
+    }
+    if let Err(e) = schedules::init_db(&pool).await {
+        println!("[SCHEDULE] Failed to initialize schedules table: {}", e);
+    }
+    if let Err(e) = relationships::init_db(&pool).await {
+        println!("[RELATIONSHIPS] Failed to initialize task_relationships table: {}", e);
+    }
+    if let Err(e) = alerts::init_db(&pool).await {
+        println!("[ALERTS] Failed to initialize alerts table: {}", e);
+    }
+    if let Err(e) = sigma::init_db(&pool).await {
+        println!("[SIGMA] Failed to initialize sigma_rules table: {}", e);
+    }
+    if let Err(e) = rule_drafts::init_db(&pool).await {
+        println!("[SIGMA] Failed to initialize sigma_drafts table: {}", e);
+    }
+    if let Err(e) = scoring::init_db(&pool).await {
+        println!("[SCORING] Failed to initialize task_scores table: {}", e);
+    }
+    if let Err(e) = ioc_correlation::init_db(&pool).await {
+        println!("[CORRELATION] Failed to initialize ioc_sightings table: {}", e);
+    }
+    if let Err(e) = dns_reputation::init_db(&pool).await {
+        println!("[DNS] Failed to initialize dns_blocklist table: {}", e);
+    }
+    if let Err(e) = lineage::init_db(&pool).await {
+        println!("[LINEAGE] Failed to initialize lineage_rules table: {}", e);
+    }
+    if let Err(e) = yara::init_db(&pool).await {
+        println!("[YARA] Failed to initialize yara tables: {}", e);
+    }
+    if let Err(e) = static_properties::init_db(&pool).await {
+        println!("[STATIC] Failed to initialize static_properties table: {}", e);
+    }
+    siem::start();
     
     let pool_data = web::Data::new(pool.clone());
 
-    let proxmox_url = env::var("PROXMOX_URL").expect("PROXMOX_URL must be set");
-    let proxmox_user = env::var("PROXMOX_USER").expect("PROXMOX_USER must be set");
-    let proxmox_token_id = env::var("PROXMOX_TOKEN_ID").expect("PROXMOX_TOKEN_ID must be set");
-    let proxmox_token_secret = env::var("PROXMOX_TOKEN_SECRET").expect("PROXMOX_TOKEN_SECRET must be set");
-
-    let client = proxmox::ProxmoxClient::new(
-        proxmox_url,
-        proxmox_user,
-        proxmox_token_id,
-        proxmox_token_secret,
-    );
+    let client = hypervisor::from_env();
+    let client_data: web::Data<dyn Hypervisor> = web::Data::from(client.clone());
 
     let broadcaster = Arc::new(stream::Broadcaster::new());
     let broadcaster_data = web::Data::new(broadcaster.clone());
@@ -2772,21 +4408,40 @@ async fn main() -> std::io::Result<()> {
     let anthropic_key = env::var("ANTHROPIC_API_KEY").unwrap_or_default();
     let openai_key = env::var("OPENAI_API_KEY").unwrap_or_default();
     let copilot_token = env::var("COPILOT_TOKEN").unwrap_or_default();
+    let azure_openai_key = env::var("AZURE_OPENAI_API_KEY").unwrap_or_default();
+    let azure_openai_endpoint = env::var("AZURE_OPENAI_ENDPOINT").unwrap_or_default();
 
     println!("[Main] Initializing AI Manager...");
     println!("[Main] OLLAMA_URL: {}", ollama_url);
     if !gemini_api_key.is_empty() { println!("[Main] Gemini API Key detected."); }
     if !anthropic_key.is_empty() { println!("[Main] Anthropic API Key detected."); }
     if !openai_key.is_empty() { println!("[Main] OpenAI API Key detected."); }
+    if !azure_openai_key.is_empty() { println!("[Main] Azure OpenAI API Key detected ({}).", azure_openai_endpoint); }
     
     let ai_manager = web::Data::new(AIManager::new(
         gemini_api_key, 
         ollama_url,
         anthropic_key,
         openai_key,
-        copilot_token
+        copilot_token,
+        azure_openai_key,
+        azure_openai_endpoint,
+    ).with_usage_log(pool.clone()));
+
+    let scheduler = Arc::new(task_queue::TaskScheduler::new(
+        client.clone(),
+        agent_manager.clone(),
+        pool.clone(),
+        ai_manager.get_ref().clone(),
+        progress_broadcaster.clone(),
     ));
+    let scheduler_data = web::Data::new(scheduler.clone());
+    // Orchestration futures are !Send, so the scheduler lives on the main arbiter
+    actix_web::rt::spawn(scheduler.clone().run());
+    actix_web::rt::spawn(schedules::start(pool.clone(), scheduler.clone()));
 
+    tokio::spawn(retention::start(pool.clone()));
+    tokio::spawn(chunked_upload::start(pool.clone()));
     tokio::spawn(start_tcp_listener(broadcaster, agent_manager, pool));
 
     // --- Background Extension Auto-Discovery ---
@@ -2803,7 +4458,8 @@ async fn main() -> std::io::Result<()> {
                 "sort_by": "PublishedDate"
             });
             let _ = client
-                .post("http://127.0.0.1:8080/api/detox/scrape")
+                .post("http://127.0.0.1:8080/api/v1/detox/scrape")
+                .header("X-API-Key", auth::internal_token())
                 .json(&payload)
                 .send()
                 .await;
@@ -2813,7 +4469,8 @@ async fn main() -> std::io::Result<()> {
                 "limit": 20
             });
             let _ = client
-                .post("http://127.0.0.1:8080/api/detox/scan-pending")
+                .post("http://127.0.0.1:8080/api/v1/detox/scan-pending")
+                .header("X-API-Key", auth::internal_token())
                 .json(&scan_payload)
                 .send()
                 .await;
@@ -2824,78 +4481,73 @@ async fn main() -> std::io::Result<()> {
 
     use actix_cors::Cors;
 
-    HttpServer::new(move || {
-        let cors = Cors::permissive();
+    let api_doc = <openapi::ApiDoc as utoipa::OpenApi>::openapi();
+
+    // Cross-origin callers must be listed in CORS_ALLOWED_ORIGINS (comma-separated); none by default
+    let cors_origins: Vec<String> = env::var("CORS_ALLOWED_ORIGINS").unwrap_or_default()
+        .split(',')
+        .map(|o| o.trim().trim_end_matches('/').to_string())
+        .filter(|o| !o.is_empty())
+        .collect();
+    println!("[CORS] Allowed origins: {}", if cors_origins.is_empty() { "none (same-origin only)".to_string() } else { cors_origins.join(", ") });
+
+    let server = HttpServer::new(move || {
+        let cors = cors_origins.iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+            .allow_any_method()
+            .allow_any_header()
+            .expose_any_header()
+            .max_age(3600);
 
         App::new()
+            .wrap(actix_web::middleware::from_fn(audit::record))
+            .wrap(actix_web::middleware::from_fn(limits::rate_limit))
+            .wrap(actix_web::middleware::from_fn(auth::require_auth))
+            .wrap(actix_web::middleware::from_fn(limits::ip_rate_limit))
             .wrap(actix_web::middleware::Logger::default())
             .wrap(cors)
-            .app_data(web::Data::new(client.clone()))
+            .app_data(client_data.clone())
             .app_data(broadcaster_data.clone())
             .app_data(agent_manager_data.clone())
             .app_data(pool_data.clone())
             .app_data(ai_manager.clone()) // AI Manager
             .app_data(progress_broadcaster_data.clone())
-            .service(health_check)
-            .service(list_all_vms)
-            .service(vm_control)
-            .service(vm_revert)
-            .service(vnc_proxy)
-            .service(vnc_websocket)
-            .service(spice_proxy)
-            .service(spice_websocket)
-            .service(terminate_process)
-            .service(exec_url)
-            .service(ai_insight_handler)
-            .service(chat_handler)
-            .service(list_tasks)
-            .service(delete_task)
-            .service(purge_all)
-            .service(pivot_binary)
-            .service(pivot_upload)
-            .service(exec_binary)
-            .service(submit_sample)
-            .service(upload_screenshot)
-            .service(list_screenshots)
-            .service(ghidra_analyze)
-            .service(ghidra_functions)
-            .service(ghidra_decompile)
-            .service(ghidra_ingest)
-            .service(ghidra_ingest_complete)
-            .service(ghidra_list_scripts)
-            .service(ghidra_run_script)
-            .service(get_ghidra_findings)
-            .service(get_ai_report)
-            .service(trigger_task_analysis)
-            .service(get_telemetry_history)
-            .service(update_task_verdict)
-            .service(generate_pdf_report)
-            .service(notes::add_note)
-            .service(notes::get_notes)
-            .service(notes::add_tag)
-            .service(notes::get_tags)
-            .service(actix_files::Files::new("/uploads", "./uploads").show_files_listing())
-            .service(actix_files::Files::new("/screenshots", "./screenshots").show_files_listing())
-            .service(set_ai_config)
-            .service(get_ai_config)
-            .service(set_ai_mode)
-            .service(get_ai_mode_handler)
-            .service(detox_api::detox_dashboard)
-            .service(detox_api::detox_extensions)
-            .service(detox_api::detox_extension_detail)
-            .service(detox_api::detox_trigger_scan)
-            .service(detox_api::detox_trigger_scrape)
-            .service(detox_api::detox_trigger_scan_pending)
-            .service(detox_api::detox_blocklist)
-            .service(detox_api::detox_submit_sandbox)
-            .service(detox_api::detox_delete_extension)
-            .service(detox_api::detox_purge_all)
-            .service(detox_api::detox_kill_processing)
-            .service(actix_files::Files::new("/vsix_archive", "/vsix_archive").show_files_listing())
-            .route("/ws", web::get().to(stream::ws_route))
-            .route("/ws/progress", web::get().to(progress_stream::ws_progress_route))
+            .app_data(scheduler_data.clone())
+            .service(openapi::swagger_ui(api_doc.clone()))
+            .service(actix_files::Files::new("/agent_releases", "./agent_releases"))
+            .service(actix_files::Files::new("/vsix_archive", "/vsix_archive"))
+            .service(web::scope(api_version::PREFIX).configure(api_routes))
+            // Pre-versioning paths, same handlers; /api/detox has to go first or the flat scope swallows it
+            .service(web::scope(api_version::LEGACY_DETOX_PREFIX).wrap(actix_web::middleware::from_fn(api_version::deprecated)).configure(detox_routes))
+            .service(web::scope("").wrap(actix_web::middleware::from_fn(api_version::deprecated)).configure(api_routes))
     })
     .bind(("0.0.0.0", 8080))?
-    .run()
-    .await
+    // Signals are handled below so in-flight analyses are wound down before the server stops
+    .disable_signals()
+    .run();
+
+    let handle = server.handle();
+    actix_web::rt::spawn(async move {
+        shutdown_signal().await;
+        scheduler.shutdown().await;
+        handle.stop(true).await;
+    });
+    server.await
+}
+
+// SIGTERM (docker stop, redeploys) or Ctrl-C.
+async fn shutdown_signal() {
+    let mut term = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+        Ok(term) => term,
+        Err(e) => {
+            println!("[Main] Warning: cannot listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = term.recv() => {}
+    }
+    println!("[Main] Shutdown signal received.");
 }
@@ -0,0 +1,145 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use sqlx::{Pool, Postgres};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, OnceLock};
+use crate::AgentManager;
+
+// --- PROMETHEUS METRICS ---
+
+/// Upper bounds (seconds) shared by every histogram; covers DB inserts through long AI calls.
+const BUCKETS: &[f64] = &[0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 15.0, 60.0, 300.0, 900.0];
+
+/// (name, type, help) for everything recorded in-process.
+const FAMILIES: &[(&str, &str, &str)] = &[
+    ("voodoobox_tasks_queued_total", "counter", "Analyses accepted into the sandbox queue."),
+    ("voodoobox_tasks_finished_total", "counter", "Analyses that ended, by outcome (completed, failed, cancelled)."),
+    ("voodoobox_stage_duration_seconds", "histogram", "Time spent in each orchestration stage."),
+    ("voodoobox_events_ingested_total", "counter", "Telemetry events stored, by source."),
+    ("voodoobox_events_rolled_up_total", "counter", "Repeated agent events counted into an earlier row instead of stored."),
+    ("voodoobox_db_insert_seconds", "histogram", "Latency of agent event batch inserts."),
+    ("voodoobox_ai_request_seconds", "histogram", "AI provider call latency, by provider and outcome."),
+    ("voodoobox_ai_tokens_total", "counter", "AI tokens by provider and direction (estimated at 4 characters per token)."),
+    ("voodoobox_ai_fallbacks_total", "counter", "AI calls handed to the next provider of the fallback chain, by failed and next provider."),
+    ("voodoobox_ai_tool_calls_total", "counter", "Tool calls made by the AI chat, by tool."),
+    ("voodoobox_ai_report_parse_total", "counter", "AI forensic reports by how their JSON was read: direct, repaired or salvaged."),
+    ("voodoobox_ai_unverified_claims_total", "counter", "Claims in AI forensic reports with no support in the task's telemetry, by kind."),
+    ("voodoobox_rate_limited_total", "counter", "Requests refused with 429, by scope (ip or key)."),
+    ("voodoobox_ws_dropped_total", "counter", "Live events skipped for /ws clients that fell behind."),
+];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Default)]
+struct Registry {
+    counters: BTreeMap<(&'static str, String), f64>,
+    histograms: BTreeMap<(&'static str, String), Histogram>,
+}
+
+fn registry() -> &'static Mutex<Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(Registry::default()))
+}
+
+fn label_set(labels: &[(&str, &str)]) -> String {
+    labels.iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, v.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+pub fn inc(name: &'static str, labels: &[(&str, &str)], by: f64) {
+    if let Ok(mut r) = registry().lock() {
+        *r.counters.entry((name, label_set(labels))).or_default() += by;
+    }
+}
+
+pub fn observe(name: &'static str, labels: &[(&str, &str)], seconds: f64) {
+    if let Ok(mut r) = registry().lock() {
+        let h = r.histograms.entry((name, label_set(labels))).or_default();
+        if h.buckets.is_empty() {
+            h.buckets = vec![0; BUCKETS.len()];
+        }
+        for (i, bound) in BUCKETS.iter().enumerate() {
+            if seconds <= *bound {
+                h.buckets[i] += 1;
+            }
+        }
+        h.sum += seconds;
+        h.count += 1;
+    }
+}
+
+/// Rough token count for providers that do not report usage.
+pub fn estimate_tokens(text: &str) -> f64 {
+    (text.chars().count() as f64 / 4.0).ceil()
+}
+
+fn series(name: &str, labels: &str, extra: Option<String>) -> String {
+    let all: Vec<String> = [(!labels.is_empty()).then(|| labels.to_string()), extra].into_iter().flatten().collect();
+    if all.is_empty() { name.to_string() } else { format!("{}{{{}}}", name, all.join(",")) }
+}
+
+fn render_registry(out: &mut String) {
+    let Ok(r) = registry().lock() else { return };
+    for (family, kind, help) in FAMILIES {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}", family, help, family, kind);
+        for ((name, labels), value) in r.counters.iter().filter(|((n, _), _)| n == family) {
+            let _ = writeln!(out, "{} {}", series(name, labels, None), value);
+        }
+        for ((name, labels), h) in r.histograms.iter().filter(|((n, _), _)| n == family) {
+            for (bound, count) in BUCKETS.iter().zip(&h.buckets) {
+                let _ = writeln!(out, "{} {}", series(&format!("{}_bucket", name), labels, Some(format!("le=\"{}\"", bound))), count);
+            }
+            let _ = writeln!(out, "{} {}", series(&format!("{}_bucket", name), labels, Some("le=\"+Inf\"".to_string())), h.count);
+            let _ = writeln!(out, "{} {}", series(&format!("{}_sum", name), labels, None), h.sum);
+            let _ = writeln!(out, "{} {}", series(&format!("{}_count", name), labels, None), h.count);
+        }
+    }
+}
+
+async fn gauge_by(out: &mut String, pool: &Pool<Postgres>, name: &str, help: &str, label: &str, sql: &str) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge", name, help, name);
+    let rows: Vec<(Option<String>, i64)> = sqlx::query_as(sql).fetch_all(pool).await.unwrap_or_default();
+    for (value, count) in rows {
+        let _ = writeln!(out, "{} {}", series(name, &label_set(&[(label, value.as_deref().unwrap_or("unknown"))]), None), count);
+    }
+}
+
+#[utoipa::path(tag = "admin", responses((status = 200, description = "Success")))]
+#[get("/metrics")]
+pub async fn get_metrics(
+    pool: web::Data<Pool<Postgres>>,
+    manager: web::Data<Arc<AgentManager>>,
+) -> impl Responder {
+    let pool = pool.get_ref();
+    let mut out = String::new();
+    render_registry(&mut out);
+
+    gauge_by(&mut out, pool, "voodoobox_queue_depth", "Sandbox queue entries by state.", "state",
+        "SELECT state, COUNT(*) FROM task_queue GROUP BY state").await;
+    gauge_by(&mut out, pool, "voodoobox_tasks", "Tasks by status.", "status",
+        "SELECT status, COUNT(*) FROM tasks GROUP BY status").await;
+    gauge_by(&mut out, pool, "voodoobox_static_jobs_running", "Tasks with a static analysis worker still busy.", "worker",
+        "SELECT 'ghidra', COUNT(*) FROM tasks WHERE ghidra_status = 'Analysis Running'
+         UNION ALL SELECT 'remnux', COUNT(*) FROM tasks WHERE remnux_status IN ('Staging File', 'Analyzing')").await;
+    gauge_by(&mut out, pool, "voodoobox_sandbox_pool_vms", "Pool VMs by state.", "state",
+        "SELECT state, COUNT(*) FROM sandbox_pool GROUP BY state").await;
+
+    let (sessions, busy) = {
+        let sessions = manager.sessions.lock().await;
+        (sessions.len(), sessions.values().filter(|s| s.active_task_id.is_some()).count())
+    };
+    let _ = writeln!(out, "# HELP voodoobox_agent_sessions Connected in-guest agents.\n# TYPE voodoobox_agent_sessions gauge");
+    let _ = writeln!(out, "voodoobox_agent_sessions{{bound=\"task\"}} {}", busy);
+    let _ = writeln!(out, "voodoobox_agent_sessions{{bound=\"idle\"}} {}", sessions - busy);
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(out)
+}
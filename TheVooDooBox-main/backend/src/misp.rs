@@ -0,0 +1,229 @@
+use actix_web::{post, web, HttpResponse, Responder};
+use serde_json::{json, Value};
+use sqlx::{Pool, Postgres};
+use crate::ai_analysis::Verdict;
+use crate::iocs::Ioc;
+
+// --- MISP CONNECTOR ---
+
+struct Config {
+    url: String,
+    api_key: String,
+    verify_tls: bool,
+    tlp: String,
+    distribution: u8,
+    min_confidence: String,
+    report_link: Option<String>,
+}
+
+fn config() -> Option<Config> {
+    let url = std::env::var("MISP_URL").ok().filter(|v| !v.trim().is_empty())?;
+    let api_key = std::env::var("MISP_API_KEY").ok().filter(|v| !v.trim().is_empty())?;
+    Some(Config {
+        url: url.trim().trim_end_matches('/').to_string(),
+        api_key: api_key.trim().to_string(),
+        verify_tls: std::env::var("MISP_VERIFY_TLS").map(|v| v != "false" && v != "0").unwrap_or(true),
+        tlp: std::env::var("MISP_TLP").unwrap_or_else(|_| "tlp:amber".to_string()).trim().to_lowercase(),
+        distribution: std::env::var("MISP_DISTRIBUTION").ok().and_then(|v| v.trim().parse().ok()).filter(|d| *d <= 3).unwrap_or(0),
+        min_confidence: std::env::var("MISP_MIN_CONFIDENCE").unwrap_or_else(|_| "medium".to_string()).trim().to_lowercase(),
+        report_link: std::env::var("MISP_REPORT_LINK").ok().filter(|v| !v.trim().is_empty()),
+    })
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS misp_exports (
+            task_id TEXT PRIMARY KEY,
+            event_id TEXT NOT NULL,
+            event_uuid TEXT NOT NULL,
+            attribute_count INTEGER NOT NULL,
+            pushed_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+fn confidence_rank(confidence: &str) -> u8 {
+    match confidence {
+        "high" => 2,
+        "medium" => 1,
+        _ => 0,
+    }
+}
+
+/// MISP (type, category) for an IOC type.
+fn attribute_type(ioc_type: &str) -> Option<(&'static str, &'static str)> {
+    Some(match ioc_type {
+        "sha256" => ("sha256", "Payload delivery"),
+        "sha1" => ("sha1", "Payload delivery"),
+        "md5" => ("md5", "Payload delivery"),
+        "ip" => ("ip-dst", "Network activity"),
+        "domain" => ("domain", "Network activity"),
+        "url" => ("url", "Network activity"),
+        "mutex" => ("mutex", "Artifacts dropped"),
+        "registry_key" => ("regkey", "Persistence mechanism"),
+        "file_path" => ("filename", "Artifacts dropped"),
+        _ => return None,
+    })
+}
+
+fn attribute(task_id: &str, kind: &str, category: &str, value: &str, to_ids: bool, comment: &str, tlp: &str) -> Value {
+    json!({
+        "uuid": crate::stix::stable_uuid(&format!("misp|{}|{}|{}", task_id, kind, value.to_lowercase())).to_string(),
+        "type": kind,
+        "category": category,
+        "value": value,
+        "to_ids": to_ids,
+        "comment": comment,
+        "Tag": [{ "name": tlp }],
+    })
+}
+
+pub async fn push(pool: &Pool<Postgres>, task_id: &str) -> Result<Value, String> {
+    let config = config().ok_or("MISP is not configured (MISP_URL / MISP_API_KEY)")?;
+
+    let task: Option<(String, String, i64)> = sqlx::query_as(
+        "SELECT original_filename, file_hash, created_at FROM tasks WHERE id = $1"
+    )
+    .bind(task_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    let (filename, file_hash, created_at) = task.ok_or("Task not found")?;
+    let report = crate::ai_analysis::stored_report(pool, task_id).await;
+    let iocs: Vec<Ioc> = crate::iocs::extract(pool, task_id).await.map_err(|e| e.to_string())?;
+
+    let verdict = report.as_ref().map(|r| r.verdict.clone());
+    // Detection flags only where the sample was judged; a benign run still documents what it did
+    let detectable = verdict.as_ref() != Some(&Verdict::Benign);
+    let min = confidence_rank(&config.min_confidence);
+
+    let mut attributes: Vec<Value> = iocs.iter()
+        .filter(|i| confidence_rank(i.confidence) >= min)
+        .filter_map(|i| {
+            let (kind, category) = attribute_type(i.ioc_type)?;
+            let to_ids = detectable && i.confidence == "high" && !matches!(i.ioc_type, "file_path" | "registry_key");
+            let comment = format!("confidence: {}; seen in: {}", i.confidence, i.sources.join(", "));
+            Some(attribute(task_id, kind, category, &i.value, to_ids, &comment, &config.tlp))
+        })
+        .collect();
+    if !file_hash.is_empty() {
+        attributes.push(attribute(task_id, "filename", "Payload delivery", &filename, false, "submitted sample", &config.tlp));
+    }
+    if let Some(r) = &report {
+        attributes.push(attribute(task_id, "text", "External analysis", &r.executive_summary, false, "executive summary", &config.tlp));
+    }
+    if let Some(template) = &config.report_link {
+        let link = template.replace("{task_id}", task_id);
+        attributes.push(attribute(task_id, "link", "External analysis", &link, false, "TheVooDooBox analysis", &config.tlp));
+    }
+
+    let mut tags = vec![json!({ "name": config.tlp })];
+    if let Some(v) = &verdict {
+        tags.push(json!({ "name": format!("voodoobox:verdict=\"{}\"", v.to_string().to_lowercase()) }));
+    }
+    if let Some(family) = report.as_ref().and_then(|r| r.malware_family.as_deref()).filter(|f| !f.trim().is_empty()) {
+        tags.push(json!({ "name": format!("voodoobox:family=\"{}\"", family.trim()) }));
+    }
+    let threat_level = match verdict {
+        Some(Verdict::Malicious) => 1,
+        Some(Verdict::Suspicious) => 2,
+        Some(Verdict::Benign) => 3,
+        None => 4,
+    };
+    let attribute_count = attributes.len();
+    let event = json!({
+        "Event": {
+            "uuid": crate::stix::stable_uuid(&format!("misp|{}|event", task_id)).to_string(),
+            "info": format!("TheVooDooBox: {} ({})", filename, verdict.map(|v| v.to_string()).unwrap_or_else(|| "no verdict".to_string())),
+            "date": chrono::DateTime::from_timestamp_millis(created_at).unwrap_or_default().format("%Y-%m-%d").to_string(),
+            "distribution": config.distribution,
+            "threat_level_id": threat_level,
+            "analysis": 2,
+            "Tag": tags,
+            "Attribute": attributes,
+        }
+    });
+
+    let client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(!config.verify_tls)
+        .timeout(std::time::Duration::from_secs(60))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let existing: Option<String> = sqlx::query_scalar("SELECT event_id FROM misp_exports WHERE task_id = $1")
+        .bind(task_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    let endpoint = match &existing {
+        Some(id) => format!("{}/events/edit/{}", config.url, id),
+        None => format!("{}/events/add", config.url),
+    };
+    let resp = client.post(&endpoint)
+        .header("Authorization", &config.api_key)
+        .header("Accept", "application/json")
+        .json(&event)
+        .send()
+        .await
+        .map_err(|e| format!("MISP request failed: {}", e))?;
+    let status = resp.status();
+    let body: Value = resp.json().await.unwrap_or(Value::Null);
+    if !status.is_success() {
+        return Err(format!("MISP returned {}: {}", status, body));
+    }
+
+    let event_id = body["Event"]["id"].as_str().map(str::to_string)
+        .or_else(|| body["Event"]["id"].as_i64().map(|i| i.to_string()))
+        .or(existing)
+        .ok_or("MISP response did not contain an event id")?;
+    let event_uuid = body["Event"]["uuid"].as_str().unwrap_or_default().to_string();
+    let _ = sqlx::query(
+        "INSERT INTO misp_exports (task_id, event_id, event_uuid, attribute_count, pushed_at)
+         VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (task_id) DO UPDATE SET event_id = EXCLUDED.event_id, event_uuid = EXCLUDED.event_uuid,
+             attribute_count = EXCLUDED.attribute_count, pushed_at = EXCLUDED.pushed_at"
+    )
+    .bind(task_id)
+    .bind(&event_id)
+    .bind(&event_uuid)
+    .bind(attribute_count as i32)
+    .bind(chrono::Utc::now().timestamp_millis())
+    .execute(pool)
+    .await;
+
+    println!("[MISP] Task {} pushed as event {} ({} attributes).", task_id, event_id, attribute_count);
+    Ok(json!({
+        "task_id": task_id,
+        "event_id": event_id,
+        "event_uuid": event_uuid,
+        "attributes": attribute_count,
+        "url": format!("{}/events/view/{}", config.url, event_id),
+    }))
+}
+
+/// Called when a task reaches Completed; a no-op unless MISP_AUTO_PUSH is on.
+pub async fn on_completed(pool: &Pool<Postgres>, task_id: &str) {
+    let auto = std::env::var("MISP_AUTO_PUSH").map(|v| v == "true" || v == "1").unwrap_or(false);
+    if !auto || config().is_none() {
+        return;
+    }
+    if let Err(e) = push(pool, task_id).await {
+        println!("[MISP] Auto-push of task {} failed: {}", task_id, e);
+    }
+}
+
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
+#[post("/tasks/{id}/export/misp")]
+pub async fn export_misp(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    match push(pool.get_ref(), &path.into_inner()).await {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(e) if e == "Task not found" => HttpResponse::NotFound().json(json!({ "error": e })),
+        Err(e) if e.starts_with("MISP is not configured") => HttpResponse::BadRequest().json(json!({ "error": e })),
+        Err(e) => HttpResponse::BadGateway().json(json!({ "error": e })),
+    }
+}
@@ -0,0 +1,136 @@
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use crate::analysis_profiles::AnalysisProfile;
+use crate::fakenet::{connection_owner, read_log_since};
+
+// --- TLS INTERCEPTION ---
+// mitmproxy flows (MITM_FLOWS) become HTTP_REQUEST events.
+
+const MAX_FLOWS_PER_TASK: usize = 2000;
+
+fn flows_path() -> String {
+    std::env::var("MITM_FLOWS").unwrap_or_else(|_| "/var/log/mitmproxy/flows.jsonl".to_string())
+}
+
+pub fn requested(profile: Option<&AnalysisProfile>) -> bool {
+    profile.and_then(|p| p.agent_flag("tls_intercept")).unwrap_or_else(|| {
+        std::env::var("SANDBOX_TLS_INTERCEPT").map(|v| v == "true" || v == "1").unwrap_or(false)
+    })
+}
+
+/// CONFIGURE_MITM for the agent; Err when the proxy is not configured.
+pub fn agent_command() -> Result<String, String> {
+    let proxy = std::env::var("MITM_PROXY").ok().filter(|p| !p.is_empty())
+        .ok_or("TLS interception requested but MITM_PROXY is not set")?;
+    let ca_pem = match std::env::var("MITM_CA_CERT") {
+        Ok(path) => Some(std::fs::read_to_string(&path).map_err(|e| format!("Cannot read MITM_CA_CERT {}: {}", path, e))?),
+        Err(_) => None,
+    };
+    Ok(serde_json::json!({ "command": "CONFIGURE_MITM", "proxy": proxy, "ca_pem": ca_pem }).to_string())
+}
+
+/// Flow log position when the detonation started.
+pub struct FlowCapture {
+    offset: u64,
+}
+
+impl FlowCapture {
+    pub fn begin() -> Self {
+        let offset = std::fs::metadata(flows_path()).map(|m| m.len()).unwrap_or(0);
+        FlowCapture { offset }
+    }
+}
+
+/// Stores this sandbox's intercepted flows since `capture` as HTTP_REQUEST events.
+pub async fn collect(pool: &Pool<Postgres>, task_id: &str, capture: &FlowCapture, sandbox_ip: &str) -> usize {
+    let path = flows_path();
+    let text = match read_log_since(&path, capture.offset).await {
+        Ok(t) => t,
+        Err(e) => {
+            println!("[MITM] Cannot read flow log {}: {}", path, e);
+            return 0;
+        }
+    };
+
+    let mut owners: HashMap<String, Option<(i32, String)>> = HashMap::new();
+    let mut recorded = 0;
+
+    for line in text.lines() {
+        if recorded >= MAX_FLOWS_PER_TASK {
+            println!("[MITM] Task {} hit the {} flow cap; remaining flows dropped", task_id, MAX_FLOWS_PER_TASK);
+            break;
+        }
+        let Ok(flow) = serde_json::from_str::<serde_json::Value>(line) else { continue };
+        if flow["client_ip"].as_str() != Some(sandbox_ip) {
+            continue;
+        }
+        let method = flow["method"].as_str().unwrap_or("GET");
+        let url = flow["url"].as_str().unwrap_or("");
+        let outcome = match (flow["status"].as_i64(), flow["error"].as_str()) {
+            (Some(status), _) => status.to_string(),
+            (None, Some(err)) => format!("error: {}", err),
+            _ => "no response".to_string(),
+        };
+        let details = format!("HTTP_REQUEST: {} {} -> {}", method, url, outcome);
+        let decoded = serde_json::json!({
+            "request_headers": flow["request_headers"],
+            "request_body": flow["request_body"],
+            "response_headers": flow["response_headers"],
+            "response_body": flow["response_body"],
+        }).to_string();
+        let timestamp = flow["ts"].as_f64()
+            .map(|t| (t * 1000.0) as i64)
+            .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+
+        let port = flow["client_port"].as_i64().map(|p| p.to_string()).unwrap_or_default();
+        if !owners.contains_key(&port) {
+            let owner = connection_owner(pool, task_id, sandbox_ip, &port).await;
+            owners.insert(port.clone(), owner);
+        }
+        let (pid, process_name) = owners[&port].clone().unwrap_or((0, "mitmproxy".to_string()));
+
+        let res: Result<i32, sqlx::Error> = sqlx::query_scalar(
+            "INSERT INTO events (event_type, process_id, parent_process_id, process_name, details, decoded_details, timestamp, task_id, severity, category, kill_chain_stage)
+             VALUES ('HTTP_REQUEST', $1, 0, $2, $3, $4, $5, $6, 30, 'network', $7)
+             RETURNING id"
+        )
+        .bind(pid)
+        .bind(&process_name)
+        .bind(&details)
+        .bind(&decoded)
+        .bind(timestamp)
+        .bind(task_id)
+        .bind(crate::kill_chain::classify("HTTP_REQUEST", &process_name, &details, None, Some("network")))
+        .fetch_one(pool)
+        .await;
+        match res {
+            Ok(id) => {
+                recorded += 1;
+                crate::metrics::inc("voodoobox_events_ingested_total", &[("source", "mitm")], 1.0);
+                crate::siem::forward(crate::siem::Record {
+                    task_id: Some(task_id.to_string()),
+                    pid: Some(pid),
+                    process_name: Some(process_name.clone()),
+                    severity: 30,
+                    ..crate::siem::Record::event("HTTP_REQUEST", &details, timestamp)
+                });
+                crate::sigma::evaluate(pool, None, &[crate::sigma::EventRef {
+                    id: Some(id),
+                    task_id: Some(task_id),
+                    event_type: "HTTP_REQUEST",
+                    process_id: pid,
+                    parent_process_id: 0,
+                    process_name: &process_name,
+                    details: &details,
+                    decoded_details: Some(&decoded),
+                    category: Some("network"),
+                    timestamp,
+                }]).await;
+            }
+            Err(e) => println!("[MITM] Failed to store flow for task {}: {}", task_id, e),
+        }
+    }
+
+    println!("[MITM] Task {}: {} intercepted HTTP(S) transactions from {}", task_id, recorded, sandbox_ip);
+    recorded
+}
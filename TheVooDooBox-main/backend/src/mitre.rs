@@ -0,0 +1,267 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::{Pool, Postgres};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::OnceLock;
+
+// --- ATT&CK COVERAGE ---
+// ATT&CK Navigator layers per task or across tasks.
+
+const MAX_CAMPAIGN_TASKS: usize = 200;
+/// Score of a technique seen only in Sysmon tags or only in the AI report.
+const SYSMON_SCORE: i32 = 50;
+const AI_SCORE: i32 = 30;
+const LAYER_VERSIONS: (&str, &str, &str) = ("15", "5.0.0", "4.5");
+
+#[derive(Default)]
+struct Observation {
+    score: i32,
+    tactics: BTreeSet<String>,
+    /// source -> what was seen, e.g. "rules" -> ["sigma: Suspicious Encoded PowerShell (3)"]
+    evidence: BTreeMap<&'static str, BTreeSet<String>>,
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CampaignQuery {
+    /// Comma-separated task ids.
+    pub task_ids: String,
+}
+
+fn technique_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?i)^t(\d{4})(?:[./](\d{3}))?$").unwrap())
+}
+
+fn sysmon_tag_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?i)technique_id=(T\d{4}(?:\.\d{3})?)").unwrap())
+}
+
+/// "t1059.001", "T1059/001" -> "T1059.001"; anything else is not a technique id.
+pub fn technique_id(raw: &str) -> Option<String> {
+    let caps = technique_re().captures(raw.trim())?;
+    Some(match caps.get(2) {
+        Some(sub) => format!("T{}.{}", &caps[1], sub.as_str()),
+        None => format!("T{}", &caps[1]),
+    })
+}
+
+/// "Command and Control" / "attack.command_and_control" -> "command-and-control"
+fn tactic_name(raw: &str) -> String {
+    raw.trim().to_lowercase().replace(['_', ' '], "-")
+}
+
+fn observe(
+    observed: &mut BTreeMap<String, Observation>,
+    id: String,
+    score: i32,
+    tactics: &[String],
+    source: &'static str,
+    evidence: String,
+) {
+    let entry = observed.entry(id).or_default();
+    entry.score = entry.score.max(score);
+    entry.tactics.extend(tactics.iter().cloned());
+    entry.evidence.entry(source).or_default().insert(evidence);
+}
+
+/// Techniques observed in one task, by id.
+async fn observations(pool: &Pool<Postgres>, task_id: &str) -> Result<BTreeMap<String, Observation>, sqlx::Error> {
+    let mut observed = BTreeMap::new();
+
+    let alerts: Vec<(String, String, i32, Vec<String>, i64)> = sqlx::query_as(
+        "SELECT source, rule_title, MAX(severity), tags, COUNT(*) FROM alerts
+         WHERE task_id = $1 AND resolution IS DISTINCT FROM 'false_positive'
+         GROUP BY source, rule_title, tags"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+    for (source, title, severity, tags, hits) in alerts {
+        let tactics: Vec<String> = tags.iter()
+            .filter_map(|t| t.strip_prefix("attack."))
+            // Tactic tags are words; techniques, groups (g0032) and software (s0154) carry digits
+            .filter(|t| !t.is_empty() && !t.chars().any(|c| c.is_ascii_digit()))
+            .map(tactic_name)
+            .collect();
+        for id in tags.iter().filter_map(|t| t.strip_prefix("attack.")).filter_map(technique_id) {
+            observe(&mut observed, id, severity, &tactics, "rules", format!("{}: {} ({})", source, title, hits));
+        }
+    }
+
+    // "[technique_id=T1059.001,technique_name=PowerShell] SYSMON: ..."
+    let tags: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT substring(details from '^\\[([^\\]]*)\\]') AS tag, COUNT(*) FROM events
+         WHERE task_id = $1 AND details LIKE '[%' GROUP BY 1"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .filter_map(|(tag, n): (Option<String>, i64)| tag.map(|t| (t, n)))
+    .collect();
+    for (tag, events) in tags {
+        for caps in sysmon_tag_re().captures_iter(&tag) {
+            if let Some(id) = technique_id(&caps[1]) {
+                observe(&mut observed, id, SYSMON_SCORE, &[], "sysmon", format!("{} ({} events)", tag, events));
+            }
+        }
+    }
+
+    if let Some(report) = crate::ai_analysis::stored_report(pool, task_id).await {
+        for (tactic, techniques) in &report.mitre_matrix {
+            for technique in techniques {
+                let status = technique.status.to_lowercase();
+                let negative = status.contains("not") || status.contains("no ") || status == "none";
+                let evidence: Vec<&String> = technique.evidence.iter().filter(|e| !e.trim().is_empty()).collect();
+                let Some(id) = technique_id(&technique.id) else { continue };
+                if negative || evidence.is_empty() {
+                    continue;
+                }
+                observe(&mut observed, id, AI_SCORE, &[tactic_name(tactic)], "ai",
+                    format!("{}: {}", technique.name, evidence.iter().map(|e| e.as_str()).collect::<Vec<_>>().join("; ")));
+            }
+        }
+    }
+    Ok(observed)
+}
+
+fn comment(obs: &Observation) -> String {
+    obs.evidence.iter()
+        .map(|(source, seen)| format!("{}: {}", source, seen.iter().cloned().collect::<Vec<_>>().join(" | ")))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Navigator entries; parents of sub-techniques are listed so the Navigator expands them.
+fn technique_entries(entries: Vec<(String, i32, String, Vec<(&str, String)>)>) -> Vec<Value> {
+    let ids: BTreeSet<String> = entries.iter().map(|e| e.0.clone()).collect();
+    let parents: BTreeSet<String> = ids.iter().filter_map(|id| id.split_once('.').map(|(p, _)| p.to_string())).collect();
+    let mut techniques: Vec<Value> = entries.into_iter().map(|(id, score, comment, metadata)| {
+        json!({
+            "techniqueID": id,
+            "score": score,
+            "color": "",
+            "comment": comment,
+            "enabled": true,
+            "metadata": metadata.into_iter().map(|(name, value)| json!({ "name": name, "value": value })).collect::<Vec<_>>(),
+            "showSubtechniques": parents.contains(&id),
+        })
+    }).collect();
+    for parent in parents.difference(&ids) {
+        techniques.push(json!({ "techniqueID": parent, "enabled": true, "showSubtechniques": true }));
+    }
+    techniques
+}
+
+fn layer(name: String, description: String, techniques: Vec<Value>, max_score: i32, metadata: Vec<(&str, String)>) -> Value {
+    let (attack, navigator, format) = LAYER_VERSIONS;
+    json!({
+        "name": name,
+        "versions": { "attack": attack, "navigator": navigator, "layer": format },
+        "domain": "enterprise-attack",
+        "description": description,
+        "sorting": 3,
+        "hideDisabled": false,
+        "techniques": techniques,
+        "gradient": { "colors": ["#fff4b3", "#ff6666"], "minValue": 0, "maxValue": max_score.max(1) },
+        "legendItems": [],
+        "showTacticRowBackground": false,
+        "selectTechniquesAcrossTactics": true,
+        "selectSubtechniquesWithParent": false,
+        "metadata": metadata.into_iter().map(|(name, value)| json!({ "name": name, "value": value })).collect::<Vec<_>>(),
+    })
+}
+
+/// Observed techniques of one task as an ATT&CK Navigator layer.
+#[utoipa::path(tag = "tasks", responses(
+    (status = 200, description = "ATT&CK Navigator layer (format 4.5)"),
+    (status = 404, description = "Task not found"),
+))]
+#[get("/tasks/{id}/mitre")]
+pub async fn get_task_mitre(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    let task_id = path.into_inner();
+    let pool = pool.get_ref();
+    let task: Option<(String, Option<String>)> = match sqlx::query_as("SELECT original_filename, verdict FROM tasks WHERE id = $1")
+        .bind(&task_id).fetch_optional(pool).await
+    {
+        Ok(t) => t,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    let Some((filename, verdict)) = task else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" }));
+    };
+
+    let observed = match observations(pool, &task_id).await {
+        Ok(o) => o,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    let entries = observed.iter().map(|(id, obs)| {
+        let sources = obs.evidence.keys().copied().collect::<Vec<_>>().join(",");
+        let mut metadata = vec![("sources", sources)];
+        if !obs.tactics.is_empty() {
+            metadata.push(("tactics", obs.tactics.iter().cloned().collect::<Vec<_>>().join(",")));
+        }
+        (id.clone(), obs.score, comment(obs), metadata)
+    }).collect();
+
+    HttpResponse::Ok().json(layer(
+        format!("{} ({})", filename, task_id),
+        format!("Techniques observed in task {} ({} techniques, verdict {}).", task_id, observed.len(), verdict.as_deref().unwrap_or("none")),
+        technique_entries(entries),
+        100,
+        vec![("task_id", task_id.clone()), ("filename", filename)],
+    ))
+}
+
+/// Techniques across several tasks, scored by how many of them showed each one.
+#[utoipa::path(tag = "tasks", params(CampaignQuery), responses(
+    (status = 200, description = "ATT&CK Navigator layer (format 4.5)"),
+    (status = 400, description = "No task ids, or more than 200"),
+))]
+#[get("/mitre")]
+pub async fn get_campaign_mitre(pool: web::Data<Pool<Postgres>>, query: web::Query<CampaignQuery>) -> impl Responder {
+    let task_ids: BTreeSet<String> = query.task_ids.split(',').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect();
+    if task_ids.is_empty() || task_ids.len() > MAX_CAMPAIGN_TASKS {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("task_ids must list between 1 and {} tasks", MAX_CAMPAIGN_TASKS)
+        }));
+    }
+
+    let pool = pool.get_ref();
+    // technique -> (task ids, sources)
+    let mut seen: BTreeMap<String, (BTreeSet<String>, BTreeSet<&'static str>)> = BTreeMap::new();
+    for task_id in &task_ids {
+        match observations(pool, task_id).await {
+            Ok(observed) => {
+                for (id, obs) in observed {
+                    let entry = seen.entry(id).or_default();
+                    entry.0.insert(task_id.clone());
+                    entry.1.extend(obs.evidence.keys().copied());
+                }
+            }
+            Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+        }
+    }
+
+    let entries = seen.iter().map(|(id, (tasks, sources))| {
+        let listed = tasks.iter().cloned().collect::<Vec<_>>().join(",");
+        (
+            id.clone(),
+            tasks.len() as i32,
+            format!("Seen in: {}", listed),
+            vec![("sources", sources.iter().copied().collect::<Vec<_>>().join(",")), ("tasks", listed)],
+        )
+    }).collect();
+
+    HttpResponse::Ok().json(layer(
+        format!("Campaign coverage ({} tasks)", task_ids.len()),
+        format!("Techniques observed across {} tasks; the score is the number of tasks that showed each.", task_ids.len()),
+        technique_entries(entries),
+        task_ids.len() as i32,
+        vec![("task_ids", task_ids.iter().cloned().collect::<Vec<_>>().join(","))],
+    ))
+}
@@ -0,0 +1,309 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use maxminddb::{geoip2, Reader};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+
+// --- NETWORK GRAPH ---
+
+#[derive(Serialize, Clone, Default)]
+pub struct GeoInfo {
+    pub country: Option<String>,
+    pub city: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub asn: Option<u32>,
+    pub as_org: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct GraphNode {
+    /// "process:<pid>", "ip:<addr>" or "domain:<name>"
+    pub id: String,
+    /// process | ip | domain
+    pub kind: &'static str,
+    pub label: String,
+    pub pid: Option<i32>,
+    pub first_seen: i64,
+    pub geo: Option<GeoInfo>,
+}
+
+#[derive(Serialize)]
+pub struct GraphEdge {
+    pub source: String,
+    pub target: String,
+    /// connection | lateral_movement | dns | resolves | http
+    pub kind: &'static str,
+    /// Destination ports for connections, methods/URLs for HTTP.
+    pub details: Vec<String>,
+    pub count: i64,
+    pub first_seen: i64,
+    pub last_seen: i64,
+}
+
+#[derive(Serialize)]
+pub struct NetworkGraph {
+    pub task_id: String,
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+    /// Whether a GeoIP/ASN database was available for enrichment.
+    pub geoip: bool,
+}
+
+#[derive(sqlx::FromRow)]
+struct NetRow {
+    event_type: String,
+    process_id: i32,
+    process_name: String,
+    details: String,
+    timestamp: i64,
+}
+
+/// Upper bound on distinct values kept per edge in `details`.
+const MAX_EDGE_DETAILS: usize = 20;
+
+fn open_db(var: &str) -> Option<Reader<Vec<u8>>> {
+    let path = std::env::var(var).ok().filter(|p| !p.is_empty())?;
+    match Reader::open_readfile(&path) {
+        Ok(reader) => Some(reader),
+        Err(e) => {
+            println!("[GEOIP] Could not open {} ({}): {}", var, path, e);
+            None
+        }
+    }
+}
+
+fn city_db() -> Option<&'static Reader<Vec<u8>>> {
+    static DB: OnceLock<Option<Reader<Vec<u8>>>> = OnceLock::new();
+    DB.get_or_init(|| open_db("GEOIP_CITY_DB")).as_ref()
+}
+
+fn asn_db() -> Option<&'static Reader<Vec<u8>>> {
+    static DB: OnceLock<Option<Reader<Vec<u8>>>> = OnceLock::new();
+    DB.get_or_init(|| open_db("GEOIP_ASN_DB")).as_ref()
+}
+
+pub fn is_public(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => !(v4.is_private() || v4.is_loopback() || v4.is_link_local() || v4.is_unspecified()
+            || v4.is_broadcast() || v4.is_multicast() || v4.is_documentation()),
+        IpAddr::V6(v6) => !(v6.is_loopback() || v6.is_unspecified() || v6.is_multicast()
+            || (v6.segments()[0] & 0xfe00) == 0xfc00 || (v6.segments()[0] & 0xffc0) == 0xfe80),
+    }
+}
+
+pub fn lookup(ip: IpAddr) -> Option<GeoInfo> {
+    if !is_public(&ip) {
+        return None;
+    }
+    let mut geo = GeoInfo::default();
+    if let Some(Ok(city)) = city_db().map(|db| db.lookup::<geoip2::City>(ip)) {
+        geo.country = city.country.and_then(|c| c.iso_code).map(str::to_string);
+        geo.city = city.city.and_then(|c| c.names).and_then(|n| n.get("en").map(|s| s.to_string()));
+        if let Some(location) = city.location {
+            geo.latitude = location.latitude;
+            geo.longitude = location.longitude;
+        }
+    }
+    if let Some(Ok(asn)) = asn_db().map(|db| db.lookup::<geoip2::Asn>(ip)) {
+        geo.asn = asn.autonomous_system_number;
+        geo.as_org = asn.autonomous_system_organization.map(str::to_string);
+    }
+    if geo.country.is_none() && geo.asn.is_none() {
+        return None;
+    }
+    Some(geo)
+}
+
+/// "1.2.3.4:443" / "[::1]:443" / "dead::beef:443" -> (ip, port)
+fn split_endpoint(endpoint: &str) -> Option<(IpAddr, Option<u16>)> {
+    let endpoint = endpoint.trim().trim_end_matches(',');
+    if let Ok(ip) = endpoint.trim_matches(|c| c == '[' || c == ']').parse() {
+        return Some((ip, None));
+    }
+    let (host, port) = endpoint.rsplit_once(':')?;
+    let ip = host.trim_matches(|c| c == '[' || c == ']').parse().ok()?;
+    Some((ip, port.parse().ok()))
+}
+
+/// Host node for a URL: an IP node when the URL names an address, otherwise a domain node.
+fn url_host(url: &str) -> Option<String> {
+    let parsed = reqwest::Url::parse(url.trim()).ok()?;
+    let host = parsed.host_str()?;
+    match host.trim_matches(|c| c == '[' || c == ']').parse::<IpAddr>() {
+        Ok(ip) => Some(format!("ip:{}", ip)),
+        Err(_) => Some(format!("domain:{}", host.trim_end_matches('.').to_lowercase())),
+    }
+}
+
+#[derive(Default)]
+struct Builder {
+    nodes: Vec<GraphNode>,
+    node_index: HashMap<String, usize>,
+    edges: Vec<GraphEdge>,
+    edge_index: HashMap<(String, String, &'static str), usize>,
+}
+
+impl Builder {
+    fn node(&mut self, id: String, label: String, pid: Option<i32>, ts: i64) -> String {
+        if let Some(&i) = self.node_index.get(&id) {
+            let node = &mut self.nodes[i];
+            node.first_seen = node.first_seen.min(ts);
+            return id;
+        }
+        let kind = match id.split(':').next() {
+            Some("process") => "process",
+            Some("ip") => "ip",
+            _ => "domain",
+        };
+        self.node_index.insert(id.clone(), self.nodes.len());
+        self.nodes.push(GraphNode { id: id.clone(), kind, label, pid, first_seen: ts, geo: None });
+        id
+    }
+
+    fn process(&mut self, row: &NetRow) -> String {
+        // PID 0 is how the agent reports activity it cannot attribute (DNS cache, proxy)
+        let label = if row.process_id == 0 { "Unattributed".to_string() } else { format!("{} ({})", row.process_name, row.process_id) };
+        self.node(format!("process:{}", row.process_id), label, Some(row.process_id), row.timestamp)
+    }
+
+    fn host(&mut self, id: String, ts: i64) -> String {
+        let label = id.split_once(':').map(|(_, v)| v.to_string()).unwrap_or_default();
+        self.node(id, label, None, ts)
+    }
+
+    fn edge(&mut self, source: String, target: String, kind: &'static str, detail: Option<String>, ts: i64) {
+        let key = (source.clone(), target.clone(), kind);
+        let i = match self.edge_index.get(&key) {
+            Some(&i) => i,
+            None => {
+                self.edge_index.insert(key, self.edges.len());
+                self.edges.push(GraphEdge { source, target, kind, details: Vec::new(), count: 0, first_seen: ts, last_seen: ts });
+                self.edges.len() - 1
+            }
+        };
+        let edge = &mut self.edges[i];
+        edge.count += 1;
+        edge.first_seen = edge.first_seen.min(ts);
+        edge.last_seen = edge.last_seen.max(ts);
+        if let Some(detail) = detail {
+            if edge.details.len() < MAX_EDGE_DETAILS && !edge.details.contains(&detail) {
+                edge.details.push(detail);
+            }
+        }
+    }
+
+    fn add(&mut self, row: &NetRow) {
+        let details = row.details.as_str();
+        match row.event_type.as_str() {
+            // "SYSMON: tcp 10.0.0.5 -> 1.2.3.4:443" / "TCP 10.0.0.5:49702 -> 1.2.3.4:443 [CRITICAL HOP]"
+            "NETWORK_CONNECT" | "LATERAL_MOVEMENT" => {
+                let Some(dest) = details.split("->").nth(1).and_then(|d| d.split_whitespace().next()) else { return };
+                let Some((ip, port)) = split_endpoint(dest) else { return };
+                if ip.is_loopback() || ip.is_unspecified() {
+                    return;
+                }
+                let proto = details.split("->").next().unwrap_or("")
+                    .split_whitespace().rev().nth(1).unwrap_or("tcp").to_lowercase();
+                let kind = if row.event_type == "LATERAL_MOVEMENT" { "lateral_movement" } else { "connection" };
+                let source = self.process(row);
+                let target = self.host(format!("ip:{}", ip), row.timestamp);
+                self.edge(source, target, kind, port.map(|p| format!("{}/{}", proto, p)), row.timestamp);
+            }
+            // "SYSMON: DNS: evil.com | IPs: 1.2.3.4, 5.6.7.8" / "DNS Query Resolved: evil.com"
+            "NETWORK_DNS" => {
+                let rest = if let Some(pos) = details.find("DNS: ") {
+                    &details[pos + 5..]
+                } else if let Some(pos) = details.find("DNS Query Resolved: ") {
+                    &details[pos + 20..]
+                } else {
+                    return;
+                };
+                let (name, ips) = match rest.split_once("| IPs:") {
+                    Some((name, ips)) => (name, Some(ips)),
+                    None => (rest, None),
+                };
+                let name = name.trim().trim_end_matches('.').to_lowercase();
+                if name.is_empty() {
+                    return;
+                }
+                let source = self.process(row);
+                let domain = self.host(format!("domain:{}", name), row.timestamp);
+                self.edge(source, domain.clone(), "dns", None, row.timestamp);
+                for ip in ips.unwrap_or("").split(',').filter_map(|ip| ip.trim().parse::<IpAddr>().ok()) {
+                    let target = self.host(format!("ip:{}", ip), row.timestamp);
+                    self.edge(domain.clone(), target, "resolves", None, row.timestamp);
+                }
+            }
+            // "HTTP_REQUEST: GET https://host/path -> 200"
+            "HTTP_REQUEST" => {
+                let mut parts = details.split_whitespace().skip(1);
+                let (Some(method), Some(url)) = (parts.next(), parts.next()) else { return };
+                let Some(host) = url_host(url) else { return };
+                let source = self.process(row);
+                let target = self.host(host, row.timestamp);
+                self.edge(source, target, "http", Some(format!("{} {}", method, url)), row.timestamp);
+            }
+            // "URL: https://host/ | Title: ..." / "REDIRECT: a -> b (302)"
+            "BROWSER_NAVIGATE" | "BROWSER_REDIRECT" => {
+                let urls: Vec<&str> = if let Some(rest) = details.strip_prefix("URL: ") {
+                    vec![rest.split('|').next().unwrap_or("")]
+                } else if let Some(rest) = details.strip_prefix("REDIRECT: ") {
+                    rest.split("->").map(|u| u.split(" (").next().unwrap_or("")).collect()
+                } else {
+                    return;
+                };
+                for url in urls {
+                    let Some(host) = url_host(url) else { continue };
+                    let source = self.process(row);
+                    let target = self.host(host, row.timestamp);
+                    let method = if row.event_type == "BROWSER_REDIRECT" { "REDIRECT" } else { "NAVIGATE" };
+                    self.edge(source, target, "http", Some(format!("{} {}", method, url.trim())), row.timestamp);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+pub async fn build(pool: &Pool<Postgres>, task_id: &str) -> Result<NetworkGraph, sqlx::Error> {
+    let rows = sqlx::query_as::<_, NetRow>(
+        "SELECT event_type, process_id, process_name, details, timestamp FROM events
+         WHERE task_id = $1
+           AND event_type IN ('NETWORK_CONNECT', 'LATERAL_MOVEMENT', 'NETWORK_DNS', 'HTTP_REQUEST', 'BROWSER_NAVIGATE', 'BROWSER_REDIRECT')
+         ORDER BY timestamp"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut builder = Builder::default();
+    for row in &rows {
+        builder.add(row);
+    }
+
+    let geoip = city_db().is_some() || asn_db().is_some();
+    if geoip {
+        for node in builder.nodes.iter_mut().filter(|n| n.kind == "ip") {
+            if let Ok(ip) = node.label.parse() {
+                node.geo = lookup(ip);
+            }
+        }
+    }
+
+    Ok(NetworkGraph { task_id: task_id.to_string(), nodes: builder.nodes, edges: builder.edges, geoip })
+}
+
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
+#[get("/tasks/{id}/network-graph")]
+pub async fn get_network_graph(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    match build(pool.get_ref(), &path.into_inner()).await {
+        Ok(graph) => HttpResponse::Ok().json(graph),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
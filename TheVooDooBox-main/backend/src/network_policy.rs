@@ -0,0 +1,87 @@
+use sqlx::{Pool, Postgres};
+use crate::analysis_profiles::INTERNET_POLICIES;
+use crate::hypervisor::{Hypervisor, NetworkPlan};
+
+// --- INTERNET ACCESS POLICY ---
+// full / fake-net / blocked / vpn, applied as bridge and firewall settings.
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query("ALTER TABLE tasks ADD COLUMN IF NOT EXISTS internet_policy TEXT")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Canonical policy name; "fakenet"/"inetsim" and "none"/"offline" are accepted spellings.
+pub fn parse_policy(raw: &str) -> Option<&'static str> {
+    match raw.trim().to_lowercase().as_str() {
+        "fakenet" | "fake_net" | "inetsim" => Some("fake-net"),
+        "none" | "offline" => Some("blocked"),
+        other => INTERNET_POLICIES.iter().find(|p| **p == other).copied(),
+    }
+}
+
+/// Site-wide policy for submissions that neither set one nor get one from their profile.
+pub fn default_policy() -> Option<&'static str> {
+    std::env::var("INTERNET_POLICY").ok().as_deref().and_then(parse_policy)
+}
+
+/// Stores the policy the task will run under (the site default if none was chosen).
+pub async fn record(pool: &Pool<Postgres>, task_id: &str, policy: Option<&str>) {
+    let Some(policy) = policy.and_then(parse_policy).or_else(default_policy) else { return };
+    if let Err(e) = sqlx::query("UPDATE tasks SET internet_policy = $1 WHERE id = $2")
+        .bind(policy)
+        .bind(task_id)
+        .execute(pool)
+        .await
+    {
+        println!("[NETPOLICY] Failed to record policy for task {}: {}", task_id, e);
+    }
+}
+
+pub async fn for_task(pool: &Pool<Postgres>, task_id: &str) -> Option<String> {
+    sqlx::query_scalar::<_, Option<String>>("SELECT internet_policy FROM tasks WHERE id = $1")
+        .bind(task_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .flatten()
+}
+
+fn env_key(policy: &str) -> String {
+    format!("NETPOLICY_{}", policy.replace('-', "").to_uppercase())
+}
+
+pub fn plan_for(policy: &str) -> Result<NetworkPlan, String> {
+    let key = env_key(policy);
+    let bridge = std::env::var(format!("{}_BRIDGE", key)).ok().filter(|b| !b.trim().is_empty());
+    let vlan_tag = std::env::var(format!("{}_VLAN", key)).ok().and_then(|v| v.trim().parse().ok());
+
+    match policy {
+        "full" => Ok(NetworkPlan { bridge, vlan_tag, allow_only: None }),
+        "fake-net" | "vpn" if bridge.is_none() => {
+            Err(format!("{} policy needs {}_BRIDGE to be configured", policy, key))
+        }
+        "fake-net" | "vpn" => Ok(NetworkPlan { bridge, vlan_tag, allow_only: None }),
+        "blocked" => {
+            let mut allow: Vec<String> = std::env::var("HOST_IP").into_iter().collect();
+            if let Ok(extra) = std::env::var("NETPOLICY_BLOCKED_ALLOW") {
+                allow.extend(extra.split(',').map(str::trim).filter(|a| !a.is_empty()).map(String::from));
+            }
+            if allow.is_empty() {
+                return Err("blocked policy needs HOST_IP so the agent can still reach the backend".to_string());
+            }
+            Ok(NetworkPlan { bridge, vlan_tag, allow_only: Some(allow) })
+        }
+        other => Err(format!("Unknown internet policy '{}'", other)),
+    }
+}
+
+/// Applies the policy to a stopped VM.
+pub async fn enforce(client: &dyn Hypervisor, node: &str, vmid: u64, policy: &str) -> Result<(), String> {
+    let plan = plan_for(policy)?;
+    client.apply_network(node, vmid, &plan).await.map_err(|e| e.to_string())?;
+    println!("[NETPOLICY] VM {} on {} configured for '{}' internet access", vmid, node, policy);
+    Ok(())
+}
@@ -0,0 +1,360 @@
+use actix_web::{get, post, delete, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::sync::{Arc, OnceLock, RwLock};
+use crate::AgentManager;
+
+// --- NOISE / ALLOW LISTS ---
+
+pub const KINDS: &[&str] = &["process", "signer", "path"];
+
+/// Seeded into an empty table.
+const DEFAULT_FILTERS: &[&str] = &[
+    "voodoobox-agent-windows.exe",
+    "voodoobox-agent.exe",
+    "mallab-agent-windows.exe",
+    "mallab-agent",
+    "officeclicktorun.exe",
+    "conhost.exe",
+    "svchost.exe",
+    "lsass.exe",
+    "services.exe",
+    "wininit.exe",
+    "smss.exe",
+    "csrss.exe",
+    "winlogon.exe",
+    "spoolsv.exe",
+    "searchindexer.exe",
+    "taskhostw.exe",
+    "sppsvc.exe",
+    "fontdrvhost.exe",
+    "dwm.exe",
+    "ctfmon.exe",
+    "taskmgr.exe",
+    "sysmon.exe",
+    "sysmon64.exe",
+    "mpcmdrun.exe",
+    "msmpeng.exe",
+    "backgroundtaskhost.exe",
+    "runtimebroker.exe",
+    "sihost.exe",
+];
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+pub struct NoiseFilter {
+    pub id: i32,
+    pub pattern: String,
+    /// "noise" drops matching events, "allow" exempts them from the noise list.
+    pub list_type: String,
+    /// process | signer | path
+    pub kind: String,
+    /// Gold image (agent hostname) this entry applies to; NULL applies everywhere.
+    pub image: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Deserialize)]
+pub struct CreateNoiseFilterRequest {
+    pub pattern: String,
+    pub list_type: Option<String>,
+    /// process (default) | signer | path
+    pub kind: Option<String>,
+    pub image: Option<String>,
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct NoiseFilterQuery {
+    pub image: Option<String>,
+}
+
+fn loaded() -> &'static RwLock<Arc<Vec<NoiseFilter>>> {
+    static FILTERS: OnceLock<RwLock<Arc<Vec<NoiseFilter>>>> = OnceLock::new();
+    FILTERS.get_or_init(|| RwLock::new(Arc::new(Vec::new())))
+}
+
+fn filters() -> Arc<Vec<NoiseFilter>> {
+    loaded().read().map(|f| Arc::clone(&f)).unwrap_or_default()
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS noise_filters (
+            id SERIAL PRIMARY KEY,
+            pattern TEXT NOT NULL,
+            list_type TEXT NOT NULL DEFAULT 'noise',
+            image TEXT,
+            created_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query("ALTER TABLE noise_filters ADD COLUMN IF NOT EXISTS kind TEXT NOT NULL DEFAULT 'process'")
+        .execute(pool)
+        .await?;
+
+    // Seed with the built-in list the first time so existing behaviour is preserved
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM noise_filters")
+        .fetch_one(pool)
+        .await?;
+    if count == 0 {
+        let now = chrono::Utc::now().timestamp();
+        for pattern in DEFAULT_FILTERS {
+            sqlx::query("INSERT INTO noise_filters (pattern, list_type, image, created_at) VALUES ($1, 'noise', NULL, $2)")
+                .bind(pattern)
+                .bind(now)
+                .execute(pool)
+                .await?;
+        }
+        println!("[NOISE] Seeded {} default noise filters.", DEFAULT_FILTERS.len());
+    }
+
+    reload(pool).await?;
+    println!("[NOISE] Database initialized (noise_filters).");
+    Ok(())
+}
+
+pub async fn reload(pool: &Pool<Postgres>) -> Result<usize, sqlx::Error> {
+    let all = sqlx::query_as::<_, NoiseFilter>(
+        "SELECT id, pattern, list_type, kind, image, created_at FROM noise_filters ORDER BY id"
+    )
+    .fetch_all(pool)
+    .await?;
+    let count = all.len();
+    if let Ok(mut current) = loaded().write() {
+        *current = Arc::new(all);
+    }
+    Ok(count)
+}
+
+/// "Signed (Verified) | Signer: Microsoft Windows" -> "microsoft windows"; unverified gives None.
+fn verified_signer(signature: Option<&str>) -> Option<String> {
+    let sig = signature?;
+    if !sig.starts_with("Signed (Verified") {
+        return None;
+    }
+    sig.split("| Signer:").nth(1).map(|s| s.trim().to_lowercase())
+}
+
+/// `*` matches any run of characters, `?` exactly one.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (p, t): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut pi, mut ti) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            backtrack = Some((pi, ti));
+            pi += 1;
+        } else if let Some((star, matched)) = backtrack {
+            pi = star + 1;
+            ti = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+fn matches(filter: &NoiseFilter, process: &str, signer: Option<&str>) -> bool {
+    match filter.kind.as_str() {
+        "signer" => signer.is_some_and(|s| s.contains(filter.pattern.as_str())),
+        "path" => glob_match(&filter.pattern, process),
+        _ => process.contains(filter.pattern.as_str()),
+    }
+}
+
+/// Whether an event is noise; `image` adds that gold image's entries to the global ones.
+pub fn is_noise(image: Option<&str>, event_type: &str, process_name: &str, signature: Option<&str>) -> bool {
+    if event_type.starts_with("REG_") || event_type == "SESSION_INIT" || event_type.starts_with("AGENT_") {
+        return false;
+    }
+    let process = process_name.to_lowercase();
+    let signer = verified_signer(signature);
+    let mut noise = false;
+    for filter in filters().iter() {
+        let applies = match (&filter.image, image) {
+            (None, _) => true,
+            (Some(scoped), Some(image)) => scoped.eq_ignore_ascii_case(image),
+            (Some(_), None) => false,
+        };
+        if !applies || !matches(filter, &process, signer.as_deref()) {
+            continue;
+        }
+        if filter.list_type == "allow" {
+            return false;
+        }
+        noise = true;
+    }
+    noise
+}
+
+/// LIKE pattern for a filter: substrings for process and signer entries, the glob for paths.
+fn like_pattern(filter: &NoiseFilter) -> String {
+    let mut like = String::new();
+    for c in filter.pattern.chars() {
+        match c {
+            '\\' | '%' | '_' => {
+                like.push('\\');
+                like.push(c);
+            }
+            '*' if filter.kind == "path" => like.push('%'),
+            '?' if filter.kind == "path" => like.push('_'),
+            _ => like.push(c),
+        }
+    }
+    if filter.kind == "path" { like } else { format!("%{}%", like) }
+}
+
+/// Appends ` AND NOT (...)` so an events query skips what the global filters call noise.
+pub fn push_exclusion(qb: &mut sqlx::QueryBuilder<'_, Postgres>) {
+    let filters = filters();
+    let global: Vec<&NoiseFilter> = filters.iter().filter(|f| f.image.is_none()).collect();
+    if !global.iter().any(|f| f.list_type == "noise") {
+        return;
+    }
+    let push_match = |qb: &mut sqlx::QueryBuilder<'_, Postgres>, list_type: &str| {
+        let of = |kind: &str| -> Vec<String> {
+            global.iter().filter(|f| f.list_type == list_type && (f.kind == kind || (kind == "process" && f.kind == "path")))
+                .map(|f| like_pattern(f)).collect()
+        };
+        qb.push("(LOWER(process_name) LIKE ANY(").push_bind(of("process"))
+            .push(") OR (COALESCE(digital_signature, '') LIKE 'Signed (Verified%' AND LOWER(split_part(digital_signature, '| Signer:', 2)) LIKE ANY(")
+            .push_bind(of("signer"))
+            .push(")))");
+    };
+    qb.push(" AND NOT (event_type NOT LIKE 'REG\\_%' AND event_type <> 'SESSION_INIT' AND event_type NOT LIKE 'AGENT\\_%' AND ");
+    push_match(qb, "noise");
+    qb.push(" AND NOT ");
+    push_match(qb, "allow");
+    qb.push(")");
+}
+
+/// Filters that apply to a given gold image: global entries plus any scoped to that image.
+pub async fn filters_for_image(pool: &Pool<Postgres>, image: Option<&str>) -> Result<Vec<NoiseFilter>, sqlx::Error> {
+    sqlx::query_as::<_, NoiseFilter>(
+        "SELECT id, pattern, list_type, kind, image, created_at FROM noise_filters
+         WHERE image IS NULL OR LOWER(image) = LOWER($1) ORDER BY id"
+    )
+    .bind(image)
+    .fetch_all(pool)
+    .await
+}
+
+/// Builds the SET_NOISE_FILTER command an agent applies before events hit the wire.
+pub async fn build_agent_command(pool: &Pool<Postgres>, image: Option<&str>) -> Result<String, sqlx::Error> {
+    // Agents match process names only; signer and path entries are applied here
+    let filters: Vec<NoiseFilter> = filters_for_image(pool, image).await?
+        .into_iter()
+        .filter(|f| f.kind == "process")
+        .collect();
+    let (allow, noise): (Vec<_>, Vec<_>) = filters.into_iter().partition(|f| f.list_type == "allow");
+
+    Ok(serde_json::json!({
+        "command": "SET_NOISE_FILTER",
+        "noise": noise.into_iter().map(|f| f.pattern.to_lowercase()).collect::<Vec<_>>(),
+        "allow": allow.into_iter().map(|f| f.pattern.to_lowercase()).collect::<Vec<_>>(),
+    }).to_string())
+}
+
+/// Re-sends the current lists to every connected agent after an edit.
+async fn push_to_agents(pool: &Pool<Postgres>, manager: &AgentManager) {
+    let targets: Vec<(String, Option<String>)> = {
+        let sessions = manager.sessions.lock().await;
+        sessions.iter().map(|(id, s)| (id.clone(), s.hostname.clone())).collect()
+    };
+
+    for (session_id, hostname) in targets {
+        match build_agent_command(pool, hostname.as_deref()).await {
+            Ok(cmd) => manager.send_command_to_session(&session_id, &cmd).await,
+            Err(e) => println!("[NOISE] Failed to build filter set for {}: {}", session_id, e),
+        }
+    }
+}
+
+#[utoipa::path(tag = "settings", responses((status = 200, description = "Success")))]
+#[get("/settings/noise-filters")]
+pub async fn list_noise_filters(
+    pool: web::Data<Pool<Postgres>>,
+    query: web::Query<NoiseFilterQuery>,
+) -> impl Responder {
+    let result = match &query.image {
+        Some(image) => filters_for_image(pool.get_ref(), Some(image)).await,
+        None => sqlx::query_as::<_, NoiseFilter>(
+            "SELECT id, pattern, list_type, kind, image, created_at FROM noise_filters ORDER BY id"
+        )
+        .fetch_all(pool.get_ref())
+        .await,
+    };
+
+    match result {
+        Ok(filters) => HttpResponse::Ok().json(filters),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "settings", responses((status = 200, description = "Success")))]
+#[post("/settings/noise-filters")]
+pub async fn add_noise_filter(
+    pool: web::Data<Pool<Postgres>>,
+    manager: web::Data<Arc<AgentManager>>,
+    req: web::Json<CreateNoiseFilterRequest>,
+) -> impl Responder {
+    let pattern = req.pattern.trim().to_lowercase();
+    if pattern.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "pattern must not be empty" }));
+    }
+    let list_type = req.list_type.clone().unwrap_or_else(|| "noise".to_string());
+    if list_type != "noise" && list_type != "allow" {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "list_type must be 'noise' or 'allow'" }));
+    }
+    let kind = req.kind.as_deref().map(|k| k.trim().to_lowercase()).unwrap_or_else(|| "process".to_string());
+    if !KINDS.contains(&kind.as_str()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "kind must be 'process', 'signer' or 'path'" }));
+    }
+
+    let result = sqlx::query_as::<_, NoiseFilter>(
+        "INSERT INTO noise_filters (pattern, list_type, kind, image, created_at) VALUES ($1, $2, $3, $4, $5)
+         RETURNING id, pattern, list_type, kind, image, created_at"
+    )
+    .bind(&pattern)
+    .bind(&list_type)
+    .bind(&kind)
+    .bind(&req.image)
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_one(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(filter) => {
+            let _ = reload(pool.get_ref()).await;
+            push_to_agents(pool.get_ref(), manager.get_ref()).await;
+            HttpResponse::Ok().json(filter)
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "settings", responses((status = 200, description = "Success")))]
+#[delete("/settings/noise-filters/{id}")]
+pub async fn delete_noise_filter(
+    pool: web::Data<Pool<Postgres>>,
+    manager: web::Data<Arc<AgentManager>>,
+    path: web::Path<i32>,
+) -> impl Responder {
+    let id = path.into_inner();
+    match sqlx::query("DELETE FROM noise_filters WHERE id = $1").bind(id).execute(pool.get_ref()).await {
+        Ok(res) if res.rows_affected() == 0 => {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": "Noise filter not found" }))
+        }
+        Ok(_) => {
+            let _ = reload(pool.get_ref()).await;
+            push_to_agents(pool.get_ref(), manager.get_ref()).await;
+            HttpResponse::Ok().json(serde_json::json!({ "status": "deleted", "id": id }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
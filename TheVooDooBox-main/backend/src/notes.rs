@@ -23,6 +23,7 @@ pub struct CreateNoteRequest {
     pub is_hint: bool,
 }
 
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
 #[post("/tasks/notes")]
 pub async fn add_note(
     pool: web::Data<PgPool>,
@@ -49,6 +50,7 @@ pub async fn add_note(
     }
 }
 
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
 #[get("/tasks/{task_id}/notes")]
 pub async fn get_notes(
     pool: web::Data<PgPool>,
@@ -86,6 +88,7 @@ pub struct CreateTagRequest {
     pub comment: Option<String>,
 }
 
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
 #[post("/tasks/tags")]
 pub async fn add_tag(
     pool: web::Data<PgPool>,
@@ -110,6 +113,7 @@ pub async fn add_tag(
     }
 }
 
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
 #[get("/tasks/{task_id}/tags")]
 pub async fn get_tags(
     pool: web::Data<PgPool>,
@@ -0,0 +1,285 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// --- COMMAND-LINE OBFUSCATION ---
+// 0-100 command-line obfuscation score.
+
+/// Texts shorter than this are not scored on entropy or symbol density.
+const STATISTICS_MIN_LEN: usize = 40;
+const DEFAULT_MIN_SCORE: i32 = 1;
+const MAX_LISTED: i64 = 500;
+
+/// (indicator, points when present)
+const WEIGHTS: &[(&str, i32)] = &[
+    ("caret_insertion", 30),
+    ("string_concatenation", 25),
+    ("char_codes", 30),
+    ("format_reordering", 25),
+    ("backtick_escapes", 20),
+    ("env_var_substring", 30),
+    ("random_case", 15),
+    ("encoded_command", 20),
+    ("string_reversal", 20),
+    ("high_entropy", 15),
+    ("symbol_density", 15),
+    ("long_command", 10),
+];
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Assessment {
+    pub score: i32,
+    pub entropy: f64,
+    pub length: usize,
+    pub indicators: Vec<&'static str>,
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ObfuscationQuery {
+    /// Lowest score listed (default 1).
+    pub min_score: Option<i32>,
+    pub limit: Option<i64>,
+}
+
+#[derive(sqlx::FromRow)]
+struct Unscored {
+    id: i32,
+    event_type: String,
+    details: String,
+    decoded_details: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct Scored {
+    id: i32,
+    event_type: String,
+    process_id: i32,
+    process_name: String,
+    details: String,
+    decoded_details: Option<String>,
+    timestamp: i64,
+    obfuscation_score: i32,
+}
+
+struct Patterns {
+    caret: Regex,
+    concat: Regex,
+    char_code: Regex,
+    format: Regex,
+    backtick: Regex,
+    env_substring: Regex,
+    encoded: Regex,
+    reversal: Regex,
+    word: Regex,
+    command_line: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static P: OnceLock<Patterns> = OnceLock::new();
+    P.get_or_init(|| Patterns {
+        caret: Regex::new(r"[A-Za-z0-9]\^[A-Za-z0-9]").unwrap(),
+        concat: Regex::new(r#"['"]\s*\+\s*['"]|['"]\s*\+\s*\$|\$\w+\s*\+\s*['"]"#).unwrap(),
+        char_code: Regex::new(r"(?i)\[char\]\s*0?x?[0-9a-f]+|\bchr[w$]?\s*\(\s*\d+|fromcharcode\s*\(|\[convert\]::toint\d+").unwrap(),
+        format: Regex::new(r#"(?i)["']\s*(?:\{\d+\}\s*){2,}["']\s*-f\b"#).unwrap(),
+        backtick: Regex::new(r"[A-Za-z]`[A-Za-z]").unwrap(),
+        env_substring: Regex::new(r"(?i)%[a-z_]+:~-?\d+(?:,-?\d+)?%|\$env:\w+\[\d+").unwrap(),
+        encoded: Regex::new(r#"(?i)(?:^|[\s"'])[-/]e(?:c|nc\w*)["',\s]+[A-Za-z0-9+/=]{16,}"#).unwrap(),
+        reversal: Regex::new(r"(?i)\[array\]::reverse|\[-1\.\.-|-join\s*\(\s*\$\w+\[-1").unwrap(),
+        // Whole words only, so base64 runs do not count as random casing
+        word: Regex::new(r"\b[A-Za-z]{6,}\b").unwrap(),
+        // "SYSMON: CMD: <cmd> | User: x", "New process: x Cmd: [..] (SHA256: ..)", "Command Line: x"
+        command_line: Regex::new(r"(?i)\b(?:CMD|Command Line): (.*?)(?: \| User:.*| \(SHA256:.*)?$").unwrap(),
+    })
+}
+
+fn shannon_entropy(text: &str) -> f64 {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in text.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+    let total = text.chars().count() as f64;
+    counts.values().map(|&n| {
+        let p = n as f64 / total;
+        -p * p.log2()
+    }).sum()
+}
+
+/// Words in alternating or random case ("pOwErShElL"); normal casing switches at most twice.
+fn random_case_words(text: &str) -> usize {
+    patterns().word.find_iter(text).filter(|w| {
+        let switches = w.as_str().chars().collect::<Vec<_>>().windows(2)
+            .filter(|p| p[0].is_ascii_uppercase() != p[1].is_ascii_uppercase())
+            .count();
+        switches >= 4
+    }).count()
+}
+
+/// Scores one command line or script block.
+pub fn assess(text: &str) -> Assessment {
+    let p = patterns();
+    let length = text.chars().count();
+    let entropy = if length == 0 { 0.0 } else { shannon_entropy(text) };
+    let mut indicators = Vec::new();
+
+    if p.caret.find_iter(text).count() >= 3 {
+        indicators.push("caret_insertion");
+    }
+    if p.concat.find_iter(text).count() >= 3 {
+        indicators.push("string_concatenation");
+    }
+    if p.char_code.find_iter(text).count() >= 3 {
+        indicators.push("char_codes");
+    }
+    if p.format.is_match(text) {
+        indicators.push("format_reordering");
+    }
+    if p.backtick.find_iter(text).count() >= 3 {
+        indicators.push("backtick_escapes");
+    }
+    if p.env_substring.find_iter(text).count() >= 2 {
+        indicators.push("env_var_substring");
+    }
+    if random_case_words(text) >= 2 {
+        indicators.push("random_case");
+    }
+    if p.encoded.is_match(text) {
+        indicators.push("encoded_command");
+    }
+    if p.reversal.is_match(text) {
+        indicators.push("string_reversal");
+    }
+    if length >= STATISTICS_MIN_LEN {
+        if entropy >= 5.2 {
+            indicators.push("high_entropy");
+        }
+        let symbols = text.chars().filter(|c| !c.is_alphanumeric() && !c.is_whitespace()).count();
+        if symbols as f64 / length as f64 >= 0.35 {
+            indicators.push("symbol_density");
+        }
+    }
+    if length >= 1000 {
+        indicators.push("long_command");
+    }
+
+    let score = indicators.iter()
+        .filter_map(|i| WEIGHTS.iter().find(|(name, _)| name == i).map(|(_, w)| *w))
+        .sum::<i32>()
+        .min(100);
+    Assessment { score, entropy: (entropy * 100.0).round() / 100.0, length, indicators }
+}
+
+/// Whether events of this type carry a command line or a script block.
+pub fn applies(event_type: &str) -> bool {
+    let upper = event_type.to_uppercase();
+    upper == "PROCESS_CREATE" || upper.contains("SCRIPT") || upper.contains("POWERSHELL")
+}
+
+/// The command line inside an event's details; script events are taken whole.
+pub fn command_text<'a>(event_type: &str, details: &'a str) -> &'a str {
+    if event_type != "PROCESS_CREATE" {
+        return details;
+    }
+    patterns().command_line.captures(details)
+        .and_then(|c| c.get(1))
+        .map(|m| m.as_str())
+        .unwrap_or(details)
+}
+
+/// The command line and the decoded payload, whichever is more obfuscated.
+pub fn assess_event(event_type: &str, details: &str, decoded_details: Option<&str>) -> Option<Assessment> {
+    if !applies(event_type) {
+        return None;
+    }
+    let command = assess(command_text(event_type, details));
+    let decoded = decoded_details.filter(|d| !d.is_empty()).map(assess);
+    Some(match decoded {
+        Some(d) if d.score > command.score => d,
+        _ => command,
+    })
+}
+
+/// events.obfuscation_score for an event; None for events without a command line or script.
+pub fn score(event_type: &str, details: &str, decoded_details: Option<&str>) -> Option<i32> {
+    assess_event(event_type, details, decoded_details).map(|a| a.score)
+}
+
+/// Scores the task's command lines stored before the column existed. Returns how many.
+pub async fn backfill(pool: &Pool<Postgres>, task_id: &str) -> Result<usize, sqlx::Error> {
+    let rows = sqlx::query_as::<_, Unscored>(
+        "SELECT id, event_type, details, decoded_details FROM events
+         WHERE task_id = $1 AND obfuscation_score IS NULL
+           AND (event_type = 'PROCESS_CREATE' OR event_type ILIKE '%script%' OR event_type ILIKE '%powershell%')"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+    let (ids, scores): (Vec<i32>, Vec<i32>) = rows.iter()
+        .filter_map(|r| score(&r.event_type, &r.details, r.decoded_details.as_deref()).map(|s| (r.id, s)))
+        .unzip();
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    sqlx::query(
+        "UPDATE events SET obfuscation_score = u.score
+         FROM UNNEST($1::int[], $2::int[]) AS u(id, score)
+         WHERE events.id = u.id AND events.task_id = $3"
+    )
+    .bind(&ids)
+    .bind(&scores)
+    .bind(task_id)
+    .execute(pool)
+    .await?;
+    Ok(ids.len())
+}
+
+/// The task's most obfuscated command lines, highest score first, with their indicators.
+#[utoipa::path(tag = "tasks", params(ObfuscationQuery), responses((status = 200, description = "Scored command lines")))]
+#[get("/tasks/{id}/obfuscation")]
+pub async fn get_obfuscation(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+    query: web::Query<ObfuscationQuery>,
+) -> impl Responder {
+    let task_id = path.into_inner();
+    let pool = pool.get_ref();
+    if let Err(e) = backfill(pool, &task_id).await {
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() }));
+    }
+
+    let rows = sqlx::query_as::<_, Scored>(
+        "SELECT id, event_type, process_id, process_name, details, decoded_details, timestamp, obfuscation_score
+         FROM events WHERE task_id = $1 AND obfuscation_score >= $2
+         ORDER BY obfuscation_score DESC, timestamp LIMIT $3"
+    )
+    .bind(&task_id)
+    .bind(query.min_score.unwrap_or(DEFAULT_MIN_SCORE))
+    .bind(query.limit.unwrap_or(100).clamp(1, MAX_LISTED))
+    .fetch_all(pool)
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let events: Vec<serde_json::Value> = rows.into_iter().map(|r| {
+                let assessment = assess_event(&r.event_type, &r.details, r.decoded_details.as_deref()).unwrap_or_default();
+                serde_json::json!({
+                    "event_id": r.id,
+                    "timestamp": r.timestamp,
+                    "process_id": r.process_id,
+                    "process_name": r.process_name,
+                    "command_line": command_text(&r.event_type, &r.details),
+                    "decoded_details": r.decoded_details,
+                    "score": r.obfuscation_score,
+                    "entropy": assessment.entropy,
+                    "length": assessment.length,
+                    "indicators": assessment.indicators,
+                })
+            }).collect();
+            HttpResponse::Ok().json(serde_json::json!({ "task_id": task_id, "events": events }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
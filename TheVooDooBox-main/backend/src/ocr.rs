@@ -0,0 +1,184 @@
+use sqlx::{Pool, Postgres};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+// --- SCREENSHOT OCR ---
+// Tesseract over non-duplicate screenshots; text lands as SCREEN_TEXT events.
+
+/// Text kept per frame; a full-screen log window can be pages long.
+const MAX_TEXT_CHARS: usize = 8000;
+/// Frames listed in the AI prompt, and characters per frame there.
+const MAX_PROMPT_FRAMES: usize = 20;
+const MAX_PROMPT_CHARS: usize = 600;
+
+/// Phrases that make a frame worth a second look, with what they suggest and the severity of its event.
+const SCREEN_TERMS: &[(&[&str], &str, i32)] = &[
+    (&["files have been encrypted", "files are encrypted", "decrypt your files", "decryption key", "bitcoin", "tor browser", ".onion", "ransom"], "ransom note", 80),
+    (&["enter your password", "sign in to your account", "verify your account", "confirm your identity", "account has been suspended", "enter your credentials"], "credential prompt", 60),
+    (&["call microsoft", "call support", "your computer has been blocked", "virus detected", "toll free", "do not restart"], "tech-support scam", 60),
+    (&["is not a valid win32 application", "the application was unable to start correctly", "side-by-side configuration", "is missing from your computer"], "error dialog", 20),
+];
+
+pub fn enabled() -> bool {
+    std::env::var("OCR_ENABLED").map(|v| v != "false" && v != "0").unwrap_or(true)
+}
+
+fn timeout_secs() -> u64 {
+    std::env::var("OCR_TIMEOUT_SECS").ok().and_then(|v| v.trim().parse().ok()).unwrap_or(60)
+}
+
+fn slots() -> &'static Semaphore {
+    static SLOTS: OnceLock<Semaphore> = OnceLock::new();
+    SLOTS.get_or_init(|| {
+        let n: usize = std::env::var("OCR_MAX_CONCURRENT").ok().and_then(|v| v.trim().parse().ok()).unwrap_or(2);
+        Semaphore::new(n.max(1))
+    })
+}
+
+/// What the text looks like, from SCREEN_TERMS: (label, event severity).
+pub fn assess(text: &str) -> (Option<&'static str>, i32) {
+    let lower = text.to_lowercase();
+    SCREEN_TERMS.iter()
+        .find(|(terms, _, _)| terms.iter().any(|t| lower.contains(t)))
+        .map(|(_, label, severity)| (Some(*label), *severity))
+        .unwrap_or((None, 10))
+}
+
+/// Drops lines without letters or digits and collapses whitespace.
+fn clean(raw: &str) -> String {
+    let lines: Vec<String> = raw.lines()
+        .map(|l| l.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|l| l.chars().filter(|c| c.is_alphanumeric()).count() >= 2)
+        .collect();
+    let text = lines.join("\n");
+    match text.char_indices().nth(MAX_TEXT_CHARS) {
+        Some((cut, _)) => text[..cut].to_string(),
+        None => text,
+    }
+}
+
+async fn read_text(task_id: &str, filename: &str) -> Result<String, String> {
+    let lang = std::env::var("OCR_LANG").ok().filter(|l| !l.is_empty()).unwrap_or_else(|| "eng".to_string());
+    let mut cmd = match std::env::var("OCR_IMAGE").ok().filter(|i| !i.is_empty()) {
+        Some(image) => {
+            let dir = std::fs::canonicalize("./screenshots").map_err(|e| e.to_string())?;
+            let mut cmd = tokio::process::Command::new("docker");
+            cmd.args(["run", "--rm", "--network", "none", "-v", &format!("{}:/shots:ro", dir.display()), &image, "tesseract"])
+                .arg(format!("/shots/{}/{}", task_id, filename));
+            cmd
+        }
+        None => {
+            let mut cmd = tokio::process::Command::new(std::env::var("TESSERACT_BIN").unwrap_or_else(|_| "tesseract".to_string()));
+            cmd.arg(format!("./screenshots/{}/{}", task_id, filename));
+            cmd
+        }
+    };
+    cmd.args(["stdout", "-l", &lang]).kill_on_drop(true);
+
+    let _slot = slots().acquire().await.map_err(|e| e.to_string())?;
+    let timeout = timeout_secs();
+    let output = tokio::time::timeout(Duration::from_secs(timeout), cmd.output())
+        .await
+        .map_err(|_| format!("timed out after {}s", timeout))?
+        .map_err(|e| format!("could not start Tesseract: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(stderr.lines().last().unwrap_or("Tesseract failed").to_string());
+    }
+    Ok(clean(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Reads one indexed frame, unless it has been read already.
+pub async fn run(pool: &Pool<Postgres>, screenshot_id: i32) -> Result<(), String> {
+    let row: Option<(String, String, i64, Option<i64>)> = sqlx::query_as(
+        "SELECT task_id, filename, timestamp, ocr_at FROM screenshots WHERE id = $1"
+    )
+    .bind(screenshot_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    let Some((task_id, filename, timestamp, None)) = row else { return Ok(()) };
+    if !crate::storage::ensure_local(&format!("screenshots/{}/{}", task_id, filename)).await {
+        return Err("screenshot file is missing".to_string());
+    }
+
+    let (text, error) = match read_text(&task_id, &filename).await {
+        Ok(text) => (Some(text), None),
+        Err(e) => (None, Some(e)),
+    };
+    sqlx::query("UPDATE screenshots SET ocr_text = $1, ocr_error = $2, ocr_at = $3 WHERE id = $4")
+        .bind(&text)
+        .bind(&error)
+        .bind(chrono::Utc::now().timestamp_millis())
+        .bind(screenshot_id)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    if let Some(e) = error {
+        return Err(e);
+    }
+
+    let text = text.unwrap_or_default();
+    if text.is_empty() {
+        return Ok(());
+    }
+    let (label, severity) = assess(&text);
+    let details = match label {
+        Some(label) => format!("Screen text ({}) in {}: {}", label, filename, text),
+        None => format!("Screen text in {}: {}", filename, text),
+    };
+    sqlx::query(
+        "INSERT INTO events (event_type, process_id, parent_process_id, process_name, details, timestamp, task_id, severity, category, kill_chain_stage)
+         VALUES ('SCREEN_TEXT', 0, 0, 'ocr', $1, $2, $3, $4, 'system', $5)"
+    )
+    .bind(&details)
+    .bind(timestamp)
+    .bind(&task_id)
+    .bind(severity)
+    .bind(crate::kill_chain::classify("SCREEN_TEXT", "ocr", &details, None, Some("system")))
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    println!("[OCR] Task {}: {} chars of text in {}", task_id, text.len(), filename);
+    Ok(())
+}
+
+/// Reads a newly indexed frame in the background.
+pub fn queue(pool: &Pool<Postgres>, screenshot_id: i32) {
+    if !enabled() {
+        return;
+    }
+    let pool = pool.clone();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = run(&pool, screenshot_id).await {
+            println!("[OCR] Screenshot {}: {}", screenshot_id, e);
+        }
+    });
+}
+
+/// (timestamp, filename, text) of every frame of the task that had text on it, oldest first.
+pub async fn texts(pool: &Pool<Postgres>, task_id: &str) -> Vec<(i64, String, String)> {
+    sqlx::query_as(
+        "SELECT timestamp, filename, ocr_text FROM screenshots
+         WHERE task_id = $1 AND ocr_text IS NOT NULL AND ocr_text <> ''
+         ORDER BY timestamp, id"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default()
+}
+
+/// Prompt section: what was on screen and when.
+pub fn describe(texts: &[(i64, String, String)]) -> String {
+    let Some(start) = texts.first().map(|t| t.0) else { return "No text read from screenshots.".to_string() };
+    texts.iter().take(MAX_PROMPT_FRAMES).map(|(ts, filename, text)| {
+        let flat = text.replace('\n', " | ");
+        let clipped: String = flat.chars().take(MAX_PROMPT_CHARS).collect();
+        match assess(text).0 {
+            Some(label) => format!("+{}s {} [{}]: {}", (ts - start) / 1000, filename, label, clipped),
+            None => format!("+{}s {}: {}", (ts - start) / 1000, filename, clipped),
+        }
+    }).collect::<Vec<_>>().join("\n")
+}
@@ -0,0 +1,762 @@
+use regex::Regex;
+use serde_json::{json, Value};
+use std::io::{Cursor, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+// --- OFFICE DOCUMENT ANALYSIS ---
+// VBA, XLM macros, embedded objects and external relationships.
+
+/// Source kept per module and per document.
+const MAX_MODULE_BYTES: usize = 1024 * 1024;
+const MAX_SOURCE_BYTES: usize = 4 * 1024 * 1024;
+/// Rounds of concatenation folding; each round joins neighbouring literals.
+const MAX_FOLD_ROUNDS: usize = 64;
+const MAX_IOCS: usize = 100;
+
+/// Macro names Office runs by itself when the document opens, closes or changes.
+const AUTO_EXEC: &[(&str, &str)] = &[
+    ("AutoExec", "Runs when Word starts"),
+    ("AutoOpen", "Runs when the Word document is opened"),
+    ("Document_Open", "Runs when the Word document is opened"),
+    ("DocumentOpen", "Runs when the Word document is opened"),
+    ("AutoNew", "Runs when a new Word document is created"),
+    ("Document_New", "Runs when a new Word document is created"),
+    ("AutoClose", "Runs when the Word document is closed"),
+    ("Document_Close", "Runs when the Word document is closed"),
+    ("Document_BeforeClose", "Runs when the Word document is closed"),
+    ("Document_ContentControlOnEnter", "Runs when a content control is entered"),
+    ("Auto_Open", "Runs when the Excel workbook is opened"),
+    ("Workbook_Open", "Runs when the Excel workbook is opened"),
+    ("Workbook_Activate", "Runs when the Excel workbook is activated"),
+    ("Auto_Close", "Runs when the Excel workbook is closed"),
+    ("Workbook_Close", "Runs when the Excel workbook is closed"),
+    ("Workbook_BeforeClose", "Runs when the Excel workbook is closed"),
+    ("_Layout", "Runs when an ActiveX control is drawn"),
+    ("_Painted", "Runs when an ActiveX control is drawn"),
+    ("_GotFocus", "Runs when an ActiveX control gets focus"),
+    ("_MouseMove", "Runs when the mouse moves over an ActiveX control"),
+];
+
+/// (keyword, what it suggests, high risk). High-risk keywords run, fetch or inject code.
+const SUSPICIOUS: &[(&str, &str, bool)] = &[
+    ("Shell", "May run an executable or system command", true),
+    ("WScript.Shell", "May run an executable or system command", true),
+    ("Shell.Application", "May run an executable or system command", true),
+    ("ShellExecute", "May run an executable or system command", true),
+    ("Run", "May run an executable or system command", false),
+    ("Exec", "May run an executable or system command", false),
+    ("Win32_Process", "May start a process through WMI", true),
+    ("MacScript", "May run AppleScript on macOS", true),
+    ("CreateObject", "May create an OLE object", false),
+    ("GetObject", "May get an OLE object (WMI, running applications)", false),
+    ("CallByName", "May call a function by name to hide it", true),
+    ("Lib", "May declare a function from a DLL", false),
+    ("VirtualAlloc", "May inject code into memory", true),
+    ("RtlMoveMemory", "May inject code into memory", true),
+    ("WriteProcessMemory", "May inject code into another process", true),
+    ("CreateThread", "May run injected code", true),
+    ("CreateRemoteThread", "May run code in another process", true),
+    ("EnumSystemLanguageGroupsW", "May run shellcode through a callback", true),
+    ("URLDownloadToFile", "May download files from the Internet", true),
+    ("Msxml2.XMLHTTP", "May download files from the Internet", true),
+    ("Microsoft.XMLHTTP", "May download files from the Internet", true),
+    ("WinHttp.WinHttpRequest", "May download files from the Internet", true),
+    ("Net.WebClient", "May download files from the Internet", true),
+    ("DownloadFile", "May download files from the Internet", true),
+    ("DownloadString", "May download files from the Internet", true),
+    ("ADODB.Stream", "May write a binary file to disk", false),
+    ("SaveToFile", "May write a file to disk", false),
+    ("CreateTextFile", "May create a text file", false),
+    ("FileCopy", "May copy a file", false),
+    ("CopyFile", "May copy a file", false),
+    ("Kill", "May delete a file", false),
+    ("Environ", "May read environment variables", false),
+    ("RegWrite", "May write to the registry", false),
+    ("RegRead", "May read the registry", false),
+    ("PowerShell", "May run PowerShell commands", true),
+    ("cmd.exe", "May run a Windows command shell", true),
+    ("mshta", "May run an HTML application", true),
+    ("certutil", "May decode or download with certutil", true),
+    ("bitsadmin", "May download with BITS", true),
+    ("regsvr32", "May load a DLL or scriptlet", true),
+    ("rundll32", "May run a DLL export", true),
+    ("schtasks", "May create a scheduled task", true),
+    ("wmic", "May run WMI commands", true),
+    ("ExecuteExcel4Macro", "May run Excel 4.0 macro code", true),
+    ("VBProject", "May modify its own VBA project", false),
+    ("AddFromString", "May inject VBA code", true),
+    ("CustomDocumentProperties", "May hide data in document properties", false),
+    ("Document.Variables", "May hide data in document variables", false),
+    ("StrReverse", "May hide strings by reversing them", false),
+    ("Chr", "May hide strings as character codes", false),
+    ("ChrW", "May hide strings as character codes", false),
+    ("Xor", "May decode data with XOR", false),
+    ("Base64", "May decode Base64 data", false),
+    ("Hex", "May decode hex strings", false),
+];
+
+/// Objects that are exploits or droppers by themselves when embedded.
+const RISKY_OBJECT_CLASSES: &[(&str, &str)] = &[
+    ("equation", "Equation Editor object (CVE-2017-11882 / CVE-2018-0802)"),
+    ("package", "OLE Package (embedded file)"),
+    ("htmlfile", "HTML file object"),
+    ("script", "Scriptlet object"),
+];
+
+/// Analysis of one Office document.
+pub struct Report {
+    pub properties: Value,
+    /// Deobfuscated VBA and XLM source, one section per module, when the document has any.
+    pub macros: Option<String>,
+}
+
+struct Module {
+    name: String,
+    source: String,
+}
+
+#[derive(Default)]
+struct Findings {
+    container: &'static str,
+    modules: Vec<Module>,
+    xlm_sheets: Vec<Value>,
+    xlm_formulas: Vec<String>,
+    xlm_auto_open: bool,
+    objects: Vec<Value>,
+    external: Vec<Value>,
+}
+
+// ---- MS-OVBA decompression ----
+
+/// MS-OVBA decompression; truncated input yields what came before the damage.
+fn decompress(data: &[u8]) -> Option<Vec<u8>> {
+    if data.first() != Some(&1) {
+        return None;
+    }
+    let mut out: Vec<u8> = Vec::with_capacity(data.len() * 2);
+    let mut pos = 1;
+    while pos + 2 <= data.len() {
+        let header = u16::from_le_bytes([data[pos], data[pos + 1]]);
+        let chunk_end = (pos + (header & 0x0FFF) as usize + 3).min(data.len());
+        pos += 2;
+        let chunk_start = out.len();
+        if header & 0x8000 == 0 {
+            // Stored uncompressed: always 4096 bytes
+            let end = (pos + 4096).min(data.len());
+            out.extend_from_slice(&data[pos..end]);
+            pos = end;
+            continue;
+        }
+        while pos < chunk_end {
+            let flags = data[pos];
+            pos += 1;
+            for bit in 0..8 {
+                if pos >= chunk_end {
+                    break;
+                }
+                if flags & (1 << bit) == 0 {
+                    out.push(data[pos]);
+                    pos += 1;
+                    continue;
+                }
+                if pos + 2 > chunk_end {
+                    return Some(out);
+                }
+                let token = u16::from_le_bytes([data[pos], data[pos + 1]]) as usize;
+                pos += 2;
+                let decompressed = (out.len() - chunk_start).max(1);
+                let bit_count = (usize::BITS - (decompressed - 1).leading_zeros()).max(4) as usize;
+                let length_mask = 0xFFFF >> bit_count;
+                let length = (token & length_mask) + 3;
+                let offset = (token >> (16 - bit_count)) + 1;
+                if offset > out.len() - chunk_start {
+                    return Some(out);
+                }
+                let from = out.len() - offset;
+                for i in 0..length {
+                    out.push(out[from + i]);
+                }
+            }
+        }
+        pos = chunk_end;
+    }
+    Some(out)
+}
+
+// ---- VBA projects ----
+
+fn read_stream<F: Read + Seek>(cf: &mut cfb::CompoundFile<F>, path: &Path) -> Option<Vec<u8>> {
+    let mut stream = cf.open_stream(path).ok()?;
+    let mut data = Vec::new();
+    stream.read_to_end(&mut data).ok()?;
+    Some(data)
+}
+
+/// (module name, stream name, source offset) from the decompressed `dir` stream.
+fn dir_modules(dir: &[u8]) -> Vec<(String, String, Option<usize>)> {
+    let mut modules = Vec::new();
+    let mut current: Option<(String, String, Option<usize>)> = None;
+    let mut pos = 0;
+    while pos + 6 <= dir.len() {
+        let id = u16::from_le_bytes([dir[pos], dir[pos + 1]]);
+        let mut size = u32::from_le_bytes([dir[pos + 2], dir[pos + 3], dir[pos + 4], dir[pos + 5]]) as usize;
+        // PROJECTVERSION declares 4 bytes but carries 6
+        if id == 0x0009 {
+            size = 6;
+        }
+        let start = pos + 6;
+        let Some(data) = dir.get(start..start + size) else { break };
+        match id {
+            // MODULENAME opens a module record
+            0x0019 => current = Some((String::from_utf8_lossy(data).to_string(), String::new(), None)),
+            0x001A => {
+                if let Some(m) = current.as_mut() {
+                    m.1 = String::from_utf8_lossy(data).to_string();
+                }
+            }
+            0x0031 if size == 4 => {
+                if let Some(m) = current.as_mut() {
+                    m.2 = Some(u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize);
+                }
+            }
+            // Module terminator
+            0x002B => modules.extend(current.take()),
+            _ => {}
+        }
+        pos = start + size;
+    }
+    modules
+}
+
+fn clip_source(mut source: String, limit: usize) -> String {
+    if source.len() > limit {
+        let mut cut = limit;
+        while !source.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        source.truncate(cut);
+        source.push_str("\n' ... [truncated]");
+    }
+    source
+}
+
+/// Module source at the `dir` offset, else wherever "Attribute VB_Name" is found.
+fn module_source(stream: &[u8], offset: Option<usize>) -> Option<String> {
+    let start = match offset {
+        Some(o) if stream.get(o) == Some(&1) => o,
+        _ => stream.windows(9).position(|w| w == b"\x00Attribut").and_then(|p| p.checked_sub(3))?,
+    };
+    let source = decompress(&stream[start..])?;
+    Some(clip_source(String::from_utf8_lossy(&source).replace("\r\n", "\n"), MAX_MODULE_BYTES))
+}
+
+/// Every VBA project in the compound file: a storage with a `dir` stream inside a `VBA` storage.
+fn vba_modules<F: Read + Seek>(cf: &mut cfb::CompoundFile<F>) -> Vec<Module> {
+    let projects: Vec<PathBuf> = cf.walk()
+        .filter(|e| e.is_stream() && e.name().eq_ignore_ascii_case("dir"))
+        .filter_map(|e| e.path().parent().map(Path::to_path_buf))
+        .filter(|p| p.file_name().is_some_and(|n| n.to_string_lossy().eq_ignore_ascii_case("VBA")))
+        .collect();
+
+    let mut modules = Vec::new();
+    for vba in projects {
+        let listed = read_stream(cf, &vba.join("dir")).and_then(|d| decompress(&d)).map(|d| dir_modules(&d)).unwrap_or_default();
+        let streams: Vec<(String, Option<usize>, String)> = if listed.is_empty() {
+            // Damaged dir: try every stream that is not project metadata
+            cf.read_storage(&vba).map(|entries| entries
+                .filter(|e| e.is_stream())
+                .map(|e| e.name().to_string())
+                .filter(|n| !n.eq_ignore_ascii_case("dir") && !n.starts_with("_VBA_PROJECT") && !n.starts_with("__SRP_"))
+                .map(|n| (n.clone(), None, n))
+                .collect()).unwrap_or_default()
+        } else {
+            listed.into_iter().map(|(name, stream, offset)| (stream, offset, name)).collect()
+        };
+        for (stream, offset, name) in streams {
+            let Some(data) = read_stream(cf, &vba.join(&stream)) else { continue };
+            if let Some(source) = module_source(&data, offset) {
+                modules.push(Module { name, source });
+            }
+        }
+    }
+    modules
+}
+
+// ---- Excel 4.0 macros in BIFF8 workbooks ----
+
+fn biff_records(data: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    let mut pos = 0;
+    std::iter::from_fn(move || {
+        let header = data.get(pos..pos + 4)?;
+        let kind = u16::from_le_bytes([header[0], header[1]]);
+        let len = u16::from_le_bytes([header[2], header[3]]) as usize;
+        let body = data.get(pos + 4..pos + 4 + len)?;
+        pos += 4 + len;
+        Some((kind, body))
+    })
+}
+
+fn sheet_state(state: u8) -> &'static str {
+    match state & 0x03 {
+        0 => "visible",
+        1 => "hidden",
+        _ => "very hidden",
+    }
+}
+
+/// Macro sheets (BOUNDSHEET) and the Auto_Open name (NAME); formulas stay undecoded.
+fn biff_xlm(workbook: &[u8], findings: &mut Findings) {
+    for (kind, body) in biff_records(workbook) {
+        match kind {
+            0x0085 if body.len() >= 8 => {
+                // BOUNDSHEET: position, visibility, sheet type (1 = macro sheet), name
+                if body[5] != 0x01 {
+                    continue;
+                }
+                let len = body[6] as usize;
+                let name = if body[7] & 1 == 0 {
+                    body.get(8..8 + len).map(|b| String::from_utf8_lossy(b).to_string())
+                } else {
+                    body.get(8..8 + len * 2).map(|b| {
+                        String::from_utf16_lossy(&b.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect::<Vec<_>>())
+                    })
+                };
+                findings.xlm_sheets.push(json!({ "name": name.unwrap_or_default(), "state": sheet_state(body[4]) }));
+            }
+            0x0018 if body.len() >= 16 => {
+                // NAME: built-in names are a single character code; 0x01 is Auto_Open
+                let builtin = u16::from_le_bytes([body[0], body[1]]) & 0x0020 != 0;
+                if builtin && body[15] == 0x01 {
+                    findings.xlm_auto_open = true;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// ---- Embedded objects in compound files ----
+
+fn cstring(data: &[u8], pos: &mut usize) -> Option<String> {
+    let len = data.get(*pos..)?.iter().position(|&b| b == 0)?;
+    let s = String::from_utf8_lossy(&data[*pos..*pos + len]).to_string();
+    *pos += len + 1;
+    Some(s)
+}
+
+/// The label and original path of a file embedded as an OLE Package.
+fn ole10_native(data: &[u8]) -> Value {
+    // Total size (4), type (2), then NUL-terminated label and source path
+    let mut pos = 6;
+    let label = cstring(data, &mut pos);
+    let path = cstring(data, &mut pos);
+    json!({ "type": "package", "label": label, "path": path, "size": data.len() })
+}
+
+/// AnsiUserType from a CompObj stream ("Microsoft Equation 3.0", "Package", ...).
+fn comp_obj_type(data: &[u8]) -> Option<String> {
+    let len = u32::from_le_bytes(data.get(28..32)?.try_into().ok()?) as usize;
+    let raw = data.get(32..32 + len)?;
+    Some(String::from_utf8_lossy(raw).trim_end_matches('\0').to_string())
+}
+
+fn ole_objects<F: Read + Seek>(cf: &mut cfb::CompoundFile<F>, findings: &mut Findings) {
+    let entries: Vec<(PathBuf, String)> = cf.walk()
+        .filter(|e| e.is_stream())
+        .map(|e| (e.path().to_path_buf(), e.name().to_string()))
+        .collect();
+    for (path, name) in entries {
+        if name == "\u{1}Ole10Native" {
+            if let Some(data) = read_stream(cf, &path) {
+                findings.objects.push(ole10_native(&data));
+            }
+        } else if name == "\u{1}CompObj" && path.parent().is_some_and(|p| p != Path::new("/")) {
+            // Root CompObj describes the document itself
+            if let Some(class) = read_stream(cf, &path).and_then(|d| comp_obj_type(&d)) {
+                let storage = path.parent().map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+                findings.objects.push(json!({ "type": "ole", "class": class, "storage": storage }));
+            }
+        }
+    }
+}
+
+/// Word, Excel or PowerPoint binary formats; None for other compound files.
+fn analyze_ole(bytes: &[u8], findings: &mut Findings) -> bool {
+    let Ok(mut cf) = cfb::CompoundFile::open(Cursor::new(bytes)) else { return false };
+    let is_document = ["WordDocument", "Workbook", "Book", "PowerPoint Document"]
+        .iter()
+        .any(|s| cf.is_stream(Path::new("/").join(s)));
+    findings.modules.extend(vba_modules(&mut cf));
+    for name in ["Workbook", "Book"] {
+        if let Some(workbook) = read_stream(&mut cf, &Path::new("/").join(name)) {
+            biff_xlm(&workbook, findings);
+        }
+    }
+    ole_objects(&mut cf, findings);
+    is_document || !findings.modules.is_empty()
+}
+
+// ---- OOXML ----
+
+fn xml_unescape(s: &str) -> String {
+    s.replace("&quot;", "\"").replace("&apos;", "'").replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&")
+}
+
+fn formula_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?s)<(?:\w+:)?f(?:\s[^>]*)?>(.*?)</(?:\w+:)?f>").unwrap())
+}
+
+fn external_target_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r#"<Relationship\b[^>]*>"#).unwrap())
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let re = Regex::new(&format!(r#"\b{}\s*=\s*"([^"]*)""#, regex::escape(name))).ok()?;
+    re.captures(tag).map(|c| xml_unescape(&c[1]))
+}
+
+fn zip_entry<R: Read + Seek>(zip: &mut zip::ZipArchive<R>, index: usize, limit: usize) -> Option<(String, Vec<u8>)> {
+    let entry = zip.by_index(index).ok()?;
+    let name = entry.name().to_string();
+    let mut data = Vec::new();
+    entry.take(limit as u64).read_to_end(&mut data).ok()?;
+    Some((name, data))
+}
+
+/// An OOXML document (docx/docm/xlsx/xlsm/pptx). None for zips that are not Office files.
+fn analyze_ooxml(bytes: &[u8], findings: &mut Findings) -> bool {
+    let Ok(mut zip) = zip::ZipArchive::new(Cursor::new(bytes)) else { return false };
+    if zip.by_name("[Content_Types].xml").is_err() {
+        return false;
+    }
+    for i in 0..zip.len() {
+        let Some(name) = zip.by_index(i).ok().map(|e| e.name().to_string()) else { continue };
+        let lower = name.to_lowercase();
+        if lower.ends_with("vbaproject.bin") {
+            let Some((_, data)) = zip_entry(&mut zip, i, MAX_SOURCE_BYTES * 4) else { continue };
+            if let Ok(mut cf) = cfb::CompoundFile::open(Cursor::new(data.as_slice())) {
+                findings.modules.extend(vba_modules(&mut cf));
+            }
+        } else if lower.contains("/macrosheets/") && lower.ends_with(".xml") {
+            let Some((_, data)) = zip_entry(&mut zip, i, MAX_SOURCE_BYTES) else { continue };
+            let xml = String::from_utf8_lossy(&data);
+            let sheet = name.rsplit('/').next().unwrap_or(&name).to_string();
+            findings.xlm_sheets.push(json!({ "name": sheet, "state": "unknown" }));
+            findings.xlm_formulas.extend(formula_re().captures_iter(&xml).map(|c| format!("{}: ={}", sheet, xml_unescape(&c[1]))));
+        } else if lower == "xl/workbook.xml" {
+            let Some((_, data)) = zip_entry(&mut zip, i, MAX_SOURCE_BYTES) else { continue };
+            let xml = String::from_utf8_lossy(&data);
+            if xml.contains("_xlnm.Auto_Open") || xml.contains("name=\"Auto_Open") {
+                findings.xlm_auto_open = true;
+            }
+        } else if lower.contains("/embeddings/") || (lower.contains("/activex/") && lower.ends_with(".bin")) {
+            let size = zip.by_index(i).map(|e| e.size()).unwrap_or(0);
+            let kind = if lower.contains("/activex/") { "activex" } else { "embedding" };
+            let mut object = json!({ "type": kind, "path": name, "size": size });
+            if let Some((_, data)) = zip_entry(&mut zip, i, MAX_SOURCE_BYTES * 4) {
+                if let Ok(mut cf) = cfb::CompoundFile::open(Cursor::new(data.as_slice())) {
+                    let mut inner = Findings::default();
+                    ole_objects(&mut cf, &mut inner);
+                    object["contains"] = json!(inner.objects);
+                    findings.modules.extend(vba_modules(&mut cf));
+                }
+            }
+            findings.objects.push(object);
+        } else if lower.ends_with(".rels") {
+            let Some((_, data)) = zip_entry(&mut zip, i, MAX_SOURCE_BYTES) else { continue };
+            let xml = String::from_utf8_lossy(&data);
+            for tag in external_target_re().find_iter(&xml).map(|m| m.as_str()) {
+                if attribute(tag, "TargetMode").is_some_and(|m| m.eq_ignore_ascii_case("External")) {
+                    let kind = attribute(tag, "Type").and_then(|t| t.rsplit('/').next().map(str::to_string)).unwrap_or_default();
+                    let target = attribute(tag, "Target").unwrap_or_default();
+                    // Hyperlinks are ordinary; remote templates and OLE links load on open
+                    if kind != "hyperlink" {
+                        findings.external.push(json!({ "part": name, "type": kind, "target": target }));
+                    }
+                }
+            }
+        }
+    }
+    true
+}
+
+// ---- RTF ----
+
+fn rtf_objclass_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\\objclass\s+([^\\}\s;]+)").unwrap())
+}
+
+fn hex_decode(s: &str) -> Vec<u8> {
+    let digits: Vec<u8> = s.bytes().filter(u8::is_ascii_hexdigit).collect();
+    digits.chunks_exact(2)
+        .filter_map(|p| u8::from_str_radix(std::str::from_utf8(p).ok()?, 16).ok())
+        .collect()
+}
+
+/// \object groups: class, whether they load on open, and what \objdata holds.
+fn analyze_rtf(bytes: &[u8], findings: &mut Findings) -> bool {
+    let text = String::from_utf8_lossy(bytes);
+    for (at, _) in text.match_indices("\\object") {
+        let rest = &text[at..];
+        if rest.starts_with("\\objectdata") {
+            continue;
+        }
+        // The group runs to the next \object or a generous window
+        let end = rest[1..].find("\\object").map(|e| e + 1).unwrap_or(rest.len()).min(4 * 1024 * 1024);
+        let group = &rest[..end];
+        let class = rtf_objclass_re().captures(group).map(|c| c[1].to_string());
+        let data = group.find("\\objdata").map(|d| {
+            let blob = &group[d + "\\objdata".len()..];
+            hex_decode(&blob[..blob.find('}').unwrap_or(blob.len())])
+        }).unwrap_or_default();
+        let lower = String::from_utf8_lossy(&data).to_lowercase();
+        // OLE1 objects name their class inside the data as well
+        let embedded_class = ["equation.3", "equation.2", "package", "htmlfile", "word.document", "excel.sheet"]
+            .iter().find(|c| lower.contains(*c)).map(|c| c.to_string());
+        findings.objects.push(json!({
+            "type": "rtf_object",
+            "class": class.or(embedded_class),
+            "auto_update": group.contains("\\objupdate"),
+            "data_size": data.len(),
+            "contains_ole": data.windows(8).any(|w| w == [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]),
+        }));
+    }
+    true
+}
+
+// ---- Deobfuscation and keyword scan ----
+
+fn chr_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?i)\bChr[WB]?\$?\(\s*(&H[0-9a-f]+|\d+)\s*(?:([+\-*])\s*(&H[0-9a-f]+|\d+)\s*)?\)").unwrap())
+}
+
+fn concat_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r#""((?:[^"\n]|"")*)"\s*[&+]\s*(?:_\s*\n\s*)?"((?:[^"\n]|"")*)""#).unwrap())
+}
+
+fn str_reverse_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r#"(?i)\bStrReverse\(\s*"((?:[^"\n]|"")*)"\s*\)"#).unwrap())
+}
+
+fn vba_number(s: &str) -> Option<i64> {
+    match s.get(..2) {
+        Some(p) if p.eq_ignore_ascii_case("&h") => i64::from_str_radix(&s[2..], 16).ok(),
+        _ => s.parse().ok(),
+    }
+}
+
+/// Folds Chr(), StrReverse and concatenations of literals into plain string literals.
+pub fn deobfuscate(source: &str) -> String {
+    let mut text = chr_re().replace_all(source, |c: &regex::Captures| {
+        let a = vba_number(&c[1]);
+        let value = match (a, c.get(2), c.get(3).and_then(|b| vba_number(b.as_str()))) {
+            (Some(a), Some(op), Some(b)) => match op.as_str() {
+                "+" => Some(a + b),
+                "-" => Some(a - b),
+                _ => Some(a * b),
+            },
+            (Some(a), None, _) => Some(a),
+            _ => None,
+        };
+        match value.and_then(|v| u32::try_from(v).ok()).and_then(char::from_u32) {
+            Some('"') => "\"\"\"\"".to_string(),
+            Some(ch) if !ch.is_control() || ch == '\t' => format!("\"{}\"", ch),
+            _ => c[0].to_string(),
+        }
+    }).to_string();
+    for _ in 0..MAX_FOLD_ROUNDS {
+        let folded = str_reverse_re().replace_all(&text, |c: &regex::Captures| {
+            format!("\"{}\"", c[1].replace("\"\"", "\"").chars().rev().collect::<String>().replace('"', "\"\""))
+        });
+        let folded = concat_re().replace_all(&folded, "\"$1$2\"").to_string();
+        if folded == text {
+            break;
+        }
+        text = folded;
+    }
+    text
+}
+
+fn keyword_re(keyword: &str) -> Regex {
+    Regex::new(&format!(r"(?i)(?:^|[^\w]){}(?:$|[^\w])", regex::escape(keyword))).unwrap()
+}
+
+fn keyword_patterns() -> &'static Vec<(Regex, &'static str, &'static str, bool)> {
+    static RE: OnceLock<Vec<(Regex, &'static str, &'static str, bool)>> = OnceLock::new();
+    RE.get_or_init(|| SUSPICIOUS.iter().map(|&(k, d, high)| (keyword_re(k), k, d, high)).collect())
+}
+
+fn auto_exec_patterns() -> &'static Vec<(Regex, &'static str, &'static str)> {
+    static RE: OnceLock<Vec<(Regex, &'static str, &'static str)>> = OnceLock::new();
+    RE.get_or_init(|| AUTO_EXEC.iter().map(|&(k, d)| {
+        // Event handlers on controls are suffixes of the control's name
+        let re = if k.starts_with('_') {
+            Regex::new(&format!(r"(?i)\bSub\s+\w+{}\b", regex::escape(k))).unwrap()
+        } else {
+            keyword_re(k)
+        };
+        (re, k, d)
+    }).collect())
+}
+
+fn ioc_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(
+        r#"(?i)\bhttps?://[^\s"'<>()]+|\b(?:\d{1,3}\.){3}\d{1,3}\b|\b[\w\-]+\.(?:exe|dll|scr|pif|vbs|vbe|js|jse|ps1|bat|cmd|hta|jar|lnk)\b"#
+    ).unwrap())
+}
+
+fn risky_class(class: &str) -> Option<&'static str> {
+    let lower = class.to_lowercase();
+    RISKY_OBJECT_CLASSES.iter().find(|(c, _)| lower.contains(c)).map(|(_, d)| *d)
+}
+
+fn report(findings: Findings) -> Report {
+    let mut source = String::new();
+    for module in &findings.modules {
+        source.push_str(&format!("' ===== VBA module: {} =====\n{}\n\n", module.name, deobfuscate(&module.source)));
+    }
+    if !findings.xlm_formulas.is_empty() {
+        source.push_str("' ===== Excel 4.0 (XLM) formulas =====\n");
+        source.push_str(&findings.xlm_formulas.join("\n"));
+        source.push('\n');
+    }
+    let source = clip_source(source, MAX_SOURCE_BYTES);
+    let raw: String = findings.modules.iter().map(|m| m.source.as_str()).collect::<Vec<_>>().join("\n");
+    let scanned = format!("{}\n{}", raw, source);
+
+    let auto_exec: Vec<Value> = auto_exec_patterns().iter()
+        .filter(|(re, _, _)| re.is_match(&scanned))
+        .map(|(_, k, d)| json!({ "keyword": k, "description": d }))
+        .collect();
+    let suspicious: Vec<Value> = keyword_patterns().iter()
+        .filter(|(re, _, _, _)| re.is_match(&scanned))
+        .map(|(_, k, d, high)| json!({ "keyword": k, "description": d, "high_risk": high }))
+        .collect();
+    let mut iocs: Vec<String> = Vec::new();
+    for m in ioc_re().find_iter(&scanned) {
+        let ioc = m.as_str().trim_end_matches(['.', ',', ';']).to_string();
+        if !iocs.contains(&ioc) && iocs.len() < MAX_IOCS {
+            iocs.push(ioc);
+        }
+    }
+
+    let mut flags: Vec<String> = Vec::new();
+    let high_risk_keywords = suspicious.iter().filter(|s| s["high_risk"] == true).count();
+    if !auto_exec.is_empty() && high_risk_keywords > 0 {
+        flags.push("macro runs on open and executes or downloads code".to_string());
+    }
+    if findings.xlm_auto_open && !findings.xlm_sheets.is_empty() {
+        flags.push("Excel 4.0 macro sheet started by Auto_Open".to_string());
+    }
+    if findings.xlm_sheets.iter().any(|s| s["state"] == "very hidden") {
+        flags.push("very hidden macro sheet".to_string());
+    }
+    for object in &findings.objects {
+        if let Some(desc) = object["class"].as_str().and_then(risky_class) {
+            flags.push(desc.to_string());
+        }
+        if object["type"] == "package" {
+            flags.push(format!("embedded file {}", object["label"].as_str().unwrap_or("(unnamed)")));
+        }
+        if object["auto_update"] == true {
+            flags.push("RTF object updates automatically on open".to_string());
+        }
+    }
+    for rel in &findings.external {
+        flags.push(format!("external {} loaded from {}", rel["type"].as_str().unwrap_or("relationship"), rel["target"].as_str().unwrap_or("")));
+    }
+    flags.dedup();
+
+    let has_macros = !findings.modules.is_empty() || !findings.xlm_sheets.is_empty();
+    let risk = if !flags.is_empty() {
+        "high"
+    } else if has_macros && (!auto_exec.is_empty() || !suspicious.is_empty()) || !findings.objects.is_empty() {
+        "medium"
+    } else if has_macros {
+        "low"
+    } else {
+        "none"
+    };
+
+    let modules: Vec<Value> = findings.modules.iter().map(|m| json!({
+        "name": m.name,
+        "lines": m.source.lines().count(),
+        "size": m.source.len(),
+    })).collect();
+    let properties = json!({
+        "container": findings.container,
+        "has_macros": has_macros,
+        "vba_modules": modules,
+        "xlm_macro_sheets": findings.xlm_sheets,
+        "xlm_formula_count": findings.xlm_formulas.len(),
+        "xlm_auto_open": findings.xlm_auto_open,
+        "auto_exec": auto_exec,
+        "suspicious": suspicious,
+        "iocs": iocs,
+        "embedded_objects": findings.objects,
+        "external_relationships": findings.external,
+        "risk": risk,
+        "risk_flags": flags,
+    });
+    Report { properties, macros: (!source.is_empty()).then_some(source) }
+}
+
+/// Office analysis of an OLE, OOXML or RTF file; None when it is not an Office document.
+pub fn analyze(bytes: &[u8], format: &str) -> Option<Report> {
+    let mut findings = Findings::default();
+    let is_office = match format {
+        "ole" => {
+            findings.container = "ole";
+            analyze_ole(bytes, &mut findings)
+        }
+        "zip" => {
+            findings.container = "ooxml";
+            analyze_ooxml(bytes, &mut findings)
+        }
+        "rtf" => {
+            findings.container = "rtf";
+            analyze_rtf(bytes, &mut findings)
+        }
+        _ => false,
+    };
+    is_office.then(|| report(findings))
+}
+
+/// One paragraph for the AI prompt.
+pub fn describe(props: &Value) -> String {
+    let names = |key: &str, field: &str| -> Vec<String> {
+        props[key].as_array().map(|a| a.iter().filter_map(|v| v[field].as_str().map(str::to_string)).collect()).unwrap_or_default()
+    };
+    let mut out = format!("Office document ({}), risk {}.", props["container"].as_str().unwrap_or("?"), props["risk"].as_str().unwrap_or("none"));
+    let modules = names("vba_modules", "name");
+    if !modules.is_empty() {
+        out.push_str(&format!(" VBA modules: {}.", modules.join(", ")));
+    }
+    let sheets = names("xlm_macro_sheets", "name");
+    if !sheets.is_empty() {
+        out.push_str(&format!(" XLM macro sheets: {} ({} formulas).", sheets.join(", "), props["xlm_formula_count"]));
+    }
+    let auto = names("auto_exec", "keyword");
+    if !auto.is_empty() {
+        out.push_str(&format!(" Auto-exec: {}.", auto.join(", ")));
+    }
+    let suspicious = names("suspicious", "keyword");
+    if !suspicious.is_empty() {
+        out.push_str(&format!(" Suspicious keywords: {}.", suspicious.join(", ")));
+    }
+    if let Some(flags) = props["risk_flags"].as_array().filter(|f| !f.is_empty()) {
+        out.push_str(&format!(" Flags: {}.", flags.iter().filter_map(Value::as_str).collect::<Vec<_>>().join("; ")));
+    }
+    if let Some(iocs) = props["iocs"].as_array().filter(|i| !i.is_empty()) {
+        out.push_str(&format!(" IOCs in macros: {}.", iocs.iter().take(20).filter_map(Value::as_str).collect::<Vec<_>>().join(", ")));
+    }
+    out
+}
@@ -0,0 +1,294 @@
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::{ResponseBuilder, SecurityRequirement};
+use utoipa::{Modify, OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
+
+// --- OPENAPI ---
+// New endpoints need an entry in `paths(...)` below.
+
+pub const SPEC_PATH: &str = "/api-docs/openapi.json";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "TheVooDooBox API", description = "Sandbox orchestration, telemetry and analysis backend (hyper-bridge)."),
+    servers((url = "/api/v1")),
+    paths(
+        crate::health::health_check,
+        crate::health::readiness,
+        crate::auth::whoami,
+        crate::auth::issue_token,
+        crate::auth::list_users,
+        crate::auth::create_user,
+        crate::auth::set_user_role,
+        crate::auth::list_api_keys,
+        crate::auth::create_api_key,
+        crate::auth::revoke_api_key,
+        crate::list_all_vms,
+        crate::vm_control,
+        crate::vm_revert,
+        crate::vm_snapshots,
+        crate::vnc_proxy,
+        crate::vnc_websocket,
+        crate::spice_proxy,
+        crate::spice_websocket,
+        crate::terminate_process,
+        crate::exec_url,
+        crate::ai_insight_handler,
+        crate::chat_handler,
+        crate::list_tasks,
+        crate::delete_task,
+        crate::purge_all,
+        crate::pivot_binary,
+        crate::pivot_upload,
+        crate::exec_binary,
+        crate::set_defenses,
+        crate::submit_sample,
+        crate::upload_screenshot,
+        crate::list_screenshots,
+        crate::upload_memory_dump,
+        crate::list_memory_dumps,
+        crate::download_memory_dump,
+        crate::screenshots::get_screenshot_file,
+        crate::ghidra_analyze,
+        crate::ghidra_functions,
+        crate::ghidra_decompile,
+        crate::ghidra_ingest,
+        crate::ghidra_ingest_complete,
+        crate::ghidra_list_scripts,
+        crate::ghidra_run_script,
+        crate::get_ghidra_findings,
+        crate::get_ai_report,
+        crate::trigger_task_analysis,
+        crate::get_telemetry_history,
+        crate::update_task_verdict,
+        crate::generate_pdf_report,
+        crate::notes::add_note,
+        crate::notes::get_notes,
+        crate::notes::add_tag,
+        crate::notes::get_tags,
+        crate::noise_filters::list_noise_filters,
+        crate::noise_filters::add_noise_filter,
+        crate::noise_filters::delete_noise_filter,
+        crate::severity_rules::list_severity_rules,
+        crate::severity_rules::add_severity_rule,
+        crate::severity_rules::delete_severity_rule,
+        crate::agent_updates::upload_release,
+        crate::agent_updates::list_releases,
+        crate::agent_updates::push_update,
+        crate::set_ai_config,
+        crate::get_ai_config,
+        crate::set_ai_mode,
+        crate::get_ai_mode_handler,
+        crate::ai_usage::get_ai_usage,
+        crate::ai_usage::get_task_ai_usage,
+        crate::action_manager::list_task_actions,
+        crate::action_manager::approve_action,
+        crate::action_manager::reject_action,
+        crate::detox_api::detox_dashboard,
+        crate::detox_api::detox_extensions,
+        crate::detox_api::detox_extension_detail,
+        crate::detox_api::detox_trigger_scan,
+        crate::detox_api::detox_trigger_scrape,
+        crate::detox_api::detox_trigger_scan_pending,
+        crate::detox_api::detox_blocklist,
+        crate::detox_api::detox_submit_sandbox,
+        crate::detox_api::detox_delete_extension,
+        crate::detox_api::detox_purge_all,
+        crate::detox_api::detox_kill_processing,
+        crate::task_queue::list_queue,
+        crate::task_queue::cancel_task,
+        crate::task_queue::rerun_task,
+        crate::comparison::get_comparison,
+        crate::batch::submit_batch,
+        crate::dedup::lookup_hash,
+        crate::analysis_profiles::list_profiles,
+        crate::analysis_profiles::get_profile,
+        crate::analysis_profiles::save_profile,
+        crate::analysis_profiles::delete_profile,
+        crate::suricata::list_network_alerts,
+        crate::suricata::upload_pcap,
+        crate::email::get_email_analysis,
+        crate::volatility::get_memory_analysis,
+        crate::volatility::dump_memory,
+        crate::process_tree::get_process_tree,
+        crate::timeline::get_timeline,
+        crate::network_graph::get_network_graph,
+        crate::iocs::get_iocs,
+        crate::stix::export_stix,
+        crate::misp::export_misp,
+        crate::event_export::export_events,
+        crate::metrics::get_metrics,
+        crate::audit::list_audit_log,
+        crate::retention::get_retention,
+        crate::retention::run_retention,
+        crate::storage::presign,
+        crate::storage::migrate,
+        crate::sample_download::download_sample,
+        crate::sample_download::guest_sample,
+        crate::screenshots::get_task_screenshots,
+        crate::sandbox_pool::list_pool,
+        crate::sandbox_pool::register_pool_vm,
+        crate::golden_image::build_golden_image,
+        crate::golden_image::delete_golden_image,
+        crate::golden_image::list_golden_image_jobs,
+        crate::golden_image::get_golden_image_job,
+        crate::sandbox_pool::update_pool_vm,
+        crate::sandbox_pool::remove_pool_vm,
+        crate::schedules::list_schedules,
+        crate::schedules::create_schedule,
+        crate::schedules::get_schedule,
+        crate::schedules::update_schedule,
+        crate::schedules::delete_schedule,
+        crate::schedules::run_schedule_now,
+        crate::url_analysis::upload_url_artifacts,
+        crate::url_analysis::get_url_artifacts,
+        crate::url_analysis::download_url_payload,
+        crate::url_analysis::download_url_artifact,
+        crate::chunked_upload::create_upload,
+        crate::chunked_upload::get_upload,
+        crate::chunked_upload::upload_chunk,
+        crate::chunked_upload::complete_upload,
+        crate::chunked_upload::cancel_upload,
+        crate::bundle::export_bundle,
+        crate::bundle::import_bundle,
+        crate::relationships::get_relationships,
+        crate::relationships::get_family,
+        crate::relationships::create_relationship,
+        crate::relationships::delete_relationship,
+        crate::kill_chain::get_kill_chain,
+        crate::sigma::list_sigma_rules,
+        crate::sigma::add_sigma_rule,
+        crate::sigma::update_sigma_rule,
+        crate::sigma::delete_sigma_rule,
+        crate::alerts::get_task_alerts,
+        crate::alerts::list_alerts,
+        crate::alerts::bulk_alert_action,
+        crate::alerts::acknowledge_alert,
+        crate::alerts::close_alert,
+        crate::alerts::reopen_alert,
+        crate::scoring::get_task_score,
+        crate::scoring::recompute_task_score,
+        crate::ioc_correlation::get_correlations,
+        crate::ioc_correlation::recompute_correlations,
+        crate::ioc_correlation::get_sightings,
+        crate::beaconing::get_beacons,
+        crate::dns_reputation::get_task_dns,
+        crate::dns_reputation::list_dns_blocklist,
+        crate::dns_reputation::add_dns_blocklist,
+        crate::dns_reputation::delete_dns_blocklist,
+        crate::lineage::list_lineage_rules,
+        crate::lineage::add_lineage_rule,
+        crate::lineage::update_lineage_rule,
+        crate::lineage::delete_lineage_rule,
+        crate::decoder::redecode_task,
+        crate::mitre::get_task_mitre,
+        crate::mitre::get_campaign_mitre,
+        crate::obfuscation::get_obfuscation,
+        crate::yara::get_yara_matches,
+        crate::yara::rescan_task,
+        crate::yara::list_yara_rules,
+        crate::yara::add_yara_rule,
+        crate::yara::update_yara_rule,
+        crate::yara::delete_yara_rule,
+        crate::rule_drafts::generate_yara,
+        crate::rule_drafts::generate_sigma,
+        crate::rule_drafts::list_sigma_drafts,
+        crate::rule_drafts::export_sigma,
+        crate::prompts::list_prompts,
+        crate::prompts::prompt_stats,
+        crate::prompts::add_prompt_version,
+        crate::prompts::update_prompt_version,
+        crate::chat_sessions::list_task_sessions,
+        crate::chat_sessions::list_sessions,
+        crate::chat_sessions::get_session,
+        crate::chat_sessions::delete_session,
+        crate::static_properties::get_static_properties,
+        crate::static_properties::reanalyze_task,
+        crate::static_properties::download_static_artifact,
+    ),
+    components(schemas(crate::Task, crate::task_queue::QueueEntry, crate::task_queue::RerunRequest, crate::schedules::ScheduleRequest, crate::chunked_upload::UploadRequest, crate::chunked_upload::UploadOptions, crate::relationships::RelationshipRequest, crate::sigma::SigmaRuleRequest, crate::alerts::AlertActionRequest, crate::alerts::BulkAlertRequest, crate::dns_reputation::BlocklistRequest, crate::lineage::LineageRuleRequest, crate::yara::YaraRuleRequest, crate::prompts::PromptTemplateRequest, crate::prompts::PromptVersionRequest, SubmitForm)),
+    modifiers(&Security),
+    tags(
+        (name = "tasks", description = "Task listing, queue, reports and per-task analysis views"),
+        (name = "submission", description = "Sample and URL detonation"),
+        (name = "telemetry", description = "Agent telemetry, screenshots and memory dumps"),
+        (name = "ai", description = "AI provider configuration, chat and insights"),
+        (name = "ghidra", description = "Static analysis through the Ghidra bridge"),
+        (name = "detox", description = "VS Code extension scanning"),
+        (name = "vms", description = "Hypervisor VM control and consoles"),
+        (name = "sandbox pool", description = "Sandbox VM pool and golden images"),
+        (name = "schedules", description = "Recurring URL and sample re-detonation with drift between runs"),
+        (name = "settings", description = "Noise filters, severity rules and analysis profiles"),
+        (name = "detections", description = "Sigma rules and the alerts they raise"),
+        (name = "agents", description = "Agent release management"),
+        (name = "auth", description = "Users, API keys and tokens"),
+        (name = "admin", description = "Audit log, retention, storage and metrics"),
+        (name = "health", description = "Liveness and readiness probes"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Multipart fields read by the submit endpoint; only `file` is required.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct SubmitForm {
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+    /// Minutes.
+    analysis_duration: Option<u64>,
+    vmid: Option<u64>,
+    node: Option<String>,
+    /// quick | deep | vsix
+    analysis_mode: Option<String>,
+    /// urgent | normal | bulk
+    priority: Option<String>,
+    profile: Option<String>,
+    /// Comma-separated profiles to detonate side by side.
+    compare_profiles: Option<String>,
+    /// full | fake-net | blocked | vpn
+    internet_policy: Option<String>,
+    /// Return an earlier analysis of the same hash instead of detonating again.
+    reuse: Option<bool>,
+    force: Option<bool>,
+    archive_password: Option<String>,
+    archive_member: Option<String>,
+    extract_archives: Option<bool>,
+}
+
+/// Both credential types, required on every non-public operation, plus the rate limiter's 429.
+struct Security;
+
+impl Modify for Security {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("api_key", SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-API-Key"))));
+        components.add_security_scheme("bearer", SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).bearer_format("JWT").build()));
+
+        for (path, item) in openapi.paths.paths.iter_mut() {
+            if !path.starts_with("/health") {
+                for operation in item.operations.values_mut() {
+                    operation.responses.responses.entry("429".to_string())
+                        .or_insert_with(|| ResponseBuilder::new().description("Rate limit exceeded; see Retry-After").build().into());
+                }
+            }
+            if crate::auth::is_public_path(path) {
+                continue;
+            }
+            for operation in item.operations.values_mut() {
+                operation.security = Some(vec![
+                    SecurityRequirement::new("api_key", Vec::<String>::new()),
+                    SecurityRequirement::new("bearer", Vec::<String>::new()),
+                ]);
+                operation.responses.responses.entry("401".to_string())
+                    .or_insert_with(|| ResponseBuilder::new().description("Missing or invalid credentials").build().into());
+                operation.responses.responses.entry("403".to_string())
+                    .or_insert_with(|| ResponseBuilder::new().description("Role not allowed for this route").build().into());
+            }
+        }
+    }
+}
+
+/// Swagger UI at /swagger-ui/, reading the spec from SPEC_PATH.
+pub fn swagger_ui(spec: utoipa::openapi::OpenApi) -> SwaggerUi {
+    SwaggerUi::new("/swagger-ui/{_:.*}").url(SPEC_PATH, spec)
+}
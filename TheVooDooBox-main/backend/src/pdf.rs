@@ -0,0 +1,601 @@
+use regex::bytes::Regex;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::sync::OnceLock;
+
+// --- PDF ANALYSIS ---
+
+/// Decoded stream size cap; a decompression bomb stops here.
+const MAX_STREAM_BYTES: usize = 16 * 1024 * 1024;
+const MAX_JS_BYTES: usize = 2 * 1024 * 1024;
+const MAX_EMBEDDED_BYTES: usize = 32 * 1024 * 1024;
+const MAX_LISTED: usize = 100;
+/// Score at which a document is called high or medium risk.
+const HIGH_RISK_SCORE: u32 = 6;
+const MEDIUM_RISK_SCORE: u32 = 3;
+
+/// pdfid's keyword list.
+const KEYWORDS: &[&str] = &[
+    "/Page", "/Encrypt", "/ObjStm", "/JS", "/JavaScript", "/AA", "/OpenAction", "/AcroForm", "/JBIG2Decode",
+    "/RichMedia", "/Launch", "/EmbeddedFile", "/XFA", "/URI", "/SubmitForm", "/GoToR", "/GoToE", "/ImportData",
+];
+
+/// JavaScript calls behind the classic Reader exploits, droppers and heap sprays.
+const JS_SUSPICIOUS: &[(&str, &str)] = &[
+    ("eval", "Evaluates generated code"),
+    ("unescape", "Decodes escaped data (shellcode, heap spray)"),
+    ("String.fromCharCode", "Builds strings from character codes"),
+    ("util.printf", "util.printf overflow (CVE-2008-2992)"),
+    ("Collab.collectEmailInfo", "Collab.collectEmailInfo overflow (CVE-2007-5659)"),
+    ("Collab.getIcon", "Collab.getIcon overflow (CVE-2009-0927)"),
+    ("media.newPlayer", "media.newPlayer use-after-free (CVE-2009-4324)"),
+    ("spell.customDictionaryOpen", "spell.customDictionaryOpen overflow (CVE-2009-1493)"),
+    ("getAnnots", "getAnnots corruption (CVE-2009-1492)"),
+    ("exportDataObject", "Extracts and may open an embedded file"),
+    ("launchURL", "Opens a URL in the browser"),
+    ("submitForm", "Sends form data to a server"),
+    ("app.setTimeOut", "Delays execution"),
+    ("%u9090", "NOP sled in escaped shellcode"),
+    ("%u0c0c", "Heap spray filler"),
+];
+
+#[derive(Default)]
+struct Object {
+    /// Dictionary text with names normalised.
+    dict: String,
+    stream: Option<Vec<u8>>,
+}
+
+/// A PDF's findings and what to store beside them.
+pub struct Report {
+    pub properties: Value,
+    /// (artifact name, contents): the JavaScript and each embedded file.
+    pub artifacts: Vec<(String, Vec<u8>)>,
+}
+
+fn object_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?s-u)(\d+)\s+(\d+)\s+obj\b(.*?)\bendobj\b").unwrap())
+}
+
+fn name_escape_re() -> &'static regex::Regex {
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    RE.get_or_init(|| regex::Regex::new(r"/[A-Za-z0-9#_.\-]*#[0-9A-Fa-f]{2}[A-Za-z0-9#_.\-]*").unwrap())
+}
+
+fn filter_re() -> &'static regex::Regex {
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    RE.get_or_init(|| regex::Regex::new(r"/Filter\s*(\[[^\]]*\]|/\w+)").unwrap())
+}
+
+fn first_re() -> &'static regex::Regex {
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    RE.get_or_init(|| regex::Regex::new(r"/First\s+(\d+)").unwrap())
+}
+
+fn action_type_re() -> &'static regex::Regex {
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    RE.get_or_init(|| regex::Regex::new(r"/S\s*/(\w+)").unwrap())
+}
+
+fn page_re() -> &'static regex::Regex {
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    RE.get_or_init(|| regex::Regex::new(r"/Type\s*/Page\b").unwrap())
+}
+
+fn embedded_ref_re() -> &'static regex::Regex {
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    RE.get_or_init(|| regex::Regex::new(r"/EF\s*<<[^>]*?/(?:UF|F)\s+(\d+)\s+\d+\s+R").unwrap())
+}
+
+fn reference_re() -> &'static regex::Regex {
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    RE.get_or_init(|| regex::Regex::new(r"^\s*(\d+)\s+\d+\s+R").unwrap())
+}
+
+/// Rewrites /J#61vaScript-style names to their plain form; returns the count rewritten.
+fn normalise_names(dict: &str) -> (String, usize) {
+    let mut count = 0;
+    let out = name_escape_re().replace_all(dict, |c: &regex::Captures| {
+        count += 1;
+        let raw = c[0].as_bytes();
+        let mut name = Vec::with_capacity(raw.len());
+        let mut i = 0;
+        while i < raw.len() {
+            if raw[i] == b'#' {
+                if let Some(b) = raw.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(std::str::from_utf8(h).ok()?, 16).ok()) {
+                    name.push(b);
+                    i += 3;
+                    continue;
+                }
+            }
+            name.push(raw[i]);
+            i += 1;
+        }
+        String::from_utf8_lossy(&name).to_string()
+    });
+    (out.to_string(), count)
+}
+
+// ---- Stream filters ----
+
+fn flate(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    // A damaged tail still leaves what inflated before it
+    let _ = flate2::read::ZlibDecoder::new(data).take(MAX_STREAM_BYTES as u64).read_to_end(&mut out);
+    if out.is_empty() {
+        let _ = flate2::read::DeflateDecoder::new(data).take(MAX_STREAM_BYTES as u64).read_to_end(&mut out);
+    }
+    out
+}
+
+fn ascii_hex(data: &[u8]) -> Vec<u8> {
+    let digits: Vec<u8> = data.iter().copied().take_while(|&b| b != b'>').filter(u8::is_ascii_hexdigit).collect();
+    digits.chunks(2).map(|p| {
+        let hi = (p[0] as char).to_digit(16).unwrap_or(0) as u8;
+        let lo = p.get(1).and_then(|&c| (c as char).to_digit(16)).unwrap_or(0) as u8;
+        hi << 4 | lo
+    }).collect()
+}
+
+fn ascii85(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut group: Vec<u32> = Vec::with_capacity(5);
+    let body = data.strip_prefix(b"<~").unwrap_or(data);
+    for &b in body {
+        match b {
+            b'~' => break,
+            b'z' if group.is_empty() => out.extend_from_slice(&[0; 4]),
+            b'!'..=b'u' => {
+                group.push((b - b'!') as u32);
+                if group.len() == 5 {
+                    let v = group.iter().fold(0u32, |acc, &d| acc.wrapping_mul(85).wrapping_add(d));
+                    out.extend_from_slice(&v.to_be_bytes());
+                    group.clear();
+                }
+            }
+            _ => {}
+        }
+    }
+    if group.len() > 1 {
+        let n = group.len();
+        group.resize(5, 84);
+        let v = group.iter().fold(0u32, |acc, &d| acc.wrapping_mul(85).wrapping_add(d));
+        out.extend_from_slice(&v.to_be_bytes()[..n - 1]);
+    }
+    out
+}
+
+/// Applies the filters in order; an unsupported one stops decoding there.
+fn decode_stream(dict: &str, raw: &[u8]) -> Vec<u8> {
+    let Some(filters) = filter_re().captures(dict) else { return raw.to_vec() };
+    let mut data = raw.to_vec();
+    for filter in filters[1].split('/').map(|f| f.trim_matches(|c: char| c.is_whitespace() || c == '[' || c == ']')).filter(|f| !f.is_empty()) {
+        data = match filter {
+            "FlateDecode" | "Fl" => flate(&data),
+            "ASCIIHexDecode" | "AHx" => ascii_hex(&data),
+            "ASCII85Decode" | "A85" => ascii85(&data),
+            _ => return data,
+        };
+    }
+    data
+}
+
+// ---- Objects ----
+
+/// Splits an object body into its dictionary and (raw) stream.
+fn split_body(body: &[u8]) -> (String, Option<Vec<u8>>) {
+    let Some(at) = find(body, b"stream") else { return (String::from_utf8_lossy(body).to_string(), None) };
+    let mut start = at + b"stream".len();
+    if body.get(start) == Some(&b'\r') {
+        start += 1;
+    }
+    if body.get(start) == Some(&b'\n') {
+        start += 1;
+    }
+    let end = rfind(body, b"endstream").filter(|&e| e >= start).unwrap_or(body.len());
+    let mut data = &body[start..end];
+    if data.ends_with(b"\r\n") {
+        data = &data[..data.len() - 2];
+    } else if data.ends_with(b"\n") || data.ends_with(b"\r") {
+        data = &data[..data.len() - 1];
+    }
+    (String::from_utf8_lossy(&body[..at]).to_string(), Some(data.to_vec()))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).rposition(|w| w == needle)
+}
+
+/// Objects packed into an /ObjStm: a header of (number, offset) pairs, then the bodies.
+fn object_stream(dict: &str, data: &[u8], objects: &mut BTreeMap<u32, Object>, escaped: &mut usize) {
+    let first = first_re().captures(dict).and_then(|c| c[1].parse::<usize>().ok());
+    let Some(first) = first.filter(|&f| f <= data.len()) else { return };
+    let header = String::from_utf8_lossy(&data[..first]);
+    let numbers: Vec<usize> = header.split_whitespace().filter_map(|n| n.parse().ok()).collect();
+    let pairs: Vec<(usize, usize)> = numbers.chunks_exact(2).map(|p| (p[0], p[1])).collect();
+    for (i, &(num, offset)) in pairs.iter().enumerate() {
+        let start = first + offset;
+        let end = pairs.get(i + 1).map(|p| first + p.1).unwrap_or(data.len()).min(data.len());
+        let Some(body) = data.get(start..end) else { continue };
+        let (dict, count) = normalise_names(&String::from_utf8_lossy(body));
+        *escaped += count;
+        objects.entry(num as u32).or_insert(Object { dict, stream: None });
+    }
+}
+
+// ---- Strings ----
+
+/// Literal string after its opening parenthesis, escapes resolved, UTF-16BE decoded.
+fn literal_string(s: &[u8]) -> String {
+    let mut out = Vec::new();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < s.len() {
+        let b = s[i];
+        match b {
+            b'\\' => {
+                i += 1;
+                match s.get(i) {
+                    Some(b'n') => out.push(b'\n'),
+                    Some(b'r') => out.push(b'\r'),
+                    Some(b't') => out.push(b'\t'),
+                    Some(b'b') => out.push(8),
+                    Some(b'f') => out.push(12),
+                    Some(b'\r') | Some(b'\n') => {}
+                    Some(d @ b'0'..=b'7') => {
+                        let mut v = (d - b'0') as u32;
+                        for _ in 0..2 {
+                            match s.get(i + 1) {
+                                Some(d @ b'0'..=b'7') => {
+                                    v = v * 8 + (d - b'0') as u32;
+                                    i += 1;
+                                }
+                                _ => break,
+                            }
+                        }
+                        out.push(v as u8);
+                    }
+                    Some(&c) => out.push(c),
+                    None => break,
+                }
+            }
+            b'(' => {
+                depth += 1;
+                out.push(b);
+            }
+            b')' if depth == 0 => break,
+            b')' => {
+                depth -= 1;
+                out.push(b);
+            }
+            _ => out.push(b),
+        }
+        i += 1;
+    }
+    text(&out)
+}
+
+fn text(bytes: &[u8]) -> String {
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = bytes[2..].chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+        return String::from_utf16_lossy(&units);
+    }
+    String::from_utf8_lossy(bytes).to_string()
+}
+
+/// The string value of `key`, following an indirect reference if needed.
+fn value(key: &str, dict: &str, objects: &BTreeMap<u32, Object>) -> Vec<String> {
+    let mut values = Vec::new();
+    for (at, _) in dict.match_indices(key) {
+        let rest = &dict[at + key.len()..];
+        // /JS is a prefix of /JavaScript; only whole names count
+        if rest.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+            continue;
+        }
+        let trimmed = rest.trim_start();
+        if let Some(s) = trimmed.strip_prefix('(') {
+            values.push(literal_string(s.as_bytes()));
+        } else if let Some(h) = trimmed.strip_prefix('<').filter(|h| !h.starts_with('<')) {
+            values.push(text(&ascii_hex(h.as_bytes())));
+        } else if let Some(c) = reference_re().captures(trimmed) {
+            let Some(target) = c[1].parse::<u32>().ok().and_then(|n| objects.get(&n)) else { continue };
+            match &target.stream {
+                Some(data) => values.push(text(data)),
+                None => {
+                    let body = target.dict.trim_start();
+                    if let Some(s) = body.strip_prefix('(') {
+                        values.push(literal_string(s.as_bytes()));
+                    } else if let Some(h) = body.strip_prefix('<').filter(|h| !h.starts_with('<')) {
+                        values.push(text(&ascii_hex(h.as_bytes())));
+                    }
+                }
+            }
+        }
+    }
+    values
+}
+
+fn clip(s: &str, max: usize) -> String {
+    match s.char_indices().nth(max) {
+        Some((cut, _)) => format!("{}...", &s[..cut]),
+        None => s.to_string(),
+    }
+}
+
+fn has_name(dict: &str, name: &str) -> bool {
+    dict.match_indices(name).any(|(at, _)| !dict[at + name.len()..].starts_with(|c: char| c.is_ascii_alphanumeric()))
+}
+
+/// What an /OpenAction or /AA entry does: its action type, followed through one reference.
+fn action_kind(dict: &str, key: &str, objects: &BTreeMap<u32, Object>) -> Option<String> {
+    let at = dict.find(key)?;
+    let rest = dict[at + key.len()..].trim_start();
+    let target = match reference_re().captures(rest) {
+        Some(c) => objects.get(&c[1].parse::<u32>().ok()?)?.dict.clone(),
+        None => rest.chars().take(2048).collect(),
+    };
+    let kind = action_type_re().captures(&target).map(|c| c[1].to_string());
+    Some(kind.unwrap_or_else(|| if has_name(&target, "/JS") { "JavaScript".to_string() } else { "destination".to_string() }))
+}
+
+// ---- Analysis ----
+
+/// Findings for a PDF, or None when the data has no PDF header in its first kilobyte.
+pub fn analyze(bytes: &[u8]) -> Option<Report> {
+    let header_offset = find(&bytes[..bytes.len().min(1024)], b"%PDF-")?;
+    let version = String::from_utf8_lossy(&bytes[header_offset + 5..(header_offset + 8).min(bytes.len())]).to_string();
+
+    let mut objects: BTreeMap<u32, Object> = BTreeMap::new();
+    let mut escaped_names = 0;
+    let mut object_streams = Vec::new();
+    for c in object_re().captures_iter(bytes) {
+        let Some(num) = std::str::from_utf8(&c[1]).ok().and_then(|n| n.parse::<u32>().ok()) else { continue };
+        let (dict, raw) = split_body(&c[3]);
+        let (dict, count) = normalise_names(&dict);
+        escaped_names += count;
+        let stream = raw.map(|r| decode_stream(&dict, &r));
+        if has_name(&dict, "/ObjStm") {
+            object_streams.push(num);
+        }
+        // Incremental updates redefine objects; the last definition wins
+        objects.insert(num, Object { dict, stream });
+    }
+    for num in &object_streams {
+        let Some((dict, data)) = objects.get(num).and_then(|o| o.stream.clone().map(|s| (o.dict.clone(), s))) else { continue };
+        object_stream(&dict, &data, &mut objects, &mut escaped_names);
+    }
+
+    let count_raw = |needle: &[u8]| bytes.windows(needle.len()).filter(|w| *w == needle).count();
+    let mut keywords: HashMap<&str, usize> = HashMap::new();
+    for object in objects.values() {
+        for &k in KEYWORDS {
+            let n = object.dict.match_indices(k).filter(|(at, _)| !object.dict[at + k.len()..].starts_with(|c: char| c.is_ascii_alphanumeric())).count();
+            *keywords.entry(k).or_default() += n;
+        }
+    }
+
+    // JavaScript: /JS values, plus script blocks in XFA forms
+    let mut scripts: Vec<String> = Vec::new();
+    for object in objects.values() {
+        if has_name(&object.dict, "/JS") {
+            scripts.extend(value("/JS", &object.dict, &objects));
+        }
+        if has_name(&object.dict, "/XFA") {
+            for form in value("/XFA", &object.dict, &objects) {
+                let lower = form.to_lowercase();
+                let mut at = 0;
+                while let Some(start) = lower[at..].find("<script").map(|s| s + at) {
+                    let Some(open_end) = lower[start..].find('>').map(|e| e + start + 1) else { break };
+                    let end = lower[open_end..].find("</script").map(|e| e + open_end).unwrap_or(lower.len());
+                    scripts.push(form[open_end..end].to_string());
+                    at = end;
+                }
+            }
+        }
+    }
+    scripts.retain(|s| !s.trim().is_empty());
+    scripts.dedup();
+    let javascript = scripts.join("\n\n// ----\n\n");
+    let js_lower = javascript.to_lowercase();
+    let js_functions: Vec<Value> = JS_SUSPICIOUS.iter()
+        .filter(|(f, _)| js_lower.contains(&f.to_lowercase()))
+        .map(|(f, d)| json!({ "function": f, "description": d }))
+        .collect();
+
+    // Embedded files, named through the file specifications that point at them
+    let mut names: HashMap<u32, String> = HashMap::new();
+    for object in objects.values() {
+        if let Some(c) = embedded_ref_re().captures(&object.dict) {
+            let name = value("/UF", &object.dict, &objects).into_iter().chain(value("/F", &object.dict, &objects)).find(|n| !n.is_empty());
+            if let (Ok(num), Some(name)) = (c[1].parse::<u32>(), name) {
+                names.insert(num, name);
+            }
+        }
+    }
+    let mut artifacts: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut embedded: Vec<Value> = Vec::new();
+    for (num, object) in &objects {
+        let Some(data) = object.stream.as_ref().filter(|_| has_name(&object.dict, "/EmbeddedFile")) else { continue };
+        let sha256 = format!("{:x}", Sha256::digest(data));
+        let name = names.get(num).cloned().unwrap_or_else(|| format!("object {}", num));
+        let kind = crate::static_properties::format(data);
+        let artifact = format!("embedded_{}.bin", &sha256[..16]);
+        if data.len() <= MAX_EMBEDDED_BYTES && !artifacts.iter().any(|(a, _)| *a == artifact) {
+            artifacts.push((artifact.clone(), data.clone()));
+        }
+        embedded.push(json!({ "name": name, "size": data.len(), "sha256": sha256, "format": kind, "artifact": artifact }));
+    }
+
+    let mut launches: Vec<Value> = Vec::new();
+    let mut uris: Vec<String> = Vec::new();
+    let mut remote: Vec<Value> = Vec::new();
+    let mut on_open: Vec<String> = Vec::new();
+    for object in objects.values() {
+        let dict = &object.dict;
+        if has_name(dict, "/Launch") {
+            let file = value("/F", dict, &objects).into_iter().next();
+            let params = value("/P", dict, &objects).into_iter().next();
+            launches.push(json!({ "file": file, "parameters": params.map(|p| clip(&p, 500)) }));
+        }
+        for uri in value("/URI", dict, &objects) {
+            if uris.len() < MAX_LISTED && !uris.contains(&uri) {
+                uris.push(uri);
+            }
+        }
+        for action in ["/GoToR", "/GoToE", "/SubmitForm", "/ImportData"] {
+            if has_name(dict, action) && remote.len() < MAX_LISTED {
+                let target = value("/F", dict, &objects).into_iter().next().or_else(|| value("/URL", dict, &objects).into_iter().next());
+                remote.push(json!({ "action": &action[1..], "target": target }));
+            }
+        }
+        let mut opens: Vec<String> = action_kind(dict, "/OpenAction", &objects).map(|k| format!("OpenAction: {}", k)).into_iter().collect();
+        if has_name(dict, "/AA") {
+            opens.push("Additional actions (/AA)".to_string());
+        }
+        for action in opens {
+            if !on_open.contains(&action) {
+                on_open.push(action);
+            }
+        }
+    }
+
+    // Structure
+    let obj = count_raw(b" obj");
+    let endobj = count_raw(b"endobj");
+    let stream = count_raw(b"stream").saturating_sub(count_raw(b"endstream"));
+    let endstream = count_raw(b"endstream");
+    let eof = count_raw(b"%%EOF");
+    let trailing = rfind(bytes, b"%%EOF").map(|e| bytes[e + 5..].iter().filter(|b| !b.is_ascii_whitespace()).count()).unwrap_or(0);
+    let encrypted = keywords.get("/Encrypt").copied().unwrap_or(0) > 0;
+    let pages = objects.values().filter(|o| page_re().is_match(&o.dict)).count();
+
+    let mut anomalies: Vec<(String, u32)> = Vec::new();
+    if header_offset > 0 {
+        anomalies.push((format!("header at offset {} instead of 0", header_offset), 1));
+    }
+    if trailing > 0 {
+        anomalies.push((format!("{} bytes after the last %%EOF", trailing), 1));
+    }
+    if obj != endobj {
+        anomalies.push((format!("{} obj but {} endobj", obj, endobj), 1));
+    }
+    if stream != endstream {
+        anomalies.push((format!("{} stream but {} endstream", stream, endstream), 1));
+    }
+    if escaped_names > 0 {
+        anomalies.push((format!("{} hex-escaped names (e.g. /J#61vaScript)", escaped_names), 2));
+    }
+    if !object_streams.is_empty() && !scripts.is_empty() {
+        anomalies.push(("JavaScript alongside object streams, which hide objects from simple parsers".to_string(), 1));
+    }
+    if !scripts.is_empty() {
+        anomalies.push((format!("{} JavaScript blocks", scripts.len()), 3));
+    }
+    if !js_functions.is_empty() {
+        anomalies.push((format!("JavaScript calls {}", js_functions.iter().filter_map(|f| f["function"].as_str()).collect::<Vec<_>>().join(", ")), 2));
+    }
+    if !scripts.is_empty() && on_open.iter().any(|a| a.contains("JavaScript")) {
+        anomalies.push(("JavaScript runs when the document opens".to_string(), 2));
+    }
+    if !launches.is_empty() {
+        anomalies.push((format!("{} launch actions", launches.len()), 4));
+    }
+    if !embedded.is_empty() {
+        anomalies.push((format!("{} embedded files", embedded.len()), 2));
+    }
+    if embedded.iter().any(|e| e["format"] == "pe" || e["format"] == "ole" || e["format"] == "zip") {
+        anomalies.push(("embedded executable or Office document".to_string(), 2));
+    }
+    if pages <= 1 && !scripts.is_empty() {
+        anomalies.push(("single-page document carrying JavaScript".to_string(), 1));
+    }
+    for (k, what) in [("/JBIG2Decode", "JBIG2 images (CVE-2009-0658 era)"), ("/RichMedia", "embedded Flash (RichMedia)"), ("/XFA", "XFA forms")] {
+        if keywords.get(k).copied().unwrap_or(0) > 0 {
+            anomalies.push((what.to_string(), 1));
+        }
+    }
+    if !remote.is_empty() {
+        anomalies.push((format!("{} remote GoTo / form submission actions", remote.len()), 1));
+    }
+    if encrypted {
+        anomalies.push(("encrypted (content cannot be inspected fully)".to_string(), 1));
+    }
+    let score: u32 = anomalies.iter().map(|(_, w)| w).sum();
+    let risk = if score >= HIGH_RISK_SCORE {
+        "high"
+    } else if score >= MEDIUM_RISK_SCORE {
+        "medium"
+    } else if score > 0 {
+        "low"
+    } else {
+        "none"
+    };
+
+    let js_artifact = (!javascript.is_empty()).then(|| {
+        let js = clip(&javascript, MAX_JS_BYTES);
+        artifacts.push(("javascript.js".to_string(), js.into_bytes()));
+        "javascript.js"
+    });
+    let keyword_counts: BTreeMap<&str, usize> = keywords.into_iter().collect();
+    let properties = json!({
+        "version": version,
+        "header_offset": header_offset,
+        "objects": objects.len(),
+        "object_streams": object_streams.len(),
+        "pages": pages,
+        "encrypted": encrypted,
+        "incremental_updates": eof.saturating_sub(1),
+        "trailing_bytes": trailing,
+        "keywords": keyword_counts,
+        "obfuscated_names": escaped_names,
+        "javascript": {
+            "blocks": scripts.len(),
+            "size": javascript.len(),
+            "suspicious_functions": js_functions,
+            "artifact": js_artifact,
+        },
+        "on_open": on_open,
+        "launch_actions": launches,
+        "uris": uris,
+        "remote_actions": remote,
+        "embedded_files": embedded,
+        "anomalies": anomalies.iter().map(|(a, w)| json!({ "anomaly": a, "weight": w })).collect::<Vec<_>>(),
+        "score": score,
+        "risk": risk,
+    });
+    Some(Report { properties, artifacts })
+}
+
+/// One paragraph for the AI prompt.
+pub fn describe(props: &Value) -> String {
+    let mut out = format!(
+        "PDF {} ({} objects, {} pages), anomaly score {} ({} risk).",
+        props["version"].as_str().unwrap_or("?"), props["objects"], props["pages"], props["score"], props["risk"].as_str().unwrap_or("none"),
+    );
+    let anomalies: Vec<&str> = props["anomalies"].as_array().map(|a| a.iter().filter_map(|v| v["anomaly"].as_str()).collect()).unwrap_or_default();
+    if !anomalies.is_empty() {
+        out.push_str(&format!(" Findings: {}.", anomalies.join("; ")));
+    }
+    if let Some(on_open) = props["on_open"].as_array().filter(|a| !a.is_empty()) {
+        out.push_str(&format!(" On open: {}.", on_open.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(", ")));
+    }
+    if let Some(launches) = props["launch_actions"].as_array().filter(|a| !a.is_empty()) {
+        let files: Vec<String> = launches.iter().map(|l| format!("{} {}", l["file"].as_str().unwrap_or("?"), l["parameters"].as_str().unwrap_or(""))).collect();
+        out.push_str(&format!(" Launches: {}.", files.join("; ").trim()));
+    }
+    if let Some(files) = props["embedded_files"].as_array().filter(|a| !a.is_empty()) {
+        let files: Vec<String> = files.iter().map(|f| format!("{} ({}, {})", f["name"].as_str().unwrap_or("?"), f["format"].as_str().unwrap_or("?"), f["sha256"].as_str().unwrap_or(""))).collect();
+        out.push_str(&format!(" Embedded files: {}.", files.join(", ")));
+    }
+    if let Some(uris) = props["uris"].as_array().filter(|a| !a.is_empty()) {
+        out.push_str(&format!(" URIs: {}.", uris.iter().take(20).filter_map(Value::as_str).collect::<Vec<_>>().join(", ")));
+    }
+    out
+}
@@ -0,0 +1,335 @@
+use goblin::pe::PE;
+use md5::{Digest, Md5};
+use serde_json::{json, Value};
+
+// --- PE STATIC ANALYSIS ---
+
+/// Section entropy above this is compressed or encrypted data.
+const HIGH_ENTROPY: f64 = 7.2;
+/// Fewer imports than this in a native executable usually means a loader resolves the rest.
+const FEW_IMPORTS: usize = 10;
+/// Imported and exported names kept per file; the rest are counted.
+const MAX_NAMES: usize = 2000;
+
+const SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+const SCN_MEM_READ: u32 = 0x4000_0000;
+const SCN_MEM_WRITE: u32 = 0x8000_0000;
+const SCN_CNT_CODE: u32 = 0x0000_0020;
+
+/// Section names left behind by common packers and protectors.
+const PACKER_SECTIONS: &[(&str, &str)] = &[
+    ("UPX0", "UPX"), ("UPX1", "UPX"), ("UPX2", "UPX"), ("UPX!", "UPX"),
+    (".aspack", "ASPack"), (".adata", "ASPack"), ("ASPack", "ASPack"),
+    (".MPRESS1", "MPRESS"), (".MPRESS2", "MPRESS"),
+    (".themida", "Themida"), (".winlice", "WinLicense"),
+    (".vmp0", "VMProtect"), (".vmp1", "VMProtect"), (".vmp2", "VMProtect"),
+    (".enigma1", "Enigma Protector"), (".enigma2", "Enigma Protector"),
+    (".petite", "Petite"), (".nsp0", "NsPack"), (".nsp1", "NsPack"), (".nsp2", "NsPack"),
+    ("PEC2", "PECompact"), ("PECompact2", "PECompact"), ("pec1", "PECompact"),
+    (".RLPack", "RLPack"), (".packed", "RLPack"), ("MEW", "MEW"), (".kkrunchy", "kkrunchy"),
+    (".perplex", "Perplex"), (".yP", "Y0da Protector"), (".spack", "Simple Pack"),
+];
+
+/// Shannon entropy in bits per byte.
+pub fn entropy(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0usize; 256];
+    for &b in data {
+        counts[b as usize] += 1;
+    }
+    let len = data.len() as f64;
+    counts.iter().filter(|&&n| n > 0).map(|&n| {
+        let p = n as f64 / len;
+        -p * p.log2()
+    }).sum()
+}
+
+fn round2(v: f64) -> f64 {
+    (v * 100.0).round() / 100.0
+}
+
+fn subsystem_name(subsystem: u16) -> &'static str {
+    match subsystem {
+        1 => "native",
+        2 => "windows_gui",
+        3 => "windows_cui",
+        9 => "windows_ce_gui",
+        10 => "efi_application",
+        11 => "efi_boot_service_driver",
+        12 => "efi_runtime_driver",
+        16 => "windows_boot_application",
+        _ => "other",
+    }
+}
+
+fn permissions(characteristics: u32) -> String {
+    [(SCN_MEM_READ, 'r'), (SCN_MEM_WRITE, 'w'), (SCN_MEM_EXECUTE, 'x')]
+        .iter()
+        .map(|&(flag, c)| if characteristics & flag != 0 { c } else { '-' })
+        .collect()
+}
+
+pub fn is_executable(characteristics: u32) -> bool {
+    characteristics & (SCN_MEM_EXECUTE | SCN_CNT_CODE) != 0
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    bytes.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Rich header: one (tool, build) record per contributing toolchain, XOR-masked.
+fn rich_header(bytes: &[u8], pe_offset: usize) -> Option<Value> {
+    let area = bytes.get(..pe_offset.min(bytes.len()))?;
+    let rich = area.windows(4).rposition(|w| w == b"Rich")?;
+    let key = read_u32(area, rich + 4)?;
+    let mut start = rich;
+    loop {
+        start = start.checked_sub(4)?;
+        if read_u32(area, start)? ^ key == 0x536E_6144 {
+            // "DanS"
+            break;
+        }
+    }
+    let clear: Vec<u8> = area[start..rich].chunks_exact(4)
+        .flat_map(|c| (u32::from_le_bytes([c[0], c[1], c[2], c[3]]) ^ key).to_le_bytes())
+        .collect();
+    // "DanS" and three zero dwords of padding, then (comp id, count) pairs
+    let entries: Vec<Value> = clear.get(16..).unwrap_or_default().chunks_exact(8).map(|c| {
+        let comp_id = u32::from_le_bytes([c[0], c[1], c[2], c[3]]);
+        json!({
+            "product_id": comp_id >> 16,
+            "build": comp_id & 0xFFFF,
+            "count": u32::from_le_bytes([c[4], c[5], c[6], c[7]]),
+        })
+    }).collect();
+    Some(json!({
+        "key": format!("{:08x}", key),
+        "hash": format!("{:x}", Md5::digest(&clear)),
+        "entries": entries,
+    }))
+}
+
+/// pefile's imphash, without its oleaut32/ws2_32 ordinal-name table.
+fn imphash(imports: &[goblin::pe::import::Import]) -> Option<String> {
+    if imports.is_empty() {
+        return None;
+    }
+    let list = imports.iter().map(|i| {
+        let dll = i.dll.to_lowercase();
+        let lib = match dll.rsplit_once('.') {
+            Some((stem, "dll" | "ocx" | "sys")) => stem.to_string(),
+            _ => dll,
+        };
+        let function = if i.name.starts_with("ORDINAL ") { format!("ord{}", i.ordinal) } else { i.name.to_lowercase() };
+        format!("{}.{}", lib, function)
+    }).collect::<Vec<_>>().join(",");
+    Some(format!("{:x}", Md5::digest(list.as_bytes())))
+}
+
+/// Certificates in the PKCS#7 blob; the signer is the one that issued no other.
+fn signature(pe: &PE) -> Value {
+    let mut certs = Vec::new();
+    for attr in &pe.certificates {
+        let blob = attr.certificate;
+        let mut at = 0;
+        while at + 4 < blob.len() {
+            if blob[at] == 0x30 && blob[at + 1] == 0x82 {
+                if let Ok((rest, cert)) = x509_parser::parse_x509_certificate(&blob[at..]) {
+                    let consumed = blob.len() - at - rest.len();
+                    certs.push(cert);
+                    at += consumed;
+                    continue;
+                }
+            }
+            at += 1;
+        }
+    }
+    if pe.certificates.is_empty() {
+        return json!({ "present": false });
+    }
+
+    let subjects: Vec<String> = certs.iter().map(|c| c.subject().to_string()).collect();
+    let issuers: Vec<String> = certs.iter().map(|c| c.issuer().to_string()).collect();
+    let leaves: Vec<_> = certs.iter().enumerate()
+        .filter(|(i, _)| !issuers.iter().enumerate().any(|(j, issuer)| j != *i && *issuer == subjects[*i]))
+        .map(|(_, c)| c)
+        .collect();
+    // The timestamping authority's certificate is also a leaf; the code signer is the other one
+    let signer = leaves.iter().find(|c| {
+        let subject = c.subject().to_string().to_lowercase();
+        !subject.contains("timestamp") && !subject.contains("time stamp") && !subject.contains("tsa")
+    }).or(leaves.first());
+    let chain: Vec<Value> = certs.iter().map(|c| json!({
+        "subject": c.subject().to_string(),
+        "issuer": c.issuer().to_string(),
+        "serial": c.raw_serial_as_string(),
+        "not_before": c.validity().not_before.to_datetime().unix_timestamp(),
+        "not_after": c.validity().not_after.to_datetime().unix_timestamp(),
+        "self_signed": c.subject() == c.issuer(),
+    })).collect();
+    json!({
+        "present": true,
+        "verified": false,
+        "signer": signer.map(|c| c.subject().to_string()),
+        "signer_issuer": signer.map(|c| c.issuer().to_string()),
+        "certificates": chain,
+    })
+}
+
+fn compile_time(timestamp: u32) -> Value {
+    let when = chrono::DateTime::from_timestamp(timestamp as i64, 0);
+    let now = chrono::Utc::now().timestamp();
+    // Reproducible builds store a hash here; Delphi's linker famously stamps 1992
+    let note = if timestamp == 0 {
+        Some("zeroed")
+    } else if (timestamp as i64) > now + 86_400 {
+        Some("in the future (forged or a reproducible-build hash)")
+    } else if timestamp < 0x2A42_5E19 {
+        Some("before 1992 (forged or Delphi)")
+    } else {
+        None
+    };
+    json!({
+        "timestamp": timestamp,
+        "utc": when.map(|t| t.to_rfc3339()),
+        "anomaly": note,
+    })
+}
+
+/// Static properties of a PE file, or None when it does not parse as one.
+pub fn analyze(bytes: &[u8]) -> Option<Value> {
+    let pe = PE::parse(bytes).ok()?;
+    let coff = &pe.header.coff_header;
+    let optional = pe.header.optional_header.as_ref();
+    let is_dotnet = optional.and_then(|o| o.data_directories.get_clr_runtime_header()).is_some();
+
+    let mut indicators: Vec<String> = Vec::new();
+    let mut packer: Option<&str> = None;
+
+    let sections: Vec<Value> = pe.sections.iter().map(|s| {
+        let name = s.name().unwrap_or("").to_string();
+        let start = s.pointer_to_raw_data as usize;
+        let end = start.saturating_add(s.size_of_raw_data as usize).min(bytes.len());
+        let data = bytes.get(start..end).unwrap_or_default();
+        let h = entropy(data);
+        let exec = is_executable(s.characteristics);
+        let writable = s.characteristics & SCN_MEM_WRITE != 0;
+
+        if let Some((_, p)) = PACKER_SECTIONS.iter().find(|(n, _)| n.eq_ignore_ascii_case(&name)) {
+            packer.get_or_insert(p);
+            indicators.push(format!("section {} is a {} section name", name, p));
+        }
+        if exec && h > HIGH_ENTROPY {
+            indicators.push(format!("executable section {} has entropy {:.2}", name, h));
+        }
+        if exec && writable {
+            indicators.push(format!("section {} is writable and executable", name));
+        }
+        if s.size_of_raw_data == 0 && s.virtual_size > 0x1000 {
+            indicators.push(format!("section {} is empty on disk but {} bytes in memory", name, s.virtual_size));
+        }
+        json!({
+            "name": name,
+            "virtual_address": s.virtual_address,
+            "virtual_size": s.virtual_size,
+            "raw_size": s.size_of_raw_data,
+            "entropy": round2(h),
+            "permissions": permissions(s.characteristics),
+            "characteristics": s.characteristics,
+        })
+    }).collect();
+
+    // The loader jumps to the entry point; packers put it in their own stub section
+    let entry = pe.entry as u32;
+    let entry_section = pe.sections.iter().position(|s| {
+        entry >= s.virtual_address && entry < s.virtual_address.saturating_add(s.virtual_size.max(s.size_of_raw_data))
+    });
+    let first_code = pe.sections.iter().position(|s| is_executable(s.characteristics));
+    if entry != 0 && !is_dotnet {
+        match entry_section {
+            None => indicators.push(format!("entry point 0x{:x} lies outside every section", entry)),
+            Some(i) if Some(i) != first_code => {
+                let name = pe.sections[i].name().unwrap_or("");
+                let last = if i + 1 == pe.sections.len() { ", the last section" } else { "" };
+                indicators.push(format!("entry point is in {}{}, not the first code section", name, last));
+            }
+            _ => {}
+        }
+    }
+    if !is_dotnet && !pe.is_lib && pe.imports.len() < FEW_IMPORTS {
+        indicators.push(format!("only {} imported functions", pe.imports.len()));
+    }
+
+    let mut budget = MAX_NAMES;
+    let imports: Vec<Value> = pe.libraries.iter().map(|lib| {
+        let functions: Vec<&str> = pe.imports.iter().filter(|i| i.dll == *lib).map(|i| i.name.as_ref()).take(budget).collect();
+        budget -= functions.len();
+        json!({ "dll": lib, "functions": functions })
+    }).collect();
+    let exports: Vec<&str> = pe.exports.iter().filter_map(|e| e.name).take(MAX_NAMES).collect();
+
+    let pe_offset = pe.header.dos_header.pe_pointer as usize;
+    let pdb = pe.debug_data.as_ref()
+        .and_then(|d| d.codeview_pdb70_debug_info.as_ref())
+        .map(|cv| String::from_utf8_lossy(cv.filename).trim_end_matches('\0').to_string());
+
+    // Strong signs only: a known packer section, or more than one weaker indicator
+    let packed = packer.is_some() || indicators.len() >= 2;
+
+    Some(json!({
+        "machine": goblin::pe::header::machine_to_str(coff.machine),
+        "is_64": pe.is_64,
+        "is_dll": pe.is_lib,
+        "is_dotnet": is_dotnet,
+        "subsystem": optional.map(|o| subsystem_name(o.windows_fields.subsystem)),
+        "image_base": pe.image_base,
+        "entry_point": entry,
+        "entry_section": entry_section.and_then(|i| pe.sections[i].name().ok()),
+        "compile_time": compile_time(coff.time_date_stamp),
+        "pdb_path": pdb,
+        "sections": sections,
+        "imported_dlls": pe.libraries,
+        "import_count": pe.imports.len(),
+        "imports": imports,
+        "export_count": pe.exports.len(),
+        "exports": exports,
+        "imphash": imphash(&pe.imports),
+        "rich_header": rich_header(bytes, pe_offset),
+        "signature": signature(&pe),
+        "packer": packer,
+        "packed": packed,
+        "packer_indicators": indicators,
+    }))
+}
+
+/// One paragraph for the AI prompt.
+pub fn describe(props: &Value) -> String {
+    let mut out = format!(
+        "PE {} {}{}, compiled {}",
+        props["machine"].as_str().unwrap_or("?"),
+        if props["is_dll"].as_bool().unwrap_or(false) { "DLL" } else { "executable" },
+        if props["is_dotnet"].as_bool().unwrap_or(false) { " (.NET)" } else { "" },
+        props["compile_time"]["utc"].as_str().unwrap_or("unknown"),
+    );
+    if let Some(anomaly) = props["compile_time"]["anomaly"].as_str() {
+        out.push_str(&format!(" [timestamp {}]", anomaly));
+    }
+    out.push_str(&format!(". Imphash {}. ", props["imphash"].as_str().unwrap_or("n/a")));
+    match props["signature"]["signer"].as_str() {
+        Some(signer) => out.push_str(&format!("Signed (unverified) by {}. ", signer)),
+        None => out.push_str("Unsigned. "),
+    }
+    if let Some(dlls) = props["imported_dlls"].as_array() {
+        let dlls: Vec<&str> = dlls.iter().filter_map(Value::as_str).collect();
+        out.push_str(&format!("Imports {} functions from {}. ", props["import_count"], dlls.join(", ")));
+    }
+    if props["packed"].as_bool().unwrap_or(false) {
+        out.push_str(&format!("Likely packed{}: ", props["packer"].as_str().map(|p| format!(" ({})", p)).unwrap_or_default()));
+        let indicators: Vec<&str> = props["packer_indicators"].as_array().map(|a| a.iter().filter_map(Value::as_str).collect()).unwrap_or_default();
+        out.push_str(&indicators.join("; "));
+        out.push('.');
+    }
+    out.trim_end().to_string()
+}
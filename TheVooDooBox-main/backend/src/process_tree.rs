@@ -0,0 +1,184 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use std::collections::{BTreeMap, HashMap};
+
+// --- PROCESS TREE ---
+
+#[derive(Serialize)]
+pub struct ProcessNode {
+    pub pid: i32,
+    pub ppid: i32,
+    pub image: String,
+    pub command_line: Option<String>,
+    /// Agent-decoded form of the command line (e.g. -EncodedCommand payloads).
+    pub decoded_command_line: Option<String>,
+    pub digital_signature: Option<String>,
+    pub first_seen: i64,
+    pub event_count: i64,
+    /// Highest agent-assigned severity (0-100) across this process's events.
+    pub max_severity: i32,
+    /// Events per event_type.
+    pub event_counts: BTreeMap<String, i64>,
+    pub children: Vec<ProcessNode>,
+}
+
+#[derive(Serialize)]
+pub struct ProcessTree {
+    pub task_id: String,
+    pub process_count: usize,
+    pub roots: Vec<ProcessNode>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ProcessRow {
+    pid: i32,
+    ppid: i32,
+    image: String,
+    create_details: Option<String>,
+    decoded_details: Option<String>,
+    digital_signature: Option<String>,
+    first_seen: i64,
+    event_count: i64,
+    max_severity: i32,
+}
+
+/// Children per entry and the roots; cycles from PID reuse are broken into roots.
+fn link(pairs: &[(i32, i32)]) -> (Vec<usize>, Vec<Vec<usize>>) {
+    let index: HashMap<i32, usize> = pairs.iter().enumerate().map(|(i, (pid, _))| (*pid, i)).collect();
+    let mut children = vec![Vec::new(); pairs.len()];
+    let mut roots = Vec::new();
+    for (i, (pid, ppid)) in pairs.iter().enumerate() {
+        match index.get(ppid) {
+            Some(&parent) if ppid != pid => children[parent].push(i),
+            _ => roots.push(i),
+        }
+    }
+
+    let mut reached = vec![false; pairs.len()];
+    let mut stack = roots.clone();
+    while let Some(i) = stack.pop() {
+        if !std::mem::replace(&mut reached[i], true) {
+            stack.extend(&children[i]);
+        }
+    }
+    for i in 0..pairs.len() {
+        if !reached[i] {
+            roots.push(i);
+            let mut stack = vec![i];
+            while let Some(j) = stack.pop() {
+                if !std::mem::replace(&mut reached[j], true) {
+                    stack.extend(&children[j]);
+                }
+            }
+        }
+    }
+    (roots, children)
+}
+
+/// Walks the forest depth-first: (index, depth, parent index it was reached through).
+fn walk(pairs: &[(i32, i32)]) -> Vec<(usize, usize, Option<usize>)> {
+    let (roots, children) = link(pairs);
+    let mut out = Vec::with_capacity(pairs.len());
+    let mut seen = vec![false; pairs.len()];
+    let mut stack: Vec<(usize, usize, Option<usize>)> = roots.iter().rev().map(|&r| (r, 0, None)).collect();
+    while let Some((i, depth, via)) = stack.pop() {
+        if std::mem::replace(&mut seen[i], true) {
+            continue;
+        }
+        out.push((i, depth, via));
+        stack.extend(children[i].iter().rev().map(|&c| (c, depth + 1, Some(i))));
+    }
+    out
+}
+
+/// Depth-first (index, depth) order of (pid, ppid) entries, siblings in input order.
+pub fn depth_first(pairs: &[(i32, i32)]) -> Vec<(usize, usize)> {
+    walk(pairs).into_iter().map(|(i, depth, _)| (i, depth)).collect()
+}
+
+/// "Process Created: C:\x.exe Command Line: x.exe -y" -> "x.exe -y"
+fn command_line(details: &str) -> String {
+    match details.find("Command Line: ") {
+        Some(pos) => details[pos + 14..].trim().to_string(),
+        None => details.trim().to_string(),
+    }
+}
+
+pub async fn build(pool: &Pool<Postgres>, task_id: &str) -> Result<ProcessTree, sqlx::Error> {
+    // The create event is authoritative for parent and image; later events may come from
+    // helpers that report a different parent for the same PID
+    let rows = sqlx::query_as::<_, ProcessRow>(
+        "SELECT process_id AS pid,
+                (ARRAY_AGG(parent_process_id ORDER BY (event_type = 'PROCESS_CREATE') DESC, timestamp))[1] AS ppid,
+                (ARRAY_AGG(process_name ORDER BY (event_type = 'PROCESS_CREATE') DESC, timestamp))[1] AS image,
+                (ARRAY_AGG(details ORDER BY timestamp) FILTER (WHERE event_type = 'PROCESS_CREATE'))[1] AS create_details,
+                (ARRAY_AGG(decoded_details ORDER BY timestamp) FILTER (WHERE event_type = 'PROCESS_CREATE'))[1] AS decoded_details,
+                (ARRAY_AGG(digital_signature ORDER BY timestamp)
+                    FILTER (WHERE event_type IN ('PROCESS_CREATE', 'EXEC_SUCCESS') AND digital_signature IS NOT NULL))[1] AS digital_signature,
+                MIN(timestamp) AS first_seen,
+                COUNT(*) AS event_count,
+                MAX(COALESCE(severity, 0)) AS max_severity
+         FROM events WHERE task_id = $1 AND process_id <> 0
+         GROUP BY process_id
+         ORDER BY first_seen"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+
+    let type_counts: Vec<(i32, String, i64)> = sqlx::query_as(
+        "SELECT process_id, event_type, COUNT(*) FROM events WHERE task_id = $1 AND process_id <> 0 GROUP BY 1, 2"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+    let mut counts: HashMap<i32, BTreeMap<String, i64>> = HashMap::new();
+    for (pid, event_type, n) in type_counts {
+        counts.entry(pid).or_default().insert(event_type, n);
+    }
+
+    let pairs: Vec<(i32, i32)> = rows.iter().map(|r| (r.pid, r.ppid)).collect();
+    let mut nodes: Vec<Option<ProcessNode>> = rows.into_iter().map(|r| Some(ProcessNode {
+        pid: r.pid,
+        ppid: r.ppid,
+        image: r.image,
+        command_line: r.create_details.as_deref().map(command_line),
+        decoded_command_line: r.decoded_details.filter(|d| !d.is_empty()),
+        digital_signature: r.digital_signature.filter(|s| !s.is_empty() && s != "N/A"),
+        first_seen: r.first_seen,
+        event_count: r.event_count,
+        max_severity: r.max_severity,
+        event_counts: counts.remove(&r.pid).unwrap_or_default(),
+        children: Vec::new(),
+    })).collect();
+
+    // Descendants come after their ancestors in depth-first order, so walking it backwards
+    // hands every node to its parent only once its own subtree is complete
+    let order = walk(&pairs);
+    let process_count = order.len();
+    let mut roots = Vec::new();
+    for &(i, _, via) in order.iter().rev() {
+        let Some(mut node) = nodes[i].take() else { continue };
+        node.children.sort_by_key(|c| c.first_seen);
+        match via.and_then(|p| nodes[p].as_mut()) {
+            Some(parent) => parent.children.push(node),
+            None => roots.push(node),
+        }
+    }
+    roots.sort_by_key(|r| r.first_seen);
+
+    Ok(ProcessTree { task_id: task_id.to_string(), process_count, roots })
+}
+
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
+#[get("/tasks/{id}/process-tree")]
+pub async fn get_process_tree(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    match build(pool.get_ref(), &path.into_inner()).await {
+        Ok(tree) => HttpResponse::Ok().json(tree),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
@@ -3,7 +3,9 @@ use actix_web_actors::ws;
 use actix_web::{web, Error, HttpRequest, HttpResponse};
 use tokio::sync::broadcast;
 use serde::Serialize;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 // ── Progress Event ──
 
@@ -20,15 +22,18 @@ pub struct ProgressEvent {
 
 pub struct ProgressBroadcaster {
     tx: broadcast::Sender<String>,
+    /// Stage each task is currently in and since when, for the stage duration metric.
+    stages: Mutex<HashMap<String, (String, Instant)>>,
 }
 
 impl ProgressBroadcaster {
     pub fn new() -> Self {
         let (tx, _) = broadcast::channel(256);
-        ProgressBroadcaster { tx }
+        ProgressBroadcaster { tx, stages: Mutex::new(HashMap::new()) }
     }
 
     pub fn send_progress(&self, task_id: &str, stage: &str, message: &str, percent: u8) {
+        self.record_stage(task_id, stage);
         let event = ProgressEvent {
             task_id: task_id.to_string(),
             stage: stage.to_string(),
@@ -41,6 +46,27 @@ impl ProgressBroadcaster {
         }
     }
 
+    fn record_stage(&self, task_id: &str, stage: &str) {
+        let Ok(mut stages) = self.stages.lock() else { return };
+        let terminal = matches!(stage, "completed" | "failed" | "cancelled");
+        if let Some((current, _)) = stages.get(task_id) {
+            if current == stage {
+                return; // same stage reporting again
+            }
+        }
+        let next = (!terminal).then(|| (stage.to_string(), Instant::now()));
+        let previous = match next {
+            Some(entry) => stages.insert(task_id.to_string(), entry),
+            None => stages.remove(task_id),
+        };
+        if let Some((name, since)) = previous {
+            crate::metrics::observe("voodoobox_stage_duration_seconds", &[("stage", &name)], since.elapsed().as_secs_f64());
+        }
+        if terminal {
+            crate::metrics::inc("voodoobox_tasks_finished_total", &[("outcome", stage)], 1.0);
+        }
+    }
+
     pub fn subscribe(&self) -> broadcast::Receiver<String> {
         self.tx.subscribe()
     }
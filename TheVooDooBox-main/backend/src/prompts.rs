@@ -0,0 +1,459 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+
+// --- PROMPT TEMPLATES ---
+// Versioned Tera prompt templates, with weighted A/B selection.
+
+/// name, what it is for, variables, built-in text.
+const TEMPLATES: &[(&str, &str, &[&str], &str)] = &[
+    ("report_map_system", "System prompt of the report's map phase (one call per telemetry chunk).", &[],
+        "You are a Forensic Pre-Processor. Your job is to extract raw technical facts from telemetry chunks."),
+    ("report_map", "One telemetry chunk for the map phase; the answer must be a JSON array of insights.",
+        &["part", "total", "target_filename", "digital_signature", "process_data"],
+        r#"Analyze this telemetry chunk (Part {{ part }}/{{ total }}). Identify suspicious behavior.
+Target File: {{ target_filename }}
+Digital Signature: {{ digital_signature }}
+
+PROCESS DATA:
+{{ process_data }}
+
+OUTPUT FORMAT:
+Return a JSON array of strings, where each string is a concise insight about a specific suspicious action.
+Example: ["Process powershell.exe (PID 454) established network connection to 45.33.2.1", "Process cmd.exe deleted shadow copies"]
+If nothing suspicious is found, return empty array [].
+DO NOT produce a full report. Only precise insights.
+
+CONTEXT:
+- If SIGNED by a reputable vendor (Microsoft, EA, Adobe, Google, etc.), treat System Queries, File Creation, and Registry Mods as NORMAL installer behavior.
+- ONLY flag behavior as suspicious if it is clearly malicious (e.g. Process Injection, Shadow Copy Deletion, Ransomware Extensions)."#),
+    ("report_reduce_system", "System prompt of the report's reduce phase.", &[],
+        "You are the Lead Digital Forensics Expert. Synthesize the provided technical insights into a final comprehensive report."),
+    ("report_reduce", "Everything known about the task, turned into the forensic report JSON.",
+        &["target_filename", "file_hash", "insights", "deterministic_score", "kill_chain", "sightings", "file_properties",
+          "static_analysis", "yara", "screen_text", "virustotal", "digital_signature", "rag_context"],
+        r#"GENERATE COMPREHENSIVE FORENSIC REPORT.
+
+TARGET: {{ target_filename }} (Hash: '{{ file_hash }}')
+VERDICT: Decide if Malicious, Suspicious, or Benign (Use 'Diagnostic Gamma' for Malicious).
+
+--- AGGREGATED TELEMETRY INSIGHTS ---
+{{ insights }}
+
+--- DETERMINISTIC BEHAVIOR SCORE (rules engine) ---
+{{ deterministic_score }}
+
+--- KILL-CHAIN STAGES (deterministic rules) ---
+{{ kill_chain }}
+
+--- PRIOR SIGHTINGS (exact IOC matches in earlier tasks) ---
+{{ sightings }}
+
+--- FILE PROPERTIES (parsed at submission) ---
+{{ file_properties }}
+
+--- STATIC ANALYSIS (Ghidra) ---
+{{ static_analysis }}
+
+--- YARA MATCHES (sample, dropped files, memory) ---
+{{ yara }}
+
+--- SCREEN TEXT (OCR of screenshots; ransom notes, dialogs, phishing pages) ---
+{{ screen_text }}
+
+--- VIRUSTOTAL ---
+{{ virustotal }}
+
+--- DIGITAL SIGNATURE ---
+{{ digital_signature }}
+
+--- RAG CONTEXT ---
+{{ rag_context }}
+
+REQUIRED JSON SCHEMA:
+{
+  "verdict": "Malicious" | "Suspicious" | "Benign",
+  "malware_family": "string or null",
+  "threat_score": 0-100,
+  "executive_summary": "High-level technical overview (1-2 paragraphs)",
+  "behavioral_timeline": [
+    { "timestamp_offset": "+2s", "stage": "Persistence", "event_description": "...", "technical_context": "...", "related_pid": 123 }
+  ],
+  "artifacts": {
+    "dropped_files": [], "c2_ips": [], "c2_domains": [], "mutual_exclusions": [], "command_lines": []
+  },
+  "mitre_matrix": {
+    "Execution": [{ "id": "T1059", "name": "Command and Scripting Interpreter", "evidence": ["..."], "status": "Detected" }],
+    "Persistence": [...],
+    "Defense Evasion": [...],
+    "Discovery": [...],
+    "Lateral Movement": [...],
+    "Command and Control": [...]
+  }
+}
+
+STRICT VERDICT RULES:
+1. IF THE FILE HAS A VERIFIED DIGITAL SIGNATURE from a known vendor (Microsoft, EA, Adobe, Google, etc.):
+   - Default to BENIGN or SUSPICIOUS (Score < 50).
+   - WMI Queries, System Info Discovery, and Dropping Files are NORMAL behavior for installers. DO NOT flag as malicious.
+   - ONLY verdict as MALICIOUS if there is conclusive evidence of Process Injection (Hollowing, Doppelganging), Shellcode Execution, or Ransomware activity.
+2. IF UNSIGNED or INVALID SIGNATURE:
+   - Treat evasion and persistence as high-risk indicators.
+3. THE DETERMINISTIC SCORE is reproducible rule evidence. If your threat_score differs from it by more than 30, the executive_summary must say why.
+4. PRIOR SIGHTINGS are exact indicator matches. An indicator shared with earlier Malicious tasks links this sample to them; name the task in the executive_summary.
+
+STRICT OUTPUT RULES:
+1. OUTPUT RAW JSON ONLY.
+2. DO NOT USE MARKDOWN BLOCKS (```json).
+3. DO NOT INCLUDE PREAMBLE, COMMENTARY, OR EXPLANATIONS.
+4. ENSURE EVERY MITRE TACTIC DETECTED IS IN THE `mitre_matrix`.
+"#),
+    ("chat_system", "System prompt of the chat when the whole context is given up front.", &["context"],
+        r#"## VooDooBox Intelligence Core | System Prompt
+You are the VooDooBox AI, a high-fidelity forensic analysis node.
+Analyze the provided context and respond to the user's query.
+
+FORMATTING RULES:
+1. You MUST enclose your internal reasoning in <think> tags before your final answer.
+2. The final answer should be clear and concise.
+
+Example:
+<think>
+User asks about file X. I see it in the context...
+</think>
+The file X appears to be malicious...
+
+CONTEXT SUMMARY:
+{{ context }}
+"#),
+    ("chat_tools_system", "System prompt of the chat about one task, where the model looks evidence up with tools.",
+        &["summary", "live", "page_context"],
+        r#"## VooDooBox Intelligence Core | System Prompt
+You are the VooDooBox AI, a high-fidelity forensic analysis node, answering an analyst's questions about one sandbox task.
+The summary below is all you are given up front. Use the tools to fetch the telemetry, process tree, decompiled code or
+VirusTotal data the question needs; do not guess at evidence you have not looked up. Cite PIDs, event types and function
+names from tool results. Keep the final answer clear and concise.
+
+TASK SUMMARY:
+{{ summary }}
+{% if live %}
+The sample is running in the sandbox now. You may propose_action (screenshot, process dump, file fetch, kill); an analyst approves each one before it runs, so tell them what you proposed and why.
+{% endif %}{% if page_context %}
+CURRENT ANALYST VIEW CONTEXT (Screen Data):
+{{ page_context }}
+{% endif %}"#),
+    ("insight", "Quick insight over evidence posted by the UI; the answer must be AIReport JSON.", &["evidence"],
+        r#"## Forensic Insight Protocol
+
+Analyze the evidence according to the following rules:
+1. FOCUS: Anomalous parent-child relations, LOLBins (certutil, etc), and registry persistence.
+2. ACCURACY: Extract EXACT PIDs. Citations required.
+3. VERDICT: Use Risk Score (0-100) and MITRE TTP IDs (e.g., T1055).
+
+<EVIDENCE>
+{{ evidence }}
+</EVIDENCE>
+
+Return ONLY RAW JSON."#),
+];
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct PromptTemplate {
+    pub id: i32,
+    pub name: String,
+    pub version: i32,
+    pub body: String,
+    pub note: String,
+    pub enabled: bool,
+    /// Share of renders among the enabled versions.
+    pub weight: i32,
+    pub created_by: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct PromptTemplateRequest {
+    /// Tera template text.
+    pub body: String,
+    pub note: Option<String>,
+    /// Default 100.
+    pub weight: Option<i32>,
+    /// Keep the enabled versions on to A/B against them; otherwise the new one replaces them.
+    pub alongside: Option<bool>,
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct PromptVersionRequest {
+    pub enabled: Option<bool>,
+    pub weight: Option<i32>,
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+pub struct PromptStatsQuery {
+    /// Look-back window in days (default 30).
+    days: Option<i64>,
+}
+
+/// A rendered prompt and the version it came from, e.g. "report_reduce@3" (@0: built-in).
+pub struct Rendered {
+    pub text: String,
+    pub version: String,
+}
+
+fn template(name: &str) -> Option<&'static (&'static str, &'static str, &'static [&'static str], &'static str)> {
+    TEMPLATES.iter().find(|(n, ..)| *n == name)
+}
+
+fn render_body(body: &str, vars: &serde_json::Value) -> Result<String, String> {
+    let context = tera::Context::from_value(vars.clone()).map_err(|e| e.to_string())?;
+    // The error's source chain says where in the template it went wrong
+    tera::Tera::one_off(body, &context, false).map_err(|e| {
+        let mut message = e.to_string();
+        let mut source = std::error::Error::source(&e);
+        while let Some(inner) = source {
+            message = format!("{}: {}", message, inner);
+            source = inner.source();
+        }
+        message
+    })
+}
+
+/// Renders `body` with every variable of `name` set, to refuse it at save time.
+fn check(name: &str, body: &str) -> Result<(), String> {
+    let (_, _, variables, _) = template(name).ok_or("Unknown template")?;
+    let vars: serde_json::Map<String, serde_json::Value> = variables.iter()
+        .map(|v| (v.to_string(), serde_json::Value::String(format!("<{}>", v))))
+        .collect();
+    render_body(body, &serde_json::Value::Object(vars)).map(|_| ())
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS prompt_templates (
+            id SERIAL PRIMARY KEY,
+            name TEXT NOT NULL,
+            version INTEGER NOT NULL,
+            body TEXT NOT NULL,
+            note TEXT NOT NULL DEFAULT '',
+            enabled BOOLEAN NOT NULL DEFAULT TRUE,
+            weight INTEGER NOT NULL DEFAULT 100,
+            created_by TEXT,
+            created_at BIGINT NOT NULL,
+            UNIQUE (name, version)
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    // Per name, so a template added in a later release is seeded too
+    let now = chrono::Utc::now().timestamp();
+    let mut seeded = 0;
+    for (name, _, _, body) in TEMPLATES {
+        let inserted = sqlx::query(
+            "INSERT INTO prompt_templates (name, version, body, note, created_at)
+             SELECT $1, 1, $2, 'built-in', $3
+             WHERE NOT EXISTS (SELECT 1 FROM prompt_templates WHERE name = $1)"
+        )
+        .bind(name)
+        .bind(body)
+        .bind(now)
+        .execute(pool)
+        .await?;
+        seeded += inserted.rows_affected();
+    }
+    if seeded > 0 {
+        println!("[AI] Seeded {} built-in prompt templates.", seeded);
+    }
+    println!("[AI] Database initialized (prompt_templates).");
+    Ok(())
+}
+
+/// Picks one enabled version of `name` by weight; None when none is enabled.
+async fn pick(pool: &Pool<Postgres>, name: &str) -> Result<Option<(i32, String)>, sqlx::Error> {
+    let versions: Vec<(i32, String, i32)> = sqlx::query_as(
+        "SELECT version, body, weight FROM prompt_templates WHERE name = $1 AND enabled AND weight > 0 ORDER BY version"
+    )
+    .bind(name)
+    .fetch_all(pool)
+    .await?;
+    let total: u128 = versions.iter().map(|(_, _, weight)| *weight as u128).sum();
+    if total == 0 {
+        return Ok(None);
+    }
+    let mut roll = uuid::Uuid::new_v4().as_u128() % total;
+    for (version, body, weight) in versions {
+        if roll < weight as u128 {
+            return Ok(Some((version, body)));
+        }
+        roll -= weight as u128;
+    }
+    Ok(None)
+}
+
+/// Renders `name` with `vars`, falling back to the built-in text.
+pub async fn render(pool: &Pool<Postgres>, name: &str, vars: serde_json::Value) -> Rendered {
+    match pick(pool, name).await {
+        Ok(Some((version, body))) => match render_body(&body, &vars) {
+            Ok(text) => return Rendered { text, version: format!("{}@{}", name, version) },
+            Err(e) => println!("[AI] Prompt {}@{} does not render, using the built-in one: {}", name, version, e),
+        },
+        Ok(None) => {}
+        Err(e) => println!("[AI] Cannot load prompt {}, using the built-in one: {}", name, e),
+    }
+    let body = template(name).map(|(_, _, _, body)| *body).unwrap_or_default();
+    let text = render_body(body, &vars).unwrap_or_else(|e| {
+        println!("[AI] Built-in prompt {} does not render: {}", name, e);
+        String::new()
+    });
+    Rendered { text, version: format!("{}@0", name) }
+}
+
+#[utoipa::path(tag = "ai", responses((status = 200, description = "Success")))]
+#[get("/settings/prompts")]
+pub async fn list_prompts(pool: web::Data<Pool<Postgres>>) -> impl Responder {
+    let versions = match sqlx::query_as::<_, PromptTemplate>("SELECT * FROM prompt_templates ORDER BY name, version")
+        .fetch_all(pool.get_ref())
+        .await
+    {
+        Ok(versions) => versions,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    let prompts: Vec<serde_json::Value> = TEMPLATES.iter().map(|(name, description, variables, _)| serde_json::json!({
+        "name": name,
+        "description": description,
+        "variables": variables,
+        "versions": versions.iter().filter(|v| v.name == *name).collect::<Vec<_>>(),
+    })).collect();
+    HttpResponse::Ok().json(prompts)
+}
+
+/// Adds the next version of a template after checking that it renders.
+#[utoipa::path(tag = "ai", request_body = PromptTemplateRequest, responses(
+    (status = 200, description = "Version stored"),
+    (status = 400, description = "Template does not render"),
+    (status = 404, description = "Unknown template"),
+))]
+#[post("/settings/prompts/{name}")]
+pub async fn add_prompt_version(
+    http_req: HttpRequest,
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+    req: web::Json<PromptTemplateRequest>,
+) -> impl Responder {
+    let name = path.into_inner();
+    if template(&name).is_none() {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Unknown template" }));
+    }
+    if let Err(e) = check(&name, &req.body) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("Template does not render: {}", e) }));
+    }
+    let author = crate::auth::current_user(&http_req).map(|u| u.username);
+
+    let stored: Result<PromptTemplate, sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+        if !req.alongside.unwrap_or(false) {
+            sqlx::query("UPDATE prompt_templates SET enabled = FALSE WHERE name = $1")
+                .bind(&name)
+                .execute(&mut *tx)
+                .await?;
+        }
+        let stored = sqlx::query_as::<_, PromptTemplate>(
+            "INSERT INTO prompt_templates (name, version, body, note, weight, created_by, created_at)
+             SELECT $1, COALESCE(MAX(version), 0) + 1, $2, $3, $4, $5, $6 FROM prompt_templates WHERE name = $1
+             RETURNING *"
+        )
+        .bind(&name)
+        .bind(&req.body)
+        .bind(req.note.as_deref().unwrap_or_default())
+        .bind(req.weight.unwrap_or(100).max(0))
+        .bind(&author)
+        .bind(chrono::Utc::now().timestamp())
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(stored)
+    }.await;
+    match stored {
+        Ok(version) => {
+            println!("[AI] Prompt {} v{} stored by {}", name, version.version, author.as_deref().unwrap_or("unknown"));
+            HttpResponse::Ok().json(version)
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Turns a version on or off or changes its A/B weight, e.g. `{"enabled": true}` to roll back.
+#[utoipa::path(tag = "ai", request_body = PromptVersionRequest, responses(
+    (status = 200, description = "Version updated"),
+    (status = 404, description = "Version not found"),
+))]
+#[post("/settings/prompts/{name}/{version}")]
+pub async fn update_prompt_version(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<(String, i32)>,
+    req: web::Json<PromptVersionRequest>,
+) -> impl Responder {
+    let (name, version) = path.into_inner();
+    let updated = sqlx::query_as::<_, PromptTemplate>(
+        "UPDATE prompt_templates SET enabled = COALESCE($3, enabled), weight = COALESCE($4, weight)
+         WHERE name = $1 AND version = $2 RETURNING *"
+    )
+    .bind(&name)
+    .bind(version)
+    .bind(req.enabled)
+    .bind(req.weight.map(|w| w.max(0)))
+    .fetch_optional(pool.get_ref())
+    .await;
+    match updated {
+        Ok(Some(version)) => HttpResponse::Ok().json(version),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({ "error": "Prompt version not found" })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// The calls made with each version of a template, side by side.
+#[utoipa::path(tag = "ai", params(PromptStatsQuery), responses((status = 200, description = "Success")))]
+#[get("/settings/prompts/{name}/stats")]
+pub async fn prompt_stats(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+    query: web::Query<PromptStatsQuery>,
+) -> impl Responder {
+    let name = path.into_inner();
+    if template(&name).is_none() {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Unknown template" }));
+    }
+    let days = query.days.unwrap_or(30).clamp(1, 3650);
+    let since = chrono::Utc::now().timestamp_millis() - days * 86_400_000;
+    // prompt_version lists every template of the call, e.g. "report_reduce_system@1,report_reduce@3"
+    let rows = sqlx::query_as::<_, (Option<String>, i64, i64, Option<f64>, Option<f64>, Option<f64>, Option<f64>, Option<f64>)>(
+        "SELECT SUBSTRING(u.prompt_version FROM $1) AS version,
+                COUNT(*), COUNT(*) FILTER (WHERE NOT u.success),
+                AVG(u.prompt_tokens)::FLOAT8, AVG(u.completion_tokens)::FLOAT8, AVG(u.duration_ms)::FLOAT8, SUM(u.cost_usd),
+                AVG(r.hallucination_score)::FLOAT8
+         FROM ai_usage u LEFT JOIN analysis_reports r ON r.task_id = u.task_id
+         WHERE u.prompt_version ~ $1 AND u.created_at >= $2
+         GROUP BY 1 ORDER BY 1"
+    )
+    .bind(format!("(?:^|,){}@([0-9]+)", name))
+    .bind(since)
+    .fetch_all(pool.get_ref())
+    .await;
+    match rows {
+        Ok(rows) => HttpResponse::Ok().json(serde_json::json!({
+            "name": name,
+            "days": days,
+            "versions": rows.into_iter().map(|(version, calls, failures, prompt_tokens, completion_tokens, duration_ms, cost_usd, hallucination)| serde_json::json!({
+                "version": version.and_then(|v| v.parse::<i32>().ok()),
+                "calls": calls,
+                "failures": failures,
+                "avg_prompt_tokens": prompt_tokens,
+                "avg_completion_tokens": completion_tokens,
+                "avg_duration_ms": duration_ms,
+                "cost_usd": cost_usd,
+                "avg_hallucination_score": hallucination,
+            })).collect::<Vec<_>>(),
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
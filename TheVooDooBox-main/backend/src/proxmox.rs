@@ -1,6 +1,12 @@
+use async_trait::async_trait;
 use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use std::error::Error;
+use crate::hypervisor::{GuestExecOutput, Hypervisor, NetworkPlan, Node, Snapshot, SpiceTicket, Vm, VncTicket};
+use reqwest::Method;
+
+/// Comment on the VM firewall rules we own, so later runs can find and replace them.
+const POLICY_RULE_TAG: &str = "voodoobox-policy";
 
 #[derive(Clone)]
 pub struct ProxmoxClient {
@@ -9,41 +15,19 @@ pub struct ProxmoxClient {
     http: Client,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Node {
-    pub node: String,
-    pub status: String,
-    pub maxcpu: Option<u64>,
-    pub maxmem: Option<u64>,
-}
-
 #[derive(Debug, Deserialize)]
 struct NodeResponse {
     data: Vec<Node>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Vm {
-    pub vmid: u64,
-    pub name: Option<String>,
-    pub status: String,
-    pub cpus: Option<u64>,
-    pub maxmem: Option<u64>,
-}
-
 #[derive(Debug, Deserialize)]
 struct VmResponse {
     data: Vec<Vm>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct VncTicket {
-    pub ticket: String,
-    pub port: String,
-    pub upid: String,
-    pub cert: Option<String>,
-    pub password: Option<String>,
-    pub host: Option<String>,
+#[derive(Debug, Deserialize)]
+struct SnapshotResponse {
+    data: Vec<Snapshot>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -51,21 +35,6 @@ struct VncTicketResponse {
     data: VncTicket,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SpiceTicket {
-    pub ticket: Option<String>, // Sometimes missing if password is used
-    pub password: Option<String>, // This is often the ticket for SPICE
-    pub host: Option<String>,
-    pub port: Option<u16>, // Standard port
-    pub proxy: String,
-    #[serde(rename = "tls-port")]
-    pub tls_port: Option<u16>,
-    pub ca: Option<String>,
-    #[serde(rename = "host-subject")]
-    pub host_subject: Option<String>,
-    pub title: Option<String>,
-}
-
 #[derive(Debug, Deserialize)]
 struct SpiceTicketResponse {
     data: SpiceTicket,
@@ -95,7 +64,80 @@ impl ProxmoxClient {
         }
     }
 
-    pub async fn get_nodes(&self) -> Result<Vec<Node>, Box<dyn Error>> {
+    /// Form-encoded API call returning the JSON body. Retried while the VM is locked: a
+    /// rollback that was just issued holds the config lock for a few seconds.
+    async fn call(&self, method: Method, path: &str, form: &[(&str, String)]) -> Result<serde_json::Value, Box<dyn Error>> {
+        let url = format!("{}{}", self.base_url, path);
+        let mut attempts = 0;
+        loop {
+            let mut req = self.http.request(method.clone(), &url).header("Authorization", &self.auth_header);
+            if !form.is_empty() {
+                req = req.form(form);
+            }
+            let resp = req.send().await?;
+            if resp.status().is_success() {
+                return Ok(resp.json().await.unwrap_or(serde_json::Value::Null));
+            }
+            let text = resp.text().await?;
+            if attempts >= 5 || !text.contains("lock") {
+                return Err(format!("Proxmox API Error: {}", text).into());
+            }
+            attempts += 1;
+            tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+        }
+    }
+
+    /// Follows a task UPID returned by an async Proxmox call until it stops.
+    async fn wait_task(&self, node: &str, upid: &str) -> Result<(), Box<dyn Error>> {
+        let path = format!("/nodes/{}/tasks/{}/status", node, urlencoding::encode(upid));
+        for _ in 0..600 {
+            let res = self.call(Method::GET, &path, &[]).await?;
+            if res["data"]["status"].as_str() == Some("stopped") {
+                let exit = res["data"]["exitstatus"].as_str().unwrap_or("");
+                return if exit == "OK" { Ok(()) } else { Err(format!("Proxmox task {} failed: {}", upid, exit).into()) };
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+        Err(format!("Proxmox task {} did not finish within 600s", upid).into())
+    }
+}
+
+/// Rewrites bridge/tag/firewall in a Proxmox `netX` value, keeping model, MAC and the rest.
+/// A new bridge drops the old VLAN tag unless the plan sets one.
+fn rewrite_nic(current: &str, plan: &NetworkPlan) -> String {
+    let mut parts: Vec<String> = current
+        .split(',')
+        .filter(|part| match part.split('=').next().unwrap_or("") {
+            "bridge" => plan.bridge.is_none(),
+            "tag" => plan.bridge.is_none() && plan.vlan_tag.is_none(),
+            "firewall" => plan.allow_only.is_none(),
+            _ => true,
+        })
+        .map(String::from)
+        .collect();
+    if let Some(bridge) = &plan.bridge {
+        parts.push(format!("bridge={}", bridge));
+    }
+    if let Some(tag) = plan.vlan_tag {
+        parts.push(format!("tag={}", tag));
+    }
+    if plan.allow_only.is_some() {
+        parts.push("firewall=1".to_string());
+    }
+    parts.join(",")
+}
+
+#[async_trait(?Send)]
+impl Hypervisor for ProxmoxClient {
+    fn kind(&self) -> &'static str {
+        "proxmox"
+    }
+
+    fn upstream_auth(&self) -> String {
+        self.auth_header.clone()
+    }
+
+    async fn get_nodes(&self) -> Result<Vec<Node>, Box<dyn Error>> {
         let url = format!("{}/nodes", self.base_url);
         
         let resp = self.http.get(&url)
@@ -111,7 +153,7 @@ impl ProxmoxClient {
         Ok(body.data)
     }
 
-    pub async fn get_vms(&self, node: &str) -> Result<Vec<Vm>, Box<dyn Error>> {
+    async fn get_vms(&self, node: &str) -> Result<Vec<Vm>, Box<dyn Error>> {
         let url = format!("{}/nodes/{}/qemu", self.base_url, node);
         
         let resp = self.http.get(&url)
@@ -127,7 +169,24 @@ impl ProxmoxClient {
         Ok(body.data)
     }
 
-    pub async fn create_vnc_proxy(&self, node: &str, vmid: u64) -> Result<VncTicket, Box<dyn Error>> {
+    /// Named snapshots of a VM (Proxmox's synthetic "current" entry is dropped).
+    async fn list_snapshots(&self, node: &str, vmid: u64) -> Result<Vec<Snapshot>, Box<dyn Error>> {
+        let url = format!("{}/nodes/{}/qemu/{}/snapshot", self.base_url, node, vmid);
+
+        let resp = self.http.get(&url)
+            .header("Authorization", &self.auth_header)
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            return Err(format!("Proxmox API Error: {}", resp.status()).into());
+        }
+
+        let body: SnapshotResponse = resp.json().await?;
+        Ok(body.data.into_iter().filter(|s| s.name != "current").collect())
+    }
+
+    async fn create_vnc_proxy(&self, node: &str, vmid: u64) -> Result<VncTicket, Box<dyn Error>> {
         let url = format!("{}/nodes/{}/qemu/{}/vncproxy", self.base_url, node, vmid);
         println!("[PROXMOX] Requesting VNC Proxy for Node: {}, VMID: {}", node, vmid);
         
@@ -167,7 +226,7 @@ impl ProxmoxClient {
         Ok(ticket_data)
     }
 
-    pub async fn create_spice_proxy(&self, node: &str, vmid: u64) -> Result<SpiceTicket, Box<dyn Error>> {
+    async fn create_spice_proxy(&self, node: &str, vmid: u64) -> Result<SpiceTicket, Box<dyn Error>> {
         let url = format!("{}/nodes/{}/qemu/{}/spiceproxy", self.base_url, node, vmid);
         println!("[PROXMOX] Requesting SPICE Proxy for Node: {}, VMID: {}", node, vmid);
         
@@ -209,7 +268,150 @@ impl ProxmoxClient {
         Ok(ticket_data)
     }
 
-    pub async fn vm_action(&self, node: &str, vmid: u64, action: &str) -> Result<(), Box<dyn Error>> {
+    async fn apply_network(&self, node: &str, vmid: u64, plan: &NetworkPlan) -> Result<(), Box<dyn Error>> {
+        let vm_path = format!("/nodes/{}/qemu/{}", node, vmid);
+
+        let config = self.call(Method::GET, &format!("{}/config", vm_path), &[]).await?;
+        let net0 = config["data"]["net0"].as_str().ok_or("VM has no net0 interface")?;
+        let nic = rewrite_nic(net0, plan);
+        if nic != net0 {
+            self.call(Method::POST, &format!("{}/config", vm_path), &[("net0", nic.clone())]).await?;
+            println!("[PROXMOX] VM {} net0 set to {}", vmid, nic);
+        }
+
+        // Firewall rules live outside the VM config, so a snapshot revert does not undo the
+        // previous run's lockdown. Remove ours highest position first so the rest keep theirs.
+        let rules_path = format!("{}/firewall/rules", vm_path);
+        let rules = self.call(Method::GET, &rules_path, &[]).await?;
+        let mut ours: Vec<u64> = rules["data"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|r| r["comment"].as_str() == Some(POLICY_RULE_TAG))
+            .filter_map(|r| r["pos"].as_u64())
+            .collect();
+        ours.sort_unstable_by(|a, b| b.cmp(a));
+        let was_locked = !ours.is_empty();
+        for pos in ours {
+            self.call(Method::DELETE, &format!("{}/{}", rules_path, pos), &[]).await?;
+        }
+
+        let options_path = format!("{}/firewall/options", vm_path);
+        match &plan.allow_only {
+            Some(addrs) => {
+                for addr in addrs {
+                    for (direction, field) in [("out", "dest"), ("in", "source")] {
+                        self.call(Method::POST, &rules_path, &[
+                            ("type", direction.to_string()),
+                            ("action", "ACCEPT".to_string()),
+                            (field, addr.clone()),
+                            ("enable", "1".to_string()),
+                            ("comment", POLICY_RULE_TAG.to_string()),
+                        ]).await?;
+                    }
+                }
+                self.call(Method::PUT, &options_path, &[
+                    ("enable", "1".to_string()),
+                    ("dhcp", "1".to_string()),
+                    ("policy_in", "DROP".to_string()),
+                    ("policy_out", "DROP".to_string()),
+                ]).await?;
+                println!("[PROXMOX] VM {} firewall locked down to {}", vmid, addrs.join(", "));
+            }
+            None if was_locked => {
+                self.call(Method::PUT, &options_path, &[
+                    ("enable", "0".to_string()),
+                    ("delete", "policy_in,policy_out".to_string()),
+                ]).await?;
+                println!("[PROXMOX] VM {} firewall lockdown lifted", vmid);
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    async fn guest_ping(&self, node: &str, vmid: u64) -> Result<(), Box<dyn Error>> {
+        self.call(Method::POST, &format!("/nodes/{}/qemu/{}/agent/ping", node, vmid), &[]).await?;
+        Ok(())
+    }
+
+    async fn guest_exec(&self, node: &str, vmid: u64, command: &[String], timeout_secs: u64) -> Result<GuestExecOutput, Box<dyn Error>> {
+        let agent_path = format!("/nodes/{}/qemu/{}/agent", node, vmid);
+        // `command` is an array parameter: one form field per element
+        let form: Vec<(&str, String)> = command.iter().map(|part| ("command", part.clone())).collect();
+        let started = self.call(Method::POST, &format!("{}/exec", agent_path), &form).await?;
+        let pid = started["data"]["pid"].as_u64().ok_or("Guest agent did not return a pid")?;
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(timeout_secs);
+        loop {
+            let status = self.call(Method::GET, &format!("{}/exec-status?pid={}", agent_path, pid), &[]).await?;
+            let data = &status["data"];
+            let exited = data["exited"].as_u64() == Some(1) || data["exited"].as_bool() == Some(true);
+            if exited || std::time::Instant::now() >= deadline {
+                return Ok(GuestExecOutput {
+                    exit_code: if exited { data["exitcode"].as_i64() } else { None },
+                    stdout: data["out-data"].as_str().unwrap_or("").to_string(),
+                    stderr: data["err-data"].as_str().unwrap_or("").to_string(),
+                });
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+    }
+
+    async fn guest_read_file(&self, node: &str, vmid: u64, path: &str) -> Result<String, Box<dyn Error>> {
+        let res = self.call(
+            Method::GET,
+            &format!("/nodes/{}/qemu/{}/agent/file-read?file={}", node, vmid, urlencoding::encode(path)),
+            &[],
+        ).await?;
+        if res["data"]["truncated"].as_bool() == Some(true) || res["data"]["truncated"].as_u64() == Some(1) {
+            return Err(format!("{} is larger than the guest agent will return", path).into());
+        }
+        Ok(res["data"]["content"].as_str().unwrap_or("").to_string())
+    }
+
+    async fn dump_memory(&self, node: &str, vmid: u64, path: &str) -> Result<(), Box<dyn Error>> {
+        // Detached (-d) so the API call returns; completion is polled through `info dump`
+        let monitor = format!("/nodes/{}/qemu/{}/monitor", node, vmid);
+        let started = self.call(Method::POST, &monitor, &[("command", format!("dump-guest-memory -d {}", path))]).await?;
+        let out = started["data"].as_str().unwrap_or("");
+        if !out.trim().is_empty() {
+            return Err(format!("dump-guest-memory failed: {}", out.trim()).into());
+        }
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+            let info = self.call(Method::POST, &monitor, &[("command", "info dump".to_string())]).await?;
+            let status = info["data"].as_str().unwrap_or("").to_lowercase();
+            if status.contains("completed") {
+                return Ok(());
+            }
+            if status.contains("failed") || !status.contains("active") {
+                return Err(format!("Memory dump did not complete: {}", status.trim()).into());
+            }
+        }
+    }
+
+    async fn create_snapshot(&self, node: &str, vmid: u64, name: &str, description: &str) -> Result<(), Box<dyn Error>> {
+        let res = self.call(
+            Method::POST,
+            &format!("/nodes/{}/qemu/{}/snapshot", node, vmid),
+            &[("snapname", name.to_string()), ("description", description.to_string())],
+        ).await?;
+        match res["data"].as_str() {
+            Some(upid) => self.wait_task(node, upid).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn delete_snapshot(&self, node: &str, vmid: u64, name: &str) -> Result<(), Box<dyn Error>> {
+        let res = self.call(Method::DELETE, &format!("/nodes/{}/qemu/{}/snapshot/{}", node, vmid, name), &[]).await?;
+        match res["data"].as_str() {
+            Some(upid) => self.wait_task(node, upid).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn vm_action(&self, node: &str, vmid: u64, action: &str) -> Result<(), Box<dyn Error>> {
         let url = format!("{}/nodes/{}/qemu/{}/status/{}", self.base_url, node, vmid, action);
         
         let mut attempts = 0;
@@ -238,7 +440,7 @@ impl ProxmoxClient {
         }
     }
 
-    pub async fn rollback_snapshot(&self, node: &str, vmid: u64, snapshot: &str) -> Result<(), Box<dyn Error>> {
+    async fn rollback_snapshot(&self, node: &str, vmid: u64, snapshot: &str) -> Result<(), Box<dyn Error>> {
         let url = format!("{}/nodes/{}/qemu/{}/snapshot/{}/rollback", self.base_url, node, vmid, snapshot);
         
         let mut attempts = 0;
@@ -0,0 +1,235 @@
+use regex::Regex;
+use sqlx::{Pool, Postgres};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::Notify;
+use crate::alerts::NewAlert;
+use crate::sigma::EventRef;
+use crate::stream::Broadcaster;
+
+// --- RANSOMWARE DETECTOR ---
+// Mass encryption, shadow copy deletion and ransom notes.
+
+const DEFAULT_BURST: usize = 30;
+const DEFAULT_WINDOW_SECS: i64 = 60;
+/// Documents sharing one appended extension before it counts as encryption.
+const APPENDED_MIN: usize = 10;
+/// Folders one note name must appear in when the name alone is not conclusive.
+const NOTE_FOLDERS_MIN: usize = 3;
+/// Windows idle longer than this are dropped (tasks that ended without release()).
+const IDLE_MS: i64 = 30 * 60 * 1000;
+
+const DOCUMENT_EXTS: &[&str] = &[
+    "doc", "docx", "xls", "xlsx", "ppt", "pptx", "pdf", "txt", "rtf", "odt", "ods", "csv", "jpg", "jpeg", "png", "gif",
+    "bmp", "mp3", "mp4", "zip", "7z", "rar", "sql", "mdb", "accdb", "psd", "dwg", "xml", "json", "html", "eml", "msg",
+];
+/// Extensions that get appended to documents for ordinary reasons.
+const BENIGN_APPENDED: &[&str] = &["tmp", "bak", "lnk", "part", "crdownload", "partial", "old", "orig", "swp", "download"];
+const NOTE_EXTS: &[&str] = &["txt", "html", "htm", "hta", "rtf", "url"];
+const SYSTEM_DIRS: &[&str] = &["\\windows\\", "\\programdata\\microsoft\\", "\\$recycle.bin\\", "\\appdata\\local\\microsoft\\", "/proc/", "/sys/"];
+
+/// (all of, any of) substrings of a lowercased command line.
+const RECOVERY_COMMANDS: &[(&[&str], &[&str])] = &[
+    (&["vssadmin"], &["delete shadows", "resize shadowstorage"]),
+    (&["wmic", "shadowcopy"], &["delete"]),
+    (&["win32_shadowcopy"], &["delete", "remove-"]),
+    (&["wbadmin", "delete"], &["catalog", "systemstatebackup", "backup"]),
+    (&["bcdedit"], &["recoveryenabled no", "bootstatuspolicy ignoreallfailures"]),
+];
+
+#[derive(Default)]
+struct Window {
+    /// (timestamp, path) of document writes inside the window.
+    touched: VecDeque<(i64, String)>,
+    appended: HashMap<String, usize>,
+    /// note file name -> folders it was dropped in
+    notes: HashMap<String, HashSet<String>>,
+    raised: HashSet<&'static str>,
+    last_seen: i64,
+}
+
+fn windows() -> &'static Mutex<HashMap<String, Window>> {
+    static WINDOWS: OnceLock<Mutex<HashMap<String, Window>>> = OnceLock::new();
+    WINDOWS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn early_stops() -> &'static Mutex<HashMap<String, Arc<Notify>>> {
+    static STOPS: OnceLock<Mutex<HashMap<String, Arc<Notify>>>> = OnceLock::new();
+    STOPS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn note_name() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?i)decrypt|ransom|restore[_\-\s]?(my|your|all)?[_\-\s]?files|recover[_\-\s]?(my|your)?[_\-\s]?files|your[_\-\s]?files|files[_\-\s]?encrypted|!+read[_\-\s]?me").unwrap())
+}
+
+fn env_number(key: &str, default: i64) -> i64 {
+    std::env::var(key).ok().and_then(|v| v.trim().parse().ok()).unwrap_or(default)
+}
+
+fn auto_stop() -> bool {
+    std::env::var("RANSOMWARE_AUTO_STOP").map(|v| v == "true" || v == "1").unwrap_or(false)
+}
+
+/// Called by the orchestrator before the monitor phase; notified when the run should end early.
+pub fn watch(task_id: &str) -> Arc<Notify> {
+    let notify = Arc::new(Notify::new());
+    if let Ok(mut stops) = early_stops().lock() {
+        stops.insert(task_id.to_string(), notify.clone());
+    }
+    notify
+}
+
+/// Drops the task's early-stop hook and detector state once its monitor phase is over.
+pub fn release(task_id: &str) {
+    if let Ok(mut stops) = early_stops().lock() {
+        stops.remove(task_id);
+    }
+    if let Ok(mut windows) = windows().lock() {
+        windows.remove(task_id);
+    }
+}
+
+/// "SYSMON: File Created: C:\x" / "File Activity: C:\x (SHA256: ...)" -> "C:\x"
+fn file_path(details: &str) -> Option<&str> {
+    let rest = details.find("File Created: ").map(|p| &details[p + 14..])
+        .or_else(|| details.find("File Activity: ").map(|p| &details[p + 15..]))?;
+    let path = rest.rsplit_once(" (SHA256:").map(|(p, _)| p).unwrap_or(rest).trim();
+    (!path.is_empty()).then_some(path)
+}
+
+/// (folder, file name, extensions from the right) for a path with either separator.
+fn split_path(path: &str) -> (String, String, Vec<String>) {
+    let lower = path.to_lowercase();
+    let (folder, name) = lower.rsplit_once(['\\', '/']).unwrap_or(("", &lower));
+    let exts = name.rsplit('.').take(name.matches('.').count()).map(str::to_string).collect();
+    (folder.to_string(), name.to_string(), exts)
+}
+
+fn is_recovery_command(cmd: &str) -> bool {
+    let cmd = cmd.to_lowercase();
+    RECOVERY_COMMANDS.iter().any(|(all, any)| all.iter().all(|s| cmd.contains(s)) && any.iter().any(|s| cmd.contains(s)))
+}
+
+/// What one event adds to its task's window; returns the kinds newly seen and a description.
+fn observe(window: &mut Window, evt: &EventRef<'_>) -> Vec<(&'static str, String)> {
+    let mut hits = Vec::new();
+    window.last_seen = evt.timestamp;
+
+    if evt.event_type == "PROCESS_CREATE" && is_recovery_command(evt.details) {
+        hits.push(("shadow_delete", format!("Recovery removed: {}", evt.details)));
+    }
+
+    if !evt.event_type.starts_with("FILE_") && evt.event_type != "DOWNLOAD_DETECTED" {
+        return hits;
+    }
+    let Some(path) = file_path(evt.details) else { return hits };
+    let (folder, name, exts) = split_path(path);
+    if SYSTEM_DIRS.iter().any(|d| path.to_lowercase().contains(d)) {
+        return hits;
+    }
+
+    if evt.event_type.starts_with("FILE_CREATE") && exts.first().is_some_and(|e| NOTE_EXTS.contains(&e.as_str())) {
+        let folders = window.notes.entry(name.clone()).or_default();
+        folders.insert(folder);
+        if note_name().is_match(&name) || folders.len() >= NOTE_FOLDERS_MIN {
+            hits.push(("ransom_note", format!("Ransom note dropped: {} ({} folder(s))", path, folders.len())));
+        }
+    }
+
+    // "name.docx.lockbit": a document given an extension nothing legitimate appends
+    let appended = match exts.as_slice() {
+        [outer, inner, ..] if DOCUMENT_EXTS.contains(&inner.as_str())
+            && !DOCUMENT_EXTS.contains(&outer.as_str())
+            && !BENIGN_APPENDED.contains(&outer.as_str())
+            && outer.len() <= 24 => Some(outer.clone()),
+        _ => None,
+    };
+    let is_document = appended.is_some() || exts.first().is_some_and(|e| DOCUMENT_EXTS.contains(&e.as_str()));
+    if !is_document {
+        return hits;
+    }
+    if let Some(ext) = appended {
+        let count = window.appended.entry(ext.clone()).or_default();
+        *count += 1;
+        if *count >= APPENDED_MIN {
+            hits.push(("mass_encryption", format!("{} documents renamed to *.{} (latest {})", count, ext, path)));
+        }
+    }
+
+    let window_ms = env_number("RANSOMWARE_WINDOW_SECS", DEFAULT_WINDOW_SECS).max(1) * 1000;
+    let burst = env_number("RANSOMWARE_FILE_BURST", DEFAULT_BURST as i64).max(1) as usize;
+    if !window.touched.iter().any(|(_, p)| p == path) {
+        window.touched.push_back((evt.timestamp, path.to_string()));
+    }
+    while window.touched.front().is_some_and(|(ts, _)| evt.timestamp - ts > window_ms) {
+        window.touched.pop_front();
+    }
+    if window.touched.len() >= burst {
+        hits.push(("mass_encryption", format!("{} user documents written, renamed or removed within {}s by {}",
+            window.touched.len(), window_ms / 1000, evt.process_name)));
+    }
+    hits
+}
+
+/// Runs the detector over freshly stored events; events outside a task are ignored.
+pub async fn evaluate(pool: &Pool<Postgres>, broadcaster: Option<&Broadcaster>, events: &[EventRef<'_>]) {
+    let mut alerts = Vec::new();
+    let mut stop = HashSet::new();
+    if let Ok(mut windows) = windows().lock() {
+        for evt in events {
+            let Some(task_id) = evt.task_id else { continue };
+            let window = windows.entry(task_id.to_string()).or_default();
+            for (kind, details) in observe(window, evt) {
+                if !window.raised.insert(kind) {
+                    continue;
+                }
+                let (title, technique, severity) = match kind {
+                    "shadow_delete" => ("RANSOMWARE_SUSPECTED: shadow copies or recovery deleted", "attack.t1490", 90),
+                    "ransom_note" => ("RANSOMWARE_SUSPECTED: ransom note created", "attack.t1486", 95),
+                    _ => ("RANSOMWARE_SUSPECTED: mass file encryption", "attack.t1486", 95),
+                };
+                alerts.push(NewAlert {
+                    task_id: Some(task_id.to_string()),
+                    event_id: evt.id,
+                    source: "ransomware",
+                    rule_id: format!("ransomware:{}", kind),
+                    rule_title: title.to_string(),
+                    level: "critical".to_string(),
+                    severity,
+                    tags: vec!["attack.impact".to_string(), technique.to_string()],
+                    rule_references: Vec::new(),
+                    process_id: evt.process_id,
+                    process_name: evt.process_name.to_string(),
+                    details,
+                    timestamp: evt.timestamp,
+                });
+                stop.insert(task_id.to_string());
+            }
+        }
+        if windows.len() > 64 {
+            let newest = windows.values().map(|w| w.last_seen).max().unwrap_or(0);
+            windows.retain(|_, w| newest - w.last_seen < IDLE_MS);
+        }
+    }
+    if alerts.is_empty() {
+        return;
+    }
+
+    for task_id in &stop {
+        println!("[RANSOMWARE] Ransomware behavior in task {}.", task_id);
+    }
+    crate::alerts::record(pool, broadcaster, alerts).await;
+
+    if auto_stop() {
+        if let Ok(stops) = early_stops().lock() {
+            for task_id in &stop {
+                if let Some(notify) = stops.get(task_id) {
+                    println!("[RANSOMWARE] RANSOMWARE_AUTO_STOP: ending the monitor phase of task {} now.", task_id);
+                    // notify_one keeps a permit if the orchestrator is not waiting yet
+                    notify.notify_one();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+
+// --- SAMPLE RELATIONSHIPS ---
+// Why one task is another's child.
+
+pub const RELATIONS: &[&str] = &["dropped", "pivot", "email_attachment", "email_link", "url_payload", "related"];
+
+const DEFAULT_MAX_NODES: i64 = 200;
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS task_relationships (
+            id SERIAL PRIMARY KEY,
+            parent_task_id TEXT NOT NULL,
+            child_task_id TEXT NOT NULL,
+            relation TEXT NOT NULL,
+            detail TEXT,
+            created_at BIGINT NOT NULL,
+            UNIQUE (parent_task_id, child_task_id, relation)
+        )"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_task_relationships_child ON task_relationships(child_task_id)")
+        .execute(pool)
+        .await?;
+
+    // Pivots and email children from before the table existed, told apart by how their ids
+    // and file names were made
+    sqlx::query(
+        "INSERT INTO task_relationships (parent_task_id, child_task_id, relation, created_at)
+         SELECT parent_task_id, id,
+                CASE WHEN id LIKE parent_task_id || '-a%' THEN 'email_attachment'
+                     WHEN id LIKE parent_task_id || '-u%' THEN 'email_link'
+                     ELSE 'pivot' END,
+                created_at
+         FROM tasks
+         WHERE parent_task_id IS NOT NULL
+           AND (filename LIKE 'pivot\\_%' OR id LIKE parent_task_id || '-a%' OR id LIKE parent_task_id || '-u%')
+         ON CONFLICT DO NOTHING"
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+fn max_nodes() -> i64 {
+    std::env::var("FAMILY_MAX_NODES")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_MAX_NODES)
+}
+
+/// Records `parent` -> `child`; an existing identical link is left alone.
+pub async fn link(pool: &Pool<Postgres>, parent: &str, child: &str, relation: &str, detail: Option<&str>) {
+    let result = sqlx::query(
+        "INSERT INTO task_relationships (parent_task_id, child_task_id, relation, detail, created_at)
+         VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT DO NOTHING"
+    )
+    .bind(parent)
+    .bind(child)
+    .bind(relation)
+    .bind(detail)
+    .bind(chrono::Utc::now().timestamp_millis())
+    .execute(pool)
+    .await;
+    if let Err(e) = result {
+        println!("[RELATIONSHIPS] Failed to link {} -> {} ({}): {}", parent, child, relation, e);
+    }
+}
+
+/// `dropped` when the parent's telemetry saw the file written, `pivot` otherwise.
+pub async fn link_pivot(pool: &Pool<Postgres>, parent: &str, child: &str, guest_path: Option<&str>) {
+    let writer: Option<String> = match guest_path.filter(|p| !p.trim().is_empty()) {
+        Some(path) => sqlx::query_scalar(
+            "SELECT process_name FROM events
+             WHERE task_id = $1 AND event_type = 'FILE_CREATE' AND position(LOWER($2) IN LOWER(details)) > 0
+             ORDER BY timestamp LIMIT 1"
+        )
+        .bind(parent)
+        .bind(path.trim())
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten(),
+        None => None,
+    };
+    match writer {
+        Some(process) => {
+            let detail = format!("{} (written by {})", guest_path.unwrap_or_default().trim(), process);
+            link(pool, parent, child, "dropped", Some(&detail)).await;
+        }
+        None => link(pool, parent, child, "pivot", guest_path).await,
+    }
+}
+
+/// Links a new sample to every URL task that downloaded the same file.
+pub async fn link_url_payloads(pool: &Pool<Postgres>, task_id: &str, sha256: &str) {
+    if sha256.is_empty() || sha256 == "N/A" {
+        return;
+    }
+    let result = sqlx::query(
+        "INSERT INTO task_relationships (parent_task_id, child_task_id, relation, detail, created_at)
+         SELECT u.task_id, $1, 'url_payload', COALESCE(p->>'source_url', p->>'filename'), $3
+         FROM url_artifacts u, jsonb_array_elements(u.payloads) p
+         WHERE p->>'sha256' = $2 AND u.task_id <> $1
+         ON CONFLICT DO NOTHING"
+    )
+    .bind(task_id)
+    .bind(sha256.to_lowercase())
+    .bind(chrono::Utc::now().timestamp_millis())
+    .execute(pool)
+    .await;
+    match result {
+        Ok(r) if r.rows_affected() > 0 => {
+            println!("[RELATIONSHIPS] Task {} linked to {} URL task(s) that downloaded it.", task_id, r.rows_affected());
+        }
+        Ok(_) => {}
+        Err(e) => println!("[RELATIONSHIPS] URL payload lookup for task {} failed: {}", task_id, e),
+    }
+}
+
+/// Drops every link to or from a deleted task.
+pub async fn forget(pool: &Pool<Postgres>, task_id: &str) {
+    let _ = sqlx::query("DELETE FROM task_relationships WHERE parent_task_id = $1 OR child_task_id = $1")
+        .bind(task_id)
+        .execute(pool)
+        .await;
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Relationship {
+    pub id: i32,
+    pub parent_task_id: String,
+    pub child_task_id: String,
+    pub relation: String,
+    pub detail: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct FamilyNode {
+    pub id: String,
+    pub original_filename: String,
+    pub file_hash: String,
+    pub status: String,
+    pub verdict: Option<String>,
+    pub risk_score: Option<i32>,
+    pub created_at: i64,
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct RelationshipRequest {
+    pub parent_task_id: String,
+    pub child_task_id: String,
+    /// dropped | pivot | email_attachment | email_link | url_payload | related (default)
+    pub relation: Option<String>,
+    pub detail: Option<String>,
+}
+
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Direct parents and children of the task")))]
+#[get("/tasks/{id}/relationships")]
+pub async fn get_relationships(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let task_id = path.into_inner();
+    let links = sqlx::query_as::<_, Relationship>(
+        "SELECT * FROM task_relationships WHERE parent_task_id = $1 OR child_task_id = $1 ORDER BY created_at"
+    )
+    .bind(&task_id)
+    .fetch_all(pool.get_ref())
+    .await;
+    match links {
+        Ok(links) => {
+            let (parents, children): (Vec<_>, Vec<_>) = links.into_iter().partition(|l| l.child_task_id == task_id);
+            HttpResponse::Ok().json(serde_json::json!({ "task_id": task_id, "parents": parents, "children": children }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "tasks", responses(
+    (status = 200, description = "Every task linked to this one, directly or not, with the links"),
+    (status = 404, description = "Task not found"),
+))]
+#[get("/tasks/{id}/family")]
+pub async fn get_family(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let task_id = path.into_inner();
+    let limit = max_nodes();
+    // UNION (not UNION ALL) drops tasks already reached, so cycles end the walk
+    let ids: Vec<String> = match sqlx::query_scalar(
+        "WITH RECURSIVE family(id) AS (
+             SELECT $1::text
+             UNION
+             SELECT CASE WHEN r.parent_task_id = f.id THEN r.child_task_id ELSE r.parent_task_id END
+             FROM task_relationships r
+             JOIN family f ON r.parent_task_id = f.id OR r.child_task_id = f.id
+         )
+         SELECT id FROM family LIMIT $2"
+    )
+    .bind(&task_id)
+    .bind(limit)
+    .fetch_all(pool.get_ref())
+    .await
+    {
+        Ok(ids) => ids,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+
+    let nodes = match sqlx::query_as::<_, FamilyNode>(
+        "SELECT id, original_filename, file_hash, status, verdict, risk_score, created_at
+         FROM tasks WHERE id = ANY($1) ORDER BY created_at"
+    )
+    .bind(&ids)
+    .fetch_all(pool.get_ref())
+    .await
+    {
+        Ok(n) => n,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    if !nodes.iter().any(|n| n.id == task_id) {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" }));
+    }
+    let edges = match sqlx::query_as::<_, Relationship>(
+        "SELECT * FROM task_relationships
+         WHERE parent_task_id = ANY($1) AND child_task_id = ANY($1)
+         ORDER BY created_at"
+    )
+    .bind(&ids)
+    .fetch_all(pool.get_ref())
+    .await
+    {
+        Ok(e) => e,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+
+    // Tasks nothing points at: where the family started
+    let roots: Vec<&str> = nodes.iter()
+        .filter(|n| !edges.iter().any(|e| e.child_task_id == n.id))
+        .map(|n| n.id.as_str())
+        .collect();
+    HttpResponse::Ok().json(serde_json::json!({
+        "task_id": task_id,
+        "roots": roots,
+        "nodes": nodes,
+        "edges": edges,
+        "truncated": ids.len() as i64 >= limit,
+    }))
+}
+
+#[utoipa::path(tag = "tasks", request_body = RelationshipRequest, responses(
+    (status = 200, description = "Link recorded"),
+    (status = 400, description = "Unknown relation or a task linked to itself"),
+    (status = 404, description = "Task not found"),
+))]
+#[post("/task-relationships")]
+pub async fn create_relationship(
+    pool: web::Data<Pool<Postgres>>,
+    req: web::Json<RelationshipRequest>,
+) -> impl Responder {
+    let relation = req.relation.as_deref().map(str::trim).unwrap_or("related");
+    if !RELATIONS.contains(&relation) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("relation must be one of {}", RELATIONS.join(", ")) }));
+    }
+    if req.parent_task_id == req.child_task_id {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "A task cannot be its own parent" }));
+    }
+    let found: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM tasks WHERE id = $1 OR id = $2")
+        .bind(&req.parent_task_id)
+        .bind(&req.child_task_id)
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(0);
+    if found < 2 {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" }));
+    }
+
+    let detail = req.detail.as_deref().map(str::trim).filter(|d| !d.is_empty());
+    link(pool.get_ref(), &req.parent_task_id, &req.child_task_id, relation, detail).await;
+    match sqlx::query_as::<_, Relationship>(
+        "SELECT * FROM task_relationships WHERE parent_task_id = $1 AND child_task_id = $2 AND relation = $3"
+    )
+    .bind(&req.parent_task_id)
+    .bind(&req.child_task_id)
+    .bind(relation)
+    .fetch_optional(pool.get_ref())
+    .await
+    {
+        Ok(Some(r)) => HttpResponse::Ok().json(r),
+        Ok(None) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Link was not stored" })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "tasks", responses(
+    (status = 200, description = "Link removed"),
+    (status = 404, description = "No such link"),
+))]
+#[delete("/task-relationships/{id}")]
+pub async fn delete_relationship(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<i32>,
+) -> impl Responder {
+    match sqlx::query("DELETE FROM task_relationships WHERE id = $1")
+        .bind(path.into_inner())
+        .execute(pool.get_ref())
+        .await
+    {
+        Ok(r) if r.rows_affected() == 0 => HttpResponse::NotFound().json(serde_json::json!({ "error": "Relationship not found" })),
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "status": "deleted" })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
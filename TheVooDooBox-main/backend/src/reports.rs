@@ -291,12 +291,12 @@ pub fn generate_pdf_file(_task_id: &String, report: &ForensicReport, context: &A
     doc.push(elements::Paragraph::new("Hierarchical view of spawned processes during detonation.").styled(style::Style::new().italic().with_font_size(10).with_color(style::Color::Rgb(100,100,100))));
     doc.push(elements::Break::new(0.5));
     
-    // Simple indentation logic based on sorting/parent relations could be complex here.
-    // We will list them with basic details for now as the `context.processes` is flat.
-    // Ideally we would build a tree, but a flat list with PPID reference is acceptable for V1.
-    for proc in &context.processes {
-        let indent = if proc.ppid > 0 { "  |-- " } else { "" };
-        let text = format!("{} {} (PID: {})", indent, proc.image_name, proc.pid);
+    // Same lineage as GET /tasks/{id}/process-tree, indented by depth
+    let pairs: Vec<(i32, i32)> = context.processes.iter().map(|p| (p.pid, p.ppid)).collect();
+    for (i, depth) in crate::process_tree::depth_first(&pairs) {
+        let proc = &context.processes[i];
+        let indent = if depth > 0 { format!("{}|-- ", "    ".repeat(depth - 1)) } else { String::new() };
+        let text = format!("{}{} (PID: {})", indent, proc.image_name, proc.pid);
         let p = elements::Paragraph::new(text);
         
         // Highlight malware PIDs (only if they are numerical)
@@ -373,6 +373,23 @@ pub fn generate_pdf_file(_task_id: &String, report: &ForensicReport, context: &A
         }
     }
 
+    if !context.yara_matches.is_empty() {
+        doc.push(elements::Break::new(0.5));
+        doc.push(elements::Paragraph::new("YARA Matches").styled(style::Style::new().bold()));
+        for m in &context.yara_matches {
+             let tags = if m.tags.is_empty() { String::new() } else { format!(" [{}]", m.tags.join(", ")) };
+             doc.push(elements::Paragraph::new(format!("- {} ({}): {}:{}{}", m.target_name, m.target_kind, m.ruleset, m.rule, tags)).styled(style::Style::new().with_color(style::Color::Rgb(220, 38, 38))));
+        }
+    }
+
+    if !context.static_properties.is_empty() {
+        doc.push(elements::Break::new(0.5));
+        doc.push(elements::Paragraph::new("File Properties").styled(style::Style::new().bold()));
+        for line in crate::static_properties::describe(&context.static_properties).lines() {
+             doc.push(elements::Paragraph::new(format!("- {}", line)).styled(style::Style::new().with_font_size(9)));
+        }
+    }
+
     // --- DETAILED ACTIVITY LOG ---
     doc.push(elements::Break::new(2.0));
     doc.push(elements::Paragraph::new("Detailed Activity Log").styled(summary_style));
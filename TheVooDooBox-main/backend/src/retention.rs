@@ -0,0 +1,303 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use chrono::{Datelike, TimeZone, Utc};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use std::env;
+
+// --- EVENT PARTITIONING & RETENTION ---
+// EVENTS_PARTITIONING=monthly and per-verdict EVENTS_RETENTION.
+
+const CLASSES: &[&str] = &["malicious", "suspicious", "benign", "unknown", "unassigned"];
+
+/// SQL expression mapping a task row `t` to its retention class.
+const TASK_CLASS: &str = "CASE WHEN LOWER(t.verdict) IN ('malicious', 'suspicious', 'benign') THEN LOWER(t.verdict) ELSE 'unknown' END";
+
+/// (class, days to keep); classes kept forever are left out.
+pub fn policy() -> Vec<(&'static str, i64)> {
+    let raw = env::var("EVENTS_RETENTION").unwrap_or_default();
+    let mut rules = Vec::new();
+    for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let Some((class, days)) = entry.split_once('=') else {
+            println!("[RETENTION] Ignoring malformed EVENTS_RETENTION entry '{}'", entry);
+            continue;
+        };
+        let class = class.trim().to_lowercase();
+        let Some(class) = CLASSES.iter().find(|c| **c == class) else {
+            println!("[RETENTION] Unknown retention class '{}'", class);
+            continue;
+        };
+        match days.trim().to_lowercase().as_str() {
+            "forever" | "never" | "0" => {}
+            days => match days.parse::<i64>() {
+                Ok(d) if d > 0 => rules.push((*class, d)),
+                _ => println!("[RETENTION] Invalid retention days '{}' for {}", days, class),
+            },
+        }
+    }
+    rules
+}
+
+fn month_start(year: i32, month: u32) -> i64 {
+    Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single().map(|d| d.timestamp_millis()).unwrap_or(0)
+}
+
+fn next_month(year: i32, month: u32) -> (i32, u32) {
+    if month == 12 { (year + 1, 1) } else { (year, month + 1) }
+}
+
+/// Creates the monthly partition holding `year`-`month` unless it already exists.
+async fn ensure_partition(pool: &Pool<Postgres>, year: i32, month: u32) -> Result<(), sqlx::Error> {
+    let (ny, nm) = next_month(year, month);
+    let sql = format!(
+        "CREATE TABLE IF NOT EXISTS events_y{:04}m{:02} PARTITION OF events FOR VALUES FROM ({}) TO ({})",
+        year, month, month_start(year, month), month_start(ny, nm)
+    );
+    sqlx::query(&sql).execute(pool).await?;
+    Ok(())
+}
+
+async fn is_partitioned(pool: &Pool<Postgres>) -> bool {
+    sqlx::query_scalar::<_, bool>("SELECT relkind = 'p' FROM pg_class WHERE oid = to_regclass('events')")
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or(false)
+}
+
+/// Current month plus two ahead, so inserts never fall back to the default partition.
+pub async fn ensure_upcoming_partitions(pool: &Pool<Postgres>) {
+    if !is_partitioned(pool).await {
+        return;
+    }
+    let now = Utc::now();
+    let (mut year, mut month) = (now.year(), now.month());
+    for _ in 0..3 {
+        if let Err(e) = ensure_partition(pool, year, month).await {
+            println!("[RETENTION] Failed to create partition for {}-{:02}: {}", year, month, e);
+        }
+        (year, month) = next_month(year, month);
+    }
+}
+
+/// One-off conversion; run after the column migrations and before the indexes.
+pub async fn partition_events(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    if env::var("EVENTS_PARTITIONING").map(|v| v.to_lowercase()).unwrap_or_default() != "monthly" || is_partitioned(pool).await {
+        return Ok(());
+    }
+    println!("[RETENTION] Converting events to a monthly partitioned table...");
+    let mut tx = pool.begin().await?;
+    sqlx::query("ALTER TABLE events RENAME TO events_legacy").execute(&mut *tx).await?;
+    sqlx::query(
+        "CREATE TABLE events (
+            id INTEGER NOT NULL DEFAULT nextval('events_id_seq'),
+            event_type TEXT NOT NULL,
+            process_id INTEGER NOT NULL,
+            parent_process_id INTEGER NOT NULL,
+            process_name TEXT NOT NULL,
+            details TEXT NOT NULL,
+            decoded_details TEXT,
+            timestamp BIGINT NOT NULL,
+            task_id TEXT,
+            session_id TEXT,
+            digital_signature TEXT,
+            severity INTEGER,
+            category TEXT,
+            kill_chain_stage TEXT,
+            decoder_version INTEGER,
+            obfuscation_score INTEGER,
+            occurrences INTEGER NOT NULL DEFAULT 1,
+            last_seen BIGINT,
+            PRIMARY KEY (id, timestamp)
+        ) PARTITION BY RANGE (timestamp)"
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("ALTER SEQUENCE events_id_seq OWNED BY events.id").execute(&mut *tx).await?;
+    sqlx::query("CREATE TABLE events_default PARTITION OF events DEFAULT").execute(&mut *tx).await?;
+
+    // Monthly partitions for the span of existing data (millisecond timestamps only)
+    let (min, max): (Option<i64>, Option<i64>) = sqlx::query_as(
+        "SELECT MIN(timestamp), MAX(timestamp) FROM events_legacy WHERE timestamp >= 946684800000"
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    let now = Utc::now().timestamp_millis();
+    let first = Utc.timestamp_millis_opt(min.unwrap_or(now).min(now)).single().unwrap_or_else(Utc::now);
+    let last = Utc.timestamp_millis_opt(max.unwrap_or(now).max(now)).single().unwrap_or_else(Utc::now);
+    let (mut year, mut month) = (first.year(), first.month());
+    let mut created = 0;
+    while (year, month) <= (last.year(), last.month()) {
+        let (ny, nm) = next_month(year, month);
+        sqlx::query(&format!(
+            "CREATE TABLE events_y{:04}m{:02} PARTITION OF events FOR VALUES FROM ({}) TO ({})",
+            year, month, month_start(year, month), month_start(ny, nm)
+        ))
+        .execute(&mut *tx)
+        .await?;
+        (year, month) = (ny, nm);
+        created += 1;
+    }
+
+    let copied = sqlx::query(
+        "INSERT INTO events (id, event_type, process_id, parent_process_id, process_name, details, decoded_details, timestamp, task_id, session_id, digital_signature, severity, category, kill_chain_stage, decoder_version, obfuscation_score, occurrences, last_seen)
+         SELECT id, event_type, process_id, parent_process_id, process_name, details, decoded_details, timestamp, task_id, session_id, digital_signature, severity, category, kill_chain_stage, decoder_version, obfuscation_score, occurrences, last_seen
+         FROM events_legacy"
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    sqlx::query("DROP TABLE events_legacy").execute(&mut *tx).await?;
+    tx.commit().await?;
+    println!("[RETENTION] Events partitioned: {} rows copied into {} monthly partitions.", copied, created);
+    ensure_upcoming_partitions(pool).await;
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct ClassSummary {
+    pub class: &'static str,
+    pub keep_days: i64,
+    pub cutoff: i64,
+    pub tasks: i64,
+    pub events: i64,
+}
+
+/// What the current policy would delete, per class.
+pub async fn preview(pool: &Pool<Postgres>) -> Result<Vec<ClassSummary>, sqlx::Error> {
+    let now = Utc::now().timestamp_millis();
+    let mut out = Vec::new();
+    for (class, days) in policy() {
+        let cutoff = now - days * 86_400_000;
+        let (tasks, events): (i64, i64) = if class == "unassigned" {
+            let events = sqlx::query_scalar("SELECT COUNT(*) FROM events WHERE task_id IS NULL AND timestamp < $1")
+                .bind(cutoff)
+                .fetch_one(pool)
+                .await?;
+            (0, events)
+        } else {
+            sqlx::query_as(&format!(
+                "SELECT COUNT(DISTINCT t.id), COUNT(e.id) FROM tasks t JOIN events e ON e.task_id = t.id
+                 WHERE {} = $1 AND COALESCE(t.completed_at, t.created_at) < $2",
+                TASK_CLASS
+            ))
+            .bind(class)
+            .bind(cutoff)
+            .fetch_one(pool)
+            .await?
+        };
+        out.push(ClassSummary { class, keep_days: days, cutoff, tasks, events });
+    }
+    Ok(out)
+}
+
+/// Applies the policy, then drops past monthly partitions it left empty.
+pub async fn run(pool: &Pool<Postgres>) -> Result<Vec<ClassSummary>, sqlx::Error> {
+    let now = Utc::now().timestamp_millis();
+    let mut out = Vec::new();
+    for (class, days) in policy() {
+        let cutoff = now - days * 86_400_000;
+        let (tasks, events) = if class == "unassigned" {
+            let deleted = sqlx::query("DELETE FROM events WHERE task_id IS NULL AND timestamp < $1")
+                .bind(cutoff)
+                .execute(pool)
+                .await?
+                .rows_affected();
+            (0, deleted as i64)
+        } else {
+            let expired: Vec<String> = sqlx::query_scalar(&format!(
+                "SELECT t.id FROM tasks t WHERE {} = $1 AND COALESCE(t.completed_at, t.created_at) < $2
+                 AND EXISTS (SELECT 1 FROM events e WHERE e.task_id = t.id)",
+                TASK_CLASS
+            ))
+            .bind(class)
+            .bind(cutoff)
+            .fetch_all(pool)
+            .await?;
+            let deleted = sqlx::query("DELETE FROM events WHERE task_id = ANY($1)")
+                .bind(&expired)
+                .execute(pool)
+                .await?
+                .rows_affected();
+            // Lets the UI say the telemetry expired rather than that there never was any
+            let _ = sqlx::query("UPDATE tasks SET events_purged_at = $2 WHERE id = ANY($1)")
+                .bind(&expired)
+                .bind(now)
+                .execute(pool)
+                .await;
+            (expired.len() as i64, deleted as i64)
+        };
+        if events > 0 {
+            println!("[RETENTION] {}: removed {} events from {} tasks older than {} days.", class, events, tasks, days);
+        }
+        out.push(ClassSummary { class, keep_days: days, cutoff, tasks, events });
+    }
+    drop_empty_partitions(pool).await;
+    Ok(out)
+}
+
+async fn drop_empty_partitions(pool: &Pool<Postgres>) {
+    let now = Utc::now();
+    let current = format!("events_y{:04}m{:02}", now.year(), now.month());
+    let partitions: Vec<String> = sqlx::query_scalar(
+        "SELECT c.relname::text FROM pg_inherits i JOIN pg_class c ON c.oid = i.inhrelid
+         WHERE i.inhparent = to_regclass('events') AND c.relname ~ '^events_y[0-9]{4}m[0-9]{2}$'"
+    )
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+    // Names sort chronologically, so only strictly older months are candidates
+    for name in partitions.into_iter().filter(|p| *p < current) {
+        let empty: bool = sqlx::query_scalar(&format!("SELECT NOT EXISTS (SELECT 1 FROM {})", name))
+            .fetch_one(pool)
+            .await
+            .unwrap_or(false);
+        if empty {
+            match sqlx::query(&format!("DROP TABLE {}", name)).execute(pool).await {
+                Ok(_) => println!("[RETENTION] Dropped empty partition {}.", name),
+                Err(e) => println!("[RETENTION] Failed to drop partition {}: {}", name, e),
+            }
+        }
+    }
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query("ALTER TABLE tasks ADD COLUMN IF NOT EXISTS events_purged_at BIGINT").execute(pool).await?;
+    Ok(())
+}
+
+/// Daily partition upkeep and retention run.
+pub async fn start(pool: Pool<Postgres>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(24 * 3600));
+    loop {
+        interval.tick().await;
+        ensure_upcoming_partitions(&pool).await;
+        if policy().is_empty() {
+            continue;
+        }
+        if let Err(e) = run(&pool).await {
+            println!("[RETENTION] Retention run failed: {}", e);
+        }
+    }
+}
+
+#[utoipa::path(tag = "admin", responses((status = 200, description = "Success")))]
+#[get("/retention")]
+pub async fn get_retention(pool: web::Data<Pool<Postgres>>) -> impl Responder {
+    match preview(pool.get_ref()).await {
+        Ok(classes) => HttpResponse::Ok().json(serde_json::json!({
+            "partitioned": is_partitioned(pool.get_ref()).await,
+            "policy": classes,
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "admin", responses((status = 200, description = "Success")))]
+#[post("/retention/run")]
+pub async fn run_retention(pool: web::Data<Pool<Postgres>>) -> impl Responder {
+    match run(pool.get_ref()).await {
+        Ok(classes) => HttpResponse::Ok().json(serde_json::json!({ "status": "completed", "policy": classes })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
@@ -0,0 +1,141 @@
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use crate::RawAgentEvent;
+
+// --- EVENT ROLL-UP ---
+// Repeated poller events fold into one row (EVENT_DEDUP_WINDOW_SECS).
+
+const DEFAULT_WINDOW_SECS: i64 = 60;
+/// Above this many tracked events, those outside the window are forgotten.
+const PRUNE_ABOVE: usize = 20_000;
+
+#[derive(Hash, PartialEq, Eq)]
+struct Key {
+    session_id: String,
+    task_id: Option<String>,
+    process_id: i32,
+    event_type: String,
+    details: String,
+}
+
+impl Key {
+    fn of(session_id: &str, evt: &RawAgentEvent) -> Self {
+        Key {
+            session_id: session_id.to_string(),
+            task_id: evt.task_id.clone(),
+            process_id: evt.process_id,
+            event_type: evt.event_type.clone(),
+            details: evt.details.clone(),
+        }
+    }
+}
+
+struct Stored {
+    id: i32,
+    last_seen: i64,
+}
+
+/// A stored event seen again `count` more times, last at `last_seen`.
+pub struct Repeat {
+    pub id: i32,
+    pub count: i32,
+    pub last_seen: i64,
+}
+
+fn window_ms() -> i64 {
+    static WINDOW: OnceLock<i64> = OnceLock::new();
+    *WINDOW.get_or_init(|| {
+        let secs: i64 = std::env::var("EVENT_DEDUP_WINDOW_SECS").ok().and_then(|v| v.trim().parse().ok()).unwrap_or(DEFAULT_WINDOW_SECS);
+        secs.max(0) * 1000
+    })
+}
+
+fn stored() -> &'static Mutex<HashMap<Key, Stored>> {
+    static STORED: OnceLock<Mutex<HashMap<Key, Stored>>> = OnceLock::new();
+    STORED.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Whether repeats of this event may be rolled up.
+fn eligible(evt: &RawAgentEvent) -> bool {
+    let is_control = evt.event_type == "SESSION_INIT" || evt.event_type.starts_with("AGENT_");
+    let is_os_record = evt.details.contains("SYSMON:") || evt.details.starts_with("KERNEL:") || evt.event_type.starts_with("KERNEL_");
+    !is_control && !is_os_record
+}
+
+/// Folds repeats into their first copy; returns those of earlier batches for `record`.
+pub fn fold(session_id: &str, batch: &mut Vec<RawAgentEvent>) -> Vec<Repeat> {
+    let window = window_ms();
+    if window == 0 {
+        return Vec::new();
+    }
+    let Ok(mut stored) = stored().lock() else { return Vec::new() };
+
+    let mut kept: Vec<RawAgentEvent> = Vec::with_capacity(batch.len());
+    let mut in_batch: HashMap<Key, usize> = HashMap::new();
+    let mut repeats: HashMap<i32, Repeat> = HashMap::new();
+    for evt in batch.drain(..) {
+        if !eligible(&evt) {
+            kept.push(evt);
+            continue;
+        }
+        let key = Key::of(session_id, &evt);
+        if let Some(&i) = in_batch.get(&key) {
+            let first = &mut kept[i];
+            let last_seen = first.last_seen.unwrap_or(first.timestamp);
+            if evt.timestamp - last_seen <= window {
+                first.occurrences = Some(first.occurrences.unwrap_or(1) + 1);
+                first.last_seen = Some(last_seen.max(evt.timestamp));
+                continue;
+            }
+        } else if let Some(earlier) = stored.get_mut(&key) {
+            if evt.timestamp - earlier.last_seen <= window {
+                earlier.last_seen = earlier.last_seen.max(evt.timestamp);
+                let repeat = repeats.entry(earlier.id).or_insert(Repeat { id: earlier.id, count: 0, last_seen: earlier.last_seen });
+                repeat.count += 1;
+                repeat.last_seen = earlier.last_seen;
+                continue;
+            }
+        }
+        in_batch.insert(key, kept.len());
+        kept.push(evt);
+    }
+    *batch = kept;
+    repeats.into_values().collect()
+}
+
+/// Tracks the batch's newly stored events so later repeats fold into them.
+pub fn remember(session_id: &str, batch: &[RawAgentEvent]) {
+    if window_ms() == 0 {
+        return;
+    }
+    let Ok(mut stored) = stored().lock() else { return };
+    for evt in batch.iter().filter(|e| eligible(e)) {
+        if let Some(id) = evt.id {
+            stored.insert(Key::of(session_id, evt), Stored { id, last_seen: evt.last_seen.unwrap_or(evt.timestamp) });
+        }
+    }
+    if stored.len() > PRUNE_ABOVE {
+        let newest = stored.values().map(|s| s.last_seen).max().unwrap_or(0);
+        stored.retain(|_, s| newest - s.last_seen <= window_ms());
+    }
+}
+
+/// Counts repeats against the rows they fold into.
+pub async fn record(pool: &Pool<Postgres>, repeats: &[Repeat]) -> Result<(), sqlx::Error> {
+    if repeats.is_empty() {
+        return Ok(());
+    }
+    sqlx::query(
+        "UPDATE events SET occurrences = events.occurrences + u.count,
+             last_seen = GREATEST(COALESCE(events.last_seen, events.timestamp), u.last_seen)
+         FROM UNNEST($1::int[], $2::int[], $3::bigint[]) AS u(id, count, last_seen)
+         WHERE events.id = u.id"
+    )
+    .bind(repeats.iter().map(|r| r.id).collect::<Vec<_>>())
+    .bind(repeats.iter().map(|r| r.count).collect::<Vec<_>>())
+    .bind(repeats.iter().map(|r| r.last_seen).collect::<Vec<_>>())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
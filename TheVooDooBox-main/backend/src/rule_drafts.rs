@@ -0,0 +1,433 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use std::collections::{HashMap, HashSet};
+use crate::ai::manager::AIManager;
+use crate::ai::provider::ChatMessage;
+use crate::ai_usage::UsageTag;
+use crate::yara::{Check, YaraRule};
+
+// --- AI DETECTION RULE DRAFTS ---
+// AI-written YARA and Sigma drafts, validated before they are stored.
+
+const MAX_ATTEMPTS: usize = 2;
+const MAX_STRINGS: usize = 150;
+const MAX_FUNCTIONS: usize = 5;
+const MAX_FUNCTION_CHARS: usize = 1500;
+const MAX_SIGMA_RULES: usize = 5;
+/// Events shown to the AI, and how much of each.
+const MAX_PROMPT_EVENTS: usize = 120;
+const MAX_EVENT_CHARS: usize = 400;
+/// Events the drafts are tested against.
+const MAX_TEST_EVENTS: i64 = 50000;
+
+/// Process lineage, command lines and registry writes.
+const HIGH_SIGNAL: &[&str] = &["PROCESS_CREATE", "REG_SET_VALUE", "REG_KEY_CREATE", "REGISTRY_SET"];
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct SigmaDraft {
+    pub id: i32,
+    pub task_id: String,
+    pub rule_id: String,
+    pub title: String,
+    pub level: String,
+    pub yaml: String,
+    /// Events of the task the rule matched when it was drafted.
+    pub matched_events: i32,
+    pub created_at: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct Event {
+    id: i32,
+    event_type: String,
+    process_id: i32,
+    parent_process_id: i32,
+    process_name: String,
+    details: String,
+    decoded_details: Option<String>,
+    category: Option<String>,
+    timestamp: i64,
+    digital_signature: Option<String>,
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS sigma_drafts (
+            id SERIAL PRIMARY KEY,
+            task_id TEXT NOT NULL,
+            rule_id TEXT NOT NULL,
+            title TEXT NOT NULL,
+            level TEXT NOT NULL,
+            yaml TEXT NOT NULL,
+            matched_events INTEGER NOT NULL,
+            created_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_sigma_drafts_task ON sigma_drafts (task_id)")
+        .execute(pool)
+        .await?;
+    println!("[SIGMA] Database initialized (sigma_drafts).");
+    Ok(())
+}
+
+/// What the AI gets to write a rule from; None when there is nothing.
+async fn material(pool: &Pool<Postgres>, task_id: &str) -> Option<String> {
+    let mut sections = Vec::new();
+
+    let props = crate::static_properties::properties(pool, task_id).await;
+    if let Some(strings) = crate::static_properties::find(&props, "strings") {
+        let lines = crate::strings::lines(strings);
+        if !lines.is_empty() {
+            sections.push(format!("EXTRACTED STRINGS (category: value):\n{}", lines.into_iter().take(MAX_STRINGS).collect::<Vec<_>>().join("\n")));
+        }
+    }
+    if let Some(pe) = crate::static_properties::find(&props, "pe") {
+        let dlls: Vec<&str> = pe["imported_dlls"].as_array().into_iter().flatten().filter_map(|d| d.as_str()).collect();
+        if !dlls.is_empty() {
+            sections.push(format!("IMPORTED DLLS: {}", dlls.join(", ")));
+        }
+    }
+
+    let mut functions = crate::ai_analysis::fetch_ghidra_analysis(&task_id.to_string(), pool).await.functions;
+    functions.sort_by_key(|f| f.suspicious_tag == "Analyzed");
+    let excerpts: Vec<String> = functions.iter().take(MAX_FUNCTIONS).map(|f| {
+        format!("Function {} ({}):\n{}", f.name, f.suspicious_tag, f.pseudocode.chars().take(MAX_FUNCTION_CHARS).collect::<String>())
+    }).collect();
+    if !excerpts.is_empty() {
+        sections.push(format!("GHIDRA EXCERPTS:\n{}", excerpts.join("\n\n")));
+    }
+
+    if let Some(report) = crate::ai_analysis::stored_report(pool, task_id).await {
+        let a = &report.artifacts;
+        let artifacts: Vec<String> = [
+            ("C2 domains", &a.c2_domains),
+            ("C2 IPs", &a.c2_ips),
+            ("Dropped files", &a.dropped_files),
+            ("Mutexes", &a.mutual_exclusions),
+            ("Command lines", &a.command_lines),
+        ].into_iter()
+            .filter(|(_, values)| !values.is_empty())
+            .map(|(label, values)| format!("{}: {}", label, values.join(", ")))
+            .collect();
+        if !artifacts.is_empty() {
+            sections.push(format!("REPORT ARTIFACTS (seen at runtime, not necessarily in the file):\n{}", artifacts.join("\n")));
+        }
+    }
+
+    (!sections.is_empty()).then(|| sections.join("\n\n"))
+}
+
+/// The first fenced block of the answer, without its language tag.
+fn fenced(answer: &str) -> Option<&str> {
+    let (_, rest) = answer.split_once("```")?;
+    let body = rest.split_once('\n').map_or(rest, |(_, body)| body);
+    Some(body.split("```").next().unwrap_or_default().trim())
+}
+
+/// The rule out of the answer: the first fenced block if there is one, else from `rule` on.
+fn extract_rule(answer: &str) -> String {
+    if let Some(rule) = fenced(answer) {
+        return rule.to_string();
+    }
+    let start = answer.find("import \"").or_else(|| answer.find("rule ")).unwrap_or(0);
+    answer[start..].trim().to_string()
+}
+
+#[utoipa::path(tag = "tasks", responses(
+    (status = 200, description = "Draft stored, disabled, in the YARA rule store"),
+    (status = 404, description = "Task not found"),
+    (status = 422, description = "Nothing to write a rule from, or the AI's rule does not compile"),
+    (status = 502, description = "The AI request failed"),
+    (status = 503, description = "YARA is not available to compile the rule"),
+))]
+#[post("/tasks/{id}/generate-yara")]
+pub async fn generate_yara(
+    pool: web::Data<Pool<Postgres>>,
+    ai_manager: web::Data<AIManager>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let task_id = path.into_inner();
+    let original = match sqlx::query_scalar::<_, String>("SELECT original_filename FROM tasks WHERE id = $1").bind(&task_id).fetch_optional(pool.get_ref()).await {
+        Ok(Some(original)) => original,
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    let Some(material) = material(pool.get_ref(), &task_id).await else {
+        return HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": "Nothing to write a rule from: the task has no extracted strings, Ghidra functions or report artifacts"
+        }));
+    };
+
+    let ident: String = format!("ai_task_{}", task_id).chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    let system_prompt = format!(
+        "You are a malware analyst writing YARA rules. Write ONE rule named {} that detects this sample and its close variants. \
+         Use strings that occur in the file itself (the extracted strings and constants from the decompiled code); \
+         runtime artifacts only when they also appear among the strings. Prefer several distinctive strings combined in the condition \
+         over a single one, and avoid strings common to benign software. Put a description and `reference = \"task {}\"` in the meta section. \
+         You may import \"pe\" or \"math\"; nothing else. Answer with the rule only, in a ```yara block.",
+        ident, task_id
+    );
+    let usage = UsageTag::new(Some(&task_id), "yara_draft");
+    let mut history = vec![ChatMessage { role: "user".to_string(), content: format!("Sample: {}\n\n{}", original, material) }];
+
+    let mut source = String::new();
+    for attempt in 1..=MAX_ATTEMPTS {
+        let answer = match ai_manager.ask(history.clone(), system_prompt.clone(), &usage).await {
+            Ok(answer) => answer,
+            Err(e) => return HttpResponse::BadGateway().json(serde_json::json!({ "error": format!("AI request failed: {}", e) })),
+        };
+        source = extract_rule(&answer);
+        let error = match crate::yara::check(&source).await {
+            Check::Valid => break,
+            Check::Invalid(e) => e,
+            Check::Unavailable(e) => {
+                return HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": format!("Cannot compile rules: {}", e) }));
+            }
+        };
+        println!("[YARA] Task {}: AI draft {} does not compile: {}", task_id, attempt, error);
+        if attempt == MAX_ATTEMPTS {
+            return HttpResponse::UnprocessableEntity().json(serde_json::json!({
+                "error": format!("The AI's rule does not compile: {}", error),
+                "source": source
+            }));
+        }
+        history.push(ChatMessage { role: "assistant".to_string(), content: answer });
+        history.push(ChatMessage { role: "user".to_string(), content: format!("That rule does not compile: {}\nReturn the corrected rule only.", error) });
+    }
+
+    // None: there is no sample on disk to try it on
+    let matches_sample = match crate::yara::sample_target(pool.get_ref(), &task_id).await {
+        Some(sample) => match crate::yara::test(&source, &sample.path).await {
+            Ok(rules) => Some(!rules.is_empty()),
+            Err(e) => {
+                println!("[YARA] Task {}: test of the AI draft failed: {}", task_id, e);
+                None
+            }
+        },
+        None => None,
+    };
+    let description = format!("AI draft for task {} ({}); {}. Review before enabling.", task_id, original, match matches_sample {
+        Some(true) => "matches the sample",
+        Some(false) => "does NOT match the sample",
+        None => "not tested against the sample",
+    });
+
+    let stored = sqlx::query_as::<_, YaraRule>(
+        "INSERT INTO yara_rules (name, description, source, enabled, created_at)
+         VALUES ($1, $2, $3, FALSE, $4)
+         ON CONFLICT (name) DO UPDATE SET description = EXCLUDED.description, source = EXCLUDED.source, enabled = FALSE
+         RETURNING *"
+    )
+    .bind(format!("ai-draft-{}", ident.trim_start_matches("ai_task_")))
+    .bind(&description)
+    .bind(&source)
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_one(pool.get_ref())
+    .await;
+    match stored {
+        Ok(rule) => {
+            println!("[YARA] Task {}: stored AI draft '{}' ({})", task_id, rule.name, description);
+            HttpResponse::Ok().json(serde_json::json!({ "rule": rule, "matches_sample": matches_sample }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// The task's high-signal events, one line each with the parent process, for the prompt.
+fn describe_events(events: &[Event]) -> Vec<String> {
+    let mut names: HashMap<i32, &str> = HashMap::new();
+    for evt in events {
+        names.entry(evt.process_id).or_insert(&evt.process_name);
+    }
+    let mut seen = HashSet::new();
+    events.iter()
+        .filter(|evt| HIGH_SIGNAL.contains(&evt.event_type.as_str()))
+        .filter(|evt| !crate::noise_filters::is_noise(None, &evt.event_type, &evt.process_name, evt.digital_signature.as_deref()))
+        .filter(|evt| seen.insert((&evt.event_type, &evt.process_name, &evt.details)))
+        .take(MAX_PROMPT_EVENTS)
+        .map(|evt| {
+            let details: String = evt.details.chars().take(MAX_EVENT_CHARS).collect();
+            format!("{} {} (pid {}, parent {} pid {}): {}", evt.event_type, evt.process_name, evt.process_id,
+                names.get(&evt.parent_process_id).unwrap_or(&"?"), evt.parent_process_id, details)
+        })
+        .collect()
+}
+
+/// Compiles and runs one draft over the task's events; Err says what is wrong with it.
+fn test_draft(yaml: &str, events: &[crate::sigma::EventRef]) -> Result<crate::sigma::Tested, String> {
+    let tested = crate::sigma::test(yaml, events)?;
+    if tested.matched == 0 {
+        return Err("it matches none of the task's events".to_string());
+    }
+    Ok(tested)
+}
+
+#[utoipa::path(tag = "tasks", responses(
+    (status = 200, description = "Drafts stored for the task, replacing earlier ones"),
+    (status = 404, description = "Task not found"),
+    (status = 422, description = "No process or registry events, or no draft passed the Sigma engine"),
+    (status = 502, description = "The AI request failed"),
+))]
+#[post("/tasks/{id}/generate-sigma")]
+pub async fn generate_sigma(
+    pool: web::Data<Pool<Postgres>>,
+    ai_manager: web::Data<AIManager>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let task_id = path.into_inner();
+    match sqlx::query_scalar::<_, String>("SELECT id FROM tasks WHERE id = $1").bind(&task_id).fetch_optional(pool.get_ref()).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+    let events = match sqlx::query_as::<_, Event>(
+        "SELECT id, event_type, process_id, parent_process_id, process_name, details, decoded_details, category, timestamp, digital_signature
+         FROM events WHERE task_id = $1 ORDER BY timestamp, id LIMIT $2"
+    )
+    .bind(&task_id)
+    .bind(MAX_TEST_EVENTS)
+    .fetch_all(pool.get_ref())
+    .await
+    {
+        Ok(events) => events,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    let lines = describe_events(&events);
+    if lines.is_empty() {
+        return HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": "Nothing to write rules from: the task has no process creation or registry events"
+        }));
+    }
+    let refs: Vec<crate::sigma::EventRef> = events.iter().map(|evt| crate::sigma::EventRef {
+        id: Some(evt.id),
+        task_id: Some(&task_id),
+        event_type: &evt.event_type,
+        process_id: evt.process_id,
+        parent_process_id: evt.parent_process_id,
+        process_name: &evt.process_name,
+        details: &evt.details,
+        decoded_details: evt.decoded_details.as_deref(),
+        category: evt.category.as_deref(),
+        timestamp: evt.timestamp,
+    }).collect();
+
+    let system_prompt = format!(
+        "You are a detection engineer writing Sigma rules from sandbox telemetry. Write at most {} rules that catch the malicious \
+         behaviour in these events (process chains, command lines, registry persistence) without firing on normal Windows activity. \
+         Each rule needs title, status: experimental, description, `references: [\"task {}\"]`, ATT&CK tags where they apply, logsource, \
+         detection and level; leave out id. The engine they must run on supports: {} \
+         Answer with the rules only, as YAML documents separated by --- in one ```yaml block.",
+        MAX_SIGMA_RULES, task_id, crate::sigma::capabilities()
+    );
+    let usage = UsageTag::new(Some(&task_id), "sigma_draft");
+    let mut history = vec![ChatMessage { role: "user".to_string(), content: format!("EVENTS:\n{}", lines.join("\n")) }];
+
+    let mut passed: Vec<(String, crate::sigma::Tested)> = Vec::new();
+    let mut rejected: Vec<String> = Vec::new();
+    for attempt in 1..=MAX_ATTEMPTS {
+        let answer = match ai_manager.ask(history.clone(), system_prompt.clone(), &usage).await {
+            Ok(answer) => answer,
+            Err(e) => return HttpResponse::BadGateway().json(serde_json::json!({ "error": format!("AI request failed: {}", e) })),
+        };
+        let body = fenced(&answer).unwrap_or(answer.trim()).to_string();
+        rejected.clear();
+        for yaml in body.split("\n---").map(|doc| doc.trim().trim_start_matches("---").trim()).filter(|doc| !doc.is_empty()) {
+            if passed.len() >= MAX_SIGMA_RULES {
+                break;
+            }
+            match test_draft(yaml, &refs) {
+                Ok(tested) => passed.push((yaml.to_string(), tested)),
+                Err(e) => rejected.push(format!("{}: {}", yaml.lines().next().unwrap_or_default(), e)),
+            }
+        }
+        if rejected.is_empty() || attempt == MAX_ATTEMPTS || passed.len() >= MAX_SIGMA_RULES {
+            break;
+        }
+        println!("[SIGMA] Task {}: {} AI draft(s) rejected on attempt {}", task_id, rejected.len(), attempt);
+        history.push(ChatMessage { role: "assistant".to_string(), content: answer });
+        history.push(ChatMessage {
+            role: "user".to_string(),
+            content: format!("These rules were rejected:\n{}\nReturn corrected versions of those rules only.", rejected.join("\n")),
+        });
+    }
+    if passed.is_empty() {
+        return HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": "No draft compiled and matched the task's events",
+            "rejected": rejected
+        }));
+    }
+
+    if let Err(e) = sqlx::query("DELETE FROM sigma_drafts WHERE task_id = $1").bind(&task_id).execute(pool.get_ref()).await {
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() }));
+    }
+    let now = chrono::Utc::now().timestamp();
+    let mut drafts = Vec::with_capacity(passed.len());
+    for (yaml, tested) in passed {
+        // The SOC needs an id to track the rule by
+        let (rule_id, yaml) = if tested.rule_id.is_empty() {
+            let id = uuid::Uuid::new_v4().to_string();
+            let yaml = format!("{}\nid: {}", yaml, id);
+            (id, yaml)
+        } else {
+            (tested.rule_id, yaml)
+        };
+        let stored = sqlx::query_as::<_, SigmaDraft>(
+            "INSERT INTO sigma_drafts (task_id, rule_id, title, level, yaml, matched_events, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *"
+        )
+        .bind(&task_id)
+        .bind(&rule_id)
+        .bind(&tested.title)
+        .bind(&tested.level)
+        .bind(&yaml)
+        .bind(tested.matched as i32)
+        .bind(now)
+        .fetch_one(pool.get_ref())
+        .await;
+        match stored {
+            Ok(draft) => drafts.push(draft),
+            Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+        }
+    }
+    println!("[SIGMA] Task {}: stored {} AI draft rule(s), {} rejected", task_id, drafts.len(), rejected.len());
+    HttpResponse::Ok().json(serde_json::json!({ "drafts": drafts, "rejected": rejected }))
+}
+
+async fn fetch_drafts(pool: &Pool<Postgres>, task_id: &str) -> Result<Vec<SigmaDraft>, sqlx::Error> {
+    sqlx::query_as::<_, SigmaDraft>("SELECT * FROM sigma_drafts WHERE task_id = $1 ORDER BY id")
+        .bind(task_id)
+        .fetch_all(pool)
+        .await
+}
+
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
+#[get("/tasks/{id}/sigma-drafts")]
+pub async fn list_sigma_drafts(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    match fetch_drafts(pool.get_ref(), &path.into_inner()).await {
+        Ok(drafts) => HttpResponse::Ok().json(drafts),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// The task's draft rules as one multi-document YAML file.
+#[utoipa::path(tag = "tasks", responses(
+    (status = 200, description = "Sigma rules as YAML"),
+    (status = 404, description = "The task has no drafts"),
+))]
+#[get("/tasks/{id}/export/sigma")]
+pub async fn export_sigma(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    let task_id = path.into_inner();
+    match fetch_drafts(pool.get_ref(), &task_id).await {
+        Ok(drafts) if drafts.is_empty() => {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": "No Sigma drafts for this task; generate them first" }))
+        }
+        Ok(drafts) => HttpResponse::Ok()
+            .content_type("application/yaml")
+            .insert_header(("Content-Disposition", format!("attachment; filename=\"{}.sigma.yml\"", task_id)))
+            .body(drafts.iter().map(|d| d.yaml.trim()).collect::<Vec<_>>().join("\n---\n") + "\n"),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
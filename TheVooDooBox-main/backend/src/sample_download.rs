@@ -0,0 +1,319 @@
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+
+// --- SAMPLE DOWNLOAD ---
+// Samples leave the lab inside an "infected"-password zip.
+
+const CHUNK_SIZE: usize = 256 * 1024;
+/// How long a guest link stays valid if the agent never fetches it.
+const GUEST_TOKEN_TTL: Duration = Duration::from_secs(30 * 60);
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+static CRC_TABLE: [u32; 256] = crc_table();
+
+fn crc32_byte(crc: u32, b: u8) -> u32 {
+    (crc >> 8) ^ CRC_TABLE[((crc ^ b as u32) & 0xff) as usize]
+}
+
+/// Traditional PKWARE encryption state.
+struct ZipCrypto {
+    keys: [u32; 3],
+}
+
+impl ZipCrypto {
+    fn new(password: &[u8]) -> Self {
+        let mut z = ZipCrypto { keys: [0x1234_5678, 0x2345_6789, 0x3456_7890] };
+        for &b in password {
+            z.update(b);
+        }
+        z
+    }
+
+    fn update(&mut self, b: u8) {
+        self.keys[0] = crc32_byte(self.keys[0], b);
+        self.keys[1] = self.keys[1].wrapping_add(self.keys[0] & 0xff).wrapping_mul(134_775_813).wrapping_add(1);
+        self.keys[2] = crc32_byte(self.keys[2], (self.keys[1] >> 24) as u8);
+    }
+
+    fn encrypt(&mut self, data: &mut [u8]) {
+        for b in data.iter_mut() {
+            let temp = (self.keys[2] | 2) as u16;
+            let key = (temp.wrapping_mul(temp ^ 1) >> 8) as u8;
+            let plain = *b;
+            *b ^= key;
+            self.update(plain);
+        }
+    }
+}
+
+fn dos_datetime() -> (u16, u16) {
+    use chrono::{Datelike, Timelike};
+    let now = chrono::Local::now();
+    let time = ((now.hour() << 11) | (now.minute() << 5) | (now.second() / 2)) as u16;
+    let date = (((now.year().max(1980) - 1980) as u32) << 9 | (now.month() << 5) | now.day()) as u16;
+    (time, date)
+}
+
+/// Fields shared by the local header and the central directory entry.
+struct Member {
+    name: Vec<u8>,
+    time: u16,
+    date: u16,
+}
+
+/// Encrypted, deflated, UTF-8 name, sizes in a trailing data descriptor.
+const FLAGS: u16 = 0x0001 | 0x0008 | 0x0800;
+
+impl Member {
+    fn local_header(&self) -> Vec<u8> {
+        let mut h = Vec::with_capacity(30 + self.name.len());
+        h.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        h.extend_from_slice(&20u16.to_le_bytes());
+        h.extend_from_slice(&FLAGS.to_le_bytes());
+        h.extend_from_slice(&8u16.to_le_bytes());
+        h.extend_from_slice(&self.time.to_le_bytes());
+        h.extend_from_slice(&self.date.to_le_bytes());
+        h.extend_from_slice(&[0u8; 12]); // crc and sizes follow in the descriptor
+        h.extend_from_slice(&(self.name.len() as u16).to_le_bytes());
+        h.extend_from_slice(&0u16.to_le_bytes());
+        h.extend_from_slice(&self.name);
+        h
+    }
+
+    /// Data descriptor, central directory and end record for a single member at offset 0.
+    fn trailer(&self, crc: u32, compressed: u32, size: u32, data_end: u32) -> Vec<u8> {
+        let mut t = Vec::new();
+        t.extend_from_slice(&0x0807_4b50u32.to_le_bytes());
+        t.extend_from_slice(&crc.to_le_bytes());
+        t.extend_from_slice(&compressed.to_le_bytes());
+        t.extend_from_slice(&size.to_le_bytes());
+
+        let cd_offset = data_end + 16;
+        let mut cd = Vec::new();
+        cd.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        cd.extend_from_slice(&20u16.to_le_bytes());
+        cd.extend_from_slice(&20u16.to_le_bytes());
+        cd.extend_from_slice(&FLAGS.to_le_bytes());
+        cd.extend_from_slice(&8u16.to_le_bytes());
+        cd.extend_from_slice(&self.time.to_le_bytes());
+        cd.extend_from_slice(&self.date.to_le_bytes());
+        cd.extend_from_slice(&crc.to_le_bytes());
+        cd.extend_from_slice(&compressed.to_le_bytes());
+        cd.extend_from_slice(&size.to_le_bytes());
+        cd.extend_from_slice(&(self.name.len() as u16).to_le_bytes());
+        cd.extend_from_slice(&[0u8; 12]); // extra, comment, disk, attributes
+        cd.extend_from_slice(&0u32.to_le_bytes()); // local header offset
+        cd.extend_from_slice(&self.name);
+
+        t.extend_from_slice(&cd);
+        t.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        t.extend_from_slice(&[0u8; 4]); // disk numbers
+        t.extend_from_slice(&1u16.to_le_bytes());
+        t.extend_from_slice(&1u16.to_le_bytes());
+        t.extend_from_slice(&(cd.len() as u32).to_le_bytes());
+        t.extend_from_slice(&cd_offset.to_le_bytes());
+        t.extend_from_slice(&0u16.to_le_bytes());
+        t
+    }
+}
+
+type Chunk = Result<web::Bytes, std::io::Error>;
+
+async fn send(tx: &tokio::sync::mpsc::Sender<Chunk>, data: Vec<u8>) -> Result<(), std::io::Error> {
+    tx.send(Ok(web::Bytes::from(data))).await.map_err(|_| std::io::Error::other("client went away"))
+}
+
+/// Streams `path` as a one-member encrypted zip into `tx`.
+async fn stream_zip(path: std::path::PathBuf, member_name: String, password: String, tx: tokio::sync::mpsc::Sender<Chunk>) -> Result<(), std::io::Error> {
+    let mut file = tokio::fs::File::open(&path).await?;
+    let (time, date) = dos_datetime();
+    let member = Member { name: member_name.into_bytes(), time, date };
+    let mut crypto = ZipCrypto::new(password.as_bytes());
+
+    let header = member.local_header();
+    let mut written = header.len() as u64;
+    send(&tx, header).await?;
+
+    // With a data descriptor the check byte is the high byte of the modification time
+    let mut preamble = uuid::Uuid::new_v4().as_bytes()[..12].to_vec();
+    preamble[11] = (time >> 8) as u8;
+    crypto.encrypt(&mut preamble);
+    let mut compressed = preamble.len() as u64;
+    send(&tx, preamble).await?;
+
+    let mut crc = flate2::Crc::new();
+    let mut size = 0u64;
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        crc.update(&buf[..n]);
+        size += n as u64;
+        encoder.write_all(&buf[..n])?;
+        let mut out = std::mem::take(encoder.get_mut());
+        if !out.is_empty() {
+            crypto.encrypt(&mut out);
+            compressed += out.len() as u64;
+            send(&tx, out).await?;
+        }
+    }
+    let mut out = encoder.finish()?;
+    crypto.encrypt(&mut out);
+    compressed += out.len() as u64;
+    send(&tx, out).await?;
+
+    written += compressed;
+    if size > u32::MAX as u64 || written > u32::MAX as u64 {
+        return Err(std::io::Error::other("sample exceeds the 4 GiB zip limit"));
+    }
+    send(&tx, member.trailer(crc.sum(), compressed as u32, size as u32, written as u32)).await?;
+    Ok(())
+}
+
+/// Keeps a hostile original name from becoming a path inside the archive.
+pub fn member_name(original: &str, fallback: &str) -> String {
+    let base = original.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+    let clean: String = base.chars().filter(|c| !c.is_control()).collect();
+    if clean.is_empty() || clean == "." || clean == ".." { fallback.to_string() } else { clean }
+}
+
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
+#[get("/tasks/{id}/sample")]
+pub async fn download_sample(
+    req: HttpRequest,
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let task_id = path.into_inner();
+    let task: Option<(String, String, String)> = match sqlx::query_as(
+        "SELECT filename, original_filename, file_hash FROM tasks WHERE id = $1"
+    )
+    .bind(&task_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    {
+        Ok(t) => t,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    let Some((filename, original_filename, file_hash)) = task else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" }));
+    };
+    // URL tasks have no file; neither do tasks whose sample was deleted
+    let key = format!("uploads/{}", filename);
+    if filename.is_empty() || filename.contains("..") || !crate::storage::ensure_local(&key).await {
+        crate::audit::log_access(pool.get_ref(), &req, &task_id, serde_json::json!({ "sha256": file_hash }), 404).await;
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "No sample file stored for this task" }));
+    }
+
+    let stem = if file_hash.is_empty() { task_id.clone() } else { file_hash.clone() };
+    let inner = member_name(&original_filename, &stem);
+    crate::audit::log_access(pool.get_ref(), &req, &task_id, serde_json::json!({ "sha256": file_hash, "file": inner }), 200).await;
+    println!("[SAMPLE] Task {} sample ({}) downloaded as encrypted zip.", task_id, inner);
+
+    encrypted_zip(crate::storage::local_path(&key), inner, &stem, format!("task {}", task_id))
+}
+
+/// Streams `local` inside a password zip named `stem`; `label` names it in the log.
+pub fn encrypted_zip(local: std::path::PathBuf, inner: String, stem: &str, label: String) -> HttpResponse {
+    let (tx, rx) = tokio::sync::mpsc::channel::<Chunk>(8);
+    tokio::spawn(async move {
+        if let Err(e) = stream_zip(local, inner, crate::archive::DEFAULT_PASSWORD.to_string(), tx.clone()).await {
+            println!("[SAMPLE] Download of {} aborted: {}", label, e);
+            let _ = tx.send(Err(e)).await;
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}.zip\"", stem)))
+        .insert_header(("X-Archive-Password", crate::archive::DEFAULT_PASSWORD))
+        .streaming(tokio_stream::wrappers::ReceiverStream::new(rx))
+}
+
+struct GuestToken {
+    task_id: String,
+    filename: String,
+    /// Served from VSIX_ARCHIVE_DIR rather than sample storage.
+    vsix: bool,
+    expires: Instant,
+}
+
+fn guest_tokens() -> &'static Mutex<HashMap<String, GuestToken>> {
+    static TOKENS: OnceLock<Mutex<HashMap<String, GuestToken>>> = OnceLock::new();
+    TOKENS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Swaps the /uploads/ or /vsix_archive/ path of `target_url` for a one-time token link.
+pub fn guest_url(task_id: &str, target_url: &str) -> String {
+    let found = match target_url.split_once("/uploads/") {
+        Some((base, filename)) => Some((base, filename, false)),
+        None => target_url.split_once("/vsix_archive/").map(|(base, filename)| (base, filename, true)),
+    };
+    let Some((base, filename, vsix)) = found else { return target_url.to_string() };
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    if let Ok(mut tokens) = guest_tokens().lock() {
+        let now = Instant::now();
+        tokens.retain(|_, t| t.expires > now);
+        tokens.insert(token.clone(), GuestToken {
+            task_id: task_id.to_string(),
+            filename: filename.to_string(),
+            vsix,
+            expires: now + GUEST_TOKEN_TTL,
+        });
+    }
+    format!("{}/guest/samples/{}", base, token)
+}
+
+#[utoipa::path(tag = "telemetry", responses(
+    (status = 200, description = "The raw sample"),
+    (status = 404, description = "Unknown, expired or already used link"),
+))]
+#[get("/guest/samples/{token}")]
+pub async fn guest_sample(req: HttpRequest, path: web::Path<String>) -> impl Responder {
+    let token = path.into_inner();
+    let taken = guest_tokens().lock().ok().and_then(|mut tokens| tokens.remove(&token));
+    let Some(GuestToken { task_id, filename, vsix, .. }) = taken.filter(|t| t.expires > Instant::now()) else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Unknown, expired or already used download link" }));
+    };
+    let key = format!("uploads/{}", filename);
+    if filename.is_empty() || filename.contains("..") || filename.contains(['/', '\\']) || (!vsix && !crate::storage::ensure_local(&key).await) {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "No sample file stored for this task" }));
+    }
+    let local = if vsix {
+        std::path::Path::new(&std::env::var("VSIX_ARCHIVE_DIR").unwrap_or_else(|_| "/vsix_archive".to_string())).join(&filename)
+    } else {
+        crate::storage::local_path(&key)
+    };
+    match actix_files::NamedFile::open_async(local).await {
+        Ok(file) => {
+            println!("[SAMPLE] Task {} sample fetched by the guest.", task_id);
+            file.into_response(&req)
+        }
+        Err(e) => HttpResponse::NotFound().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
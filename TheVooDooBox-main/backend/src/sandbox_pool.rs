@@ -0,0 +1,307 @@
+use actix_web::{get, post, delete, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use crate::hypervisor::Hypervisor;
+
+// --- SANDBOX POOL ---
+
+/// SANDBOX_SNAPSHOT (default "clean_sand"), for VMs registered without a snapshot.
+pub fn default_snapshot() -> String {
+    std::env::var("SANDBOX_SNAPSHOT")
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "clean_sand".to_string())
+}
+
+/// The snapshot a VM should be reverted to: its pool entry if registered, else the global default.
+pub async fn snapshot_for(pool: &Pool<Postgres>, vmid: u64) -> String {
+    match get(pool, vmid).await {
+        Ok(Some(vm)) => vm.snapshot,
+        _ => default_snapshot(),
+    }
+}
+
+/// Err only when the snapshot is reported missing, not when listing fails.
+pub async fn ensure_snapshot(client: &dyn Hypervisor, node: &str, vmid: u64, snapshot: &str) -> Result<(), String> {
+    match client.list_snapshots(node, vmid).await {
+        Ok(snaps) if snaps.iter().any(|s| s.name == snapshot) => Ok(()),
+        Ok(snaps) => {
+            let available: Vec<&str> = snaps.iter().map(|s| s.name.as_str()).collect();
+            Err(format!(
+                "Snapshot '{}' does not exist on VM {} (node {}). Available: {}",
+                snapshot, vmid, node,
+                if available.is_empty() { "none".to_string() } else { available.join(", ") }
+            ))
+        }
+        Err(e) => {
+            println!("[POOL] Could not list snapshots for VM {}: {}", vmid, e);
+            Ok(())
+        }
+    }
+}
+
+const STATES: &[&str] = &["free", "busy", "broken"];
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct PoolVm {
+    pub vmid: i64,
+    pub node: String,
+    pub os_profile: String,
+    pub snapshot: String,
+    pub arch: String,
+    /// free | busy | broken
+    pub state: String,
+    pub current_task_id: Option<String>,
+    pub last_error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Deserialize)]
+pub struct RegisterPoolVmRequest {
+    pub vmid: u64,
+    pub node: String,
+    pub os_profile: Option<String>,
+    pub snapshot: Option<String>,
+    pub arch: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdatePoolVmRequest {
+    pub node: Option<String>,
+    pub os_profile: Option<String>,
+    pub snapshot: Option<String>,
+    pub arch: Option<String>,
+    /// Setting a busy VM back to free releases it without waiting for its task.
+    pub state: Option<String>,
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS sandbox_pool (
+            vmid BIGINT PRIMARY KEY,
+            node TEXT NOT NULL,
+            os_profile TEXT NOT NULL DEFAULT 'windows10',
+            snapshot TEXT NOT NULL,
+            arch TEXT NOT NULL DEFAULT 'x64',
+            state TEXT NOT NULL DEFAULT 'free',
+            current_task_id TEXT,
+            last_error TEXT,
+            created_at BIGINT NOT NULL,
+            updated_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sandbox_pool")
+        .fetch_one(pool)
+        .await?;
+    if count == 0 {
+        println!("[POOL] WARNING: No sandbox VMs registered. Add some via POST /sandbox-pool or only manually pinned submissions will run.");
+    }
+
+    println!("[POOL] Database initialized (sandbox_pool).");
+    Ok(())
+}
+
+const SELECT_COLUMNS: &str = "vmid, node, os_profile, snapshot, arch, state, current_task_id, last_error, created_at, updated_at";
+
+pub async fn get(pool: &Pool<Postgres>, vmid: u64) -> Result<Option<PoolVm>, sqlx::Error> {
+    sqlx::query_as::<_, PoolVm>(&format!("SELECT {} FROM sandbox_pool WHERE vmid = $1", SELECT_COLUMNS))
+        .bind(vmid as i64)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Marks a specific registered VM busy for `task_id`; None if it is not free.
+pub async fn claim(pool: &Pool<Postgres>, vmid: u64, task_id: &str) -> Result<Option<PoolVm>, sqlx::Error> {
+    sqlx::query_as::<_, PoolVm>(&format!(
+        "UPDATE sandbox_pool SET state='busy', current_task_id=$2, updated_at=$3
+         WHERE vmid=$1 AND state='free' RETURNING {}", SELECT_COLUMNS
+    ))
+    .bind(vmid as i64)
+    .bind(task_id)
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_optional(pool)
+    .await
+}
+
+/// Marks the lowest-numbered free VM (optionally of a given OS profile) busy for `task_id`.
+pub async fn claim_free(pool: &Pool<Postgres>, task_id: &str, os_profile: Option<&str>) -> Result<Option<PoolVm>, sqlx::Error> {
+    sqlx::query_as::<_, PoolVm>(&format!(
+        "UPDATE sandbox_pool SET state='busy', current_task_id=$1, updated_at=$2
+         WHERE vmid = (SELECT vmid FROM sandbox_pool WHERE state='free' AND ($3::TEXT IS NULL OR os_profile = $3)
+                       ORDER BY vmid LIMIT 1 FOR UPDATE SKIP LOCKED)
+         RETURNING {}", SELECT_COLUMNS
+    ))
+    .bind(task_id)
+    .bind(chrono::Utc::now().timestamp())
+    .bind(os_profile)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Returns a VM to rotation. A VM flagged broken while its task ran stays broken.
+pub async fn release(pool: &Pool<Postgres>, vmid: u64) {
+    let _ = sqlx::query("UPDATE sandbox_pool SET state='free', current_task_id=NULL, updated_at=$2 WHERE vmid=$1 AND state='busy'")
+        .bind(vmid as i64)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(pool)
+        .await;
+}
+
+pub async fn mark_broken(pool: &Pool<Postgres>, vmid: u64, reason: &str) {
+    let res = sqlx::query("UPDATE sandbox_pool SET state='broken', current_task_id=NULL, last_error=$2, updated_at=$3 WHERE vmid=$1")
+        .bind(vmid as i64)
+        .bind(reason)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(pool)
+        .await;
+    if matches!(res, Ok(ref r) if r.rows_affected() > 0) {
+        println!("[POOL] VM {} marked broken: {}", vmid, reason);
+    }
+}
+
+/// Nothing survives a restart, so any VM still flagged busy is free again.
+pub async fn release_all(pool: &Pool<Postgres>) {
+    let _ = sqlx::query("UPDATE sandbox_pool SET state='free', current_task_id=NULL, updated_at=$1 WHERE state='busy'")
+        .bind(chrono::Utc::now().timestamp())
+        .execute(pool)
+        .await;
+}
+
+#[utoipa::path(tag = "sandbox pool", responses((status = 200, description = "Success")))]
+#[get("/sandbox-pool")]
+pub async fn list_pool(pool: web::Data<Pool<Postgres>>) -> impl Responder {
+    match sqlx::query_as::<_, PoolVm>(&format!("SELECT {} FROM sandbox_pool ORDER BY vmid", SELECT_COLUMNS))
+        .fetch_all(pool.get_ref())
+        .await
+    {
+        Ok(vms) => HttpResponse::Ok().json(vms),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "sandbox pool", responses((status = 200, description = "Success")))]
+#[post("/sandbox-pool")]
+pub async fn register_pool_vm(
+    pool: web::Data<Pool<Postgres>>,
+    client: web::Data<dyn Hypervisor>,
+    req: web::Json<RegisterPoolVmRequest>,
+) -> impl Responder {
+    let node = req.node.trim();
+    if node.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "node must not be empty" }));
+    }
+    // Catch typos up front; if the hypervisor is unreachable we take the caller's word for it
+    if let Ok(vms) = client.get_vms(node).await {
+        if !vms.iter().any(|v| v.vmid == req.vmid) {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("VM {} not found on node {}", req.vmid, node) }));
+        }
+    }
+
+    let snapshot = req.snapshot.as_deref().map(str::trim).filter(|s| !s.is_empty()).map(str::to_string).unwrap_or_else(default_snapshot);
+    if let Err(e) = ensure_snapshot(client.get_ref(), node, req.vmid, &snapshot).await {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let result = sqlx::query_as::<_, PoolVm>(&format!(
+        "INSERT INTO sandbox_pool (vmid, node, os_profile, snapshot, arch, state, created_at, updated_at)
+         VALUES ($1, $2, $3, $4, $5, 'free', $6, $6) RETURNING {}", SELECT_COLUMNS
+    ))
+    .bind(req.vmid as i64)
+    .bind(node)
+    .bind(req.os_profile.as_deref().map(str::trim).filter(|s| !s.is_empty()).unwrap_or("windows10"))
+    .bind(&snapshot)
+    .bind(req.arch.as_deref().map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty()).unwrap_or_else(|| "x64".to_string()))
+    .bind(now)
+    .fetch_one(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(vm) => {
+            println!("[POOL] Registered VM {} on {} (snapshot '{}')", vm.vmid, vm.node, vm.snapshot);
+            HttpResponse::Ok().json(vm)
+        }
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            HttpResponse::Conflict().json(serde_json::json!({ "error": format!("VM {} is already registered", req.vmid) }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "sandbox pool", responses((status = 200, description = "Success")))]
+#[post("/sandbox-pool/{vmid}")]
+pub async fn update_pool_vm(
+    pool: web::Data<Pool<Postgres>>,
+    client: web::Data<dyn Hypervisor>,
+    path: web::Path<u64>,
+    req: web::Json<UpdatePoolVmRequest>,
+) -> impl Responder {
+    let vmid = path.into_inner();
+    let snapshot = req.snapshot.as_deref().map(str::trim).filter(|s| !s.is_empty());
+    if let Some(snap) = snapshot {
+        let node = match (req.node.as_deref().map(str::trim).filter(|s| !s.is_empty()), get(pool.get_ref(), vmid).await) {
+            (Some(n), _) => n.to_string(),
+            (None, Ok(Some(vm))) => vm.node,
+            (None, Ok(None)) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "VM not in sandbox pool" })),
+            (None, Err(e)) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+        };
+        if let Err(e) = ensure_snapshot(client.get_ref(), &node, vmid, snap).await {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
+        }
+    }
+    let state = req.state.as_ref().map(|s| s.trim().to_lowercase());
+    if let Some(s) = &state {
+        if !STATES.contains(&s.as_str()) {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": "state must be one of free, busy, broken" }));
+        }
+    }
+
+    let result = sqlx::query_as::<_, PoolVm>(&format!(
+        "UPDATE sandbox_pool SET
+            node = COALESCE($2, node),
+            os_profile = COALESCE($3, os_profile),
+            snapshot = COALESCE($4, snapshot),
+            arch = COALESCE($5, arch),
+            state = COALESCE($6, state),
+            current_task_id = CASE WHEN $6 IS NULL OR $6 = 'busy' THEN current_task_id ELSE NULL END,
+            last_error = CASE WHEN $6 = 'free' THEN NULL ELSE last_error END,
+            updated_at = $7
+         WHERE vmid = $1 RETURNING {}", SELECT_COLUMNS
+    ))
+    .bind(vmid as i64)
+    .bind(req.node.as_deref().map(str::trim).filter(|s| !s.is_empty()))
+    .bind(req.os_profile.as_deref().map(str::trim).filter(|s| !s.is_empty()))
+    .bind(snapshot)
+    .bind(req.arch.as_deref().map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty()))
+    .bind(&state)
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_optional(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(Some(vm)) => HttpResponse::Ok().json(vm),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({ "error": "VM not in sandbox pool" })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "sandbox pool", responses((status = 200, description = "Success")))]
+#[delete("/sandbox-pool/{vmid}")]
+pub async fn remove_pool_vm(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<u64>,
+) -> impl Responder {
+    let vmid = path.into_inner();
+    match sqlx::query("DELETE FROM sandbox_pool WHERE vmid = $1").bind(vmid as i64).execute(pool.get_ref()).await {
+        Ok(res) if res.rows_affected() == 0 => {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": "VM not in sandbox pool" }))
+        }
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "status": "removed", "vmid": vmid })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
@@ -0,0 +1,653 @@
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::task_queue::{self, QueuedAnalysis, TaskScheduler};
+use crate::{analysis_profiles, comparison, network_policy};
+
+// --- SCHEDULED ANALYSES ---
+// Cron-scheduled re-detonations.
+
+/// How often due schedules are looked for.
+const TICK: Duration = Duration::from_secs(60);
+
+/// Cadences that would fire more often than this are refused; a detonation takes minutes.
+const MIN_INTERVAL_MINUTES: i64 = 15;
+
+/// Runs listed (and diffed) per schedule.
+const MAX_RUNS: i64 = 50;
+
+/// Behaviours listed per drift bucket.
+const MAX_DRIFT: usize = 100;
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Schedule {
+    pub id: i32,
+    pub name: String,
+    /// url | sample
+    pub kind: String,
+    /// The URL, or the task whose sample is re-detonated.
+    pub target: String,
+    pub cadence: String,
+    pub analysis_mode: Option<String>,
+    pub duration_seconds: Option<i64>,
+    pub priority: Option<String>,
+    pub profile: Option<String>,
+    pub internet_policy: Option<String>,
+    pub enabled: bool,
+    pub next_run_at: Option<i64>,
+    pub last_run_at: Option<i64>,
+    pub last_task_id: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct ScheduleRequest {
+    pub name: Option<String>,
+    /// Re-detonate this URL...
+    pub url: Option<String>,
+    /// ...or the sample of this task.
+    pub task_id: Option<String>,
+    /// "m h dom mon dow" in UTC, or @hourly / @daily / @weekly / @monthly.
+    pub cadence: Option<String>,
+    /// Minutes.
+    pub analysis_duration: Option<u64>,
+    pub analysis_mode: Option<String>,
+    /// urgent | normal | bulk
+    pub priority: Option<String>,
+    pub profile: Option<String>,
+    /// full | fake-net | blocked | vpn
+    pub internet_policy: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct ScheduleRun {
+    pub task_id: String,
+    pub status: String,
+    pub verdict: Option<String>,
+    pub risk_score: Option<i32>,
+    pub created_at: i64,
+    /// Versus the previous completed run; empty for the first one.
+    pub new_behaviors: Vec<String>,
+    pub gone_behaviors: Vec<String>,
+    /// Verdict changed, or behaviours appeared or disappeared.
+    pub drifted: bool,
+}
+
+#[derive(sqlx::FromRow)]
+struct RunRow {
+    id: String,
+    status: String,
+    verdict: Option<String>,
+    risk_score: Option<i32>,
+    created_at: i64,
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS analysis_schedules (
+            id SERIAL PRIMARY KEY,
+            name TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            target TEXT NOT NULL,
+            cadence TEXT NOT NULL,
+            analysis_mode TEXT,
+            duration_seconds BIGINT,
+            priority TEXT,
+            profile TEXT,
+            internet_policy TEXT,
+            enabled BOOLEAN NOT NULL DEFAULT TRUE,
+            next_run_at BIGINT,
+            last_run_at BIGINT,
+            last_task_id TEXT,
+            created_by TEXT,
+            created_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query("ALTER TABLE tasks ADD COLUMN IF NOT EXISTS schedule_id INTEGER")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_tasks_schedule ON tasks(schedule_id, created_at)")
+        .execute(pool)
+        .await?;
+
+    println!("[SCHEDULE] Database initialized (analysis_schedules).");
+    Ok(())
+}
+
+/// One cron field as the set of values it allows.
+struct Field {
+    allowed: Vec<bool>,
+    /// Written as `*` (matters for the day-of-month / day-of-week rule).
+    any: bool,
+}
+
+impl Field {
+    fn parse(raw: &str, min: u32, max: u32) -> Result<Self, String> {
+        let mut allowed = vec![false; max as usize + 1];
+        for part in raw.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((r, s)) => (r, s.parse::<u32>().ok().filter(|s| *s > 0).ok_or_else(|| format!("bad step in '{}'", part))?),
+                None => (part, 1),
+            };
+            let (lo, hi) = match range {
+                "*" => (min, max),
+                r => match r.split_once('-') {
+                    Some((a, b)) => (
+                        a.parse::<u32>().map_err(|_| format!("bad value '{}'", a))?,
+                        b.parse::<u32>().map_err(|_| format!("bad value '{}'", b))?,
+                    ),
+                    None => {
+                        let v = r.parse::<u32>().map_err(|_| format!("bad value '{}'", r))?;
+                        // "5/15" means from 5 to the end in steps of 15
+                        (v, if step > 1 { max } else { v })
+                    }
+                },
+            };
+            if lo < min || hi > max || lo > hi {
+                return Err(format!("'{}' is outside {}-{}", part, min, max));
+            }
+            for v in (lo..=hi).step_by(step as usize) {
+                allowed[v as usize] = true;
+            }
+        }
+        Ok(Self { allowed, any: raw == "*" })
+    }
+
+    fn has(&self, v: u32) -> bool {
+        self.allowed.get(v as usize).copied().unwrap_or(false)
+    }
+}
+
+/// A parsed cron expression.
+pub struct Cadence {
+    minute: Field,
+    hour: Field,
+    dom: Field,
+    month: Field,
+    dow: Field,
+}
+
+impl Cadence {
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let expr = match raw.trim().to_lowercase().as_str() {
+            "@hourly" => "0 * * * *".to_string(),
+            "@daily" | "@midnight" => "0 0 * * *".to_string(),
+            "@weekly" => "0 0 * * 0".to_string(),
+            "@monthly" => "0 0 1 * *".to_string(),
+            other => other.to_string(),
+        };
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, dom, month, dow] = fields.as_slice() else {
+            return Err("cadence needs 5 fields (minute hour day-of-month month day-of-week) or @hourly/@daily/@weekly/@monthly".to_string());
+        };
+        let mut dow = Field::parse(dow, 0, 7)?;
+        // 7 is Sunday too
+        if dow.allowed[7] {
+            dow.allowed[0] = true;
+        }
+        Ok(Self {
+            minute: Field::parse(minute, 0, 59)?,
+            hour: Field::parse(hour, 0, 23)?,
+            dom: Field::parse(dom, 1, 31)?,
+            month: Field::parse(month, 1, 12)?,
+            dow,
+        })
+    }
+
+    /// Cron rule: when both day fields are restricted, either one matching is enough.
+    fn day_matches(&self, t: &DateTime<Utc>) -> bool {
+        let dom = self.dom.has(t.day());
+        let dow = self.dow.has(t.weekday().num_days_from_sunday());
+        match (self.dom.any, self.dow.any) {
+            (false, false) => dom || dow,
+            _ => dom && dow,
+        }
+    }
+
+    /// First fire time strictly after `after`; None if nothing matches within ~5 years (e.g. Feb 31).
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut t = after.with_second(0)?.with_nanosecond(0)? + ChronoDuration::minutes(1);
+        let limit = after + ChronoDuration::days(5 * 366);
+        while t <= limit {
+            if !self.month.has(t.month()) {
+                let (y, m) = if t.month() == 12 { (t.year() + 1, 1) } else { (t.year(), t.month() + 1) };
+                t = Utc.with_ymd_and_hms(y, m, 1, 0, 0, 0).single()?;
+            } else if !self.day_matches(&t) {
+                t = (t + ChronoDuration::days(1)).with_hour(0)?.with_minute(0)?;
+            } else if !self.hour.has(t.hour()) {
+                t = (t + ChronoDuration::hours(1)).with_minute(0)?;
+            } else if !self.minute.has(t.minute()) {
+                t += ChronoDuration::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+}
+
+/// Parses and sanity-checks a cadence; returns its next fire time (unix millis).
+fn validate_cadence(raw: &str) -> Result<i64, String> {
+    let cadence = Cadence::parse(raw)?;
+    let first = cadence.next_after(Utc::now()).ok_or("cadence never fires")?;
+    let mut prev = first;
+    for _ in 0..4 {
+        let Some(next) = cadence.next_after(prev) else { break };
+        if next - prev < ChronoDuration::minutes(MIN_INTERVAL_MINUTES) {
+            return Err(format!("cadence fires more often than every {} minutes", MIN_INTERVAL_MINUTES));
+        }
+        prev = next;
+    }
+    Ok(first.timestamp_millis())
+}
+
+fn next_run(cadence: &str) -> Option<i64> {
+    Cadence::parse(cadence).ok()?.next_after(Utc::now()).map(|t| t.timestamp_millis())
+}
+
+/// Background loop: starts every enabled schedule whose time has come.
+pub async fn start(pool: Pool<Postgres>, scheduler: Arc<TaskScheduler>) {
+    let mut interval = tokio::time::interval(TICK);
+    loop {
+        interval.tick().await;
+        let due: Vec<Schedule> = sqlx::query_as(
+            "SELECT * FROM analysis_schedules WHERE enabled AND next_run_at IS NOT NULL AND next_run_at <= $1 ORDER BY next_run_at"
+        )
+        .bind(Utc::now().timestamp_millis())
+        .fetch_all(&pool)
+        .await
+        .unwrap_or_default();
+
+        for schedule in due {
+            // Advance first: a run that fails to start should not be retried every tick
+            let _ = sqlx::query("UPDATE analysis_schedules SET next_run_at=$2 WHERE id=$1")
+                .bind(schedule.id)
+                .bind(next_run(&schedule.cadence))
+                .execute(&pool)
+                .await;
+            if let Err(e) = trigger(&pool, &scheduler, &schedule).await {
+                println!("[SCHEDULE] Schedule {} ('{}') failed to start a run: {}", schedule.id, schedule.name, e);
+            }
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct SourceTask {
+    filename: String,
+    original_filename: Option<String>,
+    file_hash: Option<String>,
+    file_path: Option<String>,
+    container: Option<serde_json::Value>,
+}
+
+/// Creates and queues the schedule's next run; returns its task ID.
+async fn trigger(pool: &Pool<Postgres>, scheduler: &TaskScheduler, schedule: &Schedule) -> Result<String, String> {
+    let profile = analysis_profiles::resolve(pool, schedule.profile.as_deref()).await?;
+    let settings = analysis_profiles::SubmissionSettings {
+        duration_seconds: schedule.duration_seconds.map(|s| s as u64),
+        analysis_mode: schedule.analysis_mode.clone(),
+        priority: schedule.priority.as_deref().and_then(task_queue::parse_priority),
+        internet_policy: schedule.internet_policy.as_deref().and_then(network_policy::parse_policy),
+        ..Default::default()
+    }.or_profile(profile.as_ref());
+
+    let created_at = Utc::now().timestamp_millis();
+    let task_id = format!("{}-s{}", created_at, schedule.id);
+    let previous = schedule.last_task_id.clone();
+
+    let (target_url, detonation_name, is_url_task) = if schedule.kind == "url" {
+        let display = if schedule.target.len() > 100 { format!("{}...", &schedule.target[..97]) } else { schedule.target.clone() };
+        sqlx::query(
+            "INSERT INTO tasks (id, filename, original_filename, file_hash, status, created_at, parent_task_id, schedule_id)
+             VALUES ($1, $2, $3, 'N/A', 'Queued', $4, $5, $6)"
+        )
+        .bind(&task_id)
+        .bind(format!("URL: {}", display))
+        .bind(&schedule.target)
+        .bind(created_at)
+        .bind(&previous)
+        .bind(schedule.id)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+        (schedule.target.clone(), "URL_Detonation".to_string(), true)
+    } else {
+        let source = sqlx::query_as::<_, SourceTask>(
+            "SELECT filename, original_filename, file_hash, file_path, container FROM tasks WHERE id = $1"
+        )
+        .bind(&schedule.target)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("source task {} no longer exists", schedule.target))?;
+        sqlx::query(
+            "INSERT INTO tasks (id, filename, original_filename, file_hash, status, created_at, file_path, parent_task_id, container, schedule_id)
+             VALUES ($1, $2, $3, $4, 'Queued', $5, $6, $7, $8, $9)"
+        )
+        .bind(&task_id)
+        .bind(&source.filename)
+        .bind(&source.original_filename)
+        .bind(&source.file_hash)
+        .bind(created_at)
+        .bind(&source.file_path)
+        .bind(previous.as_ref().unwrap_or(&schedule.target))
+        .bind(&source.container)
+        .bind(schedule.id)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+        let host_ip = std::env::var("HOST_IP").unwrap_or_else(|_| "192.168.50.11".to_string());
+        let name = source.original_filename.clone().filter(|n| !n.is_empty()).unwrap_or_else(|| source.filename.clone());
+        (format!("http://{}:8080/uploads/{}", host_ip, source.filename), name, false)
+    };
+    network_policy::record(pool, &task_id, settings.internet_policy).await;
+
+    scheduler.enqueue(QueuedAnalysis {
+        task_id: task_id.clone(),
+        target_url,
+        original_filename: detonation_name,
+        duration_seconds: settings.duration_seconds.unwrap_or(300),
+        vmid: settings.vmid,
+        node: settings.node,
+        is_url_task,
+        analysis_mode: settings.analysis_mode.unwrap_or_else(|| "quick".to_string()),
+        os_profile: settings.os_profile,
+        priority: settings.priority.unwrap_or(task_queue::DEFAULT_PRIORITY).to_string(),
+        profile: profile.map(|p| p.name),
+    }).await.map_err(|e| e.to_string())?;
+
+    let _ = sqlx::query("UPDATE analysis_schedules SET last_run_at=$2, last_task_id=$3 WHERE id=$1")
+        .bind(schedule.id)
+        .bind(created_at)
+        .bind(&task_id)
+        .execute(pool)
+        .await;
+    println!("[SCHEDULE] Schedule {} ('{}') started run {}", schedule.id, schedule.name, task_id);
+    Ok(task_id)
+}
+
+/// The schedule's runs, newest first, each diffed against the completed run before it.
+async fn runs_with_drift(pool: &Pool<Postgres>, schedule_id: i32) -> Result<Vec<ScheduleRun>, sqlx::Error> {
+    let mut rows = sqlx::query_as::<_, RunRow>(
+        "SELECT id, status, verdict, risk_score, created_at FROM tasks WHERE schedule_id = $1 ORDER BY created_at DESC LIMIT $2"
+    )
+    .bind(schedule_id)
+    .bind(MAX_RUNS)
+    .fetch_all(pool)
+    .await?;
+    rows.reverse();
+
+    let mut runs = Vec::with_capacity(rows.len());
+    let mut baseline: Option<(std::collections::BTreeSet<String>, Option<String>)> = None;
+    for row in rows {
+        let mut run = ScheduleRun {
+            task_id: row.id,
+            status: row.status,
+            verdict: row.verdict,
+            risk_score: row.risk_score,
+            created_at: row.created_at,
+            new_behaviors: Vec::new(),
+            gone_behaviors: Vec::new(),
+            drifted: false,
+        };
+        if run.status == "Completed" {
+            let current = comparison::behaviors(pool, &run.task_id).await;
+            if let Some((prev, prev_verdict)) = &baseline {
+                run.new_behaviors = current.difference(prev).take(MAX_DRIFT).cloned().collect();
+                run.gone_behaviors = prev.difference(&current).take(MAX_DRIFT).cloned().collect();
+                let verdict_changed = prev_verdict.as_deref().map(str::to_lowercase) != run.verdict.as_deref().map(str::to_lowercase);
+                run.drifted = verdict_changed || !run.new_behaviors.is_empty() || !run.gone_behaviors.is_empty();
+            }
+            baseline = Some((current, run.verdict.clone()));
+        }
+        runs.push(run);
+    }
+    runs.reverse();
+    Ok(runs)
+}
+
+/// Checks the settings a schedule carries, so a bad one fails now rather than at 3am.
+fn validate_settings(req: &ScheduleRequest) -> Result<(), String> {
+    if let Some(mode) = &req.analysis_mode {
+        if !matches!(mode.as_str(), "quick" | "deep" | "vsix") {
+            return Err("analysis_mode must be quick, deep or vsix".to_string());
+        }
+    }
+    if req.priority.as_deref().is_some_and(|p| task_queue::parse_priority(p).is_none()) {
+        return Err("priority must be urgent, normal or bulk".to_string());
+    }
+    if req.internet_policy.as_deref().is_some_and(|p| network_policy::parse_policy(p).is_none()) {
+        return Err("internet_policy must be full, fake-net, blocked or vpn".to_string());
+    }
+    Ok(())
+}
+
+#[utoipa::path(tag = "schedules", responses((status = 200, description = "Every schedule")))]
+#[get("/schedules")]
+pub async fn list_schedules(pool: web::Data<Pool<Postgres>>) -> impl Responder {
+    match sqlx::query_as::<_, Schedule>("SELECT * FROM analysis_schedules ORDER BY id")
+        .fetch_all(pool.get_ref())
+        .await
+    {
+        Ok(schedules) => HttpResponse::Ok().json(schedules),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Creates a schedule for a URL (`url`) or an existing task's sample (`task_id`).
+#[utoipa::path(tag = "schedules", request_body = ScheduleRequest, responses(
+    (status = 200, description = "Schedule created"),
+    (status = 400, description = "Missing target, bad cadence or invalid settings"),
+))]
+#[post("/schedules")]
+pub async fn create_schedule(
+    http_req: actix_web::HttpRequest,
+    pool: web::Data<Pool<Postgres>>,
+    req: web::Json<ScheduleRequest>,
+) -> impl Responder {
+    let (kind, target) = match (req.url.as_deref().map(str::trim), req.task_id.as_deref().map(str::trim)) {
+        (Some(url), None) if url.starts_with("http://") || url.starts_with("https://") => ("url", url.to_string()),
+        (Some(_), None) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "url must start with http:// or https://" })),
+        (None, Some(task_id)) if !task_id.is_empty() => ("sample", task_id.to_string()),
+        _ => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Set exactly one of url or task_id" })),
+    };
+    if kind == "sample" {
+        let hash: Option<Option<String>> = sqlx::query_scalar("SELECT file_hash FROM tasks WHERE id = $1")
+            .bind(&target)
+            .fetch_optional(pool.get_ref())
+            .await
+            .ok()
+            .flatten();
+        match hash {
+            None => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" })),
+            Some(Some(h)) if h == "N/A" => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Task is a URL analysis; schedule its url instead" })),
+            _ => {}
+        }
+    }
+    let Some(cadence) = req.cadence.as_deref().map(str::trim).filter(|c| !c.is_empty()) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "cadence is required" }));
+    };
+    let next_run_at = match validate_cadence(cadence) {
+        Ok(t) => t,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+    if let Err(e) = validate_settings(&req) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
+    }
+
+    let name = req.name.as_deref().map(str::trim).filter(|n| !n.is_empty()).map(str::to_string)
+        .unwrap_or_else(|| format!("{} {}", kind, target));
+    let enabled = req.enabled.unwrap_or(true);
+    let result = sqlx::query_as::<_, Schedule>(
+        "INSERT INTO analysis_schedules (name, kind, target, cadence, analysis_mode, duration_seconds, priority, profile, internet_policy, enabled, next_run_at, created_by, created_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13) RETURNING *"
+    )
+    .bind(&name)
+    .bind(kind)
+    .bind(&target)
+    .bind(cadence)
+    .bind(&req.analysis_mode)
+    .bind(req.analysis_duration.map(|m| (m * 60) as i64))
+    .bind(req.priority.as_deref().and_then(task_queue::parse_priority))
+    .bind(&req.profile)
+    .bind(req.internet_policy.as_deref().and_then(network_policy::parse_policy))
+    .bind(enabled)
+    .bind(enabled.then_some(next_run_at))
+    .bind(crate::auth::current_user(&http_req).map(|u| u.username))
+    .bind(Utc::now().timestamp_millis())
+    .fetch_one(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(schedule) => {
+            println!("[SCHEDULE] Schedule {} created: {} {} ({})", schedule.id, kind, target, cadence);
+            HttpResponse::Ok().json(schedule)
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// The schedule and its runs, each flagged when its behaviour or verdict drifted from the run before.
+#[utoipa::path(tag = "schedules", responses(
+    (status = 200, description = "Schedule with runs and drift"),
+    (status = 404, description = "Schedule not found"),
+))]
+#[get("/schedules/{id}")]
+pub async fn get_schedule(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<i32>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let schedule = match sqlx::query_as::<_, Schedule>("SELECT * FROM analysis_schedules WHERE id = $1")
+        .bind(id)
+        .fetch_optional(pool.get_ref())
+        .await
+    {
+        Ok(Some(s)) => s,
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Schedule not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    match runs_with_drift(pool.get_ref(), id).await {
+        Ok(runs) => HttpResponse::Ok().json(serde_json::json!({ "schedule": schedule, "runs": runs })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Changes cadence, settings or enabled state; the target stays fixed.
+#[utoipa::path(tag = "schedules", request_body = ScheduleRequest, responses(
+    (status = 200, description = "Schedule updated"),
+    (status = 400, description = "Bad cadence or invalid settings"),
+    (status = 404, description = "Schedule not found"),
+))]
+#[post("/schedules/{id}")]
+pub async fn update_schedule(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<i32>,
+    req: web::Json<ScheduleRequest>,
+) -> impl Responder {
+    let id = path.into_inner();
+    if req.url.is_some() || req.task_id.is_some() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "A schedule's target cannot be changed; create a new one" }));
+    }
+    let cadence = req.cadence.as_deref().map(str::trim).filter(|c| !c.is_empty());
+    if let Some(c) = cadence {
+        if let Err(e) = validate_cadence(c) {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
+        }
+    }
+    if let Err(e) = validate_settings(&req) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
+    }
+
+    let result = sqlx::query_as::<_, Schedule>(
+        "UPDATE analysis_schedules SET
+            name = COALESCE($2, name),
+            cadence = COALESCE($3, cadence),
+            analysis_mode = COALESCE($4, analysis_mode),
+            duration_seconds = COALESCE($5, duration_seconds),
+            priority = COALESCE($6, priority),
+            profile = COALESCE($7, profile),
+            internet_policy = COALESCE($8, internet_policy),
+            enabled = COALESCE($9, enabled)
+         WHERE id = $1 RETURNING *"
+    )
+    .bind(id)
+    .bind(req.name.as_deref().map(str::trim).filter(|n| !n.is_empty()))
+    .bind(cadence)
+    .bind(&req.analysis_mode)
+    .bind(req.analysis_duration.map(|m| (m * 60) as i64))
+    .bind(req.priority.as_deref().and_then(task_queue::parse_priority))
+    .bind(&req.profile)
+    .bind(req.internet_policy.as_deref().and_then(network_policy::parse_policy))
+    .bind(req.enabled)
+    .fetch_optional(pool.get_ref())
+    .await;
+
+    let mut schedule = match result {
+        Ok(Some(s)) => s,
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Schedule not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    // Re-plan from now: the cadence or enabled state may have changed
+    schedule.next_run_at = if schedule.enabled { next_run(&schedule.cadence) } else { None };
+    let _ = sqlx::query("UPDATE analysis_schedules SET next_run_at=$2 WHERE id=$1")
+        .bind(id)
+        .bind(schedule.next_run_at)
+        .execute(pool.get_ref())
+        .await;
+    HttpResponse::Ok().json(schedule)
+}
+
+/// Deletes the schedule; tasks it already created are kept.
+#[utoipa::path(tag = "schedules", responses(
+    (status = 200, description = "Schedule deleted"),
+    (status = 404, description = "Schedule not found"),
+))]
+#[delete("/schedules/{id}")]
+pub async fn delete_schedule(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<i32>,
+) -> impl Responder {
+    let id = path.into_inner();
+    match sqlx::query("DELETE FROM analysis_schedules WHERE id = $1").bind(id).execute(pool.get_ref()).await {
+        Ok(res) if res.rows_affected() == 0 => HttpResponse::NotFound().json(serde_json::json!({ "error": "Schedule not found" })),
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "status": "deleted", "id": id })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Starts a run now, outside the cadence; the next scheduled run is unaffected.
+#[utoipa::path(tag = "schedules", responses(
+    (status = 200, description = "Run queued"),
+    (status = 404, description = "Schedule not found"),
+))]
+#[post("/schedules/{id}/run")]
+pub async fn run_schedule_now(
+    pool: web::Data<Pool<Postgres>>,
+    scheduler: web::Data<Arc<TaskScheduler>>,
+    path: web::Path<i32>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let schedule = match sqlx::query_as::<_, Schedule>("SELECT * FROM analysis_schedules WHERE id = $1")
+        .bind(id)
+        .fetch_optional(pool.get_ref())
+        .await
+    {
+        Ok(Some(s)) => s,
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Schedule not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    match trigger(pool.get_ref(), scheduler.get_ref(), &schedule).await {
+        Ok(task_id) => HttpResponse::Ok().json(serde_json::json!({ "status": "analysis_queued", "task_id": task_id, "schedule_id": id })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e })),
+    }
+}
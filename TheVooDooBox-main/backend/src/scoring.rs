@@ -0,0 +1,410 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+
+// --- BEHAVIORAL SCORING ---
+
+/// Bump when rules or weights change, so stored scores show which ruleset produced them.
+pub const RULESET_VERSION: i32 = 2;
+
+const MALICIOUS_AT: i32 = 70;
+const SUSPICIOUS_AT: i32 = 30;
+/// Evidence lines kept per triggered rule.
+const MAX_EVIDENCE: usize = 5;
+
+const LOLBINS: &[&str] = &[
+    "certutil.exe", "mshta.exe", "regsvr32.exe", "rundll32.exe", "bitsadmin.exe", "wmic.exe",
+    "msiexec.exe", "installutil.exe", "regasm.exe", "regsvcs.exe", "msbuild.exe", "cmstp.exe",
+    "forfiles.exe", "pcalua.exe", "hh.exe", "odbcconf.exe", "esentutl.exe", "expand.exe",
+    "powershell.exe", "pwsh.exe", "cmd.exe", "schtasks.exe", "curl.exe",
+];
+const SCRIPT_HOSTS: &[&str] = &["wscript.exe", "cscript.exe", "mshta.exe", "powershell.exe", "pwsh.exe"];
+const DOCUMENT_HOSTS: &[&str] = &["winword.exe", "excel.exe", "powerpnt.exe", "outlook.exe", "acrord32.exe", "msaccess.exe", "onenote.exe"];
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Evidence {
+    pub event_id: Option<i32>,
+    pub summary: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TriggeredRule {
+    pub id: String,
+    pub title: String,
+    pub weight: i32,
+    /// Every hit, including those past the rule's cap.
+    pub hits: usize,
+    pub points: i32,
+    pub evidence: Vec<Evidence>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TaskScore {
+    pub task_id: String,
+    pub score: i32,
+    pub verdict: String,
+    pub triggered: Vec<TriggeredRule>,
+    pub ruleset_version: i32,
+    pub computed_at: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct EventRow {
+    id: i32,
+    event_type: String,
+    process_id: i32,
+    parent_process_id: i32,
+    process_name: String,
+    details: String,
+    decoded_details: Option<String>,
+    timestamp: i64,
+}
+
+struct Telemetry {
+    events: Vec<EventRow>,
+    alerts: Vec<crate::alerts::Alert>,
+    /// pid -> image name, from the process creations seen.
+    names: HashMap<i32, String>,
+}
+
+struct Rule {
+    id: &'static str,
+    title: &'static str,
+    weight: i32,
+    max_hits: usize,
+    check: fn(&Telemetry) -> Vec<Evidence>,
+}
+
+const RULES: &[Rule] = &[
+    Rule { id: "injection", title: "Code injected into another process", weight: 25, max_hits: 2, check: injection },
+    Rule { id: "credential_access", title: "LSASS memory accessed", weight: 25, max_hits: 1, check: credential_access },
+    Rule { id: "inhibit_recovery", title: "Shadow copies or recovery disabled", weight: 30, max_hits: 1, check: inhibit_recovery },
+    Rule { id: "lolbin_chain", title: "LOLBin launched by a document, script host or another LOLBin", weight: 20, max_hits: 3, check: lolbin_chain },
+    Rule { id: "lolbin_remote", title: "LOLBin given a remote URL or share", weight: 15, max_hits: 2, check: lolbin_remote },
+    Rule { id: "persistence", title: "Persistence written", weight: 15, max_hits: 3, check: persistence },
+    Rule { id: "c2_beacon", title: "Periodic connections to one destination", weight: 20, max_hits: 2, check: c2_beacon },
+    Rule { id: "defense_evasion", title: "Security tooling or evidence tampered with", weight: 15, max_hits: 3, check: defense_evasion },
+    Rule { id: "anti_analysis", title: "Virtual machine or sandbox checks", weight: 10, max_hits: 2, check: anti_analysis },
+    Rule { id: "lateral_movement", title: "Lateral movement attempted", weight: 15, max_hits: 2, check: lateral_movement },
+    Rule { id: "sigma_critical", title: "Critical Sigma detections", weight: 15, max_hits: 2, check: sigma_critical },
+    Rule { id: "sigma_high", title: "High Sigma detections", weight: 10, max_hits: 2, check: sigma_high },
+];
+
+fn base_name(path: &str) -> String {
+    path.rsplit(['\\', '/']).next().unwrap_or(path).trim_matches('"').to_lowercase()
+}
+
+fn text(e: &EventRow) -> String {
+    format!("{} {} {}", e.process_name, e.details, e.decoded_details.as_deref().unwrap_or_default()).to_lowercase()
+}
+
+fn evidence(e: &EventRow) -> Evidence {
+    Evidence { event_id: Some(e.id), summary: format!("{} {}: {}", e.event_type, e.process_name, e.details.chars().take(200).collect::<String>()) }
+}
+
+/// Events of the given types (exact, or prefix ending in '*') whose text has any needle.
+fn matching(t: &Telemetry, types: &[&str], needles: &[&str]) -> Vec<Evidence> {
+    t.events.iter()
+        .filter(|e| types.iter().any(|ty| match ty.strip_suffix('*') {
+            Some(prefix) => e.event_type.starts_with(prefix),
+            None => e.event_type == *ty,
+        }))
+        .filter(|e| needles.is_empty() || {
+            let text = text(e);
+            needles.iter().any(|n| text.contains(n))
+        })
+        .map(evidence)
+        .collect()
+}
+
+fn injection(t: &Telemetry) -> Vec<Evidence> {
+    matching(t, &["REMOTE_THREAD", "MEMORY_ANOMALY", "PROCESS_TAMPER"], &[])
+}
+
+fn credential_access(t: &Telemetry) -> Vec<Evidence> {
+    t.events.iter()
+        .filter(|e| e.event_type == "PROCESS_ACCESS" && e.details.to_lowercase().contains("lsass") && base_name(&e.process_name) != "lsass.exe")
+        .map(evidence)
+        .collect()
+}
+
+fn inhibit_recovery(t: &Telemetry) -> Vec<Evidence> {
+    matching(t, &["PROCESS_CREATE"], &["delete shadows", "shadowcopy delete", "recoveryenabled no", "wbadmin delete catalog", "bootstatuspolicy ignoreallfailures"])
+}
+
+fn lolbin_chain(t: &Telemetry) -> Vec<Evidence> {
+    t.events.iter()
+        .filter(|e| e.event_type == "PROCESS_CREATE" && LOLBINS.contains(&base_name(&e.process_name).as_str()))
+        .filter_map(|e| {
+            let parent = base_name(t.names.get(&e.parent_process_id)?);
+            let suspicious = DOCUMENT_HOSTS.contains(&parent.as_str()) || SCRIPT_HOSTS.contains(&parent.as_str())
+                || (LOLBINS.contains(&parent.as_str()) && parent != "cmd.exe");
+            suspicious.then(|| Evidence {
+                event_id: Some(e.id),
+                summary: format!("{} -> {} (pid {})", parent, base_name(&e.process_name), e.process_id),
+            })
+        })
+        .collect()
+}
+
+fn lolbin_remote(t: &Telemetry) -> Vec<Evidence> {
+    t.events.iter()
+        .filter(|e| e.event_type == "PROCESS_CREATE" && LOLBINS.contains(&base_name(&e.process_name).as_str()))
+        .filter(|e| {
+            let text = text(e);
+            text.contains("http://") || text.contains("https://") || text.contains(" \\\\")
+        })
+        .map(evidence)
+        .collect()
+}
+
+fn persistence(t: &Telemetry) -> Vec<Evidence> {
+    let mut hits = matching(t, &["STARTUP_PERSISTENCE", "SCHTASK_*", "WMI_SUBSCRIPTION", "COM_HIJACK"], &[]);
+    hits.extend(matching(t, &["REG*"], &[
+        "\\currentversion\\run", "\\currentversion\\policies\\explorer\\run", "\\winlogon\\",
+        "\\image file execution options\\", "\\currentcontrolset\\services\\",
+    ]));
+    hits
+}
+
+fn c2_beacon(t: &Telemetry) -> Vec<Evidence> {
+    let connections: Vec<crate::beaconing::Connection> = t.events.iter().filter_map(|e| {
+        let (destination, local, bytes) = crate::beaconing::connection(&e.event_type, &e.details, e.decoded_details.as_deref())?;
+        Some(crate::beaconing::Connection {
+            event_id: e.id,
+            timestamp: e.timestamp,
+            destination,
+            local,
+            process_name: e.process_name.clone(),
+            bytes,
+        })
+    }).collect();
+    let mut out: Vec<Evidence> = crate::beaconing::detect(&connections).into_iter()
+        .filter(|b| b.beacon)
+        .map(|b| Evidence {
+            event_id: Some(b.first_event_id),
+            summary: format!("{} connections to {} every ~{}s", b.connections, b.destination, b.period_secs),
+        })
+        .collect();
+    out.sort_by_key(|e| e.event_id);
+    out
+}
+
+fn defense_evasion(t: &Telemetry) -> Vec<Evidence> {
+    let mut hits = matching(t, &["DEFENDER_*", "TIMESTOMP_DETECTED", "CERT_INSTALLED", "PROXY_HIJACK"], &[]);
+    hits.extend(matching(t, &["PROCESS_CREATE"], &[
+        "wevtutil cl", "clear-eventlog", "set-mppreference", "add-mppreference", "netsh advfirewall set", "fsutil usn deletejournal",
+    ]));
+    hits
+}
+
+fn anti_analysis(t: &Telemetry) -> Vec<Evidence> {
+    matching(t, &["REG*", "FILE_*", "PROCESS_CREATE", "IMAGE_LOAD"], &[
+        "vboxguest", "vboxservice", "vboxmouse", "vmtoolsd", "vmware tools", "\\acpi\\dsdt\\vbox", "sbiedll",
+        "qemu-ga", "wine_get_version", "systemmanufacturer", "win32_computersystem",
+    ])
+}
+
+fn lateral_movement(t: &Telemetry) -> Vec<Evidence> {
+    matching(t, &["LATERAL_MOVEMENT"], &[])
+}
+
+fn sigma_at(t: &Telemetry, level: &str) -> Vec<Evidence> {
+    t.alerts.iter()
+        .filter(|a| a.source == "sigma" && a.level == level)
+        .map(|a| Evidence { event_id: a.event_id, summary: format!("{} ({})", a.rule_title, a.process_name) })
+        .collect()
+}
+
+fn sigma_critical(t: &Telemetry) -> Vec<Evidence> {
+    sigma_at(t, "critical")
+}
+
+fn sigma_high(t: &Telemetry) -> Vec<Evidence> {
+    sigma_at(t, "high")
+}
+
+pub fn verdict_for(score: i32) -> &'static str {
+    if score >= MALICIOUS_AT {
+        "Malicious"
+    } else if score >= SUSPICIOUS_AT {
+        "Suspicious"
+    } else {
+        "Benign"
+    }
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS task_scores (
+            task_id TEXT PRIMARY KEY,
+            score INTEGER NOT NULL,
+            verdict TEXT NOT NULL,
+            triggered JSONB NOT NULL DEFAULT '[]',
+            ruleset_version INTEGER NOT NULL,
+            computed_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    println!("[SCORING] Database initialized (task_scores).");
+    Ok(())
+}
+
+/// Scores a task from its stored telemetry and saves the result.
+pub async fn score_task(pool: &Pool<Postgres>, task_id: &str) -> Result<TaskScore, sqlx::Error> {
+    let events = sqlx::query_as::<_, EventRow>(
+        "SELECT id, event_type, process_id, parent_process_id, process_name, details, decoded_details, timestamp
+         FROM events WHERE task_id = $1 ORDER BY timestamp, id"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+    // Alerts an analyst closed as false positives no longer count
+    let alerts = sqlx::query_as::<_, crate::alerts::Alert>(
+        "SELECT * FROM alerts WHERE task_id = $1 AND resolution IS DISTINCT FROM 'false_positive' ORDER BY timestamp, id"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+    let names = events.iter()
+        .filter(|e| e.event_type == "PROCESS_CREATE")
+        .map(|e| (e.process_id, e.process_name.clone()))
+        .collect();
+    let telemetry = Telemetry { events, alerts, names };
+
+    let mut triggered = Vec::new();
+    for rule in RULES {
+        let mut hits = (rule.check)(&telemetry);
+        if hits.is_empty() {
+            continue;
+        }
+        let count = hits.len();
+        hits.truncate(MAX_EVIDENCE);
+        triggered.push(TriggeredRule {
+            id: rule.id.to_string(),
+            title: rule.title.to_string(),
+            weight: rule.weight,
+            hits: count,
+            points: rule.weight * count.min(rule.max_hits) as i32,
+            evidence: hits,
+        });
+    }
+    let score = triggered.iter().map(|r| r.points).sum::<i32>().min(100);
+    let result = TaskScore {
+        task_id: task_id.to_string(),
+        score,
+        verdict: verdict_for(score).to_string(),
+        triggered,
+        ruleset_version: RULESET_VERSION,
+        computed_at: chrono::Utc::now().timestamp_millis(),
+    };
+
+    sqlx::query(
+        "INSERT INTO task_scores (task_id, score, verdict, triggered, ruleset_version, computed_at)
+         VALUES ($1, $2, $3, $4::jsonb, $5, $6)
+         ON CONFLICT (task_id) DO UPDATE SET
+            score = EXCLUDED.score,
+            verdict = EXCLUDED.verdict,
+            triggered = EXCLUDED.triggered,
+            ruleset_version = EXCLUDED.ruleset_version,
+            computed_at = EXCLUDED.computed_at"
+    )
+    .bind(task_id)
+    .bind(result.score)
+    .bind(&result.verdict)
+    .bind(serde_json::to_string(&result.triggered).unwrap_or_else(|_| "[]".to_string()))
+    .bind(result.ruleset_version)
+    .bind(result.computed_at)
+    .execute(pool)
+    .await?;
+
+    println!("[SCORING] Task {}: {} ({}), {} rules triggered.", task_id, result.score, result.verdict, result.triggered.len());
+    Ok(result)
+}
+
+/// The stored score, if the task has been scored.
+pub async fn load(pool: &Pool<Postgres>, task_id: &str) -> Result<Option<TaskScore>, sqlx::Error> {
+    let row: Option<(i32, String, String, i32, i64)> = sqlx::query_as(
+        "SELECT score, verdict, triggered::text, ruleset_version, computed_at FROM task_scores WHERE task_id = $1"
+    )
+    .bind(task_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|(score, verdict, triggered, ruleset_version, computed_at)| TaskScore {
+        task_id: task_id.to_string(),
+        score,
+        verdict,
+        triggered: serde_json::from_str(&triggered).unwrap_or_default(),
+        ruleset_version,
+        computed_at,
+    }))
+}
+
+/// Gives the task this verdict unless it already has one (from the AI or an analyst).
+pub async fn fill_verdict(pool: &Pool<Postgres>, score: &TaskScore) {
+    let res = sqlx::query(
+        "UPDATE tasks SET verdict = $2, risk_score = $3
+         WHERE id = $1 AND verdict IS NULL AND NOT COALESCE(verdict_manual, FALSE)"
+    )
+    .bind(&score.task_id)
+    .bind(&score.verdict)
+    .bind(score.score)
+    .execute(pool)
+    .await;
+    match res {
+        Ok(r) if r.rows_affected() > 0 => println!("[SCORING] Task {}: no AI verdict, using the deterministic one ({}).", score.task_id, score.verdict),
+        Ok(_) => {}
+        Err(e) => println!("[SCORING] Failed to set verdict for task {}: {}", score.task_id, e),
+    }
+}
+
+/// One paragraph for prompts and reports.
+pub fn describe(score: &TaskScore) -> String {
+    if score.triggered.is_empty() {
+        return format!("{}/100 ({}), no behaviour rules triggered.", score.score, score.verdict);
+    }
+    let rules = score.triggered.iter()
+        .map(|r| format!("{} ({} hits, +{})", r.title, r.hits, r.points))
+        .collect::<Vec<_>>()
+        .join("; ");
+    format!("{}/100 ({}). Triggered: {}.", score.score, score.verdict, rules)
+}
+
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Deterministic risk score and the rules that produced it")))]
+#[get("/tasks/{id}/score")]
+pub async fn get_task_score(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    let task_id = path.into_inner();
+    let stored = match load(pool.get_ref(), &task_id).await {
+        Ok(s) => s,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    // Scores from an older ruleset are recomputed so they compare with current ones
+    if let Some(score) = stored.filter(|s| s.ruleset_version == RULESET_VERSION) {
+        return HttpResponse::Ok().json(score);
+    }
+    rescore(pool.get_ref(), &task_id).await
+}
+
+#[utoipa::path(tag = "tasks", responses(
+    (status = 200, description = "Score recomputed from the task's current telemetry"),
+    (status = 404, description = "Task not found"),
+))]
+#[post("/tasks/{id}/score")]
+pub async fn recompute_task_score(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    rescore(pool.get_ref(), &path.into_inner()).await
+}
+
+async fn rescore(pool: &Pool<Postgres>, task_id: &str) -> HttpResponse {
+    match sqlx::query_scalar::<_, String>("SELECT id FROM tasks WHERE id = $1").bind(task_id).fetch_optional(pool).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+    match score_task(pool, task_id).await {
+        Ok(score) => HttpResponse::Ok().json(score),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
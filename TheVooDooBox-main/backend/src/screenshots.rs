@@ -0,0 +1,283 @@
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use serde::{Deserialize, Serialize, Serializer};
+use sqlx::{Pool, Postgres};
+
+// --- SCREENSHOT INDEX ---
+// Frames are indexed with a difference hash so duplicates collapse.
+
+/// Hashes at most this many bits apart are the same frame (cursor blink, clock tick).
+const DUPLICATE_DISTANCE: u32 = 3;
+const DEFAULT_LIMIT: i64 = 500;
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS screenshots (
+            id SERIAL PRIMARY KEY,
+            task_id TEXT NOT NULL,
+            filename TEXT NOT NULL,
+            timestamp BIGINT NOT NULL,
+            event_id INTEGER,
+            monitor INTEGER NOT NULL DEFAULT 0,
+            phash BIGINT,
+            width INTEGER,
+            height INTEGER,
+            size_bytes BIGINT NOT NULL DEFAULT 0,
+            duplicate_of INTEGER,
+            UNIQUE (task_id, filename)
+        )"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_screenshots_task_time ON screenshots (task_id, timestamp)").execute(pool).await?;
+    sqlx::query("ALTER TABLE screenshots ADD COLUMN IF NOT EXISTS ocr_text TEXT").execute(pool).await?;
+    sqlx::query("ALTER TABLE screenshots ADD COLUMN IF NOT EXISTS ocr_error TEXT").execute(pool).await?;
+    sqlx::query("ALTER TABLE screenshots ADD COLUMN IF NOT EXISTS ocr_at BIGINT").execute(pool).await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_screenshots_ocr ON screenshots USING GIN (to_tsvector('english', COALESCE(ocr_text, '')))").execute(pool).await?;
+    Ok(())
+}
+
+fn hex_hash<S: Serializer>(hash: &Option<i64>, s: S) -> Result<S::Ok, S::Error> {
+    match hash {
+        // As hex: a 64-bit integer does not survive a JavaScript number
+        Some(h) => s.serialize_str(&format!("{:016x}", *h as u64)),
+        None => s.serialize_none(),
+    }
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Screenshot {
+    pub id: i32,
+    pub task_id: String,
+    pub filename: String,
+    pub timestamp: i64,
+    pub event_id: Option<i32>,
+    pub monitor: i32,
+    #[serde(serialize_with = "hex_hash")]
+    pub phash: Option<i64>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub size_bytes: i64,
+    /// First frame of the run this one repeats, if any.
+    pub duplicate_of: Option<i32>,
+    /// Text read off the frame; None until OCR has run (or when it failed).
+    pub ocr_text: Option<String>,
+}
+
+impl Screenshot {
+    pub fn url(&self) -> String {
+        format!("/tasks/{}/screenshots/{}", self.task_id, self.filename)
+    }
+}
+
+/// Difference hash over a 9x8 greyscale thumbnail.
+fn dhash(bytes: &[u8]) -> Option<(u64, u32, u32)> {
+    use image::GenericImageView;
+    let img = image::load_from_memory(bytes).ok()?;
+    let (width, height) = (img.width(), img.height());
+    let small = img.resize_exact(9, 8, image::imageops::FilterType::Triangle).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y).0[0] < small.get_pixel(x + 1, y).0[0] {
+                hash |= 1;
+            }
+        }
+    }
+    Some((hash, width, height))
+}
+
+/// Agent uploads end in `_screenN.png` (or carry `screenN_` in periodic names).
+pub fn monitor_of(filename: &str) -> i32 {
+    filename.split(['_', '.'])
+        .find_map(|part| part.strip_prefix("screen").and_then(|n| n.parse().ok()))
+        .unwrap_or(0)
+}
+
+/// Indexes a capture already written to ./screenshots/<task>/<filename>.
+pub async fn record(pool: &Pool<Postgres>, task_id: &str, filename: &str, event_id: Option<i32>, timestamp: i64) -> Result<i32, String> {
+    let bytes = tokio::fs::read(format!("./screenshots/{}/{}", task_id, filename)).await.map_err(|e| e.to_string())?;
+    let size = bytes.len() as i64;
+    let hashed = tokio::task::spawn_blocking(move || dhash(&bytes)).await.map_err(|e| e.to_string())?;
+    let monitor = monitor_of(filename);
+
+    let mut duplicate_of = None;
+    if let Some((hash, _, _)) = hashed {
+        let previous: Option<(i32, Option<i64>, Option<i32>)> = sqlx::query_as(
+            "SELECT id, phash, duplicate_of FROM screenshots
+             WHERE task_id = $1 AND monitor = $2 AND timestamp <= $3 AND filename <> $4
+             ORDER BY timestamp DESC, id DESC LIMIT 1"
+        )
+        .bind(task_id)
+        .bind(monitor)
+        .bind(timestamp)
+        .bind(filename)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+        if let Some((id, Some(prev), original)) = previous {
+            if (prev as u64 ^ hash).count_ones() <= DUPLICATE_DISTANCE {
+                duplicate_of = Some(original.unwrap_or(id));
+            }
+        }
+    }
+
+    let id: i32 = sqlx::query_scalar(
+        "INSERT INTO screenshots (task_id, filename, timestamp, event_id, monitor, phash, width, height, size_bytes, duplicate_of)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+         ON CONFLICT (task_id, filename) DO UPDATE SET
+            timestamp = EXCLUDED.timestamp, event_id = EXCLUDED.event_id, monitor = EXCLUDED.monitor,
+            phash = EXCLUDED.phash, width = EXCLUDED.width, height = EXCLUDED.height,
+            size_bytes = EXCLUDED.size_bytes, duplicate_of = EXCLUDED.duplicate_of
+         RETURNING id"
+    )
+    .bind(task_id)
+    .bind(filename)
+    .bind(timestamp)
+    .bind(event_id)
+    .bind(monitor)
+    .bind(hashed.map(|(h, _, _)| h as i64))
+    .bind(hashed.map(|(_, w, _)| w as i32))
+    .bind(hashed.map(|(_, _, h)| h as i32))
+    .bind(size)
+    .bind(duplicate_of)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    // A repeat of the previous frame has the same text
+    if duplicate_of.is_none() {
+        crate::ocr::queue(pool, id);
+    }
+    Ok(id)
+}
+
+/// Capture time of an unindexed file: from its name, its event, or its mtime.
+async fn guess_timestamp(pool: &Pool<Postgres>, name: &str, event_id: Option<i32>, entry: &tokio::fs::DirEntry) -> i64 {
+    if let Some(ms) = name.strip_prefix("screenshot_").and_then(|r| r.split(['.', '_']).next()).and_then(|ms| ms.parse::<i64>().ok()) {
+        return ms;
+    }
+    if let Some(id) = event_id {
+        let ts: Option<i64> = sqlx::query_scalar("SELECT timestamp FROM events WHERE id = $1")
+            .bind(id).fetch_optional(pool).await.ok().flatten();
+        if let Some(ts) = ts {
+            return ts;
+        }
+    }
+    entry.metadata().await.ok()
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+/// Indexes files of a task that have no row yet (captures from before the index existed).
+pub async fn sync_task(pool: &Pool<Postgres>, task_id: &str) {
+    if task_id.contains("..") || task_id.contains('/') {
+        return;
+    }
+    crate::storage::ensure_local_prefix(&format!("screenshots/{}/", task_id)).await;
+    let Ok(mut entries) = tokio::fs::read_dir(format!("./screenshots/{}", task_id)).await else { return };
+    let known: Vec<String> = sqlx::query_scalar("SELECT filename FROM screenshots WHERE task_id = $1")
+        .bind(task_id)
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default();
+
+    let mut missing = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let Ok(name) = entry.file_name().into_string() else { continue };
+        if known.contains(&name) || entry.path().is_dir() {
+            continue;
+        }
+        let event_id: Option<i32> = name.strip_prefix("event_").and_then(|r| r.split('_').next()).and_then(|id| id.parse().ok());
+        let timestamp = guess_timestamp(pool, &name, event_id, &entry).await;
+        missing.push((timestamp, name, event_id));
+    }
+    // Oldest first so duplicate runs chain onto the right frame
+    missing.sort();
+    for (timestamp, name, event_id) in missing {
+        if let Err(e) = record(pool, task_id, &name, event_id, timestamp).await {
+            println!("[SCREENSHOTS] Failed to index {}/{}: {}", task_id, name, e);
+        }
+    }
+}
+
+#[derive(Deserialize, Default, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ScreenshotQuery {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub monitor: Option<i32>,
+    /// Leave out frames that repeat the one before them.
+    pub dedup: Option<bool>,
+    /// Only frames whose OCR text matches this (web search syntax: words, "phrases", -not).
+    pub text: Option<String>,
+    pub limit: Option<i64>,
+}
+
+pub async fn list(pool: &Pool<Postgres>, task_id: &str, query: &ScreenshotQuery) -> Result<Vec<Screenshot>, sqlx::Error> {
+    sync_task(pool, task_id).await;
+    let mut qb = sqlx::QueryBuilder::<Postgres>::new(
+        "SELECT id, task_id, filename, timestamp, event_id, monitor, phash, width, height, size_bytes, duplicate_of, ocr_text
+         FROM screenshots WHERE task_id = "
+    );
+    qb.push_bind(task_id.to_string());
+    if let Some(from) = query.from {
+        qb.push(" AND timestamp >= ").push_bind(from);
+    }
+    if let Some(to) = query.to {
+        qb.push(" AND timestamp <= ").push_bind(to);
+    }
+    if let Some(monitor) = query.monitor {
+        qb.push(" AND monitor = ").push_bind(monitor);
+    }
+    if query.dedup.unwrap_or(false) {
+        qb.push(" AND duplicate_of IS NULL");
+    }
+    if let Some(text) = query.text.as_ref().filter(|t| !t.is_empty()) {
+        // Same expression as idx_screenshots_ocr
+        qb.push(" AND to_tsvector('english', COALESCE(ocr_text, '')) @@ websearch_to_tsquery('english', ")
+            .push_bind(text.clone()).push(")");
+    }
+    qb.push(" ORDER BY timestamp, id LIMIT ").push_bind(query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, 5000));
+    qb.build_query_as::<Screenshot>().fetch_all(pool).await
+}
+
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
+#[get("/tasks/{id}/screenshots")]
+pub async fn get_task_screenshots(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+    query: web::Query<ScreenshotQuery>,
+) -> impl Responder {
+    match list(pool.get_ref(), &path.into_inner(), &query).await {
+        Ok(shots) => {
+            let shots: Vec<serde_json::Value> = shots.iter().map(|s| {
+                let mut value = serde_json::to_value(s).unwrap_or_default();
+                value["url"] = serde_json::Value::String(s.url());
+                value
+            }).collect();
+            HttpResponse::Ok().json(shots)
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "The PNG"), (status = 404, description = "No such task or screenshot")))]
+#[get("/tasks/{id}/screenshots/{name}")]
+pub async fn get_screenshot_file(req: HttpRequest, pool: web::Data<Pool<Postgres>>, path: web::Path<(String, String)>) -> impl Responder {
+    let (task_id, name) = path.into_inner();
+    match crate::task_exists(pool.get_ref(), &task_id).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+    let key = format!("screenshots/{}/{}", task_id, name);
+    if name.contains(['/', '\\']) || name.contains("..") || !crate::storage::ensure_local(&key).await {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Screenshot not found" }));
+    }
+    match actix_files::NamedFile::open_async(crate::storage::local_path(&key)).await {
+        Ok(file) => file.into_response(&req),
+        Err(_) => HttpResponse::NotFound().json(serde_json::json!({ "error": "Screenshot not found" })),
+    }
+}
@@ -0,0 +1,190 @@
+use actix_web::{get, post, delete, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use crate::AgentManager;
+
+// --- SEVERITY RULES ---
+
+/// Severity at or above which an event counts as high-signal downstream.
+pub const SEVERITY_HIGH: i32 = 75;
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+pub struct SeverityRule {
+    pub id: i32,
+    /// Exact event type, or a prefix ending in '*' (e.g. "FILE_*").
+    pub event_type: String,
+    /// Optional case-insensitive substring matched against process name + details.
+    pub details_contains: Option<String>,
+    pub severity: i32,
+    pub category: String,
+    pub created_at: i64,
+}
+
+#[derive(Deserialize)]
+pub struct CreateSeverityRuleRequest {
+    pub event_type: String,
+    pub details_contains: Option<String>,
+    pub severity: i32,
+    pub category: String,
+}
+
+/// Seed table; agents carry no rules of their own and get this via SET_SEVERITY_RULES.
+const DEFAULT_RULES: &[(&str, Option<&str>, i32, &str)] = &[
+    ("REMOTE_THREAD", None, 90, "injection"),
+    ("MEMORY_ANOMALY", None, 90, "injection"),
+    ("PROCESS_TAMPER", None, 85, "injection"),
+    ("PROCESS_ACCESS", Some("lsass"), 90, "credential_access"),
+    ("DEFENDER_*", None, 85, "defense_evasion"),
+    ("TIMESTOMP_DETECTED", None, 70, "defense_evasion"),
+    ("CERT_INSTALLED", None, 80, "defense_evasion"),
+    ("PROXY_HIJACK", None, 75, "defense_evasion"),
+    ("COM_HIJACK", None, 80, "persistence"),
+    ("STARTUP_PERSISTENCE", None, 80, "persistence"),
+    ("SCHTASK_*", None, 80, "persistence"),
+    ("WMI_SUBSCRIPTION", None, 85, "persistence"),
+    ("REG*", None, 40, "registry"),
+    ("LATERAL_MOVEMENT", None, 80, "lateral_movement"),
+    ("PROCESS_CREATE", None, 30, "execution"),
+    ("PROCESS_CREATE", Some("powershell"), 60, "execution"),
+    ("EXEC_SUCCESS", None, 40, "execution"),
+    ("NETWORK_*", None, 30, "network"),
+    ("FILE_*", None, 20, "file"),
+    ("FILE_*", Some("\\temp\\"), 50, "file"),
+    ("FILE_*", Some("\\appdata\\"), 50, "file"),
+    ("ADS_CREATED", None, 60, "defense_evasion"),
+    ("DOWNLOAD_DETECTED", None, 50, "file"),
+    ("IMAGE_LOAD", None, 10, "module"),
+    ("BROWSER_*", None, 20, "browser"),
+    ("WINDOW_DIALOG", None, 20, "ui"),
+];
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS severity_rules (
+            id SERIAL PRIMARY KEY,
+            event_type TEXT NOT NULL,
+            details_contains TEXT,
+            severity INTEGER NOT NULL,
+            category TEXT NOT NULL,
+            created_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM severity_rules")
+        .fetch_one(pool)
+        .await?;
+    if count == 0 {
+        let now = chrono::Utc::now().timestamp();
+        for (event_type, contains, severity, category) in DEFAULT_RULES {
+            sqlx::query("INSERT INTO severity_rules (event_type, details_contains, severity, category, created_at) VALUES ($1, $2, $3, $4, $5)")
+                .bind(event_type)
+                .bind(contains)
+                .bind(severity)
+                .bind(category)
+                .bind(now)
+                .execute(pool)
+                .await?;
+        }
+        println!("[SEVERITY] Seeded {} default severity rules.", DEFAULT_RULES.len());
+    }
+
+    println!("[SEVERITY] Database initialized (severity_rules).");
+    Ok(())
+}
+
+async fn all_rules(pool: &Pool<Postgres>) -> Result<Vec<SeverityRule>, sqlx::Error> {
+    sqlx::query_as::<_, SeverityRule>(
+        "SELECT id, event_type, details_contains, severity, category, created_at FROM severity_rules ORDER BY id"
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Builds the SET_SEVERITY_RULES command that replaces an agent's scoring table.
+pub async fn build_agent_command(pool: &Pool<Postgres>) -> Result<String, sqlx::Error> {
+    let rules = all_rules(pool).await?;
+    Ok(serde_json::json!({
+        "command": "SET_SEVERITY_RULES",
+        "rules": rules.iter().map(|r| serde_json::json!({
+            "event_type": r.event_type,
+            "details_contains": r.details_contains.as_ref().map(|c| c.to_lowercase()),
+            "severity": r.severity,
+            "category": r.category,
+        })).collect::<Vec<_>>(),
+    }).to_string())
+}
+
+async fn push_to_agents(pool: &Pool<Postgres>, manager: &AgentManager) {
+    match build_agent_command(pool).await {
+        Ok(cmd) => manager.broadcast_command(&cmd).await,
+        Err(e) => println!("[SEVERITY] Failed to build rule set: {}", e),
+    }
+}
+
+#[utoipa::path(tag = "settings", responses((status = 200, description = "Success")))]
+#[get("/settings/severity-rules")]
+pub async fn list_severity_rules(pool: web::Data<Pool<Postgres>>) -> impl Responder {
+    match all_rules(pool.get_ref()).await {
+        Ok(rules) => HttpResponse::Ok().json(rules),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "settings", responses((status = 200, description = "Success")))]
+#[post("/settings/severity-rules")]
+pub async fn add_severity_rule(
+    pool: web::Data<Pool<Postgres>>,
+    manager: web::Data<Arc<AgentManager>>,
+    req: web::Json<CreateSeverityRuleRequest>,
+) -> impl Responder {
+    let event_type = req.event_type.trim().to_uppercase();
+    if event_type.is_empty() || req.category.trim().is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "event_type and category must not be empty" }));
+    }
+    if !(0..=100).contains(&req.severity) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "severity must be between 0 and 100" }));
+    }
+
+    let result = sqlx::query_as::<_, SeverityRule>(
+        "INSERT INTO severity_rules (event_type, details_contains, severity, category, created_at) VALUES ($1, $2, $3, $4, $5)
+         RETURNING id, event_type, details_contains, severity, category, created_at"
+    )
+    .bind(&event_type)
+    .bind(req.details_contains.as_ref().map(|c| c.to_lowercase()).filter(|c| !c.is_empty()))
+    .bind(req.severity)
+    .bind(req.category.trim())
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_one(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(rule) => {
+            push_to_agents(pool.get_ref(), manager.get_ref()).await;
+            HttpResponse::Ok().json(rule)
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "settings", responses((status = 200, description = "Success")))]
+#[delete("/settings/severity-rules/{id}")]
+pub async fn delete_severity_rule(
+    pool: web::Data<Pool<Postgres>>,
+    manager: web::Data<Arc<AgentManager>>,
+    path: web::Path<i32>,
+) -> impl Responder {
+    let id = path.into_inner();
+    match sqlx::query("DELETE FROM severity_rules WHERE id = $1").bind(id).execute(pool.get_ref()).await {
+        Ok(res) if res.rows_affected() == 0 => {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": "Severity rule not found" }))
+        }
+        Ok(_) => {
+            push_to_agents(pool.get_ref(), manager.get_ref()).await;
+            HttpResponse::Ok().json(serde_json::json!({ "status": "deleted", "id": id }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
@@ -0,0 +1,320 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+// --- SIEM FORWARDING ---
+// Outputs picked with SIEM_OUTPUTS (syslog, hec, elastic).
+
+const QUEUE_SIZE: usize = 10_000;
+const BATCH_SIZE: usize = 200;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+const VENDOR: &str = "TheVooDooBox";
+const PRODUCT: &str = "Sandbox";
+
+#[derive(Serialize, Clone, Default)]
+pub struct Record {
+    /// event | alert | verdict
+    pub kind: &'static str,
+    pub task_id: Option<String>,
+    pub timestamp: i64,
+    /// Event type, alert signature or verdict.
+    pub name: String,
+    /// Timeline category for events; "alert" / "verdict" otherwise.
+    pub category: String,
+    /// 0-100
+    pub severity: i32,
+    pub event_id: Option<i32>,
+    pub pid: Option<i32>,
+    pub ppid: Option<i32>,
+    pub process_name: Option<String>,
+    pub details: String,
+    pub src_ip: Option<String>,
+    pub dest_ip: Option<String>,
+    pub dest_port: Option<i32>,
+}
+
+impl Record {
+    pub fn event(event_type: &str, details: &str, timestamp: i64) -> Self {
+        Record {
+            kind: "event",
+            name: event_type.to_string(),
+            category: crate::timeline::category_of(event_type).to_string(),
+            details: details.to_string(),
+            timestamp,
+            ..Default::default()
+        }
+    }
+}
+
+struct Filter {
+    categories: Option<Vec<String>>,
+    min_severity: i32,
+}
+
+static QUEUE: OnceLock<(mpsc::Sender<Record>, Filter)> = OnceLock::new();
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Queues a record for every configured output; free when forwarding is off.
+pub fn forward(record: Record) {
+    let Some((tx, filter)) = QUEUE.get() else { return };
+    if record.kind == "event" && record.severity < filter.min_severity {
+        return;
+    }
+    if let Some(categories) = &filter.categories {
+        if !categories.contains(&record.category) {
+            return;
+        }
+    }
+    if tx.try_send(record).is_err() {
+        let dropped = DROPPED.fetch_add(1, Ordering::Relaxed) + 1;
+        if dropped % 1000 == 1 {
+            println!("[SIEM] Output is behind; {} records dropped so far", dropped);
+        }
+    }
+}
+
+enum Output {
+    Syslog { addr: String, tcp: bool, leef: bool },
+    Hec { url: String, token: String, index: Option<String> },
+    Elastic { url: String, index: String, api_key: Option<String> },
+}
+
+fn env(key: &str) -> Option<String> {
+    std::env::var(key).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+fn outputs() -> Vec<Output> {
+    let mut outputs = Vec::new();
+    for name in env("SIEM_OUTPUTS").unwrap_or_default().split(',').map(|s| s.trim().to_lowercase()) {
+        match name.as_str() {
+            "" => {}
+            "syslog" => match env("SIEM_SYSLOG_ADDR") {
+                Some(addr) => outputs.push(Output::Syslog {
+                    addr,
+                    tcp: env("SIEM_SYSLOG_PROTO").is_some_and(|p| p.eq_ignore_ascii_case("tcp")),
+                    leef: env("SIEM_SYSLOG_FORMAT").is_some_and(|f| f.eq_ignore_ascii_case("leef")),
+                }),
+                None => println!("[SIEM] syslog output needs SIEM_SYSLOG_ADDR; skipped"),
+            },
+            "hec" | "splunk" => match (env("SIEM_HEC_URL"), env("SIEM_HEC_TOKEN")) {
+                (Some(url), Some(token)) => outputs.push(Output::Hec { url, token, index: env("SIEM_HEC_INDEX") }),
+                _ => println!("[SIEM] hec output needs SIEM_HEC_URL and SIEM_HEC_TOKEN; skipped"),
+            },
+            "elastic" | "elasticsearch" => match env("SIEM_ELASTIC_URL") {
+                Some(url) => outputs.push(Output::Elastic {
+                    url: url.trim_end_matches('/').to_string(),
+                    index: env("SIEM_ELASTIC_INDEX").unwrap_or_else(|| "voodoobox-events".to_string()),
+                    api_key: env("SIEM_ELASTIC_API_KEY"),
+                }),
+                None => println!("[SIEM] elastic output needs SIEM_ELASTIC_URL; skipped"),
+            },
+            other => println!("[SIEM] Unknown output '{}' ignored", other),
+        }
+    }
+    outputs
+}
+
+/// Starts the forwarding worker when at least one output is configured.
+pub fn start() {
+    let outputs = outputs();
+    if outputs.is_empty() {
+        return;
+    }
+    let filter = Filter {
+        categories: env("SIEM_CATEGORIES")
+            .filter(|c| !c.eq_ignore_ascii_case("all"))
+            .map(|c| c.split(',').map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty()).collect()),
+        min_severity: env("SIEM_MIN_SEVERITY").and_then(|v| v.parse().ok()).unwrap_or(0),
+    };
+    let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+    if QUEUE.set((tx, filter)).is_err() {
+        return;
+    }
+    println!("[SIEM] Forwarding to {} output(s)", outputs.len());
+    tokio::spawn(run(outputs, rx));
+}
+
+async fn run(outputs: Vec<Output>, mut rx: mpsc::Receiver<Record>) {
+    let http = reqwest::Client::builder()
+        .danger_accept_invalid_certs(env("SIEM_VERIFY_TLS").is_some_and(|v| v == "false" || v == "0"))
+        .timeout(Duration::from_secs(15))
+        .build()
+        .unwrap_or_default();
+    let host = env("HOSTNAME").unwrap_or_else(|| "voodoobox".to_string());
+    let mut tcp: Option<tokio::net::TcpStream> = None;
+    let udp = tokio::net::UdpSocket::bind("0.0.0.0:0").await.ok();
+
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    loop {
+        // Block for the first record, then take whatever else arrives within the flush window
+        match rx.recv().await {
+            Some(record) => batch.push(record),
+            None => return,
+        }
+        let deadline = tokio::time::Instant::now() + FLUSH_INTERVAL;
+        while batch.len() < BATCH_SIZE {
+            match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(record)) => batch.push(record),
+                _ => break,
+            }
+        }
+
+        for output in &outputs {
+            let result = match output {
+                Output::Syslog { addr, tcp: use_tcp, leef } => {
+                    let lines: Vec<String> = batch.iter().map(|r| syslog_line(&host, r, *leef)).collect();
+                    if *use_tcp {
+                        send_tcp(&mut tcp, addr, &lines).await
+                    } else {
+                        send_udp(udp.as_ref(), addr, &lines).await
+                    }
+                }
+                Output::Hec { url, token, index } => {
+                    let body: String = batch.iter().map(|r| {
+                        let mut event = serde_json::json!({
+                            "time": r.timestamp as f64 / 1000.0,
+                            "host": host,
+                            "source": "voodoobox",
+                            "sourcetype": format!("voodoobox:{}", r.kind),
+                            "event": r,
+                        });
+                        if let Some(index) = index {
+                            event["index"] = serde_json::json!(index);
+                        }
+                        event.to_string()
+                    }).collect::<Vec<_>>().join("\n");
+                    post(http.post(url).header("Authorization", format!("Splunk {}", token)).body(body)).await
+                }
+                Output::Elastic { url, index, api_key } => {
+                    let mut body = String::new();
+                    for r in &batch {
+                        let mut doc = serde_json::to_value(r).unwrap_or_default();
+                        doc["@timestamp"] = serde_json::json!(chrono::DateTime::from_timestamp_millis(r.timestamp).unwrap_or_default().to_rfc3339());
+                        body.push_str(&serde_json::json!({ "index": { "_index": index } }).to_string());
+                        body.push('\n');
+                        body.push_str(&doc.to_string());
+                        body.push('\n');
+                    }
+                    let mut req = http.post(format!("{}/_bulk", url)).header("Content-Type", "application/x-ndjson").body(body);
+                    if let Some(key) = api_key {
+                        req = req.header("Authorization", format!("ApiKey {}", key));
+                    }
+                    post(req).await
+                }
+            };
+            if let Err(e) = result {
+                println!("[SIEM] Failed to forward {} record(s): {}", batch.len(), e);
+            }
+        }
+        batch.clear();
+    }
+}
+
+async fn post(req: reqwest::RequestBuilder) -> Result<(), String> {
+    let resp = req.send().await.map_err(|e| e.to_string())?;
+    if resp.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("HTTP {}", resp.status()))
+    }
+}
+
+async fn send_udp(socket: Option<&tokio::net::UdpSocket>, addr: &str, lines: &[String]) -> Result<(), String> {
+    let socket = socket.ok_or("no UDP socket")?;
+    for line in lines {
+        socket.send_to(line.as_bytes(), addr).await.map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+async fn send_tcp(stream: &mut Option<tokio::net::TcpStream>, addr: &str, lines: &[String]) -> Result<(), String> {
+    let mut payload = lines.join("\n");
+    payload.push('\n');
+    // One reconnect per batch covers a restarted collector
+    for _ in 0..2 {
+        if stream.is_none() {
+            *stream = tokio::net::TcpStream::connect(addr).await.ok();
+        }
+        let Some(s) = stream.as_mut() else { continue };
+        if s.write_all(payload.as_bytes()).await.is_ok() {
+            return Ok(());
+        }
+        *stream = None;
+    }
+    Err(format!("could not deliver to {}", addr))
+}
+
+/// RFC 5424 framing (facility local0) around a CEF or LEEF payload.
+fn syslog_line(host: &str, r: &Record, leef: bool) -> String {
+    let severity = match r.severity {
+        s if s >= 80 => 2,
+        s if s >= 60 => 3,
+        s if s >= 40 => 4,
+        s if s >= 20 => 5,
+        _ => 6,
+    };
+    let time = chrono::DateTime::from_timestamp_millis(r.timestamp).unwrap_or_default().to_rfc3339();
+    let payload = if leef { leef_payload(r) } else { cef_payload(r) };
+    format!("<{}>1 {} {} voodoobox - - - {}", 16 * 8 + severity, time, host, payload)
+}
+
+fn cef_header(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+fn cef_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('=', "\\=").replace('\r', "").replace('\n', "\\n")
+}
+
+fn extensions(r: &Record) -> Vec<(&'static str, String)> {
+    let mut ext = vec![("rt", r.timestamp.to_string()), ("cat", r.category.clone())];
+    if let Some(task_id) = &r.task_id {
+        ext.push(("cs1Label", "taskId".to_string()));
+        ext.push(("cs1", task_id.clone()));
+    }
+    if let Some(id) = r.event_id {
+        ext.push(("externalId", id.to_string()));
+    }
+    if let Some(pid) = r.pid {
+        ext.push(("spid", pid.to_string()));
+    }
+    if let Some(ppid) = r.ppid {
+        ext.push(("cn1Label", "parentPid".to_string()));
+        ext.push(("cn1", ppid.to_string()));
+    }
+    if let Some(name) = &r.process_name {
+        ext.push(("sproc", name.clone()));
+    }
+    if let Some(src) = &r.src_ip {
+        ext.push(("src", src.clone()));
+    }
+    if let Some(dst) = &r.dest_ip {
+        ext.push(("dst", dst.clone()));
+    }
+    if let Some(port) = r.dest_port {
+        ext.push(("dpt", port.to_string()));
+    }
+    ext.push(("msg", r.details.chars().take(2000).collect()));
+    ext
+}
+
+fn cef_payload(r: &Record) -> String {
+    let ext: Vec<String> = extensions(r).into_iter().map(|(k, v)| format!("{}={}", k, cef_value(&v))).collect();
+    format!(
+        "CEF:0|{}|{}|1.0|{}|{}|{}|{}",
+        VENDOR, PRODUCT, cef_header(&r.name), cef_header(&format!("{} {}", r.kind, r.name)),
+        (r.severity / 10).clamp(0, 10), ext.join(" ")
+    )
+}
+
+fn leef_payload(r: &Record) -> String {
+    let mut ext: Vec<String> = extensions(r).into_iter()
+        .map(|(k, v)| format!("{}={}", k, v.replace(['\t', '\r', '\n'], " ")))
+        .collect();
+    ext.push(format!("sev={}", (r.severity / 10).clamp(1, 10)));
+    format!("LEEF:1.0|{}|{}|1.0|{}|{}", VENDOR, PRODUCT, r.name.replace('|', " "), ext.join("\t"))
+}
@@ -0,0 +1,917 @@
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+use crate::alerts::NewAlert;
+use crate::stream::Broadcaster;
+
+// --- SIGMA RULES ---
+
+/// Sigma logsource category -> event types it covers ('*' suffix = prefix).
+const LOGSOURCES: &[(&str, &[&str])] = &[
+    ("process_creation", &["PROCESS_CREATE"]),
+    ("network_connection", &["NETWORK_CONNECT", "LATERAL_MOVEMENT"]),
+    ("dns_query", &["NETWORK_DNS"]),
+    ("dns", &["NETWORK_DNS"]),
+    ("proxy", &["HTTP_REQUEST", "NETWORK_HTTP"]),
+    ("file_event", &["FILE_*", "DOWNLOAD_DETECTED"]),
+    ("file_change", &["FILE_*"]),
+    ("file_delete", &["FILE_REMOVE*", "FILE_DELETE*"]),
+    ("registry_event", &["REG*"]),
+    ("registry_set", &["REG*"]),
+    ("registry_add", &["REG*"]),
+    ("registry_delete", &["REG*"]),
+    ("image_load", &["IMAGE_LOAD"]),
+    ("process_access", &["PROCESS_ACCESS"]),
+    ("process_tampering", &["PROCESS_TAMPER"]),
+    ("create_remote_thread", &["REMOTE_THREAD"]),
+    ("create_stream_hash", &["ADS_CREATED"]),
+    ("wmi_event", &["WMI_SUBSCRIPTION"]),
+];
+
+/// Seeded the first time, the same way the severity table is.
+const DEFAULT_RULES: &[&str] = &[
+    r#"title: Encoded PowerShell Command Line
+id: 3b6ab547-8ec2-4991-b9d2-2b06702a48d7
+status: stable
+description: PowerShell started with a base64-encoded command.
+references:
+    - https://attack.mitre.org/techniques/T1059/001/
+tags:
+    - attack.execution
+    - attack.t1059.001
+logsource:
+    category: process_creation
+    product: windows
+detection:
+    selection_img:
+        Image|endswith:
+            - '\powershell.exe'
+            - '\pwsh.exe'
+    selection_cli:
+        CommandLine|contains:
+            - ' -enc '
+            - ' -ec '
+            - ' -encodedcommand '
+    condition: all of selection_*
+level: high
+"#,
+    r#"title: Shadow Copy Deletion
+id: 9f1b1d0c-6a3e-4c44-9d1e-5f0f3f3c2a11
+status: stable
+description: Volume shadow copies deleted, as ransomware does before encrypting.
+references:
+    - https://attack.mitre.org/techniques/T1490/
+tags:
+    - attack.impact
+    - attack.t1490
+logsource:
+    category: process_creation
+    product: windows
+detection:
+    vssadmin:
+        CommandLine|contains|all:
+            - 'vssadmin'
+            - 'delete'
+            - 'shadows'
+    wmic:
+        CommandLine|contains|all:
+            - 'wmic'
+            - 'shadowcopy'
+            - 'delete'
+    condition: 1 of them
+level: critical
+"#,
+    r#"title: Certutil Download
+id: 1c9a4e57-3d1f-4b5e-8a42-7c0b2f6d9e30
+status: stable
+description: certutil used to fetch a remote file.
+references:
+    - https://attack.mitre.org/techniques/T1105/
+tags:
+    - attack.command_and_control
+    - attack.t1105
+logsource:
+    category: process_creation
+    product: windows
+detection:
+    selection:
+        CommandLine|contains|all:
+            - 'certutil'
+            - 'urlcache'
+            - 'http'
+    condition: selection
+level: high
+"#,
+    r#"title: LSASS Memory Access
+id: 5e2d8a61-0b7f-4c3a-9e15-2f4c6b8d1a73
+status: stable
+description: A process opened a handle to lsass.exe.
+references:
+    - https://attack.mitre.org/techniques/T1003/001/
+tags:
+    - attack.credential_access
+    - attack.t1003.001
+logsource:
+    category: process_access
+    product: windows
+detection:
+    selection:
+        Details|contains: 'lsass'
+    filter:
+        Image|endswith: '\lsass.exe'
+    condition: selection and not filter
+level: high
+"#,
+    r#"title: Run Key Persistence
+id: 7a3f9c24-5d8e-4b16-a0c7-3e9d2f1b6c58
+status: stable
+description: A value written under a Run / RunOnce key.
+references:
+    - https://attack.mitre.org/techniques/T1547/001/
+tags:
+    - attack.persistence
+    - attack.t1547.001
+logsource:
+    category: registry_set
+    product: windows
+detection:
+    selection:
+        TargetObject|contains:
+            - '\CurrentVersion\Run\'
+            - '\CurrentVersion\RunOnce\'
+    condition: selection
+level: medium
+"#,
+    r#"title: Defender Tampering
+id: 2d7e4b90-8c1a-4f35-b6e2-9a0c5d3f7e14
+status: stable
+description: The agent saw Defender settings or exclusions being changed.
+references:
+    - https://attack.mitre.org/techniques/T1562/001/
+tags:
+    - attack.defense_evasion
+    - attack.t1562.001
+logsource:
+    product: windows
+detection:
+    selection:
+        EventType|startswith: 'DEFENDER_'
+    condition: selection
+level: high
+"#,
+];
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+pub struct SigmaRule {
+    pub id: i32,
+    /// The rule's Sigma `id`, or a generated one when it had none.
+    pub rule_id: String,
+    pub title: String,
+    pub level: String,
+    pub tags: Vec<String>,
+    pub yaml: String,
+    pub enabled: bool,
+    pub created_at: i64,
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct SigmaRuleRequest {
+    /// The rule as Sigma YAML; required when creating. A rule whose `id` is already stored replaces it.
+    pub yaml: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+/// Event fields the engine reads; built by each ingest path from what it just stored.
+pub struct EventRef<'a> {
+    pub id: Option<i32>,
+    pub task_id: Option<&'a str>,
+    pub event_type: &'a str,
+    pub process_id: i32,
+    pub parent_process_id: i32,
+    pub process_name: &'a str,
+    pub details: &'a str,
+    pub decoded_details: Option<&'a str>,
+    pub category: Option<&'a str>,
+    pub timestamp: i64,
+}
+
+enum Pattern {
+    Null,
+    Exact(String),
+    Contains(String),
+    StartsWith(String),
+    EndsWith(String),
+    /// Built from a value with inner wildcards; runs on the lowercased value.
+    Wildcard(Regex),
+    /// `|re`, case-sensitive.
+    Regex(Regex),
+}
+
+struct FieldTest {
+    field: String,
+    patterns: Vec<Pattern>,
+    /// `|all`: every pattern must match, instead of any.
+    all: bool,
+}
+
+enum Search {
+    /// Maps OR'ed together, each an AND of its field tests.
+    Fields(Vec<Vec<FieldTest>>),
+    /// Bare values looked for anywhere in the event.
+    Keywords(Vec<Pattern>),
+}
+
+enum Condition {
+    Search(String),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    AnyOf(Vec<String>),
+    AllOf(Vec<String>),
+}
+
+struct Rule {
+    rule_id: String,
+    title: String,
+    level: String,
+    severity: i32,
+    tags: Vec<String>,
+    references: Vec<String>,
+    /// Empty: any event type.
+    event_types: &'static [&'static str],
+    searches: HashMap<String, Search>,
+    condition: Condition,
+}
+
+fn loaded() -> &'static RwLock<Arc<Vec<Rule>>> {
+    static RULES: OnceLock<RwLock<Arc<Vec<Rule>>>> = OnceLock::new();
+    RULES.get_or_init(|| RwLock::new(Arc::new(Vec::new())))
+}
+
+fn rules() -> Arc<Vec<Rule>> {
+    loaded().read().map(|r| Arc::clone(&r)).unwrap_or_default()
+}
+
+pub fn level_severity(level: &str) -> Option<i32> {
+    match level {
+        "informational" => Some(10),
+        "low" => Some(30),
+        "medium" => Some(50),
+        "high" => Some(75),
+        "critical" => Some(90),
+        _ => None,
+    }
+}
+
+fn strings(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::Sequence(items)) => items.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+        Some(Value::String(s)) => vec![s.clone()],
+        _ => Vec::new(),
+    }
+}
+
+/// Parses and checks a rule. The Sigma id is left empty when the rule has none.
+fn compile(yaml: &str) -> Result<Rule, String> {
+    let doc: Value = serde_yaml::from_str(yaml).map_err(|e| format!("Invalid YAML: {}", e))?;
+    if doc.get("correlation").is_some() {
+        return Err("Correlation rules are not supported".to_string());
+    }
+    let title = doc.get("title").and_then(Value::as_str).map(str::trim).filter(|t| !t.is_empty())
+        .ok_or("Rule has no title")?;
+    let level = doc.get("level").and_then(Value::as_str).unwrap_or("medium").trim().to_lowercase();
+    let severity = level_severity(&level).ok_or_else(|| format!("Unknown level '{}'", level))?;
+
+    let event_types: &'static [&'static str] = match doc.get("logsource").and_then(|l| l.get("category")).and_then(Value::as_str) {
+        Some(category) => LOGSOURCES.iter()
+            .find(|(c, _)| c.eq_ignore_ascii_case(category))
+            .map(|(_, types)| *types)
+            .ok_or_else(|| format!("No telemetry for logsource category '{}'", category))?,
+        None => &[],
+    };
+
+    let detection = doc.get("detection").and_then(Value::as_mapping).ok_or("Rule has no detection")?;
+    let mut searches = HashMap::new();
+    let mut condition_src = None;
+    for (key, value) in detection {
+        let key = key.as_str().ok_or("Detection keys must be strings")?;
+        match key {
+            "condition" => condition_src = Some(value),
+            "timeframe" => return Err("timeframe is not supported".to_string()),
+            _ => {
+                searches.insert(key.to_string(), search(value).map_err(|e| format!("{}: {}", key, e))?);
+            }
+        }
+    }
+    let condition_src = match condition_src {
+        Some(Value::String(s)) => s.clone(),
+        // A list of conditions means any of them
+        Some(Value::Sequence(items)) if !items.is_empty() => items.iter()
+            .map(|c| c.as_str().map(|c| format!("({})", c)).ok_or("Conditions must be strings"))
+            .collect::<Result<Vec<_>, _>>()?
+            .join(" or "),
+        _ => return Err("Detection has no condition".to_string()),
+    };
+    let condition = parse_condition(&condition_src, &searches)?;
+
+    Ok(Rule {
+        rule_id: doc.get("id").and_then(Value::as_str).map(str::trim).unwrap_or_default().to_string(),
+        title: title.to_string(),
+        level,
+        severity,
+        tags: strings(doc.get("tags")),
+        references: strings(doc.get("references")),
+        event_types,
+        searches,
+        condition,
+    })
+}
+
+fn search(value: &Value) -> Result<Search, String> {
+    match value {
+        Value::Mapping(map) => Ok(Search::Fields(vec![field_tests(map)?])),
+        Value::Sequence(items) if items.iter().all(Value::is_mapping) => items.iter()
+            .filter_map(Value::as_mapping)
+            .map(field_tests)
+            .collect::<Result<Vec<_>, _>>()
+            .map(Search::Fields),
+        Value::Sequence(items) => items.iter()
+            .map(|v| pattern(v, Some("contains")))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Search::Keywords),
+        Value::String(_) => Ok(Search::Keywords(vec![pattern(value, Some("contains"))?])),
+        _ => Err("expected a map, a list or a keyword".to_string()),
+    }
+}
+
+fn field_tests(map: &serde_yaml::Mapping) -> Result<Vec<FieldTest>, String> {
+    let mut tests = Vec::new();
+    for (key, value) in map {
+        let key = key.as_str().ok_or("field names must be strings")?;
+        let mut parts = key.split('|');
+        let field = parts.next().unwrap_or_default().trim().to_string();
+        if field.is_empty() {
+            return Err(format!("'{}' has no field name", key));
+        }
+        let mut all = false;
+        let mut modifier = None;
+        for m in parts {
+            match m {
+                "all" => all = true,
+                "contains" | "startswith" | "endswith" | "re" if modifier.is_none() => modifier = Some(m),
+                _ => return Err(format!("unsupported modifier '{}'", m)),
+            }
+        }
+        let patterns = match value {
+            Value::Sequence(items) => items.iter().map(|v| pattern(v, modifier)).collect::<Result<Vec<_>, _>>()?,
+            v => vec![pattern(v, modifier)?],
+        };
+        tests.push(FieldTest { field, patterns, all });
+    }
+    Ok(tests)
+}
+
+fn pattern(value: &Value, modifier: Option<&str>) -> Result<Pattern, String> {
+    let text = match value {
+        Value::Null => return Ok(Pattern::Null),
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        _ => return Err("values must be scalars".to_string()),
+    };
+    if modifier == Some("re") {
+        return Regex::new(&text).map(Pattern::Regex).map_err(|e| format!("bad regex: {}", e));
+    }
+    let glob = match modifier {
+        Some("contains") => format!("*{}*", text),
+        Some("startswith") => format!("{}*", text),
+        Some("endswith") => format!("*{}", text),
+        _ => text,
+    }
+    .to_lowercase();
+
+    let leading = glob.starts_with('*');
+    let trailing = glob.len() > 1 && glob.ends_with('*');
+    let inner = glob.trim_start_matches('*').trim_end_matches('*');
+    if inner.contains(['*', '?']) {
+        let body: String = glob.chars().map(|c| match c {
+            '*' => ".*".to_string(),
+            '?' => ".".to_string(),
+            c => regex::escape(&c.to_string()),
+        }).collect();
+        return Regex::new(&format!("(?s)^{}$", body)).map(Pattern::Wildcard).map_err(|e| format!("bad wildcard: {}", e));
+    }
+    let inner = inner.to_string();
+    Ok(match (leading, trailing) {
+        (true, true) => Pattern::Contains(inner),
+        (true, false) => Pattern::EndsWith(inner),
+        (false, true) => Pattern::StartsWith(inner),
+        (false, false) => Pattern::Exact(inner),
+    })
+}
+
+struct ConditionParser<'a> {
+    tokens: Vec<String>,
+    pos: usize,
+    names: Vec<&'a String>,
+}
+
+fn parse_condition(src: &str, searches: &HashMap<String, Search>) -> Result<Condition, String> {
+    if src.contains('|') {
+        return Err("Aggregations in conditions are not supported".to_string());
+    }
+    let tokens = src.replace('(', " ( ").replace(')', " ) ").split_whitespace().map(str::to_string).collect();
+    let mut parser = ConditionParser { tokens, pos: 0, names: searches.keys().collect() };
+    let condition = parser.or()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(condition),
+        Some(t) => Err(format!("Unexpected '{}' in condition", t)),
+    }
+}
+
+impl ConditionParser<'_> {
+    fn next(&mut self) -> Option<String> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn eat(&mut self, word: &str) -> bool {
+        let hit = self.tokens.get(self.pos).is_some_and(|t| t.eq_ignore_ascii_case(word));
+        if hit {
+            self.pos += 1;
+        }
+        hit
+    }
+
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut left = self.and()?;
+        while self.eat("or") {
+            left = Condition::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut left = self.unary()?;
+        while self.eat("and") {
+            left = Condition::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Condition, String> {
+        let token = self.next().ok_or("Condition ends early")?;
+        match token.to_lowercase().as_str() {
+            "not" => Ok(Condition::Not(Box::new(self.unary()?))),
+            "(" => {
+                let inner = self.or()?;
+                if !self.eat(")") {
+                    return Err("Unbalanced parentheses in condition".to_string());
+                }
+                Ok(inner)
+            }
+            quantifier @ ("1" | "any" | "all") => {
+                let quantifier = quantifier.to_string();
+                if !self.eat("of") {
+                    return Err(format!("Expected 'of' after '{}'", quantifier));
+                }
+                let target = self.next().ok_or("Condition ends early")?;
+                let names = self.resolve(&target)?;
+                Ok(if quantifier == "all" { Condition::AllOf(names) } else { Condition::AnyOf(names) })
+            }
+            _ if self.names.iter().any(|n| **n == token) => Ok(Condition::Search(token)),
+            _ => Err(format!("Condition names unknown search '{}'", token)),
+        }
+    }
+
+    fn resolve(&self, target: &str) -> Result<Vec<String>, String> {
+        let names: Vec<String> = self.names.iter()
+            .filter(|n| target == "them" || match target.strip_suffix('*') {
+                Some(prefix) => n.starts_with(prefix),
+                None => n.as_str() == target,
+            })
+            .map(|n| n.to_string())
+            .collect();
+        if names.is_empty() {
+            return Err(format!("'{}' matches no search", target));
+        }
+        Ok(names)
+    }
+}
+
+struct Extractors {
+    command_line: Regex,
+    file_created: Regex,
+    registry_key: Regex,
+    registry_value: Regex,
+    dns_query: Regex,
+    destination: Regex,
+    url: Regex,
+}
+
+fn extractors() -> &'static Extractors {
+    static EXTRACTORS: OnceLock<Extractors> = OnceLock::new();
+    EXTRACTORS.get_or_init(|| Extractors {
+        // "SYSMON: CMD: <cmd> | User: x" and "New process: x Cmd: [..]"
+        command_line: Regex::new(r"(?i)\bCMD: (.*?)(?: \| User:.*)?$").unwrap(),
+        file_created: Regex::new(r"(?i)File Created: (.+)$").unwrap(),
+        registry_key: Regex::new(r#"(?i)\b((?:HKLM|HKCU|HKCR|HKU|HKCC|HKEY_[A-Z_]+)\\[^'"\r\n|]*?)(?:\s+Value:|\s*['"|]|\s*$)"#).unwrap(),
+        registry_value: Regex::new(r"Value: '([^']*)'").unwrap(),
+        dns_query: Regex::new(r"(?i)\bDNS(?: Query Resolved)?:?\s+(?:[A-Z]+ -> )?([^\s|]+)").unwrap(),
+        destination: Regex::new(r"->\s*([^\s:]+):(\d+)").unwrap(),
+        url: Regex::new(r#"(?i)\b(?:https?|ftp)://[^\s'"<>|]+"#).unwrap(),
+    })
+}
+
+fn capture(re: &Regex, text: &str, group: usize) -> Option<String> {
+    re.captures(text).and_then(|c| c.get(group)).map(|m| m.as_str().trim().to_string())
+}
+
+/// Values an event offers for a Sigma field; empty when it has none.
+fn field_values(evt: &EventRef, field: &str) -> Vec<String> {
+    let x = extractors();
+    let details = evt.details;
+    match field.to_ascii_lowercase().as_str() {
+        "eventtype" | "event_type" => vec![evt.event_type.to_string()],
+        "image" | "originalfilename" | "processname" | "process_name" => {
+            // Agents often report a bare file name; `\name` keeps `|endswith: '\x.exe'` working
+            let mut v = vec![evt.process_name.to_string()];
+            if !evt.process_name.contains(['\\', '/']) {
+                v.push(format!("\\{}", evt.process_name));
+            }
+            v
+        }
+        "processid" => vec![evt.process_id.to_string()],
+        "category" => evt.category.map(str::to_string).into_iter().collect(),
+        "details" | "message" => vec![details.to_string()],
+        "commandline" => {
+            let mut v: Vec<String> = capture(&x.command_line, details, 1).into_iter().collect();
+            v.extend(evt.decoded_details.map(str::to_string));
+            v
+        }
+        "targetfilename" => capture(&x.file_created, details, 1)
+            .or_else(|| evt.event_type.starts_with("FILE_").then(|| details.to_string()))
+            .into_iter().collect(),
+        "targetobject" => capture(&x.registry_key, details, 1)
+            .map(|key| match capture(&x.registry_value, details, 1) {
+                Some(value) => format!("{}\\{}", key, value),
+                None => key,
+            })
+            .into_iter().collect(),
+        "queryname" => capture(&x.dns_query, details, 1).into_iter().collect(),
+        "destinationhostname" => capture(&x.dns_query, details, 1)
+            .or_else(|| capture(&x.destination, details, 1))
+            .into_iter().collect(),
+        "destinationip" => capture(&x.destination, details, 1).into_iter().collect(),
+        "destinationport" => capture(&x.destination, details, 2).into_iter().collect(),
+        "url" | "c-uri" => capture(&x.url, details, 0)
+            .or_else(|| evt.decoded_details.and_then(|d| capture(&x.url, d, 0)))
+            .into_iter().collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl Pattern {
+    /// `lower` is `value` lowercased; everything but `|re` compares against it.
+    fn matches(&self, value: &str, lower: &str) -> bool {
+        match self {
+            Pattern::Null => value.is_empty(),
+            Pattern::Exact(p) => lower == p,
+            Pattern::Contains(p) => lower.contains(p.as_str()),
+            Pattern::StartsWith(p) => lower.starts_with(p.as_str()),
+            Pattern::EndsWith(p) => lower.ends_with(p.as_str()),
+            Pattern::Wildcard(re) => re.is_match(lower),
+            Pattern::Regex(re) => re.is_match(value),
+        }
+    }
+}
+
+impl FieldTest {
+    fn matches(&self, evt: &EventRef) -> bool {
+        let values = field_values(evt, &self.field);
+        if values.is_empty() {
+            return self.patterns.iter().any(|p| matches!(p, Pattern::Null));
+        }
+        values.iter().any(|v| {
+            let lower = v.to_lowercase();
+            if self.all {
+                self.patterns.iter().all(|p| p.matches(v, &lower))
+            } else {
+                self.patterns.iter().any(|p| p.matches(v, &lower))
+            }
+        })
+    }
+}
+
+impl Search {
+    fn matches(&self, evt: &EventRef) -> bool {
+        match self {
+            Search::Fields(maps) => maps.iter().any(|tests| tests.iter().all(|t| t.matches(evt))),
+            Search::Keywords(patterns) => {
+                let text = format!("{} {} {}", evt.process_name, evt.details, evt.decoded_details.unwrap_or_default());
+                let lower = text.to_lowercase();
+                patterns.iter().any(|p| p.matches(&text, &lower))
+            }
+        }
+    }
+}
+
+impl Rule {
+    fn matches(&self, evt: &EventRef) -> bool {
+        let in_scope = self.event_types.is_empty() || self.event_types.iter().any(|t| match t.strip_suffix('*') {
+            Some(prefix) => evt.event_type.starts_with(prefix),
+            None => evt.event_type == *t,
+        });
+        in_scope && self.eval(&self.condition, evt)
+    }
+
+    fn eval(&self, condition: &Condition, evt: &EventRef) -> bool {
+        let search = |name: &String| self.searches.get(name).is_some_and(|s| s.matches(evt));
+        match condition {
+            Condition::Search(name) => search(name),
+            Condition::Not(inner) => !self.eval(inner, evt),
+            Condition::And(a, b) => self.eval(a, evt) && self.eval(b, evt),
+            Condition::Or(a, b) => self.eval(a, evt) || self.eval(b, evt),
+            Condition::AnyOf(names) => names.iter().any(search),
+            Condition::AllOf(names) => names.iter().all(search),
+        }
+    }
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS sigma_rules (
+            id SERIAL PRIMARY KEY,
+            rule_id TEXT NOT NULL UNIQUE,
+            title TEXT NOT NULL,
+            level TEXT NOT NULL,
+            tags TEXT[] NOT NULL DEFAULT '{}',
+            yaml TEXT NOT NULL,
+            enabled BOOLEAN NOT NULL DEFAULT TRUE,
+            created_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sigma_rules")
+        .fetch_one(pool)
+        .await?;
+    if count == 0 {
+        for yaml in DEFAULT_RULES {
+            match compile(yaml) {
+                Ok(rule) => {
+                    upsert(pool, &rule, yaml, None).await?;
+                }
+                Err(e) => println!("[SIGMA] Built-in rule does not compile: {}", e),
+            }
+        }
+        println!("[SIGMA] Seeded {} default Sigma rules.", DEFAULT_RULES.len());
+    }
+
+    if let Ok(dir) = std::env::var("SIGMA_RULES_DIR") {
+        import_dir(pool, &dir).await;
+    }
+
+    let loaded = reload(pool).await?;
+    println!("[SIGMA] Database initialized (sigma_rules), {} rules active.", loaded);
+    Ok(())
+}
+
+async fn import_dir(pool: &Pool<Postgres>, dir: &str) {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(e) => e,
+        Err(e) => {
+            println!("[SIGMA] Cannot read SIGMA_RULES_DIR {}: {}", dir, e);
+            return;
+        }
+    };
+    let mut imported = 0;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        if !matches!(path.extension().and_then(|e| e.to_str()), Some("yml" | "yaml")) {
+            continue;
+        }
+        let Ok(yaml) = tokio::fs::read_to_string(&path).await else { continue };
+        match compile(&yaml) {
+            Ok(rule) => match upsert(pool, &rule, &yaml, None).await {
+                Ok(_) => imported += 1,
+                Err(e) => println!("[SIGMA] Failed to store {}: {}", path.display(), e),
+            },
+            Err(e) => println!("[SIGMA] Skipping {}: {}", path.display(), e),
+        }
+    }
+    println!("[SIGMA] Imported {} rules from {}.", imported, dir);
+}
+
+/// Upserts by Sigma id; `enabled` = None keeps the current state.
+async fn upsert(pool: &Pool<Postgres>, rule: &Rule, yaml: &str, enabled: Option<bool>) -> Result<SigmaRule, sqlx::Error> {
+    let rule_id = if rule.rule_id.is_empty() { uuid::Uuid::new_v4().to_string() } else { rule.rule_id.clone() };
+    sqlx::query_as::<_, SigmaRule>(
+        "INSERT INTO sigma_rules (rule_id, title, level, tags, yaml, enabled, created_at)
+         VALUES ($1, $2, $3, $4, $5, COALESCE($6, TRUE), $7)
+         ON CONFLICT (rule_id) DO UPDATE SET
+            title = EXCLUDED.title,
+            level = EXCLUDED.level,
+            tags = EXCLUDED.tags,
+            yaml = EXCLUDED.yaml,
+            enabled = COALESCE($6, sigma_rules.enabled)
+         RETURNING *"
+    )
+    .bind(&rule_id)
+    .bind(&rule.title)
+    .bind(&rule.level)
+    .bind(&rule.tags)
+    .bind(yaml)
+    .bind(enabled)
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_one(pool)
+    .await
+}
+
+/// A rule that is not stored, compiled and run over some events.
+pub struct Tested {
+    /// Empty when the rule has no Sigma id.
+    pub rule_id: String,
+    pub title: String,
+    pub level: String,
+    /// How many of the events it matched.
+    pub matched: usize,
+}
+
+/// Checks a rule the way add_sigma_rule does and counts the `events` it matches.
+pub fn test(yaml: &str, events: &[EventRef]) -> Result<Tested, String> {
+    let rule = compile(yaml)?;
+    let matched = events.iter().filter(|evt| rule.matches(evt)).count();
+    Ok(Tested { rule_id: rule.rule_id, title: rule.title, level: rule.level, matched })
+}
+
+/// What rules can use here, for the AI drafting them.
+pub fn capabilities() -> String {
+    let categories: Vec<&str> = LOGSOURCES.iter().map(|(category, _)| *category).collect();
+    format!(
+        "Logsource categories: {}. Fields: Image, CommandLine, TargetObject (registry key\\value), TargetFilename, QueryName, \
+         DestinationHostname, DestinationIp, DestinationPort, Url, Details (the raw event text), EventType; other fields never match. \
+         Modifiers: contains, startswith, endswith, re, all. No aggregations, timeframe or correlation rules.",
+        categories.join(", ")
+    )
+}
+
+/// Recompiles the enabled rules and swaps them in; returns how many are active.
+pub async fn reload(pool: &Pool<Postgres>) -> Result<usize, sqlx::Error> {
+    let rows: Vec<(String, String)> = sqlx::query_as("SELECT rule_id, yaml FROM sigma_rules WHERE enabled ORDER BY id")
+        .fetch_all(pool)
+        .await?;
+    let mut compiled = Vec::with_capacity(rows.len());
+    for (rule_id, yaml) in rows {
+        match compile(&yaml) {
+            Ok(mut rule) => {
+                rule.rule_id = rule_id;
+                compiled.push(rule);
+            }
+            Err(e) => println!("[SIGMA] Rule {} no longer compiles: {}", rule_id, e),
+        }
+    }
+    let count = compiled.len();
+    if let Ok(mut current) = loaded().write() {
+        *current = Arc::new(compiled);
+    }
+    Ok(count)
+}
+
+/// Runs the active rules over stored events, skipping control events.
+pub async fn evaluate(pool: &Pool<Postgres>, broadcaster: Option<&Broadcaster>, events: &[EventRef<'_>]) {
+    let rules = rules();
+    if rules.is_empty() {
+        return;
+    }
+    let mut hits = Vec::new();
+    for evt in events {
+        if evt.event_type == "SESSION_INIT" || evt.event_type.starts_with("AGENT_") {
+            continue;
+        }
+        for rule in rules.iter().filter(|r| r.matches(evt)) {
+            hits.push(NewAlert {
+                task_id: evt.task_id.map(str::to_string),
+                event_id: evt.id,
+                source: "sigma",
+                rule_id: rule.rule_id.clone(),
+                rule_title: rule.title.clone(),
+                level: rule.level.clone(),
+                severity: rule.severity,
+                tags: rule.tags.clone(),
+                rule_references: rule.references.clone(),
+                process_id: evt.process_id,
+                process_name: evt.process_name.to_string(),
+                details: evt.details.to_string(),
+                timestamp: evt.timestamp,
+            });
+        }
+    }
+    if !hits.is_empty() {
+        crate::alerts::record(pool, broadcaster, hits).await;
+    }
+}
+
+#[utoipa::path(tag = "detections", responses((status = 200, description = "Success")))]
+#[get("/settings/sigma-rules")]
+pub async fn list_sigma_rules(pool: web::Data<Pool<Postgres>>) -> impl Responder {
+    match sqlx::query_as::<_, SigmaRule>("SELECT * FROM sigma_rules ORDER BY id").fetch_all(pool.get_ref()).await {
+        Ok(rules) => HttpResponse::Ok().json(rules),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Adds a rule; one whose Sigma `id` is already stored is replaced.
+#[utoipa::path(tag = "detections", request_body = SigmaRuleRequest, responses(
+    (status = 200, description = "Rule stored and active"),
+    (status = 400, description = "Rule does not parse or uses unsupported Sigma features"),
+))]
+#[post("/settings/sigma-rules")]
+pub async fn add_sigma_rule(pool: web::Data<Pool<Postgres>>, req: web::Json<SigmaRuleRequest>) -> impl Responder {
+    let Some(yaml) = req.yaml.as_deref().filter(|y| !y.trim().is_empty()) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "yaml is required" }));
+    };
+    let rule = match compile(yaml) {
+        Ok(r) => r,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+    match upsert(pool.get_ref(), &rule, yaml, req.enabled).await {
+        Ok(stored) => {
+            let _ = reload(pool.get_ref()).await;
+            HttpResponse::Ok().json(stored)
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Replaces a rule's YAML and/or turns it on or off.
+#[utoipa::path(tag = "detections", request_body = SigmaRuleRequest, responses(
+    (status = 200, description = "Rule updated"),
+    (status = 400, description = "Rule does not parse or uses unsupported Sigma features"),
+    (status = 404, description = "Rule not found"),
+))]
+#[post("/settings/sigma-rules/{id}")]
+pub async fn update_sigma_rule(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<i32>,
+    req: web::Json<SigmaRuleRequest>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let parsed = match req.yaml.as_deref() {
+        Some(yaml) => match compile(yaml) {
+            Ok(r) => Some(r),
+            Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+        },
+        None => None,
+    };
+
+    // The stored rule_id stays, so an edit cannot collide with another rule
+    let result = sqlx::query_as::<_, SigmaRule>(
+        "UPDATE sigma_rules SET
+            title = COALESCE($2, title),
+            level = COALESCE($3, level),
+            tags = COALESCE($4, tags),
+            yaml = COALESCE($5, yaml),
+            enabled = COALESCE($6, enabled)
+         WHERE id = $1 RETURNING *"
+    )
+    .bind(id)
+    .bind(parsed.as_ref().map(|r| r.title.clone()))
+    .bind(parsed.as_ref().map(|r| r.level.clone()))
+    .bind(parsed.as_ref().map(|r| r.tags.clone()))
+    .bind(&req.yaml)
+    .bind(req.enabled)
+    .fetch_optional(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(Some(stored)) => {
+            let _ = reload(pool.get_ref()).await;
+            HttpResponse::Ok().json(stored)
+        }
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({ "error": "Sigma rule not found" })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "detections", responses((status = 200, description = "Success")))]
+#[delete("/settings/sigma-rules/{id}")]
+pub async fn delete_sigma_rule(pool: web::Data<Pool<Postgres>>, path: web::Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    match sqlx::query("DELETE FROM sigma_rules WHERE id = $1").bind(id).execute(pool.get_ref()).await {
+        Ok(res) if res.rows_affected() == 0 => {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": "Sigma rule not found" }))
+        }
+        Ok(_) => {
+            let _ = reload(pool.get_ref()).await;
+            HttpResponse::Ok().json(serde_json::json!({ "status": "deleted", "id": id }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
@@ -0,0 +1,65 @@
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::progress_stream::ProgressBroadcaster;
+use crate::{ai_analysis, AgentManager, AIManager};
+
+// --- STATIC-ONLY ANALYSIS ---
+// analysis_mode "static" skips the sandbox.
+
+pub const MODE: &str = "static";
+
+fn wait_limit() -> Duration {
+    let secs = std::env::var("STATIC_WAIT_SECS").ok().and_then(|v| v.trim().parse().ok()).unwrap_or(600);
+    Duration::from_secs(secs)
+}
+
+/// True once neither Ghidra nor REMnux is still working on the sample.
+async fn settled(pool: &Pool<Postgres>, task_id: &str) -> bool {
+    let row = sqlx::query_as::<_, (Option<String>, Option<String>)>("SELECT ghidra_status, remnux_status FROM tasks WHERE id = $1")
+        .bind(task_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten();
+    let Some((ghidra, remnux)) = row else { return true };
+    let ghidra_busy = matches!(ghidra.as_deref(), None | Some("Not Started") | Some("Analysis Running"));
+    let remnux_busy = matches!(remnux.as_deref(), None | Some("Staging File") | Some("Analyzing"));
+    !ghidra_busy && !remnux_busy
+}
+
+pub async fn run(
+    pool: Pool<Postgres>,
+    ai_manager: AIManager,
+    manager: Arc<AgentManager>,
+    progress: Arc<ProgressBroadcaster>,
+    task_id: String,
+) {
+    println!("[STATIC] Task {}: static-only analysis, no detonation.", task_id);
+    let _ = sqlx::query("UPDATE tasks SET status='Static Analysis' WHERE id=$1")
+        .bind(&task_id).execute(&pool).await;
+    progress.send_progress(&task_id, "static", "Running static analysis (VirusTotal, Ghidra, REMnux)", 20);
+
+    let deadline = tokio::time::Instant::now() + wait_limit();
+    while !settled(&pool, &task_id).await {
+        if tokio::time::Instant::now() >= deadline {
+            println!("[STATIC] Task {}: static tools still running at the deadline; reporting with what is there.", task_id);
+            break;
+        }
+        tokio::time::sleep(Duration::from_secs(10)).await;
+    }
+
+    progress.send_progress(&task_id, "ai_analysis", "Generating report from static evidence", 85);
+    // No guest to act on, so auto-response stays off
+    if let Err(e) = ai_analysis::generate_ai_report(&task_id, &pool, &ai_manager, manager, false, MODE).await {
+        println!("[STATIC] Failed to generate report for task {}: {}", task_id, e);
+    }
+
+    let _ = sqlx::query("UPDATE tasks SET status='Completed', completed_at=$2 WHERE id=$1")
+        .bind(&task_id)
+        .bind(chrono::Utc::now().timestamp_millis())
+        .execute(&pool)
+        .await;
+    progress.send_progress(&task_id, "completed", "Static analysis complete", 100);
+    crate::misp::on_completed(&pool, &task_id).await;
+}
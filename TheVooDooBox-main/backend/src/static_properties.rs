@@ -0,0 +1,272 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+
+// --- STATIC PROPERTIES ---
+
+/// Larger samples are not read into memory for parsing.
+const MAX_FILE_BYTES: u64 = 256 * 1024 * 1024;
+
+#[derive(Serialize, sqlx::FromRow, Clone, Debug)]
+pub struct StaticProperty {
+    pub task_id: String,
+    /// file | pe | office | pdf | strings
+    pub kind: String,
+    pub properties: serde_json::Value,
+    pub created_at: i64,
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS static_properties (
+            task_id TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            properties JSONB NOT NULL,
+            created_at BIGINT NOT NULL,
+            PRIMARY KEY (task_id, kind)
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    println!("[STATIC] Database initialized (static_properties).");
+    Ok(())
+}
+
+/// Format by magic bytes; only what an analyzer here (or the UI) cares to tell apart.
+pub fn format(bytes: &[u8]) -> &'static str {
+    if bytes.starts_with(b"MZ") {
+        "pe"
+    } else if bytes.starts_with(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]) {
+        "ole"
+    } else if bytes.starts_with(b"PK\x03\x04") {
+        "zip"
+    } else if bytes.starts_with(b"\x7FELF") {
+        "elf"
+    } else if bytes.starts_with(b"{\\rtf") {
+        "rtf"
+    } else if bytes[..bytes.len().min(1024)].windows(5).any(|w| w == b"%PDF-") {
+        // Readers accept the header anywhere in the first kilobyte, and maldocs use that
+        "pdf"
+    } else {
+        "unknown"
+    }
+}
+
+/// One analyzer's result; artifacts are (file name, contents).
+struct Output {
+    kind: &'static str,
+    properties: serde_json::Value,
+    artifacts: Vec<(String, Vec<u8>)>,
+}
+
+impl Output {
+    fn new(kind: &'static str, properties: serde_json::Value) -> Self {
+        Output { kind, properties, artifacts: Vec::new() }
+    }
+}
+
+fn artifact_key(task_id: &str, name: &str) -> String {
+    format!("static_artifacts/{}/{}", task_id, name)
+}
+
+/// Every analyzer's output for the sample.
+fn run_analyzers(bytes: &[u8]) -> Vec<Output> {
+    let format = format(bytes);
+    let mut out = vec![Output::new("file", serde_json::json!({
+        "size": bytes.len(),
+        "format": format,
+        "entropy": (crate::pe::entropy(bytes) * 100.0).round() / 100.0,
+        "tlsh": crate::tlsh::hash(bytes),
+    }))];
+    if format == "pe" {
+        if let Some(pe) = crate::pe::analyze(bytes) {
+            out.push(Output::new("pe", pe));
+        }
+    }
+    if let Some(strings) = crate::strings::analyze(bytes, format) {
+        out.push(Output::new("strings", strings));
+    }
+    if let Some(report) = crate::office::analyze(bytes, format) {
+        let mut office = Output::new("office", report.properties);
+        if let Some(macros) = report.macros {
+            office.properties["macro_source_artifact"] = serde_json::json!("macros.vba");
+            office.artifacts.push(("macros.vba".to_string(), macros.into_bytes()));
+        }
+        out.push(office);
+    }
+    if format == "pdf" {
+        if let Some(report) = crate::pdf::analyze(bytes) {
+            out.push(Output { kind: "pdf", properties: report.properties, artifacts: report.artifacts });
+        }
+    }
+    out
+}
+
+async fn sample_bytes(pool: &Pool<Postgres>, task_id: &str) -> Result<Option<Vec<u8>>, String> {
+    let filename = sqlx::query_scalar::<_, String>("SELECT filename FROM tasks WHERE id = $1")
+        .bind(task_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .unwrap_or_default();
+    // URL tasks have no file to parse
+    if filename.is_empty() || filename.contains("..") || filename.contains('/') {
+        return Ok(None);
+    }
+    let key = format!("uploads/{}", filename);
+    if !crate::storage::ensure_local(&key).await {
+        return Ok(None);
+    }
+    let path = crate::storage::local_path(&key);
+    let size = tokio::fs::metadata(&path).await.map_err(|e| e.to_string())?.len();
+    if size > MAX_FILE_BYTES {
+        println!("[STATIC] Task {}: sample is {} bytes, too large to parse.", task_id, size);
+        return Ok(None);
+    }
+    tokio::fs::read(&path).await.map(Some).map_err(|e| e.to_string())
+}
+
+/// Parses the task's sample and replaces its stored properties. Returns the kinds written.
+pub async fn analyze(pool: &Pool<Postgres>, task_id: &str) -> Result<Vec<&'static str>, String> {
+    let Some(bytes) = sample_bytes(pool, task_id).await? else { return Ok(Vec::new()) };
+    let results = tokio::task::spawn_blocking(move || run_analyzers(&bytes)).await.map_err(|e| e.to_string())?;
+
+    sqlx::query("DELETE FROM static_properties WHERE task_id = $1")
+        .bind(task_id)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    let dir = crate::storage::local_path(&format!("static_artifacts/{}", task_id));
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    let now = chrono::Utc::now().timestamp_millis();
+    for output in &results {
+        for (name, contents) in &output.artifacts {
+            let key = artifact_key(task_id, name);
+            tokio::fs::create_dir_all(&dir).await.map_err(|e| e.to_string())?;
+            tokio::fs::write(crate::storage::local_path(&key), contents).await.map_err(|e| e.to_string())?;
+            crate::storage::persist(&key).await;
+        }
+        sqlx::query("INSERT INTO static_properties (task_id, kind, properties, created_at) VALUES ($1, $2, $3, $4)")
+            .bind(task_id)
+            .bind(output.kind)
+            .bind(&output.properties)
+            .bind(now)
+            .execute(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    let kinds: Vec<&'static str> = results.iter().map(|o| o.kind).collect();
+    println!("[STATIC] Task {}: stored {}.", task_id, kinds.join(", "));
+    Ok(kinds)
+}
+
+pub fn queue(pool: &Pool<Postgres>, task_id: &str) {
+    let (pool, task_id) = (pool.clone(), task_id.to_string());
+    actix_web::rt::spawn(async move {
+        if let Err(e) = analyze(&pool, &task_id).await {
+            println!("[STATIC] Task {}: analysis failed: {}", task_id, e);
+        }
+    });
+}
+
+async fn fetch(pool: &Pool<Postgres>, task_id: &str) -> Result<Vec<StaticProperty>, sqlx::Error> {
+    sqlx::query_as::<_, StaticProperty>(
+        "SELECT task_id, kind, properties, created_at FROM static_properties WHERE task_id = $1 ORDER BY kind"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// The task's properties; empty when there are none or the lookup fails.
+pub async fn properties(pool: &Pool<Postgres>, task_id: &str) -> Vec<StaticProperty> {
+    fetch(pool, task_id).await.unwrap_or_default()
+}
+
+/// Properties of one kind, if stored.
+pub fn find<'a>(props: &'a [StaticProperty], kind: &str) -> Option<&'a serde_json::Value> {
+    props.iter().find(|p| p.kind == kind).map(|p| &p.properties)
+}
+
+/// Prompt text: one line per analyzer that has something to say.
+pub fn describe(props: &[StaticProperty]) -> String {
+    let mut lines = Vec::new();
+    if let Some(file) = find(props, "file") {
+        lines.push(format!(
+            "File: {} bytes, format {}, entropy {}, TLSH {}",
+            file["size"], file["format"].as_str().unwrap_or("unknown"), file["entropy"], file["tlsh"].as_str().unwrap_or("n/a"),
+        ));
+    }
+    if let Some(pe) = find(props, "pe") {
+        lines.push(crate::pe::describe(pe));
+    }
+    if let Some(office) = find(props, "office") {
+        lines.push(crate::office::describe(office));
+    }
+    if let Some(pdf) = find(props, "pdf") {
+        lines.push(crate::pdf::describe(pdf));
+    }
+    if let Some(strings) = find(props, "strings") {
+        lines.push(crate::strings::describe(strings));
+    }
+    if lines.is_empty() {
+        "No static file properties.".to_string()
+    } else {
+        lines.join("\n")
+    }
+}
+
+/// Static file properties of the task's sample.
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
+#[get("/tasks/{id}/static")]
+pub async fn get_static_properties(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    match fetch(pool.get_ref(), &path.into_inner()).await {
+        Ok(props) => HttpResponse::Ok().json(props),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Re-runs static analysis of the task's sample.
+#[utoipa::path(tag = "tasks", responses(
+    (status = 200, description = "Properties re-computed"),
+    (status = 404, description = "Task not found"),
+))]
+#[post("/tasks/{id}/static")]
+pub async fn reanalyze_task(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    let task_id = path.into_inner();
+    match sqlx::query_scalar::<_, String>("SELECT status FROM tasks WHERE id = $1").bind(&task_id).fetch_optional(pool.get_ref()).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+    match analyze(pool.get_ref(), &task_id).await {
+        Ok(kinds) => HttpResponse::Ok().json(serde_json::json!({ "task_id": task_id, "kinds": kinds })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e })),
+    }
+}
+
+/// A file an analyzer extracted, served as plain text.
+#[utoipa::path(tag = "tasks", responses(
+    (status = 200, description = "Artifact contents"),
+    (status = 404, description = "Artifact not stored"),
+))]
+#[get("/tasks/{id}/static/artifacts/{name}")]
+pub async fn download_static_artifact(path: web::Path<(String, String)>) -> impl Responder {
+    let (task_id, name) = path.into_inner();
+    if [&task_id, &name].iter().any(|p| p.is_empty() || p.contains("..") || p.contains('/') || p.contains('\\')) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid task id or artifact name" }));
+    }
+    let key = artifact_key(&task_id, &name);
+    if !crate::storage::ensure_local(&key).await {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Artifact not stored" }));
+    }
+    match tokio::fs::read(crate::storage::local_path(&key)).await {
+        Ok(bytes) => HttpResponse::Ok()
+            .content_type("text/plain; charset=utf-8")
+            .insert_header(("X-Content-Type-Options", "nosniff"))
+            .insert_header(("Content-Security-Policy", "sandbox"))
+            .body(bytes),
+        Err(_) => HttpResponse::NotFound().json(serde_json::json!({ "error": "Artifact not stored" })),
+    }
+}
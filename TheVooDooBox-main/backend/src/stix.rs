@@ -0,0 +1,207 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use crate::ai_analysis::Verdict;
+use crate::iocs::Ioc;
+
+// --- STIX 2.1 EXPORT ---
+
+const PRODUCER: &str = "TheVooDooBox";
+
+/// Name-based UUID (v5 layout over a truncated SHA-256) so the same input always maps to the same id.
+pub fn stable_uuid(name: &str) -> uuid::Uuid {
+    let digest = Sha256::digest(name.as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    uuid::Builder::from_sha1_bytes(bytes).into_uuid()
+}
+
+fn stix_id(kind: &str, task_id: &str, key: &str) -> String {
+    format!("{}--{}", kind, stable_uuid(&format!("{}|{}|{}", kind, task_id, key)))
+}
+
+fn stix_time(ms: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(ms)
+        .unwrap_or_default()
+        .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        .to_string()
+}
+
+fn quote(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+/// STIX pattern for an indicator, for the IOC types that have a standard object path.
+fn pattern(ioc: &Ioc) -> Option<String> {
+    let value = quote(&ioc.value);
+    Some(match ioc.ioc_type {
+        "sha256" => format!("[file:hashes.'SHA-256' = '{}']", value),
+        "sha1" => format!("[file:hashes.'SHA-1' = '{}']", value),
+        "md5" => format!("[file:hashes.MD5 = '{}']", value),
+        "ip" if ioc.value.contains(':') => format!("[ipv6-addr:value = '{}']", value),
+        "ip" => format!("[ipv4-addr:value = '{}']", value),
+        "domain" => format!("[domain-name:value = '{}']", value),
+        "url" => format!("[url:value = '{}']", value),
+        "mutex" => format!("[mutex:name = '{}']", value),
+        "registry_key" => format!("[windows-registry-key:key = '{}']", value),
+        _ => return None,
+    })
+}
+
+/// "Defense Evasion" -> "defense-evasion", the phase names ATT&CK uses in kill_chain_phases.
+fn phase_name(tactic: &str) -> String {
+    tactic.trim().to_lowercase().split_whitespace().collect::<Vec<_>>().join("-")
+}
+
+pub async fn bundle(pool: &Pool<Postgres>, task_id: &str) -> Result<Option<Value>, sqlx::Error> {
+    let task: Option<(String, String, i64, Option<i64>)> = sqlx::query_as(
+        "SELECT original_filename, file_hash, created_at, completed_at FROM tasks WHERE id = $1"
+    )
+    .bind(task_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some((filename, file_hash, created_at, completed_at)) = task else { return Ok(None) };
+
+    let report = crate::ai_analysis::stored_report(pool, task_id).await;
+    let iocs = crate::iocs::extract(pool, task_id).await?;
+
+    let created = stix_time(created_at);
+    let modified = stix_time(completed_at.unwrap_or(created_at));
+    let identity_id = stix_id("identity", "", PRODUCER);
+    let mut objects = vec![json!({
+        "type": "identity", "spec_version": "2.1", "id": identity_id,
+        "created": stix_time(0), "modified": stix_time(0),
+        "name": PRODUCER, "identity_class": "system",
+    })];
+    let relationship = |objects: &mut Vec<Value>, kind: &str, source: &str, target: &str| {
+        objects.push(json!({
+            "type": "relationship", "spec_version": "2.1",
+            "id": stix_id("relationship", task_id, &format!("{}|{}|{}", kind, source, target)),
+            "created": created, "modified": modified, "created_by_ref": identity_id,
+            "relationship_type": kind, "source_ref": source, "target_ref": target,
+        }));
+    };
+
+    let file_id = (!file_hash.is_empty()).then(|| {
+        let id = stix_id("file", task_id, &file_hash);
+        objects.push(json!({
+            "type": "file", "spec_version": "2.1", "id": id,
+            "name": filename, "hashes": { "SHA-256": file_hash },
+        }));
+        id
+    });
+
+    // A benign verdict gets the sample and the report, but nothing that claims malice
+    let verdict = report.as_ref().map(|r| r.verdict.clone());
+    let malicious = verdict.as_ref() != Some(&Verdict::Benign);
+
+    let malware_id = malicious.then(|| {
+        let family = report.as_ref().and_then(|r| r.malware_family.clone()).filter(|f| !f.trim().is_empty());
+        let id = stix_id("malware", task_id, "sample");
+        let mut malware = json!({
+            "type": "malware", "spec_version": "2.1", "id": id,
+            "created": created, "modified": modified, "created_by_ref": identity_id,
+            "name": family.clone().unwrap_or_else(|| filename.clone()),
+            "is_family": false,
+            "malware_types": ["unknown"],
+        });
+        if let Some(r) = &report {
+            malware["description"] = json!(r.executive_summary);
+            malware["confidence"] = json!(r.threat_score.clamp(0, 100));
+        }
+        if let Some(family) = family {
+            malware["aliases"] = json!([family]);
+        }
+        if let Some(file_id) = &file_id {
+            malware["sample_refs"] = json!([file_id]);
+        }
+        objects.push(malware);
+        id
+    });
+
+    if let Some(malware_id) = &malware_id {
+        let c2: Vec<String> = report.as_ref()
+            .map(|r| r.artifacts.c2_ips.iter().chain(&r.artifacts.c2_domains).map(|v| v.to_lowercase()).collect())
+            .unwrap_or_default();
+        for ioc in iocs.iter().filter(|i| i.confidence != "low") {
+            let Some(pattern) = pattern(ioc) else { continue };
+            let id = stix_id("indicator", task_id, &pattern);
+            // The submitted sample and what the report calls C2 are malicious; the rest is
+            // simply what the sample was seen doing
+            let flagged = ioc.value.eq_ignore_ascii_case(&file_hash) || c2.contains(&ioc.value.to_lowercase());
+            objects.push(json!({
+                "type": "indicator", "spec_version": "2.1", "id": id,
+                "created": created, "modified": modified, "created_by_ref": identity_id,
+                "name": format!("{} {}", ioc.ioc_type, ioc.value),
+                "indicator_types": [if flagged { "malicious-activity" } else { "anomalous-activity" }],
+                "pattern": pattern, "pattern_type": "stix",
+                "valid_from": ioc.first_seen.map(stix_time).unwrap_or_else(|| created.clone()),
+                "confidence": if ioc.confidence == "high" { 85 } else { 50 },
+                "labels": ioc.sources,
+            }));
+            relationship(&mut objects, "indicates", &id, malware_id);
+        }
+
+        let mut tactics: Vec<(&String, &Vec<crate::ai_analysis::MitreTechnique>)> = report.as_ref()
+            .map(|r| r.mitre_matrix.iter().collect())
+            .unwrap_or_default();
+        tactics.sort_by_key(|(tactic, _)| tactic.as_str());
+        for (tactic, techniques) in tactics {
+            for technique in techniques.iter().filter(|t| !t.id.trim().is_empty()) {
+                let technique_id = technique.id.trim().to_uppercase();
+                let id = stix_id("attack-pattern", task_id, &technique_id);
+                if !objects.iter().any(|o| o["id"] == json!(id)) {
+                    objects.push(json!({
+                        "type": "attack-pattern", "spec_version": "2.1", "id": id,
+                        "created": created, "modified": modified, "created_by_ref": identity_id,
+                        "name": technique.name,
+                        "kill_chain_phases": [{ "kill_chain_name": "mitre-attack", "phase_name": phase_name(tactic) }],
+                        "external_references": [{
+                            "source_name": "mitre-attack",
+                            "external_id": technique_id,
+                            "url": format!("https://attack.mitre.org/techniques/{}/", technique_id.replace('.', "/")),
+                        }],
+                    }));
+                    relationship(&mut objects, "uses", malware_id, &id);
+                }
+            }
+        }
+    }
+
+    let refs: Vec<Value> = objects.iter().skip(1).map(|o| o["id"].clone()).collect();
+    objects.push(json!({
+        "type": "report", "spec_version": "2.1", "id": stix_id("report", task_id, "report"),
+        "created": created, "modified": modified, "created_by_ref": identity_id,
+        "name": format!("{} analysis of {}", PRODUCER, filename),
+        "description": report.as_ref().map(|r| r.executive_summary.clone()).unwrap_or_default(),
+        "report_types": ["malware"],
+        "published": modified,
+        "labels": verdict.map(|v| vec![v.to_string().to_lowercase()]).unwrap_or_default(),
+        "external_references": [{ "source_name": PRODUCER, "external_id": task_id }],
+        "object_refs": refs,
+    }));
+
+    Ok(Some(json!({
+        "type": "bundle",
+        "id": stix_id("bundle", task_id, &modified),
+        "objects": objects,
+    })))
+}
+
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
+#[get("/tasks/{id}/export/stix")]
+pub async fn export_stix(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let task_id = path.into_inner();
+    match bundle(pool.get_ref(), &task_id).await {
+        Ok(Some(bundle)) => HttpResponse::Ok()
+            .content_type("application/stix+json;version=2.1")
+            .insert_header(("Content-Disposition", format!("attachment; filename=\"{}.stix.json\"", task_id)))
+            .json(bundle),
+        Ok(None) => HttpResponse::NotFound().json(json!({ "error": "Task not found" })),
+        Err(e) => HttpResponse::InternalServerError().json(json!({ "error": e.to_string() })),
+    }
+}
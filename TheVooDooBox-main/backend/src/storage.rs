@@ -0,0 +1,517 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use async_trait::async_trait;
+use futures::StreamExt;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+// --- OBJECT STORAGE ---
+// Local working copy, written through to S3 with STORAGE_BACKEND=s3.
+
+/// Longest a presigned link may live (the SigV4 maximum).
+const MAX_PRESIGN_SECS: u64 = 7 * 24 * 3600;
+
+const CHUNK_SIZE: usize = 256 * 1024;
+
+/// Top-level directories managed by the store.
+pub const ROOTS: &[&str] = &["uploads", "screenshots", "reports", "url_artifacts", "static_artifacts"];
+
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    fn kind(&self) -> &'static str;
+
+    /// Uploads the file at `path` under `key`.
+    async fn put_file(&self, key: &str, path: &Path) -> Result<(), String>;
+
+    /// Downloads `key` into `path`; Ok(false) when there is no such object.
+    async fn get_file(&self, key: &str, path: &Path) -> Result<bool, String>;
+
+    async fn delete(&self, key: &str) -> Result<(), String>;
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, String>;
+
+    /// Time-limited direct download link, where the backend supports one.
+    fn presigned_url(&self, key: &str, ttl: Duration) -> Option<String>;
+}
+
+/// The working copy is the store; nothing to mirror.
+pub struct LocalStore;
+
+#[async_trait]
+impl ObjectStore for LocalStore {
+    fn kind(&self) -> &'static str {
+        "local"
+    }
+
+    async fn put_file(&self, key: &str, path: &Path) -> Result<(), String> {
+        let dest = local_path(key);
+        if dest == path {
+            return Ok(());
+        }
+        if let Some(parent) = dest.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(|e| e.to_string())?;
+        }
+        tokio::fs::copy(path, dest).await.map(|_| ()).map_err(|e| e.to_string())
+    }
+
+    async fn get_file(&self, key: &str, path: &Path) -> Result<bool, String> {
+        let src = local_path(key);
+        if src == path {
+            return Ok(src.exists());
+        }
+        match tokio::fs::copy(src, path).await {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), String> {
+        match tokio::fs::remove_file(local_path(key)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, String> {
+        Ok(walk_local(prefix.trim_end_matches('/')).into_iter().collect())
+    }
+
+    fn presigned_url(&self, _key: &str, _ttl: Duration) -> Option<String> {
+        None
+    }
+}
+
+pub struct S3Store {
+    endpoint: reqwest::Url,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    prefix: String,
+    client: reqwest::Client,
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex_sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// SigV4 canonical form of a query: keys sorted, both sides RFC 3986 encoded.
+fn canonical_query(params: &[(String, String)]) -> String {
+    let mut encoded: Vec<(String, String)> = params.iter()
+        .map(|(k, v)| (urlencoding::encode(k).into_owned(), urlencoding::encode(v).into_owned()))
+        .collect();
+    encoded.sort();
+    encoded.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join("&")
+}
+
+/// Hex SigV4 signature; `headers` must be lowercase and sorted.
+#[allow(clippy::too_many_arguments)]
+pub fn sigv4_signature(
+    secret_key: &str,
+    region: &str,
+    service: &str,
+    amz_date: &str,
+    method: &str,
+    canonical_uri: &str,
+    query: &str,
+    headers: &[(&str, String)],
+    payload_hash: &str,
+) -> String {
+    let date = &amz_date[..8];
+    let canonical_headers: String = headers.iter().map(|(k, v)| format!("{}:{}\n", k, v.trim())).collect();
+    let signed_headers = headers.iter().map(|(k, _)| *k).collect::<Vec<_>>().join(";");
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method, canonical_uri, query, canonical_headers, signed_headers, payload_hash
+    );
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, hex_sha256(canonical_request.as_bytes()));
+    let key = hmac(format!("AWS4{}", secret_key).as_bytes(), date);
+    let key = hmac(&key, region);
+    let key = hmac(&key, service);
+    let key = hmac(&key, "aws4_request");
+    hmac(&key, &string_to_sign).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl S3Store {
+    pub fn from_env() -> Result<S3Store, String> {
+        let var = |name: &str| std::env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        let endpoint = var("S3_ENDPOINT").ok_or("S3_ENDPOINT is not set")?;
+        let endpoint = reqwest::Url::parse(endpoint.trim_end_matches('/')).map_err(|e| format!("S3_ENDPOINT: {}", e))?;
+        Ok(S3Store {
+            endpoint,
+            bucket: var("S3_BUCKET").ok_or("S3_BUCKET is not set")?,
+            region: var("S3_REGION").unwrap_or_else(|| "us-east-1".to_string()),
+            access_key: var("S3_ACCESS_KEY").ok_or("S3_ACCESS_KEY is not set")?,
+            secret_key: var("S3_SECRET_KEY").ok_or("S3_SECRET_KEY is not set")?,
+            prefix: var("S3_PREFIX").map(|p| format!("{}/", p.trim_matches('/'))).unwrap_or_default(),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(30 * 60))
+                .build()
+                .map_err(|e| e.to_string())?,
+        })
+    }
+
+    fn host(&self) -> String {
+        let host = self.endpoint.host_str().unwrap_or_default();
+        match self.endpoint.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        }
+    }
+
+    /// Path-style URI of an object (or of the bucket for an empty key), already encoded.
+    fn canonical_uri(&self, key: &str) -> String {
+        let base = self.endpoint.path().trim_end_matches('/');
+        let full = format!("{}{}", self.prefix, key);
+        let encoded: Vec<String> = full.split('/').map(|s| urlencoding::encode(s).into_owned()).collect();
+        if key.is_empty() {
+            format!("{}/{}", base, urlencoding::encode(&self.bucket))
+        } else {
+            format!("{}/{}/{}", base, urlencoding::encode(&self.bucket), encoded.join("/"))
+        }
+    }
+
+    fn url(&self, uri: &str, query: &str) -> String {
+        let mut url = format!("{}://{}{}", self.endpoint.scheme(), self.host(), uri);
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(query);
+        }
+        url
+    }
+
+    /// Signed request with an unsigned payload (so bodies can be streamed).
+    fn request(&self, method: reqwest::Method, key: &str, params: &[(String, String)]) -> reqwest::RequestBuilder {
+        let amz_date = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let uri = self.canonical_uri(key);
+        let query = canonical_query(params);
+        let payload_hash = "UNSIGNED-PAYLOAD";
+        let headers = [
+            ("host", self.host()),
+            ("x-amz-content-sha256", payload_hash.to_string()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        let signature = sigv4_signature(&self.secret_key, &self.region, "s3", &amz_date, method.as_str(), &uri, &query, &headers, payload_hash);
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}/{}/s3/aws4_request, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+            self.access_key, &amz_date[..8], self.region, signature
+        );
+        self.client.request(method, self.url(&uri, &query))
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", amz_date)
+            .header("Authorization", authorization)
+    }
+}
+
+async fn check(resp: reqwest::Response) -> Result<reqwest::Response, String> {
+    if resp.status().is_success() {
+        return Ok(resp);
+    }
+    let status = resp.status();
+    let body = resp.text().await.unwrap_or_default();
+    Err(format!("S3 returned {}: {}", status, body.chars().take(300).collect::<String>()))
+}
+
+/// Values of every `<tag>` element in an S3 (or EC2) XML response.
+pub fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+    let (open, close) = (format!("<{}>", tag), format!("</{}>", tag));
+    let mut out = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let Some(end) = rest.find(&close) else { break };
+        out.push(rest[..end].replace("&amp;", "&").replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'"));
+        rest = &rest[end + close.len()..];
+    }
+    out
+}
+
+#[async_trait]
+impl ObjectStore for S3Store {
+    fn kind(&self) -> &'static str {
+        "s3"
+    }
+
+    async fn put_file(&self, key: &str, path: &Path) -> Result<(), String> {
+        let file = tokio::fs::File::open(path).await.map_err(|e| e.to_string())?;
+        let length = file.metadata().await.map_err(|e| e.to_string())?.len();
+        let stream = futures::stream::unfold(file, |mut file| async move {
+            let mut buf = vec![0u8; CHUNK_SIZE];
+            match file.read(&mut buf).await {
+                Ok(0) => None,
+                Ok(n) => {
+                    buf.truncate(n);
+                    Some((Ok::<_, std::io::Error>(buf), file))
+                }
+                Err(e) => Some((Err(e), file)),
+            }
+        });
+        let resp = self.request(reqwest::Method::PUT, key, &[])
+            .header("Content-Length", length)
+            .body(reqwest::Body::wrap_stream(stream))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        check(resp).await.map(|_| ())
+    }
+
+    async fn get_file(&self, key: &str, path: &Path) -> Result<bool, String> {
+        let resp = self.request(reqwest::Method::GET, key, &[]).send().await.map_err(|e| e.to_string())?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        let resp = check(resp).await?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(|e| e.to_string())?;
+        }
+        // Download next to the target and rename, so a broken transfer never looks complete
+        let partial = path.with_extension("part");
+        let mut file = tokio::fs::File::create(&partial).await.map_err(|e| e.to_string())?;
+        let mut body = resp.bytes_stream();
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|e| e.to_string())?;
+            file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+        }
+        file.flush().await.map_err(|e| e.to_string())?;
+        tokio::fs::rename(&partial, path).await.map_err(|e| e.to_string())?;
+        Ok(true)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), String> {
+        let resp = self.request(reqwest::Method::DELETE, key, &[]).send().await.map_err(|e| e.to_string())?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        check(resp).await.map(|_| ())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, String> {
+        let mut keys = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut params = vec![
+                ("list-type".to_string(), "2".to_string()),
+                ("prefix".to_string(), format!("{}{}", self.prefix, prefix)),
+            ];
+            if let Some(t) = &token {
+                params.push(("continuation-token".to_string(), t.clone()));
+            }
+            let resp = self.request(reqwest::Method::GET, "", &params).send().await.map_err(|e| e.to_string())?;
+            let xml = check(resp).await?.text().await.map_err(|e| e.to_string())?;
+            keys.extend(xml_values(&xml, "Key").into_iter().filter_map(|k| k.strip_prefix(&self.prefix).map(str::to_string)));
+            token = xml_values(&xml, "NextContinuationToken").into_iter().next();
+            if token.is_none() {
+                return Ok(keys);
+            }
+        }
+    }
+
+    fn presigned_url(&self, key: &str, ttl: Duration) -> Option<String> {
+        let amz_date = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let uri = self.canonical_uri(key);
+        let mut params = vec![
+            ("X-Amz-Algorithm".to_string(), "AWS4-HMAC-SHA256".to_string()),
+            ("X-Amz-Credential".to_string(), format!("{}/{}/{}/s3/aws4_request", self.access_key, &amz_date[..8], self.region)),
+            ("X-Amz-Date".to_string(), amz_date.clone()),
+            ("X-Amz-Expires".to_string(), ttl.as_secs().clamp(1, MAX_PRESIGN_SECS).to_string()),
+            ("X-Amz-SignedHeaders".to_string(), "host".to_string()),
+        ];
+        let query = canonical_query(&params);
+        let signature = sigv4_signature(&self.secret_key, &self.region, "s3", &amz_date, "GET", &uri, &query, &[("host", self.host())], "UNSIGNED-PAYLOAD");
+        params.push(("X-Amz-Signature".to_string(), signature));
+        Some(self.url(&uri, &canonical_query(&params)))
+    }
+}
+
+pub fn store() -> &'static dyn ObjectStore {
+    static STORE: OnceLock<Box<dyn ObjectStore>> = OnceLock::new();
+    STORE.get_or_init(|| {
+        match std::env::var("STORAGE_BACKEND").unwrap_or_default().to_lowercase().as_str() {
+            "s3" | "minio" => match S3Store::from_env() {
+                Ok(s3) => {
+                    println!("[STORAGE] Writing through to s3 bucket '{}' at {}", s3.bucket, s3.endpoint);
+                    Box::new(s3)
+                }
+                Err(e) => {
+                    println!("[STORAGE] S3 backend misconfigured ({}); using local disk only.", e);
+                    Box::new(LocalStore)
+                }
+            },
+            _ => Box::new(LocalStore),
+        }
+    }).as_ref()
+}
+
+fn is_remote() -> bool {
+    store().kind() != "local"
+}
+
+pub fn local_path(key: &str) -> PathBuf {
+    Path::new(".").join(key)
+}
+
+/// Keys of every file below a local directory (relative to the working directory).
+fn walk_local(dir: &str) -> Vec<String> {
+    let mut keys = Vec::new();
+    let mut pending = vec![local_path(dir)];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if let Ok(rel) = path.strip_prefix(".") {
+                let key = rel.to_string_lossy().replace('\\', "/");
+                // Half-written downloads and batch staging areas are not content
+                if !key.ends_with(".part") && !key.contains("/.") {
+                    keys.push(key);
+                }
+            }
+        }
+    }
+    keys
+}
+
+/// Mirrors a local file to the bucket; failures are only logged.
+pub async fn persist(key: &str) {
+    if !is_remote() {
+        return;
+    }
+    if let Err(e) = store().put_file(key, &local_path(key)).await {
+        println!("[STORAGE] Failed to upload {}: {}", key, e);
+    }
+}
+
+/// Makes sure `key` is on local disk, fetching it from the bucket if needed.
+pub async fn ensure_local(key: &str) -> bool {
+    let path = local_path(key);
+    if path.exists() {
+        return true;
+    }
+    if !is_remote() {
+        return false;
+    }
+    match store().get_file(key, &path).await {
+        Ok(found) => {
+            if found {
+                println!("[STORAGE] Restored {} from object storage.", key);
+            }
+            found
+        }
+        Err(e) => {
+            println!("[STORAGE] Failed to fetch {}: {}", key, e);
+            false
+        }
+    }
+}
+
+/// Pulls every object under `prefix` that is missing locally (e.g. a task's screenshots).
+pub async fn ensure_local_prefix(prefix: &str) {
+    if !is_remote() {
+        return;
+    }
+    match store().list(prefix).await {
+        Ok(keys) => {
+            for key in keys {
+                ensure_local(&key).await;
+            }
+        }
+        Err(e) => println!("[STORAGE] Failed to list {}: {}", prefix, e),
+    }
+}
+
+/// Deletes the bucket copies of `keys`; local files are the caller's business.
+pub async fn remove(keys: &[String]) {
+    if !is_remote() {
+        return;
+    }
+    for key in keys {
+        if let Err(e) = store().delete(key).await {
+            println!("[STORAGE] Failed to delete {}: {}", key, e);
+        }
+    }
+}
+
+pub async fn remove_prefix(prefix: &str) {
+    if !is_remote() {
+        return;
+    }
+    match store().list(prefix).await {
+        Ok(keys) => remove(&keys).await,
+        Err(e) => println!("[STORAGE] Failed to list {} for deletion: {}", prefix, e),
+    }
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PresignQuery {
+    pub key: String,
+    /// Seconds, default 900.
+    pub ttl: Option<u64>,
+}
+
+/// Direct download link for a screenshot or report. Samples are deliberately not offered here.
+#[utoipa::path(tag = "admin", responses((status = 200, description = "Success")))]
+#[get("/storage/presign")]
+pub async fn presign(query: web::Query<PresignQuery>) -> impl Responder {
+    let key = query.key.trim_start_matches("./").trim_start_matches('/');
+    if key.contains("..") || !(key.starts_with("screenshots/") || key.starts_with("reports/")) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Only screenshots/ and reports/ keys can be presigned" }));
+    }
+    if !is_remote() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Presigned URLs need STORAGE_BACKEND=s3" }));
+    }
+    // Make sure a link is never handed out for a file that only exists locally
+    if local_path(key).exists() {
+        persist(key).await;
+    }
+    let ttl = Duration::from_secs(query.ttl.unwrap_or(900));
+    match store().presigned_url(key, ttl) {
+        Some(url) => HttpResponse::Ok().json(serde_json::json!({ "url": url, "expires_in": ttl.as_secs().min(MAX_PRESIGN_SECS) })),
+        None => HttpResponse::BadRequest().json(serde_json::json!({ "error": "Backend does not support presigned URLs" })),
+    }
+}
+
+/// Uploads local files that are not in the bucket yet.
+#[utoipa::path(tag = "admin", responses((status = 200, description = "Success")))]
+#[post("/storage/migrate")]
+pub async fn migrate() -> impl Responder {
+    if !is_remote() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "STORAGE_BACKEND is local; nothing to migrate to" }));
+    }
+    let mut uploaded = 0;
+    let mut present = 0;
+    let mut failed = Vec::new();
+    for root in ROOTS {
+        let existing: std::collections::HashSet<String> = match store().list(&format!("{}/", root)).await {
+            Ok(keys) => keys.into_iter().collect(),
+            Err(e) => return HttpResponse::BadGateway().json(serde_json::json!({ "error": e })),
+        };
+        for key in walk_local(root) {
+            if existing.contains(&key) {
+                present += 1;
+                continue;
+            }
+            match store().put_file(&key, &local_path(&key)).await {
+                Ok(()) => uploaded += 1,
+                Err(e) => failed.push(serde_json::json!({ "key": key, "error": e })),
+            }
+        }
+    }
+    println!("[STORAGE] Migration: {} uploaded, {} already present, {} failed.", uploaded, present, failed.len());
+    HttpResponse::Ok().json(serde_json::json!({ "uploaded": uploaded, "already_present": present, "failed": failed }))
+}
@@ -1,33 +1,126 @@
 use actix::prelude::*;
 use actix_web_actors::ws;
 use actix_web::{web, Error, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
 use tokio::sync::broadcast;
 
+// -- Live event stream
+// Every stored agent event is published once; each /ws client filters server side. A client
+// starts with everything (or the filter from its query string: task_id, event_type and
+// min_severity, the first two comma-separated) and narrows or widens it by sending
+//   {"action": "subscribe", "task_ids": [...], "event_types": [...], "min_severity": 50}
+//   {"action": "unsubscribe", "task_ids": [...], "event_types": [...]}
+//   {"action": "reset"}
+// and gets {"type": "subscribed", "filter": {...}} back. Empty sets mean "any".
+// Backpressure: a client that cannot keep up is not buffered for; once it is more than the
+// channel capacity behind, the oldest events are skipped and it receives
+// {"type": "lagged", "dropped": n} so it knows to refetch.
+
+/// Events held for the slowest client before it starts losing the oldest.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// One published event with what the filters look at.
+pub struct Envelope {
+    task_id: Option<String>,
+    event_type: String,
+    severity: i32,
+    json: String,
+}
+
 // -- Broadcast Server (Actor-ish structure but using Tokio Broadcast)
 
 pub struct Broadcaster {
-    tx: broadcast::Sender<String>,
+    tx: broadcast::Sender<Arc<Envelope>>,
 }
 
 impl Broadcaster {
     pub fn new() -> Self {
-        let (tx, _) = broadcast::channel(100);
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
         Broadcaster { tx }
     }
-    
-    pub fn send_message(&self, msg: &str) {
-        let _ = self.tx.send(msg.to_string());
+
+    pub fn publish(&self, task_id: Option<&str>, event_type: &str, severity: i32, json: String) {
+        let _ = self.tx.send(Arc::new(Envelope {
+            task_id: task_id.map(str::to_string),
+            event_type: event_type.to_uppercase(),
+            severity,
+            json,
+        }));
     }
-    
-    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Envelope>> {
         self.tx.subscribe()
     }
 }
 
+#[derive(Default, Serialize)]
+struct Filter {
+    task_ids: HashSet<String>,
+    event_types: HashSet<String>,
+    min_severity: i32,
+}
+
+impl Filter {
+    fn matches(&self, env: &Envelope) -> bool {
+        env.severity >= self.min_severity
+            && (self.task_ids.is_empty() || env.task_id.as_ref().is_some_and(|t| self.task_ids.contains(t)))
+            && (self.event_types.is_empty() || self.event_types.contains(&env.event_type))
+    }
+}
+
+fn list(raw: Option<&String>) -> HashSet<String> {
+    raw.map(|v| v.split(',').map(str::trim).filter(|s| !s.is_empty()).map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+#[derive(Deserialize)]
+struct ClientMessage {
+    action: String,
+    task_id: Option<String>,
+    #[serde(default)]
+    task_ids: Vec<String>,
+    event_type: Option<String>,
+    #[serde(default)]
+    event_types: Vec<String>,
+    min_severity: Option<i32>,
+}
+
 // -- WebSocket Session Actor
 
 pub struct WsSession {
-    rx: Option<broadcast::Receiver<String>>,
+    rx: Option<broadcast::Receiver<Arc<Envelope>>>,
+    /// Shared with the forwarding loop, which drops what the client did not ask for.
+    filter: Arc<RwLock<Filter>>,
+}
+
+impl WsSession {
+    fn apply(&self, msg: ClientMessage) -> Result<(), String> {
+        let Ok(mut filter) = self.filter.write() else { return Err("filter unavailable".to_string()) };
+        let tasks = msg.task_ids.into_iter().chain(msg.task_id);
+        let types: Vec<String> = msg.event_types.into_iter().chain(msg.event_type).map(|t| t.to_uppercase()).collect();
+        match msg.action.as_str() {
+            "subscribe" => {
+                filter.task_ids.extend(tasks);
+                filter.event_types.extend(types);
+                if let Some(min) = msg.min_severity {
+                    filter.min_severity = min;
+                }
+            }
+            "unsubscribe" => {
+                for t in tasks {
+                    filter.task_ids.remove(&t);
+                }
+                for t in &types {
+                    filter.event_types.remove(t);
+                }
+            }
+            "reset" => *filter = Filter::default(),
+            other => return Err(format!("unknown action '{}'; use subscribe, unsubscribe or reset", other)),
+        }
+        Ok(())
+    }
 }
 
 impl Actor for WsSession {
@@ -37,9 +130,28 @@ impl Actor for WsSession {
         // Start listening to broadcast updates
         if let Some(mut rx) = self.rx.take() {
             let addr = ctx.address();
+            let filter = self.filter.clone();
             let fut = async move {
-                while let Ok(msg) = rx.recv().await {
-                    addr.do_send(BroadcastMessage(msg));
+                loop {
+                    let msg = match rx.recv().await {
+                        Ok(env) => {
+                            let wanted = filter.read().map(|f| f.matches(&env)).unwrap_or(true);
+                            if !wanted {
+                                continue;
+                            }
+                            env.json.clone()
+                        }
+                        Err(broadcast::error::RecvError::Lagged(dropped)) => {
+                            crate::metrics::inc("voodoobox_ws_dropped_total", &[], dropped as f64);
+                            serde_json::json!({ "type": "lagged", "dropped": dropped }).to_string()
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    };
+                    // Waiting on the bounded mailbox is the backpressure: a slow client lags
+                    // behind in the channel instead of piling up here
+                    if addr.send(BroadcastMessage(msg)).await.is_err() {
+                        break;
+                    }
                 }
             };
             ctx.spawn(actix::fut::wrap_future(fut));
@@ -64,6 +176,20 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
         match msg {
             Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Text(text)) => {
+                let reply = match serde_json::from_str::<ClientMessage>(&text).map_err(|e| e.to_string()).and_then(|m| self.apply(m)) {
+                    Ok(()) => match self.filter.read() {
+                        Ok(f) => serde_json::json!({ "type": "subscribed", "filter": *f }),
+                        Err(_) => return,
+                    },
+                    Err(e) => serde_json::json!({ "type": "error", "error": e }),
+                };
+                ctx.text(reply.to_string());
+            }
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
             _ => (),
         }
     }
@@ -72,10 +198,18 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
 // -- HTTP Endpoint for WS Upgrade
 
 pub async fn ws_route(
-    req: HttpRequest, 
-    stream: web::Payload, 
+    req: HttpRequest,
+    stream: web::Payload,
     broadcaster: web::Data<std::sync::Arc<Broadcaster>>
 ) -> Result<HttpResponse, Error> {
+    let query = web::Query::<std::collections::HashMap<String, String>>::from_query(req.query_string())
+        .map(|q| q.into_inner())
+        .unwrap_or_default();
+    let filter = Filter {
+        task_ids: list(query.get("task_id")),
+        event_types: list(query.get("event_type")).into_iter().map(|t| t.to_uppercase()).collect(),
+        min_severity: query.get("min_severity").and_then(|s| s.parse().ok()).unwrap_or(0),
+    };
     let rx = broadcaster.subscribe();
-    ws::start(WsSession { rx: Some(rx) }, &req, stream)
+    ws::start(WsSession { rx: Some(rx), filter: Arc::new(RwLock::new(filter)) }, &req, stream)
 }
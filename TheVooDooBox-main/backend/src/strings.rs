@@ -0,0 +1,497 @@
+use regex::Regex;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::OnceLock;
+
+// --- STRING EXTRACTION ---
+
+/// Bytes scanned per sample; strings past this are not looked for.
+const MAX_SCAN_BYTES: usize = 32 * 1024 * 1024;
+const MIN_LENGTH: usize = 6;
+const MAX_LENGTH: usize = 1024;
+/// Strings kept per category and per kind of recovered string.
+const MAX_LISTED: usize = 200;
+/// Decoding-loop keys tried against the data.
+const MAX_LOOP_KEYS: usize = 8;
+/// How far past the XOR a loop's backward jump may be, and how far back it may reach.
+const LOOP_WINDOW: usize = 40;
+const LOOP_REACH: usize = 48;
+/// Bytes of other instructions allowed between two stores of one stack string.
+const STACK_GAP: usize = 16;
+
+/// Plaintexts likely to sit in a XOR-encoded string; each hit gives away the key.
+const KNOWN_PLAINTEXTS: &[&[u8]] = &[
+    b"http://", b"https://", b"HKEY_", b"SOFTWARE\\", b"Software\\", b"\\Windows\\", b"CurrentVersion",
+    b"cmd.exe", b"powershell", b"Mozilla/", b".exe\0", b".dll\0", b".pdb\0",
+];
+
+const CATEGORIES: &[&str] = &["url", "ip", "registry", "pdb", "path", "email"];
+
+fn url_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r#"(?i)\b(?:https?|ftp)://[^\s"'<>]{4,}"#).unwrap())
+}
+
+fn ip_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\b(?:(?:25[0-5]|2[0-4]\d|1?\d?\d)\.){3}(?:25[0-5]|2[0-4]\d|1?\d?\d)(?::\d{1,5})?\b").unwrap())
+}
+
+fn registry_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r#"(?i)\b(?:HKEY_[A-Z_]+|HKLM|HKCU|HKCR|HKU)\\[^"\r\n]+|\b(?:SOFTWARE|SYSTEM)\\(?:Microsoft|CurrentControlSet|Classes|Policies|Wow6432Node)\\[^"\r\n]+"#).unwrap()
+    })
+}
+
+fn pdb_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r#"(?i)[^\s"<>|*?]*\.pdb\b"#).unwrap())
+}
+
+fn path_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r#"(?i)(?:\b[a-z]:\\|\\\\[a-z0-9][\w.$-]+\\|%[a-z_]+%\\)[\w.$%~ -][^"<>|*?\r\n]*|(?:^|\s)/(?:tmp|etc|var|dev/shm|bin|usr|proc)/[^\s"']+"#).unwrap())
+}
+
+fn email_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}\b").unwrap())
+}
+
+/// The indicators in one string, as (category, match).
+fn categorise(s: &str) -> Vec<(&'static str, String)> {
+    let mut found = Vec::new();
+    for m in url_re().find_iter(s) {
+        found.push(("url", m.as_str().to_string()));
+    }
+    for m in ip_re().find_iter(s) {
+        let ip = m.as_str();
+        // Version numbers ("6.0.0.0") and masks read as addresses
+        let octets: Vec<&str> = ip.split(['.', ':']).collect();
+        if octets[0] == "0" || octets[1..4].iter().all(|o| *o == "0") || ip.starts_with("255.") {
+            continue;
+        }
+        found.push(("ip", ip.to_string()));
+    }
+    for m in registry_re().find_iter(s) {
+        found.push(("registry", m.as_str().trim_end().to_string()));
+    }
+    let mut pdb = false;
+    for m in pdb_re().find_iter(s).filter(|m| m.len() > 4) {
+        pdb = true;
+        found.push(("pdb", m.as_str().to_string()));
+    }
+    if !pdb {
+        for m in path_re().find_iter(s) {
+            found.push(("path", m.as_str().trim().to_string()));
+        }
+    }
+    for m in email_re().find_iter(s) {
+        found.push(("email", m.as_str().to_string()));
+    }
+    found
+}
+
+fn printable(b: u8) -> bool {
+    (0x20..0x7F).contains(&b) || b == b'\t'
+}
+
+/// Printable ASCII runs, then UTF-16LE ones, of at least MIN_LENGTH characters.
+fn plain_strings(data: &[u8]) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut run = Vec::new();
+    for &b in data {
+        if printable(b) && run.len() < MAX_LENGTH {
+            run.push(b);
+        } else {
+            if run.len() >= MIN_LENGTH {
+                out.push(String::from_utf8_lossy(&run).to_string());
+            }
+            run.clear();
+            if printable(b) {
+                run.push(b);
+            }
+        }
+    }
+    if run.len() >= MIN_LENGTH {
+        out.push(String::from_utf8_lossy(&run).to_string());
+    }
+    out.extend(wide_strings(data));
+    out
+}
+
+fn wide_strings(data: &[u8]) -> Vec<String> {
+    let mut out = Vec::new();
+    for parity in 0..2 {
+        let mut run = String::new();
+        for pair in data.get(parity..).unwrap_or_default().chunks_exact(2) {
+            if pair[1] == 0 && printable(pair[0]) && run.len() < MAX_LENGTH {
+                run.push(pair[0] as char);
+            } else {
+                if run.len() >= MIN_LENGTH {
+                    out.push(std::mem::take(&mut run));
+                }
+                run.clear();
+            }
+        }
+        if run.len() >= MIN_LENGTH {
+            out.push(run);
+        }
+    }
+    out
+}
+
+// ---- Stack strings ----
+
+/// One `mov [ebp|esp + disp], imm`: length, base register, displacement and bytes.
+fn stack_store(code: &[u8], at: usize) -> Option<(usize, u8, i64, Vec<u8>)> {
+    let mut i = at;
+    let (mut word, mut qword) = (false, false);
+    match *code.get(i)? {
+        0x66 => {
+            word = true;
+            i += 1;
+        }
+        0x48 => {
+            qword = true;
+            i += 1;
+        }
+        _ => {}
+    }
+    let opcode = *code.get(i)?;
+    if opcode != 0xC6 && opcode != 0xC7 {
+        return None;
+    }
+    let modrm = *code.get(i + 1)?;
+    let (mode, reg, rm) = (modrm >> 6, (modrm >> 3) & 7, modrm & 7);
+    // [ebp+disp] or [esp+disp]; [esp] itself has no displacement
+    let plain_esp = mode == 0 && rm == 4;
+    if reg != 0 || !(mode == 1 || mode == 2 || plain_esp) || (rm != 4 && rm != 5) {
+        return None;
+    }
+    i += 2;
+    if rm == 4 {
+        // SIB: esp base, no index
+        if *code.get(i)? != 0x24 {
+            return None;
+        }
+        i += 1;
+    }
+    let (disp, disp_len) = match mode {
+        0 => (0, 0),
+        1 => (*code.get(i)? as i8 as i64, 1),
+        _ => (i32::from_le_bytes(code.get(i..i + 4)?.try_into().ok()?) as i64, 4),
+    };
+    i += disp_len;
+    let size = match (opcode, word) {
+        (0xC6, _) => 1,
+        (_, true) => 2,
+        _ => 4,
+    };
+    let mut bytes = code.get(i..i + size)?.to_vec();
+    i += size;
+    if qword && size == 4 {
+        // imm32 sign-extended to 64 bits
+        let fill = if bytes[3] & 0x80 != 0 { 0xFF } else { 0 };
+        bytes.extend_from_slice(&[fill; 4]);
+    }
+    Some((i - at, rm, disp, bytes))
+}
+
+/// Strings assembled on the stack: runs of stores to one frame, laid out by displacement.
+fn stack_strings(code: &[u8]) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut frame: BTreeMap<i64, u8> = BTreeMap::new();
+    let mut frame_base = 0u8;
+    let mut last_end = 0usize;
+    let mut i = 0;
+    let flush = |frame: &mut BTreeMap<i64, u8>, out: &mut Vec<String>| {
+        let mut buffer = Vec::new();
+        let mut next = None;
+        for (&disp, &b) in frame.iter() {
+            if next.is_some_and(|n| n != disp) {
+                buffer.push(0);
+            }
+            buffer.push(b);
+            next = Some(disp + 1);
+        }
+        frame.clear();
+        out.extend(plain_strings(&buffer));
+    };
+    while i < code.len() {
+        match stack_store(code, i) {
+            Some((len, base, disp, bytes)) => {
+                if !frame.is_empty() && (base != frame_base || i - last_end > STACK_GAP) {
+                    flush(&mut frame, &mut out);
+                }
+                frame_base = base;
+                for (n, b) in bytes.into_iter().enumerate() {
+                    frame.insert(disp + n as i64, b);
+                }
+                i += len;
+                last_end = i;
+            }
+            None => {
+                if !frame.is_empty() && i - last_end > STACK_GAP {
+                    flush(&mut frame, &mut out);
+                }
+                i += 1;
+            }
+        }
+        if out.len() >= MAX_LISTED {
+            break;
+        }
+    }
+    flush(&mut frame, &mut out);
+    out
+}
+
+// ---- XOR ----
+
+/// Length of the ModRM operand (ModRM, SIB and displacement) at `at`.
+fn operand_len(code: &[u8], at: usize) -> Option<usize> {
+    let modrm = *code.get(at)?;
+    let (mode, rm) = (modrm >> 6, modrm & 7);
+    if mode == 3 {
+        return Some(1);
+    }
+    let mut len = 1;
+    if rm == 4 {
+        let sib = *code.get(at + 1)?;
+        len += 1;
+        if mode == 0 && sib & 7 == 5 {
+            len += 4;
+        }
+    } else if mode == 0 && rm == 5 {
+        len += 4;
+    }
+    len += match mode {
+        1 => 1,
+        2 => 4,
+        _ => 0,
+    };
+    Some(len)
+}
+
+/// Keys of `xor ..., imm8` instructions followed closely by a backward jump.
+fn loop_keys(code: &[u8]) -> Vec<u8> {
+    let mut counts: BTreeMap<u8, usize> = BTreeMap::new();
+    for at in 0..code.len() {
+        let (len, key) = match code[at] {
+            0x80 | 0x83 => {
+                let Some(modrm) = code.get(at + 1) else { continue };
+                if (modrm >> 3) & 7 != 6 {
+                    continue;
+                }
+                let Some(operand) = operand_len(code, at + 1) else { continue };
+                let Some(&key) = code.get(at + 1 + operand) else { continue };
+                (2 + operand, key)
+            }
+            0x34 => match code.get(at + 1) {
+                Some(&key) => (2, key),
+                None => continue,
+            },
+            _ => continue,
+        };
+        if key == 0 || key == 0xFF {
+            continue;
+        }
+        let end = at + len;
+        let closes_loop = (end..(end + LOOP_WINDOW).min(code.len().saturating_sub(1))).any(|j| {
+            let short = matches!(code[j], 0x72..=0x7F | 0xE2 | 0xEB);
+            let target = (j + 2) as i64 + code[j + 1] as i8 as i64;
+            short && target <= at as i64 && target >= at as i64 - LOOP_REACH as i64
+        });
+        if closes_loop {
+            *counts.entry(key).or_default() += 1;
+        }
+    }
+    let mut keys: Vec<(u8, usize)> = counts.into_iter().collect();
+    keys.sort_by_key(|k| std::cmp::Reverse(k.1));
+    keys.into_iter().take(MAX_LOOP_KEYS).map(|(k, _)| k).collect()
+}
+
+/// `data` XORed with `key`, zero bytes left alone.
+fn xor(data: &[u8], key: u8) -> Vec<u8> {
+    data.iter().map(|&b| if b == 0 { 0 } else { b ^ key }).collect()
+}
+
+/// The printable run around `at` once XORed with `key`.
+fn xor_run(data: &[u8], at: usize, key: u8) -> (usize, String) {
+    let decodes = |b: u8| b != 0 && printable(b ^ key);
+    let mut start = at;
+    while start > 0 && decodes(data[start - 1]) && at - start < MAX_LENGTH {
+        start -= 1;
+    }
+    let mut end = at;
+    while end < data.len() && decodes(data[end]) && end - start < MAX_LENGTH {
+        end += 1;
+    }
+    (start, String::from_utf8_lossy(&xor(&data[start..end], key)).to_string())
+}
+
+/// Strings whose known plaintext shows up XORed with some key: (key, string).
+fn known_plaintext_xor(data: &[u8]) -> Vec<(u8, String)> {
+    let mut out = Vec::new();
+    let mut seen: HashSet<(u8, usize)> = HashSet::new();
+    for plain in KNOWN_PLAINTEXTS {
+        for (at, window) in data.windows(plain.len()).enumerate() {
+            let key = window[0] ^ plain[0];
+            // 0x20 only flips letter case
+            if key == 0 || key == 0x20 || window.iter().zip(plain.iter()).any(|(b, p)| b ^ key != *p) {
+                continue;
+            }
+            let (start, s) = xor_run(data, at, key);
+            if s.len() >= MIN_LENGTH && seen.insert((key, start)) {
+                out.push((key, s));
+            }
+            if out.len() >= MAX_LISTED {
+                return out;
+            }
+        }
+    }
+    out
+}
+
+// ---- Analysis ----
+
+/// Section contents of an executable, split by whether they hold code.
+#[derive(Default)]
+struct Regions<'a> {
+    code: Vec<&'a [u8]>,
+    data: Vec<&'a [u8]>,
+}
+
+/// Code and data regions of an x86/x64 PE or ELF; None for other architectures.
+fn regions(bytes: &[u8]) -> Option<Regions<'_>> {
+    let slice = |offset: usize, size: usize| bytes.get(offset..offset.saturating_add(size).min(bytes.len())).unwrap_or_default();
+    let mut regions = Regions::default();
+    match goblin::Object::parse(bytes).ok()? {
+        goblin::Object::PE(pe) => {
+            let machine = pe.header.coff_header.machine;
+            if machine != goblin::pe::header::COFF_MACHINE_X86 && machine != goblin::pe::header::COFF_MACHINE_X86_64 {
+                return None;
+            }
+            for s in &pe.sections {
+                let region = slice(s.pointer_to_raw_data as usize, s.size_of_raw_data as usize);
+                if crate::pe::is_executable(s.characteristics) { regions.code.push(region) } else { regions.data.push(region) }
+            }
+        }
+        goblin::Object::Elf(elf) => {
+            if elf.header.e_machine != goblin::elf::header::EM_386 && elf.header.e_machine != goblin::elf::header::EM_X86_64 {
+                return None;
+            }
+            for s in elf.section_headers.iter().filter(|s| s.sh_type != goblin::elf::section_header::SHT_NOBITS) {
+                let region = slice(s.sh_offset as usize, s.sh_size as usize);
+                if s.sh_flags & goblin::elf::section_header::SHF_EXECINSTR as u64 != 0 { regions.code.push(region) } else { regions.data.push(region) }
+            }
+        }
+        _ => return None,
+    }
+    Some(regions)
+}
+
+/// Strings for an executable; `format` is static_properties::format's answer.
+pub fn analyze(bytes: &[u8], format: &str) -> Option<Value> {
+    if format != "pe" && format != "elf" {
+        return None;
+    }
+    let scanned = &bytes[..bytes.len().min(MAX_SCAN_BYTES)];
+    // Unparseable headers or a foreign architecture: plain strings only
+    let Regions { code, data } = regions(scanned).unwrap_or_default();
+
+    let plain = plain_strings(scanned);
+    let stack: Vec<String> = code.iter().flat_map(|c| stack_strings(c)).collect();
+    let keys: Vec<u8> = code.iter().flat_map(|c| loop_keys(c)).collect();
+    let mut decoded: Vec<(u8, String, &str)> = known_plaintext_xor(scanned).into_iter().map(|(k, s)| (k, s, "known plaintext")).collect();
+    let data_regions: Vec<&[u8]> = if data.is_empty() { vec![scanned] } else { data };
+    for &key in keys.iter().take(MAX_LOOP_KEYS) {
+        for region in &data_regions {
+            let xored = xor(region, key);
+            // Random bytes XOR to printable runs all the time; only indicators are believed
+            for s in plain_strings(&xored).into_iter().filter(|s| !categorise(s).is_empty()) {
+                if decoded.len() < MAX_LISTED && !decoded.iter().any(|(k, d, _)| *k == key && *d == s) {
+                    decoded.push((key, s, "decoding loop"));
+                }
+            }
+        }
+    }
+
+    let mut categories: BTreeMap<&str, Vec<Value>> = CATEGORIES.iter().map(|c| (*c, Vec::new())).collect();
+    let mut seen: HashSet<(&str, String)> = HashSet::new();
+    let sources = plain.iter().map(|s| (s.as_str(), "static"))
+        .chain(stack.iter().map(|s| (s.as_str(), "stack")))
+        .chain(decoded.iter().map(|(_, s, _)| (s.as_str(), "xor")));
+    for (s, source) in sources {
+        for (category, value) in categorise(s) {
+            let listed = categories.get_mut(category).unwrap();
+            if listed.len() < MAX_LISTED && seen.insert((category, value.clone())) {
+                listed.push(json!({ "value": value, "source": source }));
+            }
+        }
+    }
+
+    let mut stack_listed: Vec<String> = Vec::new();
+    for s in stack {
+        if stack_listed.len() < MAX_LISTED && !stack_listed.contains(&s) {
+            stack_listed.push(s);
+        }
+    }
+    let mut loop_keys: Vec<u8> = Vec::new();
+    for k in keys {
+        if !loop_keys.contains(&k) {
+            loop_keys.push(k);
+        }
+    }
+    Some(json!({
+        "scanned_bytes": scanned.len(),
+        "truncated": bytes.len() > scanned.len(),
+        "static_strings": plain.len(),
+        "stack_strings": stack_listed,
+        "decoded_strings": decoded.iter().map(|(k, s, method)| json!({ "string": s, "key": format!("0x{:02x}", k), "method": method })).collect::<Vec<_>>(),
+        "decoding_loop_keys": loop_keys.iter().map(|k| format!("0x{:02x}", k)).collect::<Vec<_>>(),
+        "categories": categories,
+    }))
+}
+
+/// Kept strings as "kind: value" lines, for StaticAnalysisData.strings.
+pub fn lines(props: &Value) -> Vec<String> {
+    let mut out = Vec::new();
+    for category in CATEGORIES {
+        for entry in props["categories"][category].as_array().into_iter().flatten() {
+            out.push(format!("{}: {}", category, entry["value"].as_str().unwrap_or("")));
+        }
+    }
+    for s in props["stack_strings"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+        out.push(format!("stack: {}", s));
+    }
+    for d in props["decoded_strings"].as_array().into_iter().flatten() {
+        out.push(format!("xor {}: {}", d["key"].as_str().unwrap_or("?"), d["string"].as_str().unwrap_or("")));
+    }
+    out
+}
+
+/// One paragraph for the AI prompt.
+pub fn describe(props: &Value) -> String {
+    let clipped = |s: &str| -> String { s.chars().take(120).collect() };
+    let mut out = format!("Strings: {} plain.", props["static_strings"]);
+    for category in CATEGORIES {
+        let values: Vec<String> = props["categories"][category].as_array().into_iter().flatten()
+            .filter_map(|e| e["value"].as_str()).map(clipped).collect();
+        if !values.is_empty() {
+            let shown: Vec<&str> = values.iter().take(10).map(String::as_str).collect();
+            out.push_str(&format!(" {} {}: {}.", values.len(), category, shown.join(", ")));
+        }
+    }
+    let stack: Vec<String> = props["stack_strings"].as_array().into_iter().flatten().filter_map(Value::as_str).map(clipped).collect();
+    if !stack.is_empty() {
+        out.push_str(&format!(" {} stack strings: {}.", stack.len(), stack.iter().take(15).map(String::as_str).collect::<Vec<_>>().join(", ")));
+    }
+    let decoded: Vec<String> = props["decoded_strings"].as_array().into_iter().flatten()
+        .map(|d| format!("{} (xor {})", clipped(d["string"].as_str().unwrap_or("")), d["key"].as_str().unwrap_or("?"))).collect();
+    if !decoded.is_empty() {
+        out.push_str(&format!(" {} XOR-decoded strings: {}.", decoded.len(), decoded.iter().take(15).map(String::as_str).collect::<Vec<_>>().join(", ")));
+    }
+    out
+}
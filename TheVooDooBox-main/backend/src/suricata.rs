@@ -0,0 +1,282 @@
+use actix_multipart::Multipart;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use futures::TryStreamExt;
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use tokio::io::AsyncWriteExt;
+use crate::fakenet::read_log_since;
+
+// --- SURICATA IDS ---
+
+const MAX_ALERTS_PER_TASK: usize = 1000;
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct NetworkAlert {
+    pub id: i32,
+    pub task_id: String,
+    pub timestamp: i64,
+    /// live | pcap
+    pub source: String,
+    pub signature_id: i64,
+    pub signature: String,
+    pub category: Option<String>,
+    /// Suricata severity: 1 (high) to 3 (low).
+    pub severity: i32,
+    pub src_ip: Option<String>,
+    pub src_port: Option<i32>,
+    pub dest_ip: Option<String>,
+    pub dest_port: Option<i32>,
+    pub proto: Option<String>,
+    pub app_proto: Option<String>,
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS network_alerts (
+            id SERIAL PRIMARY KEY,
+            task_id TEXT NOT NULL,
+            timestamp BIGINT NOT NULL,
+            source TEXT NOT NULL,
+            signature_id BIGINT NOT NULL,
+            signature TEXT NOT NULL,
+            category TEXT,
+            severity INTEGER NOT NULL,
+            src_ip TEXT,
+            src_port INTEGER,
+            dest_ip TEXT,
+            dest_port INTEGER,
+            proto TEXT,
+            app_proto TEXT,
+            raw JSONB
+        )"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_network_alerts_task ON network_alerts (task_id)")
+        .execute(pool)
+        .await?;
+    println!("[SURICATA] Database initialized (network_alerts).");
+    Ok(())
+}
+
+fn eve_path() -> Option<String> {
+    std::env::var("SURICATA_EVE").ok().filter(|p| !p.is_empty())
+}
+
+/// eve.json position when the detonation started; None when no live sensor is configured.
+pub struct LiveCapture {
+    path: String,
+    offset: u64,
+}
+
+impl LiveCapture {
+    pub fn begin() -> Option<Self> {
+        let path = eve_path()?;
+        let offset = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        Some(LiveCapture { path, offset })
+    }
+}
+
+async fn store(pool: &Pool<Postgres>, task_id: &str, source: &str, eve: &serde_json::Value) -> bool {
+    let alert = &eve["alert"];
+    let timestamp = eve["timestamp"].as_str()
+        .and_then(|t| chrono::DateTime::parse_from_str(t, "%Y-%m-%dT%H:%M:%S%.f%z").ok())
+        .map(|t| t.timestamp_millis())
+        .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+    let port = |key: &str| eve[key].as_i64().map(|p| p as i32);
+
+    let res = sqlx::query(
+        "INSERT INTO network_alerts (task_id, timestamp, source, signature_id, signature, category, severity, src_ip, src_port, dest_ip, dest_port, proto, app_proto, raw)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)"
+    )
+    .bind(task_id)
+    .bind(timestamp)
+    .bind(source)
+    .bind(alert["signature_id"].as_i64().unwrap_or(0))
+    .bind(alert["signature"].as_str().unwrap_or("Unknown signature"))
+    .bind(alert["category"].as_str())
+    .bind(alert["severity"].as_i64().unwrap_or(3) as i32)
+    .bind(eve["src_ip"].as_str())
+    .bind(port("src_port"))
+    .bind(eve["dest_ip"].as_str())
+    .bind(port("dest_port"))
+    .bind(eve["proto"].as_str())
+    .bind(eve["app_proto"].as_str())
+    .bind(eve)
+    .execute(pool)
+    .await;
+    match &res {
+        Ok(_) => crate::siem::forward(crate::siem::Record {
+            kind: "alert",
+            task_id: Some(task_id.to_string()),
+            timestamp,
+            name: alert["signature"].as_str().unwrap_or("Unknown signature").to_string(),
+            category: "alert".to_string(),
+            // Suricata severity 1 is the most severe
+            severity: match alert["severity"].as_i64().unwrap_or(3) { 1 => 90, 2 => 60, _ => 30 },
+            details: alert["category"].as_str().unwrap_or_default().to_string(),
+            src_ip: eve["src_ip"].as_str().map(str::to_string),
+            dest_ip: eve["dest_ip"].as_str().map(str::to_string),
+            dest_port: port("dest_port"),
+            ..Default::default()
+        }),
+        Err(e) => println!("[SURICATA] Failed to store alert for task {}: {}", task_id, e),
+    }
+    res.is_ok()
+}
+
+/// Stores alert records from eve.json lines, optionally only those involving `sandbox_ip`.
+async fn ingest_eve(pool: &Pool<Postgres>, task_id: &str, source: &str, text: &str, sandbox_ip: Option<&str>) -> usize {
+    let mut stored = 0;
+    for line in text.lines() {
+        if stored >= MAX_ALERTS_PER_TASK {
+            println!("[SURICATA] Task {} hit the {} alert cap; remaining alerts dropped", task_id, MAX_ALERTS_PER_TASK);
+            break;
+        }
+        let Ok(eve) = serde_json::from_str::<serde_json::Value>(line) else { continue };
+        if eve["event_type"].as_str() != Some("alert") {
+            continue;
+        }
+        if let Some(ip) = sandbox_ip {
+            if eve["src_ip"].as_str() != Some(ip) && eve["dest_ip"].as_str() != Some(ip) {
+                continue;
+            }
+        }
+        if store(pool, task_id, source, &eve).await {
+            stored += 1;
+        }
+    }
+    stored
+}
+
+/// Keeps the live sensor's alerts for this sandbox since `capture`.
+pub async fn collect_live(pool: &Pool<Postgres>, task_id: &str, capture: &LiveCapture, sandbox_ip: &str) -> usize {
+    let text = match read_log_since(&capture.path, capture.offset).await {
+        Ok(t) => t,
+        Err(e) => {
+            println!("[SURICATA] Cannot read eve log {}: {}", capture.path, e);
+            return 0;
+        }
+    };
+    let stored = ingest_eve(pool, task_id, "live", &text, Some(sandbox_ip)).await;
+    println!("[SURICATA] Task {}: {} live alerts for {}", task_id, stored, sandbox_ip);
+    stored
+}
+
+/// Replays a capture through Suricata and stores the alerts.
+pub async fn scan_pcap(pool: &Pool<Postgres>, task_id: &str, pcap: &std::path::Path) -> Result<usize, String> {
+    let out_dir = pcap.with_extension("suricata");
+    tokio::fs::create_dir_all(&out_dir).await.map_err(|e| e.to_string())?;
+
+    let bin = std::env::var("SURICATA_BIN").unwrap_or_else(|_| "suricata".to_string());
+    let mut cmd = tokio::process::Command::new(&bin);
+    cmd.arg("-r").arg(pcap).arg("-l").arg(&out_dir).args(["-k", "none"]);
+    if let Ok(config) = std::env::var("SURICATA_CONFIG") {
+        cmd.arg("-c").arg(config);
+    }
+    if let Ok(rules) = std::env::var("SURICATA_RULES") {
+        cmd.arg("-S").arg(rules);
+    }
+    let output = cmd.output().await.map_err(|e| format!("Failed to run {}: {}", bin, e))?;
+    if !output.status.success() {
+        let _ = tokio::fs::remove_dir_all(&out_dir).await;
+        return Err(format!("Suricata exited with {}: {}", output.status, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    let eve = tokio::fs::read_to_string(out_dir.join("eve.json")).await.unwrap_or_default();
+    let stored = ingest_eve(pool, task_id, "pcap", &eve, None).await;
+    let _ = tokio::fs::remove_dir_all(&out_dir).await;
+    println!("[SURICATA] Task {}: {} alerts from {}", task_id, stored, pcap.display());
+    Ok(stored)
+}
+
+/// Highest-severity first, one row per signature, for the report.
+pub async fn top_alerts(pool: &Pool<Postgres>, task_id: &str, limit: i64) -> Vec<NetworkAlert> {
+    sqlx::query_as::<_, NetworkAlert>(
+        "SELECT DISTINCT ON (signature_id) id, task_id, timestamp, source, signature_id, signature, category, severity, src_ip, src_port, dest_ip, dest_port, proto, app_proto
+         FROM network_alerts WHERE task_id = $1
+         ORDER BY signature_id, timestamp"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await
+    .map(|mut alerts| {
+        alerts.sort_by_key(|a| (a.severity, a.timestamp));
+        alerts.truncate(limit as usize);
+        alerts
+    })
+    .unwrap_or_default()
+}
+
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
+#[get("/tasks/{id}/network-alerts")]
+pub async fn list_network_alerts(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    match sqlx::query_as::<_, NetworkAlert>(
+        "SELECT id, task_id, timestamp, source, signature_id, signature, category, severity, src_ip, src_port, dest_ip, dest_port, proto, app_proto
+         FROM network_alerts WHERE task_id = $1 ORDER BY timestamp"
+    )
+    .bind(path.into_inner())
+    .fetch_all(pool.get_ref())
+    .await
+    {
+        Ok(alerts) => HttpResponse::Ok().json(alerts),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Multipart upload of a .pcap/.pcapng for the task; scanned in the background.
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
+#[post("/tasks/{id}/pcap")]
+pub async fn upload_pcap(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, actix_web::Error> {
+    let task_id = path.into_inner();
+    let exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM tasks WHERE id = $1")
+        .bind(&task_id)
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(0);
+    if exists == 0 {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" })));
+    }
+
+    let dir = std::path::PathBuf::from("./uploads/pcap");
+    tokio::fs::create_dir_all(&dir).await.map_err(actix_web::error::ErrorInternalServerError)?;
+    let pcap = dir.join(format!("{}-{}.pcap", task_id, chrono::Utc::now().timestamp_millis()));
+
+    let mut received = false;
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        if field.content_disposition().and_then(|cd| cd.get_filename()).is_none() {
+            continue;
+        }
+        let mut f = tokio::fs::File::create(&pcap).await.map_err(actix_web::error::ErrorInternalServerError)?;
+        while let Ok(Some(chunk)) = field.try_next().await {
+            f.write_all(&chunk).await.map_err(actix_web::error::ErrorInternalServerError)?;
+        }
+        received = true;
+        break;
+    }
+    if !received {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": "No capture file uploaded" })));
+    }
+
+    let scan_pool = pool.get_ref().clone();
+    let scan_task = task_id.clone();
+    let scan_path = pcap.clone();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = scan_pcap(&scan_pool, &scan_task, &scan_path).await {
+            println!("[SURICATA] PCAP scan for task {} failed: {}", scan_task, e);
+        }
+    });
+
+    Ok(HttpResponse::Accepted().json(serde_json::json!({
+        "status": "scanning",
+        "task_id": task_id,
+        "message": "Capture received; alerts appear under /tasks/{id}/network-alerts when the scan finishes"
+    })))
+}
@@ -0,0 +1,1161 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{watch, Mutex, Notify};
+use crate::progress_stream::ProgressBroadcaster;
+use crate::hypervisor::Hypervisor;
+use crate::cloud::{self, CloudBurst};
+use crate::{analysis_profiles, comparison, network_policy, orchestrate_sandbox, sandbox_pool, static_only, AgentManager, AIManager};
+
+// --- ANALYSIS QUEUE ---
+
+/// How often the scheduler re-checks the queue when nothing wakes it (pinned VMs freeing up, etc).
+const IDLE_POLL: Duration = Duration::from_secs(15);
+
+/// How long a local sandbox gets to boot and connect its agent after the revert.
+pub const AGENT_TIMEOUT_SECS: u64 = 90;
+
+/// Dispatch order: urgent before normal before bulk, FIFO within a priority.
+const DISPATCH_ORDER: &str = "CASE priority WHEN 'urgent' THEN 0 WHEN 'bulk' THEN 2 ELSE 1 END, enqueued_at ASC";
+
+/// SANDBOX_PREFLIGHT_SECS: boot-and-connect budget before a pool VM is swapped out.
+const DEFAULT_PREFLIGHT_SECS: u64 = 45;
+
+/// Pre-flight failovers per task before it stays on whatever VM it gets and waits it out.
+const MAX_PREFLIGHT_RETRIES: i32 = 2;
+
+/// Per-run overhead (revert, boot, report) assumed for estimates until a few runs have finished.
+const DEFAULT_OVERHEAD_MS: i64 = 3 * 60 * 1000;
+
+pub const DEFAULT_PRIORITY: &str = "normal";
+
+/// Stages after which all telemetry is in and only the report is left.
+const RESUMABLE_STAGES: [&str; 3] = ["stopping_vm", "memory_analysis", "ai_analysis"];
+
+const DEFAULT_SHUTDOWN_GRACE_SECS: u64 = 60;
+
+/// Normalises a submitted priority to urgent | normal | bulk.
+pub fn parse_priority(raw: &str) -> Option<&'static str> {
+    match raw.trim().to_lowercase().as_str() {
+        "urgent" | "high" => Some("urgent"),
+        "normal" | "" => Some("normal"),
+        "bulk" | "low" => Some("bulk"),
+        _ => None,
+    }
+}
+
+/// Everything orchestrate_sandbox needs to (re)run a task from scratch.
+pub struct QueuedAnalysis {
+    pub task_id: String,
+    pub target_url: String,
+    pub original_filename: String,
+    pub duration_seconds: u64,
+    /// Explicit VM choice from the submitter; both must be set for it to be honoured.
+    pub vmid: Option<u64>,
+    pub node: Option<String>,
+    pub is_url_task: bool,
+    pub analysis_mode: String,
+    /// Restricts auto-assignment to pool VMs with this OS profile (e.g. "windows11").
+    pub os_profile: Option<String>,
+    /// urgent | normal | bulk
+    pub priority: String,
+    /// Analysis profile the submission resolved to; its agent settings are applied at run time.
+    pub profile: Option<String>,
+}
+
+#[derive(Serialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct QueueEntry {
+    pub task_id: String,
+    pub target_url: String,
+    pub original_filename: String,
+    pub duration_seconds: i64,
+    pub vmid: Option<i64>,
+    pub node: Option<String>,
+    pub is_url_task: bool,
+    pub analysis_mode: String,
+    #[sqlx(default)]
+    pub os_profile: Option<String>,
+    #[sqlx(default)]
+    pub priority: String,
+    #[sqlx(default)]
+    pub profile: Option<String>,
+    /// queued | running | done | cancelled
+    pub state: String,
+    pub assigned_vmid: Option<i64>,
+    pub assigned_node: Option<String>,
+    pub enqueued_at: i64,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    /// Provider instance ID while the task runs on a cloud burst instance.
+    #[sqlx(default)]
+    pub cloud_instance_id: Option<String>,
+    /// Pool VMs that already failed pre-flight checks for this task.
+    #[sqlx(default)]
+    pub preflight_failures: i32,
+}
+
+/// The sandbox a queued task was scheduled onto.
+#[derive(Clone)]
+pub struct AssignedVm {
+    pub vmid: u64,
+    pub node: String,
+    pub name: String,
+    pub snapshot: String,
+    pub agent_timeout_secs: u64,
+    /// Pre-flight window; only set when a failing VM can be swapped.
+    pub preflight_secs: Option<u64>,
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS task_queue (
+            task_id TEXT PRIMARY KEY,
+            target_url TEXT NOT NULL,
+            original_filename TEXT NOT NULL,
+            duration_seconds BIGINT NOT NULL,
+            vmid BIGINT,
+            node TEXT,
+            is_url_task BOOLEAN NOT NULL DEFAULT FALSE,
+            analysis_mode TEXT NOT NULL,
+            state TEXT NOT NULL DEFAULT 'queued',
+            assigned_vmid BIGINT,
+            assigned_node TEXT,
+            enqueued_at BIGINT NOT NULL,
+            started_at BIGINT,
+            finished_at BIGINT
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query("ALTER TABLE task_queue ADD COLUMN IF NOT EXISTS os_profile TEXT")
+        .execute(pool)
+        .await?;
+
+    sqlx::query("ALTER TABLE task_queue ADD COLUMN IF NOT EXISTS priority TEXT NOT NULL DEFAULT 'normal'")
+        .execute(pool)
+        .await?;
+
+    sqlx::query("ALTER TABLE task_queue ADD COLUMN IF NOT EXISTS profile TEXT")
+        .execute(pool)
+        .await?;
+
+    sqlx::query("ALTER TABLE task_queue ADD COLUMN IF NOT EXISTS cloud_instance_id TEXT")
+        .execute(pool)
+        .await?;
+
+    sqlx::query("ALTER TABLE task_queue ADD COLUMN IF NOT EXISTS preflight_failures INTEGER NOT NULL DEFAULT 0")
+        .execute(pool)
+        .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_task_queue_state ON task_queue(state, enqueued_at)")
+        .execute(pool)
+        .await?;
+
+    for column in [
+        "orchestration_stage TEXT",
+        "orchestration_vmid BIGINT",
+        "orchestration_node TEXT",
+        "orchestration_session TEXT",
+        "orchestration_updated_at BIGINT",
+    ] {
+        sqlx::query(&format!("ALTER TABLE tasks ADD COLUMN IF NOT EXISTS {}", column))
+            .execute(pool)
+            .await?;
+    }
+
+    println!("[QUEUE] Database initialized (task_queue).");
+    Ok(())
+}
+
+/// MAX_CONCURRENT_ANALYSES, default 1 (the historical single-sandbox behaviour).
+fn max_concurrent_from_env() -> usize {
+    std::env::var("MAX_CONCURRENT_ANALYSES")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(1)
+}
+
+fn preflight_secs_from_env() -> u64 {
+    std::env::var("SANDBOX_PREFLIGHT_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_PREFLIGHT_SECS)
+}
+
+fn shutdown_grace_from_env() -> Duration {
+    let secs = std::env::var("SHUTDOWN_GRACE_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_SHUTDOWN_GRACE_SECS);
+    Duration::from_secs(secs)
+}
+
+/// Records how far a run got; the restart logic decides from this whether to resume or rerun.
+pub async fn checkpoint(pool: &Pool<Postgres>, task_id: &str, stage: &str) {
+    let _ = sqlx::query("UPDATE tasks SET orchestration_stage=$2, orchestration_updated_at=$3 WHERE id=$1")
+        .bind(task_id)
+        .bind(stage)
+        .bind(chrono::Utc::now().timestamp_millis())
+        .execute(pool)
+        .await;
+}
+
+/// The VM a run holds; cleared with the session when the run is re-queued.
+pub async fn checkpoint_vm(pool: &Pool<Postgres>, task_id: &str, vmid: u64, node: &str) {
+    let _ = sqlx::query("UPDATE tasks SET orchestration_vmid=$2, orchestration_node=$3, orchestration_session=NULL, orchestration_updated_at=$4 WHERE id=$1")
+        .bind(task_id)
+        .bind(vmid as i64)
+        .bind(node)
+        .bind(chrono::Utc::now().timestamp_millis())
+        .execute(pool)
+        .await;
+}
+
+pub async fn checkpoint_session(pool: &Pool<Postgres>, task_id: &str, session_id: &str) {
+    let _ = sqlx::query("UPDATE tasks SET orchestration_session=$2, orchestration_updated_at=$3 WHERE id=$1")
+        .bind(task_id)
+        .bind(session_id)
+        .bind(chrono::Utc::now().timestamp_millis())
+        .execute(pool)
+        .await;
+}
+
+async fn clear_checkpoint(pool: &Pool<Postgres>, task_ids: &[String]) {
+    let _ = sqlx::query(
+        "UPDATE tasks SET orchestration_stage=NULL, orchestration_vmid=NULL, orchestration_node=NULL, orchestration_session=NULL
+         WHERE id = ANY($1)"
+    )
+    .bind(task_ids)
+    .execute(pool)
+    .await;
+}
+
+/// Takes a VM that failed pre-flight out of rotation and re-queues its task.
+pub async fn fail_over(pool: &Pool<Postgres>, progress: &ProgressBroadcaster, task_id: &str, vmid: u64, reason: &str) {
+    println!("[PREFLIGHT] VM {} failed pre-flight for task {}: {}. Re-queuing on another VM.", vmid, task_id, reason);
+    sandbox_pool::mark_broken(pool, vmid, &format!("Pre-flight: {}", reason)).await;
+    let requeued = sqlx::query(
+        "UPDATE task_queue SET state='queued', assigned_vmid=NULL, assigned_node=NULL, started_at=NULL,
+                preflight_failures = preflight_failures + 1
+         WHERE task_id=$1 AND state='running'"
+    )
+    .bind(task_id)
+    .execute(pool)
+    .await;
+    if !matches!(requeued, Ok(ref r) if r.rows_affected() == 1) {
+        return;
+    }
+    let _ = sqlx::query("UPDATE tasks SET status='Queued', sandbox_id=NULL WHERE id=$1")
+        .bind(task_id)
+        .execute(pool)
+        .await;
+    clear_checkpoint(pool, &[task_id.to_string()]).await;
+    progress.send_progress(task_id, "queued", &format!("Sandbox failed pre-flight checks ({}); moving to another VM", reason), 0);
+}
+
+/// Where a queued task stands; the start time assumes every slot takes queued work in order.
+pub struct QueueEstimate {
+    /// 1-based position in dispatch order.
+    pub position: i64,
+    /// Unix millis.
+    pub estimated_start: i64,
+}
+
+pub struct TaskScheduler {
+    client: Arc<dyn Hypervisor>,
+    manager: Arc<AgentManager>,
+    pool: Pool<Postgres>,
+    ai_manager: AIManager,
+    progress: Arc<ProgressBroadcaster>,
+    max_concurrent: usize,
+    /// Overflow onto cloud instances when the pool is exhausted; capped separately.
+    cloud: Option<CloudBurst>,
+    /// VMIDs currently held by a running analysis; its size is the live concurrency.
+    busy_vms: Mutex<HashSet<u64>>,
+    /// Cancel signal per running task.
+    cancels: Mutex<HashMap<String, Arc<Notify>>>,
+    wake: Notify,
+    /// Flipped once on shutdown; running jobs watch it.
+    stopping: watch::Sender<bool>,
+}
+
+/// How a dispatched job's run ended.
+enum RunEnd {
+    Finished,
+    Cancelled,
+    /// Interrupted by shutdown and put back in the queue, or left for the restart to resume.
+    Suspended,
+}
+
+/// A run that was still in flight when the backend went down, with its last checkpoint.
+#[derive(sqlx::FromRow)]
+struct Interrupted {
+    task_id: String,
+    analysis_mode: String,
+    stage: Option<String>,
+    vmid: Option<i64>,
+    node: Option<String>,
+    session_id: Option<String>,
+}
+
+/// Where dispatch put a job; cloud instances are launched inside the job's own task.
+enum Placement {
+    Local(AssignedVm),
+    Cloud(u64),
+}
+
+pub enum CancelOutcome {
+    Dequeued,
+    Interrupted,
+    NotActive,
+}
+
+impl TaskScheduler {
+    pub fn new(
+        client: Arc<dyn Hypervisor>,
+        manager: Arc<AgentManager>,
+        pool: Pool<Postgres>,
+        ai_manager: AIManager,
+        progress: Arc<ProgressBroadcaster>,
+    ) -> Self {
+        Self {
+            client,
+            manager,
+            pool,
+            ai_manager,
+            progress,
+            max_concurrent: max_concurrent_from_env(),
+            cloud: CloudBurst::from_env(),
+            busy_vms: Mutex::new(HashSet::new()),
+            cancels: Mutex::new(HashMap::new()),
+            wake: Notify::new(),
+            stopping: watch::channel(false).0,
+        }
+    }
+
+    /// Static-only submissions skip the queue entirely; their report is produced right away.
+    pub fn run_static(&self, task_id: String) {
+        let (pool, ai_manager, manager, progress) = (self.pool.clone(), self.ai_manager.clone(), self.manager.clone(), self.progress.clone());
+        actix_web::rt::spawn(static_only::run(pool, ai_manager, manager, progress, task_id));
+    }
+
+    /// Persists the job and nudges the scheduler. The tasks row must already exist.
+    pub async fn enqueue(&self, job: QueuedAnalysis) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO task_queue (task_id, target_url, original_filename, duration_seconds, vmid, node, is_url_task, analysis_mode, os_profile, priority, profile, state, enqueued_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, 'queued', $12)"
+        )
+        .bind(&job.task_id)
+        .bind(&job.target_url)
+        .bind(&job.original_filename)
+        .bind(job.duration_seconds as i64)
+        .bind(job.vmid.map(|v| v as i64))
+        .bind(&job.node)
+        .bind(job.is_url_task)
+        .bind(&job.analysis_mode)
+        .bind(&job.os_profile)
+        .bind(&job.priority)
+        .bind(&job.profile)
+        .bind(chrono::Utc::now().timestamp_millis())
+        .execute(&self.pool)
+        .await?;
+
+        println!("[QUEUE] Task {} queued ({} mode, {} priority).", job.task_id, job.analysis_mode, job.priority);
+        crate::metrics::inc("voodoobox_tasks_queued_total", &[("mode", &job.analysis_mode)], 1.0);
+        self.progress.send_progress(&job.task_id, "queued", "Waiting for a free sandbox", 0);
+        self.wake.notify_one();
+        Ok(())
+    }
+
+    /// Scheduler loop; run once at startup.
+    pub async fn run(self: Arc<Self>) {
+        self.recover_interrupted().await;
+        println!("[QUEUE] Scheduler started (max concurrent analyses: {}).", self.max_concurrent);
+
+        let mut stopping = self.stopping.subscribe();
+        while !*stopping.borrow() {
+            self.clone().dispatch().await;
+            tokio::select! {
+                _ = tokio::time::timeout(IDLE_POLL, self.wake.notified()) => {}
+                _ = stopping.changed() => {}
+            }
+        }
+        println!("[QUEUE] Scheduler stopped dispatching.");
+    }
+
+    /// Stops dispatching; returns once every job has let go of its VM.
+    pub async fn shutdown(&self) {
+        let grace = shutdown_grace_from_env();
+        let running = self.cancels.lock().await.len();
+        println!("[QUEUE] Shutting down; {} running analysis job(s), {}s grace.", running, grace.as_secs());
+        self.stopping.send_replace(true);
+
+        // Reverting a VM after the grace period still has to happen before we exit
+        let deadline = tokio::time::Instant::now() + grace + Duration::from_secs(60);
+        while !self.cancels.lock().await.is_empty() && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        let left = self.cancels.lock().await.len();
+        if left > 0 {
+            println!("[QUEUE] {} job(s) still running at exit; they are picked up after the restart.", left);
+        }
+    }
+
+    /// Resolves once shutdown has begun.
+    async fn stopped(&self) {
+        let mut stopping = self.stopping.subscribe();
+        let _ = stopping.wait_for(|s| *s).await;
+    }
+
+    /// Mid-detonation jobs start over; jobs past the VM phase resume at the report.
+    async fn recover_interrupted(self: &Arc<Self>) {
+        // Cloud instances from before the restart would otherwise run (and bill) forever
+        let orphans: Vec<String> = sqlx::query_scalar(
+            "SELECT cloud_instance_id FROM task_queue WHERE state='running' AND cloud_instance_id IS NOT NULL"
+        )
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_default();
+        for instance_id in &orphans {
+            match &self.cloud {
+                Some(burst) => burst.terminate_orphan(instance_id).await,
+                None => println!("[CLOUD] Warning: Instance {} outlived a restart but CLOUD_PROVIDER is unset; terminate it manually.", instance_id),
+            }
+        }
+
+        let resumable = sqlx::query_as::<_, Interrupted>(
+            "SELECT q.task_id, q.analysis_mode, t.orchestration_stage AS stage, t.orchestration_vmid AS vmid,
+                    t.orchestration_node AS node, t.orchestration_session AS session_id
+             FROM task_queue q JOIN tasks t ON t.id = q.task_id
+             WHERE q.state='running' AND t.orchestration_stage = ANY($1)"
+        )
+        .bind(&RESUMABLE_STAGES[..])
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_default();
+        let resumed: Vec<String> = resumable.iter().map(|r| r.task_id.clone()).collect();
+
+        let interrupted: Vec<String> = sqlx::query_scalar(
+            "UPDATE task_queue SET state='queued', assigned_vmid=NULL, assigned_node=NULL, started_at=NULL, cloud_instance_id=NULL
+             WHERE state='running' AND NOT (task_id = ANY($1)) RETURNING task_id"
+        )
+        .bind(&resumed)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_default();
+
+        // A run cut off while stopping its VM may have left it dirty; revert before anything is dispatched to it
+        for job in resumable.iter().filter(|r| r.stage.as_deref() == Some("stopping_vm")) {
+            if let (Some(vmid), Some(node)) = (job.vmid.map(|v| v as u64), &job.node) {
+                if !cloud::is_cloud_vmid(vmid) {
+                    self.revert_vm(&job.task_id, vmid, node).await;
+                }
+            }
+        }
+
+        sandbox_pool::release_all(&self.pool).await;
+        if !interrupted.is_empty() {
+            let _ = sqlx::query("UPDATE tasks SET status='Queued' WHERE id = ANY($1)")
+                .bind(&interrupted)
+                .execute(&self.pool)
+                .await;
+            clear_checkpoint(&self.pool, &interrupted).await;
+            println!("[QUEUE] Re-queued {} task(s) interrupted by restart.", interrupted.len());
+        }
+        if !resumable.is_empty() {
+            println!("[QUEUE] Resuming {} task(s) at report generation.", resumable.len());
+        }
+        for job in resumable {
+            self.resume(job).await;
+        }
+
+        let pending: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM task_queue WHERE state='queued'")
+            .fetch_one(&self.pool)
+            .await
+            .unwrap_or(0);
+        if pending > 0 {
+            println!("[QUEUE] Resuming {} queued task(s).", pending);
+        }
+    }
+
+    /// Starts queued jobs by priority, then age, while slots and VMs are free.
+    async fn dispatch(self: Arc<Self>) {
+        if *self.stopping.borrow() {
+            return;
+        }
+        let queued = match sqlx::query_as::<_, QueueEntry>(
+            &format!("SELECT * FROM task_queue WHERE state='queued' ORDER BY {}", DISPATCH_ORDER)
+        )
+        .fetch_all(&self.pool)
+        .await {
+            Ok(rows) => rows,
+            Err(e) => {
+                println!("[QUEUE] Failed to read queue: {}", e);
+                return;
+            }
+        };
+
+        let mut busy = self.busy_vms.lock().await;
+        // Profiles (None = any) with no free VM left this round
+        let mut exhausted: HashSet<Option<String>> = HashSet::new();
+
+        for entry in queued {
+            let local_full = busy.iter().filter(|v| !cloud::is_cloud_vmid(**v)).count() >= self.max_concurrent;
+            let cloud_slot = self.cloud.as_ref().and_then(|c| c.free_slot(&busy));
+            if local_full && cloud_slot.is_none() {
+                break;
+            }
+
+            // Pinned to a registered VM: wait for it. Pinned to an unregistered VM: ad hoc,
+            // tracked in memory only. Otherwise take whatever the pool has free, then burst.
+            let placement = match (entry.vmid, &entry.node) {
+                (Some(vmid), Some(node)) => {
+                    let vmid = vmid as u64;
+                    if local_full || busy.contains(&vmid) {
+                        continue; // Pinned VM in use; later jobs may still fit elsewhere
+                    }
+                    match sandbox_pool::get(&self.pool, vmid).await {
+                        Ok(Some(_)) => match sandbox_pool::claim(&self.pool, vmid, &entry.task_id).await {
+                            Ok(Some(pooled)) => Placement::Local(self.assigned(pooled, None).await),
+                            _ => continue,
+                        },
+                        Ok(None) => Placement::Local(AssignedVm {
+                            vmid,
+                            node: node.clone(),
+                            name: self.vm_name(node, vmid).await,
+                            snapshot: sandbox_pool::default_snapshot(),
+                            agent_timeout_secs: AGENT_TIMEOUT_SECS,
+                            preflight_secs: None,
+                        }),
+                        Err(_) => continue,
+                    }
+                }
+                _ => {
+                    let pooled = if local_full || exhausted.contains(&entry.os_profile) {
+                        None
+                    } else {
+                        match sandbox_pool::claim_free(&self.pool, &entry.task_id, entry.os_profile.as_deref()).await {
+                            Ok(Some(pooled)) => Some(pooled),
+                            _ => {
+                                exhausted.insert(entry.os_profile.clone());
+                                None
+                            }
+                        }
+                    };
+                    match (pooled, cloud_slot) {
+                        (Some(pooled), _) => {
+                            let preflight = (entry.preflight_failures < MAX_PREFLIGHT_RETRIES).then(preflight_secs_from_env);
+                            Placement::Local(self.assigned(pooled, preflight).await)
+                        }
+                        (None, Some(slot)) if self.cloud.as_ref().is_some_and(|c| c.serves(entry.os_profile.as_deref())) => {
+                            // Burst instances get whatever network the provider subnet gives them,
+                            // so tasks with a restricted internet policy wait for a local VM
+                            match network_policy::for_task(&self.pool, &entry.task_id).await.as_deref() {
+                                None | Some("full") => Placement::Cloud(slot),
+                                Some(_) => continue,
+                            }
+                        }
+                        _ => continue,
+                    }
+                }
+            };
+
+            let (vmid, node) = match &placement {
+                Placement::Local(vm) => (vm.vmid, vm.node.clone()),
+                Placement::Cloud(slot) => (*slot, self.cloud.as_ref().map(|c| c.kind()).unwrap_or_default().to_string()),
+            };
+            let claimed = sqlx::query(
+                "UPDATE task_queue SET state='running', assigned_vmid=$2, assigned_node=$3, started_at=$4
+                 WHERE task_id=$1 AND state='queued'"
+            )
+            .bind(&entry.task_id)
+            .bind(vmid as i64)
+            .bind(&node)
+            .bind(chrono::Utc::now().timestamp_millis())
+            .execute(&self.pool)
+            .await;
+            if !matches!(claimed, Ok(ref r) if r.rows_affected() == 1) {
+                sandbox_pool::release(&self.pool, vmid).await;
+                continue;
+            }
+
+            busy.insert(vmid);
+            match &placement {
+                Placement::Local(vm) => println!("[QUEUE] Task {} -> VM {} ({}) on {} [{}/{} slots]", entry.task_id, vm.vmid, vm.name, vm.node, busy.len(), self.max_concurrent),
+                Placement::Cloud(_) => println!("[QUEUE] Task {} -> {} burst instance (pool exhausted)", entry.task_id, node),
+            }
+
+            let cancel = Arc::new(Notify::new());
+            self.cancels.lock().await.insert(entry.task_id.clone(), cancel.clone());
+
+            let scheduler = self.clone();
+            actix_web::rt::spawn(async move {
+                let task_id = entry.task_id.clone();
+                let (client, ephemeral, vm) = match placement {
+                    Placement::Local(vm) => (scheduler.client.clone(), None, vm),
+                    Placement::Cloud(slot) => match scheduler.launch_cloud(&task_id, slot).await {
+                        Some((ephemeral, vm)) => (ephemeral.clone() as Arc<dyn Hypervisor>, Some(ephemeral), vm),
+                        None => {
+                            scheduler.cancels.lock().await.remove(&task_id);
+                            scheduler.finish(&task_id, slot).await;
+                            return;
+                        }
+                    },
+                };
+                let held = vm.clone();
+                // Settings are read at run time, so profile edits apply to jobs still waiting
+                let profile = match &entry.profile {
+                    Some(name) => analysis_profiles::get(&scheduler.pool, name).await.ok().flatten(),
+                    None => None,
+                };
+                // Run on its own task so a panic comes back as an Err here instead of leaking the VM
+                let mut run = actix_web::rt::spawn(orchestrate_sandbox(
+                    client.clone(),
+                    scheduler.manager.clone(),
+                    scheduler.pool.clone(),
+                    scheduler.ai_manager.clone(),
+                    entry.task_id,
+                    entry.target_url,
+                    entry.original_filename,
+                    entry.duration_seconds as u64,
+                    vm,
+                    entry.is_url_task,
+                    entry.analysis_mode,
+                    profile,
+                    scheduler.progress.clone(),
+                ));
+                // Aborting the orchestration task is what breaks its wait/sleep loops
+                let end = tokio::select! {
+                    res = &mut run => match res {
+                        Ok(()) => RunEnd::Finished,
+                        Err(e) => {
+                            scheduler.crashed(client.as_ref(), &task_id, &held, &e.to_string()).await;
+                            RunEnd::Finished
+                        }
+                    },
+                    _ = cancel.notified() => {
+                        run.abort();
+                        scheduler.abort(client.as_ref(), &task_id, &held).await;
+                        RunEnd::Cancelled
+                    }
+                    _ = scheduler.stopped() => {
+                        if scheduler.report_only(&task_id).await {
+                            match tokio::time::timeout(shutdown_grace_from_env(), &mut run).await {
+                                Ok(Ok(())) => RunEnd::Finished,
+                                Ok(Err(e)) => {
+                                    scheduler.crashed(client.as_ref(), &task_id, &held, &e.to_string()).await;
+                                    RunEnd::Finished
+                                }
+                                Err(_) => {
+                                    run.abort();
+                                    RunEnd::Suspended
+                                }
+                            }
+                        } else {
+                            run.abort();
+                            scheduler.suspend(client.as_ref(), &task_id, &held).await;
+                            RunEnd::Suspended
+                        }
+                    }
+                };
+                // Orchestration bails out early on some failures; never leave an instance behind
+                if let Some(ephemeral) = ephemeral {
+                    if let Err(e) = ephemeral.terminate().await {
+                        println!("[CLOUD] CRITICAL: Failed to terminate instance {}: {}", ephemeral.instance.id, e);
+                    }
+                }
+                match end {
+                    RunEnd::Suspended => scheduler.release(held.vmid).await,
+                    RunEnd::Finished | RunEnd::Cancelled => scheduler.finish(&task_id, held.vmid).await,
+                }
+                scheduler.cancels.lock().await.remove(&task_id);
+            });
+        }
+
+        let local_busy = busy.iter().filter(|v| !cloud::is_cloud_vmid(**v)).count();
+        if !exhausted.is_empty() && local_busy < self.max_concurrent {
+            println!("[QUEUE] Jobs waiting but no free VM in the sandbox pool.");
+        }
+    }
+
+    /// Boots the burst instance for a dispatched job. On failure the task is marked failed and None returned.
+    async fn launch_cloud(&self, task_id: &str, slot: u64) -> Option<(Arc<cloud::EphemeralVm>, AssignedVm)> {
+        let burst = self.cloud.as_ref()?;
+        let _ = sqlx::query("UPDATE tasks SET status='Launching Cloud Sandbox' WHERE id=$1")
+            .bind(task_id)
+            .execute(&self.pool)
+            .await;
+        self.progress.send_progress(task_id, "preparing", &format!("Launching {} sandbox instance", burst.kind()), 5);
+
+        match burst.launch(task_id, slot).await {
+            Ok((ephemeral, vm)) => {
+                let _ = sqlx::query("UPDATE task_queue SET cloud_instance_id=$2 WHERE task_id=$1")
+                    .bind(task_id)
+                    .bind(&ephemeral.instance.id)
+                    .execute(&self.pool)
+                    .await;
+                Some((ephemeral, vm))
+            }
+            Err(e) => {
+                println!("[CLOUD] Failed to launch instance for task {}: {}", task_id, e);
+                let _ = sqlx::query("UPDATE tasks SET status='Failed (Cloud Launch)', completed_at=$2 WHERE id=$1")
+                    .bind(task_id)
+                    .bind(chrono::Utc::now().timestamp_millis())
+                    .execute(&self.pool)
+                    .await;
+                self.progress.send_progress(task_id, "failed", &format!("Cloud sandbox launch failed: {}", e), 100);
+                None
+            }
+        }
+    }
+
+    /// Drops a queued job, or interrupts a running one and cleans up its VM.
+    pub async fn cancel(&self, task_id: &str) -> CancelOutcome {
+        let dequeued = sqlx::query("UPDATE task_queue SET state='cancelled', finished_at=$2 WHERE task_id=$1 AND state='queued'")
+            .bind(task_id)
+            .bind(chrono::Utc::now().timestamp_millis())
+            .execute(&self.pool)
+            .await;
+        if matches!(dequeued, Ok(ref r) if r.rows_affected() == 1) {
+            let _ = sqlx::query("UPDATE tasks SET status='Cancelled', completed_at=$2 WHERE id=$1")
+                .bind(task_id)
+                .bind(chrono::Utc::now().timestamp_millis())
+                .execute(&self.pool)
+                .await;
+            self.progress.send_progress(task_id, "cancelled", "Removed from queue", 100);
+            println!("[QUEUE] Task {} cancelled before it started.", task_id);
+            comparison::refresh_parent(&self.pool, task_id).await;
+            return CancelOutcome::Dequeued;
+        }
+
+        match self.cancels.lock().await.get(task_id) {
+            Some(signal) => {
+                // notify_one stores a permit, so this cannot be lost between select! polls
+                signal.notify_one();
+                CancelOutcome::Interrupted
+            }
+            None => CancelOutcome::NotActive,
+        }
+    }
+
+    /// Frees the agent session and reverts the VM of an interrupted run.
+    async fn abort(&self, client: &dyn Hypervisor, task_id: &str, vm: &AssignedVm) {
+        println!("[QUEUE] Cancelling task {} on VM {} ({})", task_id, vm.vmid, vm.name);
+        self.progress.send_progress(task_id, "cancelling", "Cancelling analysis and reverting sandbox", 90);
+        self.unbind_sessions(task_id).await;
+        self.rollback(client, vm, "cancel").await;
+
+        let _ = sqlx::query("UPDATE tasks SET status='Cancelled', completed_at=$2 WHERE id=$1")
+            .bind(task_id)
+            .bind(chrono::Utc::now().timestamp_millis())
+            .execute(&self.pool)
+            .await;
+        let _ = sqlx::query("UPDATE task_queue SET state='cancelled', finished_at=$2 WHERE task_id=$1")
+            .bind(task_id)
+            .bind(chrono::Utc::now().timestamp_millis())
+            .execute(&self.pool)
+            .await;
+        self.progress.send_progress(task_id, "cancelled", "Analysis cancelled", 100);
+    }
+
+    /// Reverts the VM and fails the task after an orchestration panic.
+    async fn crashed(&self, client: &dyn Hypervisor, task_id: &str, vm: &AssignedVm, error: &str) {
+        println!("[QUEUE] CRITICAL: Orchestration of task {} on VM {} panicked: {}", task_id, vm.vmid, error);
+        self.unbind_sessions(task_id).await;
+        self.rollback(client, vm, "orchestrator panic").await;
+        let _ = sqlx::query("UPDATE tasks SET status='Failed (Orchestrator Error)', completed_at=$2 WHERE id=$1")
+            .bind(task_id)
+            .bind(chrono::Utc::now().timestamp_millis())
+            .execute(&self.pool)
+            .await;
+        self.progress.send_progress(task_id, "failed", "Analysis failed", 100);
+    }
+
+    /// Reverts the VM and re-queues a run interrupted by shutdown.
+    async fn suspend(&self, client: &dyn Hypervisor, task_id: &str, vm: &AssignedVm) {
+        println!("[QUEUE] Shutdown: reverting VM {} ({}) and re-queuing task {}", vm.vmid, vm.name, task_id);
+        self.unbind_sessions(task_id).await;
+        self.rollback(client, vm, "shutdown").await;
+
+        let _ = sqlx::query(
+            "UPDATE task_queue SET state='queued', assigned_vmid=NULL, assigned_node=NULL, started_at=NULL, cloud_instance_id=NULL
+             WHERE task_id=$1 AND state='running'"
+        )
+        .bind(task_id)
+        .execute(&self.pool)
+        .await;
+        let _ = sqlx::query("UPDATE tasks SET status='Queued', sandbox_id=NULL WHERE id=$1")
+            .bind(task_id)
+            .execute(&self.pool)
+            .await;
+        clear_checkpoint(&self.pool, &[task_id.to_string()]).await;
+        self.progress.send_progress(task_id, "queued", "Backend restarting; analysis will start over", 0);
+    }
+
+    /// Whether the run's last checkpoint leaves only the report to write.
+    async fn report_only(&self, task_id: &str) -> bool {
+        let stage: Option<String> = sqlx::query_scalar("SELECT orchestration_stage FROM tasks WHERE id=$1")
+            .bind(task_id)
+            .fetch_optional(&self.pool)
+            .await
+            .ok()
+            .flatten()
+            .flatten();
+        stage.is_some_and(|s| RESUMABLE_STAGES.contains(&s.as_str()))
+    }
+
+    /// Picks an interrupted run back up at its report. No VM is involved, so it takes no slot.
+    async fn resume(self: &Arc<Self>, job: Interrupted) {
+        let cancel = Arc::new(Notify::new());
+        self.cancels.lock().await.insert(job.task_id.clone(), cancel.clone());
+
+        let scheduler = self.clone();
+        actix_web::rt::spawn(async move {
+            let task_id = job.task_id;
+            // Events the agent sent after the run was cut off never got their task_id
+            if let Some(session_id) = &job.session_id {
+                let _ = sqlx::query("UPDATE events SET task_id=$1 WHERE session_id=$2 AND task_id IS NULL")
+                    .bind(&task_id)
+                    .bind(session_id)
+                    .execute(&scheduler.pool)
+                    .await;
+            }
+            let _ = sqlx::query("UPDATE tasks SET status='Resuming Analysis' WHERE id=$1")
+                .bind(&task_id)
+                .execute(&scheduler.pool)
+                .await;
+            println!("[QUEUE] Task {} resumed after restart (was at '{}').", task_id, job.stage.as_deref().unwrap_or("?"));
+
+            let run = crate::write_final_report(&scheduler.pool, &scheduler.ai_manager, scheduler.manager.clone(), &scheduler.progress, &task_id, &job.analysis_mode);
+            tokio::pin!(run);
+            let done = tokio::select! {
+                _ = &mut run => true,
+                _ = cancel.notified() => {
+                    let _ = sqlx::query("UPDATE tasks SET status='Cancelled', completed_at=$2 WHERE id=$1")
+                        .bind(&task_id)
+                        .bind(chrono::Utc::now().timestamp_millis())
+                        .execute(&scheduler.pool)
+                        .await;
+                    let _ = sqlx::query("UPDATE task_queue SET state='cancelled', finished_at=$2 WHERE task_id=$1")
+                        .bind(&task_id)
+                        .bind(chrono::Utc::now().timestamp_millis())
+                        .execute(&scheduler.pool)
+                        .await;
+                    scheduler.progress.send_progress(&task_id, "cancelled", "Analysis cancelled", 100);
+                    true
+                }
+                _ = scheduler.stopped() => tokio::time::timeout(shutdown_grace_from_env(), &mut run).await.is_ok(),
+            };
+            if done {
+                let _ = sqlx::query("UPDATE task_queue SET state='done', finished_at=$2 WHERE task_id=$1 AND state='running'")
+                    .bind(&task_id)
+                    .bind(chrono::Utc::now().timestamp_millis())
+                    .execute(&scheduler.pool)
+                    .await;
+                clear_checkpoint(&scheduler.pool, &[task_id.clone()]).await;
+                comparison::refresh_parent(&scheduler.pool, &task_id).await;
+            }
+            scheduler.cancels.lock().await.remove(&task_id);
+        });
+    }
+
+    async fn unbind_sessions(&self, task_id: &str) {
+        let mut sessions = self.manager.sessions.lock().await;
+        for (sid, session) in sessions.iter_mut() {
+            if session.active_task_id.as_deref() == Some(task_id) {
+                session.active_task_id = None;
+                println!("[AGENT] Task {} unbound from session {}", task_id, sid);
+            }
+        }
+    }
+
+    async fn rollback(&self, client: &dyn Hypervisor, vm: &AssignedVm, after: &str) {
+        if let Err(e) = client.stop(&vm.node, vm.vmid).await {
+            println!("[QUEUE] Warning: Failed to stop VM {}: {}", vm.vmid, e);
+        }
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        if let Err(e) = client.rollback_snapshot(&vm.node, vm.vmid, &vm.snapshot).await {
+            sandbox_pool::mark_broken(&self.pool, vm.vmid, &format!("Rollback to '{}' failed after {}: {}", vm.snapshot, after, e)).await;
+        }
+    }
+
+    /// Rollback for a VM known only from a checkpoint; the snapshot comes from the pool.
+    async fn revert_vm(&self, task_id: &str, vmid: u64, node: &str) {
+        let snapshot = match sandbox_pool::get(&self.pool, vmid).await {
+            Ok(Some(pooled)) => pooled.snapshot,
+            _ => sandbox_pool::default_snapshot(),
+        };
+        println!("[QUEUE] Reverting VM {} left mid-cleanup by task {}", vmid, task_id);
+        let vm = AssignedVm {
+            vmid,
+            node: node.to_string(),
+            name: format!("vm{}", vmid),
+            snapshot,
+            agent_timeout_secs: AGENT_TIMEOUT_SECS,
+            preflight_secs: None,
+        };
+        self.rollback(self.client.as_ref(), &vm, "restart").await;
+    }
+
+    async fn finish(&self, task_id: &str, vmid: u64) {
+        let _ = sqlx::query("UPDATE task_queue SET state='done', finished_at=$2 WHERE task_id=$1 AND state='running'")
+            .bind(task_id)
+            .bind(chrono::Utc::now().timestamp_millis())
+            .execute(&self.pool)
+            .await;
+        clear_checkpoint(&self.pool, &[task_id.to_string()]).await;
+        self.release(vmid).await;
+        comparison::refresh_parent(&self.pool, task_id).await;
+        self.wake.notify_one();
+    }
+
+    async fn release(&self, vmid: u64) {
+        sandbox_pool::release(&self.pool, vmid).await;
+        self.busy_vms.lock().await.remove(&vmid);
+    }
+
+    /// Position and estimated start of every queued task.
+    pub async fn queue_estimates(&self) -> HashMap<String, QueueEstimate> {
+        let now = chrono::Utc::now().timestamp_millis();
+        let overhead: Option<f64> = sqlx::query_scalar(
+            "SELECT AVG(GREATEST(finished_at - started_at - duration_seconds * 1000, 0))::FLOAT8 FROM (
+                 SELECT finished_at, started_at, duration_seconds FROM task_queue
+                 WHERE state='done' AND started_at IS NOT NULL AND finished_at IS NOT NULL
+                 ORDER BY finished_at DESC LIMIT 50
+             ) recent"
+        )
+        .fetch_one(&self.pool)
+        .await
+        .ok()
+        .flatten();
+        let overhead_ms = overhead.map(|o| o as i64).unwrap_or(DEFAULT_OVERHEAD_MS);
+
+        let running: Vec<(Option<i64>, i64)> = sqlx::query_as("SELECT started_at, duration_seconds FROM task_queue WHERE state='running'")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap_or_default();
+        let queued: Vec<(String, i64)> = sqlx::query_as(
+            &format!("SELECT task_id, duration_seconds FROM task_queue WHERE state='queued' ORDER BY {}", DISPATCH_ORDER)
+        )
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_default();
+
+        // When each slot frees up
+        let capacity = self.max_concurrent + self.cloud.as_ref().map_or(0, |c| c.capacity());
+        let mut slots: Vec<i64> = running.iter()
+            .map(|(started, secs)| (started.unwrap_or(now) + secs * 1000 + overhead_ms).max(now))
+            .collect();
+        slots.resize(slots.len().max(capacity), now);
+
+        let mut estimates = HashMap::new();
+        for (i, (task_id, secs)) in queued.into_iter().enumerate() {
+            let Some((slot, &start)) = slots.iter().enumerate().min_by_key(|(_, t)| **t) else { break };
+            slots[slot] = start + secs * 1000 + overhead_ms;
+            estimates.insert(task_id, QueueEstimate { position: i as i64 + 1, estimated_start: start });
+        }
+        estimates
+    }
+
+    async fn assigned(&self, pooled: sandbox_pool::PoolVm, preflight_secs: Option<u64>) -> AssignedVm {
+        let vmid = pooled.vmid as u64;
+        AssignedVm {
+            vmid,
+            name: self.vm_name(&pooled.node, vmid).await,
+            node: pooled.node,
+            snapshot: pooled.snapshot,
+            agent_timeout_secs: AGENT_TIMEOUT_SECS,
+            preflight_secs,
+        }
+    }
+
+    async fn vm_name(&self, node: &str, vmid: u64) -> String {
+        self.client.get_vms(node).await.ok()
+            .and_then(|vms| vms.into_iter().find(|v| v.vmid == vmid))
+            .and_then(|v| v.name)
+            .unwrap_or_else(|| format!("vm{}", vmid))
+    }
+}
+
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Queued and running jobs", body = [QueueEntry])))]
+#[get("/queue")]
+pub async fn list_queue(pool: web::Data<Pool<Postgres>>) -> impl Responder {
+    match sqlx::query_as::<_, QueueEntry>(
+        &format!("SELECT * FROM task_queue WHERE state IN ('queued', 'running') ORDER BY state DESC, {}", DISPATCH_ORDER)
+    )
+    .fetch_all(pool.get_ref())
+    .await {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "tasks", responses(
+    (status = 200, description = "Removed from the queue"),
+    (status = 202, description = "Running analysis is being stopped and its VM reverted"),
+    (status = 409, description = "Task is not queued or running"),
+))]
+#[post("/tasks/{id}/cancel")]
+pub async fn cancel_task(
+    scheduler: web::Data<Arc<TaskScheduler>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let task_id = path.into_inner();
+    match scheduler.cancel(&task_id).await {
+        CancelOutcome::Dequeued => HttpResponse::Ok().json(serde_json::json!({ "status": "cancelled", "task_id": task_id })),
+        CancelOutcome::Interrupted => HttpResponse::Accepted().json(serde_json::json!({
+            "status": "cancelling",
+            "task_id": task_id,
+            "message": "Stopping analysis and reverting sandbox"
+        })),
+        CancelOutcome::NotActive => HttpResponse::Conflict().json(serde_json::json!({ "error": "Task is not queued or running" })),
+    }
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct RerunRequest {
+    pub vmid: Option<u64>,
+    pub node: Option<String>,
+    /// Minutes; defaults to the original run's duration.
+    pub analysis_duration: Option<u64>,
+    pub analysis_mode: Option<String>,
+    /// urgent | normal | bulk; defaults to the original run's priority.
+    pub priority: Option<String>,
+    /// full | fake-net | blocked | vpn; defaults to the original run's policy.
+    pub internet_policy: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct OriginalTask {
+    filename: String,
+    original_filename: Option<String>,
+    file_hash: Option<String>,
+    file_path: Option<String>,
+    internet_policy: Option<String>,
+}
+
+/// Re-detonates an existing task's sample (or URL) as a new task linked back via parent_task_id.
+#[utoipa::path(tag = "tasks", request_body = RerunRequest, responses(
+    (status = 200, description = "Re-run queued as a new task"),
+    (status = 400, description = "Invalid mode, priority or internet policy"),
+    (status = 404, description = "Task not found"),
+    (status = 410, description = "Stored sample no longer exists"),
+))]
+#[post("/tasks/{id}/rerun")]
+pub async fn rerun_task(
+    pool: web::Data<Pool<Postgres>>,
+    scheduler: web::Data<Arc<TaskScheduler>>,
+    path: web::Path<String>,
+    req: web::Json<RerunRequest>,
+) -> impl Responder {
+    let parent_id = path.into_inner();
+    let original = match sqlx::query_as::<_, OriginalTask>(
+        "SELECT filename, original_filename, file_hash, file_path, internet_policy FROM tasks WHERE id = $1"
+    )
+    .bind(&parent_id)
+    .fetch_optional(pool.get_ref())
+    .await {
+        Ok(Some(t)) => t,
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+
+    // The queue row has the exact detonation parameters; tasks predating the queue are rebuilt
+    // from the stored upload (URL tasks keep the URL in original_filename and have no hash).
+    let previous = sqlx::query_as::<_, QueueEntry>("SELECT * FROM task_queue WHERE task_id = $1")
+        .bind(&parent_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .ok()
+        .flatten();
+    let os_profile = previous.as_ref().and_then(|p| p.os_profile.clone());
+    let profile = previous.as_ref().and_then(|p| p.profile.clone());
+    let prev_priority = previous.as_ref()
+        .and_then(|p| parse_priority(&p.priority))
+        .unwrap_or(DEFAULT_PRIORITY);
+    let (target_url, detonation_name, is_url_task, prev_mode, prev_duration) = match previous {
+        Some(p) => (p.target_url, p.original_filename, p.is_url_task, p.analysis_mode, p.duration_seconds as u64),
+        None if original.file_hash.as_deref() == Some("N/A") => {
+            let url = original.original_filename.clone().unwrap_or_default();
+            (url, "URL_Detonation".to_string(), true, "quick".to_string(), 300)
+        }
+        None => {
+            if !original.file_path.as_ref().is_some_and(|p| std::path::Path::new(p).exists()) {
+                return HttpResponse::Gone().json(serde_json::json!({ "error": "Stored sample for this task no longer exists" }));
+            }
+            let host_ip = std::env::var("HOST_IP").unwrap_or_else(|_| "192.168.50.11".to_string());
+            let url = format!("http://{}:8080/uploads/{}", host_ip, original.filename);
+            let name = original.original_filename.clone().filter(|n| !n.is_empty()).unwrap_or_else(|| original.filename.clone());
+            (url, name, false, "quick".to_string(), 300)
+        }
+    };
+
+    let analysis_mode = match req.analysis_mode.as_deref().map(|m| m.trim().to_lowercase()) {
+        Some(m) if m == "quick" || m == "deep" || m == "vsix" => m,
+        Some(_) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "analysis_mode must be quick, deep or vsix" })),
+        None => prev_mode,
+    };
+    let duration_seconds = req.analysis_duration.map(|m| m * 60).unwrap_or(prev_duration);
+    let priority = match req.priority.as_deref().map(parse_priority) {
+        Some(Some(p)) => p,
+        Some(None) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "priority must be urgent, normal or bulk" })),
+        None => prev_priority,
+    };
+    let internet_policy = match req.internet_policy.as_deref().map(network_policy::parse_policy) {
+        Some(Some(p)) => Some(p.to_string()),
+        Some(None) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "internet_policy must be full, fake-net, blocked or vpn" })),
+        None => original.internet_policy.clone(),
+    };
+
+    let created_at = chrono::Utc::now().timestamp_millis();
+    let task_id = created_at.to_string();
+    if let Err(e) = sqlx::query(
+        "INSERT INTO tasks (id, filename, original_filename, file_hash, status, created_at, sandbox_id, file_path, parent_task_id, internet_policy, container)
+         VALUES ($1, $2, $3, $4, 'Queued', $5, $6, $7, $8, $9, (SELECT container FROM tasks WHERE id = $8))"
+    )
+    .bind(&task_id)
+    .bind(&original.filename)
+    .bind(&original.original_filename)
+    .bind(&original.file_hash)
+    .bind(created_at)
+    .bind(req.vmid.map(|id| id.to_string()))
+    .bind(&original.file_path)
+    .bind(&parent_id)
+    .bind(&internet_policy)
+    .execute(pool.get_ref())
+    .await {
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() }));
+    }
+
+    let job = QueuedAnalysis {
+        task_id: task_id.clone(),
+        target_url,
+        original_filename: detonation_name,
+        duration_seconds,
+        vmid: req.vmid,
+        node: req.node.clone(),
+        is_url_task,
+        analysis_mode: analysis_mode.clone(),
+        // A pinned VM overrides the original run's profile
+        os_profile: if req.vmid.is_some() { None } else { os_profile },
+        priority: priority.to_string(),
+        profile,
+    };
+    if let Err(e) = scheduler.enqueue(job).await {
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() }));
+    }
+
+    println!("[QUEUE] Task {} re-run as {} ({} mode, {}s)", parent_id, task_id, analysis_mode, duration_seconds);
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "analysis_queued",
+        "task_id": task_id,
+        "parent_task_id": parent_id,
+        "mode": analysis_mode,
+    }))
+}
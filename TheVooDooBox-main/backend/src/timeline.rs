@@ -0,0 +1,243 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+
+// --- NORMALIZED TIMELINE ---
+
+/// Identical events closer together than this are folded into one entry.
+const DUPLICATE_WINDOW_MS: i64 = 1000;
+const DEFAULT_LIMIT: usize = 5000;
+
+#[derive(Serialize, Clone)]
+pub struct TimelineEntry {
+    pub timestamp: i64,
+    /// agent | sysmon | kernel | browser | screenshot
+    pub source: &'static str,
+    /// Timeline category, e.g. process, file, network, persistence.
+    pub category: &'static str,
+    pub event_type: String,
+    pub event_id: Option<i32>,
+    pub pid: Option<i32>,
+    pub ppid: Option<i32>,
+    pub process_name: Option<String>,
+    pub summary: String,
+    pub decoded: Option<String>,
+    pub severity: i32,
+    /// Detection category the agent's severity rules assigned, if any.
+    pub detection: Option<String>,
+    /// ATT&CK tactic from the kill-chain rules, if any.
+    pub stage: Option<String>,
+    /// Obfuscation score (0-100) of command lines and script blocks.
+    pub obfuscation: Option<i32>,
+    /// URL under /screenshots for screenshot entries.
+    pub screenshot: Option<String>,
+    /// How many identical reports were folded into this entry (1 = none).
+    pub occurrences: u32,
+    /// When the last of those reports arrived, if there were several.
+    pub last_seen: Option<i64>,
+}
+
+#[derive(Deserialize, Default, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TimelineQuery {
+    /// Comma-separated categories to keep.
+    pub categories: Option<String>,
+    pub min_severity: Option<i32>,
+    /// Keep only command lines and script blocks scored at least this obfuscated.
+    pub min_obfuscation: Option<i32>,
+    /// Comma-separated kill-chain stages to keep (e.g. persistence,command_and_control).
+    pub stages: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(sqlx::FromRow)]
+struct EventRow {
+    id: i32,
+    event_type: String,
+    process_id: i32,
+    parent_process_id: i32,
+    process_name: String,
+    details: String,
+    decoded_details: Option<String>,
+    timestamp: i64,
+    severity: Option<i32>,
+    category: Option<String>,
+    kill_chain_stage: Option<String>,
+    obfuscation_score: Option<i32>,
+    occurrences: i32,
+    last_seen: Option<i64>,
+}
+
+fn source_of(event_type: &str, details: &str) -> &'static str {
+    if event_type.starts_with("BROWSER_") {
+        "browser"
+    } else if event_type.starts_with("KERNEL_") || details.starts_with("KERNEL:") {
+        "kernel"
+    } else if details.contains("SYSMON:") {
+        "sysmon"
+    } else {
+        "agent"
+    }
+}
+
+pub fn category_of(event_type: &str) -> &'static str {
+    match event_type {
+        "PROCESS_CREATE" | "PROCESS_TERMINATE" | "PROCESS_ACCESS" | "IMAGE_LOAD" => "process",
+        "EXEC_SUCCESS" | "EXEC_ERROR" | "URL_OPEN" | "DOWNLOAD_DETECTED" | "DOWNLOAD_ERROR"
+        | "VSIX_INSTALLED" | "VSIX_ERROR" | "GUEST_AGENT_EXEC" => "execution",
+        "FILE_CREATE" | "FILE_VERIFIED" | "ADS_CREATED" | "TIMESTOMP_DETECTED" | "CLIPBOARD_CAPTURE" => "file",
+        "NETWORK_DNS" => "dns",
+        "SCREEN_TEXT" => "screenshot",
+        "NETWORK_CONNECT" | "HTTP_REQUEST" | "LATERAL_MOVEMENT" => "network",
+        "MEMORY_ANOMALY" | "PROCESS_TAMPER" | "REMOTE_THREAD" => "injection",
+        "STARTUP_PERSISTENCE" | "PERSISTENCE_SWEEP" | "SCHTASK_CREATED" | "SCHTASK_MODIFIED" | "WMI_SUBSCRIPTION" | "COM_HIJACK" => "persistence",
+        "DEFENDER_EXCLUSION_ADDED" | "DEFENDER_EXCLUSION_REMOVED" | "DEFENDER_TAMPER" | "CERT_INSTALLED" | "PROXY_HIJACK" => "defense_evasion",
+        t if t.starts_with("REG") => "registry",
+        t if t.starts_with("BROWSER_") => "browser",
+        t if t.starts_with("NETWORK_") => "network",
+        _ => "system",
+    }
+}
+
+/// Screenshots from the index; a run of identical frames becomes one entry with its count.
+async fn screenshots(pool: &Pool<Postgres>, task_id: &str) -> Vec<TimelineEntry> {
+    let all = crate::screenshots::ScreenshotQuery { limit: Some(5000), ..Default::default() };
+    let Ok(shots) = crate::screenshots::list(pool, task_id, &all).await else { return Vec::new() };
+    let mut repeats: HashMap<i32, u32> = HashMap::new();
+    for shot in &shots {
+        // Triggered frames keep their own entry even when the screen did not change
+        if let (Some(original), None) = (shot.duplicate_of, shot.event_id) {
+            *repeats.entry(original).or_insert(0) += 1;
+        }
+    }
+    shots.iter().filter(|s| s.duplicate_of.is_none() || s.event_id.is_some()).map(|shot| TimelineEntry {
+        timestamp: shot.timestamp,
+        source: "screenshot",
+        category: "screenshot",
+        event_type: "SCREENSHOT".to_string(),
+        event_id: shot.event_id,
+        pid: None,
+        ppid: None,
+        process_name: None,
+        summary: if shot.event_id.is_some() { "Screenshot triggered by event".to_string() } else { "Periodic screenshot".to_string() },
+        decoded: None,
+        severity: 0,
+        detection: None,
+        stage: None,
+        obfuscation: None,
+        screenshot: Some(shot.url()),
+        occurrences: 1 + repeats.get(&shot.id).copied().unwrap_or(0),
+        last_seen: None,
+    }).collect()
+}
+
+/// The merged, de-duplicated timeline for a task, oldest first.
+pub async fn build(pool: &Pool<Postgres>, task_id: &str, query: &TimelineQuery) -> Result<Vec<TimelineEntry>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, EventRow>(
+        "SELECT id, event_type, process_id, parent_process_id, process_name, details, decoded_details, timestamp, severity, category, kill_chain_stage, obfuscation_score,
+                COALESCE(occurrences, 1) AS occurrences, last_seen
+         FROM events WHERE task_id = $1 ORDER BY timestamp, id"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut timeline: Vec<TimelineEntry> = Vec::with_capacity(rows.len());
+    // Process starts are reported by both Sysmon and the poller; keep one per PID, preferring
+    // Sysmon's (it has the command line) but at the earliest time either saw it
+    let mut process_starts: HashMap<i32, usize> = HashMap::new();
+    // Last entry per (type, pid, details) for folding repeats
+    let mut last_entry: HashMap<(String, i32, String), usize> = HashMap::new();
+
+    for row in rows {
+        let source = source_of(&row.event_type, &row.details);
+        if row.event_type == "PROCESS_CREATE" && row.process_id != 0 {
+            if let Some(&i) = process_starts.get(&row.process_id) {
+                let kept = &mut timeline[i];
+                kept.occurrences += row.occurrences.max(1) as u32;
+                kept.last_seen = kept.last_seen.max(Some(row.last_seen.unwrap_or(row.timestamp)));
+                if source == "sysmon" && kept.source != "sysmon" {
+                    kept.source = source;
+                    kept.summary = row.details;
+                    kept.obfuscation = row.obfuscation_score
+                        .or_else(|| crate::obfuscation::score(&row.event_type, &row.details, row.decoded_details.as_deref()));
+                    kept.decoded = row.decoded_details.or(kept.decoded.take());
+                    kept.event_id = Some(row.id);
+                }
+                kept.severity = kept.severity.max(row.severity.unwrap_or(0));
+                continue;
+            }
+        }
+
+        let key = (row.event_type.clone(), row.process_id, row.details.clone());
+        if let Some(&i) = last_entry.get(&key) {
+            if row.timestamp - timeline[i].timestamp <= DUPLICATE_WINDOW_MS {
+                timeline[i].occurrences += row.occurrences.max(1) as u32;
+                timeline[i].last_seen = timeline[i].last_seen.max(Some(row.last_seen.unwrap_or(row.timestamp)));
+                continue;
+            }
+        }
+
+        // Events stored before the column existed are classified on the fly
+        let stage = row.kill_chain_stage.clone().or_else(|| {
+            crate::kill_chain::classify(&row.event_type, &row.process_name, &row.details, row.decoded_details.as_deref(), row.category.as_deref())
+                .map(str::to_string)
+        });
+        let obfuscation = row.obfuscation_score
+            .or_else(|| crate::obfuscation::score(&row.event_type, &row.details, row.decoded_details.as_deref()));
+        let index = timeline.len();
+        if row.event_type == "PROCESS_CREATE" && row.process_id != 0 {
+            process_starts.insert(row.process_id, index);
+        }
+        last_entry.insert(key, index);
+        timeline.push(TimelineEntry {
+            timestamp: row.timestamp,
+            source,
+            category: category_of(&row.event_type),
+            event_type: row.event_type,
+            event_id: Some(row.id),
+            pid: Some(row.process_id),
+            ppid: Some(row.parent_process_id),
+            process_name: Some(row.process_name),
+            summary: row.details,
+            decoded: row.decoded_details.filter(|d| !d.is_empty()),
+            severity: row.severity.unwrap_or(0),
+            detection: row.category.filter(|c| !c.is_empty()),
+            stage,
+            obfuscation,
+            screenshot: None,
+            occurrences: row.occurrences.max(1) as u32,
+            last_seen: row.last_seen,
+        });
+    }
+
+    timeline.extend(screenshots(pool, task_id).await);
+    timeline.sort_by_key(|e| (e.timestamp, e.event_id.unwrap_or(i32::MAX)));
+
+    let categories: Option<Vec<String>> = query.categories.as_ref()
+        .map(|c| c.split(',').map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty()).collect());
+    let stages: Option<Vec<String>> = query.stages.as_ref()
+        .map(|c| c.split(',').map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty()).collect());
+    let min_severity = query.min_severity.unwrap_or(0);
+    Ok(timeline.into_iter()
+        .filter(|e| e.severity >= min_severity)
+        .filter(|e| query.min_obfuscation.is_none_or(|min| e.obfuscation.is_some_and(|o| o >= min)))
+        .filter(|e| categories.as_ref().is_none_or(|c| c.iter().any(|c| c == e.category)))
+        .filter(|e| stages.as_ref().is_none_or(|s| e.stage.as_ref().is_some_and(|stage| s.contains(stage))))
+        .take(query.limit.unwrap_or(DEFAULT_LIMIT))
+        .collect())
+}
+
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
+#[get("/tasks/{id}/timeline")]
+pub async fn get_timeline(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+    query: web::Query<TimelineQuery>,
+) -> impl Responder {
+    match build(pool.get_ref(), &path.into_inner(), &query).await {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
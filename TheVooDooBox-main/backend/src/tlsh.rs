@@ -0,0 +1,96 @@
+// --- TLSH ---
+
+/// Below this many bytes there is not enough data for a stable digest.
+const MIN_DATA_LENGTH: usize = 50;
+const WINDOW: usize = 5;
+const BUCKETS: usize = 128;
+const CODE_SIZE: usize = BUCKETS / 4;
+
+/// Pearson's permutation of 0..=255 used by the reference implementation.
+const V_TABLE: [u8; 256] = [
+    1, 87, 49, 12, 176, 178, 102, 166, 121, 193, 6, 84, 249, 230, 44, 163,
+    14, 197, 213, 181, 161, 85, 218, 80, 64, 239, 24, 226, 236, 142, 38, 200,
+    110, 177, 104, 103, 141, 253, 255, 50, 77, 101, 81, 18, 45, 96, 31, 222,
+    25, 107, 190, 70, 86, 237, 240, 34, 72, 242, 20, 214, 244, 227, 149, 235,
+    97, 234, 57, 22, 60, 250, 82, 175, 208, 5, 127, 199, 111, 62, 135, 248,
+    174, 169, 211, 58, 66, 154, 106, 195, 245, 171, 17, 187, 182, 179, 0, 243,
+    132, 56, 148, 75, 128, 133, 158, 100, 130, 126, 91, 13, 153, 246, 216, 219,
+    119, 68, 223, 78, 83, 88, 201, 99, 122, 11, 92, 32, 136, 114, 52, 10,
+    138, 30, 48, 183, 156, 35, 61, 26, 143, 74, 251, 94, 129, 162, 63, 152,
+    170, 7, 115, 167, 241, 206, 3, 150, 55, 59, 151, 220, 90, 53, 23, 131,
+    125, 173, 15, 238, 79, 95, 89, 16, 105, 137, 225, 224, 217, 160, 37, 123,
+    118, 73, 2, 157, 46, 116, 9, 145, 134, 228, 207, 212, 202, 215, 69, 229,
+    27, 188, 67, 124, 168, 252, 42, 4, 29, 108, 21, 247, 19, 205, 39, 203,
+    233, 40, 186, 147, 198, 192, 155, 33, 164, 191, 98, 204, 165, 180, 117, 76,
+    140, 36, 210, 172, 41, 54, 159, 8, 185, 232, 113, 196, 231, 47, 146, 120,
+    51, 65, 28, 144, 254, 221, 93, 189, 194, 139, 112, 43, 71, 109, 184, 209,
+];
+
+fn pearson(salt: u8, i: u8, j: u8, k: u8) -> u8 {
+    let mut h = V_TABLE[salt as usize];
+    h = V_TABLE[(h ^ i) as usize];
+    h = V_TABLE[(h ^ j) as usize];
+    V_TABLE[(h ^ k) as usize]
+}
+
+/// Log-scale length byte.
+fn length_code(len: usize) -> u8 {
+    let len = len as f64;
+    let code = if len <= 656.0 {
+        (len.ln() / 1.5f64.ln()).floor()
+    } else if len <= 3199.0 {
+        (len.ln() / 1.3f64.ln() - 8.72777).floor()
+    } else {
+        (len.ln() / 1.1f64.ln() - 62.5472).floor()
+    };
+    (code as u32 & 0xFF) as u8
+}
+
+fn swap_nibbles(b: u8) -> u8 {
+    b.rotate_left(4)
+}
+
+/// "T1" + 70 hex digits; None when the data is too short or too uniform to hash.
+pub fn hash(data: &[u8]) -> Option<String> {
+    if data.len() < MIN_DATA_LENGTH {
+        return None;
+    }
+    let mut buckets = [0u32; 256];
+    let mut checksum = 0u8;
+    for i in WINDOW - 1..data.len() {
+        let (a, b, c, d, e) = (data[i], data[i - 1], data[i - 2], data[i - 3], data[i - 4]);
+        checksum = pearson(0, a, b, checksum);
+        buckets[pearson(2, a, b, c) as usize] += 1;
+        buckets[pearson(3, a, b, d) as usize] += 1;
+        buckets[pearson(5, a, c, d) as usize] += 1;
+        buckets[pearson(7, a, c, e) as usize] += 1;
+        buckets[pearson(11, a, b, e) as usize] += 1;
+        buckets[pearson(13, a, d, e) as usize] += 1;
+    }
+
+    let effective = &buckets[..BUCKETS];
+    if effective.iter().filter(|&&n| n > 0).count() <= BUCKETS / 2 {
+        return None;
+    }
+    let mut sorted = effective.to_vec();
+    sorted.sort_unstable();
+    let (q1, q2, q3) = (sorted[BUCKETS / 4 - 1], sorted[BUCKETS / 2 - 1], sorted[BUCKETS * 3 / 4 - 1]);
+    if q3 == 0 {
+        return None;
+    }
+
+    let mut code = [0u8; CODE_SIZE];
+    for (i, byte) in code.iter_mut().enumerate() {
+        for j in 0..4 {
+            let n = effective[4 * i + j];
+            let quartile = if n > q3 { 3 } else if n > q2 { 2 } else if n > q1 { 1 } else { 0 };
+            *byte |= quartile << (j * 2);
+        }
+    }
+    let q1_ratio = ((q1 as f32 * 100.0 / q3 as f32) as u32 % 16) as u8;
+    let q2_ratio = ((q2 as f32 * 100.0 / q3 as f32) as u32 % 16) as u8;
+
+    let mut digest = vec![swap_nibbles(checksum), swap_nibbles(length_code(data.len())), (q1_ratio << 4) | q2_ratio];
+    digest.extend(code.iter().rev());
+    Some(format!("T1{}", digest.iter().map(|b| format!("{:02X}", b)).collect::<String>()))
+}
@@ -0,0 +1,461 @@
+use actix_multipart::Multipart;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use base64::Engine;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use crate::{limits, screenshots, storage, AgentManager};
+
+// --- URL ANALYSIS ARTIFACTS ---
+
+/// Leading DOM bytes searched for the page title.
+const TITLE_SCAN_BYTES: usize = 256 * 1024;
+const MAX_FAVICON_BYTES: usize = 1024 * 1024;
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS url_artifacts (
+            task_id TEXT PRIMARY KEY,
+            requested_url TEXT NOT NULL,
+            final_url TEXT,
+            redirect_chain JSONB NOT NULL DEFAULT '[]',
+            certificates JSONB NOT NULL DEFAULT '[]',
+            page_title TEXT,
+            dom_sha256 TEXT,
+            dom_size BIGINT,
+            favicon_url TEXT,
+            favicon_mmh3 INTEGER,
+            favicon_sha256 TEXT,
+            payloads JSONB NOT NULL DEFAULT '[]',
+            error TEXT,
+            crawled_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_url_artifacts_favicon ON url_artifacts(favicon_mmh3)")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct PeerCertificate {
+    host: String,
+    der: String,
+}
+
+/// The agent's crawl report; hops are stored as sent.
+#[derive(Deserialize)]
+struct CrawlReport {
+    requested_url: String,
+    final_url: Option<String>,
+    #[serde(default)]
+    chain: Vec<serde_json::Value>,
+    #[serde(default)]
+    certificates: Vec<PeerCertificate>,
+    favicon_url: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Payload {
+    filename: String,
+    sha256: String,
+    size: u64,
+    /// Where the crawl got it: the final URL of the chain.
+    source_url: Option<String>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct UrlArtifacts {
+    pub task_id: String,
+    pub requested_url: String,
+    pub final_url: Option<String>,
+    pub redirect_chain: serde_json::Value,
+    pub certificates: serde_json::Value,
+    pub page_title: Option<String>,
+    pub dom_sha256: Option<String>,
+    pub dom_size: Option<i64>,
+    pub favicon_url: Option<String>,
+    pub favicon_mmh3: Option<i32>,
+    pub favicon_sha256: Option<String>,
+    pub payloads: serde_json::Value,
+    pub error: Option<String>,
+    pub crawled_at: i64,
+}
+
+/// MurmurHash3 x86 32-bit, seed 0.
+fn murmur3_32(data: &[u8]) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+    let mut h: u32 = 0;
+    let mut blocks = data.chunks_exact(4);
+    for block in &mut blocks {
+        let mut k = u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        h ^= k;
+        h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        let mut k = 0u32;
+        for (i, b) in tail.iter().enumerate() {
+            k |= (*b as u32) << (8 * i);
+        }
+        h ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    }
+    h ^= data.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+    h
+}
+
+/// Shodan's `http.favicon.hash`: signed mmh3 of base64.encodebytes output.
+pub fn favicon_hash(icon: &[u8]) -> i32 {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(icon);
+    let mut wrapped = String::with_capacity(encoded.len() + encoded.len() / 76 + 1);
+    for line in encoded.as_bytes().chunks(76) {
+        wrapped.push_str(std::str::from_utf8(line).unwrap_or_default());
+        wrapped.push('\n');
+    }
+    murmur3_32(wrapped.as_bytes()) as i32
+}
+
+/// `*.example.com` covers one label, as browsers apply it.
+fn name_matches(pattern: &str, host: &str) -> bool {
+    let (pattern, host) = (pattern.to_lowercase(), host.to_lowercase());
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => host.split_once('.').is_some_and(|(_, rest)| rest == suffix),
+        None => pattern == host,
+    }
+}
+
+/// Subject, issuer, validity and names of one peer certificate, plus what is off about it.
+fn describe_certificate(cert: &PeerCertificate) -> serde_json::Value {
+    use x509_parser::prelude::*;
+    let Ok(der) = base64::engine::general_purpose::STANDARD.decode(&cert.der) else {
+        return serde_json::json!({ "host": cert.host, "error": "certificate is not valid base64" });
+    };
+    let sha256 = format!("{:x}", Sha256::digest(&der));
+    let parsed = match X509Certificate::from_der(&der) {
+        Ok((_, c)) => c,
+        Err(e) => return serde_json::json!({ "host": cert.host, "sha256": sha256, "error": e.to_string() }),
+    };
+
+    let names: Vec<String> = parsed.subject_alternative_name().ok().flatten()
+        .map(|ext| ext.value.general_names.iter().filter_map(|n| match n {
+            GeneralName::DNSName(d) => Some(d.to_string()),
+            _ => None,
+        }).collect())
+        .unwrap_or_default();
+    let common_name = parsed.subject().iter_common_name().next().and_then(|cn| cn.as_str().ok()).map(str::to_string);
+    let not_before = parsed.validity().not_before.timestamp() * 1000;
+    let not_after = parsed.validity().not_after.timestamp() * 1000;
+    let now = chrono::Utc::now().timestamp_millis();
+    let host_matches = names.iter().chain(common_name.iter()).any(|n| name_matches(n, &cert.host));
+
+    serde_json::json!({
+        "host": cert.host,
+        "sha256": sha256,
+        "subject": parsed.subject().to_string(),
+        "issuer": parsed.issuer().to_string(),
+        "serial": parsed.raw_serial_as_string(),
+        "not_before": not_before,
+        "not_after": not_after,
+        "validity_days": (not_after - not_before) / 86_400_000,
+        "san": names,
+        "self_signed": parsed.subject() == parsed.issuer(),
+        "expired": now > not_after || now < not_before,
+        "host_matches": host_matches,
+    })
+}
+
+fn page_title(dom: &[u8]) -> Option<String> {
+    let head = String::from_utf8_lossy(&dom[..dom.len().min(TITLE_SCAN_BYTES)]).to_string();
+    let re = regex::Regex::new(r"(?is)<title[^>]*>(.*?)</title>").ok()?;
+    let title = re.captures(&head)?[1].split_whitespace().collect::<Vec<_>>().join(" ");
+    (!title.is_empty()).then(|| title.chars().take(300).collect())
+}
+
+fn artifact_key(task_id: &str, name: &str) -> String {
+    format!("url_artifacts/{}/{}", task_id, name)
+}
+
+/// Agent upload: `session_id`, then the `report` JSON, then `dom`, `favicon` and `payload` files.
+#[utoipa::path(tag = "telemetry", responses(
+    (status = 200, description = "URL artifacts stored on the session's task"),
+    (status = 400, description = "Unknown session or malformed report"),
+    (status = 413, description = "Upload over MAX_URL_ARTIFACT_UPLOAD_MB"),
+))]
+#[post("/vms/telemetry/url-artifacts")]
+pub async fn upload_url_artifacts(
+    req: HttpRequest,
+    mut payload: Multipart,
+    manager: web::Data<Arc<AgentManager>>,
+    pool: web::Data<Pool<Postgres>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut limit = limits::UploadLimit::url_artifacts();
+    if let Err(resp) = limit.check_declared(&req) {
+        return Ok(resp);
+    }
+    let mut task_id: Option<String> = None;
+    let mut report: Option<CrawlReport> = None;
+    let mut dom: Option<(String, u64, Option<String>)> = None;
+    let mut favicon: Option<(String, i32)> = None;
+    let mut payloads: Vec<Payload> = Vec::new();
+
+    while let Ok(Some(mut field)) = TryStreamExt::try_next(&mut payload).await {
+        let field_name = field.content_disposition().and_then(|cd| cd.get_name()).unwrap_or("").to_string();
+        match field_name.as_str() {
+            "session_id" => {
+                let session_id = crate::read_text_field(&mut field).await;
+                match crate::upload_task_id(&manager, Some(&session_id)).await {
+                    Ok(t) if t != "unsorted" => task_id = Some(t),
+                    Ok(_) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": "Session is not running a task" }))),
+                    Err(resp) => return Ok(resp),
+                }
+                continue;
+            }
+            "report" => {
+                let raw = crate::read_text_field(&mut field).await;
+                match serde_json::from_str(&raw) {
+                    Ok(r) => report = Some(r),
+                    Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("Invalid report: {}", e) }))),
+                }
+                continue;
+            }
+            "dom" | "favicon" | "payload" => {}
+            _ => continue,
+        }
+        let Some(task_id) = task_id.as_deref() else {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": "session_id field required before the file" })));
+        };
+
+        // Payloads are written under a temporary name until their hash is known
+        let name = match field_name.as_str() {
+            "dom" => "dom.html".to_string(),
+            "favicon" => "favicon".to_string(),
+            _ => format!("payload_{}.part", uuid::Uuid::new_v4()),
+        };
+        let key = artifact_key(task_id, &name);
+        let path = storage::local_path(&key);
+        if let Some(dir) = path.parent() {
+            let _ = tokio::fs::create_dir_all(dir).await;
+        }
+        let mut f = tokio::fs::File::create(&path).await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        let mut hasher = Sha256::new();
+        let mut size = 0u64;
+        // The favicon is hashed whole and the title read from the head of the DOM
+        let mut kept = Vec::new();
+        let keep = if field_name == "favicon" { MAX_FAVICON_BYTES } else if field_name == "dom" { TITLE_SCAN_BYTES } else { 0 };
+        while let Ok(Some(chunk)) = TryStreamExt::try_next(&mut field).await {
+            if let Err(resp) = limit.add(chunk.len()) {
+                drop(f);
+                let _ = tokio::fs::remove_file(&path).await;
+                return Ok(resp);
+            }
+            f.write_all(&chunk).await.map_err(actix_web::error::ErrorInternalServerError)?;
+            hasher.update(&chunk);
+            size += chunk.len() as u64;
+            if kept.len() < keep {
+                kept.extend_from_slice(&chunk[..chunk.len().min(keep - kept.len())]);
+            }
+        }
+        drop(f);
+        let sha256 = format!("{:x}", hasher.finalize());
+
+        match field_name.as_str() {
+            "dom" => {
+                storage::persist(&key).await;
+                dom = Some((sha256, size, page_title(&kept)));
+            }
+            "favicon" => {
+                storage::persist(&key).await;
+                favicon = Some((sha256, favicon_hash(&kept)));
+            }
+            _ => {
+                let final_key = artifact_key(task_id, &format!("payload_{}", sha256));
+                let _ = tokio::fs::rename(&path, storage::local_path(&final_key)).await;
+                storage::persist(&final_key).await;
+                let original = field.content_disposition().and_then(|cd| cd.get_filename()).unwrap_or_default().to_string();
+                let vt_pool = pool.get_ref().clone();
+                let vt_hash = sha256.clone();
+                actix_web::rt::spawn(async move {
+                    let _ = crate::virustotal::get_cached_or_fetch(&vt_pool, &vt_hash).await;
+                });
+                payloads.push(Payload {
+                    filename: crate::sample_download::member_name(&original, &sha256),
+                    sha256,
+                    size,
+                    source_url: None,
+                });
+            }
+        }
+    }
+
+    let (Some(task_id), Some(report)) = (task_id, report) else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": "session_id and report fields are required" })));
+    };
+    for p in &mut payloads {
+        p.source_url = report.final_url.clone();
+    }
+    let certificates: Vec<serde_json::Value> = report.certificates.iter().map(describe_certificate).collect();
+    let hops = report.chain.len();
+
+    let result = sqlx::query(
+        "INSERT INTO url_artifacts (task_id, requested_url, final_url, redirect_chain, certificates, page_title, dom_sha256, dom_size, favicon_url, favicon_mmh3, favicon_sha256, payloads, error, crawled_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+         ON CONFLICT (task_id) DO UPDATE SET
+            requested_url = EXCLUDED.requested_url, final_url = EXCLUDED.final_url, redirect_chain = EXCLUDED.redirect_chain,
+            certificates = EXCLUDED.certificates, page_title = EXCLUDED.page_title, dom_sha256 = EXCLUDED.dom_sha256,
+            dom_size = EXCLUDED.dom_size, favicon_url = EXCLUDED.favicon_url, favicon_mmh3 = EXCLUDED.favicon_mmh3,
+            favicon_sha256 = EXCLUDED.favicon_sha256, payloads = EXCLUDED.payloads, error = EXCLUDED.error,
+            crawled_at = EXCLUDED.crawled_at"
+    )
+    .bind(&task_id)
+    .bind(&report.requested_url)
+    .bind(&report.final_url)
+    .bind(serde_json::Value::Array(report.chain))
+    .bind(serde_json::Value::Array(certificates))
+    .bind(dom.as_ref().and_then(|d| d.2.clone()))
+    .bind(dom.as_ref().map(|d| d.0.clone()))
+    .bind(dom.as_ref().map(|d| d.1 as i64))
+    .bind(favicon.as_ref().and(report.favicon_url.clone()))
+    .bind(favicon.as_ref().map(|f| f.1))
+    .bind(favicon.as_ref().map(|f| f.0.clone()))
+    .bind(serde_json::to_value(&payloads).unwrap_or_default())
+    .bind(&report.error)
+    .bind(chrono::Utc::now().timestamp_millis())
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(_) => {
+            println!("[URL] Task {}: {} hop(s) to {}, {} certificate(s), {} payload(s).",
+                task_id, hops, report.final_url.as_deref().unwrap_or("-"), report.certificates.len(), payloads.len());
+            Ok(HttpResponse::Ok().json(serde_json::json!({ "status": "success", "task_id": task_id })))
+        }
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() }))),
+    }
+}
+
+/// Redirect chain, certificates, DOM and favicon hashes, payloads and screenshot of a URL task.
+#[utoipa::path(tag = "tasks", responses(
+    (status = 200, description = "URL analysis artifacts"),
+    (status = 404, description = "No crawl recorded for this task"),
+))]
+#[get("/tasks/{id}/url-artifacts")]
+pub async fn get_url_artifacts(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let task_id = path.into_inner();
+    let artifacts = match sqlx::query_as::<_, UrlArtifacts>("SELECT * FROM url_artifacts WHERE task_id = $1")
+        .bind(&task_id)
+        .fetch_optional(pool.get_ref())
+        .await
+    {
+        Ok(Some(a)) => a,
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "No URL artifacts for this task" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    let shots: Vec<screenshots::Screenshot> = sqlx::query_as(
+        "SELECT s.* FROM screenshots s JOIN events e ON e.id = s.event_id
+         WHERE s.task_id = $1 AND e.event_type = 'URL_PAGE_LOADED' ORDER BY s.monitor"
+    )
+    .bind(&task_id)
+    .fetch_all(pool.get_ref())
+    .await
+    .unwrap_or_default();
+    let base = format!("{}/tasks/{}/url-artifacts", crate::api_version::PREFIX, task_id);
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "artifacts": artifacts,
+        "page_screenshots": shots.iter().map(|s| s.url()).collect::<Vec<_>>(),
+        "dom_url": artifacts.dom_sha256.as_ref().map(|_| format!("{}/dom", base)),
+        "favicon_download_url": artifacts.favicon_sha256.as_ref().map(|_| format!("{}/favicon", base)),
+        "payload_urls": artifacts.payloads.as_array().map(|ps| ps.iter()
+            .filter_map(|p| p["sha256"].as_str())
+            .map(|h| format!("{}/payloads/{}", base, h))
+            .collect::<Vec<_>>()),
+    }))
+}
+
+/// The crawled DOM (as plain text, so it is never rendered) or the favicon.
+#[utoipa::path(tag = "tasks", responses(
+    (status = 200, description = "Artifact file"),
+    (status = 404, description = "Artifact not stored"),
+))]
+#[get("/tasks/{id}/url-artifacts/{kind}")]
+pub async fn download_url_artifact(path: web::Path<(String, String)>) -> impl Responder {
+    let (task_id, kind) = path.into_inner();
+    if task_id.contains("..") || task_id.contains('/') {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid task id" }));
+    }
+    let name = match kind.as_str() {
+        "dom" => "dom.html",
+        "favicon" => "favicon",
+        _ => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Unknown artifact; use dom or favicon" })),
+    };
+    let key = artifact_key(&task_id, name);
+    if !storage::ensure_local(&key).await {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Artifact not stored" }));
+    }
+    let Ok(bytes) = tokio::fs::read(storage::local_path(&key)).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Artifact not stored" }));
+    };
+    let content_type = match (kind.as_str(), bytes.get(..4)) {
+        ("favicon", Some([0x89, b'P', b'N', b'G'])) => "image/png",
+        ("favicon", Some([0, 0, 1, 0])) => "image/x-icon",
+        ("favicon", Some([b'G', b'I', b'F', _])) => "image/gif",
+        ("favicon", Some([0xff, 0xd8, 0xff, _])) => "image/jpeg",
+        // Anything else (SVG included) could carry script
+        _ => "text/plain; charset=utf-8",
+    };
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(("X-Content-Type-Options", "nosniff"))
+        .insert_header(("Content-Security-Policy", "sandbox"))
+        .body(bytes)
+}
+
+/// A file the crawl downloaded, inside the usual password-protected zip.
+#[utoipa::path(tag = "tasks", responses(
+    (status = 200, description = "Encrypted zip holding the payload"),
+    (status = 404, description = "No such payload on this task"),
+))]
+#[get("/tasks/{id}/url-artifacts/payloads/{sha256}")]
+pub async fn download_url_payload(
+    req: HttpRequest,
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (task_id, sha256) = path.into_inner();
+    let payloads: Option<serde_json::Value> = sqlx::query_scalar("SELECT payloads FROM url_artifacts WHERE task_id = $1")
+        .bind(&task_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .ok()
+        .flatten();
+    let entry = payloads
+        .and_then(|v| serde_json::from_value::<Vec<Payload>>(v).ok())
+        .and_then(|ps| ps.into_iter().find(|p| p.sha256 == sha256));
+    let Some(entry) = entry else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "No such payload on this task" }));
+    };
+    let key = artifact_key(&task_id, &format!("payload_{}", entry.sha256));
+    if !storage::ensure_local(&key).await {
+        crate::audit::log_access(pool.get_ref(), &req, &task_id, serde_json::json!({ "sha256": entry.sha256 }), 404).await;
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Payload file no longer stored" }));
+    }
+    crate::audit::log_access(pool.get_ref(), &req, &task_id, serde_json::json!({ "sha256": entry.sha256, "file": entry.filename }), 200).await;
+    crate::sample_download::encrypted_zip(storage::local_path(&key), entry.filename, &entry.sha256, format!("URL payload {}", entry.sha256))
+}
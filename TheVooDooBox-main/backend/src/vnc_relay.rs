@@ -10,6 +10,8 @@ pub struct VncRelay {
     upstream_tx: Option<tokio::sync::mpsc::UnboundedSender<TungsteniteMessage>>,
     target_wss_url: String,
     auth_header: String,
+    /// Set for hypervisors exposing plain RFB over TCP (libvirt) instead of a websocket.
+    tcp_target: Option<String>,
 }
 
 impl VncRelay {
@@ -18,10 +20,81 @@ impl VncRelay {
             upstream_tx: None,
             target_wss_url,
             auth_header,
+            tcp_target: None,
         }
     }
 
+    pub fn tcp(addr: String) -> Self {
+        Self {
+            upstream_tx: None,
+            target_wss_url: String::new(),
+            auth_header: String::new(),
+            tcp_target: Some(addr),
+        }
+    }
+
+    fn start_tcp_proxy(&mut self, addr: String, ctx: &mut ws::WebsocketContext<Self>) {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<TungsteniteMessage>();
+        self.upstream_tx = Some(tx);
+        let recipient = ctx.address().recipient();
+
+        println!("[VNC_RELAY] Starting TCP proxy to upstream: {}", addr);
+
+        tokio::spawn(async move {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+            let stream = match tokio::net::TcpStream::connect(&addr).await {
+                Ok(s) => s,
+                Err(e) => {
+                    println!("[VNC_RELAY] Failed to connect to {}: {}", addr, e);
+                    return;
+                }
+            };
+            let (mut read, mut write) = stream.into_split();
+
+            let f_write = async move {
+                while let Some(msg) = rx.recv().await {
+                    let bytes = match msg {
+                        TungsteniteMessage::Binary(bin) => bin.to_vec(),
+                        TungsteniteMessage::Text(txt) => txt.as_str().as_bytes().to_vec(),
+                        _ => continue,
+                    };
+                    if let Err(e) = write.write_all(&bytes).await {
+                        println!("[VNC_RELAY] Upstream Write Error: {}", e);
+                        break;
+                    }
+                }
+            };
+
+            let f_read = async move {
+                let mut buf = vec![0u8; 64 * 1024];
+                loop {
+                    match read.read(&mut buf).await {
+                        Ok(0) => {
+                            println!("[VNC_RELAY] Upstream Closed Connection");
+                            break;
+                        }
+                        Ok(n) => recipient.do_send(BinaryMessage(buf[..n].to_vec())),
+                        Err(e) => {
+                            println!("[VNC_RELAY] Upstream Read Error: {}", e);
+                            break;
+                        }
+                    }
+                }
+            };
+
+            tokio::select! {
+                _ = f_write => println!("[VNC_RELAY] Write loop ended"),
+                _ = f_read => println!("[VNC_RELAY] Read loop ended"),
+            }
+        });
+    }
+
     fn start_proxy(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        if let Some(addr) = self.tcp_target.clone() {
+            return self.start_tcp_proxy(addr, ctx);
+        }
+
         let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<TungsteniteMessage>();
         self.upstream_tx = Some(tx);
         
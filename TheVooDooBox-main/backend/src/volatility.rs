@@ -0,0 +1,236 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::analysis_profiles::AnalysisProfile;
+use crate::hypervisor::Hypervisor;
+
+// --- MEMORY FORENSICS ---
+
+/// Rows kept per plugin; malfind on a busy guest can produce thousands.
+const MAX_ROWS: usize = 5000;
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct MemoryAnalysis {
+    pub id: i32,
+    pub task_id: String,
+    pub plugin: String,
+    /// completed | failed
+    pub status: String,
+    pub row_count: i32,
+    pub rows: serde_json::Value,
+    pub error: Option<String>,
+    pub created_at: i64,
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS memory_analyses (
+            id SERIAL PRIMARY KEY,
+            task_id TEXT NOT NULL,
+            plugin TEXT NOT NULL,
+            status TEXT NOT NULL,
+            row_count INTEGER NOT NULL DEFAULT 0,
+            rows JSONB NOT NULL DEFAULT '[]',
+            error TEXT,
+            created_at BIGINT NOT NULL,
+            UNIQUE (task_id, plugin)
+        )"
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub fn requested(profile: Option<&AnalysisProfile>) -> bool {
+    profile.and_then(|p| p.agent_flag("memory_dump")).unwrap_or_else(|| {
+        std::env::var("SANDBOX_MEMORY_DUMP").map(|v| v == "true" || v == "1").unwrap_or(false)
+    })
+}
+
+fn host_dir() -> String {
+    std::env::var("MEMDUMP_HOST_DIR").unwrap_or_else(|_| "/mnt/voodoo_memdumps".to_string())
+}
+
+fn local_dir() -> String {
+    std::env::var("MEMDUMP_DIR").unwrap_or_else(|_| host_dir())
+}
+
+fn plugins() -> Vec<String> {
+    std::env::var("VOLATILITY_PLUGINS")
+        .unwrap_or_else(|_| "windows.pslist,windows.malfind,windows.netscan".to_string())
+        .split(',')
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+/// Dumps the VM's memory; returns the dump's file name inside the shared directory.
+pub async fn capture(client: &dyn Hypervisor, node: &str, vmid: u64, task_id: &str) -> Result<String, String> {
+    let file_name = format!("{}-{}.elf", task_id.replace(['/', '\\'], "_"), chrono::Utc::now().timestamp());
+    let host_path = format!("{}/{}", host_dir().trim_end_matches('/'), file_name);
+    println!("[VOLATILITY] Task {}: dumping memory of VM {} to {}", task_id, vmid, host_path);
+    client.dump_memory(node, vmid, &host_path).await.map_err(|e| e.to_string())?;
+    Ok(file_name)
+}
+
+/// Volatility's JSON renderer nests child rows (e.g. pstree) under `__children`; flatten them.
+fn flatten_rows(value: serde_json::Value, out: &mut Vec<serde_json::Value>) {
+    let serde_json::Value::Array(rows) = value else { return };
+    for mut row in rows {
+        if out.len() >= MAX_ROWS {
+            return;
+        }
+        let children = row.as_object_mut().and_then(|o| o.remove("__children"));
+        out.push(row);
+        if let Some(children) = children {
+            flatten_rows(children, out);
+        }
+    }
+}
+
+async fn run_plugin(file_name: &str, plugin: &str) -> Result<Vec<serde_json::Value>, String> {
+    let mut cmd = match std::env::var("VOLATILITY_IMAGE").ok().filter(|i| !i.is_empty()) {
+        Some(image) => {
+            let mut cmd = tokio::process::Command::new("docker");
+            cmd.args(["run", "--rm", "-v", &format!("{}:/dumps:ro", local_dir()), &image, "vol"])
+                .arg("-f").arg(format!("/dumps/{}", file_name));
+            cmd
+        }
+        None => {
+            let mut cmd = tokio::process::Command::new(std::env::var("VOLATILITY_BIN").unwrap_or_else(|_| "vol".to_string()));
+            cmd.arg("-f").arg(format!("{}/{}", local_dir().trim_end_matches('/'), file_name));
+            cmd
+        }
+    };
+    cmd.args(["-q", "-r", "json", plugin]).kill_on_drop(true);
+
+    let timeout = std::env::var("VOLATILITY_TIMEOUT_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(900);
+    let output = tokio::time::timeout(Duration::from_secs(timeout), cmd.output())
+        .await
+        .map_err(|_| format!("timed out after {}s", timeout))?
+        .map_err(|e| format!("could not start Volatility: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(stderr.lines().last().unwrap_or("Volatility failed").to_string());
+    }
+    let parsed: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("unreadable Volatility output: {}", e))?;
+    let mut rows = Vec::new();
+    flatten_rows(parsed, &mut rows);
+    Ok(rows)
+}
+
+/// Runs every configured plugin over a dump and stores the results for the task.
+pub async fn analyze(pool: &Pool<Postgres>, task_id: &str, file_name: &str) {
+    for plugin in plugins() {
+        let (status, rows, error) = match run_plugin(file_name, &plugin).await {
+            Ok(rows) => ("completed", rows, None),
+            Err(e) => {
+                println!("[VOLATILITY] Task {}: {} failed: {}", task_id, plugin, e);
+                ("failed", Vec::new(), Some(e))
+            }
+        };
+        println!("[VOLATILITY] Task {}: {} returned {} row(s)", task_id, plugin, rows.len());
+        let _ = sqlx::query(
+            "INSERT INTO memory_analyses (task_id, plugin, status, row_count, rows, error, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)
+             ON CONFLICT (task_id, plugin) DO UPDATE
+             SET status = EXCLUDED.status, row_count = EXCLUDED.row_count, rows = EXCLUDED.rows,
+                 error = EXCLUDED.error, created_at = EXCLUDED.created_at"
+        )
+        .bind(task_id)
+        .bind(&plugin)
+        .bind(status)
+        .bind(rows.len() as i32)
+        .bind(serde_json::Value::Array(rows))
+        .bind(error)
+        .bind(chrono::Utc::now().timestamp_millis())
+        .execute(pool)
+        .await;
+    }
+
+    // YARA gets the dump too, before it is gone
+    let dump = crate::yara::Target {
+        kind: "memory",
+        path: std::path::PathBuf::from(format!("{}/{}", local_dir().trim_end_matches('/'), file_name)),
+        name: file_name.to_string(),
+    };
+    if let Err(e) = crate::yara::scan_file(pool, task_id, &dump).await {
+        println!("[VOLATILITY] Task {}: YARA scan of the dump failed: {}", task_id, e);
+    }
+
+    let keep = std::env::var("MEMDUMP_KEEP").map(|v| v == "true" || v == "1").unwrap_or(false);
+    if !keep {
+        let _ = tokio::fs::remove_file(format!("{}/{}", local_dir().trim_end_matches('/'), file_name)).await;
+    }
+}
+
+/// malfind hits (injected or unbacked executable memory), one line each, for the AI context.
+pub async fn injection_hits(pool: &Pool<Postgres>, task_id: &str, limit: usize) -> Vec<String> {
+    let rows: Option<serde_json::Value> = sqlx::query_scalar(
+        "SELECT rows FROM memory_analyses WHERE task_id = $1 AND plugin LIKE '%malfind' AND status = 'completed'"
+    )
+    .bind(task_id)
+    .fetch_optional(pool)
+    .await
+    .ok()
+    .flatten();
+
+    let Some(serde_json::Value::Array(rows)) = rows else { return Vec::new() };
+    rows.iter().take(limit).map(|row| format!(
+        "{} (pid {}) {} region at {}",
+        row["Process"].as_str().unwrap_or("?"),
+        row["PID"].as_i64().unwrap_or(0),
+        row["Protection"].as_str().unwrap_or("executable"),
+        row["Start VPN"].as_i64().map(|a| format!("{:#x}", a)).unwrap_or_else(|| "?".to_string()),
+    )).collect()
+}
+
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
+#[get("/tasks/{id}/memory")]
+pub async fn get_memory_analysis(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    match sqlx::query_as::<_, MemoryAnalysis>(
+        "SELECT id, task_id, plugin, status, row_count, rows, error, created_at
+         FROM memory_analyses WHERE task_id = $1 ORDER BY plugin"
+    )
+    .bind(path.into_inner())
+    .fetch_all(pool.get_ref())
+    .await {
+        Ok(rows) => HttpResponse::Ok().json(rows),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DumpRequest {
+    pub node: String,
+    pub vmid: u64,
+}
+
+/// Dumps a running VM's memory on demand and analyses it in the background for the task.
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
+#[post("/tasks/{id}/memory")]
+pub async fn dump_memory(
+    pool: web::Data<Pool<Postgres>>,
+    client: web::Data<dyn Hypervisor>,
+    path: web::Path<String>,
+    req: web::Json<DumpRequest>,
+) -> impl Responder {
+    let task_id = path.into_inner();
+    let client: Arc<dyn Hypervisor> = client.into_inner();
+    let pool = pool.get_ref().clone();
+    let DumpRequest { node, vmid } = req.into_inner();
+    let response = serde_json::json!({ "status": "memory_dump_started", "task_id": task_id, "vmid": vmid });
+    actix_web::rt::spawn(async move {
+        match capture(client.as_ref(), &node, vmid, &task_id).await {
+            Ok(file_name) => analyze(&pool, &task_id, &file_name).await,
+            Err(e) => println!("[VOLATILITY] Task {}: memory dump failed: {}", task_id, e),
+        }
+    });
+    HttpResponse::Accepted().json(response)
+}
@@ -0,0 +1,323 @@
+use async_trait::async_trait;
+use reqwest::{Client, Method, StatusCode};
+use serde::Deserialize;
+use std::error::Error;
+use tokio::sync::Mutex;
+use crate::hypervisor::{ConsoleTransport, Hypervisor, Node, Snapshot, Vm, VncTicket};
+
+// --- VMWARE ESXi / vCENTER BACKEND ---
+
+pub struct VsphereClient {
+    /// VSPHERE_URL, e.g. https://vcenter.lab.local
+    pub base_url: String,
+    user: String,
+    password: String,
+    /// VSPHERE_API_RELEASE for VI/JSON paths, default 8.0.1.0.
+    release: String,
+    http: Client,
+    session: Mutex<Option<String>>,
+}
+
+#[derive(Deserialize)]
+struct HostSummary {
+    host: String,
+    name: String,
+    connection_state: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct VmSummary {
+    vm: String,
+    name: String,
+    power_state: String,
+    cpu_count: Option<u64>,
+    #[serde(rename = "memory_size_MiB")]
+    memory_size_mib: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SnapshotTree {
+    name: String,
+    description: Option<String>,
+    snapshot: ManagedObjectRef,
+    #[serde(default)]
+    child_snapshot_list: Vec<SnapshotTree>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SnapshotInfo {
+    #[serde(default)]
+    root_snapshot_list: Vec<SnapshotTree>,
+}
+
+#[derive(Deserialize)]
+struct ManagedObjectRef {
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct TaskInfo {
+    state: String,
+    error: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct WebMksTicket {
+    ticket: String,
+    host: Option<String>,
+    port: Option<u64>,
+}
+
+impl VsphereClient {
+    pub fn from_env() -> Self {
+        let base_url = std::env::var("VSPHERE_URL").expect("VSPHERE_URL must be set");
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            user: std::env::var("VSPHERE_USER").expect("VSPHERE_USER must be set"),
+            password: std::env::var("VSPHERE_PASSWORD").expect("VSPHERE_PASSWORD must be set"),
+            release: std::env::var("VSPHERE_API_RELEASE").unwrap_or_else(|_| "8.0.1.0".to_string()),
+            http: Client::builder()
+                .danger_accept_invalid_certs(true)
+                .timeout(std::time::Duration::from_secs(30))
+                .build()
+                .unwrap(),
+            session: Mutex::new(None),
+        }
+    }
+
+    async fn login(&self) -> Result<String, Box<dyn Error>> {
+        let resp = self.http.post(format!("{}/api/session", self.base_url))
+            .basic_auth(&self.user, Some(&self.password))
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(format!("vSphere login failed: {}", resp.status()).into());
+        }
+        // The body is the session token as a JSON string
+        let token: String = resp.json().await?;
+        *self.session.lock().await = Some(token.clone());
+        Ok(token)
+    }
+
+    /// Sends an authenticated request, logging in again once if the session expired.
+    async fn call(&self, method: Method, path: &str, body: Option<serde_json::Value>) -> Result<reqwest::Response, Box<dyn Error>> {
+        let cached = self.session.lock().await.clone();
+        let mut token = match cached {
+            Some(t) => t,
+            None => self.login().await?,
+        };
+
+        for attempt in 0..2 {
+            let mut req = self.http.request(method.clone(), format!("{}{}", self.base_url, path))
+                .header("vmware-api-session-id", &token);
+            if let Some(b) = &body {
+                req = req.json(b);
+            }
+            let resp = req.send().await?;
+            if resp.status() == StatusCode::UNAUTHORIZED && attempt == 0 {
+                token = self.login().await?;
+                continue;
+            }
+            if !resp.status().is_success() {
+                let status = resp.status();
+                let text = resp.text().await.unwrap_or_default();
+                return Err(format!("vSphere API Error {}: {}", status, text).into());
+            }
+            return Ok(resp);
+        }
+        Err("vSphere session could not be re-established".into())
+    }
+
+    fn vim(&self, path: &str) -> String {
+        format!("/sdk/vim25/{}/{}", self.release, path)
+    }
+
+    async fn host_id(&self, node: &str) -> Result<String, Box<dyn Error>> {
+        let hosts: Vec<HostSummary> = self.call(Method::GET, "/api/vcenter/host", None).await?.json().await?;
+        hosts.into_iter()
+            .find(|h| h.name == node || h.host == node)
+            .map(|h| h.host)
+            .ok_or_else(|| format!("Unknown vSphere host '{}'", node).into())
+    }
+
+    async fn snapshot_tree(&self, vmid: u64) -> Result<Vec<SnapshotTree>, Box<dyn Error>> {
+        let resp = self.call(Method::GET, &self.vim(&format!("VirtualMachine/vm-{}/snapshot", vmid)), None).await?;
+        // null when the VM has no snapshots
+        let info: Option<SnapshotInfo> = resp.json().await?;
+        Ok(info.map(|i| i.root_snapshot_list).unwrap_or_default())
+    }
+
+    async fn wait_for_task(&self, task: &str) -> Result<(), Box<dyn Error>> {
+        for _ in 0..120 {
+            let info: TaskInfo = self.call(Method::GET, &self.vim(&format!("Task/{}/info", task)), None).await?.json().await?;
+            match info.state.as_str() {
+                "success" => return Ok(()),
+                "error" => return Err(format!("vSphere task {} failed: {}", task, info.error.unwrap_or_default()).into()),
+                _ => tokio::time::sleep(std::time::Duration::from_secs(1)).await,
+            }
+        }
+        Err(format!("vSphere task {} did not finish within 120s", task).into())
+    }
+}
+
+fn flatten_snapshots<'a>(trees: &'a [SnapshotTree], parent: Option<&str>, out: &mut Vec<(&'a SnapshotTree, Option<String>)>) {
+    for t in trees {
+        out.push((t, parent.map(str::to_string)));
+        flatten_snapshots(&t.child_snapshot_list, Some(&t.name), out);
+    }
+}
+
+/// Proxmox status strings, so the frontend needs no per-backend handling.
+fn proxmox_status(power_state: &str) -> String {
+    match power_state {
+        "POWERED_ON" => "running",
+        "SUSPENDED" => "paused",
+        _ => "stopped",
+    }.to_string()
+}
+
+#[async_trait(?Send)]
+impl Hypervisor for VsphereClient {
+    fn kind(&self) -> &'static str {
+        "vsphere"
+    }
+
+    fn console_transport(&self) -> ConsoleTransport {
+        ConsoleTransport::TicketWebsocket
+    }
+
+    async fn get_nodes(&self) -> Result<Vec<Node>, Box<dyn Error>> {
+        let hosts: Vec<HostSummary> = self.call(Method::GET, "/api/vcenter/host", None).await?.json().await?;
+        Ok(hosts.into_iter()
+            .map(|h| Node {
+                node: h.name,
+                status: if h.connection_state.as_deref() == Some("CONNECTED") { "online" } else { "offline" }.to_string(),
+                maxcpu: None,
+                maxmem: None,
+            })
+            .collect())
+    }
+
+    async fn get_vms(&self, node: &str) -> Result<Vec<Vm>, Box<dyn Error>> {
+        let host = self.host_id(node).await?;
+        let vms: Vec<VmSummary> = self.call(Method::GET, &format!("/api/vcenter/vm?hosts={}", host), None).await?.json().await?;
+        Ok(vms.into_iter()
+            .filter_map(|v| {
+                let vmid = v.vm.trim_start_matches("vm-").parse().ok()?;
+                Some(Vm {
+                    vmid,
+                    name: Some(v.name),
+                    status: proxmox_status(&v.power_state),
+                    cpus: v.cpu_count,
+                    maxmem: v.memory_size_mib.map(|m| m * 1024 * 1024),
+                })
+            })
+            .collect())
+    }
+
+    async fn vm_action(&self, _node: &str, vmid: u64, action: &str) -> Result<(), Box<dyn Error>> {
+        let path = match action {
+            "start" | "resume" => format!("/api/vcenter/vm/vm-{}/power?action=start", vmid),
+            "stop" => format!("/api/vcenter/vm/vm-{}/power?action=stop", vmid),
+            "reset" => format!("/api/vcenter/vm/vm-{}/power?action=reset", vmid),
+            "suspend" => format!("/api/vcenter/vm/vm-{}/power?action=suspend", vmid),
+            // Graceful variants need VMware Tools in the guest
+            "shutdown" => format!("/api/vcenter/vm/vm-{}/guest/power?action=shutdown", vmid),
+            "reboot" => format!("/api/vcenter/vm/vm-{}/guest/power?action=reboot", vmid),
+            other => return Err(format!("Unsupported VM action '{}'", other).into()),
+        };
+        match self.call(Method::POST, &path, None).await {
+            Ok(_) => Ok(()),
+            // Already powered on/off; Proxmox treats these as no-ops too
+            Err(e) if e.to_string().contains("already") => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn rollback_snapshot(&self, _node: &str, vmid: u64, snapshot: &str) -> Result<(), Box<dyn Error>> {
+        let trees = self.snapshot_tree(vmid).await?;
+        let mut all = Vec::new();
+        flatten_snapshots(&trees, None, &mut all);
+        let target = all.iter()
+            .find(|(t, _)| t.name == snapshot)
+            .map(|(t, _)| t.snapshot.value.clone())
+            .ok_or_else(|| format!("Snapshot '{}' not found on vm-{}", snapshot, vmid))?;
+
+        let task: ManagedObjectRef = self.call(
+            Method::POST,
+            &self.vim(&format!("VirtualMachineSnapshot/{}/RevertToSnapshot_Task", target)),
+            Some(serde_json::json!({})),
+        ).await?.json().await?;
+        self.wait_for_task(&task.value).await
+    }
+
+    async fn list_snapshots(&self, _node: &str, vmid: u64) -> Result<Vec<Snapshot>, Box<dyn Error>> {
+        let trees = self.snapshot_tree(vmid).await?;
+        let mut all = Vec::new();
+        flatten_snapshots(&trees, None, &mut all);
+        Ok(all.into_iter()
+            .map(|(t, parent)| Snapshot {
+                name: t.name.clone(),
+                description: t.description.clone().filter(|d| !d.is_empty()),
+                snaptime: None,
+                parent,
+            })
+            .collect())
+    }
+
+    async fn create_snapshot(&self, _node: &str, vmid: u64, name: &str, description: &str) -> Result<(), Box<dyn Error>> {
+        let task: ManagedObjectRef = self.call(
+            Method::POST,
+            &self.vim(&format!("VirtualMachine/vm-{}/CreateSnapshot_Task", vmid)),
+            Some(serde_json::json!({ "name": name, "description": description, "memory": false, "quiesce": false })),
+        ).await?.json().await?;
+        self.wait_for_task(&task.value).await
+    }
+
+    async fn delete_snapshot(&self, _node: &str, vmid: u64, name: &str) -> Result<(), Box<dyn Error>> {
+        let trees = self.snapshot_tree(vmid).await?;
+        let mut all = Vec::new();
+        flatten_snapshots(&trees, None, &mut all);
+        let target = all.iter()
+            .find(|(t, _)| t.name == name)
+            .map(|(t, _)| t.snapshot.value.clone())
+            .ok_or_else(|| format!("Snapshot '{}' not found on vm-{}", name, vmid))?;
+
+        let task: ManagedObjectRef = self.call(
+            Method::POST,
+            &self.vim(&format!("VirtualMachineSnapshot/{}/RemoveSnapshot_Task", target)),
+            Some(serde_json::json!({ "removeChildren": false })),
+        ).await?.json().await?;
+        self.wait_for_task(&task.value).await
+    }
+
+    async fn create_vnc_proxy(&self, _node: &str, vmid: u64) -> Result<VncTicket, Box<dyn Error>> {
+        let ticket: WebMksTicket = self.call(
+            Method::POST,
+            &self.vim(&format!("VirtualMachine/vm-{}/AcquireTicket", vmid)),
+            Some(serde_json::json!({ "ticketType": "webmks" })),
+        ).await?.json().await?;
+
+        // No host in the ticket means "the server you asked", i.e. vCenter/ESXi itself
+        let host = ticket.host.unwrap_or_else(|| {
+            self.base_url
+                .trim_start_matches("https://")
+                .trim_start_matches("http://")
+                .split(['/', ':'])
+                .next()
+                .unwrap_or("localhost")
+                .to_string()
+        });
+
+        Ok(VncTicket {
+            ticket: ticket.ticket,
+            port: ticket.port.unwrap_or(443).to_string(),
+            upid: format!("vsphere:vm-{}", vmid),
+            cert: None,
+            password: None,
+            host: Some(host),
+        })
+    }
+}
@@ -0,0 +1,658 @@
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+// --- YARA SCANNING ---
+
+/// (name, description, source)
+const DEFAULT_RULES: &[(&str, &str, &str)] = &[
+    (
+        "upx_packed",
+        "Executable packed with UPX",
+        r#"rule upx_packed : packer
+{
+    strings:
+        $upx0 = "UPX0"
+        $upx1 = "UPX1"
+        $magic = "UPX!"
+    condition:
+        uint16(0) == 0x5A4D and 2 of them
+}"#,
+    ),
+    (
+        "powershell_download_cradle",
+        "PowerShell that downloads and runs code",
+        r#"rule powershell_download_cradle : execution
+{
+    strings:
+        $download_string = "DownloadString" ascii wide nocase
+        $download_file = "DownloadFile" ascii wide nocase
+        $web_request = "Invoke-WebRequest" ascii wide nocase
+        $web_client = "Net.WebClient" ascii wide nocase
+        $iex = "IEX" ascii wide
+        $invoke_expression = "Invoke-Expression" ascii wide nocase
+    condition:
+        any of ($download_string, $download_file, $web_request) and any of ($web_client, $iex, $invoke_expression)
+}"#,
+    ),
+    (
+        "mimikatz",
+        "Mimikatz credential dumper",
+        r#"rule mimikatz : credential_access
+{
+    strings:
+        $logonpasswords = "sekurlsa::logonpasswords" ascii wide nocase
+        $lsadump = "lsadump::sam" ascii wide nocase
+        $name = "mimikatz" ascii wide nocase
+        $author = "gentilkiwi" ascii wide
+    condition:
+        2 of them
+}"#,
+    ),
+    (
+        "ransomware_indicators",
+        "Ransom note text or shadow copy deletion",
+        r#"rule ransomware_indicators : impact
+{
+    strings:
+        $note = "your files have been encrypted" ascii wide nocase
+        $shadows = "vssadmin delete shadows" ascii wide nocase
+        $shadowcopy = "shadowcopy delete" ascii wide nocase
+        $bitcoin = "bitcoin" ascii wide nocase
+        $decrypt = "decrypt" ascii wide nocase
+        $onion = ".onion" ascii wide nocase
+    condition:
+        $note or $shadows or $shadowcopy or all of ($bitcoin, $decrypt, $onion)
+}"#,
+    ),
+];
+
+/// String matches kept per rule and file; a rule on a memory dump can match thousands of times.
+const MAX_STRINGS_PER_MATCH: usize = 20;
+const MAX_STRING_CHARS: usize = 200;
+
+#[derive(Serialize, sqlx::FromRow, Clone)]
+pub struct YaraRule {
+    pub id: i32,
+    pub name: String,
+    pub description: String,
+    pub source: String,
+    pub enabled: bool,
+    pub created_at: i64,
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct YaraRuleRequest {
+    /// Required when creating; a rule set with an existing name is replaced.
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// YARA source; may hold several rules.
+    pub source: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Serialize, sqlx::FromRow, Clone, Debug)]
+pub struct YaraMatch {
+    pub id: i32,
+    pub task_id: String,
+    /// sample | dropped | memory
+    pub target_kind: String,
+    /// Storage key or path of the scanned file.
+    pub target: String,
+    pub target_name: String,
+    /// The yara_rules set the rule came from.
+    pub ruleset: String,
+    pub rule: String,
+    pub tags: Vec<String>,
+    /// [{offset, identifier, data}]
+    pub strings: serde_json::Value,
+    pub scanned_at: i64,
+}
+
+/// One file to scan for a task.
+pub struct Target {
+    pub kind: &'static str,
+    pub path: PathBuf,
+    pub name: String,
+}
+
+struct Hit {
+    namespace: String,
+    rule: String,
+    tags: Vec<String>,
+    strings: Vec<serde_json::Value>,
+}
+
+/// The enabled rule sets written out for the CLI; the directory goes when this is dropped.
+struct Compiled {
+    dir: PathBuf,
+    args: Vec<String>,
+    rulesets: HashMap<String, String>,
+}
+
+impl Drop for Compiled {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub enum Check {
+    Valid,
+    Invalid(String),
+    Unavailable(String),
+}
+
+fn bin() -> String {
+    std::env::var("YARA_BIN").unwrap_or_else(|_| "yara".to_string())
+}
+
+fn timeout_secs() -> u64 {
+    std::env::var("YARA_TIMEOUT_SECS").ok().and_then(|v| v.trim().parse().ok()).unwrap_or(300)
+}
+
+fn slots() -> &'static Semaphore {
+    static SLOTS: OnceLock<Semaphore> = OnceLock::new();
+    SLOTS.get_or_init(|| {
+        let n: usize = std::env::var("YARA_MAX_CONCURRENT").ok().and_then(|v| v.trim().parse().ok()).unwrap_or(2);
+        Semaphore::new(n.max(1))
+    })
+}
+
+fn scratch_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("voodoobox-yara-{}", uuid::Uuid::new_v4()))
+}
+
+pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS yara_rules (
+            id SERIAL PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            description TEXT NOT NULL,
+            source TEXT NOT NULL,
+            enabled BOOLEAN NOT NULL DEFAULT TRUE,
+            created_at BIGINT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS yara_matches (
+            id SERIAL PRIMARY KEY,
+            task_id TEXT NOT NULL,
+            target_kind TEXT NOT NULL,
+            target TEXT NOT NULL,
+            target_name TEXT NOT NULL,
+            ruleset TEXT NOT NULL,
+            rule TEXT NOT NULL,
+            tags TEXT[] NOT NULL DEFAULT '{}',
+            strings JSONB NOT NULL DEFAULT '[]',
+            scanned_at BIGINT NOT NULL,
+            UNIQUE (task_id, target, ruleset, rule)
+        )"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_yara_matches_task ON yara_matches (task_id)")
+        .execute(pool)
+        .await?;
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM yara_rules")
+        .fetch_one(pool)
+        .await?;
+    if count == 0 {
+        let now = chrono::Utc::now().timestamp();
+        for (name, description, source) in DEFAULT_RULES {
+            sqlx::query(
+                "INSERT INTO yara_rules (name, description, source, created_at) VALUES ($1, $2, $3, $4) ON CONFLICT (name) DO NOTHING"
+            )
+            .bind(name)
+            .bind(description)
+            .bind(source)
+            .bind(now)
+            .execute(pool)
+            .await?;
+        }
+        println!("[YARA] Seeded {} default rule sets.", DEFAULT_RULES.len());
+    }
+    println!("[YARA] Database initialized (yara_rules, yara_matches).");
+    Ok(())
+}
+
+/// Compiles a rule set against an empty file.
+pub async fn check(source: &str) -> Check {
+    let dir = scratch_dir();
+    if let Err(e) = tokio::fs::create_dir_all(&dir).await {
+        return Check::Unavailable(e.to_string());
+    }
+    let (rules, empty) = (dir.join("rules.yar"), dir.join("empty"));
+    let written = tokio::fs::write(&rules, source).await.and(tokio::fs::write(&empty, b"").await);
+    let result = match written {
+        Err(e) => Check::Unavailable(e.to_string()),
+        Ok(()) => match tokio::process::Command::new(bin()).arg("-w").arg(&rules).arg(&empty).kill_on_drop(true).output().await {
+            Err(e) => Check::Unavailable(format!("could not start {}: {}", bin(), e)),
+            Ok(output) if output.status.success() => Check::Valid,
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let path = rules.to_string_lossy();
+                Check::Invalid(stderr.trim().replace(path.as_ref(), "rule").lines().next().unwrap_or("rule does not compile").to_string())
+            }
+        },
+    };
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    result
+}
+
+/// Writes the enabled rule sets out, one namespace each; None when there are none.
+async fn prepare(pool: &Pool<Postgres>) -> Result<Option<Compiled>, String> {
+    let rules = sqlx::query_as::<_, YaraRule>("SELECT * FROM yara_rules WHERE enabled ORDER BY id")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    if rules.is_empty() {
+        return Ok(None);
+    }
+    let mut compiled = Compiled { dir: scratch_dir(), args: Vec::new(), rulesets: HashMap::new() };
+    tokio::fs::create_dir_all(&compiled.dir).await.map_err(|e| e.to_string())?;
+    for rule in rules {
+        let namespace = format!("r{}", rule.id);
+        let path = compiled.dir.join(format!("{}.yar", namespace));
+        tokio::fs::write(&path, &rule.source).await.map_err(|e| e.to_string())?;
+        compiled.args.push(format!("{}:{}", namespace, path.display()));
+        compiled.rulesets.insert(namespace, rule.name);
+    }
+    Ok(Some(compiled))
+}
+
+fn clip(s: &str) -> String {
+    match s.char_indices().nth(MAX_STRING_CHARS) {
+        Some((cut, _)) => format!("{}...", &s[..cut]),
+        None => s.to_string(),
+    }
+}
+
+/// `yara -e -g -s` output: "ns:rule [tag,tag] file" per match, then "0xoffset:$id: data" per string.
+fn parse(stdout: &str) -> Vec<Hit> {
+    let mut hits: Vec<Hit> = Vec::new();
+    for line in stdout.lines() {
+        if let Some(string) = line.strip_prefix("0x") {
+            let Some(hit) = hits.last_mut() else { continue };
+            if hit.strings.len() >= MAX_STRINGS_PER_MATCH {
+                continue;
+            }
+            let mut parts = string.splitn(3, ':');
+            let (Some(offset), Some(identifier)) = (parts.next(), parts.next()) else { continue };
+            hit.strings.push(serde_json::json!({
+                "offset": u64::from_str_radix(offset, 16).unwrap_or(0),
+                "identifier": identifier,
+                "data": clip(parts.next().unwrap_or_default().trim_start()),
+            }));
+            continue;
+        }
+        let Some((head, rest)) = line.split_once(' ') else { continue };
+        let Some((namespace, rule)) = head.split_once(':') else { continue };
+        let tags = rest.strip_prefix('[')
+            .and_then(|r| r.split_once(']'))
+            .map(|(tags, _)| tags.split(',').filter(|t| !t.is_empty()).map(str::to_string).collect())
+            .unwrap_or_default();
+        hits.push(Hit { namespace: namespace.to_string(), rule: rule.to_string(), tags, strings: Vec::new() });
+    }
+    hits
+}
+
+async fn run(compiled: &Compiled, file: &Path) -> Result<Vec<Hit>, String> {
+    let timeout = timeout_secs();
+    let mut cmd = tokio::process::Command::new(bin());
+    cmd.args(["-w", "-e", "-g", "-s", "-a", &timeout.to_string()])
+        .args(&compiled.args)
+        .arg(file)
+        .kill_on_drop(true);
+    let output = tokio::time::timeout(Duration::from_secs(timeout + 30), cmd.output())
+        .await
+        .map_err(|_| format!("timed out after {}s", timeout))?
+        .map_err(|e| format!("could not start {}: {}", bin(), e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(stderr.lines().last().unwrap_or("yara failed").to_string());
+    }
+    Ok(parse(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Replaces the matches stored for one file.
+async fn store(pool: &Pool<Postgres>, task_id: &str, target: &Target, compiled: &Compiled, hits: Vec<Hit>) -> Result<usize, sqlx::Error> {
+    let key = target.path.to_string_lossy().trim_start_matches("./").to_string();
+    sqlx::query("DELETE FROM yara_matches WHERE task_id = $1 AND target = $2")
+        .bind(task_id)
+        .bind(&key)
+        .execute(pool)
+        .await?;
+    let now = chrono::Utc::now().timestamp_millis();
+    let stored = hits.len();
+    for hit in hits {
+        let ruleset = compiled.rulesets.get(&hit.namespace).cloned().unwrap_or(hit.namespace);
+        sqlx::query(
+            "INSERT INTO yara_matches (task_id, target_kind, target, target_name, ruleset, rule, tags, strings, scanned_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+             ON CONFLICT (task_id, target, ruleset, rule) DO UPDATE
+             SET tags = EXCLUDED.tags, strings = EXCLUDED.strings, scanned_at = EXCLUDED.scanned_at"
+        )
+        .bind(task_id)
+        .bind(target.kind)
+        .bind(&key)
+        .bind(&target.name)
+        .bind(&ruleset)
+        .bind(&hit.rule)
+        .bind(&hit.tags)
+        .bind(serde_json::Value::Array(hit.strings))
+        .bind(now)
+        .execute(pool)
+        .await?;
+    }
+    Ok(stored)
+}
+
+async fn scan_with(pool: &Pool<Postgres>, task_id: &str, compiled: &Compiled, target: &Target) -> Result<usize, String> {
+    let hits = run(compiled, &target.path).await?;
+    let stored = store(pool, task_id, target, compiled, hits).await.map_err(|e| e.to_string())?;
+    println!("[YARA] Task {}: {} rule(s) matched {} {}", task_id, stored, target.kind, target.name);
+    Ok(stored)
+}
+
+/// Runs a rule set that is not stored over one file. Returns the rules that matched.
+pub async fn test(source: &str, file: &Path) -> Result<Vec<String>, String> {
+    let _slot = slots().acquire().await.map_err(|e| e.to_string())?;
+    let mut compiled = Compiled { dir: scratch_dir(), args: Vec::new(), rulesets: HashMap::new() };
+    tokio::fs::create_dir_all(&compiled.dir).await.map_err(|e| e.to_string())?;
+    let path = compiled.dir.join("test.yar");
+    tokio::fs::write(&path, source).await.map_err(|e| e.to_string())?;
+    compiled.args.push(format!("test:{}", path.display()));
+    Ok(run(&compiled, file).await?.into_iter().map(|hit| hit.rule).collect())
+}
+
+/// Scans one file for the task, replacing its earlier matches. Returns the rules matched.
+pub async fn scan_file(pool: &Pool<Postgres>, task_id: &str, target: &Target) -> Result<usize, String> {
+    let _slot = slots().acquire().await.map_err(|e| e.to_string())?;
+    let Some(compiled) = prepare(pool).await? else { return Ok(0) };
+    scan_with(pool, task_id, &compiled, target).await
+}
+
+async fn sample_path(filename: &str) -> Option<PathBuf> {
+    // URL tasks have no file; neither do tasks whose sample was deleted
+    if filename.is_empty() || filename.contains("..") || filename.contains('/') {
+        return None;
+    }
+    let key = format!("uploads/{}", filename);
+    crate::storage::ensure_local(&key).await.then(|| crate::storage::local_path(&key))
+}
+
+fn dir_targets(dir: &str, kind: &'static str, name: impl Fn(&str) -> Option<String>) -> Vec<Target> {
+    let Ok(entries) = std::fs::read_dir(dir) else { return Vec::new() };
+    let mut targets: Vec<Target> = entries.flatten()
+        .filter(|e| e.path().is_file())
+        .filter_map(|e| {
+            let file = e.file_name().to_string_lossy().to_string();
+            if file.ends_with(".part") {
+                return None;
+            }
+            name(&file).map(|name| Target { kind, path: e.path(), name })
+        })
+        .collect();
+    targets.sort_by(|a, b| a.path.cmp(&b.path));
+    targets
+}
+
+/// The task's sample, when it has one on disk.
+pub async fn sample_target(pool: &Pool<Postgres>, task_id: &str) -> Option<Target> {
+    let (filename, original) = sqlx::query_as::<_, (String, String)>("SELECT filename, original_filename FROM tasks WHERE id = $1")
+        .bind(task_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()?;
+    let path = sample_path(&filename).await?;
+    Some(Target { kind: "sample", path, name: original })
+}
+
+/// Everything there is to scan for a task: its sample, dropped files and memory dumps.
+async fn targets(pool: &Pool<Postgres>, task_id: &str) -> Vec<Target> {
+    let mut targets: Vec<Target> = sample_target(pool, task_id).await.into_iter().collect();
+
+    let pivoted = sqlx::query_as::<_, (String, String)>("SELECT filename, original_filename FROM tasks WHERE parent_task_id = $1 ORDER BY created_at")
+        .bind(task_id)
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default();
+    for (filename, original) in pivoted {
+        if let Some(path) = sample_path(&filename).await {
+            targets.push(Target { kind: "dropped", path, name: original });
+        }
+    }
+
+    crate::storage::ensure_local_prefix(&format!("url_artifacts/{}/", task_id)).await;
+    targets.extend(dir_targets(&format!("./url_artifacts/{}", task_id), "dropped", |f| {
+        f.strip_prefix("payload_").map(|sha256| format!("payload {}", sha256))
+    }));
+    targets.extend(dir_targets(&format!("./memory_dumps/{}", task_id), "memory", |f| Some(f.to_string())));
+    targets
+}
+
+/// Rescans everything the task has with the current rules. Returns the rules matched.
+pub async fn scan_task(pool: &Pool<Postgres>, task_id: &str) -> Result<usize, String> {
+    let targets = targets(pool, task_id).await;
+    let _slot = slots().acquire().await.map_err(|e| e.to_string())?;
+    sqlx::query("DELETE FROM yara_matches WHERE task_id = $1")
+        .bind(task_id)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    let Some(compiled) = prepare(pool).await? else { return Ok(0) };
+    let mut matched = 0;
+    for target in &targets {
+        match scan_with(pool, task_id, &compiled, target).await {
+            Ok(n) => matched += n,
+            Err(e) => println!("[YARA] Task {}: scan of {} failed: {}", task_id, target.path.display(), e),
+        }
+    }
+    Ok(matched)
+}
+
+/// Scans the task in the background.
+pub fn queue_task(pool: &Pool<Postgres>, task_id: &str) {
+    let (pool, task_id) = (pool.clone(), task_id.to_string());
+    actix_web::rt::spawn(async move {
+        if let Err(e) = scan_task(&pool, &task_id).await {
+            println!("[YARA] Task {}: scan failed: {}", task_id, e);
+        }
+    });
+}
+
+/// Scans one new file of the task in the background.
+pub fn queue_file(pool: &Pool<Postgres>, task_id: &str, target: Target) {
+    let (pool, task_id) = (pool.clone(), task_id.to_string());
+    actix_web::rt::spawn(async move {
+        if let Err(e) = scan_file(&pool, &task_id, &target).await {
+            println!("[YARA] Task {}: scan of {} failed: {}", task_id, target.path.display(), e);
+        }
+    });
+}
+
+async fn fetch_matches(pool: &Pool<Postgres>, task_id: &str) -> Result<Vec<YaraMatch>, sqlx::Error> {
+    sqlx::query_as::<_, YaraMatch>(
+        "SELECT * FROM yara_matches WHERE task_id = $1 ORDER BY target_kind DESC, target_name, ruleset, rule"
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// The task's matches, samples first, for the report and the AI context.
+pub async fn matches(pool: &Pool<Postgres>, task_id: &str) -> Vec<YaraMatch> {
+    fetch_matches(pool, task_id).await.unwrap_or_default()
+}
+
+/// One line per match for the AI prompt.
+pub fn describe(matches: &[YaraMatch]) -> String {
+    if matches.is_empty() {
+        return "No YARA rule matched.".to_string();
+    }
+    matches.iter().map(|m| {
+        let strings: Vec<&str> = m.strings.as_array().into_iter().flatten()
+            .filter_map(|s| s["data"].as_str())
+            .take(3)
+            .collect();
+        format!("{} ({}) matched {}:{}{}{}", m.target_name, m.target_kind, m.ruleset, m.rule,
+            if m.tags.is_empty() { String::new() } else { format!(" [{}]", m.tags.join(", ")) },
+            if strings.is_empty() { String::new() } else { format!(" on {}", strings.join(", ")) })
+    }).collect::<Vec<_>>().join("\n")
+}
+
+#[utoipa::path(tag = "tasks", responses((status = 200, description = "Success")))]
+#[get("/tasks/{id}/yara")]
+pub async fn get_yara_matches(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    match fetch_matches(pool.get_ref(), &path.into_inner()).await {
+        Ok(matches) => HttpResponse::Ok().json(matches),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Rescans the task's sample, dropped files and memory dumps with the current rules.
+#[utoipa::path(tag = "tasks", responses(
+    (status = 202, description = "Scan started"),
+    (status = 404, description = "Task not found"),
+))]
+#[post("/tasks/{id}/yara/scan")]
+pub async fn rescan_task(pool: web::Data<Pool<Postgres>>, path: web::Path<String>) -> impl Responder {
+    let task_id = path.into_inner();
+    match sqlx::query_scalar::<_, String>("SELECT status FROM tasks WHERE id = $1").bind(&task_id).fetch_optional(pool.get_ref()).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Task not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+    queue_task(pool.get_ref(), &task_id);
+    HttpResponse::Accepted().json(serde_json::json!({
+        "status": "scanning",
+        "task_id": task_id,
+        "message": "Matches appear under /tasks/{id}/yara when the scan finishes"
+    }))
+}
+
+/// Checks and normalizes a request; `existing` supplies the fields an update leaves out.
+async fn validate(req: &YaraRuleRequest, existing: Option<&YaraRule>) -> Result<(String, String, String), HttpResponse> {
+    let bad = |msg: String| HttpResponse::BadRequest().json(serde_json::json!({ "error": msg }));
+    let name = req.name.as_deref().map(|n| n.trim().to_lowercase()).or_else(|| existing.map(|e| e.name.clone()))
+        .filter(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-')))
+        .ok_or_else(|| bad("name is required (letters, digits, _ and -)".to_string()))?;
+    let source = match req.source.as_deref().map(str::trim) {
+        Some(s) if !s.is_empty() => s.to_string(),
+        Some(_) => return Err(bad("source is empty".to_string())),
+        None => existing.map(|e| e.source.clone()).ok_or_else(|| bad("source is required".to_string()))?,
+    };
+    if req.source.is_some() {
+        match check(&source).await {
+            Check::Valid => {}
+            Check::Invalid(e) => return Err(bad(format!("Rule does not compile: {}", e))),
+            Check::Unavailable(e) => {
+                return Err(HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": format!("Cannot compile rules: {}", e) })));
+            }
+        }
+    }
+    let description = req.description.as_deref().map(str::trim).filter(|d| !d.is_empty()).map(str::to_string)
+        .or_else(|| existing.map(|e| e.description.clone()))
+        .unwrap_or_else(|| name.clone());
+    Ok((name, description, source))
+}
+
+#[utoipa::path(tag = "detections", responses((status = 200, description = "Success")))]
+#[get("/settings/yara-rules")]
+pub async fn list_yara_rules(pool: web::Data<Pool<Postgres>>) -> impl Responder {
+    match sqlx::query_as::<_, YaraRule>("SELECT * FROM yara_rules ORDER BY id").fetch_all(pool.get_ref()).await {
+        Ok(rules) => HttpResponse::Ok().json(rules),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Adds a rule set after compiling it; one with the same name is replaced.
+#[utoipa::path(tag = "detections", request_body = YaraRuleRequest, responses(
+    (status = 200, description = "Rule set stored"),
+    (status = 400, description = "Missing field or rule does not compile"),
+    (status = 503, description = "YARA is not available to compile the rule"),
+))]
+#[post("/settings/yara-rules")]
+pub async fn add_yara_rule(pool: web::Data<Pool<Postgres>>, req: web::Json<YaraRuleRequest>) -> impl Responder {
+    let (name, description, source) = match validate(&req, None).await {
+        Ok(fields) => fields,
+        Err(resp) => return resp,
+    };
+    let stored = sqlx::query_as::<_, YaraRule>(
+        "INSERT INTO yara_rules (name, description, source, enabled, created_at)
+         VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (name) DO UPDATE SET description = EXCLUDED.description, source = EXCLUDED.source, enabled = EXCLUDED.enabled
+         RETURNING *"
+    )
+    .bind(&name)
+    .bind(&description)
+    .bind(&source)
+    .bind(req.enabled.unwrap_or(true))
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_one(pool.get_ref())
+    .await;
+    match stored {
+        Ok(rule) => HttpResponse::Ok().json(rule),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Changes any of a rule set's fields, e.g. `{"enabled": false}` to turn a built-in set off.
+#[utoipa::path(tag = "detections", request_body = YaraRuleRequest, responses(
+    (status = 200, description = "Rule set updated"),
+    (status = 400, description = "Invalid field or rule does not compile"),
+    (status = 404, description = "Rule set not found"),
+))]
+#[post("/settings/yara-rules/{id}")]
+pub async fn update_yara_rule(
+    pool: web::Data<Pool<Postgres>>,
+    path: web::Path<i32>,
+    req: web::Json<YaraRuleRequest>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let existing = match sqlx::query_as::<_, YaraRule>("SELECT * FROM yara_rules WHERE id = $1").bind(id).fetch_optional(pool.get_ref()).await {
+        Ok(Some(rule)) => rule,
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "YARA rule not found" })),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    let (name, description, source) = match validate(&req, Some(&existing)).await {
+        Ok(fields) => fields,
+        Err(resp) => return resp,
+    };
+    let updated = sqlx::query_as::<_, YaraRule>(
+        "UPDATE yara_rules SET name = $2, description = $3, source = $4, enabled = $5 WHERE id = $1 RETURNING *"
+    )
+    .bind(id)
+    .bind(&name)
+    .bind(&description)
+    .bind(&source)
+    .bind(req.enabled.unwrap_or(existing.enabled))
+    .fetch_one(pool.get_ref())
+    .await;
+    match updated {
+        Ok(rule) => HttpResponse::Ok().json(rule),
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+#[utoipa::path(tag = "detections", responses((status = 200, description = "Success")))]
+#[delete("/settings/yara-rules/{id}")]
+pub async fn delete_yara_rule(pool: web::Data<Pool<Postgres>>, path: web::Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    match sqlx::query("DELETE FROM yara_rules WHERE id = $1").bind(id).execute(pool.get_ref()).await {
+        Ok(res) if res.rows_affected() == 0 => {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": "YARA rule not found" }))
+        }
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "status": "deleted", "id": id })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+    }
+}